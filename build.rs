@@ -1,12 +1,34 @@
 extern crate cc;
 
 use std::env;
+use std::process::Command;
 
 fn main() {
     match env::var("CARGO_CFG_TARGET_OS").unwrap_or_default().as_str() {
         "android" => build_android(),
         _ => {}
     }
+
+    // Set by `build_android` rather than by enabling a feature of the crate.
+    println!("cargo:rustc-check-cfg=cfg(feature, values(\"dl_iterate_phdr\"))");
+    println!("cargo:rustc-check-cfg=cfg(backtrace_asm)");
+    match rustc_minor_version() {
+        Some(minor) if minor >= 59 => println!("cargo:rustc-cfg=backtrace_asm"),
+        _ => {}
+    }
+}
+
+// Inline assembly is stable since Rust 1.59, which is newer than what we
+// support, so check whether the compiler has it.
+fn rustc_minor_version() -> Option<u32> {
+    let rustc = env::var_os("RUSTC")?;
+    let output = Command::new(rustc).arg("--version").output().ok()?;
+    let version = String::from_utf8(output.stdout).ok()?;
+    let mut parts = version.split_whitespace().nth(1)?.split('.');
+    if parts.next()? != "1" {
+        return None;
+    }
+    parts.next()?.parse().ok()
 }
 
 fn build_android() {
@@ -0,0 +1,225 @@
+//! Compact storage of many backtraces.
+//!
+//! Backtraces captured in the same program share most of their frames: the
+//! outermost ones are the same few ways threads start, and the ones below the
+//! places events are recorded from are the same few call paths. The arena
+//! stores each backtrace as a path in a tree of frames, from the outermost
+//! frame, so shared frames are only stored once.
+
+use crate::Backtrace;
+use core::convert::TryFrom;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::prelude::v1::*;
+
+// The parent of the outermost frames, and the handle of empty backtraces.
+const ROOT: u32 = !0;
+
+/// A store of backtraces which shares the frames they have in common.
+///
+/// Tools which keep a backtrace for each of millions of events, like
+/// allocation profilers and leak detectors, can't afford a vector of frames
+/// for each. Instead they can insert the backtraces into an arena, which hands
+/// out a small `BacktraceHandle` for each and only stores the frames which
+/// differ from the ones of backtraces inserted before. Inserting the same
+/// backtrace again returns the same handle, so handles can also be used to
+/// count events by where they come from.
+///
+/// Only the instruction pointers of the frames are stored, and `get` turns
+/// them back into an unresolved `Backtrace` when it's needed, such as when a
+/// report is printed.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::{Backtrace, BacktraceArena};
+///
+/// let mut arena = BacktraceArena::new();
+/// let handle = arena.insert(&Backtrace::new_unresolved());
+///
+/// let mut backtrace = arena.get(handle);
+/// backtrace.resolve();
+/// println!("{:?}", backtrace);
+/// ```
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug, Default)]
+pub struct BacktraceArena {
+    nodes: Vec<Node>,
+    // The node of each frame called from a node, or from `ROOT`.
+    children: HashMap<(u32, usize), u32>,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    ip: usize,
+    parent: u32,
+}
+
+/// A backtrace stored in a `BacktraceArena`.
+///
+/// Handles are only meaningful to the arena which returned them. Handles of
+/// the same arena are equal if and only if their backtraces have the same
+/// instruction pointers.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BacktraceHandle(u32);
+
+impl BacktraceArena {
+    /// Creates an empty arena.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn new() -> BacktraceArena {
+        BacktraceArena::default()
+    }
+
+    /// Stores the frames of `backtrace`, as returned by `Backtrace::frames`,
+    /// and returns a handle to them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena would hold more than `u32::MAX - 1` distinct
+    /// frames.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn insert(&mut self, backtrace: &Backtrace) -> BacktraceHandle {
+        self.insert_outermost_first(backtrace.frames().iter().rev().map(|f| f.ip() as usize))
+    }
+
+    /// Stores the instruction pointers `ips`, listed innermost first as they
+    /// are by `trace_ips_into`, and returns a handle to them.
+    ///
+    /// This avoids creating a `Backtrace` for each event when only its
+    /// instruction pointers are captured.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena would hold more than `u32::MAX - 1` distinct
+    /// frames.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn insert_ips(&mut self, ips: &[*mut c_void]) -> BacktraceHandle {
+        self.insert_outermost_first(ips.iter().rev().map(|ip| *ip as usize))
+    }
+
+    fn insert_outermost_first<I>(&mut self, ips: I) -> BacktraceHandle
+    where
+        I: Iterator<Item = usize>,
+    {
+        let mut node = ROOT;
+        for ip in ips {
+            let nodes = &mut self.nodes;
+            node = *self.children.entry((node, ip)).or_insert_with(|| {
+                let index = u32::try_from(nodes.len())
+                    .ok()
+                    .filter(|index| *index != ROOT)
+                    .expect("too many frames in a BacktraceArena");
+                nodes.push(Node { ip, parent: node });
+                index
+            });
+        }
+        BacktraceHandle(node)
+    }
+
+    /// Returns the instruction pointers of the backtrace `handle` refers to,
+    /// innermost first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `handle` wasn't returned by this arena.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn ips(&self, handle: BacktraceHandle) -> Vec<*mut c_void> {
+        let mut ips = Vec::new();
+        let mut node = handle.0;
+        while node != ROOT {
+            let n = &self.nodes[node as usize];
+            ips.push(n.ip as *mut c_void);
+            node = n.parent;
+        }
+        ips
+    }
+
+    /// Returns the unresolved backtrace `handle` refers to.
+    ///
+    /// The frames of the returned backtrace only know their instruction
+    /// pointers, and `Backtrace::resolve` resolves them in this process.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `handle` wasn't returned by this arena.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn get(&self, handle: BacktraceHandle) -> Backtrace {
+        let ips = self.ips(handle).into_iter().map(|ip| ip as usize).collect();
+        Backtrace::from_ips(ips)
+    }
+
+    /// Returns the number of distinct frames stored, which is what the
+    /// memory the arena uses grows with.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn frame_count(&self) -> usize {
+        self.nodes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ips(ips: &[usize]) -> Vec<*mut c_void> {
+        ips.iter().map(|ip| *ip as *mut c_void).collect()
+    }
+
+    #[test]
+    fn shares_outer_frames() {
+        let mut arena = BacktraceArena::new();
+        let a = arena.insert_ips(&ips(&[4, 3, 2, 1]));
+        let b = arena.insert_ips(&ips(&[5, 3, 2, 1]));
+        let c = arena.insert_ips(&ips(&[3, 2, 1]));
+        assert_eq!(arena.frame_count(), 5);
+        assert_ne!(a, b);
+        assert_eq!(arena.insert_ips(&ips(&[4, 3, 2, 1])), a);
+        assert_eq!(arena.ips(a), ips(&[4, 3, 2, 1]));
+        assert_eq!(arena.ips(b), ips(&[5, 3, 2, 1]));
+        assert_eq!(arena.ips(c), ips(&[3, 2, 1]));
+
+        let empty = arena.insert_ips(&[]);
+        assert!(arena.ips(empty).is_empty());
+    }
+
+    #[test]
+    fn round_trips_backtraces() {
+        let mut arena = BacktraceArena::new();
+        let bt = Backtrace::new_unresolved();
+        let handle = arena.insert(&bt);
+        assert_eq!(arena.get(handle), bt);
+        assert_eq!(arena.insert(&arena.get(handle)), handle);
+    }
+}
@@ -4,7 +4,23 @@
 //! of two possible methods. The `StackWalkEx` function is primarily used if
 //! possible, but not all systems have that. Failing that the `StackWalk64`
 //! function is used instead. Note that `StackWalkEx` is favored because it
-//! handles debuginfo internally and returns inline frame information.
+//! handles debuginfo internally and knows about inlined functions. The
+//! virtual frames it reports for those are skipped though, as symbolization
+//! reports inlined functions as extra symbols of the physical frame instead,
+//! the same as on other platforms.
+//!
+//! ARM64EC processes mix native ARM64 code with emulated x64 code, which
+//! `StackWalkEx` can't unwind across, so there the stack is walked with
+//! `RtlVirtualUnwind` instead. The same is done on other 64-bit targets if
+//! dbghelp is disabled or can't be loaded.
+//!
+//! Either way the stack is walked one frame at a time for as long as the
+//! callback asks for more. There's no fast path with `RtlCaptureStackBackTrace`
+//! and its fixed limit on the number of frames, so there's nothing to fall
+//! back from for deep stacks. The depth is configured with
+//! `crate::set_max_frames`, like on other platforms, and
+//! `crate::Backtrace::is_truncated` reports when a backtrace was cut short by
+//! that limit.
 //!
 //! Note that all dbghelp support is loaded dynamically, see `src/dbghelp.rs`
 //! for more information about that.
@@ -15,6 +31,10 @@ use super::super::{dbghelp, windows::*};
 use core::ffi::c_void;
 use core::mem;
 
+// The `FrameType` byte of `InlineFrameContext` for frames of inlined functions.
+#[cfg(not(target_arch = "arm64ec"))]
+const STACK_FRAME_TYPE_INLINE: DWORD = 0x02;
+
 #[derive(Clone, Copy)]
 pub enum StackFrame {
     New(STACKFRAME_EX),
@@ -49,6 +69,29 @@ impl Frame {
         Some(self.base_address)
     }
 
+    pub fn is_signal_frame(&self) -> Option<bool> {
+        None
+    }
+
+    pub fn cfa(&self) -> Option<*mut c_void> {
+        None
+    }
+
+    pub fn frame_pointer(&self) -> Option<*mut c_void> {
+        match self.addr_frame().Offset {
+            0 => None,
+            fp => Some(fp as *mut _),
+        }
+    }
+
+    pub fn register(&self, _dwarf_register: u16) -> Option<usize> {
+        None
+    }
+
+    pub fn trust(&self) -> super::FrameTrust {
+        super::FrameTrust::Cfi
+    }
+
     fn addr_pc(&self) -> &ADDRESS64 {
         match self.stack_frame {
             StackFrame::New(ref new) => &new.AddrPC,
@@ -63,6 +106,13 @@ impl Frame {
         }
     }
 
+    fn addr_frame(&self) -> &ADDRESS64 {
+        match self.stack_frame {
+            StackFrame::New(ref new) => &new.AddrFrame,
+            StackFrame::Old(ref old) => &old.AddrFrame,
+        }
+    }
+
     fn addr_frame_mut(&mut self) -> &mut ADDRESS64 {
         match self.stack_frame {
             StackFrame::New(ref mut new) => &mut new.AddrFrame,
@@ -90,17 +140,89 @@ struct MyContext(CONTEXT);
 
 #[inline(always)]
 pub unsafe fn trace(cb: &mut dyn FnMut(&super::Frame) -> bool) {
-    // Allocate necessary structures for doing the stack walk
-    let process = GetCurrentProcess();
-    let thread = GetCurrentThread();
-
     let mut context = mem::zeroed::<MyContext>();
     RtlCaptureContext(&mut context.0);
+    trace_with_context(&mut context, cb)
+}
+
+#[inline(always)]
+pub unsafe fn trace_from_context(
+    context: *const c_void,
+    cb: &mut dyn FnMut(&super::Frame) -> bool,
+) -> bool {
+    if context.is_null() {
+        return false;
+    }
+    // `StackWalk*` mutates the context as it walks, and the caller's context
+    // may not be aligned to our liking, so work on an aligned copy.
+    let mut copy = mem::zeroed::<MyContext>();
+    copy.0 = *(context as *const CONTEXT);
+    trace_with_context(&mut copy, cb);
+    true
+}
+
+/// Walks the stack of another thread of this process, identified by its
+/// thread id.
+///
+/// The thread is suspended for the duration of the walk, so `cb` must not do
+/// anything which may need a lock held by that thread, such as allocating.
+/// Returns `false` if the thread could not be suspended or inspected.
+///
+/// The walk itself mustn't take such locks either, which rules out dbghelp:
+/// `StackWalkEx` may allocate, or take the loader lock or its own lock, and
+/// the suspended thread may hold any of those. On 64-bit targets the stack is
+/// therefore walked with `RtlVirtualUnwind` only. 32-bit images have no
+/// unwind data for that, so there dbghelp is still used, with the risk of a
+/// deadlock if the thread was suspended while holding one of those locks.
+pub unsafe fn trace_thread(thread_id: DWORD, cb: &mut dyn FnMut(&super::Frame) -> bool) -> bool {
+    // Initialize dbghelp before suspending anything, as that may allocate.
+    #[cfg(not(target_pointer_width = "64"))]
+    let _dbghelp = dbghelp::init();
+
+    let access = THREAD_SUSPEND_RESUME | THREAD_GET_CONTEXT | THREAD_QUERY_INFORMATION;
+    let thread = OpenThread(access, FALSE, thread_id);
+    if thread.is_null() {
+        return false;
+    }
+    if SuspendThread(thread) == !0 {
+        CloseHandle(thread);
+        return false;
+    }
+
+    let mut context = mem::zeroed::<MyContext>();
+    context.0.ContextFlags = CONTEXT_FULL;
+    let ok = GetThreadContext(thread, &mut context.0) == TRUE;
+    if ok {
+        #[cfg(target_pointer_width = "64")]
+        virtual_unwind(&mut context, cb);
+        #[cfg(not(target_pointer_width = "64"))]
+        walk(thread, &mut context, cb);
+    }
+
+    ResumeThread(thread);
+    CloseHandle(thread);
+    ok
+}
+
+unsafe fn trace_with_context(context: &mut MyContext, cb: &mut dyn FnMut(&super::Frame) -> bool) {
+    walk(GetCurrentThread(), context, cb)
+}
+
+#[cfg(not(target_arch = "arm64ec"))]
+unsafe fn walk(thread: HANDLE, context: &mut MyContext, cb: &mut dyn FnMut(&super::Frame) -> bool) {
+    // Allocate necessary structures for doing the stack walk
+    let process = GetCurrentProcess();
 
     // Ensure this process's symbols are initialized
     let dbghelp = match dbghelp::init() {
         Ok(dbghelp) => dbghelp,
-        Err(()) => return, // oh well...
+        Err(()) => {
+            // Without dbghelp the stack can still be walked with the unwind
+            // data of 64-bit images, but 32-bit ones don't have any.
+            #[cfg(target_pointer_width = "64")]
+            virtual_unwind(context, cb);
+            return;
+        }
     };
 
     // On x86_64 and ARM64 we opt to not use the default `Sym*` functions from
@@ -164,6 +286,10 @@ pub unsafe fn trace(cb: &mut dyn FnMut(&super::Frame) -> bool) {
                 0,
             ) == TRUE
             {
+                let inline_context = (*frame_ptr).InlineFrameContext;
+                if (inline_context >> 8) & 0xff == STACK_FRAME_TYPE_INLINE {
+                    continue;
+                }
                 frame.inner.base_address = get_module_base(process_handle, frame.ip() as _) as _;
 
                 if !cb(&frame) {
@@ -206,7 +332,69 @@ pub unsafe fn trace(cb: &mut dyn FnMut(&super::Frame) -> bool) {
     }
 }
 
-#[cfg(target_arch = "x86_64")]
+// The unwind data of an ARM64EC image describes both its ARM64 and its x64
+// functions, and `RtlVirtualUnwind` picks the right kind for each frame,
+// including the thunks that transition between the two. The context stays in
+// the x64 layout throughout, so frames look the same either way.
+#[cfg(target_arch = "arm64ec")]
+unsafe fn walk(
+    _thread: HANDLE,
+    context: &mut MyContext,
+    cb: &mut dyn FnMut(&super::Frame) -> bool,
+) {
+    virtual_unwind(context, cb)
+}
+
+// Walks the stack using only the unwind data of the loaded images, without
+// any help from dbghelp.
+#[cfg(target_pointer_width = "64")]
+unsafe fn virtual_unwind(context: &mut MyContext, cb: &mut dyn FnMut(&super::Frame) -> bool) {
+    use core::ptr;
+
+    let mut frame = super::Frame {
+        inner: Frame {
+            stack_frame: StackFrame::New(mem::zeroed()),
+            base_address: 0 as _,
+        },
+    };
+    init_frame(&mut frame.inner, &context.0);
+    while !frame.ip().is_null() {
+        let ip = frame.ip() as DWORD64;
+        let sp = frame.sp();
+        let mut base = 0;
+        let entry = RtlLookupFunctionEntry(ip, &mut base, ptr::null_mut());
+        if entry.is_null() {
+            // Either the stack is corrupt or the code isn't part of any
+            // image, and in both cases there's no telling where it returns
+            // to.
+            break;
+        }
+
+        frame.inner.base_address = base as _;
+        if !cb(&frame) {
+            break;
+        }
+
+        let mut handler_data = ptr::null_mut();
+        let mut establisher_frame = 0;
+        RtlVirtualUnwind(
+            0,
+            base,
+            ip,
+            entry,
+            &mut context.0,
+            &mut handler_data,
+            &mut establisher_frame,
+            ptr::null_mut(),
+        );
+        init_frame(&mut frame.inner, &context.0);
+        if frame.ip() as DWORD64 == ip && frame.sp() == sp {
+            break;
+        }
+    }
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "arm64ec"))]
 fn init_frame(frame: &mut Frame, ctx: &CONTEXT) -> WORD {
     frame.addr_pc_mut().Offset = ctx.Rip as u64;
     frame.addr_pc_mut().Mode = AddrModeFlat;
@@ -0,0 +1,531 @@
+//! Backtrace support using an in-crate DWARF CFI unwinder.
+//!
+//! This is enabled with the `dwarf-unwind` feature of this crate and replaces
+//! the system unwinder (`_Unwind_Backtrace` from libgcc or libunwind) on Linux
+//! and Android for x86_64 and aarch64. It's intended for environments where
+//! the system unwinder is unavailable or unusable, such as minimal sysroots.
+//!
+//! Unwinding works the same way as in the system unwinders: for each frame
+//! the loaded object containing the program counter is found with
+//! `_dl_find_object` where available and `dl_iterate_phdr` otherwise, its
+//! `.eh_frame_hdr` binary search table is used to find the FDE covering the
+//! program counter, and the CFI program of that FDE is evaluated with `gimli`
+//! to recover the registers of the caller.
+//!
+//! Only the registers needed to continue unwinding (program counter, stack
+//! pointer, frame pointer and, on aarch64, the link register) are tracked.
+//! CFA rules which are DWARF expressions are not supported and stop the walk.
+
+use addr2line::gimli::{
+    self, BaseAddresses, CfaRule, EhFrame, EhFrameHdr, NativeEndian, Pointer, Register,
+    RegisterRule, UnwindContext, UnwindSection,
+};
+use core::arch::asm;
+use core::ffi::c_void;
+use core::mem;
+use core::slice;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Clone)]
+pub struct Frame {
+    ip: *mut c_void,
+    sp: *mut c_void,
+    symbol_address: *mut c_void,
+    cfa: Option<usize>,
+    fp: usize,
+    signal_frame: bool,
+    trust: super::FrameTrust,
+}
+
+// Frames only contain plain addresses which are never dereferenced.
+unsafe impl Send for Frame {}
+unsafe impl Sync for Frame {}
+
+impl Frame {
+    pub fn ip(&self) -> *mut c_void {
+        self.ip
+    }
+
+    pub fn sp(&self) -> *mut c_void {
+        self.sp
+    }
+
+    pub fn symbol_address(&self) -> *mut c_void {
+        self.symbol_address
+    }
+
+    pub fn module_base_address(&self) -> Option<*mut c_void> {
+        None
+    }
+
+    pub fn is_signal_frame(&self) -> Option<bool> {
+        Some(self.signal_frame)
+    }
+
+    pub fn cfa(&self) -> Option<*mut c_void> {
+        self.cfa.map(|cfa| cfa as *mut c_void)
+    }
+
+    pub fn frame_pointer(&self) -> Option<*mut c_void> {
+        Some(self.fp as *mut c_void)
+    }
+
+    pub fn register(&self, dwarf_register: u16) -> Option<usize> {
+        // The frame pointer is the only callee-saved register we track.
+        if Register(dwarf_register) == Registers::FP {
+            Some(self.fp)
+        } else {
+            None
+        }
+    }
+
+    pub fn trust(&self) -> super::FrameTrust {
+        self.trust
+    }
+}
+
+/// The subset of the register file needed to unwind.
+#[derive(Clone, Copy, Default)]
+struct Registers {
+    pc: usize,
+    sp: usize,
+    fp: usize,
+    #[cfg(target_arch = "aarch64")]
+    lr: usize,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl Registers {
+    const PC: Register = gimli::X86_64::RA;
+    const FP: Register = gimli::X86_64::RBP;
+
+    fn get(&self, reg: Register) -> Option<usize> {
+        match reg {
+            gimli::X86_64::RA => Some(self.pc),
+            gimli::X86_64::RSP => Some(self.sp),
+            gimli::X86_64::RBP => Some(self.fp),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, reg: Register, val: usize) {
+        match reg {
+            gimli::X86_64::RA => self.pc = val,
+            gimli::X86_64::RSP => self.sp = val,
+            gimli::X86_64::RBP => self.fp = val,
+            _ => {}
+        }
+    }
+
+    #[inline(always)]
+    fn current() -> Registers {
+        let (pc, sp, fp): (usize, usize, usize);
+        unsafe {
+            asm!(
+                "lea {pc}, [rip]",
+                "mov {sp}, rsp",
+                "mov {fp}, rbp",
+                pc = out(reg) pc,
+                sp = out(reg) sp,
+                fp = out(reg) fp,
+                options(nomem, nostack, preserves_flags),
+            );
+        }
+        Registers { pc, sp, fp }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+impl Registers {
+    const PC: Register = gimli::AArch64::X30;
+    const FP: Register = gimli::AArch64::X29;
+
+    fn get(&self, reg: Register) -> Option<usize> {
+        match reg {
+            gimli::AArch64::X29 => Some(self.fp),
+            gimli::AArch64::X30 => Some(self.lr),
+            gimli::AArch64::SP => Some(self.sp),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, reg: Register, val: usize) {
+        match reg {
+            gimli::AArch64::X29 => self.fp = val,
+            gimli::AArch64::X30 => self.lr = val,
+            gimli::AArch64::SP => self.sp = val,
+            _ => {}
+        }
+    }
+
+    #[inline(always)]
+    fn current() -> Registers {
+        let (pc, sp, fp, lr): (usize, usize, usize, usize);
+        unsafe {
+            asm!(
+                "adr {pc}, .",
+                "mov {sp}, sp",
+                "mov {fp}, x29",
+                "mov {lr}, x30",
+                pc = out(reg) pc,
+                sp = out(reg) sp,
+                fp = out(reg) fp,
+                lr = out(reg) lr,
+                options(nomem, nostack, preserves_flags),
+            );
+        }
+        Registers { pc, sp, fp, lr }
+    }
+}
+
+#[inline(always)]
+pub unsafe fn trace(cb: &mut dyn FnMut(&super::Frame) -> bool) {
+    // The program counter read here isn't a return address, so it can be
+    // looked up as-is.
+    walk(Registers::current(), true, cb)
+}
+
+#[inline(always)]
+pub unsafe fn trace_from_context(
+    context: *const c_void,
+    cb: &mut dyn FnMut(&super::Frame) -> bool,
+) -> bool {
+    match context_registers(context) {
+        Some(regs) => {
+            walk(regs, true, cb);
+            true
+        }
+        None => false,
+    }
+}
+
+unsafe fn context_registers(context: *const c_void) -> Option<Registers> {
+    if context.is_null() {
+        return None;
+    }
+    let uc = &*(context as *const libc::ucontext_t);
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            let gregs = &uc.uc_mcontext.gregs;
+            Some(Registers {
+                pc: gregs[libc::REG_RIP as usize] as usize,
+                sp: gregs[libc::REG_RSP as usize] as usize,
+                fp: gregs[libc::REG_RBP as usize] as usize,
+            })
+        } else {
+            let mc = &uc.uc_mcontext;
+            Some(Registers {
+                pc: mc.pc as usize,
+                sp: mc.sp as usize,
+                fp: mc.regs[29] as usize,
+                lr: mc.regs[30] as usize,
+            })
+        }
+    }
+}
+
+unsafe fn walk(mut regs: Registers, mut exact_pc: bool, cb: &mut dyn FnMut(&super::Frame) -> bool) {
+    let mut ctx = UnwindContext::new();
+    let mut signal_frame = false;
+    let mut first = true;
+    loop {
+        if regs.pc == 0 {
+            break;
+        }
+        // Return addresses point after the call instruction, which may be
+        // the first instruction of the next FDE, so look up the one before.
+        // Frames interrupted by a signal are the exception as they resume at
+        // the faulting instruction itself.
+        let lookup = if exact_pc || signal_frame {
+            regs.pc
+        } else {
+            regs.pc - 1
+        };
+        let unwinder = find_unwinder(lookup);
+        // Step before yielding the frame as the caller's stack pointer is
+        // this frame's CFA.
+        let next = match &unwinder {
+            Some(u) => u.step(&mut ctx, &regs, lookup),
+            None => step_registered(&regs, lookup),
+        };
+
+        let frame = super::Frame {
+            inner: Frame {
+                ip: regs.pc as *mut c_void,
+                sp: regs.sp as *mut c_void,
+                symbol_address: unwinder
+                    .as_ref()
+                    .map(|u| u.function_start)
+                    .unwrap_or(regs.pc) as *mut c_void,
+                cfa: next.as_ref().map(|n| n.sp),
+                fp: regs.fp,
+                signal_frame,
+                // Only the first frame's registers aren't recovered by
+                // unwinding.
+                trust: if first {
+                    super::FrameTrust::Context
+                } else {
+                    super::FrameTrust::Cfi
+                },
+            },
+        };
+        if !cb(&frame) {
+            break;
+        }
+
+        let next = match next {
+            Some(next) => next,
+            None => break,
+        };
+        // Make sure we're always making progress up the stack, otherwise
+        // corrupt unwind information could make us loop forever.
+        if next.sp <= regs.sp {
+            break;
+        }
+        regs = next;
+        exact_pc = false;
+        first = false;
+        // The 'S' augmentation marks signal trampolines, whose caller is the
+        // frame which was interrupted.
+        signal_frame = match &unwinder {
+            Some(u) => u.fde.cie().is_signal_trampoline(),
+            None => false,
+        };
+    }
+}
+
+/// Unwinds with the rule registered with `register_unwind_rule` for code
+/// without an FDE.
+#[cfg(feature = "std")]
+unsafe fn step_registered(regs: &Registers, lookup: usize) -> Option<Registers> {
+    let rule = super::unwind_rules::find(lookup)?;
+    #[cfg(target_arch = "x86_64")]
+    let lr = 0;
+    #[cfg(target_arch = "aarch64")]
+    let lr = regs.lr;
+    let (pc, sp) = rule.step(regs.sp, lr);
+    let mut next = *regs;
+    next.pc = super::strip_pac(pc);
+    next.sp = sp;
+    Some(next)
+}
+
+#[cfg(not(feature = "std"))]
+unsafe fn step_registered(_regs: &Registers, _lookup: usize) -> Option<Registers> {
+    None
+}
+
+/// The unwind information of the object covering a specific address.
+struct Unwinder {
+    eh_frame: EhFrame<gimli::EndianSlice<'static, NativeEndian>>,
+    bases: BaseAddresses,
+    fde: gimli::FrameDescriptionEntry<gimli::EndianSlice<'static, NativeEndian>, usize>,
+    function_start: usize,
+}
+
+impl Unwinder {
+    unsafe fn step(
+        &self,
+        ctx: &mut UnwindContext<gimli::EndianSlice<'static, NativeEndian>>,
+        regs: &Registers,
+        lookup: usize,
+    ) -> Option<Registers> {
+        let row = self
+            .fde
+            .unwind_info_for_address(&self.eh_frame, &self.bases, ctx, lookup as u64)
+            .ok()?;
+        let cfa = match *row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                (regs.get(register)? as i64).wrapping_add(offset) as usize
+            }
+            CfaRule::Expression(_) => return None,
+        };
+
+        let mut next = *regs;
+        next.sp = cfa;
+        for reg in tracked_registers().iter() {
+            let val = match row.register(*reg) {
+                RegisterRule::Undefined => {
+                    if *reg == Registers::PC {
+                        0
+                    } else {
+                        continue;
+                    }
+                }
+                RegisterRule::SameValue => continue,
+                RegisterRule::Offset(off) => *((cfa as i64).wrapping_add(off) as *const usize),
+                RegisterRule::ValOffset(off) => (cfa as i64).wrapping_add(off) as usize,
+                RegisterRule::Register(other) => regs.get(other)?,
+                _ => return None,
+            };
+            next.set(*reg, val);
+        }
+
+        let ra = self.fde.cie().return_address_register();
+        next.pc = super::strip_pac(next.get(ra).unwrap_or(0));
+        Some(next)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn tracked_registers() -> [Register; 2] {
+    [gimli::X86_64::RA, gimli::X86_64::RBP]
+}
+
+#[cfg(target_arch = "aarch64")]
+fn tracked_registers() -> [Register; 2] {
+    [gimli::AArch64::X29, gimli::AArch64::X30]
+}
+
+/// Locates the `.eh_frame_hdr` of the loaded object containing `pc` and finds
+/// the FDE for `pc` in it.
+unsafe fn find_unwinder(pc: usize) -> Option<Unwinder> {
+    let mut search = Search {
+        pc,
+        eh_frame_hdr: 0,
+        eh_frame_hdr_len: 0,
+        segments: [(0, 0); MAX_SEGMENTS],
+        nsegments: 0,
+    };
+    if !find_object(&mut search) {
+        libc::dl_iterate_phdr(Some(callback), &mut search as *mut Search as *mut _);
+    }
+    if search.eh_frame_hdr == 0 {
+        return None;
+    }
+
+    let hdr_data = slice::from_raw_parts(search.eh_frame_hdr as *const u8, search.eh_frame_hdr_len);
+    let mut bases = BaseAddresses::default().set_eh_frame_hdr(search.eh_frame_hdr as u64);
+    let hdr = EhFrameHdr::new(hdr_data, NativeEndian)
+        .parse(&bases, mem::size_of::<usize>() as u8)
+        .ok()?;
+    let eh_frame_ptr = match hdr.eh_frame_ptr() {
+        Pointer::Direct(addr) => addr as usize,
+        Pointer::Indirect(addr) => *(addr as *const usize),
+    };
+
+    // The length of `.eh_frame` isn't recorded anywhere in memory, so bound
+    // it by the end of the loaded segment containing it.
+    let end = search.segments[..search.nsegments]
+        .iter()
+        .find(|(start, end)| *start <= eh_frame_ptr && eh_frame_ptr < *end)?
+        .1;
+    let eh_frame_data = slice::from_raw_parts(eh_frame_ptr as *const u8, end - eh_frame_ptr);
+    let eh_frame = EhFrame::new(eh_frame_data, NativeEndian);
+    bases = bases.set_eh_frame(eh_frame_ptr as u64);
+
+    let table = hdr.table()?;
+    let fde = table
+        .fde_for_address(&eh_frame, &bases, pc as u64, EhFrame::cie_from_offset)
+        .ok()?;
+    let function_start = fde.initial_address() as usize;
+    Some(Unwinder {
+        eh_frame,
+        bases,
+        fde,
+        function_start,
+    })
+}
+
+/// The `struct dl_find_object` of glibc on x86_64 and aarch64.
+#[repr(C)]
+struct DlFindObject {
+    dlfo_flags: u64,
+    dlfo_map_start: *mut c_void,
+    dlfo_map_end: *mut c_void,
+    dlfo_link_map: *mut c_void,
+    dlfo_eh_frame: *mut c_void,
+    __dlfo_reserved: [u64; 7],
+}
+
+type DlFindObjectFn = unsafe extern "C" fn(*mut c_void, *mut DlFindObject) -> libc::c_int;
+
+/// The address of `_dl_find_object`, or `NO_DL_FIND_OBJECT` if there isn't
+/// one, or 0 if it hasn't been looked up yet.
+static DL_FIND_OBJECT: AtomicUsize = AtomicUsize::new(0);
+const NO_DL_FIND_OBJECT: usize = 1;
+
+/// Fills in `search` with `_dl_find_object`, which glibc 2.35 added for
+/// unwinders.
+///
+/// Unlike `dl_iterate_phdr` it doesn't take the loader lock or go through
+/// every loaded object, but only does a binary search over them. It only
+/// tells us the range the whole object is mapped at rather than each of its
+/// segments, which is good enough to bound `.eh_frame` by though. Returns
+/// `false` if it isn't available, for `dl_iterate_phdr` to be used instead.
+unsafe fn find_object(search: &mut Search) -> bool {
+    let mut func = DL_FIND_OBJECT.load(Ordering::Relaxed);
+    if func == 0 {
+        // Look it up at runtime so that the same binary still works with
+        // older versions of glibc, or other libcs.
+        func = libc::dlsym(
+            libc::RTLD_DEFAULT,
+            b"_dl_find_object\0".as_ptr() as *const libc::c_char,
+        ) as usize;
+        if func == 0 {
+            func = NO_DL_FIND_OBJECT;
+        }
+        DL_FIND_OBJECT.store(func, Ordering::Relaxed);
+    }
+    if func == NO_DL_FIND_OBJECT {
+        return false;
+    }
+    let func = mem::transmute::<usize, DlFindObjectFn>(func);
+
+    let mut object = mem::zeroed::<DlFindObject>();
+    if func(search.pc as *mut c_void, &mut object) == 0 && !object.dlfo_eh_frame.is_null() {
+        let start = object.dlfo_map_start as usize;
+        let end = object.dlfo_map_end as usize;
+        search.eh_frame_hdr = object.dlfo_eh_frame as usize;
+        search.eh_frame_hdr_len = end.saturating_sub(search.eh_frame_hdr);
+        search.segments[0] = (start, end);
+        search.nsegments = 1;
+    }
+    true
+}
+
+const MAX_SEGMENTS: usize = 16;
+
+struct Search {
+    pc: usize,
+    eh_frame_hdr: usize,
+    eh_frame_hdr_len: usize,
+    segments: [(usize, usize); MAX_SEGMENTS],
+    nsegments: usize,
+}
+
+unsafe extern "C" fn callback(
+    info: *mut libc::dl_phdr_info,
+    _size: libc::size_t,
+    data: *mut libc::c_void,
+) -> libc::c_int {
+    let info = &*info;
+    let search = &mut *(data as *mut Search);
+    let bias = info.dlpi_addr as usize;
+    let headers = slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize);
+
+    let contains_pc = headers.iter().any(|h| {
+        let start = bias.wrapping_add(h.p_vaddr as usize);
+        h.p_type == libc::PT_LOAD && start <= search.pc && search.pc < start + h.p_memsz as usize
+    });
+    if !contains_pc {
+        return 0;
+    }
+
+    for h in headers {
+        let start = bias.wrapping_add(h.p_vaddr as usize);
+        match h.p_type {
+            libc::PT_GNU_EH_FRAME => {
+                search.eh_frame_hdr = start;
+                search.eh_frame_hdr_len = h.p_memsz as usize;
+            }
+            libc::PT_LOAD if search.nsegments < MAX_SEGMENTS => {
+                search.segments[search.nsegments] = (start, start + h.p_memsz as usize);
+                search.nsegments += 1;
+            }
+            _ => {}
+        }
+    }
+    // Stop iterating, we found the object.
+    1
+}
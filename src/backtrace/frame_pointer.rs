@@ -0,0 +1,82 @@
+//! Backtrace support by walking the chain of frame pointers.
+//!
+//! This is enabled with the `fp-unwind` feature of this crate and is an
+//! alternative to `_Unwind_Backtrace` for code which needs a very cheap
+//! capture, such as sampling profilers. Instead of interpreting DWARF unwind
+//! information this simply follows the linked list of saved frame pointers on
+//! the stack, which only takes a couple of loads per frame.
+//!
+//! This is only accurate if all code on the stack has been compiled with frame
+//! pointers (e.g. `-Cforce-frame-pointers=yes` for Rust code), otherwise
+//! frames will be missing or the walk will stop early. If the frame pointer of
+//! the capturing function doesn't look valid at all, `trace` reports that
+//! nothing was walked and the regular unwinder is used instead.
+
+use core::arch::asm;
+use core::ffi::c_void;
+use core::mem;
+
+/// Frames larger than this are assumed to indicate a corrupt chain.
+const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// Walks the frame pointer chain starting at the caller of this function.
+///
+/// Returns `false` without calling `cb` if frame pointers don't appear to be
+/// available, in which case the caller should fall back to another unwinder.
+#[inline(always)]
+pub unsafe fn trace(cb: &mut dyn FnMut(&super::Frame) -> bool) -> bool {
+    let mut fp = current_frame_pointer();
+    if !is_valid(fp, 0) {
+        return false;
+    }
+
+    loop {
+        // Both x86_64 and aarch64 lay out a frame record as the caller's
+        // frame pointer followed by the return address.
+        let record = fp as *const usize;
+        let next = *record;
+        let ip = super::strip_pac(*record.add(1));
+        if ip == 0 {
+            break;
+        }
+
+        let frame = super::Frame {
+            inner: super::FrameImp::Chained {
+                ip: ip as *mut c_void,
+                sp: (fp + 2 * mem::size_of::<usize>()) as *mut c_void,
+            },
+        };
+        if !cb(&frame) {
+            break;
+        }
+
+        if !is_valid(next, fp) {
+            break;
+        }
+        fp = next;
+    }
+    true
+}
+
+/// Checks that `fp` plausibly points at a frame record which is older than
+/// the one at `prev` (or is the first one if `prev` is zero).
+fn is_valid(fp: usize, prev: usize) -> bool {
+    if fp == 0 || fp % mem::align_of::<usize>() != 0 {
+        return false;
+    }
+    // Stacks grow downwards, so each caller's record must be at a higher
+    // address than its callee's.
+    prev == 0 || (fp > prev && fp - prev < MAX_FRAME_SIZE)
+}
+
+#[inline(always)]
+fn current_frame_pointer() -> usize {
+    let fp: usize;
+    unsafe {
+        #[cfg(target_arch = "x86_64")]
+        asm!("mov {}, rbp", out(reg) fp, options(nomem, nostack, preserves_flags));
+        #[cfg(target_arch = "aarch64")]
+        asm!("mov {}, x29", out(reg) fp, options(nomem, nostack, preserves_flags));
+    }
+    fp
+}
@@ -0,0 +1,135 @@
+//! Capturing backtraces on wasm32-unknown-unknown from the JS host.
+//!
+//! WebAssembly code can't walk its own stack, but the JS engine running it
+//! can, and reports the stack as text in `new Error().stack`. Each line of
+//! that is one frame, with a function name (the name from the module's
+//! `name` section for wasm frames) and where it is. There are no addresses,
+//! so frames carry their names and locations with them, which is what
+//! symbolization then reports.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use core::ffi::c_void;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+extern "C" {
+    type Error;
+
+    #[wasm_bindgen(constructor)]
+    fn new() -> Error;
+
+    // Not every engine sets `stack`, so this is read as any value.
+    #[wasm_bindgen(structural, method, getter)]
+    fn stack(error: &Error) -> JsValue;
+}
+
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub name: Option<Box<str>>,
+    /// Where the frame is: a script URL for JS frames, and for wasm frames
+    /// the module's URL followed by the function index and the offset in the
+    /// module, as in `app_bg.wasm:wasm-function[12]:0x3a4`.
+    pub filename: Option<Box<str>>,
+    pub lineno: Option<u32>,
+    pub colno: Option<u32>,
+}
+
+impl Frame {
+    pub fn ip(&self) -> *mut c_void {
+        core::ptr::null_mut()
+    }
+
+    pub fn sp(&self) -> *mut c_void {
+        core::ptr::null_mut()
+    }
+
+    pub fn symbol_address(&self) -> *mut c_void {
+        core::ptr::null_mut()
+    }
+
+    pub fn module_base_address(&self) -> Option<*mut c_void> {
+        None
+    }
+
+    pub fn is_signal_frame(&self) -> Option<bool> {
+        None
+    }
+
+    pub fn cfa(&self) -> Option<*mut c_void> {
+        None
+    }
+
+    pub fn frame_pointer(&self) -> Option<*mut c_void> {
+        None
+    }
+
+    pub fn register(&self, _dwarf_register: u16) -> Option<usize> {
+        None
+    }
+
+    pub fn trust(&self) -> super::FrameTrust {
+        super::FrameTrust::Unknown
+    }
+}
+
+pub fn trace(cb: &mut dyn FnMut(&super::Frame) -> bool) {
+    let stack: String = match Error::new().stack().as_string() {
+        Some(stack) => stack,
+        None => return,
+    };
+    for frame in stack.lines().filter_map(parse_line) {
+        if !cb(&super::Frame { inner: frame }) {
+            return;
+        }
+    }
+}
+
+// There's no saved register state to start from in JS.
+pub unsafe fn trace_from_context(
+    _context: *const c_void,
+    _cb: &mut dyn FnMut(&super::Frame) -> bool,
+) -> bool {
+    false
+}
+
+/// Parses one line of a stack, in either the V8 format of
+/// `    at name (location)` or `    at location`, or the Firefox and Safari
+/// format of `name@location`. Other lines, like the `Error` heading V8
+/// starts with, aren't frames.
+fn parse_line(line: &str) -> Option<Frame> {
+    let line = line.trim();
+    let (name, location) = if line.starts_with("at ") {
+        let line = &line[3..];
+        match line.find(" (") {
+            Some(i) if line.ends_with(')') => (&line[..i], &line[i + 2..line.len() - 1]),
+            _ => ("", line),
+        }
+    } else {
+        let i = line.find('@')?;
+        (&line[..i], &line[i + 1..])
+    };
+    if location.is_empty() {
+        return None;
+    }
+
+    // JS frames end with `:line:column`, while wasm frames are left as they
+    // are.
+    let mut parts = location.rsplitn(3, ':');
+    let colno = parts.next().and_then(|c| c.parse().ok());
+    let lineno = parts.next().and_then(|l| l.parse().ok());
+    let (filename, lineno, colno) = match (parts.next(), lineno, colno) {
+        (Some(filename), Some(lineno), Some(colno)) => (filename, Some(lineno), Some(colno)),
+        _ => (location, None, None),
+    };
+    Some(Frame {
+        name: if name.is_empty() {
+            None
+        } else {
+            Some(name.into())
+        },
+        filename: Some(filename.into()),
+        lineno,
+        colno,
+    })
+}
@@ -24,9 +24,36 @@ pub enum Frame {
         ip: *mut c_void,
         sp: *mut c_void,
         symbol_address: *mut c_void,
+        signal_frame: Option<bool>,
+        trust: super::FrameTrust,
+    },
+    #[allow(dead_code)]
+    Chained {
+        ip: *mut c_void,
+        sp: *mut c_void,
+    },
+    #[allow(dead_code)]
+    Scanned {
+        ip: *mut c_void,
+        sp: *mut c_void,
     },
 }
 
+// DWARF numbers of the callee-saved registers which `Frame::register` reports,
+// and the DWARF number of the frame pointer register.
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        const CALLEE_SAVED: [u16; 6] = [3, 6, 12, 13, 14, 15];
+        const FRAME_POINTER: Option<u16> = Some(6);
+    } else if #[cfg(target_arch = "aarch64")] {
+        const CALLEE_SAVED: [u16; 11] = [19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29];
+        const FRAME_POINTER: Option<u16> = Some(29);
+    } else {
+        const CALLEE_SAVED: [u16; 0] = [];
+        const FRAME_POINTER: Option<u16> = None;
+    }
+}
+
 // With a raw libunwind pointer it should only ever be access in a readonly
 // threadsafe fashion, so it's `Sync`. When sending to other threads via `Clone`
 // we always switch to a version which doesn't retain interior pointers, so we
@@ -38,21 +65,28 @@ impl Frame {
     pub fn ip(&self) -> *mut c_void {
         let ctx = match *self {
             Frame::Raw(ctx) => ctx,
-            Frame::Cloned { ip, .. } => return ip,
+            Frame::Cloned { ip, .. } | Frame::Chained { ip, .. } | Frame::Scanned { ip, .. } => {
+                return ip
+            }
         };
-        unsafe { uw::_Unwind_GetIP(ctx) as *mut c_void }
+        let ip = unsafe { uw::_Unwind_GetIP(ctx) as usize };
+        super::strip_pac(ip) as *mut c_void
     }
 
     pub fn sp(&self) -> *mut c_void {
         match *self {
             Frame::Raw(ctx) => unsafe { uw::get_sp(ctx) as *mut c_void },
-            Frame::Cloned { sp, .. } => sp,
+            Frame::Cloned { sp, .. } | Frame::Chained { sp, .. } | Frame::Scanned { sp, .. } => sp,
         }
     }
 
     pub fn symbol_address(&self) -> *mut c_void {
-        if let Frame::Cloned { symbol_address, .. } = *self {
-            return symbol_address;
+        match *self {
+            Frame::Cloned { symbol_address, .. } => return symbol_address,
+            // Scanned frames are typically in code without unwind information,
+            // which is what `_Unwind_FindEnclosingFunction` relies on.
+            Frame::Scanned { ip, .. } => return ip,
+            _ => {}
         }
 
         // The macOS linker emits a "compact" unwind table that only includes an
@@ -76,20 +110,128 @@ impl Frame {
     pub fn module_base_address(&self) -> Option<*mut c_void> {
         None
     }
+
+    pub fn is_signal_frame(&self) -> Option<bool> {
+        match *self {
+            Frame::Raw(ctx) => unsafe { uw::is_signal_frame(ctx) },
+            Frame::Cloned { signal_frame, .. } => signal_frame,
+            Frame::Chained { .. } => None,
+            Frame::Scanned { .. } => Some(false),
+        }
+    }
+
+    pub fn cfa(&self) -> Option<*mut c_void> {
+        match *self {
+            // Frame records are pushed right below the caller's stack
+            // pointer, which is exactly what we record as `sp` here.
+            Frame::Chained { sp, .. } => Some(sp),
+            // `_Unwind_GetCFA` returns the CFA with libgcc but the stack
+            // pointer with LLVM's libunwind, so we can't tell in general.
+            Frame::Raw(_) | Frame::Cloned { .. } | Frame::Scanned { .. } => None,
+        }
+    }
+
+    pub fn frame_pointer(&self) -> Option<*mut c_void> {
+        match *self {
+            Frame::Chained { sp, .. } => {
+                Some((sp as usize - 2 * core::mem::size_of::<usize>()) as *mut c_void)
+            }
+            _ => self.register(FRAME_POINTER?).map(|fp| fp as *mut c_void),
+        }
+    }
+
+    pub fn register(&self, dwarf_register: u16) -> Option<usize> {
+        // Registers are only read when asked for, as reading them all for
+        // every frame is slow. For the same reason clones don't keep them.
+        if !CALLEE_SAVED.contains(&dwarf_register) {
+            return None;
+        }
+        match *self {
+            Frame::Raw(ctx) => Some(unsafe { uw::_Unwind_GetGR(ctx, dwarf_register as _) }),
+            Frame::Cloned { .. } | Frame::Chained { .. } | Frame::Scanned { .. } => None,
+        }
+    }
+
+    pub fn trust(&self) -> super::FrameTrust {
+        match *self {
+            Frame::Raw(_) => super::FrameTrust::Cfi,
+            Frame::Cloned { trust, .. } => trust,
+            Frame::Chained { .. } => super::FrameTrust::FramePointer,
+            Frame::Scanned { .. } => super::FrameTrust::Scan,
+        }
+    }
 }
 
 impl Clone for Frame {
     fn clone(&self) -> Frame {
+        if let Frame::Scanned { ip, sp } = *self {
+            return Frame::Scanned { ip, sp };
+        }
         Frame::Cloned {
             ip: self.ip(),
             sp: self.sp(),
             symbol_address: self.symbol_address(),
+            signal_frame: self.is_signal_frame(),
+            trust: self.trust(),
+        }
+    }
+}
+
+#[inline(always)]
+pub unsafe fn trace(cb: &mut dyn FnMut(&super::Frame) -> bool) {
+    // With the `fp-unwind` feature try the much cheaper frame pointer walk
+    // first, falling back to the system unwinder if frame pointers aren't
+    // available.
+    #[cfg(all(
+        feature = "fp-unwind",
+        any(target_arch = "x86_64", target_arch = "aarch64"),
+    ))]
+    {
+        if super::frame_pointer::trace(cb) {
+            return;
         }
     }
+    trace_unwind(cb)
 }
 
 #[inline(always)]
-pub unsafe fn trace(mut cb: &mut dyn FnMut(&super::Frame) -> bool) {
+unsafe fn trace_unwind(cb: &mut dyn FnMut(&super::Frame) -> bool) {
+    #[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+    {
+        if super::stack_scanning() {
+            return trace_unwind_and_scan(cb);
+        }
+    }
+    trace_unwind_raw(cb)
+}
+
+/// Unwinds as usual, and then continues by scanning the stack for return
+/// addresses if the unwinder ran out of frames before `cb` was done.
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+#[inline(always)]
+unsafe fn trace_unwind_and_scan(cb: &mut dyn FnMut(&super::Frame) -> bool) {
+    let mut last_sp = 0;
+    let mut done = false;
+    trace_unwind_raw(&mut |frame: &super::Frame| {
+        last_sp = frame.sp() as usize;
+        done = !cb(frame);
+        !done
+    });
+    if done || last_sp == 0 {
+        return;
+    }
+    super::scan::scan(last_sp, &mut |ip, sp| {
+        cb(&super::Frame {
+            inner: Frame::Scanned {
+                ip: ip as *mut c_void,
+                sp: sp as *mut c_void,
+            },
+        })
+    });
+}
+
+#[inline(always)]
+unsafe fn trace_unwind_raw(mut cb: &mut dyn FnMut(&super::Frame) -> bool) {
     uw::_Unwind_Backtrace(trace_fn, &mut cb as *mut _ as *mut _);
 
     extern "C" fn trace_fn(
@@ -113,6 +255,102 @@ pub unsafe fn trace(mut cb: &mut dyn FnMut(&super::Frame) -> bool) {
     }
 }
 
+/// Unwinds starting from the register state saved in a `ucontext_t`.
+///
+/// The system unwinder has no way to start from an arbitrary register state,
+/// but it is able to unwind through the signal trampoline that the kernel sets
+/// up. We therefore unwind from here as usual and skip all frames up to the
+/// interrupted one, identified by its program counter. This is only a best
+/// effort: if that frame is never found (e.g. the context didn't come from a
+/// signal on this thread) we only yield the interrupted frame itself and
+/// return `false`.
+#[inline(always)]
+pub unsafe fn trace_from_context(
+    context: *const c_void,
+    cb: &mut dyn FnMut(&super::Frame) -> bool,
+) -> bool {
+    let (pc, sp) = match context_registers(context) {
+        Some(regs) => regs,
+        None => return false,
+    };
+
+    // Frame pointers can't be followed through a signal trampoline, so this
+    // always uses the system unwinder.
+    let mut found = false;
+    trace_unwind(&mut |frame: &super::Frame| {
+        if !found {
+            // The frame of the interrupted code was not entered through a
+            // call, so its reported ip is exactly its program counter, modulo
+            // unwinders which unconditionally add one to signal frames.
+            let ip = frame.ip() as usize;
+            if ip != pc && ip != pc + 1 {
+                return true;
+            }
+            found = true;
+            // Its registers are restored from the context as they are.
+            let mut seed = frame.clone();
+            if let Frame::Cloned { ref mut trust, .. } = seed.inner {
+                *trust = super::FrameTrust::Context;
+            }
+            return cb(&seed);
+        }
+        cb(frame)
+    });
+
+    if !found {
+        let frame = super::Frame {
+            inner: Frame::Cloned {
+                ip: pc as *mut c_void,
+                sp: sp as *mut c_void,
+                symbol_address: pc as *mut c_void,
+                signal_frame: None,
+                trust: super::FrameTrust::Context,
+            },
+        };
+        cb(&frame);
+    }
+    found
+}
+
+/// Extracts the program counter and stack pointer from a `ucontext_t`.
+#[allow(unused_variables)]
+unsafe fn context_registers(context: *const c_void) -> Option<(usize, usize)> {
+    if context.is_null() {
+        return None;
+    }
+    cfg_if::cfg_if! {
+        if #[cfg(all(
+            any(target_os = "linux", target_os = "android"),
+            target_arch = "x86_64",
+        ))] {
+            let uc = &*(context as *const libc::ucontext_t);
+            let gregs = &uc.uc_mcontext.gregs;
+            Some((
+                gregs[libc::REG_RIP as usize] as usize,
+                gregs[libc::REG_RSP as usize] as usize,
+            ))
+        } else if #[cfg(all(
+            any(target_os = "linux", target_os = "android"),
+            target_arch = "x86",
+        ))] {
+            let uc = &*(context as *const libc::ucontext_t);
+            let gregs = &uc.uc_mcontext.gregs;
+            Some((
+                gregs[libc::REG_EIP as usize] as usize,
+                gregs[libc::REG_ESP as usize] as usize,
+            ))
+        } else if #[cfg(all(
+            any(target_os = "linux", target_os = "android"),
+            target_arch = "aarch64",
+        ))] {
+            let uc = &*(context as *const libc::ucontext_t);
+            Some((uc.uc_mcontext.pc as usize, uc.uc_mcontext.sp as usize))
+        } else {
+            None
+        }
+    }
+}
+
 /// Unwind library interface used for backtraces
 ///
 /// Note that dead code is allowed as here are just bindings
@@ -163,6 +401,11 @@ mod uw {
             extern "C" {
                 pub fn _Unwind_GetIP(ctx: *mut _Unwind_Context) -> libc::uintptr_t;
                 pub fn _Unwind_FindEnclosingFunction(pc: *mut c_void) -> *mut c_void;
+                pub fn _Unwind_GetGR(ctx: *mut _Unwind_Context, index: libc::c_int) -> libc::uintptr_t;
+                pub fn _Unwind_GetIPInfo(
+                    ctx: *mut _Unwind_Context,
+                    ip_before_insn: *mut libc::c_int,
+                ) -> libc::uintptr_t;
 
                 #[cfg(not(all(target_os = "linux", target_arch = "s390x")))]
                 // This function is a misnomer: rather than getting this frame's
@@ -175,14 +418,21 @@ mod uw {
 
             }
 
+            // Both libgcc and LLVM's libunwind report through `ip_before_insn`
+            // whether the frame was interrupted by a signal, in which case
+            // its IP is the interrupted instruction rather than a return
+            // address.
+            pub unsafe fn is_signal_frame(ctx: *mut _Unwind_Context) -> Option<bool> {
+                let mut ip_before_insn = 0;
+                _Unwind_GetIPInfo(ctx, &mut ip_before_insn);
+                Some(ip_before_insn != 0)
+            }
+
             // s390x uses a biased CFA value, therefore we need to use
             // _Unwind_GetGR to get the stack pointer register (%r15)
             // instead of relying on _Unwind_GetCFA.
             #[cfg(all(target_os = "linux", target_arch = "s390x"))]
             pub unsafe fn get_sp(ctx: *mut _Unwind_Context) -> libc::uintptr_t {
-                extern "C" {
-                    pub fn _Unwind_GetGR(ctx: *mut _Unwind_Context, index: libc::c_int) -> libc::uintptr_t;
-                }
                 _Unwind_GetGR(ctx, 15)
             }
         } else {
@@ -241,16 +491,25 @@ mod uw {
                 (val & !1) as libc::uintptr_t
             }
 
+            // ARM EHABI doesn't have a notion of signal frames.
+            pub unsafe fn is_signal_frame(_ctx: *mut _Unwind_Context) -> Option<bool> {
+                None
+            }
+
             // R13 is the stack pointer on arm.
             const SP: _Unwind_Word = 13;
 
             pub unsafe fn get_sp(ctx: *mut _Unwind_Context) -> libc::uintptr_t {
+                _Unwind_GetGR(ctx, SP as libc::c_int)
+            }
+
+            pub unsafe fn _Unwind_GetGR(ctx: *mut _Unwind_Context, index: libc::c_int) -> libc::uintptr_t {
                 let mut val: _Unwind_Word = 0;
                 let ptr = &mut val as *mut _Unwind_Word;
                 let _ = _Unwind_VRS_Get(
                     ctx,
                     _Unwind_VRS_RegClass::_UVRSC_CORE,
-                    SP,
+                    index as _Unwind_Word,
                     _Unwind_VRS_DataRepresentation::_UVRSD_UINT32,
                     ptr as *mut c_void,
                 );
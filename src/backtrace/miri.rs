@@ -54,6 +54,26 @@ impl Frame {
     pub fn module_base_address(&self) -> Option<*mut c_void> {
         None
     }
+
+    pub fn is_signal_frame(&self) -> Option<bool> {
+        None
+    }
+
+    pub fn cfa(&self) -> Option<*mut c_void> {
+        None
+    }
+
+    pub fn frame_pointer(&self) -> Option<*mut c_void> {
+        None
+    }
+
+    pub fn register(&self, _dwarf_register: u16) -> Option<usize> {
+        None
+    }
+
+    pub fn trust(&self) -> super::FrameTrust {
+        super::FrameTrust::Unknown
+    }
 }
 
 pub fn trace<F: FnMut(&super::Frame) -> bool>(cb: F) {
@@ -62,6 +82,14 @@ pub fn trace<F: FnMut(&super::Frame) -> bool>(cb: F) {
     unsafe { trace_unsynchronized(cb) };
 }
 
+// Miri has no notion of saved register state, so there's nothing to unwind.
+pub unsafe fn trace_from_context<F>(_context: *const c_void, _cb: F) -> bool
+where
+    F: FnMut(&super::Frame) -> bool,
+{
+    false
+}
+
 pub fn resolve_addr(ptr: *mut c_void) -> Frame {
     // SAFETY: Miri will stop execution with an error if this pointer
     // is invalid.
@@ -1,5 +1,7 @@
 use core::ffi::c_void;
 use core::fmt;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 /// Inspects the current call-stack, passing all active frames into the closure
 /// provided to calculate a stack trace.
@@ -50,6 +52,7 @@ use core::fmt;
 #[cfg(feature = "std")]
 pub fn trace<F: FnMut(&Frame) -> bool>(cb: F) {
     let _guard = crate::lock::lock();
+    init_max_frames();
     unsafe { trace_unsynchronized(cb) }
 }
 
@@ -63,7 +66,403 @@ pub fn trace<F: FnMut(&Frame) -> bool>(cb: F) {
 ///
 /// See information on `trace` for caveats on `cb` panicking.
 pub unsafe fn trace_unsynchronized<F: FnMut(&Frame) -> bool>(mut cb: F) {
-    trace_imp(&mut cb)
+    trace_guarded(&mut cb);
+}
+
+/// Same as `trace_unsynchronized`, but returns whether the walk was cut short
+/// by the frame limit or because a cycle was detected.
+pub(crate) unsafe fn trace_guarded(cb: &mut dyn FnMut(&Frame) -> bool) -> bool {
+    let mut guard = Guard::new();
+    trace_imp(&mut |frame: &Frame| guard.check(frame) && cb(frame));
+    guard.truncated
+}
+
+/// Captures the current call-stack into `frames`, returning how many of its
+/// elements were written, starting from the first.
+///
+/// This is `trace` for when allocating isn't an option: the walk stops once
+/// `frames` is full, and the crate doesn't allocate while capturing (though
+/// Miri and the `js` backend can't avoid it). The first call of the process
+/// to any trace function reads `BACKTRACE_MAX_FRAMES` from the environment,
+/// which does allocate, so call `max_frames` beforehand if this needs to hold
+/// from the start.
+///
+/// The written frames are initialized and are the caller's to drop, which
+/// only matters under Miri and with the `js` feature, where frames own the
+/// names they were captured with.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::Frame;
+/// use std::mem::MaybeUninit;
+///
+/// let mut frames: [MaybeUninit<Frame>; 32] = unsafe { MaybeUninit::uninit().assume_init() };
+/// let len = backtrace::trace_into(&mut frames);
+/// for frame in &frames[..len] {
+///     let frame = unsafe { &*frame.as_ptr() };
+///     // ...
+/// #   let _ = frame;
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub fn trace_into(frames: &mut [MaybeUninit<Frame>]) -> usize {
+    let _guard = crate::lock::lock();
+    init_max_frames();
+    unsafe { trace_into_unsynchronized(frames) }
+}
+
+/// Same as `trace_into`, only unsafe as it's unsynchronized.
+///
+/// This function does not have synchronization guarantees but is available
+/// when the `std` feature of this crate isn't compiled in, and unlike
+/// `trace_into` takes no lock, so it can be used from a signal handler where
+/// the platform's unwinder can. See the `trace_into` function for more
+/// documentation.
+///
+/// # Safety
+///
+/// This must not be called concurrently with the synchronized functions of
+/// this crate, which is what the lock taken by `trace_into` ensures.
+pub unsafe fn trace_into_unsynchronized(frames: &mut [MaybeUninit<Frame>]) -> usize {
+    let mut len = 0;
+    trace_unsynchronized(|frame| match frames.get_mut(len) {
+        Some(slot) => {
+            *slot = MaybeUninit::new(frame.clone());
+            len += 1;
+            true
+        }
+        None => false,
+    });
+    len
+}
+
+/// Same as `trace_into`, but only captures the instruction pointer of each
+/// frame, as returned by `Frame::ip`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn trace_ips_into(ips: &mut [*mut c_void]) -> usize {
+    let _guard = crate::lock::lock();
+    init_max_frames();
+    unsafe { trace_ips_into_unsynchronized(ips) }
+}
+
+/// Same as `trace_ips_into`, only unsafe as it's unsynchronized.
+///
+/// See `trace_into_unsynchronized` for more information.
+///
+/// # Safety
+///
+/// See information on `trace_into_unsynchronized`.
+pub unsafe fn trace_ips_into_unsynchronized(ips: &mut [*mut c_void]) -> usize {
+    let mut len = 0;
+    trace_unsynchronized(|frame| match ips.get_mut(len) {
+        Some(slot) => {
+            *slot = frame.ip();
+            len += 1;
+            true
+        }
+        None => false,
+    });
+    len
+}
+
+/// Captures the current call-stack, returning an iterator over its frames.
+///
+/// This is `trace` without the callback: the whole stack is walked up front
+/// and the frames are then handed out one by one, most recently called
+/// function first, so the caller is free to do anything while iterating,
+/// including capturing other backtraces. Like with `trace` the first frames
+/// are those of this crate's own functions. Prefer `trace` or `trace_into`
+/// where the allocation matters.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Example
+///
+/// ```
+/// for frame in backtrace::frames() {
+///     backtrace::resolve_frame(&frame, |symbol| {
+///         // ...
+/// #       let _ = symbol;
+///     });
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub fn frames() -> Frames {
+    let mut frames = std::vec::Vec::new();
+    trace(|frame| {
+        frames.push(frame.clone());
+        true
+    });
+    Frames {
+        inner: frames.into_iter(),
+    }
+}
+
+/// An iterator over the frames of a call-stack, as returned by `frames`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct Frames {
+    inner: std::vec::IntoIter<Frame>,
+}
+
+#[cfg(feature = "std")]
+impl Iterator for Frames {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(feature = "std")]
+impl DoubleEndedIterator for Frames {
+    fn next_back(&mut self) -> Option<Frame> {
+        self.inner.next_back()
+    }
+}
+
+#[cfg(feature = "std")]
+impl ExactSizeIterator for Frames {}
+
+/// Inspects a call-stack starting from a saved register state, passing all
+/// active frames into the closure provided.
+///
+/// This is intended for crash handlers: from inside of a signal handler or a
+/// vectored exception handler the interesting stack is the one which was
+/// interrupted, not the one of the handler itself. The `context` argument
+/// points to the saved register state of the interrupted code and the first
+/// frame yielded corresponds to its faulting instruction.
+///
+/// The type that `context` points to is platform specific:
+///
+/// * On Unix this is a `ucontext_t`, typically the third argument of a
+///   `SA_SIGINFO` signal handler.
+/// * On Windows this is a `CONTEXT`, typically the `ContextRecord` of an
+///   `EXCEPTION_POINTERS`.
+///
+/// Otherwise this behaves the same as `trace`. On platforms where the layout
+/// of the saved register state isn't known this function yields no frames.
+///
+/// Returns whether the frames after the first one are the callers of the
+/// interrupted code. Most unwinders start from `context` directly, but the
+/// system unwinder used on most Unix platforms can't: there the stack of the
+/// caller, which must be the thread that `context` was saved on, is walked
+/// instead and the frames up to the interrupted one, found by its program
+/// counter, are skipped. This is a best-effort filter, and if the interrupted
+/// frame isn't on that stack only it is yielded and `false` is returned.
+/// `false` is also returned when no frames are yielded at all.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Safety
+///
+/// The `context` pointer must point to a valid, initialized register state of
+/// the appropriate type for this platform, and the stack it describes must
+/// still be live for the duration of this call.
+#[cfg(feature = "std")]
+pub unsafe fn trace_from_context<F: FnMut(&Frame) -> bool>(context: *const c_void, cb: F) -> bool {
+    let _guard = crate::lock::lock();
+    init_max_frames();
+    trace_from_context_unsynchronized(context, cb)
+}
+
+/// Same as `trace_from_context`, only unsynchronized.
+///
+/// This function does not have synchronization guarantees but is available
+/// when the `std` feature of this crate isn't compiled in. See the
+/// `trace_from_context` function for more documentation.
+///
+/// # Safety
+///
+/// See information on `trace_from_context` for the requirements on `context`.
+pub unsafe fn trace_from_context_unsynchronized<F: FnMut(&Frame) -> bool>(
+    context: *const c_void,
+    mut cb: F,
+) -> bool {
+    trace_from_context_guarded(context, &mut cb).0
+}
+
+/// Same as `trace_from_context_unsynchronized`, but also returns whether the
+/// walk was cut short by the frame limit or because a cycle was detected.
+pub(crate) unsafe fn trace_from_context_guarded(
+    context: *const c_void,
+    cb: &mut dyn FnMut(&Frame) -> bool,
+) -> (bool, bool) {
+    let mut guard = Guard::new();
+    let unwound = trace_from_context_imp(context, &mut |frame: &Frame| {
+        guard.check(frame) && cb(frame)
+    });
+    (unwound, guard.truncated)
+}
+
+/// The number of frames after which a trace is stopped unless configured
+/// otherwise.
+const DEFAULT_MAX_FRAMES: usize = 1 << 16;
+
+// Zero means "not yet initialized" until `init_max_frames` consults the
+// environment.
+static MAX_FRAMES: AtomicUsize = AtomicUsize::new(0);
+
+/// Configures the maximum number of frames yielded by a single call to `trace`
+/// and its variants, including when capturing a `Backtrace`.
+///
+/// Corrupted stacks can otherwise cause a backtrace to never terminate on some
+/// platforms. The default limit is 65536 frames and can also be set with the
+/// `BACKTRACE_MAX_FRAMES` environment variable; calling this function
+/// overrides the environment. A `max` of zero is treated as one.
+///
+/// The environment is read by `max_frames`, by the functions which take the
+/// crate's lock such as `trace` and `Backtrace::new`, and by the functions
+/// preparing for traces from signal handlers, such as `signal_safe::prepare`.
+/// The unsynchronized functions don't read it, as they may run where that
+/// isn't safe, and use the default limit until one of the others has.
+pub fn set_max_frames(max: usize) {
+    MAX_FRAMES.store(max.max(1), Ordering::Relaxed);
+}
+
+/// Returns the maximum number of frames yielded by a single trace.
+///
+/// See `set_max_frames` for more information.
+pub fn max_frames() -> usize {
+    init_max_frames();
+    configured_max_frames()
+}
+
+/// Reads the limit from the environment unless it's configured already.
+///
+/// Reading the environment may allocate and take a lock, so this is called
+/// where a trace is set up rather than during the trace.
+pub(crate) fn init_max_frames() {
+    if MAX_FRAMES.load(Ordering::Relaxed) == 0 {
+        let max = max_frames_from_env().unwrap_or(DEFAULT_MAX_FRAMES);
+        // `set_max_frames` may have been called in the meantime, which wins.
+        let _ = MAX_FRAMES.compare_exchange(0, max, Ordering::Relaxed, Ordering::Relaxed);
+    }
+}
+
+fn configured_max_frames() -> usize {
+    match MAX_FRAMES.load(Ordering::Relaxed) {
+        0 => DEFAULT_MAX_FRAMES,
+        max => max,
+    }
+}
+
+#[cfg(feature = "std")]
+fn max_frames_from_env() -> Option<usize> {
+    let var = std::env::var("BACKTRACE_MAX_FRAMES").ok()?;
+    var.trim().parse::<usize>().ok().map(|max| max.max(1))
+}
+
+#[cfg(not(feature = "std"))]
+fn max_frames_from_env() -> Option<usize> {
+    None
+}
+
+static STACK_SCANNING: AtomicBool = AtomicBool::new(false);
+
+/// Configures whether traces are continued by scanning the stack when the
+/// unwinder runs into a frame without unwind information, which is off by
+/// default.
+///
+/// Without unwind information, as for hand-written assembly or stripped
+/// libraries, the unwinder can't tell where the caller's frame is, so a trace
+/// ends there. With scanning turned on, the stack above the last frame found
+/// is searched for values which look like return addresses instead: pointers
+/// into an executable mapping of the process right after a call instruction.
+/// The frames found this way may well be leftovers of calls which returned
+/// already, so they're reported as such by `Frame::is_scanned`.
+///
+/// Scanning reads the memory map of the process, so traces which end early
+/// allocate and read from the filesystem while it's turned on.
+///
+/// # Caveats
+///
+/// This only has an effect on Linux and Android with the system unwinder, and
+/// not with the frame pointer walk of the `fp-unwind` feature.
+pub fn set_stack_scanning(enabled: bool) {
+    STACK_SCANNING.store(enabled, Ordering::Relaxed);
+}
+
+#[allow(dead_code)]
+pub(crate) fn stack_scanning() -> bool {
+    STACK_SCANNING.load(Ordering::Relaxed)
+}
+
+/// Makes sure a walk terminates even if the stack is corrupt.
+///
+/// Besides enforcing `max_frames`, this detects cycles in the sequence of
+/// (ip, sp) pairs yielded by the unwinder with Brent's algorithm, which only
+/// needs constant space so it works without an allocator as well.
+struct Guard {
+    remaining: usize,
+    saved: (usize, usize),
+    power: usize,
+    steps: usize,
+    truncated: bool,
+}
+
+impl Guard {
+    fn new() -> Guard {
+        Guard {
+            remaining: configured_max_frames(),
+            saved: (0, 0),
+            power: 1,
+            steps: 0,
+            truncated: false,
+        }
+    }
+
+    fn check(&mut self, frame: &Frame) -> bool {
+        if self.remaining == 0 {
+            self.truncated = true;
+            return false;
+        }
+        self.remaining -= 1;
+
+        // Backends which can't recover the stack pointer report it as null,
+        // and then recursion would look like a cycle.
+        let pair = (frame.ip() as usize, frame.sp() as usize);
+        if pair.1 == 0 {
+            return true;
+        }
+        if pair == self.saved {
+            self.truncated = true;
+            return false;
+        }
+        self.steps += 1;
+        if self.steps == self.power {
+            self.saved = pair;
+            self.power *= 2;
+            self.steps = 0;
+        }
+        true
+    }
 }
 
 /// A trait representing one frame of a backtrace, yielded to the `trace`
@@ -90,6 +489,18 @@ impl Frame {
         self.inner.ip()
     }
 
+    /// Returns the address which `backtrace::resolve_frame` looks up for this
+    /// frame.
+    ///
+    /// As `ip` is normally a return address, this is usually `ip` minus one,
+    /// so that the call instruction is looked up rather than the instruction
+    /// after it. For frames interrupted by a signal it's `ip` itself. Use
+    /// `backtrace::resolve_frame_with_ip_adjustment` to look up frames
+    /// otherwise, see `IpAdjustment` for more information.
+    pub fn adjusted_ip(&self) -> *mut c_void {
+        super::symbolize::ResolveWhat::Frame(self).address_or_ip()
+    }
+
     /// Returns the current stack pointer of this frame.
     ///
     /// In the case that a backend cannot recover the stack pointer for this
@@ -114,6 +525,128 @@ impl Frame {
     pub fn module_base_address(&self) -> Option<*mut c_void> {
         self.inner.module_base_address()
     }
+
+    /// Returns whether this frame was interrupted by a signal.
+    ///
+    /// For such frames the caller in the backtrace is a signal trampoline
+    /// rather than real code, and `ip` is the address of the interrupted
+    /// instruction itself instead of a return address. Symbolization takes
+    /// this into account automatically when given a `Frame`.
+    ///
+    /// Returns `None` if the backend can't tell.
+    pub fn is_signal_frame(&self) -> Option<bool> {
+        self.inner.is_signal_frame()
+    }
+
+    /// Returns the canonical frame address (CFA) of this frame.
+    ///
+    /// The CFA is the value of the stack pointer in the caller just before the
+    /// call instruction which created this frame. Not all backends are able to
+    /// compute this, in which case `None` is returned.
+    pub fn cfa(&self) -> Option<*mut c_void> {
+        self.inner.cfa()
+    }
+
+    /// Returns the value of the frame pointer register in this frame.
+    ///
+    /// Returns `None` if the backend doesn't know the frame pointer. Note that
+    /// code compiled without frame pointers may use this register for other
+    /// purposes, so the value isn't necessarily a pointer into the stack.
+    pub fn frame_pointer(&self) -> Option<*mut c_void> {
+        self.inner.frame_pointer()
+    }
+
+    /// Returns the value of a callee-saved register in this frame.
+    ///
+    /// Registers are identified by their DWARF register number for the current
+    /// architecture. Only a small set of callee-saved registers is available,
+    /// and only with some backends (currently `_Unwind_Backtrace` and the
+    /// `dwarf-unwind` unwinder on x86_64 and aarch64). `None` is returned for
+    /// everything else, including for clones of frames from
+    /// `_Unwind_Backtrace`, whose registers can only be read while tracing.
+    pub fn register(&self, dwarf_register: u16) -> Option<usize> {
+        self.inner.register(dwarf_register)
+    }
+
+    /// Returns whether this frame was found by scanning the stack for return
+    /// addresses rather than by unwinding, in which case it may not be a frame
+    /// of the call stack at all.
+    ///
+    /// This is the same as `self.trust() == FrameTrust::Scan`, see
+    /// `set_stack_scanning` for more information.
+    pub fn is_scanned(&self) -> bool {
+        self.trust() == FrameTrust::Scan
+    }
+
+    /// Returns how this frame was found, which tells how much it can be
+    /// relied upon.
+    pub fn trust(&self) -> FrameTrust {
+        self.inner.trust()
+    }
+}
+
+/// How a frame of a trace was found, as returned by `Frame::trust`.
+///
+/// Crash triage tools weight frames by how they were found, like Breakpad's
+/// processor does: a frame restored from a saved register state is certain,
+/// while one found by scanning the stack may be a leftover of a call which
+/// returned already. Variants are ordered from the least to the most
+/// trustworthy, so they can be compared.
+///
+/// Which variants a backend reports depends on the platform: the system
+/// unwinder of Unix platforms reports all but `Unknown`, the `dwarf-unwind`
+/// unwinder reports `Context` and `Cfi`, and on Windows all frames are
+/// reported as `Cfi`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum FrameTrust {
+    /// The backend doesn't tell how it found the frame, as for the frames of
+    /// Miri and of JavaScript's stack traces, and for frames deserialized
+    /// from another format.
+    Unknown,
+    /// Found by scanning the stack for something which looks like a return
+    /// address, see `set_stack_scanning`.
+    Scan,
+    /// Found by following the chain of saved frame pointers, which is only
+    /// right if all code on the stack maintains it.
+    FramePointer,
+    /// Found by unwinding with the call frame information (CFI) of the code,
+    /// such as the `.eh_frame` section of ELF objects.
+    Cfi,
+    /// Taken from a saved register state, such as the first frame of
+    /// `trace_from_context` or of the `dwarf-unwind` unwinder.
+    Context,
+}
+
+/// Strips a pointer authentication code from a return address.
+///
+/// With pointer authentication (armv8.3+, and arm64e on Apple platforms)
+/// return addresses saved on the stack carry a signature in their upper bits,
+/// which has to be removed before they can be symbolized.
+#[allow(dead_code)]
+#[inline]
+pub(crate) fn strip_pac(ip: usize) -> usize {
+    cfg_if::cfg_if! {
+        if #[cfg(all(target_arch = "aarch64", backtrace_asm, not(miri)))] {
+            let mut ip = ip;
+            // `xpaclri` lives in the hint space, so it's a no-op on cores
+            // without pointer authentication. It only operates on `x30`.
+            unsafe {
+                core::arch::asm!(
+                    "hint #7",
+                    inout("x30") ip,
+                    options(nomem, nostack, preserves_flags),
+                );
+            }
+            ip
+        } else if #[cfg(target_arch = "aarch64")] {
+            // Without inline assembly, clear the bits above the 48 bits of
+            // virtual address used by user space, where the signature goes.
+            ip & ((1 << 48) - 1)
+        } else {
+            ip
+        }
+    }
 }
 
 impl fmt::Debug for Frame {
@@ -125,13 +658,36 @@ impl fmt::Debug for Frame {
     }
 }
 
+#[cfg(feature = "std")]
+mod unwind_rules;
+#[cfg(feature = "std")]
+pub use self::unwind_rules::{register_unwind_rule, unregister_unwind_rule, UnwindRule};
+
 cfg_if::cfg_if! {
     // This needs to come first, to ensure that
     // Miri takes priority over the host platform
     if #[cfg(miri)] {
         pub(crate) mod miri;
         use self::miri::trace as trace_imp;
+        use self::miri::trace_from_context as trace_from_context_imp;
         pub(crate) use self::miri::Frame as FrameImp;
+        pub(crate) const SUPPORTED: bool = true;
+    } else if #[cfg(all(feature = "js", target_arch = "wasm32", target_os = "unknown"))] {
+        pub(crate) mod js;
+        use self::js::trace as trace_imp;
+        use self::js::trace_from_context as trace_from_context_imp;
+        pub(crate) use self::js::Frame as FrameImp;
+        pub(crate) const SUPPORTED: bool = true;
+    } else if #[cfg(all(
+        feature = "dwarf-unwind",
+        any(target_os = "linux", target_os = "android"),
+        any(target_arch = "x86_64", target_arch = "aarch64"),
+    ))] {
+        mod dwarf;
+        use self::dwarf::trace as trace_imp;
+        use self::dwarf::trace_from_context as trace_from_context_imp;
+        pub(crate) use self::dwarf::Frame as FrameImp;
+        pub(crate) const SUPPORTED: bool = true;
     } else if #[cfg(
         any(
             all(
@@ -143,20 +699,37 @@ cfg_if::cfg_if! {
                 target_env = "sgx",
                 target_vendor = "fortanix",
             ),
+            target_os = "hermit",
         )
     )] {
         mod libunwind;
+        #[cfg(all(
+            feature = "fp-unwind",
+            any(target_arch = "x86_64", target_arch = "aarch64"),
+        ))]
+        mod frame_pointer;
+        #[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+        mod scan;
         use self::libunwind::trace as trace_imp;
+        use self::libunwind::trace_from_context as trace_from_context_imp;
         pub(crate) use self::libunwind::Frame as FrameImp;
+        pub(crate) const SUPPORTED: bool = true;
     } else if #[cfg(all(windows, not(target_vendor = "uwp")))] {
         mod dbghelp;
         use self::dbghelp::trace as trace_imp;
+        use self::dbghelp::trace_from_context as trace_from_context_imp;
         pub(crate) use self::dbghelp::Frame as FrameImp;
-        #[cfg(target_env = "msvc")] // only used in dbghelp symbolize
+        pub(crate) const SUPPORTED: bool = true;
+        #[cfg(all(target_env = "msvc", not(feature = "pdb")))] // only used in dbghelp symbolize
         pub(crate) use self::dbghelp::StackFrame;
+        #[cfg(feature = "std")]
+        pub(crate) use self::dbghelp::trace_thread;
     } else {
         mod noop;
         use self::noop::trace as trace_imp;
+        use self::noop::trace_from_context as trace_from_context_imp;
         pub(crate) use self::noop::Frame as FrameImp;
+        // Tracing yields no frames at all on this target.
+        pub(crate) const SUPPORTED: bool = false;
     }
 }
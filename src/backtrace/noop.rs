@@ -6,6 +6,14 @@ use core::ffi::c_void;
 #[inline(always)]
 pub fn trace(_cb: &mut dyn FnMut(&super::Frame) -> bool) {}
 
+#[inline(always)]
+pub unsafe fn trace_from_context(
+    _context: *const c_void,
+    _cb: &mut dyn FnMut(&super::Frame) -> bool,
+) -> bool {
+    false
+}
+
 #[derive(Clone)]
 pub struct Frame;
 
@@ -25,4 +33,24 @@ impl Frame {
     pub fn module_base_address(&self) -> Option<*mut c_void> {
         None
     }
+
+    pub fn is_signal_frame(&self) -> Option<bool> {
+        None
+    }
+
+    pub fn cfa(&self) -> Option<*mut c_void> {
+        None
+    }
+
+    pub fn frame_pointer(&self) -> Option<*mut c_void> {
+        None
+    }
+
+    pub fn register(&self, _dwarf_register: u16) -> Option<usize> {
+        None
+    }
+
+    pub fn trust(&self) -> super::FrameTrust {
+        super::FrameTrust::Unknown
+    }
 }
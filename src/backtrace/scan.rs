@@ -0,0 +1,163 @@
+//! Continuing a trace past frames without unwind information.
+//!
+//! The system unwinder stops at the first frame it has no unwind information
+//! for, such as one of hand-written assembly or of a stripped library. With
+//! `set_stack_scanning` turned on, the stack above the last frame it found is
+//! instead searched for words which look like return addresses: values which
+//! point into an executable mapping of the process, right after what decodes
+//! as a call instruction. Each of those is reported as a frame.
+//!
+//! Stale return addresses left on the stack by calls which have returned
+//! already pass that test just as well, so scanned frames are only a guess and
+//! are marked as such with `Frame::is_scanned`.
+
+use core::mem;
+use core::ptr;
+use std::fs;
+use std::prelude::v1::*;
+use std::str;
+
+/// How far past the last frame found the stack is searched for another one
+/// before giving up.
+const MAX_GAP: usize = 64 * 1024;
+
+struct Mapping {
+    start: usize,
+    end: usize,
+    readable: bool,
+    writable: bool,
+    executable: bool,
+}
+
+/// Scans the stack upwards from `sp`, the stack pointer of the last frame the
+/// unwinder found, calling `cb` with the return address and the address just
+/// past it for each plausible frame until it returns `false`.
+pub(super) unsafe fn scan(sp: usize, cb: &mut dyn FnMut(usize, usize) -> bool) {
+    let maps = match read_maps() {
+        Some(maps) => maps,
+        None => return,
+    };
+    let stack_end = match find(&maps, sp) {
+        Some(m) if m.readable && m.writable => m.end,
+        _ => return,
+    };
+    let word = mem::size_of::<usize>();
+    let mut addr = (sp + word - 1) & !(word - 1);
+    let mut last = addr;
+    while addr + word <= stack_end && addr - last < MAX_GAP {
+        let value = super::strip_pac(ptr::read(addr as *const usize));
+        addr += word;
+        if is_return_address(&maps, value) {
+            if !cb(value, addr) {
+                return;
+            }
+            last = addr;
+        }
+    }
+}
+
+fn read_maps() -> Option<Vec<Mapping>> {
+    let data = fs::read("/proc/self/maps").ok()?;
+    Some(data.split(|b| *b == b'\n').filter_map(parse_line).collect())
+}
+
+/// Parses the address range and permissions of a line of `/proc/self/maps`,
+/// like `7f0e1c000000-7f0e1c021000 r-xp 00000000 00:00 0 /lib/libc.so.6`.
+fn parse_line(line: &[u8]) -> Option<Mapping> {
+    let mut parts = line.split(|b| *b == b' ');
+    let range = parts.next()?;
+    let perms = parts.next()?;
+    let dash = range.iter().position(|b| *b == b'-')?;
+    let start = usize::from_str_radix(str::from_utf8(&range[..dash]).ok()?, 16).ok()?;
+    let end = usize::from_str_radix(str::from_utf8(&range[dash + 1..]).ok()?, 16).ok()?;
+    if perms.len() < 3 {
+        return None;
+    }
+    Some(Mapping {
+        start,
+        end,
+        readable: perms[0] == b'r',
+        writable: perms[1] == b'w',
+        executable: perms[2] == b'x',
+    })
+}
+
+fn find(maps: &[Mapping], addr: usize) -> Option<&Mapping> {
+    maps.iter().find(|m| m.start <= addr && addr < m.end)
+}
+
+/// Returns whether `value` points right after a call instruction in readable,
+/// executable memory.
+fn is_return_address(maps: &[Mapping], value: usize) -> bool {
+    let m = match find(maps, value.wrapping_sub(1)) {
+        Some(m) if m.readable && m.executable => m,
+        _ => return false,
+    };
+    // Only the bytes within the mapping can be read.
+    let len = (value - m.start).min(MAX_CALL_LEN);
+    let code = unsafe { core::slice::from_raw_parts((value - len) as *const u8, len) };
+    follows_call(code)
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_arch = "x86_64", target_arch = "x86"))] {
+        const MAX_CALL_LEN: usize = 7;
+
+        /// Checks whether `code` ends with a near call, either `call rel32` or
+        /// one of the forms of `call r/m` (`FF /2`) of up to seven bytes.
+        fn follows_call(code: &[u8]) -> bool {
+            let n = code.len();
+            if n >= 5 && code[n - 5] == 0xe8 {
+                return true;
+            }
+            (2..=n).any(|len| code[n - len] == 0xff && (code[n - len + 1] >> 3) & 7 == 2)
+        }
+    } else if #[cfg(target_arch = "aarch64")] {
+        const MAX_CALL_LEN: usize = 4;
+
+        /// Checks whether `code` ends with a `bl` or `blr` instruction.
+        fn follows_call(code: &[u8]) -> bool {
+            if code.len() < 4 {
+                return false;
+            }
+            let insn = u32::from_le_bytes([code[0], code[1], code[2], code[3]]);
+            insn & 0xfc00_0000 == 0x9400_0000 || insn & 0xffff_fc1f == 0xd63f_0000
+        }
+    } else {
+        const MAX_CALL_LEN: usize = 0;
+
+        /// Call instructions aren't decoded on other architectures, so any
+        /// address in executable memory is taken.
+        fn follows_call(_code: &[u8]) -> bool {
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_lines() {
+        let m =
+            parse_line(b"7f0e1c000000-7f0e1c021000 r-xp 00000000 08:01 42 /lib/libc.so.6").unwrap();
+        assert_eq!((m.start, m.end), (0x7f0e1c000000, 0x7f0e1c021000));
+        assert!(m.readable && !m.writable && m.executable);
+        assert!(parse_line(b"").is_none());
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn calls() {
+        // call rel32
+        assert!(follows_call(&[0x90, 0x90, 0xe8, 1, 2, 3, 4]));
+        // call rax
+        assert!(follows_call(&[0x90, 0x90, 0x90, 0x90, 0x90, 0xff, 0xd0]));
+        // call [rip + disp32]
+        assert!(follows_call(&[0x90, 0xff, 0x15, 1, 2, 3, 4]));
+        // jmp rax
+        assert!(!follows_call(&[0x90, 0x90, 0x90, 0x90, 0x90, 0xff, 0xe0]));
+        assert!(!follows_call(&[0x90; 7]));
+    }
+}
@@ -0,0 +1,366 @@
+//! Unwind rules registered at runtime for code without unwind information.
+//!
+//! Hand-written assembly and the trampolines generated by FFI libraries often
+//! come without `.eh_frame` entries, so unwinders stop at them. Rules
+//! registered with `register_unwind_rule` describe where the return address
+//! and the caller's stack pointer are for such code.
+//!
+//! The in-crate DWARF unwinder looks rules up directly when it finds no FDE
+//! for an address. For the system unwinder an `.eh_frame` section with a CIE
+//! and a single FDE is encoded for each rule and passed to `__register_frame`,
+//! the same as JIT compilers do for their code.
+
+use core::ffi::c_void;
+use core::mem;
+use std::prelude::v1::*;
+
+/// How to find the caller of code covered by `register_unwind_rule`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnwindRule {
+    frame_size: usize,
+    return_address: Option<usize>,
+}
+
+impl UnwindRule {
+    /// The return address is stored `return_address_offset` bytes above the
+    /// stack pointer, and the caller's stack pointer is `frame_size` bytes
+    /// above it.
+    ///
+    /// For example on x86_64 a function which has pushed one register is
+    /// described by `UnwindRule::stack(8, 16)`.
+    pub fn stack(return_address_offset: usize, frame_size: usize) -> UnwindRule {
+        UnwindRule {
+            frame_size,
+            return_address: Some(return_address_offset),
+        }
+    }
+
+    /// The return address is still in the link register, and the caller's
+    /// stack pointer is `frame_size` bytes above the stack pointer.
+    ///
+    /// This only applies to architectures with a link register, like aarch64.
+    pub fn link_register(frame_size: usize) -> UnwindRule {
+        UnwindRule {
+            frame_size,
+            return_address: None,
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        match self.return_address {
+            Some(offset) => {
+                offset < self.frame_size && self.frame_size - offset >= mem::size_of::<usize>()
+            }
+            None => HAS_LINK_REGISTER,
+        }
+    }
+
+    /// Applies this rule to a frame with the given stack pointer and link
+    /// register, returning the caller's program counter and stack pointer.
+    #[cfg_attr(not(feature = "dwarf-unwind"), allow(dead_code))]
+    pub(crate) unsafe fn step(&self, sp: usize, lr: usize) -> (usize, usize) {
+        let pc = match self.return_address {
+            Some(offset) => *((sp + offset) as *const usize),
+            None => lr,
+        };
+        (pc, sp + self.frame_size)
+    }
+}
+
+struct Registered {
+    start: usize,
+    end: usize,
+    rule: UnwindRule,
+    // Kept alive for as long as the system unwinder refers to it. Words
+    // rather than bytes for the alignment unwinders expect.
+    eh_frame: Option<Box<[u64]>>,
+}
+
+// Only accessed with the crate's global lock held, through `rules`.
+static mut RULES: *mut Vec<Registered> = core::ptr::null_mut();
+
+// unsafe because this is required to be externally synchronized
+unsafe fn rules() -> &'static mut Vec<Registered> {
+    if RULES.is_null() {
+        RULES = Box::into_raw(Box::new(Vec::new()));
+    }
+    &mut *RULES
+}
+
+/// Registers `rule` for the code from `start` up to `end`, returning whether
+/// it can be used on this platform.
+///
+/// Unwinding then continues through frames of that code instead of stopping
+/// at them. Since return addresses point after the call instruction, the
+/// range has to include the calls in the code, not just the instructions
+/// following them. A rule registered later takes precedence over earlier ones
+/// for overlapping ranges.
+///
+/// Rules are used by the system unwinder on Linux and Android, where they
+/// also let panics unwind through the code, and by the unwinder of the
+/// `dwarf-unwind` feature. They are only supported on x86_64 and aarch64.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn register_unwind_rule(start: *const c_void, end: *const c_void, rule: UnwindRule) -> bool {
+    let (start, end) = (start as usize, end as usize);
+    if !SUPPORTED || start >= end || !rule.is_valid() {
+        return false;
+    }
+    let _guard = crate::lock::lock();
+    let eh_frame = unsafe { system::register(start, end, &rule) };
+    unsafe {
+        rules().push(Registered {
+            start,
+            end,
+            rule,
+            eh_frame,
+        });
+    }
+    true
+}
+
+/// Removes the rule registered for the code starting at `start`, returning
+/// whether there was one.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn unregister_unwind_rule(start: *const c_void) -> bool {
+    let _guard = crate::lock::lock();
+    unsafe {
+        let rules = rules();
+        let i = match rules.iter().rposition(|r| r.start == start as usize) {
+            Some(i) => i,
+            None => return false,
+        };
+        let registered = rules.remove(i);
+        if let Some(eh_frame) = &registered.eh_frame {
+            system::deregister(eh_frame);
+        }
+        true
+    }
+}
+
+// unsafe because this must not be called concurrently with registering or
+// unregistering rules, which the lock of the tracing functions ensures.
+#[cfg_attr(not(feature = "dwarf-unwind"), allow(dead_code))]
+pub(crate) unsafe fn find(addr: usize) -> Option<UnwindRule> {
+    // This is called while unwinding, so don't allocate here.
+    if RULES.is_null() {
+        return None;
+    }
+    (*RULES)
+        .iter()
+        .rev()
+        .find(|r| r.start <= addr && addr < r.end)
+        .map(|r| r.rule)
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))] {
+        const SUPPORTED: bool = true;
+    } else {
+        const SUPPORTED: bool = false;
+    }
+}
+
+const HAS_LINK_REGISTER: bool = cfg!(target_arch = "aarch64");
+
+cfg_if::cfg_if! {
+    if #[cfg(all(
+        any(target_os = "linux", target_os = "android"),
+        any(target_arch = "x86_64", target_arch = "aarch64"),
+    ))] {
+        mod system {
+            use core::mem;
+            use std::prelude::v1::*;
+
+            extern "C" {
+                fn __register_frame(begin: *const u8);
+                fn __deregister_frame(begin: *const u8);
+            }
+
+            #[cfg(target_arch = "x86_64")]
+            const SP: u8 = 7;
+            #[cfg(target_arch = "x86_64")]
+            const RA: u8 = 16;
+            #[cfg(target_arch = "aarch64")]
+            const SP: u8 = 31;
+            #[cfg(target_arch = "aarch64")]
+            const RA: u8 = 30;
+
+            const DW_CFA_NOP: u8 = 0x00;
+            const DW_CFA_SAME_VALUE: u8 = 0x08;
+            const DW_CFA_DEF_CFA: u8 = 0x0c;
+            const DW_CFA_OFFSET: u8 = 0x80;
+            const DW_EH_PE_ABSPTR: u8 = 0x00;
+
+            pub(super) unsafe fn register(
+                start: usize,
+                end: usize,
+                rule: &super::UnwindRule,
+            ) -> Option<Box<[u64]>> {
+                let bytes = encode(start, end, rule);
+                let mut words = vec![0u64; bytes.len() / 8 + 1].into_boxed_slice();
+                let dst = words.as_mut_ptr() as *mut u8;
+                dst.copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+                __register_frame(begin(&words));
+                Some(words)
+            }
+
+            pub(super) unsafe fn deregister(eh_frame: &[u64]) {
+                __deregister_frame(begin(eh_frame));
+            }
+
+            // libgcc takes the whole section, while LLVM's libunwind, which
+            // musl and Android targets use, takes a single FDE.
+            fn begin(eh_frame: &[u64]) -> *const u8 {
+                let ptr = eh_frame.as_ptr() as *const u8;
+                if cfg!(target_env = "gnu") {
+                    ptr
+                } else {
+                    let cie_len = unsafe { *(ptr as *const u32) };
+                    unsafe { ptr.add(4 + cie_len as usize) }
+                }
+            }
+
+            /// Encodes an `.eh_frame` section describing `rule` for the code
+            /// from `start` up to `end`: a CIE, an FDE and the zero
+            /// terminator.
+            pub(super) fn encode(start: usize, end: usize, rule: &super::UnwindRule) -> Vec<u8> {
+                let mut out = Vec::new();
+
+                let cie = entry(&mut out, |out| {
+                    out.extend_from_slice(&0u32.to_ne_bytes()); // CIE id
+                    out.push(1); // version
+                    out.extend_from_slice(b"zR\0");
+                    uleb128(out, 1); // code alignment factor
+                    out.push(0x7f); // data alignment factor, -1 as a SLEB128
+                    out.push(RA);
+                    uleb128(out, 1); // augmentation data length
+                    out.push(DW_EH_PE_ABSPTR);
+                });
+
+                entry(&mut out, |out| {
+                    // The CIE pointer is the distance back to the CIE from
+                    // this field.
+                    let offset = (out.len() - cie) as u32;
+                    out.extend_from_slice(&offset.to_ne_bytes());
+                    out.extend_from_slice(&(start as u64).to_ne_bytes());
+                    out.extend_from_slice(&((end - start) as u64).to_ne_bytes());
+                    uleb128(out, 0); // augmentation data length
+                    out.push(DW_CFA_DEF_CFA);
+                    uleb128(out, SP.into());
+                    uleb128(out, rule.frame_size as u64);
+                    match rule.return_address {
+                        // With a data alignment factor of -1 the offset counts
+                        // down from the CFA in bytes.
+                        Some(offset) => {
+                            out.push(DW_CFA_OFFSET | RA);
+                            uleb128(out, (rule.frame_size - offset) as u64);
+                        }
+                        None => {
+                            out.push(DW_CFA_SAME_VALUE);
+                            uleb128(out, RA.into());
+                        }
+                    }
+                });
+
+                out.extend_from_slice(&0u32.to_ne_bytes());
+                out
+            }
+
+            /// Appends a CIE or FDE with the contents written by `f`, padded
+            /// to a multiple of the word size, returning where it starts.
+            fn entry(out: &mut Vec<u8>, f: impl FnOnce(&mut Vec<u8>)) -> usize {
+                let start = out.len();
+                out.extend_from_slice(&[0; 4]);
+                f(out);
+                let word = mem::size_of::<usize>();
+                let padding = (word - (out.len() - start) % word) % word;
+                out.resize(out.len() + padding, DW_CFA_NOP);
+                let len = (out.len() - start - 4) as u32;
+                out[start..start + 4].copy_from_slice(&len.to_ne_bytes());
+                start
+            }
+
+            pub(super) fn uleb128(out: &mut Vec<u8>, mut val: u64) {
+                loop {
+                    let byte = (val & 0x7f) as u8;
+                    val >>= 7;
+                    if val == 0 {
+                        out.push(byte);
+                        return;
+                    }
+                    out.push(byte | 0x80);
+                }
+            }
+        }
+    } else {
+        mod system {
+            use std::prelude::v1::*;
+
+            pub(super) unsafe fn register(
+                _start: usize,
+                _end: usize,
+                _rule: &super::UnwindRule,
+            ) -> Option<Box<[u64]>> {
+                None
+            }
+
+            pub(super) unsafe fn deregister(_eh_frame: &[u64]) {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(all(
+        any(target_os = "linux", target_os = "android"),
+        any(target_arch = "x86_64", target_arch = "aarch64"),
+    ))]
+    fn encoding() {
+        let rule = UnwindRule::stack(8, 16);
+        let bytes = system::encode(0x1000, 0x1010, &rule);
+        assert_eq!(bytes.len() % 4, 0);
+        let cie_len = u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        assert_eq!((4 + cie_len) % mem::size_of::<usize>(), 0);
+        let fde = 4 + cie_len;
+        let fde_len =
+            u32::from_ne_bytes([bytes[fde], bytes[fde + 1], bytes[fde + 2], bytes[fde + 3]])
+                as usize;
+        assert_eq!(fde + 4 + fde_len + 4, bytes.len());
+        assert_eq!(&bytes[bytes.len() - 4..], &[0; 4]);
+    }
+
+    #[test]
+    fn validity() {
+        assert!(UnwindRule::stack(8, 16).is_valid());
+        assert!(!UnwindRule::stack(16, 16).is_valid());
+        assert!(!UnwindRule::stack(!0, 16).is_valid());
+        assert_eq!(UnwindRule::link_register(0).is_valid(), HAS_LINK_REGISTER);
+    }
+
+    #[test]
+    #[cfg(all(
+        any(target_os = "linux", target_os = "android"),
+        any(target_arch = "x86_64", target_arch = "aarch64"),
+    ))]
+    fn leb128() {
+        let mut out = Vec::new();
+        system::uleb128(&mut out, 624485);
+        assert_eq!(out, [0xe5, 0x8e, 0x26]);
+    }
+}
@@ -0,0 +1,190 @@
+//! A C interface to capturing and symbolizing backtraces.
+//!
+//! This lets C and C++ code in the same process, like the crash handler of a
+//! program mixing languages, use the unwinder and symbolizer of this crate
+//! rather than shipping libbacktrace alongside it. The functions are prefixed
+//! with `backtrace_rs_` so that they don't clash with glibc's `backtrace` or
+//! the functions of libbacktrace. To call them from C, link this crate into a
+//! `staticlib` or `cdylib` and declare them as follows:
+//!
+//! ```c
+//! struct backtrace_rs_symbol {
+//!     void *addr;
+//!     const char *name;
+//!     const char *filename;
+//!     uint32_t lineno;
+//!     uint32_t colno;
+//! };
+//!
+//! typedef void (*backtrace_rs_symbol_cb)(const struct backtrace_rs_symbol *symbol,
+//!                                        void *data);
+//!
+//! size_t backtrace_rs_capture(void **ips, size_t len);
+//! size_t backtrace_rs_capture_unsynchronized(void **ips, size_t len);
+//! void backtrace_rs_resolve(void *addr, backtrace_rs_symbol_cb cb, void *data);
+//!
+//! /* Unix only. */
+//! int backtrace_rs_print(int fd);
+//! int backtrace_rs_prepare_signal_safe(void);
+//! void backtrace_rs_print_signal_safe(int fd);
+//! ```
+//!
+//! # Required features
+//!
+//! This module requires the `capi` feature of the `backtrace` crate to be
+//! enabled.
+
+use core::ffi::c_void;
+use core::ptr;
+use core::slice;
+use std::ffi::CString;
+use std::prelude::v1::*;
+
+/// A symbol as passed to the callback of `backtrace_rs_resolve`.
+///
+/// The strings are NUL-terminated and only valid during the callback. Missing
+/// information is null, or zero for the line and column.
+#[repr(C)]
+#[derive(Debug)]
+pub struct BacktraceRsSymbol {
+    /// The address of the symbol, or null if unknown.
+    pub addr: *mut c_void,
+    /// The demangled name of the symbol.
+    pub name: *const libc::c_char,
+    /// The path of the source file.
+    pub filename: *const libc::c_char,
+    /// The line number in the source file.
+    pub lineno: u32,
+    /// The column number in the source file.
+    pub colno: u32,
+}
+
+/// The callback of `backtrace_rs_resolve`, which is passed each symbol along
+/// with the `data` given to it.
+pub type BacktraceRsSymbolCallback =
+    unsafe extern "C" fn(symbol: *const BacktraceRsSymbol, data: *mut c_void);
+
+/// Captures the instruction pointers of the current call-stack into the `len`
+/// elements at `ips`, returning how many were written.
+///
+/// This is `trace_ips_into`.
+///
+/// # Safety
+///
+/// `ips` must point to `len` writable pointers, or `len` must be zero.
+#[no_mangle]
+pub unsafe extern "C" fn backtrace_rs_capture(ips: *mut *mut c_void, len: usize) -> usize {
+    crate::trace_ips_into(ips_slice(ips, len))
+}
+
+/// Same as `backtrace_rs_capture`, but without taking the crate's lock, for
+/// use from signal handlers.
+///
+/// This is `trace_ips_into_unsynchronized`.
+///
+/// # Safety
+///
+/// See `backtrace_rs_capture`, and `trace_ips_into_unsynchronized`.
+#[no_mangle]
+pub unsafe extern "C" fn backtrace_rs_capture_unsynchronized(
+    ips: *mut *mut c_void,
+    len: usize,
+) -> usize {
+    crate::trace_ips_into_unsynchronized(ips_slice(ips, len))
+}
+
+unsafe fn ips_slice<'a>(ips: *mut *mut c_void, len: usize) -> &'a mut [*mut c_void] {
+    if ips.is_null() || len == 0 {
+        &mut []
+    } else {
+        slice::from_raw_parts_mut(ips, len)
+    }
+}
+
+/// Resolves `addr` to symbols, calling `cb` with each of them and `data`.
+///
+/// This is `resolve`, so `addr` is looked up as a return address, and `cb` is
+/// called more than once for inlined functions, innermost first.
+///
+/// # Safety
+///
+/// `cb` must be safe to call with the symbols and `data`.
+#[no_mangle]
+pub unsafe extern "C" fn backtrace_rs_resolve(
+    addr: *mut c_void,
+    cb: Option<BacktraceRsSymbolCallback>,
+    data: *mut c_void,
+) {
+    let cb = match cb {
+        Some(cb) => cb,
+        None => return,
+    };
+    crate::resolve(addr, |symbol| {
+        let name = symbol
+            .name()
+            .and_then(|name| CString::new(name.to_string()).ok());
+        let filename = symbol.filename_raw().and_then(|file| match file {
+            crate::BytesOrWideString::Bytes(bytes) => CString::new(bytes).ok(),
+            crate::BytesOrWideString::Wide(wide) => {
+                CString::new(String::from_utf16_lossy(wide)).ok()
+            }
+        });
+        let symbol = BacktraceRsSymbol {
+            addr: symbol.addr().unwrap_or(ptr::null_mut()),
+            name: name.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+            filename: filename.as_ref().map_or(ptr::null(), |s| s.as_ptr()),
+            lineno: symbol.lineno().unwrap_or(0),
+            colno: symbol.colno().unwrap_or(0),
+        };
+        cb(&symbol, data);
+    });
+}
+
+/// Captures and symbolizes the current backtrace, and prints it to the file
+/// descriptor `fd` in the format of `Backtrace`'s `Debug` output. Returns 0,
+/// or -1 if formatting failed.
+///
+/// This isn't safe to call from signal handlers, see
+/// `backtrace_rs_print_signal_safe` for that.
+///
+/// # Safety
+///
+/// `fd` must be a valid file descriptor.
+#[cfg(unix)]
+#[no_mangle]
+pub unsafe extern "C" fn backtrace_rs_print(fd: libc::c_int) -> libc::c_int {
+    use core::fmt::Write;
+
+    let bt = crate::Backtrace::new();
+    let mut out = crate::signal_safe::FdWriter::new(fd);
+    match write!(out, "{:?}", bt) {
+        Ok(()) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// Prepares for `backtrace_rs_print_signal_safe`, returning 1 if
+/// signal-safe symbolization is supported and 0 otherwise.
+///
+/// This is `signal_safe::prepare`, and must not be called from a signal
+/// handler.
+#[cfg(unix)]
+#[no_mangle]
+pub extern "C" fn backtrace_rs_prepare_signal_safe() -> libc::c_int {
+    crate::signal_safe::prepare() as libc::c_int
+}
+
+/// Captures the current backtrace and prints it to the file descriptor `fd`
+/// without allocating or taking locks, for use from signal handlers.
+///
+/// This is `signal_safe::trace_and_print_signal_safe`, which symbolizes the
+/// frames if `backtrace_rs_prepare_signal_safe` has been called.
+///
+/// # Safety
+///
+/// See `signal_safe::trace_and_print_signal_safe`.
+#[cfg(unix)]
+#[no_mangle]
+pub unsafe extern "C" fn backtrace_rs_print_signal_safe(fd: libc::c_int) {
+    crate::signal_safe::trace_and_print_signal_safe(fd)
+}
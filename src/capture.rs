@@ -1,9 +1,21 @@
-use crate::PrintFmt;
-use crate::{resolve, resolve_frame, trace, BacktraceFmt, Symbol, SymbolName};
+use crate::print::is_runtime_symbol;
+use crate::symbolize::ResolveWhat;
+use crate::threads::OsThreadId;
+use crate::wire::{RawBytesError, RawModule};
+use crate::{resolve, resolve_frame, BacktraceFmt, Symbol, SymbolLanguage, SymbolName};
+use crate::{FrameTrust, PrintFmt};
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::ffi::c_void;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::prelude::v1::*;
+use std::ptr;
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
@@ -16,19 +28,73 @@ use serde::{Deserialize, Serialize};
 /// `Backtrace` supports pretty-printing of backtraces through its `Debug`
 /// implementation.
 ///
+/// With the `serialize-serde` feature a `Backtrace` can be serialized along
+/// with the symbols it was resolved to, including their files, lines and
+/// modules, so it can be printed where the binaries it was captured from
+/// aren't available. The serialized form records the version of its schema,
+/// `Backtrace::SERDE_SCHEMA_VERSION`.
+///
+/// Backtraces are equal, hashed and ordered by the instruction pointers of
+/// their `frames`, whether or not they've been resolved, so they can be used
+/// as keys of a `HashMap` to group the places allocations or other events
+/// come from.
+///
+/// # Environment variables
+///
+/// How backtraces are captured and printed can be tuned without recompiling
+/// with these variables, which are read once, the first time they're needed:
+///
+/// * `BACKTRACE_MAX_FRAMES` limits the number of frames captured, see
+///   `backtrace::set_max_frames`.
+/// * `BACKTRACE_STYLE` sets the format used by `{:?}`: `short`, the default,
+///   `full`, `compact` or `json`. `{:#?}` always uses the full format.
+/// * `BACKTRACE_NO_RESOLVE`, set to anything but `0` or an empty value, makes
+///   `Backtrace::new` skip resolving symbols, leaving only the addresses
+///   printed by the full format to be symbolized offline.
+///
 /// # Required features
 ///
 /// This function requires the `std` feature of the `backtrace` crate to be
 /// enabled, and the `std` feature is enabled by default.
 #[derive(Clone)]
 #[cfg_attr(feature = "serialize-rustc", derive(RustcDecodable, RustcEncodable))]
-#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
 pub struct Backtrace {
     // Frames here are listed from top-to-bottom of the stack
     frames: Vec<BacktraceFrame>,
     // The index we believe is the actual start of the backtrace, omitting
     // frames like `Backtrace::new` and `backtrace::trace`.
     actual_start_index: usize,
+    // Whether the capture stopped early, see `is_truncated`.
+    truncated: bool,
+    // Whether `Debug` resolves the frames it prints, see
+    // `ResolvePolicy::OnPrint`.
+    resolve_on_print: bool,
+    // The symbols resolved by printing, indexed like `frames`.
+    printed: PrintCache,
+    // The raw id and the name of the capturing thread, see `thread_id`.
+    thread_id: Option<u64>,
+    thread_name: Option<String>,
+    // In nanoseconds since `EPOCH`, see `timestamp`.
+    timestamp: Option<u64>,
+    // Whether this was created by `Backtrace::disabled`.
+    disabled: bool,
+}
+
+/// Symbols resolved to print a backtrace with `ResolvePolicy::OnPrint`, kept so
+/// that printing it again doesn't resolve them again.
+#[derive(Default)]
+struct PrintCache(Mutex<Vec<Option<Vec<BacktraceSymbol>>>>);
+
+impl PrintCache {
+    fn lock(&self) -> MutexGuard<'_, Vec<Option<Vec<BacktraceSymbol>>>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Clone for PrintCache {
+    fn clone(&self) -> PrintCache {
+        PrintCache(Mutex::new(self.lock().clone()))
+    }
 }
 
 fn _assert_send_sync() {
@@ -39,7 +105,8 @@ fn _assert_send_sync() {
 /// Captured version of a frame in a backtrace.
 ///
 /// This type is returned as a list from `Backtrace::frames` and represents one
-/// stack frame in a captured backtrace.
+/// stack frame in a captured backtrace. Frames are equal, hashed and ordered
+/// by their instruction pointers.
 ///
 /// # Required features
 ///
@@ -54,7 +121,6 @@ pub struct BacktraceFrame {
 #[derive(Clone)]
 enum Frame {
     Raw(crate::Frame),
-    #[allow(dead_code)]
     Deserialized {
         ip: usize,
         symbol_address: usize,
@@ -86,6 +152,20 @@ impl Frame {
             } => module_base_address.map(|addr| addr as *mut c_void),
         }
     }
+
+    fn trust(&self) -> FrameTrust {
+        match *self {
+            Frame::Raw(ref f) => f.trust(),
+            Frame::Deserialized { .. } => FrameTrust::Unknown,
+        }
+    }
+
+    fn resolve_what(&self) -> ResolveWhat<'_> {
+        match *self {
+            Frame::Raw(ref f) => ResolveWhat::Frame(f),
+            Frame::Deserialized { ip, .. } => ResolveWhat::Address(ip as *mut c_void),
+        }
+    }
 }
 
 /// Captured version of a symbol in a backtrace.
@@ -106,9 +186,39 @@ pub struct BacktraceSymbol {
     filename: Option<PathBuf>,
     lineno: Option<u32>,
     colno: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    module_path: Option<PathBuf>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    module_base: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    function_start: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    offset_in_function: Option<usize>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    function_size: Option<usize>,
+    // The DWARF code, so that the language can be serialized.
+    #[cfg_attr(feature = "serde", serde(default))]
+    language: Option<u16>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    inline_depth: Option<u32>,
 }
 
 impl Backtrace {
+    /// The version of the schema a `Backtrace` is serialized with by `serde`.
+    ///
+    /// The version is serialized as the `version` field, next to the frames
+    /// and their symbols. It's incremented whenever a field is changed in a
+    /// way older versions of this crate can't read, and deserializing a
+    /// backtrace with a newer version than this one fails. Backtraces
+    /// serialized before the version was recorded are read as version 1.
+    ///
+    /// # Required features
+    ///
+    /// This constant requires the `serialize-serde` feature of the
+    /// `backtrace` crate to be enabled.
+    #[cfg(feature = "serde")]
+    pub const SERDE_SCHEMA_VERSION: u32 = 2;
+
     /// Captures a backtrace at the callsite of this function, returning an
     /// owned representation.
     ///
@@ -123,6 +233,9 @@ impl Backtrace {
     /// avoids the symbol resolution step (which typically takes the longest)
     /// and allows deferring that to a later date.
     ///
+    /// Symbols aren't resolved if the `BACKTRACE_NO_RESOLVE` environment
+    /// variable is set, see the documentation of `Backtrace`.
+    ///
     /// # Examples
     ///
     /// ```
@@ -138,7 +251,9 @@ impl Backtrace {
     #[inline(never)] // want to make sure there's a frame here to remove
     pub fn new() -> Backtrace {
         let mut bt = Self::create(Self::new as usize);
-        bt.resolve();
+        if !no_resolve_from_env() {
+            bt.resolve();
+        }
         bt
     }
 
@@ -170,246 +285,2055 @@ impl Backtrace {
         Self::create(Self::new_unresolved as usize)
     }
 
-    fn create(ip: usize) -> Backtrace {
-        let mut frames = Vec::new();
-        let mut actual_start_index = None;
-        trace(|frame| {
-            frames.push(BacktraceFrame {
-                frame: Frame::Raw(frame.clone()),
-                symbols: None,
-            });
-
-            if frame.symbol_address() as usize == ip && actual_start_index.is_none() {
-                actual_start_index = Some(frames.len());
-            }
-            true
-        });
-
-        Backtrace {
-            frames,
-            actual_start_index: actual_start_index.unwrap_or(0),
-        }
-    }
-
-    /// Returns the frames from when this backtrace was captured.
+    /// Creates a backtrace without frames, for when capturing is turned off.
     ///
-    /// The first entry of this slice is likely the function `Backtrace::new`,
-    /// and the last frame is likely something about how this thread or the main
-    /// function started.
+    /// Its `status` is `BacktraceStatus::Disabled`, and its `Debug`
+    /// implementation says it's disabled. This lets libraries which store a
+    /// backtrace in their errors skip the cost of capturing one, while still
+    /// telling why it's missing.
     ///
     /// # Required features
     ///
     /// This function requires the `std` feature of the `backtrace` crate to be
     /// enabled, and the `std` feature is enabled by default.
-    pub fn frames(&self) -> &[BacktraceFrame] {
-        &self.frames[self.actual_start_index..]
+    pub fn disabled() -> Backtrace {
+        let mut bt = Backtrace::from(Vec::new());
+        bt.disabled = true;
+        bt
     }
 
-    /// If this backtrace was created from `new_unresolved` then this function
-    /// will resolve all addresses in the backtrace to their symbolic names.
+    /// Captures a backtrace of another thread of this process.
     ///
-    /// If this backtrace has been previously resolved or was created through
-    /// `new`, this function does nothing.
+    /// This is primarily intended for deadlock dumps and watchdogs which need
+    /// to know what some other thread is currently doing. The returned
+    /// backtrace is resolved, like with `new`, and its first frame is the
+    /// instruction that the target thread was executing when it was
+    /// interrupted.
+    ///
+    /// Returns `None` if the thread couldn't be traced, for example because
+    /// it has exited or because this platform doesn't support tracing other
+    /// threads. Passing the id of the calling thread is the same as calling
+    /// `Backtrace::new`.
+    ///
+    /// # Platform-specific behavior
+    ///
+    /// On Linux and Android the target thread is interrupted with `SIGPROF`.
+    /// The first capture installs a handler for it which is never uninstalled,
+    /// since a signal it gave up waiting for may still arrive later. Signals
+    /// other than its own are forwarded to the handler it replaced, or if
+    /// there was none, get the default action of terminating the process.
+    ///
+    /// On Windows the target thread is briefly suspended and its stack is
+    /// walked with the unwind data of the loaded images, which doesn't need
+    /// any lock the suspended thread may hold. On 32-bit targets there is no
+    /// such data, and the stack is walked with dbghelp instead. That may
+    /// deadlock if the target thread holds the loader lock, the heap lock or
+    /// dbghelp's own lock, so there this should be reserved for watchdogs
+    /// which have no better option.
+    ///
+    /// Other platforms currently always return `None` for other threads.
     ///
     /// # Required features
     ///
     /// This function requires the `std` feature of the `backtrace` crate to be
     /// enabled, and the `std` feature is enabled by default.
-    pub fn resolve(&mut self) {
-        for frame in self.frames.iter_mut().filter(|f| f.symbols.is_none()) {
-            let mut symbols = Vec::new();
-            {
-                let sym = |symbol: &Symbol| {
-                    symbols.push(BacktraceSymbol {
-                        name: symbol.name().map(|m| m.as_bytes().to_vec()),
-                        addr: symbol.addr().map(|a| a as usize),
-                        filename: symbol.filename().map(|m| m.to_owned()),
-                        lineno: symbol.lineno(),
-                        colno: symbol.colno(),
-                    });
-                };
-                match frame.frame {
-                    Frame::Raw(ref f) => resolve_frame(f, sym),
-                    Frame::Deserialized { ip, .. } => {
-                        resolve(ip as *mut c_void, sym);
-                    }
-                }
-            }
-            frame.symbols = Some(symbols);
+    #[inline(never)] // want to make sure there's a frame here to remove
+    pub fn capture_thread(thread: OsThreadId) -> Option<Backtrace> {
+        if thread == OsThreadId::current() {
+            let mut bt = Self::create(Self::capture_thread as *const () as usize);
+            bt.resolve();
+            return Some(bt);
         }
+        let mut bt = Self::from_ips(crate::threads::trace_thread(thread)?);
+        bt.resolve();
+        Some(bt)
     }
-}
 
-impl From<Vec<BacktraceFrame>> for Backtrace {
-    fn from(frames: Vec<BacktraceFrame>) -> Self {
-        Backtrace {
+    /// Captures the backtrace of the code which raised a Windows exception.
+    ///
+    /// This is intended for crash handlers, such as vectored exception
+    /// handlers or unhandled exception filters. Calling `Backtrace::new` from
+    /// one of those captures the handler itself and the system's exception
+    /// dispatching frames, whereas this backtrace starts at the faulting
+    /// instruction. The returned backtrace is resolved, like with `new`.
+    ///
+    /// `exception_info` points to the `EXCEPTION_POINTERS` passed to the
+    /// handler, and the stack is walked from its `ContextRecord`, the same as
+    /// with `trace_from_context`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    ///
+    /// # Safety
+    ///
+    /// `exception_info` must point to a valid `EXCEPTION_POINTERS` for an
+    /// exception raised on the calling thread, and this must be called while
+    /// the exception is being handled so that the faulting stack is still
+    /// live.
+    #[cfg(windows)]
+    pub unsafe fn from_exception_info(exception_info: *const c_void) -> Backtrace {
+        use crate::windows::EXCEPTION_POINTERS;
+
+        let mut frames = Vec::new();
+        let truncated = if exception_info.is_null() {
+            false
+        } else {
+            let context = (*(exception_info as *const EXCEPTION_POINTERS)).ContextRecord;
+            let _guard = crate::lock::lock();
+            crate::backtrace::init_max_frames();
+            crate::backtrace::trace_from_context_guarded(context as *const c_void, &mut |frame| {
+                frames.push(BacktraceFrame {
+                    frame: Frame::Raw(frame.clone()),
+                    symbols: None,
+                });
+                true
+            })
+            .1
+        };
+        let mut bt = Backtrace {
             frames,
             actual_start_index: 0,
-        }
+            truncated,
+            resolve_on_print: false,
+            printed: PrintCache::default(),
+            thread_id: None,
+            thread_name: None,
+            timestamp: None,
+            disabled: false,
+        };
+        bt.resolve();
+        bt
     }
-}
 
-impl From<crate::Frame> for BacktraceFrame {
-    fn from(frame: crate::Frame) -> BacktraceFrame {
-        BacktraceFrame {
-            frame: Frame::Raw(frame),
-            symbols: None,
+    // Creates an unresolved backtrace from instruction pointers listed
+    // top-to-bottom.
+    pub(crate) fn from_ips(ips: Vec<usize>) -> Backtrace {
+        let frames = ips
+            .into_iter()
+            .map(|ip| BacktraceFrame {
+                frame: Frame::Deserialized {
+                    ip,
+                    symbol_address: ip,
+                    module_base_address: None,
+                },
+                symbols: None,
+            })
+            .collect();
+        Backtrace {
+            frames,
+            actual_start_index: 0,
+            truncated: false,
+            resolve_on_print: false,
+            printed: PrintCache::default(),
+            thread_id: None,
+            thread_name: None,
+            timestamp: None,
+            disabled: false,
         }
     }
-}
 
-impl Into<Vec<BacktraceFrame>> for Backtrace {
-    fn into(self) -> Vec<BacktraceFrame> {
-        self.frames
+    pub(crate) fn create(ip: usize) -> Backtrace {
+        Self::create_with(ip, 0, None)
     }
-}
 
-impl BacktraceFrame {
-    /// Same as `Frame::ip`
+    /// Returns a builder to configure how a backtrace is captured.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use backtrace::{Backtrace, ResolvePolicy};
+    ///
+    /// let bt = Backtrace::builder()
+    ///     .skip(1)
+    ///     .max_frames(16)
+    ///     .resolve(ResolvePolicy::Lazy)
+    ///     .capture();
+    /// assert!(bt.frames().len() <= 16);
+    /// ```
     ///
     /// # Required features
     ///
     /// This function requires the `std` feature of the `backtrace` crate to be
     /// enabled, and the `std` feature is enabled by default.
-    pub fn ip(&self) -> *mut c_void {
-        self.frame.ip() as *mut c_void
+    pub fn builder() -> BacktraceBuilder {
+        BacktraceBuilder::new()
     }
 
-    /// Same as `Frame::symbol_address`
+    // Captures frames up to and including the one whose symbol address is
+    // `ip`, hiding them and `skip` more frames from `frames`, and keeping at
+    // most `limit` frames after those.
+    fn create_with(ip: usize, skip: usize, limit: Option<usize>) -> Backtrace {
+        if let Some(mut ips) = crate::symbolize::fixture_frames() {
+            let skip = skip.min(ips.len());
+            let len = limit.map_or(ips.len(), |limit| (skip + limit).min(ips.len()));
+            let truncated = len < ips.len();
+            ips.truncate(len);
+            let mut bt = Backtrace::from_ips(ips);
+            bt.actual_start_index = skip;
+            bt.truncated = truncated;
+            return bt;
+        }
+
+        let mut frames = Vec::new();
+        let mut actual_start_index = None;
+        let mut limited = false;
+        let truncated = {
+            let _guard = crate::lock::lock();
+            crate::backtrace::init_max_frames();
+            unsafe {
+                crate::backtrace::trace_guarded(&mut |frame| {
+                    if let (Some(start), Some(limit)) = (actual_start_index, limit) {
+                        if frames.len() - start >= skip + limit {
+                            limited = true;
+                            return false;
+                        }
+                    }
+                    frames.push(BacktraceFrame {
+                        frame: Frame::Raw(frame.clone()),
+                        symbols: None,
+                    });
+
+                    if frame.symbol_address() as usize == ip && actual_start_index.is_none() {
+                        actual_start_index = Some(frames.len());
+                    }
+                    true
+                })
+            }
+        };
+
+        let actual_start_index = (actual_start_index.unwrap_or(0) + skip).min(frames.len());
+        Backtrace {
+            frames,
+            actual_start_index,
+            truncated: truncated || limited,
+            resolve_on_print: false,
+            printed: PrintCache::default(),
+            thread_id: None,
+            thread_name: None,
+            timestamp: None,
+            disabled: false,
+        }
+    }
+
+    /// Returns whether capturing this backtrace stopped before reaching the
+    /// bottom of the stack.
+    ///
+    /// This happens when the stack is deeper than `backtrace::max_frames`, or
+    /// when the unwinder started yielding the same frames over and over again
+    /// because of a corrupt stack.
     ///
     /// # Required features
     ///
     /// This function requires the `std` feature of the `backtrace` crate to be
     /// enabled, and the `std` feature is enabled by default.
-    pub fn symbol_address(&self) -> *mut c_void {
-        self.frame.symbol_address() as *mut c_void
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
     }
 
-    /// Same as `Frame::module_base_address`
+    /// Returns the id of the thread this backtrace was captured on, if it was
+    /// recorded with `BacktraceBuilder::record_thread`.
     ///
     /// # Required features
     ///
     /// This function requires the `std` feature of the `backtrace` crate to be
     /// enabled, and the `std` feature is enabled by default.
-    pub fn module_base_address(&self) -> Option<*mut c_void> {
-        self.frame
-            .module_base_address()
-            .map(|addr| addr as *mut c_void)
+    pub fn thread_id(&self) -> Option<OsThreadId> {
+        self.thread_id.map(OsThreadId::from_raw)
     }
 
-    /// Returns the list of symbols that this frame corresponds to.
-    ///
-    /// Normally there is only one symbol per frame, but sometimes if a number
-    /// of functions are inlined into one frame then multiple symbols will be
-    /// returned. The first symbol listed is the "innermost function", whereas
-    /// the last symbol is the outermost (last caller).
-    ///
-    /// Note that if this frame came from an unresolved backtrace then this will
-    /// return an empty list.
+    /// Returns the name of the thread this backtrace was captured on, if it
+    /// was recorded with `BacktraceBuilder::record_thread` and the thread has
+    /// a name.
     ///
     /// # Required features
     ///
     /// This function requires the `std` feature of the `backtrace` crate to be
     /// enabled, and the `std` feature is enabled by default.
-    pub fn symbols(&self) -> &[BacktraceSymbol] {
-        self.symbols.as_ref().map(|s| &s[..]).unwrap_or(&[])
+    pub fn thread_name(&self) -> Option<&str> {
+        self.thread_name.as_deref()
     }
-}
 
-impl BacktraceSymbol {
-    /// Same as `Symbol::name`
+    /// Returns when this backtrace was captured, if it was recorded with
+    /// `BacktraceBuilder::record_timestamp`.
+    ///
+    /// The timestamp is monotonic, measured from a point in time fixed when
+    /// the first timestamp in the process is taken, so timestamps of
+    /// backtraces captured in the same process can be compared to order
+    /// them. They can't be compared across processes.
     ///
     /// # Required features
     ///
     /// This function requires the `std` feature of the `backtrace` crate to be
     /// enabled, and the `std` feature is enabled by default.
-    pub fn name(&self) -> Option<SymbolName<'_>> {
-        self.name.as_ref().map(|s| SymbolName::new(s))
+    pub fn timestamp(&self) -> Option<Duration> {
+        self.timestamp.map(Duration::from_nanos)
     }
 
-    /// Same as `Symbol::addr`
+    /// Returns whether this backtrace was captured, or why it has no frames.
+    ///
+    /// A backtrace without frames is either one created by `disabled`, or
+    /// one captured on a target this crate can't walk the stack of. Anything
+    /// else is `BacktraceStatus::Captured`, including deserialized
+    /// backtraces.
     ///
     /// # Required features
     ///
     /// This function requires the `std` feature of the `backtrace` crate to be
     /// enabled, and the `std` feature is enabled by default.
-    pub fn addr(&self) -> Option<*mut c_void> {
-        self.addr.map(|s| s as *mut c_void)
+    pub fn status(&self) -> BacktraceStatus {
+        if self.disabled {
+            BacktraceStatus::Disabled
+        } else if self.frames.is_empty() && !crate::backtrace::SUPPORTED {
+            BacktraceStatus::Unsupported
+        } else {
+            BacktraceStatus::Captured
+        }
     }
 
-    /// Same as `Symbol::filename`
+    /// Returns the frames from when this backtrace was captured.
+    ///
+    /// The first entry of this slice is likely the function `Backtrace::new`,
+    /// and the last frame is likely something about how this thread or the main
+    /// function started.
     ///
     /// # Required features
     ///
     /// This function requires the `std` feature of the `backtrace` crate to be
     /// enabled, and the `std` feature is enabled by default.
-    pub fn filename(&self) -> Option<&Path> {
-        self.filename.as_ref().map(|p| &**p)
+    pub fn frames(&self) -> &[BacktraceFrame] {
+        &self.frames[self.actual_start_index..]
     }
 
-    /// Same as `Symbol::lineno`
+    /// If this backtrace was created from `new_unresolved` then this function
+    /// will resolve all addresses in the backtrace to their symbolic names.
+    ///
+    /// If this backtrace has been previously resolved or was created through
+    /// `new`, this function does nothing.
     ///
     /// # Required features
     ///
     /// This function requires the `std` feature of the `backtrace` crate to be
     /// enabled, and the `std` feature is enabled by default.
-    pub fn lineno(&self) -> Option<u32> {
-        self.lineno
+    pub fn resolve(&mut self) {
+        let mut frames = self
+            .frames
+            .iter_mut()
+            .filter(|f| f.symbols.is_none())
+            .collect::<Vec<_>>();
+        let mut symbols = frames.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+        {
+            let what = frames
+                .iter()
+                .map(|f| f.frame.resolve_what())
+                .collect::<Vec<_>>();
+            crate::symbolize::resolve_what_many(&what, &mut |i, symbol| {
+                symbols[i].push(BacktraceSymbol::new(symbol));
+            });
+        }
+        for (frame, symbols) in frames.iter_mut().zip(symbols) {
+            frame.symbols = Some(symbols);
+        }
     }
 
-    /// Same as `Symbol::colno`
+    /// Like `resolve`, but gives up once `timeout` has passed, returning
+    /// whether all frames have been resolved.
+    ///
+    /// Resolving symbols can take very long in pathological cases, like with
+    /// debug info on a slow network file system, huge amounts of DWARF, or
+    /// debug info downloaded from a debuginfod server. When a backtrace is
+    /// printed while handling an error that shouldn't hold up the error path,
+    /// so this resolves the frames one by one, starting with the first one
+    /// returned by `frames`, and stops at the first frame once time is up.
+    /// Frames left unresolved are reported by `BacktraceFrame::is_resolved`
+    /// and printed without symbols, and can be resolved later by calling this
+    /// or `resolve` again.
+    ///
+    /// Note that the resolution of a single frame is never interrupted, so
+    /// this can take longer than `timeout` when loading the debug info of one
+    /// module does.
     ///
     /// # Required features
     ///
     /// This function requires the `std` feature of the `backtrace` crate to be
     /// enabled, and the `std` feature is enabled by default.
-    pub fn colno(&self) -> Option<u32> {
-        self.colno
+    pub fn resolve_with_timeout(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now().checked_add(timeout);
+        let (skipped, frames) = self.frames.split_at_mut(self.actual_start_index);
+        for frame in frames.iter_mut().chain(skipped) {
+            if frame.symbols.is_some() {
+                continue;
+            }
+            // A deadline too far away to represent is never reached.
+            if let Some(deadline) = deadline {
+                if Instant::now() >= deadline {
+                    return false;
+                }
+            }
+            frame.resolve();
+        }
+        true
     }
-}
 
-impl fmt::Debug for Backtrace {
-    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let full = fmt.alternate();
-        let (frames, style) = if full {
-            (&self.frames[..], PrintFmt::Full)
-        } else {
-            (&self.frames[self.actual_start_index..], PrintFmt::Short)
-        };
+    // Resolves all of `backtraces` at once, looking up each distinct address
+    // only once, which is what matters for many samples of the same code.
+    pub(crate) fn resolve_all<'a, I>(backtraces: I)
+    where
+        I: IntoIterator<Item = &'a mut Backtrace>,
+    {
+        let mut frames = backtraces
+            .into_iter()
+            .flat_map(|bt| bt.frames.iter_mut())
+            .filter(|f| f.symbols.is_none())
+            .collect::<Vec<_>>();
+        let mut unique = std::collections::HashMap::new();
+        let mut what = Vec::new();
+        let slots = frames
+            .iter()
+            .map(|f| {
+                *unique.entry(f.ip() as usize).or_insert_with(|| {
+                    what.push(f.frame.resolve_what());
+                    what.len() - 1
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut symbols = what.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+        crate::symbolize::resolve_what_many(&what, &mut |i, symbol| {
+            symbols[i].push(BacktraceSymbol::new(symbol));
+        });
+        drop(what);
+        for (frame, slot) in frames.iter_mut().zip(slots) {
+            frame.symbols = Some(symbols[slot].clone());
+        }
+    }
 
-        // When printing paths we try to strip the cwd if it exists, otherwise
-        // we just print the path as-is. Note that we also only do this for the
-        // short format, because if it's full we presumably want to print
-        // everything.
-        let cwd = std::env::current_dir();
-        let mut print_path =
-            move |fmt: &mut fmt::Formatter<'_>, path: crate::BytesOrWideString<'_>| {
-                let path = path.into_path_buf();
-                if !full {
-                    if let Ok(cwd) = &cwd {
-                        if let Ok(suffix) = path.strip_prefix(cwd) {
-                            return fmt::Display::fmt(&suffix.display(), fmt);
+    // Resolves the frames of this backtrace, which was captured in another
+    // process, with `symbolizer`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn resolve_offline(&mut self, symbolizer: &mut crate::offline::Symbolizer) {
+        for frame in self.frames.iter_mut().filter(|f| f.symbols.is_none()) {
+            let mut symbols = Vec::new();
+            symbolizer.resolve(frame.ip() as usize, &mut |symbol| {
+                symbols.push(BacktraceSymbol::new(symbol));
+            });
+            frame.symbols = Some(symbols);
+        }
+    }
+
+    /// Returns a hash of the frames of this backtrace which is stable across
+    /// runs of the same build of a program.
+    ///
+    /// Raw instruction pointers differ between runs because of address space
+    /// layout randomization, so instead each frame is identified by the
+    /// build-id (or file name, if there's no build-id) of the module
+    /// containing it together with its offset within that module. This makes
+    /// the fingerprint suitable to group identical crashes reported from many
+    /// processes. Frames whose module isn't known fall back to their symbol
+    /// names if resolved, and to their raw address otherwise.
+    ///
+    /// The hash function used is FNV-1a, which will not change between
+    /// versions of this crate.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn fingerprint(&self) -> u64 {
+        let _guard = crate::lock::lock();
+        let mut hasher = Fnv1a::new();
+        for frame in self.frames() {
+            let mut found = false;
+            if let Frame::Raw(ref f) = frame.frame {
+                let hasher = &mut hasher;
+                unsafe {
+                    crate::symbolize::with_module(f.ip(), &mut |module| {
+                        match module.build_id {
+                            Some(build_id) => hasher.write(build_id),
+                            None => {
+                                let name = Path::new(module.name).file_name();
+                                let name = name.unwrap_or(module.name).to_string_lossy();
+                                hasher.write(name.as_bytes());
+                            }
                         }
-                    }
+                        hasher.write(&module.offset.to_le_bytes());
+                        found = true;
+                    });
                 }
-                fmt::Display::fmt(&path.display(), fmt)
-            };
+            }
+            if found {
+                continue;
+            }
 
-        let mut f = BacktraceFmt::new(fmt, style, &mut print_path);
-        f.add_context()?;
-        for frame in frames {
-            f.frame().backtrace_frame(frame)?;
+            let ip = frame.ip() as usize;
+            if let Some(base) = frame.module_base_address() {
+                hasher.write(&ip.wrapping_sub(base as usize).to_le_bytes());
+            } else if frame.symbols().iter().any(|s| s.name.is_some()) {
+                for name in frame.symbols().iter().filter_map(|s| s.name.as_ref()) {
+                    hasher.write(name);
+                }
+            } else {
+                hasher.write(&ip.to_le_bytes());
+            }
         }
-        f.finish()?;
-        Ok(())
-    }
-}
-
-impl Default for Backtrace {
-    fn default() -> Backtrace {
-        Backtrace::new()
+        hasher.finish()
     }
-}
+
+    /// Returns the frames this backtrace shares with `other`, the calls both
+    /// were made through.
+    ///
+    /// Frames are compared by their instruction pointers from the outermost
+    /// one, usually the start of the thread, so the frames returned are the
+    /// ones at the end of `frames`, and the same ones at the end of
+    /// `other.frames()`. Only backtraces captured in the same process can
+    /// share frames.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn common_prefix(&self, other: &Backtrace) -> &[BacktraceFrame] {
+        let frames = self.frames();
+        &frames[frames.len() - self.shared_frames(other)..]
+    }
+
+    /// Compares the frames of this backtrace with those of `other`.
+    ///
+    /// The returned `BacktraceDiff` splits the frames of both backtraces into
+    /// the ones they share, as returned by `common_prefix`, and the ones above
+    /// the point where they diverge. This is useful to cluster the stacks of
+    /// threads, such as ones involved in a deadlock, or to check where a call
+    /// path differs from an expected one.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn diff<'a>(&'a self, other: &'a Backtrace) -> BacktraceDiff<'a> {
+        BacktraceDiff {
+            ours: self.frames(),
+            theirs: other.frames(),
+            shared: self.shared_frames(other),
+        }
+    }
+
+    // Counts the frames at the end of `frames` which `other` has too.
+    fn shared_frames(&self, other: &Backtrace) -> usize {
+        self.frames()
+            .iter()
+            .rev()
+            .zip(other.frames().iter().rev())
+            .take_while(|(a, b)| a.ip() == b.ip())
+            .count()
+    }
+
+    /// Encodes the unresolved frames of this backtrace, together with the
+    /// modules they belong to, in a compact binary format.
+    ///
+    /// This is intended for a crashing process to send its backtrace to a
+    /// supervisor over a pipe or socket, which then decodes it with
+    /// `from_raw_bytes` and symbolizes it. Symbols which have already been
+    /// resolved are not included. Frames hidden by the short format (see
+    /// `frames`) are omitted.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn to_raw_bytes(&self) -> Vec<u8> {
+        let _guard = crate::lock::lock();
+        let mut modules: Vec<RawModule> = Vec::new();
+        let mut raw = Vec::with_capacity(self.frames().len());
+        for frame in self.frames() {
+            let ip = frame.ip() as usize;
+            let mut index = None;
+            if let Frame::Raw(_) = frame.frame {
+                unsafe {
+                    crate::symbolize::with_module(ip as *mut c_void, &mut |module| {
+                        let base = ip.wrapping_sub(module.offset);
+                        let path = Path::new(module.name);
+                        index = match modules.iter().position(|m| m.path() == path) {
+                            Some(i) => Some(i),
+                            None => {
+                                let build_id = module.build_id.map(|id| id.to_vec());
+                                modules.push(RawModule::new(path.to_path_buf(), base, build_id));
+                                Some(modules.len() - 1)
+                            }
+                        };
+                    });
+                }
+            }
+            raw.push((ip, index));
+        }
+        crate::wire::encode(&raw, &modules)
+    }
+
+    /// Decodes a backtrace previously encoded with `to_raw_bytes`, returning
+    /// it along with the table of modules its frames belong to.
+    ///
+    /// The returned backtrace is unresolved. As its addresses refer to the
+    /// process it was captured in, it generally can't be resolved with
+    /// `resolve` in another process; the modules are returned so they can be
+    /// symbolized offline instead. For each frame whose module is known,
+    /// `BacktraceFrame::module_base_address` returns the module's base.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn from_raw_bytes(bytes: &[u8]) -> Result<(Backtrace, Vec<RawModule>), RawBytesError> {
+        let (raw, modules) = crate::wire::decode(bytes)?;
+        let frames: Vec<BacktraceFrame> = raw
+            .into_iter()
+            .map(|(ip, module)| BacktraceFrame {
+                frame: Frame::Deserialized {
+                    ip,
+                    symbol_address: ip,
+                    module_base_address: module.map(|i| modules[i].base()),
+                },
+                symbols: None,
+            })
+            .collect();
+        Ok((Backtrace::from(frames), modules))
+    }
+
+    /// Resolves the symbols of only some of the frames of this backtrace.
+    ///
+    /// The `range` indexes into the slice returned by `frames`, so for
+    /// example `resolve_range(0..5)` resolves the top five frames. Indices
+    /// past the end of the backtrace are ignored, and frames which have
+    /// already been resolved are skipped.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn resolve_range(&mut self, range: Range<usize>) {
+        let frames = &mut self.frames[self.actual_start_index..];
+        let end = range.end.min(frames.len());
+        let start = range.start.min(end);
+        for frame in frames[start..end].iter_mut() {
+            frame.resolve();
+        }
+    }
+
+    /// Returns a wrapper which displays this backtrace as a GitHub-flavored
+    /// markdown block.
+    ///
+    /// The frames are printed inside of a fenced code block, identically to
+    /// the `Debug` implementation of this type (and the alternate flag `{:#}`
+    /// selects the full format, just like `{:#?}`). By default the block is
+    /// collapsible with the top application frame used as its summary, which
+    /// can be disabled with `MarkdownBacktrace::details`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use backtrace::Backtrace;
+    ///
+    /// let bt = Backtrace::new();
+    /// let comment = format!("Crashed with:\n\n{}", bt.markdown());
+    /// ```
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn markdown(&self) -> MarkdownBacktrace<'_> {
+        MarkdownBacktrace {
+            backtrace: self,
+            details: true,
+        }
+    }
+
+    /// Returns a wrapper which displays this backtrace in the format of
+    /// `atos` on macOS.
+    ///
+    /// Each symbol is printed on its own line as `symbol (in Module)
+    /// (file.rs:12)`, or as `symbol (in Module) + offset` if there's no line
+    /// information, and frames which couldn't be symbolized as `0x1234 (in
+    /// Module)`. This lets the frames be lined up with the output of `atos`
+    /// and with Xcode's symbolicated crash reports.
+    ///
+    /// Modules are looked up among the ones currently loaded into this
+    /// process, see `modules`, so this is only meaningful for backtraces
+    /// captured by this process.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn atos(&self) -> AtosBacktrace<'_> {
+        AtosBacktrace { backtrace: self }
+    }
+
+    /// Returns a wrapper which displays this backtrace in the format of
+    /// `addr2line -f -i` from binutils.
+    ///
+    /// Each symbol is printed as its function name on one line, followed by
+    /// `file:line` on the next, with inlined functions before the functions
+    /// they're inlined into. Like `addr2line`, unknown names and files are
+    /// printed as `??` and unknown line numbers as `?`, and frames which
+    /// couldn't be symbolized as `??` followed by `??:0`. This lets scripts and
+    /// tests which compare the output of `addr2line` use this crate's instead.
+    ///
+    /// Names are printed as they're found in the debug information, which is
+    /// mangled, unless demangling is enabled with
+    /// `Addr2lineBacktrace::demangle` like with `addr2line -C`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn addr2line(&self) -> Addr2lineBacktrace<'_> {
+        Addr2lineBacktrace {
+            backtrace: self,
+            demangle: false,
+        }
+    }
+
+    /// Returns a wrapper which displays this backtrace as the symbolizer
+    /// markup of Fuchsia, to be symbolized offline by its `symbolizer` tool.
+    ///
+    /// Nothing is resolved locally. Instead the modules containing any of
+    /// the frames are described with their build-ids and where they're
+    /// mapped, followed by the raw address of each frame, like
+    ///
+    /// ```text
+    /// {{{reset}}}
+    /// {{{module:0:app:elf:83238ab56ba10497}}}
+    /// {{{mmap:0x55e0f0a00000:0x5000:load:0:r:0x0}}}
+    /// {{{bt:0:0x55e0f0a01234:ra}}}
+    /// ```
+    ///
+    /// Modules are looked up among the ones currently loaded into this
+    /// process, see `modules`, and ones without a build-id are left out since
+    /// they couldn't be symbolized anyway.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn symbolizer_markup(&self) -> SymbolizerMarkupBacktrace<'_> {
+        SymbolizerMarkupBacktrace { backtrace: self }
+    }
+
+    /// Returns a wrapper which displays this backtrace in the machine-readable
+    /// format of Breakpad's `minidump_stackwalk -m`, as consumed by Socorro
+    /// and other Breakpad pipelines.
+    ///
+    /// Each loaded module is described with its Breakpad debug identifier and
+    /// address range, followed by one line per frame, like
+    ///
+    /// ```text
+    /// Module|app||app|B58A2383A16B970400000000000000000|0x55e0f0a00000|0x55e0f0a04fff|1
+    /// 0|0|app|app::main|src/main.rs|12|0x14
+    /// 0|1|libc.so.6||||0x29d90
+    /// ```
+    ///
+    /// Frames are those of thread 0. The last field is the offset from the
+    /// start of the function if it's known, from the start of the module if
+    /// only that is known, and the absolute address otherwise.
+    ///
+    /// Modules are looked up among the ones currently loaded into this
+    /// process, see `modules`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn breakpad(&self) -> BreakpadBacktrace<'_> {
+        BreakpadBacktrace { backtrace: self }
+    }
+
+    /// Returns a wrapper which displays this backtrace like its `Debug`
+    /// implementation, but without the frames of the standard library, of
+    /// panicking and of this crate, see `PrintFmt::Compact`.
+    ///
+    /// This is what the standard library prints for panics with
+    /// `RUST_BACKTRACE=1`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn compact(&self) -> CompactBacktrace<'_> {
+        CompactBacktrace { backtrace: self }
+    }
+
+    /// Returns a wrapper which displays this backtrace as JSON, with the
+    /// schema documented at `PrintFmt::Json`.
+    ///
+    /// Like the short format of `Debug`, the frames of capturing the backtrace
+    /// are left out. Frames which haven't been resolved are printed without
+    /// symbols, unless the backtrace resolves on print.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn json(&self) -> JsonBacktrace<'_> {
+        JsonBacktrace { backtrace: self }
+    }
+
+    // Backtraces which are resolved on print only resolve the frames in `range`
+    // which are actually printed, into a temporary copy. Their symbols are
+    // cached for the next time they're printed.
+    fn frames_to_print(&self, range: Range<usize>) -> Cow<'_, [BacktraceFrame]> {
+        let frames = &self.frames[range.clone()];
+        if !self.resolve_on_print || frames.iter().all(|f| f.symbols.is_some()) {
+            return Cow::Borrowed(frames);
+        }
+        let mut printed = self.printed.lock();
+        printed.resize(self.frames.len(), None);
+        let resolved = frames
+            .iter()
+            .zip(&mut printed[range])
+            .map(|(frame, symbols)| {
+                let mut frame = frame.clone();
+                if frame.symbols.is_none() {
+                    match symbols {
+                        Some(symbols) => frame.symbols = Some(symbols.clone()),
+                        None => {
+                            frame.resolve();
+                            *symbols = frame.symbols.clone();
+                        }
+                    }
+                }
+                frame
+            })
+            .collect::<Vec<_>>();
+        Cow::Owned(resolved)
+    }
+
+    // Finds the demangled name of the first symbol which doesn't belong to the
+    // standard library or to this crate.
+    fn top_application_symbol(&self) -> Option<String> {
+        self.frames()
+            .iter()
+            .flat_map(|frame| frame.symbols())
+            .filter_map(|symbol| symbol.name())
+            .map(|name| format!("{:#}", name))
+            .find(|name| !is_runtime_symbol(name))
+    }
+}
+
+impl From<Vec<BacktraceFrame>> for Backtrace {
+    fn from(frames: Vec<BacktraceFrame>) -> Self {
+        Backtrace {
+            frames,
+            actual_start_index: 0,
+            truncated: false,
+            resolve_on_print: false,
+            printed: PrintCache::default(),
+            thread_id: None,
+            thread_name: None,
+            timestamp: None,
+            disabled: false,
+        }
+    }
+}
+
+impl From<crate::Frame> for BacktraceFrame {
+    fn from(frame: crate::Frame) -> BacktraceFrame {
+        BacktraceFrame {
+            frame: Frame::Raw(frame),
+            symbols: None,
+        }
+    }
+}
+
+impl Into<Vec<BacktraceFrame>> for Backtrace {
+    fn into(self) -> Vec<BacktraceFrame> {
+        self.frames
+    }
+}
+
+impl BacktraceFrame {
+    /// Resolves the symbols of this frame, unless that was done already, and
+    /// then calls `cb` with each of them.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn resolve_with(&mut self, cb: &mut dyn FnMut(&BacktraceSymbol)) {
+        self.resolve();
+        for symbol in self.symbols() {
+            cb(symbol);
+        }
+    }
+
+    // Resolves the symbols of this frame, unless that was done already.
+    fn resolve(&mut self) {
+        if self.symbols.is_some() {
+            return;
+        }
+        let mut symbols = Vec::new();
+        {
+            let sym = |symbol: &Symbol| symbols.push(BacktraceSymbol::new(symbol));
+            match self.frame {
+                Frame::Raw(ref f) => resolve_frame(f, sym),
+                Frame::Deserialized { ip, .. } => {
+                    resolve(ip as *mut c_void, sym);
+                }
+            }
+        }
+        self.symbols = Some(symbols);
+    }
+
+    /// Same as `Frame::ip`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn ip(&self) -> *mut c_void {
+        self.frame.ip() as *mut c_void
+    }
+
+    /// Same as `Frame::symbol_address`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn symbol_address(&self) -> *mut c_void {
+        self.frame.symbol_address() as *mut c_void
+    }
+
+    /// Same as `Frame::module_base_address`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn module_base_address(&self) -> Option<*mut c_void> {
+        self.frame
+            .module_base_address()
+            .map(|addr| addr as *mut c_void)
+    }
+
+    /// Same as `Frame::is_scanned`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn is_scanned(&self) -> bool {
+        self.frame.trust() == FrameTrust::Scan
+    }
+
+    /// Same as `Frame::trust`, except that frames of backtraces which were
+    /// deserialized are reported as `FrameTrust::Unknown`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn trust(&self) -> FrameTrust {
+        self.frame.trust()
+    }
+
+    /// Returns the list of symbols that this frame corresponds to.
+    ///
+    /// Normally there is only one symbol per frame, but sometimes if a number
+    /// of functions are inlined into one frame then multiple symbols will be
+    /// returned. The first symbol listed is the "innermost function", whereas
+    /// the last symbol is the outermost (last caller).
+    ///
+    /// Note that if this frame came from an unresolved backtrace then this will
+    /// return an empty list, see `is_resolved`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn symbols(&self) -> &[BacktraceSymbol] {
+        self.symbols.as_ref().map(|s| &s[..]).unwrap_or(&[])
+    }
+
+    /// Returns whether the symbols of this frame have been resolved.
+    ///
+    /// This is false for the frames of a backtrace created with
+    /// `new_unresolved` until it is resolved, and for the frames which
+    /// `Backtrace::resolve_with_timeout` ran out of time for. Unlike an empty
+    /// list of `symbols` for a resolved frame, which means that no symbols
+    /// were found, an unresolved frame can still be resolved later.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn is_resolved(&self) -> bool {
+        self.symbols.is_some()
+    }
+}
+
+impl BacktraceSymbol {
+    fn new(symbol: &Symbol) -> BacktraceSymbol {
+        BacktraceSymbol {
+            name: symbol.name().map(|m| m.as_bytes().to_vec()),
+            addr: symbol.addr().map(|a| a as usize),
+            filename: symbol.filename().map(|m| m.to_owned()),
+            lineno: symbol.lineno(),
+            colno: symbol.colno(),
+            module_path: symbol.module_path().map(|m| m.to_owned()),
+            module_base: symbol.module_base().map(|b| b as usize),
+            function_start: symbol.function_start().map(|s| s as usize),
+            offset_in_function: symbol.offset_in_function(),
+            function_size: symbol.function_size(),
+            language: symbol.language_code(),
+            inline_depth: symbol.inline_depth(),
+        }
+    }
+
+    /// Same as `Symbol::name`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn name(&self) -> Option<SymbolName<'_>> {
+        self.name.as_ref().map(|s| SymbolName::new(s))
+    }
+
+    /// Same as `Symbol::addr`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn addr(&self) -> Option<*mut c_void> {
+        self.addr.map(|s| s as *mut c_void)
+    }
+
+    /// Same as `Symbol::is_async_poll`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn is_async_poll(&self) -> bool {
+        match self.name() {
+            Some(name) => name.is_async_poll(),
+            None => false,
+        }
+    }
+
+    /// Same as `Symbol::filename`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn filename(&self) -> Option<&Path> {
+        self.filename.as_ref().map(|p| &**p)
+    }
+
+    /// Same as `Symbol::lineno`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn lineno(&self) -> Option<u32> {
+        self.lineno
+    }
+
+    /// Same as `Symbol::colno`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn colno(&self) -> Option<u32> {
+        self.colno
+    }
+
+    /// Same as `Symbol::module_path`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn module_path(&self) -> Option<&Path> {
+        self.module_path.as_deref()
+    }
+
+    /// Same as `Symbol::module_base`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn module_base(&self) -> Option<*mut c_void> {
+        self.module_base.map(|b| b as *mut c_void)
+    }
+
+    /// Same as `Symbol::function_start`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn function_start(&self) -> Option<*mut c_void> {
+        self.function_start.map(|s| s as *mut c_void)
+    }
+
+    /// Same as `Symbol::offset_in_function`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn offset_in_function(&self) -> Option<usize> {
+        self.offset_in_function
+    }
+
+    /// Same as `Symbol::function_size`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn function_size(&self) -> Option<usize> {
+        self.function_size
+    }
+
+    /// Same as `Symbol::language`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn language(&self) -> Option<SymbolLanguage> {
+        self.language.map(SymbolLanguage::from_dwarf)
+    }
+
+    /// Same as `Symbol::inline_depth`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn inline_depth(&self) -> Option<u32> {
+        self.inline_depth
+    }
+
+    /// Same as `Symbol::is_inline`
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn is_inline(&self) -> bool {
+        self.inline_depth.unwrap_or(0) > 0
+    }
+}
+
+/// A comparison of the frames of two backtraces.
+///
+/// This type is created by `Backtrace::diff`. Frames are compared by their
+/// instruction pointers, starting from the outermost ones.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug)]
+pub struct BacktraceDiff<'a> {
+    ours: &'a [BacktraceFrame],
+    theirs: &'a [BacktraceFrame],
+    // How many frames at the end of both are the same.
+    shared: usize,
+}
+
+impl<'a> BacktraceDiff<'a> {
+    /// Returns the frames both backtraces share, from the frames of the
+    /// backtraces `diff` was called on.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn shared(&self) -> &'a [BacktraceFrame] {
+        &self.ours[self.ours.len() - self.shared..]
+    }
+
+    /// Returns the frames only the backtrace `diff` was called on has, above
+    /// the shared ones.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn ours(&self) -> &'a [BacktraceFrame] {
+        &self.ours[..self.ours.len() - self.shared]
+    }
+
+    /// Returns the frames only the backtrace passed to `diff` has, above the
+    /// shared ones.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn theirs(&self) -> &'a [BacktraceFrame] {
+        &self.theirs[..self.theirs.len() - self.shared]
+    }
+
+    /// Returns the indices into the `frames` of each backtrace at which the
+    /// shared frames start, where the calls of the backtraces diverge.
+    ///
+    /// These are also the numbers of frames only either backtrace has.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn divergence(&self) -> (usize, usize) {
+        (
+            self.ours.len() - self.shared,
+            self.theirs.len() - self.shared,
+        )
+    }
+
+    /// Returns whether both backtraces have the same frames.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn is_same(&self) -> bool {
+        self.divergence() == (0, 0)
+    }
+}
+
+impl fmt::Debug for Backtrace {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if fmt.alternate() {
+            return self.print(fmt, PrintFmt::Full);
+        }
+        match style_from_env() {
+            Some(PrintFmt::Json) => fmt::Display::fmt(&self.json(), fmt),
+            Some(style) => self.print(fmt, style),
+            None => self.print(fmt, PrintFmt::Short),
+        }
+    }
+}
+
+// The settings read from the environment, see the documentation of
+// `Backtrace`. Zero means the variable hasn't been read yet.
+static STYLE_FROM_ENV: AtomicUsize = AtomicUsize::new(0);
+static NO_RESOLVE_FROM_ENV: AtomicUsize = AtomicUsize::new(0);
+
+fn style_from_env() -> Option<PrintFmt> {
+    let style = match STYLE_FROM_ENV.load(atomic::Ordering::Relaxed) {
+        0 => {
+            let var = std::env::var("BACKTRACE_STYLE").unwrap_or_default();
+            let style = match &*var.trim().to_ascii_lowercase() {
+                "short" => 2,
+                "full" => 3,
+                "compact" => 4,
+                "json" => 5,
+                _ => 1,
+            };
+            STYLE_FROM_ENV.store(style, atomic::Ordering::Relaxed);
+            style
+        }
+        style => style,
+    };
+    match style {
+        2 => Some(PrintFmt::Short),
+        3 => Some(PrintFmt::Full),
+        4 => Some(PrintFmt::Compact),
+        5 => Some(PrintFmt::Json),
+        _ => None,
+    }
+}
+
+fn no_resolve_from_env() -> bool {
+    match NO_RESOLVE_FROM_ENV.load(atomic::Ordering::Relaxed) {
+        0 => {
+            let no_resolve = match std::env::var_os("BACKTRACE_NO_RESOLVE") {
+                Some(var) => !var.is_empty() && var != "0",
+                None => false,
+            };
+            NO_RESOLVE_FROM_ENV.store(1 + no_resolve as usize, atomic::Ordering::Relaxed);
+            no_resolve
+        }
+        value => value == 2,
+    }
+}
+
+impl Backtrace {
+    fn print(&self, fmt: &mut fmt::Formatter<'_>, style: PrintFmt) -> fmt::Result {
+        let full = style == PrintFmt::Full;
+        let start = if full { 0 } else { self.actual_start_index };
+
+        // A precision limits how many frames are printed, e.g. `{:.10?}`.
+        let end = match fmt.precision() {
+            Some(limit) => self.frames.len().min(start + limit),
+            None => self.frames.len(),
+        };
+
+        let frames = self.frames_to_print(start..end);
+
+        // When printing paths we try to strip the cwd if it exists, otherwise
+        // we just print the path as-is. Note that we also only do this for the
+        // short format, because if it's full we presumably want to print
+        // everything.
+        let cwd = std::env::current_dir();
+        let mut print_path =
+            move |fmt: &mut fmt::Formatter<'_>, path: crate::BytesOrWideString<'_>| {
+                let path = path.into_path_buf();
+                if !full {
+                    if let Ok(cwd) = &cwd {
+                        if let Ok(suffix) = path.strip_prefix(cwd) {
+                            return fmt::Display::fmt(&suffix.display(), fmt);
+                        }
+                    }
+                }
+                fmt::Display::fmt(&path.display(), fmt)
+            };
+
+        match self.status() {
+            BacktraceStatus::Disabled => return writeln!(fmt, "disabled backtrace"),
+            BacktraceStatus::Unsupported => return writeln!(fmt, "unsupported backtrace"),
+            BacktraceStatus::Captured => {}
+        }
+
+        // Say where and when the backtrace was captured, if recorded.
+        if self.thread_id.is_some() || self.timestamp.is_some() {
+            fmt.write_str("captured")?;
+            if let Some(id) = self.thread_id() {
+                let name = self.thread_name().unwrap_or("<unnamed>");
+                write!(fmt, " on thread '{}' ({})", name, id)?;
+            }
+            if let Some(timestamp) = self.timestamp() {
+                write!(fmt, " at {:?}", timestamp)?;
+            }
+            fmt.write_str(":\n")?;
+        }
+
+        let mut f = BacktraceFmt::new(fmt, style, &mut print_path);
+        f.add_context()?;
+        let mut i = 0;
+        while i < frames.len() {
+            match repeated_cycle(&frames[i..]) {
+                Some((len, times)) => {
+                    for frame in &frames[i..i + len] {
+                        f.frame().backtrace_frame(frame)?;
+                    }
+                    f.skip_repeated(len, times)?;
+                    i += len * (times + 1);
+                }
+                None => {
+                    f.frame().backtrace_frame(&frames[i])?;
+                    i += 1;
+                }
+            }
+        }
+        f.finish()?;
+        if self.truncated {
+            writeln!(fmt, "note: backtrace truncated")?;
+        }
+        Ok(())
+    }
+}
+
+/// A wrapper around a `Backtrace` which displays it as a GitHub-flavored
+/// markdown block.
+///
+/// This type is created by `Backtrace::markdown` and is primarily intended for
+/// bots which post crash reports into issues or pull requests. The frames are
+/// printed inside of a fenced code block in exactly the same format as the
+/// `Debug` implementation of `Backtrace`, and by default the whole block is
+/// wrapped in a collapsible `<details>` element whose summary is the top
+/// application frame.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub struct MarkdownBacktrace<'a> {
+    backtrace: &'a Backtrace,
+    details: bool,
+}
+
+impl MarkdownBacktrace<'_> {
+    /// Configures whether the code fence is wrapped in a collapsible
+    /// `<details>` block. Defaults to `true`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn details(mut self, details: bool) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+impl fmt::Display for MarkdownBacktrace<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.details {
+            fmt.write_str("<details>\n<summary>")?;
+            match self.backtrace.top_application_symbol() {
+                Some(name) => write_html_escaped(fmt, &name)?,
+                None => fmt.write_str("backtrace")?,
+            }
+            fmt.write_str("</summary>\n\n")?;
+        }
+        fmt.write_str("```text\n")?;
+        if fmt.alternate() {
+            write!(fmt, "{:#?}", self.backtrace)?;
+        } else {
+            write!(fmt, "{:?}", self.backtrace)?;
+        }
+        fmt.write_str("```\n")?;
+        if self.details {
+            fmt.write_str("\n</details>\n")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for MarkdownBacktrace<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, fmt)
+    }
+}
+
+/// A wrapper which displays a backtrace in the format of `atos`.
+///
+/// This type is created by `Backtrace::atos`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub struct AtosBacktrace<'a> {
+    backtrace: &'a Backtrace,
+}
+
+impl fmt::Display for AtosBacktrace<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let modules = crate::modules();
+        let frames = &self.backtrace.frames[self.backtrace.actual_start_index..];
+        for frame in frames {
+            let ip = frame.ip() as usize;
+            let module = modules
+                .iter()
+                .find(|module| module.contains(ip))
+                .and_then(|module| module.path().file_name());
+            let write_module = |fmt: &mut fmt::Formatter<'_>| match module {
+                Some(module) => write!(fmt, " (in {})", Path::new(module).display()),
+                None => Ok(()),
+            };
+
+            if frame.symbols().is_empty() {
+                write!(fmt, "{:#x}", ip)?;
+                write_module(fmt)?;
+                fmt.write_str("\n")?;
+                continue;
+            }
+            // `atos` only prints an offset from the start of the function
+            // when there's no line information.
+            let start = frame.symbol_address() as usize;
+            for symbol in frame.symbols() {
+                match symbol.name() {
+                    Some(name) => write!(fmt, "{:#}", name)?,
+                    None => write!(fmt, "{:#x}", ip)?,
+                }
+                write_module(fmt)?;
+                match (symbol.filename(), symbol.lineno()) {
+                    (Some(file), Some(line)) => {
+                        let file = file.file_name().map(Path::new).unwrap_or(file);
+                        write!(fmt, " ({}:{})", file.display(), line)?;
+                    }
+                    _ if start != 0 && start < ip => write!(fmt, " + {}", ip - start)?,
+                    _ => {}
+                }
+                fmt.write_str("\n")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for AtosBacktrace<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, fmt)
+    }
+}
+
+/// A wrapper which displays a backtrace in the format of `addr2line -f -i`.
+///
+/// This type is created by `Backtrace::addr2line`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub struct Addr2lineBacktrace<'a> {
+    backtrace: &'a Backtrace,
+    demangle: bool,
+}
+
+impl Addr2lineBacktrace<'_> {
+    /// Configures whether names are demangled, like with `addr2line -C`.
+    /// Defaults to `false`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn demangle(mut self, demangle: bool) -> Self {
+        self.demangle = demangle;
+        self
+    }
+}
+
+impl fmt::Display for Addr2lineBacktrace<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bt = self.backtrace;
+        let frames = bt.frames_to_print(bt.actual_start_index..bt.frames.len());
+        for frame in frames.iter() {
+            if frame.symbols().is_empty() {
+                fmt.write_str("??\n??:0\n")?;
+                continue;
+            }
+            for symbol in frame.symbols() {
+                match symbol.name() {
+                    Some(name) if self.demangle => writeln!(fmt, "{:#}", name)?,
+                    Some(name) => writeln!(fmt, "{}", String::from_utf8_lossy(name.as_bytes()))?,
+                    None => fmt.write_str("??\n")?,
+                }
+                match symbol.filename() {
+                    Some(file) => write!(fmt, "{}:", file.display())?,
+                    None => fmt.write_str("??:")?,
+                }
+                match symbol.lineno() {
+                    Some(line) if line != 0 => writeln!(fmt, "{}", line)?,
+                    _ => fmt.write_str("?\n")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Addr2lineBacktrace<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, fmt)
+    }
+}
+
+/// A wrapper which displays a backtrace as Fuchsia's symbolizer markup.
+///
+/// This type is created by `Backtrace::symbolizer_markup`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub struct SymbolizerMarkupBacktrace<'a> {
+    backtrace: &'a Backtrace,
+}
+
+impl fmt::Display for SymbolizerMarkupBacktrace<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frames = &self.backtrace.frames[self.backtrace.actual_start_index..];
+        let modules = crate::modules()
+            .into_iter()
+            .filter(|module| module.build_id().is_some())
+            .filter(|module| frames.iter().any(|f| module.contains(f.ip() as usize)));
+
+        fmt.write_str("{{{reset}}}\n")?;
+        for (id, module) in modules.enumerate() {
+            let name = module.path().file_name().unwrap_or_default();
+            write!(
+                fmt,
+                "{{{{{{module:{}:{}:elf:",
+                id,
+                Path::new(name).display()
+            )?;
+            for byte in module.build_id().unwrap_or(&[]) {
+                write!(fmt, "{:02x}", byte)?;
+            }
+            fmt.write_str("}}}\n")?;
+            // The permissions of the segments aren't known, but they're only
+            // informational for the symbolizer.
+            for range in module.ranges() {
+                writeln!(
+                    fmt,
+                    "{{{{{{mmap:{:#x}:{:#x}:load:{}:r:{:#x}}}}}}}",
+                    range.start,
+                    range.end - range.start,
+                    id,
+                    range.start.wrapping_sub(module.bias()),
+                )?;
+            }
+        }
+        // Frame addresses are return addresses, which the symbolizer moves
+        // back into the call instruction.
+        for (i, frame) in frames.iter().enumerate() {
+            writeln!(fmt, "{{{{{{bt:{}:{:#x}:ra}}}}}}", i, frame.ip() as usize)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for SymbolizerMarkupBacktrace<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, fmt)
+    }
+}
+
+/// A wrapper which displays a backtrace without the frames of the runtime.
+///
+/// This type is created by `Backtrace::compact`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub struct CompactBacktrace<'a> {
+    backtrace: &'a Backtrace,
+}
+
+impl fmt::Display for CompactBacktrace<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.backtrace.print(fmt, PrintFmt::Compact)
+    }
+}
+
+impl fmt::Debug for CompactBacktrace<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, fmt)
+    }
+}
+
+/// A wrapper which displays a backtrace as JSON.
+///
+/// This type is created by `Backtrace::json`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub struct JsonBacktrace<'a> {
+    backtrace: &'a Backtrace,
+}
+
+impl fmt::Display for JsonBacktrace<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bt = self.backtrace;
+        let frames = bt.frames_to_print(bt.actual_start_index..bt.frames.len());
+        let mut print_path = |fmt: &mut fmt::Formatter<'_>, path: crate::BytesOrWideString<'_>| {
+            fmt::Display::fmt(&path, fmt)
+        };
+        let mut f = BacktraceFmt::new(fmt, PrintFmt::Json, &mut print_path);
+        f.add_context()?;
+        for frame in frames.iter() {
+            f.frame().backtrace_frame(frame)?;
+        }
+        f.finish()
+    }
+}
+
+impl fmt::Debug for JsonBacktrace<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, fmt)
+    }
+}
+
+/// A wrapper which displays a backtrace in Breakpad's machine-readable format.
+///
+/// This type is created by `Backtrace::breakpad`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub struct BreakpadBacktrace<'a> {
+    backtrace: &'a Backtrace,
+}
+
+impl fmt::Display for BreakpadBacktrace<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let modules = crate::modules();
+        let start = |module: &crate::LoadedModule| module.ranges().first().map_or(0, |r| r.start);
+        let name = |module: &crate::LoadedModule| {
+            Path::new(module.path().file_name().unwrap_or_default())
+                .display()
+                .to_string()
+        };
+
+        // The executable is the first module on every platform.
+        for (i, module) in modules.iter().enumerate() {
+            let end = module.ranges().last().map_or(0, |r| r.end);
+            let name = name(module);
+            // Windows modules are described by their PDB files.
+            let debug_file = if cfg!(windows) {
+                Path::new(&name).with_extension("pdb").display().to_string()
+            } else {
+                name.clone()
+            };
+            write!(fmt, "Module|{}||{}|", name, debug_file)?;
+            if let Some(id) = module.build_id() {
+                write_breakpad_id(fmt, id)?;
+            }
+            writeln!(
+                fmt,
+                "|{:#x}|{:#x}|{}",
+                start(module),
+                end.saturating_sub(1),
+                (i == 0) as u8
+            )?;
+        }
+
+        let frames = &self.backtrace.frames[self.backtrace.actual_start_index..];
+        for (i, frame) in frames.iter().enumerate() {
+            let ip = frame.ip() as usize;
+            let module = modules.iter().find(|module| module.contains(ip));
+            write!(fmt, "0|{}|", i)?;
+            if let Some(module) = module {
+                fmt.write_str(&name(module))?;
+            }
+            // Inlined functions come first, and the last symbol is the
+            // function the frame's code belongs to.
+            let symbol = frame.symbols().last();
+            match symbol.and_then(|s| s.name()) {
+                Some(name) => write!(fmt, "|{:#}|", name)?,
+                None => fmt.write_str("||")?,
+            }
+            if let Some(file) = symbol.and_then(|s| s.filename()) {
+                match crate::print::remap(file) {
+                    Some(file) => write!(fmt, "{}", file.display())?,
+                    None => write!(fmt, "{}", file.display())?,
+                }
+            }
+            fmt.write_str("|")?;
+            if let Some(line) = symbol.and_then(|s| s.lineno()) {
+                write!(fmt, "{}", line)?;
+            }
+            let function = frame.symbol_address() as usize;
+            let offset = if symbol.and_then(|s| s.name()).is_some() && function != 0 {
+                ip.wrapping_sub(function)
+            } else if let Some(module) = module {
+                ip - start(module)
+            } else {
+                ip
+            };
+            writeln!(fmt, "|{:#x}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for BreakpadBacktrace<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, fmt)
+    }
+}
+
+// Writes the Breakpad debug identifier for a build-id, which is a GUID and an
+// age. A PDB's GUID and age are what the build-id is on Windows, a Mach-O
+// UUID is used as the GUID as it is, and otherwise the first 16 bytes of the
+// build-id are taken as a GUID in little-endian order. The age is 0 unless
+// it's given.
+fn write_breakpad_id(fmt: &mut fmt::Formatter<'_>, id: &[u8]) -> fmt::Result {
+    let mut guid = [0; 16];
+    let len = id.len().min(16);
+    guid[..len].copy_from_slice(&id[..len]);
+    if !cfg!(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "tvos",
+        target_os = "watchos",
+    )) {
+        guid[..4].reverse();
+        guid[4..6].reverse();
+        guid[6..8].reverse();
+    }
+    for byte in guid.iter() {
+        write!(fmt, "{:02X}", byte)?;
+    }
+    let age = match id.get(16..20) {
+        Some(age) if cfg!(windows) => u32::from_le_bytes([age[0], age[1], age[2], age[3]]),
+        _ => 0,
+    };
+    write!(fmt, "{:X}", age)
+}
+
+// Symbols in a summary are rendered as HTML by GitHub, and Rust symbol names
+// are full of `<` and `>`, so escape them.
+fn write_html_escaped(fmt: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    for c in s.chars() {
+        match c {
+            '<' => fmt.write_str("&lt;")?,
+            '>' => fmt.write_str("&gt;")?,
+            '&' => fmt.write_str("&amp;")?,
+            c => fmt::Write::write_char(fmt, c)?,
+        }
+    }
+    Ok(())
+}
+
+// The longest cycle of frames looked for by `repeated_cycle`.
+const MAX_CYCLE_LEN: usize = 16;
+
+// Finds a cycle of frames at the start of `frames` which is repeated right
+// after it at least twice, as with recursion, returning its length and how
+// many more times it's repeated. Frames are compared by their instruction
+// pointers. Of cycles covering as many frames, the shortest one is returned.
+fn repeated_cycle(frames: &[BacktraceFrame]) -> Option<(usize, usize)> {
+    let mut best: Option<(usize, usize)> = None;
+    for len in 1..=MAX_CYCLE_LEN.min(frames.len() / 3) {
+        let cycle = &frames[..len];
+        let times = frames[len..]
+            .chunks_exact(len)
+            .take_while(|chunk| chunk.iter().zip(cycle).all(|(a, b)| a.ip() == b.ip()))
+            .count();
+        let better = match best {
+            Some((l, t)) => len * times > l * t,
+            None => true,
+        };
+        if times >= 2 && better {
+            best = Some((len, times));
+        }
+    }
+    best
+}
+
+/// The 64-bit FNV-1a hash, used for `Backtrace::fingerprint` because unlike
+/// the hashers of the standard library it's guaranteed to never change.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Fnv1a {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        // Separate consecutive writes so e.g. "ab" + "c" differs from
+        // "a" + "bc".
+        self.0 ^= 0xff;
+        self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for Backtrace {
+    fn default() -> Backtrace {
+        Backtrace::new()
+    }
+}
+
+/// A builder to configure how a `Backtrace` is captured.
+///
+/// This type is created by `Backtrace::builder`, and `capture` can be called
+/// any number of times to capture a new backtrace with the same settings.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug)]
+pub struct BacktraceBuilder {
+    skip: usize,
+    max_frames: Option<usize>,
+    resolve: ResolvePolicy,
+    thread: bool,
+    timestamp: bool,
+}
+
+/// When the symbols of a captured `Backtrace` are resolved.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResolvePolicy {
+    /// Symbols are resolved right away, like with `Backtrace::new`.
+    Eager,
+    /// Only addresses are captured, like with `Backtrace::new_unresolved`.
+    /// Symbols are resolved later by calling `Backtrace::resolve`.
+    Lazy,
+    /// Only addresses are captured, and the `Debug` implementation resolves
+    /// the frames it prints.
+    ///
+    /// This is meant for backtraces which are stored in errors and only
+    /// rarely printed. Frames which aren't printed, for example because of a
+    /// precision like `{:.10?}` limiting the number of printed frames, are
+    /// never resolved. The symbols resolved for printing are kept for the next
+    /// time the backtrace is printed, but `Backtrace::frames` only returns
+    /// them once `Backtrace::resolve` is called.
+    OnPrint,
+}
+
+/// Whether a `Backtrace` was captured, as returned by `Backtrace::status`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BacktraceStatus {
+    /// Capturing backtraces isn't supported on this target, so the backtrace
+    /// has no frames.
+    Unsupported,
+    /// Capturing was turned off, see `Backtrace::disabled`, so the backtrace
+    /// has no frames.
+    Disabled,
+    /// The backtrace was captured, and has `Backtrace::frames`.
+    Captured,
+}
+
+impl BacktraceBuilder {
+    fn new() -> BacktraceBuilder {
+        BacktraceBuilder {
+            skip: 0,
+            max_frames: None,
+            resolve: ResolvePolicy::Eager,
+            thread: false,
+            timestamp: false,
+        }
+    }
+
+    /// Omits `n` more frames from the top of the backtrace, in addition to
+    /// the frames of this crate which are always omitted.
+    ///
+    /// This is useful for error libraries whose constructors would otherwise
+    /// show up at the top of every backtrace. The skipped frames are still
+    /// shown by the full (`{:#?}`) format. Defaults to 0.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn skip(mut self, n: usize) -> BacktraceBuilder {
+        self.skip = n;
+        self
+    }
+
+    /// Stops capturing once `max` frames, not counting omitted ones, have
+    /// been captured.
+    ///
+    /// A backtrace which was cut short this way reports `true` from
+    /// `Backtrace::is_truncated`. Defaults to no limit other than the global
+    /// one configured with `backtrace::set_max_frames`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn max_frames(mut self, max: usize) -> BacktraceBuilder {
+        self.max_frames = Some(max);
+        self
+    }
+
+    /// Configures when symbols are resolved. Defaults to
+    /// `ResolvePolicy::Eager`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn resolve(mut self, policy: ResolvePolicy) -> BacktraceBuilder {
+        self.resolve = policy;
+        self
+    }
+
+    /// Configures whether the id and name of the capturing thread are
+    /// recorded, see `Backtrace::thread_id` and `Backtrace::thread_name`.
+    /// They're also printed by the `Debug` implementation of `Backtrace`.
+    /// Defaults to `false`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn record_thread(mut self, record: bool) -> BacktraceBuilder {
+        self.thread = record;
+        self
+    }
+
+    /// Configures whether a monotonic timestamp is recorded, see
+    /// `Backtrace::timestamp`. It's also printed by the `Debug`
+    /// implementation of `Backtrace`. Defaults to `false`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn record_timestamp(mut self, record: bool) -> BacktraceBuilder {
+        self.timestamp = record;
+        self
+    }
+
+    /// Captures a backtrace at the callsite of this function with the
+    /// configured settings.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[inline(never)] // want to make sure there's a frame here to remove
+    pub fn capture(&self) -> Backtrace {
+        let mut bt = Backtrace::create_with(
+            Self::capture as *const () as usize,
+            self.skip,
+            self.max_frames,
+        );
+        if self.timestamp {
+            bt.timestamp = Some(timestamp());
+        }
+        if self.thread {
+            bt.thread_id = Some(OsThreadId::current().as_raw());
+            bt.thread_name = std::thread::current().name().map(String::from);
+        }
+        match self.resolve {
+            ResolvePolicy::Eager => bt.resolve(),
+            ResolvePolicy::Lazy => {}
+            ResolvePolicy::OnPrint => bt.resolve_on_print = true,
+        }
+        bt
+    }
+}
+
+// The point in time timestamps are measured from, see `Backtrace::timestamp`.
+static mut EPOCH: *mut Instant = ptr::null_mut();
+
+// Returns the nanoseconds since `EPOCH`, which is set on the first call.
+fn timestamp() -> u64 {
+    let _guard = crate::lock::lock();
+    unsafe {
+        if EPOCH.is_null() {
+            EPOCH = Box::into_raw(Box::new(Instant::now()));
+        }
+        let elapsed = (*EPOCH).elapsed();
+        elapsed.as_secs() * 1_000_000_000 + u64::from(elapsed.subsec_nanos())
+    }
+}
+
+impl Default for BacktraceBuilder {
+    fn default() -> BacktraceBuilder {
+        BacktraceBuilder::new()
+    }
+}
 
 impl fmt::Debug for BacktraceFrame {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -420,6 +2344,58 @@ impl fmt::Debug for BacktraceFrame {
     }
 }
 
+impl PartialEq for Backtrace {
+    fn eq(&self, other: &Backtrace) -> bool {
+        self.frames() == other.frames()
+    }
+}
+
+impl Eq for Backtrace {}
+
+impl Hash for Backtrace {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.frames().hash(state)
+    }
+}
+
+impl PartialOrd for Backtrace {
+    fn partial_cmp(&self, other: &Backtrace) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Backtrace {
+    fn cmp(&self, other: &Backtrace) -> Ordering {
+        self.frames().cmp(other.frames())
+    }
+}
+
+impl PartialEq for BacktraceFrame {
+    fn eq(&self, other: &BacktraceFrame) -> bool {
+        self.ip() == other.ip()
+    }
+}
+
+impl Eq for BacktraceFrame {}
+
+impl Hash for BacktraceFrame {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.ip().hash(state)
+    }
+}
+
+impl PartialOrd for BacktraceFrame {
+    fn partial_cmp(&self, other: &BacktraceFrame) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BacktraceFrame {
+    fn cmp(&self, other: &BacktraceFrame) -> Ordering {
+        self.ip().cmp(&other.ip())
+    }
+}
+
 impl fmt::Debug for BacktraceSymbol {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt.debug_struct("BacktraceSymbol")
@@ -477,12 +2453,33 @@ mod rustc_serialize_impls {
             .encode(e)
         }
     }
+
+    // Symbols cached for printing aren't serialized, so decoded backtraces
+    // start out without any.
+    impl Decodable for PrintCache {
+        fn decode<D>(d: &mut D) -> Result<Self, D::Error>
+        where
+            D: Decoder,
+        {
+            d.read_nil()?;
+            Ok(PrintCache::default())
+        }
+    }
+
+    impl Encodable for PrintCache {
+        fn encode<E>(&self, e: &mut E) -> Result<(), E::Error>
+        where
+            E: Encoder,
+        {
+            e.emit_nil()
+        }
+    }
 }
 
 #[cfg(feature = "serde")]
 mod serde_impls {
     use super::*;
-    use serde::de::Deserializer;
+    use serde::de::{Deserializer, Error};
     use serde::ser::Serializer;
     use serde::{Deserialize, Serialize};
 
@@ -510,6 +2507,91 @@ mod serde_impls {
         }
     }
 
+    #[derive(Serialize)]
+    struct SerializedBacktrace<'a> {
+        version: u32,
+        frames: &'a [BacktraceFrame],
+        actual_start_index: usize,
+        truncated: bool,
+        resolve_on_print: bool,
+        thread_id: Option<u64>,
+        thread_name: Option<&'a str>,
+        timestamp: Option<u64>,
+        disabled: bool,
+    }
+
+    #[derive(Deserialize)]
+    struct DeserializedBacktrace {
+        #[serde(default = "unversioned")]
+        version: u32,
+        frames: Vec<BacktraceFrame>,
+        actual_start_index: usize,
+        #[serde(default)]
+        truncated: bool,
+        #[serde(default)]
+        resolve_on_print: bool,
+        #[serde(default)]
+        thread_id: Option<u64>,
+        #[serde(default)]
+        thread_name: Option<String>,
+        #[serde(default)]
+        timestamp: Option<u64>,
+        #[serde(default)]
+        disabled: bool,
+    }
+
+    // The version of backtraces serialized before the version was recorded.
+    fn unversioned() -> u32 {
+        1
+    }
+
+    impl Serialize for Backtrace {
+        fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            SerializedBacktrace {
+                version: Backtrace::SERDE_SCHEMA_VERSION,
+                frames: &self.frames,
+                actual_start_index: self.actual_start_index,
+                truncated: self.truncated,
+                resolve_on_print: self.resolve_on_print,
+                thread_id: self.thread_id,
+                thread_name: self.thread_name.as_deref(),
+                timestamp: self.timestamp,
+                disabled: self.disabled,
+            }
+            .serialize(s)
+        }
+    }
+
+    impl<'a> Deserialize<'a> for Backtrace {
+        fn deserialize<D>(d: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'a>,
+        {
+            let bt = DeserializedBacktrace::deserialize(d)?;
+            if bt.version > Backtrace::SERDE_SCHEMA_VERSION {
+                return Err(D::Error::custom(format_args!(
+                    "unsupported backtrace schema version {}, expected at most {}",
+                    bt.version,
+                    Backtrace::SERDE_SCHEMA_VERSION
+                )));
+            }
+            Ok(Backtrace {
+                frames: bt.frames,
+                actual_start_index: bt.actual_start_index,
+                truncated: bt.truncated,
+                resolve_on_print: bt.resolve_on_print,
+                printed: PrintCache::default(),
+                thread_id: bt.thread_id,
+                thread_name: bt.thread_name,
+                timestamp: bt.timestamp,
+                disabled: bt.disabled,
+            })
+        }
+    }
+
     impl<'a> Deserialize<'a> for BacktraceFrame {
         fn deserialize<D>(d: D) -> Result<Self, D::Error>
         where
@@ -552,4 +2634,144 @@ mod tests {
             println!("{:?}", frame.symbols());
         }
     }
+
+    #[test]
+    fn test_markdown() {
+        let bt = Backtrace::new();
+        let plain = format!("{:?}", bt);
+
+        let md = format!("{}", bt.markdown());
+        assert!(md.starts_with("<details>\n<summary>"));
+        assert!(md.ends_with("```\n\n</details>\n"));
+        assert!(md.contains(&format!("```text\n{}```\n", plain)));
+        assert!(!md.contains("<summary>backtrace::"));
+
+        let md = format!("{}", bt.markdown().details(false));
+        assert_eq!(md, format!("```text\n{}```\n", plain));
+    }
+
+    #[test]
+    fn test_builder() {
+        let full = Backtrace::builder().resolve(ResolvePolicy::Lazy).capture();
+        assert!(full.frames().iter().all(|f| f.symbols.is_none()));
+
+        let bt = Backtrace::builder().skip(1).max_frames(2).capture();
+        assert!(bt.frames().len() <= 2);
+        assert!(bt.frames().iter().all(|f| f.symbols.is_some()));
+        if full.frames().len() > 3 {
+            assert!(bt.is_truncated());
+            assert_eq!(bt.frames().len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_resolve_range() {
+        let mut bt = Backtrace::new_unresolved();
+        let len = bt.frames().len();
+        bt.resolve_range(0..1);
+        bt.resolve_range(len..len + 10);
+        assert!(bt.frames().iter().take(1).all(|f| f.symbols.is_some()));
+        assert!(bt.frames().iter().skip(1).all(|f| f.symbols.is_none()));
+
+        let mut names = 0;
+        bt.frames[bt.actual_start_index + len - 1].resolve_with(&mut |_| names += 1);
+        assert_eq!(
+            bt.frames()
+                .last()
+                .unwrap()
+                .symbols
+                .as_ref()
+                .map(|s| s.len()),
+            Some(names)
+        );
+    }
+
+    #[test]
+    fn test_resolve_with_timeout() {
+        let mut bt = Backtrace::new_unresolved();
+        assert_eq!(
+            bt.resolve_with_timeout(Duration::from_secs(0)),
+            bt.frames.is_empty()
+        );
+        assert!(bt.frames().iter().all(|f| !f.is_resolved()));
+
+        assert!(bt.resolve_with_timeout(Duration::from_secs(3600)));
+        assert!(bt.frames.iter().all(|f| f.is_resolved()));
+        let mut resolved = bt.clone();
+        for frame in resolved.frames.iter_mut() {
+            frame.symbols = None;
+        }
+        resolved.resolve();
+        assert_eq!(format!("{:?}", bt), format!("{:?}", resolved));
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        // Capture from the same call site so both backtraces are identical.
+        let captures = (0..2)
+            .map(|_| Backtrace::new_unresolved())
+            .collect::<Vec<_>>();
+        let (a, b) = (&captures[0], &captures[1]);
+        assert_eq!(a.fingerprint(), b.fingerprint());
+
+        let mut resolved = a.clone();
+        resolved.resolve();
+        assert_eq!(a.fingerprint(), resolved.fingerprint());
+    }
+
+    #[test]
+    fn test_raw_bytes() {
+        let bt = Backtrace::new_unresolved();
+        let (decoded, modules) = Backtrace::from_raw_bytes(&bt.to_raw_bytes()).unwrap();
+        assert_eq!(decoded.frames().len(), bt.frames().len());
+        for (a, b) in decoded.frames().iter().zip(bt.frames()) {
+            assert_eq!(a.ip(), b.ip());
+            if let Some(base) = a.module_base_address() {
+                assert!(modules.iter().any(|m| m.base() == base as usize));
+            }
+        }
+    }
+
+    #[test]
+    fn test_resolve_uses_resolver_hooks() {
+        // Nothing is ever mapped this low, so only the hook knows about it.
+        crate::register_resolver(
+            0x5000..0x6000,
+            Box::new(|_, cb| cb(&Symbol::new("generated_code"))),
+        );
+
+        let mut frames: Vec<BacktraceFrame> = Backtrace::new_unresolved().into();
+        frames.extend(Backtrace::from_ips(vec![0x5011]).frames);
+        let mut bt = Backtrace::from(frames);
+        bt.resolve();
+
+        let frames = bt.frames();
+        assert!(frames[..frames.len() - 1]
+            .iter()
+            .any(|f| !f.symbols().is_empty()));
+        let names = frames[frames.len() - 1]
+            .symbols()
+            .iter()
+            .map(|s| s.name().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["generated_code"]);
+    }
+
+    #[test]
+    fn test_resolve_on_print() {
+        let bt = Backtrace::builder()
+            .resolve(ResolvePolicy::OnPrint)
+            .capture();
+        assert!(bt.frames().iter().all(|f| f.symbols.is_none()));
+
+        let mut resolved = bt.clone();
+        resolved.resolve();
+        assert_eq!(format!("{:?}", bt), format!("{:?}", resolved));
+        assert_eq!(format!("{:.1?}", bt), format!("{:.1?}", resolved));
+        assert!(bt.frames().iter().all(|f| f.symbols.is_none()));
+
+        let printed = bt.printed.lock();
+        assert_eq!(printed.len(), bt.frames.len());
+        assert!(printed[bt.actual_start_index..].iter().all(|s| s.is_some()));
+    }
 }
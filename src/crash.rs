@@ -0,0 +1,412 @@
+//! Reporting crashes to a supervisor process.
+//!
+//! Symbolizing a backtrace needs memory, locks and debug information read
+//! from the filesystem, none of which can be relied upon in a process which
+//! has just crashed, say because its heap is corrupted. Crash handlers like
+//! Crashpad's therefore split the work: the crashing process only writes out
+//! what it knows about the crash to a pipe or socket, and a healthy supervisor
+//! process reads it and does the symbolization.
+//!
+//! This module provides both halves. The crashing process calls `prepare` at
+//! startup, which records the table of loaded modules ahead of time, and then
+//! `write_crash_report` from its signal handler. The supervisor reads the
+//! report with `CrashReport::read_from` and resolves it with
+//! `CrashReport::resolve`, which uses the object files at the paths of the
+//! crashed process's modules.
+//!
+//! # Format
+//!
+//! All integers are little-endian. The layout is:
+//!
+//! ```text
+//! magic        b"BTCR"
+//! version      u8 (currently 1)
+//! signal       u32
+//! address      u64, the faulting address of the signal
+//! registers    u8 count, then for each:
+//!                u8 name length, name bytes
+//!                u64 value
+//! backtrace    the raw backtrace, as written by `Backtrace::to_raw_bytes`
+//! ```
+//!
+//! # Required features
+//!
+//! This module requires the `std` feature of the `backtrace` crate to be
+//! enabled, and the `std` feature is enabled by default.
+
+use crate::offline::{Module, Symbolizer};
+use crate::signal_safe::FdWriter;
+use crate::wire::{self, ErrorKind, Reader};
+use crate::{Backtrace, RawBytesError, RawModule};
+use core::ffi::c_void;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering::SeqCst};
+use std::io::{self, Read};
+use std::prelude::v1::*;
+use std::str;
+
+const MAGIC: &[u8; 4] = b"BTCR";
+const VERSION: u8 = 1;
+
+/// The most frames written for a crash.
+const MAX_FRAMES: usize = 256;
+
+/// What `prepare` recorded about the modules of this process.
+struct Prepared {
+    /// The module table, encoded for the raw backtrace.
+    modules: Vec<u8>,
+    /// The address ranges of the modules, with their index into the table.
+    ranges: Vec<(usize, usize, u32)>,
+}
+
+// Replaced by `prepare`, which leaks the previous value since a signal
+// handler may still be reading it.
+static PREPARED: AtomicPtr<Prepared> = AtomicPtr::new(ptr::null_mut());
+
+/// Records the modules loaded into this process, so that crash reports list
+/// them.
+///
+/// This must be called outside of any signal handler, before a crash report
+/// can be written, and again after libraries have been loaded or unloaded.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn prepare() {
+    crate::backtrace::init_max_frames();
+    let mut modules = Vec::new();
+    let mut ranges = Vec::new();
+    for (i, module) in crate::modules().into_iter().enumerate() {
+        for range in module.ranges() {
+            ranges.push((range.start, range.end, i as u32));
+        }
+        modules.push(RawModule::new(
+            module.path().to_path_buf(),
+            module.bias(),
+            module.build_id().map(|id| id.to_vec()),
+        ));
+    }
+    let mut encoded = Vec::new();
+    wire::encode_modules(&mut encoded, &modules);
+    let prepared = Box::new(Prepared {
+        modules: encoded,
+        ranges,
+    });
+    PREPARED.store(Box::into_raw(prepared), SeqCst);
+}
+
+/// Writes a report of the crash a signal handler was called for to the file
+/// descriptor `fd`, such as a pipe or socket connected to a supervisor.
+///
+/// `signal`, `info` and `context` are the arguments the handler was called
+/// with, which must have been installed with `SA_SIGINFO`. The report holds
+/// the signal, its faulting address, the registers of the interrupted thread,
+/// the instruction pointers of up to 256 frames traced from `context`, and
+/// the modules recorded by `prepare`, which is all read back by
+/// `CrashReport`.
+///
+/// This performs no allocation and takes no locks, so it can be used from
+/// within a signal handler.
+///
+/// # Safety
+///
+/// `info` and `context` must be null or the pointers passed to a signal
+/// handler running on the calling thread, this must not be called
+/// concurrently with `prepare`, and `fd` must be a valid file descriptor.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub unsafe fn write_crash_report(
+    fd: libc::c_int,
+    signal: libc::c_int,
+    info: *const libc::siginfo_t,
+    context: *const c_void,
+) {
+    let mut out = FdWriter::new(fd);
+    out.write_bytes(MAGIC);
+    out.write_bytes(&[VERSION]);
+    out.write_bytes(&(signal as u32).to_le_bytes());
+    let addr = if info.is_null() {
+        0
+    } else {
+        (*info).si_addr() as usize
+    };
+    out.write_bytes(&(addr as u64).to_le_bytes());
+    write_registers(&mut out, context);
+
+    let prepared = PREPARED.load(SeqCst).as_ref();
+    out.write_bytes(wire::MAGIC);
+    out.write_bytes(&[wire::VERSION]);
+    match prepared {
+        Some(prepared) => out.write_bytes(&prepared.modules),
+        None => out.write_bytes(&0u32.to_le_bytes()),
+    }
+    let mut ips = [0; MAX_FRAMES];
+    let mut len = 0;
+    crate::trace_from_context_unsynchronized(context, |frame| {
+        ips[len] = frame.ip() as usize;
+        len += 1;
+        len < ips.len()
+    });
+    out.write_bytes(&(len as u32).to_le_bytes());
+    for ip in &ips[..len] {
+        let module = prepared
+            .and_then(|p| p.ranges.iter().find(|r| r.0 <= *ip && *ip < r.1))
+            .map_or(wire::NO_MODULE, |r| r.2);
+        out.write_bytes(&(*ip as u64).to_le_bytes());
+        out.write_bytes(&module.to_le_bytes());
+    }
+    out.flush();
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(all(target_os = "linux", target_arch = "x86_64"))] {
+        const REGISTERS: [(&str, libc::c_int); 18] = [
+            ("rax", libc::REG_RAX),
+            ("rbx", libc::REG_RBX),
+            ("rcx", libc::REG_RCX),
+            ("rdx", libc::REG_RDX),
+            ("rsi", libc::REG_RSI),
+            ("rdi", libc::REG_RDI),
+            ("rbp", libc::REG_RBP),
+            ("rsp", libc::REG_RSP),
+            ("r8", libc::REG_R8),
+            ("r9", libc::REG_R9),
+            ("r10", libc::REG_R10),
+            ("r11", libc::REG_R11),
+            ("r12", libc::REG_R12),
+            ("r13", libc::REG_R13),
+            ("r14", libc::REG_R14),
+            ("r15", libc::REG_R15),
+            ("rip", libc::REG_RIP),
+            ("eflags", libc::REG_EFL),
+        ];
+
+        unsafe fn write_registers(out: &mut FdWriter, context: *const c_void) {
+            let uc = match (context as *const libc::ucontext_t).as_ref() {
+                Some(uc) => uc,
+                None => return out.write_bytes(&[0]),
+            };
+            out.write_bytes(&[REGISTERS.len() as u8]);
+            for (name, index) in REGISTERS.iter() {
+                write_register(out, name, uc.uc_mcontext.gregs[*index as usize] as u64);
+            }
+        }
+    } else if #[cfg(all(target_os = "linux", target_arch = "aarch64"))] {
+        const NAMES: [&str; 31] = [
+            "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11", "x12",
+            "x13", "x14", "x15", "x16", "x17", "x18", "x19", "x20", "x21", "x22", "x23", "x24",
+            "x25", "x26", "x27", "x28", "x29", "x30",
+        ];
+
+        unsafe fn write_registers(out: &mut FdWriter, context: *const c_void) {
+            let mc = match (context as *const libc::ucontext_t).as_ref() {
+                Some(uc) => &uc.uc_mcontext,
+                None => return out.write_bytes(&[0]),
+            };
+            out.write_bytes(&[NAMES.len() as u8 + 3]);
+            for (name, value) in NAMES.iter().zip(mc.regs.iter()) {
+                write_register(out, name, *value as u64);
+            }
+            write_register(out, "sp", mc.sp as u64);
+            write_register(out, "pc", mc.pc as u64);
+            write_register(out, "pstate", mc.pstate as u64);
+        }
+    } else {
+        unsafe fn write_registers(out: &mut FdWriter, _context: *const c_void) {
+            out.write_bytes(&[0]);
+        }
+    }
+}
+
+#[allow(dead_code)]
+fn write_register(out: &mut FdWriter, name: &str, value: u64) {
+    out.write_bytes(&[name.len() as u8]);
+    out.write_bytes(name.as_bytes());
+    out.write_bytes(&value.to_le_bytes());
+}
+
+/// A crash report written by `write_crash_report`, as read by a supervisor.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug)]
+pub struct CrashReport {
+    signal: i32,
+    address: usize,
+    registers: Vec<(String, u64)>,
+    backtrace: Backtrace,
+    modules: Vec<RawModule>,
+}
+
+impl CrashReport {
+    /// Decodes a crash report from the bytes written by
+    /// `write_crash_report`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn from_bytes(bytes: &[u8]) -> Result<CrashReport, RawBytesError> {
+        let mut r = Reader(bytes);
+        if r.take(MAGIC.len())? != MAGIC {
+            return Err(wire::error(ErrorKind::BadMagic));
+        }
+        match r.take(1)?[0] {
+            VERSION => {}
+            v => return Err(wire::error(ErrorKind::UnsupportedVersion(v))),
+        }
+        let signal = r.u32()? as i32;
+        let address = r.u64()? as usize;
+        let count = r.take(1)?[0];
+        let mut registers = Vec::new();
+        for _ in 0..count {
+            let len = r.take(1)?[0] as usize;
+            let name = String::from_utf8_lossy(r.take(len)?).into_owned();
+            registers.push((name, r.u64()?));
+        }
+        let (backtrace, modules) = Backtrace::from_raw_bytes(r.0)?;
+        Ok(CrashReport {
+            signal,
+            address,
+            registers,
+            backtrace,
+            modules,
+        })
+    }
+
+    /// Reads a crash report from `reader` until it's closed, as the
+    /// supervisor's end of a pipe or socket is once the crashed process has
+    /// exited.
+    ///
+    /// Malformed reports are returned as errors of the kind
+    /// `io::ErrorKind::InvalidData`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<CrashReport> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        CrashReport::from_bytes(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Symbolizes the backtrace of the report with the object files of the
+    /// crashed process's modules, which are looked for at the same paths.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn resolve(&mut self) {
+        let mut symbolizer = Symbolizer::new();
+        for module in self.modules.iter() {
+            symbolizer.add_module(Module::from(module));
+        }
+        self.backtrace.resolve_offline(&mut symbolizer);
+    }
+
+    /// Returns the number of the signal the process crashed with.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn signal(&self) -> i32 {
+        self.signal
+    }
+
+    /// Returns the faulting address of the signal, which is 0 for signals
+    /// without one.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn address(&self) -> usize {
+        self.address
+    }
+
+    /// Returns the registers of the crashed thread and their values, named
+    /// as they are in assembly like `rip` and `x30`.
+    ///
+    /// Registers are only reported on x86_64 and AArch64 Linux.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn registers(&self) -> &[(String, u64)] {
+        &self.registers
+    }
+
+    /// Returns the value of the register called `name`, if it was reported.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn register(&self, name: &str) -> Option<u64> {
+        self.registers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| *value)
+    }
+
+    /// Returns the backtrace of the crashed thread, whose first frame is the
+    /// instruction the thread was executing. It's unresolved until `resolve`
+    /// is called.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.backtrace
+    }
+
+    /// Returns the modules which were loaded into the crashed process.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn modules(&self) -> &[RawModule] {
+        &self.modules
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed() {
+        let mut report = Vec::new();
+        report.extend_from_slice(MAGIC);
+        report.push(VERSION);
+        report.extend_from_slice(&11u32.to_le_bytes());
+        report.extend_from_slice(&0x10u64.to_le_bytes());
+        report.push(1);
+        report.push(2);
+        report.extend_from_slice(b"pc");
+        report.extend_from_slice(&0x1234u64.to_le_bytes());
+        report.extend_from_slice(&wire::encode(&[(0x1234, None)], &[]));
+
+        let parsed = CrashReport::from_bytes(&report).unwrap();
+        assert_eq!((parsed.signal(), parsed.address()), (11, 0x10));
+        assert_eq!(parsed.register("pc"), Some(0x1234));
+        assert_eq!(parsed.backtrace().frames().len(), 1);
+
+        assert!(CrashReport::from_bytes(&report[..report.len() - 1]).is_err());
+        assert!(CrashReport::from_bytes(b"BTRW").is_err());
+        report[4] = 2;
+        assert!(CrashReport::from_bytes(&report).is_err());
+    }
+}
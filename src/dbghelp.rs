@@ -26,6 +26,7 @@
 use super::windows::*;
 use core::mem;
 use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 // Work around `SymGetOptions` and `SymSetOptions` not being present in winapi
 // itself. Otherwise this is only used when we're double-checking types against
@@ -55,6 +56,18 @@ mod dbghelp {
             pdwDisplacement: PDWORD,
             Line: PIMAGEHLP_LINEW64,
         ) -> BOOL;
+        pub fn SymSetSearchPathW(hProcess: HANDLE, SearchPath: PCWSTR) -> BOOL;
+        pub fn SymRefreshModuleList(hProcess: HANDLE) -> BOOL;
+        pub fn SymAddrIncludeInlineTrace(hProcess: HANDLE, Address: DWORD64) -> DWORD;
+        pub fn SymQueryInlineTrace(
+            hProcess: HANDLE,
+            StartAddress: DWORD64,
+            StartContext: DWORD,
+            StartRetAddress: DWORD64,
+            CurAddress: DWORD64,
+            CurContext: LPDWORD,
+            CurFrameIndex: LPDWORD,
+        ) -> BOOL;
     }
 
     pub fn assert_equal_types<T>(a: T, _b: T) -> T {
@@ -68,7 +81,7 @@ macro_rules! dbghelp {
     (extern "system" {
         $(fn $name:ident($($arg:ident: $argty:ty),*) -> $ret: ty;)*
     }) => (
-        pub struct Dbghelp {
+        pub(crate) struct Dbghelp {
             /// The loaded DLL for `dbghelp.dll`
             dll: HMODULE,
 
@@ -85,7 +98,7 @@ macro_rules! dbghelp {
         };
 
         // Convenience typedef for each function type.
-        $(pub type $name = unsafe extern "system" fn($($argty),*) -> $ret;)*
+        $(pub(crate) type $name = unsafe extern "system" fn($($argty),*) -> $ret;)*
 
         impl Dbghelp {
             /// Attempts to open `dbghelp.dll`. Returns success if it works or
@@ -110,7 +123,7 @@ macro_rules! dbghelp {
             // Function for each method we'd like to use. When called it will
             // either read the cached function pointer or load it and return the
             // loaded value. Loads are asserted to succeed.
-            $(pub fn $name(&mut self) -> Option<$name> {
+            $(pub(crate) fn $name(&mut self) -> Option<$name> {
                 unsafe {
                     if self.$name == 0 {
                         let name = concat!(stringify!($name), "\0");
@@ -137,13 +150,13 @@ macro_rules! dbghelp {
         // functions.
         #[allow(dead_code)]
         impl Init {
-            $(pub fn $name(&self) -> $name {
+            $(pub(crate) fn $name(&self) -> $name {
                 unsafe {
                     DBGHELP.$name().unwrap()
                 }
             })*
 
-            pub fn dbghelp(&self) -> *mut Dbghelp {
+            pub(crate) fn dbghelp(&self) -> *mut Dbghelp {
                 unsafe {
                     &mut DBGHELP
                 }
@@ -153,7 +166,9 @@ macro_rules! dbghelp {
 
 }
 
+const SYMOPT_UNDNAME: DWORD = 0x00000002;
 const SYMOPT_DEFERRED_LOADS: DWORD = 0x00000004;
+const SYMOPT_DISABLE_SYMSRV_AUTODETECT: DWORD = 0x02000000;
 
 dbghelp! {
     extern "system" {
@@ -223,21 +238,55 @@ dbghelp! {
             pdwDisplacement: PDWORD,
             Line: PIMAGEHLP_LINEW64
         ) -> BOOL;
+        fn SymSetSearchPathW(
+            hProcess: HANDLE,
+            SearchPath: PCWSTR
+        ) -> BOOL;
+        fn SymRefreshModuleList(
+            hProcess: HANDLE
+        ) -> BOOL;
+        fn SymAddrIncludeInlineTrace(
+            hProcess: HANDLE,
+            Address: DWORD64
+        ) -> DWORD;
+        fn SymQueryInlineTrace(
+            hProcess: HANDLE,
+            StartAddress: DWORD64,
+            StartContext: DWORD,
+            StartRetAddress: DWORD64,
+            CurAddress: DWORD64,
+            CurContext: LPDWORD,
+            CurFrameIndex: LPDWORD
+        ) -> BOOL;
     }
 }
 
-pub struct Init {
+pub(crate) struct Init {
     lock: HANDLE,
 }
 
+// Set when `dbghelp.dll` must not be used, so that `init` always fails.
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether `init` may load and use `dbghelp.dll`. If it's disabled before
+/// the first call to `init` the DLL is never loaded.
+#[cfg(feature = "std")]
+pub(crate) fn set_enabled(enabled: bool) {
+    DISABLED.store(!enabled, Ordering::SeqCst);
+}
+
 /// Initialize all support necessary to access `dbghelp` API functions from this
 /// crate.
 ///
 /// Note that this function is **safe**, it internally has its own
 /// synchronization. Also note that it is safe to call this function multiple
 /// times recursively.
-pub fn init() -> Result<Init, ()> {
-    use core::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+pub(crate) fn init() -> Result<Init, ()> {
+    use core::sync::atomic::Ordering::SeqCst;
+
+    if DISABLED.load(SeqCst) {
+        return Err(());
+    }
 
     unsafe {
         // First thing we need to do is to synchronize this function. This can
@@ -311,17 +360,16 @@ pub fn init() -> Result<Init, ()> {
         // done yet or not.
         DBGHELP.ensure_open()?;
 
-        static mut INITIALIZED: bool = false;
         if INITIALIZED {
             return Ok(ret);
         }
 
-        let orig = DBGHELP.SymGetOptions().unwrap()();
-
-        // Ensure that the `SYMOPT_DEFERRED_LOADS` flag is set, because
+        // Unless configured otherwise with `set_dbghelp_deferred_loads`,
+        // ensure that the `SYMOPT_DEFERRED_LOADS` flag is set, because
         // according to MSVC's own docs about this: "This is the fastest, most
         // efficient way to use the symbol handler.", so let's do that!
-        DBGHELP.SymSetOptions().unwrap()(orig | SYMOPT_DEFERRED_LOADS);
+        let orig = DBGHELP.SymGetOptions().unwrap()();
+        DBGHELP.SymSetOptions().unwrap()(options(orig));
 
         // Actually initialize symbols with MSVC. Note that this can fail, but we
         // ignore it. There's not a ton of prior art for this per se, but LLVM
@@ -337,10 +385,233 @@ pub fn init() -> Result<Init, ()> {
         // initialization.
         DBGHELP.SymInitializeW().unwrap()(GetCurrentProcess(), ptr::null_mut(), TRUE);
         INITIALIZED = true;
+
+        // After a `cleanup_dbghelp` the search path has to be set up again.
+        #[cfg(feature = "std")]
+        {
+            if SEARCH_PATH.is_some() || !SYMBOL_SERVERS {
+                ret.update_search_path();
+            }
+        }
         Ok(ret)
     }
 }
 
+// Whether `SymInitializeW` has been called, and `SymCleanup` hasn't since.
+// Only accessed with the lock of an `Init` held.
+static mut INITIALIZED: bool = false;
+
+// The options set with `set_dbghelp_deferred_loads` and
+// `set_dbghelp_undecorated_names`. The latter is 0 to leave dbghelp's default
+// alone, 1 for off and 2 for on.
+static DEFERRED_LOADS: AtomicBool = AtomicBool::new(true);
+static UNDECORATED_NAMES: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns `orig` with the configured options applied.
+fn options(orig: DWORD) -> DWORD {
+    let mut options = if DEFERRED_LOADS.load(Ordering::SeqCst) {
+        orig | SYMOPT_DEFERRED_LOADS
+    } else {
+        orig & !SYMOPT_DEFERRED_LOADS
+    };
+    match UNDECORATED_NAMES.load(Ordering::SeqCst) {
+        1 => options &= !SYMOPT_UNDNAME,
+        2 => options |= SYMOPT_UNDNAME,
+        _ => {}
+    }
+    options
+}
+
+/// Loads and initializes `dbghelp.dll` now rather than when the first
+/// backtrace is captured or symbolized, returning whether it's available.
+///
+/// On Windows backtraces are walked and symbolized with dbghelp, and loading
+/// and initializing it can take hundreds of milliseconds. That's better paid
+/// at a predictable time, like during startup, than wherever the first
+/// backtrace happens to be captured, say in the middle of a frame of a game.
+/// The options dbghelp is initialized with can be configured before this with
+/// `set_dbghelp_deferred_loads` and `set_dbghelp_undecorated_names`, as well
+/// as `set_dbghelp_enabled`, `set_symbol_search_path` and
+/// `set_symbol_server_downloads`.
+///
+/// Initialization is only done once, so calling this again is cheap, unless
+/// `cleanup_dbghelp` has been called since. With deferred loads, which is the
+/// default, the debug information of each module is still only loaded the
+/// first time an address in it is symbolized; turn them off with
+/// `set_dbghelp_deferred_loads` to load it all here instead.
+///
+/// Returns `false` if dbghelp was disabled with `set_dbghelp_enabled` or
+/// couldn't be loaded, in which case backtraces fall back to what's possible
+/// without it.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn preinitialize_dbghelp() -> bool {
+    let _guard = crate::lock::lock();
+    init().is_ok()
+}
+
+/// Configures whether dbghelp defers loading the debug information of a
+/// module until it's needed, which is on by default.
+///
+/// With deferred loads turned off, the debug information of every module
+/// loaded at the time is read when dbghelp is initialized, such as by
+/// `preinitialize_dbghelp`, so that symbolizing doesn't stall on it later.
+/// This takes effect immediately if dbghelp has been initialized already,
+/// though only for modules loaded afterwards.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn set_dbghelp_deferred_loads(enabled: bool) {
+    DEFERRED_LOADS.store(enabled, Ordering::SeqCst);
+    update_options();
+}
+
+/// Configures whether dbghelp reports the undecorated names of C++ symbols,
+/// such as `Foo::bar` rather than `?bar@Foo@@QEAAXXZ`.
+///
+/// By default dbghelp's own default is kept. Names which this crate demangles
+/// itself, like those of Rust, aren't affected. This takes effect immediately
+/// if dbghelp has been initialized already.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn set_dbghelp_undecorated_names(enabled: bool) {
+    UNDECORATED_NAMES.store(if enabled { 2 } else { 1 }, Ordering::SeqCst);
+    update_options();
+}
+
+#[cfg(feature = "std")]
+fn update_options() {
+    let _guard = crate::lock::lock();
+    unsafe {
+        if !INITIALIZED {
+            return;
+        }
+        if let Ok(dbghelp) = init() {
+            let orig = dbghelp.SymGetOptions()();
+            dbghelp.SymSetOptions()(options(orig));
+        }
+    }
+}
+
+/// Releases the symbol handler of dbghelp, along with the debug information it
+/// has loaded.
+///
+/// This is the counterpart of `preinitialize_dbghelp`: the next backtrace
+/// which needs dbghelp initializes it again, with the options and search path
+/// configured at that time. The DLL itself stays loaded.
+///
+/// # Caveats
+///
+/// There's only one symbol handler per process, shared with everything else
+/// which uses dbghelp. That includes the copy of this crate in the standard
+/// library, which prints the backtraces of panics, and which won't be able to
+/// symbolize anything after this until the process exits.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn cleanup_dbghelp() {
+    let _guard = crate::lock::lock();
+    unsafe {
+        if !INITIALIZED {
+            return;
+        }
+        if let Ok(dbghelp) = init() {
+            dbghelp.SymCleanup()(GetCurrentProcess());
+            INITIALIZED = false;
+        }
+    }
+}
+
+// The search path set with `set_search_path` and whether symbol servers may be
+// used, both only accessed with the lock of an `Init` held.
+#[cfg(feature = "std")]
+static mut SEARCH_PATH: Option<std::ffi::OsString> = None;
+#[cfg(feature = "std")]
+static mut SYMBOL_SERVERS: bool = true;
+
+#[cfg(feature = "std")]
+impl Init {
+    /// Sets the path that dbghelp searches for PDB files, or goes back to its
+    /// default path for `None`.
+    pub(crate) fn set_search_path(&self, path: Option<&std::ffi::OsStr>) {
+        unsafe {
+            SEARCH_PATH = path.map(|path| path.to_owned());
+        }
+        self.update_search_path();
+    }
+
+    /// Sets whether PDB files may be downloaded from symbol servers.
+    pub(crate) fn set_symbol_servers(&self, enabled: bool) {
+        unsafe {
+            SYMBOL_SERVERS = enabled;
+        }
+        self.update_search_path();
+    }
+
+    fn update_search_path(&self) {
+        use std::os::windows::ffi::OsStrExt;
+        use std::prelude::v1::*;
+
+        // Without a path of our own, this is the path dbghelp forms itself
+        // when initialized without one.
+        let path = unsafe { SEARCH_PATH.clone() }.unwrap_or_else(|| {
+            let mut path = std::ffi::OsString::from(".");
+            for var in ["_NT_SYMBOL_PATH", "_NT_ALTERNATE_SYMBOL_PATH"].iter() {
+                if let Some(value) = std::env::var_os(var) {
+                    path.push(";");
+                    path.push(value);
+                }
+            }
+            path
+        });
+        let servers = unsafe { SYMBOL_SERVERS };
+
+        // Symbol servers are the `srv*` and `symsrv*` elements of the path,
+        // and URLs, which dbghelp detects as symbol servers on its own unless
+        // told not to.
+        let path = path.encode_wide().collect::<Vec<_>>();
+        let mut wide = Vec::with_capacity(path.len() + 1);
+        for element in path.split(|c| *c == u16::from(b';')) {
+            let lower = String::from_utf16_lossy(element).to_ascii_lowercase();
+            let is_server =
+                lower.starts_with("srv*") || lower.starts_with("symsrv*") || lower.contains("://");
+            if element.is_empty() || (is_server && !servers) {
+                continue;
+            }
+            if !wide.is_empty() {
+                wide.push(u16::from(b';'));
+            }
+            wide.extend_from_slice(element);
+        }
+        wide.push(0);
+
+        unsafe {
+            let options = self.SymGetOptions()();
+            let options = if servers {
+                options & !SYMOPT_DISABLE_SYMSRV_AUTODETECT
+            } else {
+                options | SYMOPT_DISABLE_SYMSRV_AUTODETECT
+            };
+            self.SymSetOptions()(options);
+            self.SymSetSearchPathW()(GetCurrentProcess(), wide.as_ptr());
+        }
+    }
+}
+
 impl Drop for Init {
     fn drop(&mut self) {
         unsafe {
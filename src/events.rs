@@ -0,0 +1,76 @@
+//! Emitting backtraces as `tracing` events, with the `tracing` feature.
+
+use crate::Backtrace;
+use std::prelude::v1::*;
+use tracing::field::{self, debug};
+use tracing::Level;
+
+// `tracing::event!` needs the level to be a constant.
+macro_rules! event {
+    ($level:expr, $($fields:tt)*) => {
+        if $level == Level::ERROR {
+            tracing::event!(target: "backtrace", Level::ERROR, $($fields)*)
+        } else if $level == Level::WARN {
+            tracing::event!(target: "backtrace", Level::WARN, $($fields)*)
+        } else if $level == Level::INFO {
+            tracing::event!(target: "backtrace", Level::INFO, $($fields)*)
+        } else if $level == Level::DEBUG {
+            tracing::event!(target: "backtrace", Level::DEBUG, $($fields)*)
+        } else {
+            tracing::event!(target: "backtrace", Level::TRACE, $($fields)*)
+        }
+    };
+}
+
+impl Backtrace {
+    /// Emits the frames of this backtrace as `tracing` events, so they reach
+    /// logging and observability backends as structured data rather than as
+    /// one multi-line message.
+    ///
+    /// One event with the target `backtrace` and the message `frame` is
+    /// emitted at `level` for each symbol of each of `frames`, innermost
+    /// first, with these fields:
+    ///
+    /// * `index`, the index of the frame, shared by the symbols of a frame
+    ///   with inlined functions,
+    /// * `ip`, the instruction pointer of the frame,
+    /// * `symbol`, the demangled name of the function without its hash,
+    /// * `file`, `line` and `column`, the location in the source, and
+    /// * `module`, the path of the binary the frame is in.
+    ///
+    /// Fields which aren't known are left out, and frames which haven't been
+    /// resolved only get an event with their `index` and `ip`. Events are
+    /// emitted in the current span, so entering a span first groups the
+    /// frames of a backtrace together.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` and `tracing` features of the
+    /// `backtrace` crate to be enabled, and the `std` feature is enabled by
+    /// default.
+    pub fn emit_tracing_events(&self, level: Level) {
+        for (index, frame) in self.frames().iter().enumerate() {
+            let ip = debug(frame.ip());
+            if frame.symbols().is_empty() {
+                event!(level, index, ip, "frame");
+                continue;
+            }
+            for symbol in frame.symbols() {
+                let name = symbol.name().map(|name| format!("{:#}", name));
+                let file = symbol.filename().map(|file| file.display());
+                let module = symbol.module_path().map(|path| path.display());
+                event!(
+                    level,
+                    index,
+                    ip,
+                    symbol = name.as_deref(),
+                    file = file.map(field::display),
+                    line = symbol.lineno(),
+                    column = symbol.colno(),
+                    module = module.map(field::display),
+                    "frame"
+                );
+            }
+        }
+    }
+}
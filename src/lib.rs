@@ -72,8 +72,10 @@
 //!   because debug information wasn't generated by the compiler, or it's just
 //!   missing on the filesystem.
 //!
-//! * Not all platforms are supported. For example there's no way to get a
-//!   backtrace on WebAssembly at the moment.
+//! * Not all platforms are supported. For example WebAssembly code can't
+//!   walk its own stack, so there are no backtraces on wasm targets, except
+//!   for wasm32-unknown-unknown with the `js` feature, where the frames the
+//!   JS host reports are used (with names and locations, but no addresses).
 //!
 //! * Crate features may be disabled. Currently this crate supports using Gimli
 //!   libbacktrace on non-Windows platforms for reading debuginfo for
@@ -106,15 +108,25 @@
 extern crate std;
 
 // This is only used for gimli right now, which is only used on some platforms, and miri
-// so don't worry if it's unused in other configurations.
+// and the JS backend, so don't worry if it's unused in other configurations.
 #[allow(unused_extern_crates)]
 extern crate alloc;
 
-pub use self::backtrace::{trace_unsynchronized, Frame};
+pub use self::backtrace::{max_frames, set_max_frames, set_stack_scanning};
+pub use self::backtrace::{
+    trace_from_context_unsynchronized, trace_unsynchronized, Frame, FrameTrust,
+};
+pub use self::backtrace::{trace_into_unsynchronized, trace_ips_into_unsynchronized};
 mod backtrace;
 
+pub use self::symbolize::image;
 pub use self::symbolize::resolve_frame_unsynchronized;
-pub use self::symbolize::{resolve_unsynchronized, Symbol, SymbolName};
+pub use self::symbolize::set_demangling_enabled;
+pub use self::symbolize::{
+    resolve_frame_with_ip_adjustment_unsynchronized, resolve_with_ip_adjustment_unsynchronized,
+    IpAdjustment,
+};
+pub use self::symbolize::{resolve_unsynchronized, Symbol, SymbolLanguage, SymbolName};
 mod symbolize;
 
 pub use self::types::BytesOrWideString;
@@ -124,17 +136,88 @@ mod types;
 pub use self::symbolize::clear_symbol_cache;
 
 mod print;
-pub use print::{BacktraceFmt, BacktraceFrameFmt, PrintFmt};
+pub use print::{BacktraceFmt, BacktraceFrameFmt, PrintElement, PrintFmt};
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "std")] {
-        pub use self::backtrace::trace;
-        pub use self::symbolize::{resolve, resolve_frame};
-        pub use self::capture::{Backtrace, BacktraceFrame, BacktraceSymbol};
+        pub use self::backtrace::{frames, Frames};
+        pub use self::backtrace::{register_unwind_rule, unregister_unwind_rule, UnwindRule};
+        pub use self::backtrace::{trace, trace_from_context, trace_into, trace_ips_into};
+        pub use self::symbolize::{offline, resolve, resolve_frame, resolve_many};
+        pub use self::symbolize::{resolve_frame_with_ip_adjustment, resolve_with_ip_adjustment};
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub use self::symbolize::remote;
+        pub use self::symbolize::{register_module, unregister_module, ModuleSource};
+        pub use self::symbolize::{evict_symbol_cache, set_symbol_cache_capacity};
+        pub use self::symbolize::{set_object_file_mmap, set_symbol_memory_limit};
+        pub use self::symbolize::{set_symbol_search_path, set_symbol_server_downloads};
+        pub use self::symbolize::set_dbghelp_enabled;
+        pub use self::symbolize::{symbol_cache_usage, SymbolCacheUsage};
+        pub use self::symbolize::prewarm_symbol_cache;
+        pub use self::symbolize::{evict_symbol_cache_module, ModuleKey};
+        pub use self::symbolize::{symbol_cache_stats, ModuleCacheStats};
+        pub use self::symbolize::find_symbol;
+        pub use self::symbolize::write_perf_map;
+        #[cfg(unix)]
+        pub use self::symbolize::write_perf_map_file;
+        pub use self::symbolize::register_resolver;
+        pub use self::symbolize::{install_fixture, Fixture, FixtureGuard};
+        pub use self::symbolize::{clear_demanglers, register_demangler};
+        pub use self::symbolize::{set_diagnostics_enabled, take_diagnostics};
+        pub use self::symbolize::{Diagnostic, DiagnosticKind};
+        pub use self::print::{add_path_remapping, clear_path_remappings};
+        pub use self::print::{clear_frame_filter, set_frame_filter, FrameAction};
+        pub use self::symbolize::{set_object_source, FileSystemSource, ObjectData, ObjectSource};
+        pub use self::capture::{
+            Addr2lineBacktrace, AtosBacktrace, Backtrace, BacktraceDiff, BacktraceFrame,
+            BacktraceSymbol, BreakpadBacktrace, CompactBacktrace, JsonBacktrace,
+            MarkdownBacktrace, SymbolizerMarkupBacktrace,
+        };
+        pub use self::capture::{BacktraceBuilder, BacktraceStatus, ResolvePolicy};
         mod capture;
+        pub use self::threads::{capture_all_threads, OsThreadId, ThreadBacktrace};
+        mod threads;
+        pub use self::wire::{RawBytesError, RawModule};
+        mod wire;
+        pub use self::parse::{parse_backtrace, ParsedFrame, ParsedSymbol};
+        mod parse;
+        pub use self::arena::{BacktraceArena, BacktraceHandle};
+        mod arena;
+        #[cfg(feature = "tracing")]
+        mod events;
+        pub use self::modules::{modules, LoadedModule};
+        pub use self::modules::{add_module_listener, poll_module_events, ModuleEvent};
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        pub use self::modules::process_modules;
+        mod modules;
     }
 }
 
+#[cfg(all(feature = "std", unix))]
+pub mod signal_safe;
+
+#[cfg(feature = "std")]
+pub mod reentrant;
+
+#[cfg(feature = "capi")]
+pub mod capi;
+
+#[cfg(all(
+    feature = "std",
+    any(target_os = "linux", target_os = "android"),
+    not(miri)
+))]
+pub mod profiling;
+
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+pub mod crash;
+
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+pub mod stack_overflow;
+
+#[cfg(all(feature = "std", target_env = "sgx", target_vendor = "fortanix"))]
+pub mod sgx;
+
 #[allow(dead_code)]
 struct Bomb {
     enabled: bool,
@@ -173,6 +256,11 @@ mod lock {
         }
     }
 
+    /// Whether the current thread holds the lock.
+    pub fn is_held() -> bool {
+        LOCK_HELD.with(|l| l.get())
+    }
+
     pub fn lock() -> LockGuard {
         if LOCK_HELD.with(|l| l.get()) {
             return LockGuard(None);
@@ -187,7 +275,12 @@ mod lock {
     }
 }
 
+#[cfg(all(windows, not(target_vendor = "uwp"), feature = "std"))]
+pub use self::dbghelp::{
+    cleanup_dbghelp, preinitialize_dbghelp, set_dbghelp_deferred_loads,
+    set_dbghelp_undecorated_names,
+};
 #[cfg(all(windows, not(target_vendor = "uwp")))]
 mod dbghelp;
-#[cfg(windows)]
+#[cfg(any(windows, target_os = "cygwin"))]
 mod windows;
@@ -0,0 +1,314 @@
+use core::ops::Range;
+use core::ptr;
+use std::path::{Path, PathBuf};
+use std::prelude::v1::*;
+
+/// Returns the modules (the executable and shared libraries) currently loaded
+/// into this process.
+///
+/// Crash reporters typically ship this list along with raw, unresolved
+/// backtraces, so that the addresses in them can later be attributed to
+/// modules and symbolized offline (see the `offline` module).
+///
+/// This uses the same enumeration that the symbolizer uses internally, so on
+/// platforms where this crate doesn't know how to find loaded libraries the
+/// returned list is empty.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn modules() -> Vec<LoadedModule> {
+    let _guard = crate::lock::lock();
+    let modules = crate::symbolize::loaded_modules();
+    unsafe {
+        if !LISTENERS.is_null() {
+            (*LISTENERS).update(modules.clone());
+        }
+    }
+    modules
+}
+
+type Listener = Box<dyn Fn(ModuleEvent<'_>) + Send + Sync>;
+
+struct Listeners {
+    listeners: Vec<Listener>,
+    /// The modules which the listeners were last told about.
+    known: Vec<LoadedModule>,
+}
+
+// Only accessed with the crate's global lock held, and null until the first
+// listener is added.
+static mut LISTENERS: *mut Listeners = ptr::null_mut();
+
+/// A change to the modules loaded into this process, as passed to the
+/// listeners added with `add_module_listener`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Copy, Debug)]
+pub enum ModuleEvent<'a> {
+    /// The module has been loaded.
+    Loaded(&'a LoadedModule),
+    /// The module has been unloaded, and its address ranges may be reused.
+    Unloaded(&'a LoadedModule),
+}
+
+/// Adds a listener which is told about modules being loaded into and
+/// unloaded from this process.
+///
+/// Crash reporters can use this to stream a record of each module as it's
+/// loaded, so that raw traces recorded later can be symbolized even if the
+/// process dies before its modules can be listed with `modules`.
+///
+/// The listener is first called with `ModuleEvent::Loaded` for each module
+/// which is loaded already. Changes are noticed whenever this crate looks at
+/// the loaded modules anyway: when symbolizing finds that the dynamic loader's
+/// list of objects changed (on platforms with `dl_iterate_phdr`) or that
+/// dbghelp's list of modules has to be refreshed (on Windows), and when
+/// `modules` is called. To be told about changes sooner, call
+/// `poll_module_events`, for example after loading a plugin.
+///
+/// The listener is called with the same lock held as for symbolization, so it
+/// must not itself add listeners, poll for events or symbolize.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn add_module_listener(listener: Box<dyn Fn(ModuleEvent<'_>) + Send + Sync>) {
+    let _guard = crate::lock::lock();
+    unsafe {
+        if LISTENERS.is_null() {
+            LISTENERS = Box::into_raw(Box::new(Listeners {
+                listeners: Vec::new(),
+                known: Vec::new(),
+            }));
+        }
+        let listeners = &mut *LISTENERS;
+        listeners.update(crate::symbolize::loaded_modules());
+        for module in listeners.known.iter() {
+            listener(ModuleEvent::Loaded(module));
+        }
+        listeners.listeners.push(listener);
+    }
+}
+
+/// Tells the listeners added with `add_module_listener` about the modules
+/// loaded and unloaded since they were last told about any.
+///
+/// This does nothing if no listeners were added.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn poll_module_events() {
+    let _guard = crate::lock::lock();
+    unsafe { modules_changed() }
+}
+
+/// Called by the symbolizer when it noticed that modules were loaded or
+/// unloaded.
+// unsafe because this is required to be externally synchronized
+pub(crate) unsafe fn modules_changed() {
+    if !LISTENERS.is_null() {
+        (*LISTENERS).update(crate::symbolize::loaded_modules());
+    }
+}
+
+impl Listeners {
+    fn update(&mut self, loaded: Vec<LoadedModule>) {
+        let unloaded = self
+            .known
+            .iter()
+            .filter(|m| !loaded.iter().any(|l| l.is_same(m)));
+        for module in unloaded {
+            self.notify(ModuleEvent::Unloaded(module));
+        }
+        let known = &self.known;
+        for module in loaded
+            .iter()
+            .filter(|l| !known.iter().any(|m| m.is_same(l)))
+        {
+            self.notify(ModuleEvent::Loaded(module));
+        }
+        self.known = loaded;
+    }
+
+    fn notify(&self, event: ModuleEvent<'_>) {
+        for listener in self.listeners.iter() {
+            listener(event);
+        }
+    }
+}
+
+/// Returns the modules loaded into another process, identified by `pid`, in
+/// the same form as `modules` returns them for the current one, starting with
+/// the executable.
+///
+/// This is meant for crash handlers which run out of process. The modules are
+/// found through `/proc/<pid>/maps`, so reading them takes the same permission
+/// as attaching to the process with `ptrace`. The returned list is empty if the
+/// mappings can't be read.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default. It's only available
+/// on Linux and Android.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn process_modules(pid: u32) -> Vec<LoadedModule> {
+    crate::symbolize::process_modules(pid)
+}
+
+/// A module loaded into the current process, as returned by `modules`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug)]
+pub struct LoadedModule {
+    path: PathBuf,
+    bias: usize,
+    slide: usize,
+    ranges: Vec<Range<usize>>,
+    build_id: Option<Vec<u8>>,
+}
+
+impl LoadedModule {
+    pub(crate) fn new(
+        path: PathBuf,
+        bias: usize,
+        slide: usize,
+        ranges: Vec<Range<usize>>,
+        build_id: Option<Vec<u8>>,
+    ) -> LoadedModule {
+        LoadedModule {
+            path,
+            bias,
+            slide,
+            ranges,
+            build_id,
+        }
+    }
+
+    /// Returns the path the module was loaded from.
+    ///
+    /// This may be empty, for example for the main executable on some
+    /// platforms if its path couldn't be determined.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the load bias of the module.
+    ///
+    /// Subtracting this from an address within the module yields the address
+    /// used by the module's symbol table and debug information.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn bias(&self) -> usize {
+        self.bias
+    }
+
+    /// Returns the ASLR slide of the module: how far it has been moved from
+    /// the addresses stated in its headers.
+    ///
+    /// This is the same as `bias` except on macOS for some of the libraries
+    /// in the dyld shared cache, and is the value that `atos` and Apple's
+    /// crash reports expect, the load address of a library being its `__TEXT`
+    /// segment's address plus the slide.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn slide(&self) -> usize {
+        self.slide
+    }
+
+    /// Returns the lowest address the module is loaded at, or zero if its
+    /// address ranges aren't known.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn base(&self) -> usize {
+        self.ranges.first().map_or(0, |r| r.start)
+    }
+
+    /// Returns the address ranges the module's segments are loaded at.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn ranges(&self) -> &[Range<usize>] {
+        &self.ranges
+    }
+
+    /// Returns whether `addr` lies within one of the module's segments.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn contains(&self, addr: usize) -> bool {
+        self.ranges.iter().any(|r| r.start <= addr && addr < r.end)
+    }
+
+    /// Whether `other` is this module, rather than another one loaded in its
+    /// place.
+    fn is_same(&self, other: &LoadedModule) -> bool {
+        self.path == other.path && self.ranges == other.ranges && self.build_id == other.build_id
+    }
+
+    /// Returns the build-id (the GNU build-id note on ELF platforms, the UUID
+    /// on macOS, the GUID and age of the PDB on Windows) of the module, if
+    /// known.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn build_id(&self) -> Option<&[u8]> {
+        self.build_id.as_ref().map(|id| &id[..])
+    }
+
+    /// Returns the `LC_UUID` of a Mach-O image, which identifies the `*.dSYM`
+    /// bundle to symbolize it with and the image in Apple's crash reports.
+    ///
+    /// This is the same as `build_id`, as a fixed-size array.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "tvos",
+        target_os = "watchos"
+    ))]
+    pub fn uuid(&self) -> Option<[u8; 16]> {
+        let id = self.build_id()?;
+        if id.len() != 16 {
+            return None;
+        }
+        let mut uuid = [0; 16];
+        uuid.copy_from_slice(id);
+        Some(uuid)
+    }
+}
@@ -0,0 +1,399 @@
+//! Parsing of printed backtraces.
+//!
+//! This reads back the text printed by the `Debug` implementation of
+//! `Backtrace`, in either of its formats, and by `BacktraceFmt` with
+//! `PrintFmt::Short`, `PrintFmt::Full` or `PrintFmt::Compact`, which the
+//! standard library's backtraces also follow. Lines which aren't part of a
+//! backtrace, like the message of a panic, and lines which carry no frame
+//! information, like source context and notes about skipped frames, are
+//! ignored.
+
+use std::path::{Path, PathBuf};
+use std::prelude::v1::*;
+
+/// A frame of a backtrace read back by `parse_backtrace`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParsedFrame {
+    index: usize,
+    ip: Option<usize>,
+    module: Option<(String, usize)>,
+    symbols: Vec<ParsedSymbol>,
+}
+
+impl ParsedFrame {
+    /// Returns the index the frame was printed with.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the instruction pointer of the frame, if it was printed.
+    ///
+    /// Only `PrintFmt::Full` prints addresses, and it prints them relative to
+    /// their module instead when `BacktraceFmt::module_offsets` is enabled.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn ip(&self) -> Option<usize> {
+        self.ip
+    }
+
+    /// Returns the file name of the module the frame's instruction pointer is
+    /// in, if it was printed relative to the module.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn module_name(&self) -> Option<&str> {
+        self.module.as_ref().map(|(name, _)| &**name)
+    }
+
+    /// Returns the offset of the frame's instruction pointer in its module,
+    /// if it was printed relative to the module.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn module_offset(&self) -> Option<usize> {
+        self.module.as_ref().map(|(_, offset)| *offset)
+    }
+
+    /// Returns the symbols printed for the frame, the outermost inlined
+    /// function last.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn symbols(&self) -> &[ParsedSymbol] {
+        &self.symbols
+    }
+}
+
+/// A symbol of a frame read back by `parse_backtrace`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParsedSymbol {
+    name: Option<String>,
+    filename: Option<PathBuf>,
+    lineno: Option<u32>,
+    colno: Option<u32>,
+}
+
+impl ParsedSymbol {
+    /// Returns the name of the symbol, or `None` if it was printed as unknown.
+    ///
+    /// The name is as it was printed, so it includes the hash of Rust symbols
+    /// printed with `PrintFmt::Full`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the file name of the symbol, if it was printed.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn filename(&self) -> Option<&Path> {
+        self.filename.as_deref()
+    }
+
+    /// Returns the line number of the symbol, if it was printed.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn lineno(&self) -> Option<u32> {
+        self.lineno
+    }
+
+    /// Returns the column number of the symbol, if it was printed.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn colno(&self) -> Option<u32> {
+        self.colno
+    }
+}
+
+/// Parses a backtrace printed by this crate back into its frames.
+///
+/// This reads the formats printed by the `Debug` implementation of
+/// `Backtrace` and by `BacktraceFmt`, except for `PrintFmt::Json`, which can
+/// be read with any JSON parser. Each frame gets the instruction pointer
+/// printed for it, if any, and its symbols with their names, files and
+/// lines. Frames skipped as repeated or omitted aren't returned.
+///
+/// Lines which aren't part of a backtrace are ignored, so `text` can be a
+/// whole log. When it contains several backtraces their frames are all
+/// returned in order, and each backtrace starts with a frame whose `index` is
+/// 0 unless its first frames were omitted.
+///
+/// # Example
+///
+/// ```
+/// let text = "\
+///    0: app::run
+///              at src/main.rs:7:5
+///    1: app::main
+///              at src/main.rs:3:5
+/// ";
+/// let frames = backtrace::parse_backtrace(text);
+/// assert_eq!(frames.len(), 2);
+/// let symbol = &frames[1].symbols()[0];
+/// assert_eq!(symbol.name(), Some("app::main"));
+/// assert_eq!(symbol.lineno(), Some(3));
+/// ```
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn parse_backtrace(text: &str) -> Vec<ParsedFrame> {
+    let mut frames: Vec<ParsedFrame> = Vec::new();
+    // Whether the lines following belong to the last frame.
+    let mut in_frame = false;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(frame) = parse_frame_line(line) {
+            frames.push(frame);
+            in_frame = true;
+            continue;
+        }
+        let frame = match frames.last_mut() {
+            Some(frame) if in_frame => frame,
+            _ => continue,
+        };
+        let mut words = line.splitn(2, ' ');
+        if let (Some("at"), Some(location)) = (words.next(), words.next()) {
+            if let Some(symbol) = frame.symbols.last_mut() {
+                if symbol.filename.is_none() {
+                    parse_location(location, symbol);
+                }
+            }
+        } else if line.starts_with("[...") || is_source_context(line) {
+            // Notes about skipped frames and source context carry nothing.
+        } else if line.is_empty() || line.contains(": ") || line.ends_with(':') {
+            // Anything else with a colon in it is a log message, a panic's or
+            // the header of the next backtrace, which ends the backtrace.
+            in_frame = false;
+        } else {
+            frame.symbols.push(symbol(line));
+        }
+    }
+    frames
+}
+
+// Parses a line like `  3: 0x1234 - name`, `  3: libfoo.so+0x12 - name` or
+// `  3: name`.
+fn parse_frame_line(line: &str) -> Option<ParsedFrame> {
+    let colon = line.find(": ")?;
+    let index = &line[..colon];
+    if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let index = index.parse().ok()?;
+    let mut rest = &line[colon + 2..];
+    let mut ip = None;
+    let mut module = None;
+    if let Some(dash) = rest.find(" - ") {
+        let address = rest[..dash].trim();
+        if let Some(addr) = parse_hex(address) {
+            ip = Some(addr);
+            rest = &rest[dash + 3..];
+        } else if let Some(plus) = address.rfind("+0x") {
+            if let Some(offset) = parse_hex(&address[plus + 1..]) {
+                module = Some((address[..plus].to_string(), offset));
+                rest = &rest[dash + 3..];
+            }
+        }
+    }
+    Some(ParsedFrame {
+        index,
+        ip,
+        module,
+        symbols: vec![symbol(rest.trim())],
+    })
+}
+
+fn symbol(name: &str) -> ParsedSymbol {
+    let name = match name {
+        "<unknown>" | "<untrusted>" | "" => None,
+        name => Some(name.to_string()),
+    };
+    ParsedSymbol {
+        name,
+        filename: None,
+        lineno: None,
+        colno: None,
+    }
+}
+
+fn parse_hex(s: &str) -> Option<usize> {
+    if !s.starts_with("0x") {
+        return None;
+    }
+    usize::from_str_radix(&s[2..], 16).ok()
+}
+
+// Parses `path:line` or `path:line:column`, where the path may contain
+// colons itself.
+fn parse_location(location: &str, symbol: &mut ParsedSymbol) {
+    let (rest, last) = match split_number(location) {
+        Some(split) => split,
+        None => return,
+    };
+    match split_number(rest) {
+        Some((path, line)) => {
+            symbol.filename = Some(PathBuf::from(path));
+            symbol.lineno = Some(line);
+            symbol.colno = Some(last);
+        }
+        None => {
+            symbol.filename = Some(PathBuf::from(rest));
+            symbol.lineno = Some(last);
+        }
+    }
+}
+
+// Splits a trailing `:number` off of `s`.
+fn split_number(s: &str) -> Option<(&str, u32)> {
+    let colon = s.rfind(':')?;
+    let number = s[colon + 1..].parse().ok()?;
+    if colon == 0 {
+        return None;
+    }
+    Some((&s[..colon], number))
+}
+
+// Whether `line` is a line of source context printed after a location, like
+// `> 3 |     run();` or `|     ^`.
+fn is_source_context(line: &str) -> bool {
+    line.trim_start_matches('>')
+        .trim_start()
+        .trim_start_matches(|c: char| c.is_ascii_digit())
+        .trim_start()
+        .starts_with('|')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(index: usize, ip: Option<usize>, symbols: Vec<ParsedSymbol>) -> ParsedFrame {
+        ParsedFrame {
+            index,
+            ip,
+            module: None,
+            symbols,
+        }
+    }
+
+    fn sym(name: &str, location: Option<(&str, u32, Option<u32>)>) -> ParsedSymbol {
+        ParsedSymbol {
+            name: Some(name.to_string()),
+            filename: location.map(|(file, _, _)| file.into()),
+            lineno: location.map(|(_, line, _)| line),
+            colno: location.and_then(|(_, _, col)| col),
+        }
+    }
+
+    #[test]
+    fn short() {
+        let text = "\
+thread 'main' panicked at src/main.rs:2:5:
+oops
+stack backtrace:
+   0: app::run
+             at src/main.rs:7:5
+                 6 | fn run() {
+               > 7 |     panic!(\"oops\");
+                   |     ^
+   1: app::inlined
+             at src/lib.rs:10
+      app::main
+             at src/main.rs:3:5
+      [... frames 0-1 repeated 3 times ...]
+   8: <unknown>
+note: Some details are omitted, run with `RUST_BACKTRACE=full` for a verbose backtrace.
+";
+        assert_eq!(
+            parse_backtrace(text),
+            vec![
+                frame(
+                    0,
+                    None,
+                    vec![sym("app::run", Some(("src/main.rs", 7, Some(5))))]
+                ),
+                frame(
+                    1,
+                    None,
+                    vec![
+                        sym("app::inlined", Some(("src/lib.rs", 10, None))),
+                        sym("app::main", Some(("src/main.rs", 3, Some(5)))),
+                    ]
+                ),
+                frame(8, None, vec![symbol("<unknown>")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn full() {
+        let text = "
+   0:     0x55d5c4a1b2c3 - app::run::h0123456789abcdef
+                               at C:\\src\\main.rs:7:5
+                           app::main::hfedcba9876543210
+   1:     0x55d5c4a1b000 - <unknown>
+   2: libfoo.so+0x1a2b - foo
+";
+        let frames = parse_backtrace(text);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(
+            frames[0],
+            frame(
+                0,
+                Some(0x55d5c4a1b2c3),
+                vec![
+                    sym(
+                        "app::run::h0123456789abcdef",
+                        Some(("C:\\src\\main.rs", 7, Some(5)))
+                    ),
+                    sym("app::main::hfedcba9876543210", None),
+                ]
+            )
+        );
+        assert_eq!(frames[1].ip(), Some(0x55d5c4a1b000));
+        assert_eq!(frames[1].symbols()[0].name(), None);
+        assert_eq!(frames[2].ip(), None);
+        assert_eq!(frames[2].module_name(), Some("libfoo.so"));
+        assert_eq!(frames[2].module_offset(), Some(0x1a2b));
+        assert_eq!(frames[2].symbols()[0].name(), Some("foo"));
+    }
+}
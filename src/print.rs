@@ -1,13 +1,26 @@
 #[cfg(feature = "std")]
 use super::{BacktraceFrame, BacktraceSymbol};
 use super::{BytesOrWideString, Frame, SymbolName};
+use core::cell::RefCell;
 use core::ffi::c_void;
 use core::fmt;
 
 const HEX_WIDTH: usize = 2 + 2 * core::mem::size_of::<usize>();
 
+#[cfg(feature = "std")]
+mod filter;
 #[cfg(target_os = "fuchsia")]
 mod fuchsia;
+#[cfg(feature = "std")]
+mod remap;
+
+#[cfg(feature = "std")]
+pub use self::filter::{clear_frame_filter, set_frame_filter, FrameAction};
+
+#[cfg(feature = "std")]
+pub(crate) use self::remap::remap;
+#[cfg(feature = "std")]
+pub use self::remap::{add_path_remapping, clear_path_remappings};
 
 /// A formatter for backtraces.
 ///
@@ -17,11 +30,34 @@ mod fuchsia;
 pub struct BacktraceFmt<'a, 'b> {
     fmt: &'a mut fmt::Formatter<'b>,
     frame_index: usize,
+    /// How many symbols have been printed, to separate them in JSON.
+    symbol_count: usize,
+    /// How many frames `Compact` has left out since the last printed one.
+    omitted: usize,
+    /// Whether `Compact` has reached `__rust_begin_short_backtrace`.
+    stopped: bool,
+    /// How many lines of source to print around each location.
+    #[cfg(feature = "std")]
+    source_context: usize,
+    /// Whether addresses are printed relative to their modules.
+    #[cfg(feature = "std")]
+    module_offsets: bool,
+    /// The filter used instead of the one of `set_frame_filter`.
+    #[cfg(feature = "std")]
+    frame_filter: Option<&'a mut FrameFilterFn<'b>>,
     format: PrintFmt,
     print_path:
         &'a mut (dyn FnMut(&mut fmt::Formatter<'_>, BytesOrWideString<'_>) -> fmt::Result + 'b),
+    style: Option<&'a mut StyleFn<'b>>,
 }
 
+type PrintPathFn<'b> =
+    dyn FnMut(&mut fmt::Formatter<'_>, BytesOrWideString<'_>) -> fmt::Result + 'b;
+type StyleFn<'b> =
+    dyn FnMut(&mut fmt::Formatter<'_>, PrintElement, &dyn fmt::Display) -> fmt::Result + 'b;
+#[cfg(feature = "std")]
+type FrameFilterFn<'b> = dyn FnMut(&crate::Symbol) -> FrameAction + 'b;
+
 /// The styles of printing that we can print
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum PrintFmt {
@@ -29,10 +65,61 @@ pub enum PrintFmt {
     Short,
     /// Prints a backtrace that contains all possible information
     Full,
+    /// Prints a terse backtrace like `Short`, but leaves out the frames of the
+    /// standard library, of panicking and of this crate, like the standard
+    /// library does with `RUST_BACKTRACE=1`.
+    ///
+    /// Frames from the `__rust_begin_short_backtrace` marker on, which start
+    /// the `main` function and threads, aren't printed either. Frames left
+    /// out between printed ones are replaced with a note.
+    Compact,
+    /// Prints a JSON array with an object for each symbol, see below.
+    ///
+    /// The schema is stable. Each object has these fields, in this order:
+    ///
+    /// * `frame`: the index of the frame, shared by the symbols of a frame
+    ///   with inlined functions.
+    /// * `ip`: the instruction pointer of the frame, as a hex string.
+    /// * `module`: the path of the module containing `ip`, or `null`.
+    /// * `module_offset`: `ip` relative to the addresses stated in the module,
+    ///   as a hex string, or `null`.
+    /// * `function`: the demangled name of the function, without its hash,
+    ///   or `null`.
+    /// * `file`, `line` and `column`: the source location, or `null`.
+    /// * `inline_depth`: how deeply the function is inlined, where 0 is the
+    ///   function the code belongs to, or `null` if unknown.
+    ///
+    /// A frame without symbols has a single object whose `function`, `file`,
+    /// `line`, `column` and `inline_depth` are `null`. Modules are only known
+    /// with the `std` feature.
+    Json,
     #[doc(hidden)]
     __Nonexhaustive,
 }
 
+/// The parts of a printed backtrace which can be styled, see
+/// `BacktraceFmt::set_style`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum PrintElement {
+    /// The index of a frame, padded to a width of 4.
+    FrameIndex,
+    /// The instruction pointer of a frame, or its module and offset, printed
+    /// with `PrintFmt::Full`.
+    Address,
+    /// The name of a function, or `<unknown>`.
+    Function,
+    /// The filename of a location, as printed by the `print_path` callback.
+    Path,
+    /// The line number of a location.
+    LineNumber,
+    /// The column number of a location.
+    ColumnNumber,
+    /// A note about frames which aren't printed, like
+    /// `[... omitted 3 frames ...]`.
+    Note,
+}
+
 impl<'a, 'b> BacktraceFmt<'a, 'b> {
     /// Create a new `BacktraceFmt` which will write output to the provided
     /// `fmt`.
@@ -50,8 +137,18 @@ impl<'a, 'b> BacktraceFmt<'a, 'b> {
         BacktraceFmt {
             fmt,
             frame_index: 0,
+            symbol_count: 0,
+            omitted: 0,
+            stopped: false,
+            #[cfg(feature = "std")]
+            source_context: 0,
+            #[cfg(feature = "std")]
+            module_offsets: false,
+            #[cfg(feature = "std")]
+            frame_filter: None,
             format,
             print_path,
+            style: None,
         }
     }
 
@@ -61,11 +158,132 @@ impl<'a, 'b> BacktraceFmt<'a, 'b> {
     /// symbolicated later, and otherwise this should just be the first method
     /// you call after creating a `BacktraceFmt`.
     pub fn add_context(&mut self) -> fmt::Result {
+        if let PrintFmt::Json = self.format {
+            return self.fmt.write_str("[");
+        }
         #[cfg(target_os = "fuchsia")]
         fuchsia::print_dso_context(self.fmt)?;
         Ok(())
     }
 
+    /// Prints `lines` lines of source code around the line of each location
+    /// printed, with a caret under the column if it's known, like
+    ///
+    /// ```text
+    ///    1: app::main
+    ///              at src/main.rs:3:5
+    ///                  2 | fn main() {
+    ///                > 3 |     run();
+    ///                    |     ^
+    ///                  4 | }
+    /// ```
+    ///
+    /// The line reported is in the middle, or as close to it as the start of
+    /// the file allows.
+    ///
+    /// Nothing is printed for locations whose files can't be read, and none
+    /// of this is printed by default. The JSON format has no source code.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[cfg(feature = "std")]
+    pub fn set_source_context(&mut self, lines: usize) {
+        self.source_context = lines;
+    }
+
+    /// Configures whether addresses are printed relative to the modules
+    /// containing them, like `libfoo.so+0x1a2b`, rather than as absolute
+    /// addresses, which are disabled by default.
+    ///
+    /// The offset is relative to the addresses stated in the module, so that
+    /// it can be looked up in its debug information later, for example with
+    /// `addr2line`, even though the module was loaded elsewhere. Addresses
+    /// whose modules aren't known are still printed as they are. Addresses
+    /// are only printed with `PrintFmt::Full`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[cfg(feature = "std")]
+    pub fn set_module_offsets(&mut self, enabled: bool) {
+        self.module_offsets = enabled;
+    }
+
+    /// Sets a filter deciding which symbols are printed, which is used
+    /// instead of the one set with `backtrace::set_frame_filter`.
+    ///
+    /// See `backtrace::set_frame_filter` for how the filter is applied.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[cfg(feature = "std")]
+    pub fn set_frame_filter(
+        &mut self,
+        filter: &'a mut (dyn FnMut(&crate::Symbol) -> FrameAction + 'b),
+    ) {
+        self.frame_filter = Some(filter);
+    }
+
+    /// Sets a callback which prints each part of the backtrace, to style it
+    /// for example with colors.
+    ///
+    /// The callback is given the kind of part printed and its text, which it
+    /// should print around whatever it adds, and is called for everything but
+    /// the punctuation and whitespace between parts. Nothing of the JSON
+    /// format is styled.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use backtrace::{Backtrace, BacktraceFmt, BytesOrWideString, PrintElement, PrintFmt};
+    /// use std::fmt;
+    ///
+    /// struct Colored(Backtrace);
+    ///
+    /// impl fmt::Display for Colored {
+    ///     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+    ///         let mut print_path = |fmt: &mut fmt::Formatter<'_>, path: BytesOrWideString<'_>| {
+    ///             fmt::Display::fmt(&path, fmt)
+    ///         };
+    ///         let mut style = |fmt: &mut fmt::Formatter<'_>, element, text: &dyn fmt::Display| {
+    ///             match element {
+    ///                 PrintElement::Function => write!(fmt, "\x1b[1m{}\x1b[0m", text),
+    ///                 PrintElement::Path => write!(fmt, "\x1b[34m{}\x1b[0m", text),
+    ///                 _ => write!(fmt, "{}", text),
+    ///             }
+    ///         };
+    ///         let mut f = BacktraceFmt::new(fmt, PrintFmt::Short, &mut print_path);
+    ///         f.set_style(&mut style);
+    ///         for frame in self.0.frames() {
+    ///             f.frame().backtrace_frame(frame)?;
+    ///         }
+    ///         f.finish()
+    ///     }
+    /// }
+    ///
+    /// println!("{}", Colored(Backtrace::new()));
+    /// ```
+    pub fn set_style(
+        &mut self,
+        style: &'a mut (dyn FnMut(&mut fmt::Formatter<'_>, PrintElement, &dyn fmt::Display) -> fmt::Result
+                     + 'b),
+    ) {
+        self.style = Some(style);
+    }
+
+    // Prints `text`, styled as `element`.
+    fn print_styled(&mut self, element: PrintElement, text: &dyn fmt::Display) -> fmt::Result {
+        match &mut self.style {
+            Some(style) => style(self.fmt, element, text),
+            None => fmt::Display::fmt(text, self.fmt),
+        }
+    }
+
     /// Adds a frame to the backtrace output.
     ///
     /// This commit returns an RAII instance of a `BacktraceFrameFmt` which can be used
@@ -75,15 +293,75 @@ impl<'a, 'b> BacktraceFmt<'a, 'b> {
         BacktraceFrameFmt {
             fmt: self,
             symbol_index: 0,
+            skipped: false,
+        }
+    }
+
+    /// Skips the frames of a cycle repeated by recursion, printing a note
+    /// instead.
+    ///
+    /// The last `len` frames added are the cycle, which is repeated `times`
+    /// more times after them. Those frames aren't added, but are counted in
+    /// the numbering of the frames after them. Nothing is printed in the JSON
+    /// format.
+    pub fn skip_repeated(&mut self, len: usize, times: usize) -> fmt::Result {
+        let first = self.frame_index.saturating_sub(len);
+        let last = self.frame_index.saturating_sub(1);
+        self.frame_index += len * times;
+        if self.stopped {
+            return Ok(());
+        }
+        match self.format {
+            PrintFmt::Json => return Ok(()),
+            PrintFmt::Compact => self.print_omitted()?,
+            _ => {}
+        }
+        let s = if times == 1 { "" } else { "s" };
+        self.fmt.write_str("      ")?;
+        if first == last {
+            self.print_styled(
+                PrintElement::Note,
+                &format_args!("[... frame {} repeated {} time{} ...]", first, times, s),
+            )?;
+        } else {
+            self.print_styled(
+                PrintElement::Note,
+                &format_args!(
+                    "[... frames {}-{} repeated {} time{} ...]",
+                    first, last, times, s
+                ),
+            )?;
         }
+        self.fmt.write_str("\n")
+    }
+
+    // Prints a note for the frames `Compact` has left out since the last
+    // printed one, if any.
+    fn print_omitted(&mut self) -> fmt::Result {
+        // The frames left out before the first printed one are the ones of
+        // capturing the backtrace or of panicking, which go without saying.
+        if self.omitted > 0 && self.frame_index > self.omitted {
+            let omitted = self.omitted;
+            let s = if omitted == 1 { "" } else { "s" };
+            self.fmt.write_str("      ")?;
+            self.print_styled(
+                PrintElement::Note,
+                &format_args!("[... omitted {} frame{} ...]", omitted, s),
+            )?;
+            self.fmt.write_str("\n")?;
+        }
+        self.omitted = 0;
+        Ok(())
     }
 
     /// Completes the backtrace output.
     ///
-    /// This is currently a no-op but is added for future compatibility with
-    /// backtrace formats.
+    /// This closes the array of the JSON format, and is otherwise a no-op
+    /// added for future compatibility with backtrace formats.
     pub fn finish(&mut self) -> fmt::Result {
-        // Currently a no-op-- including this hook to allow for future additions.
+        if let PrintFmt::Json = self.format {
+            return self.fmt.write_str("]\n");
+        }
         Ok(())
     }
 }
@@ -94,6 +372,8 @@ impl<'a, 'b> BacktraceFmt<'a, 'b> {
 pub struct BacktraceFrameFmt<'fmt, 'a, 'b> {
     fmt: &'fmt mut BacktraceFmt<'a, 'b>,
     symbol_index: usize,
+    /// Whether `Compact` has left out any symbols of this frame.
+    skipped: bool,
 }
 
 impl BacktraceFrameFmt<'_, '_, '_> {
@@ -109,8 +389,33 @@ impl BacktraceFrameFmt<'_, '_, '_> {
     #[cfg(feature = "std")]
     pub fn backtrace_frame(&mut self, frame: &BacktraceFrame) -> fmt::Result {
         let symbols = frame.symbols();
-        for symbol in symbols {
-            self.backtrace_symbol(frame, symbol)?;
+        // The marker can be an outer function of inlined ones, which mustn't
+        // be printed either.
+        if let PrintFmt::Compact = self.fmt.format {
+            if symbols
+                .iter()
+                .filter_map(|s| s.name())
+                .any(|name| contains(name.as_bytes(), BEGIN_SHORT_BACKTRACE))
+            {
+                self.fmt.stopped = true;
+            }
+        }
+        for (i, symbol) in symbols.iter().enumerate() {
+            // The innermost inlined function comes first, which gives the
+            // depth of symbols captured without one.
+            let depth = symbol
+                .inline_depth()
+                .unwrap_or((symbols.len() - 1 - i) as u32);
+            self.print(
+                frame.ip(),
+                symbol.name(),
+                symbol
+                    .filename()
+                    .and_then(|p| Some(BytesOrWideString::Bytes(p.to_str()?.as_bytes()))),
+                symbol.lineno(),
+                symbol.colno(),
+                Some(depth),
+            )?;
         }
         if symbols.is_empty() {
             self.print_raw(frame.ip(), None, None, None)?;
@@ -130,7 +435,7 @@ impl BacktraceFrameFmt<'_, '_, '_> {
         frame: &BacktraceFrame,
         symbol: &BacktraceSymbol,
     ) -> fmt::Result {
-        self.print_raw_with_column(
+        self.print(
             frame.ip(),
             symbol.name(),
             // TODO: this isn't great that we don't end up printing anything
@@ -141,6 +446,7 @@ impl BacktraceFrameFmt<'_, '_, '_> {
                 .and_then(|p| Some(BytesOrWideString::Bytes(p.to_str()?.as_bytes()))),
             symbol.lineno(),
             symbol.colno(),
+            symbol.inline_depth(),
         )?;
         Ok(())
     }
@@ -148,12 +454,13 @@ impl BacktraceFrameFmt<'_, '_, '_> {
     /// Prints a raw traced `Frame` and `Symbol`, typically from within the raw
     /// callbacks of this crate.
     pub fn symbol(&mut self, frame: &Frame, symbol: &super::Symbol) -> fmt::Result {
-        self.print_raw_with_column(
+        self.print(
             frame.ip(),
             symbol.name(),
             symbol.filename_raw(),
             symbol.lineno(),
             symbol.colno(),
+            symbol.inline_depth(),
         )?;
         Ok(())
     }
@@ -186,10 +493,73 @@ impl BacktraceFrameFmt<'_, '_, '_> {
         lineno: Option<u32>,
         colno: Option<u32>,
     ) -> fmt::Result {
-        // Fuchsia is unable to symbolize within a process so it has a special
-        // format which can be used to symbolize later. Print that instead of
-        // printing addresses in our own format here.
-        if cfg!(target_os = "fuchsia") {
+        self.print(frame_ip, symbol_name, filename, lineno, colno, None)
+    }
+
+    fn print(
+        &mut self,
+        frame_ip: *mut c_void,
+        symbol_name: Option<SymbolName<'_>>,
+        filename: Option<BytesOrWideString<'_>>,
+        lineno: Option<u32>,
+        colno: Option<u32>,
+        inline_depth: Option<u32>,
+    ) -> fmt::Result {
+        if self.fmt.stopped {
+            self.skipped = true;
+            return Ok(());
+        }
+        if let PrintFmt::Compact = self.fmt.format {
+            let hidden = match &symbol_name {
+                Some(name) => self.is_hidden(name),
+                None => false,
+            };
+            if hidden {
+                self.skipped = true;
+                return Ok(());
+            }
+        }
+        #[cfg(feature = "std")]
+        {
+            if let Some(name) = &symbol_name {
+                let symbol = filter::symbol(name, filename.as_ref(), lineno, colno);
+                let action = match &mut self.fmt.frame_filter {
+                    Some(filter) => filter(&symbol),
+                    None => filter::filter(&symbol),
+                };
+                match action {
+                    FrameAction::Keep => {}
+                    FrameAction::Skip => {
+                        self.skipped = true;
+                        return Ok(());
+                    }
+                    FrameAction::StopHere => {
+                        self.fmt.stopped = true;
+                        self.skipped = true;
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        if let PrintFmt::Compact = self.fmt.format {
+            self.fmt.print_omitted()?;
+        }
+
+        #[cfg(feature = "std")]
+        let remapped = filename.as_ref().and_then(remap::remap_raw);
+        #[cfg(feature = "std")]
+        let filename = match remapped.as_ref().and_then(|path| remap::path_raw(path)) {
+            Some(path) => Some(path),
+            None => filename,
+        };
+
+        if let PrintFmt::Json = self.fmt.format {
+            self.print_raw_json(frame_ip, symbol_name, filename, lineno, colno, inline_depth)?;
+            self.fmt.symbol_count += 1;
+        } else if cfg!(target_os = "fuchsia") {
+            // Fuchsia is unable to symbolize within a process so it has a
+            // special format which can be used to symbolize later. Print that
+            // instead of printing addresses in our own format here.
             self.print_raw_fuchsia(frame_ip)?;
         } else {
             self.print_raw_generic(frame_ip, symbol_name, filename, lineno, colno)?;
@@ -209,7 +579,7 @@ impl BacktraceFrameFmt<'_, '_, '_> {
     ) -> fmt::Result {
         // No need to print "null" frames, it basically just means that the
         // system backtrace was a bit eager to trace back super far.
-        if let PrintFmt::Short = self.fmt.format {
+        if let PrintFmt::Short | PrintFmt::Compact = self.fmt.format {
             if frame_ip.is_null() {
                 return Ok(());
             }
@@ -218,19 +588,30 @@ impl BacktraceFrameFmt<'_, '_, '_> {
         // To reduce TCB size in Sgx enclave, we do not want to implement symbol
         // resolution functionality.  Rather, we can print the offset of the
         // address here, which could be later mapped to correct function.
+        // Addresses outside of the enclave aren't in its image, so those are
+        // left alone and marked as untrusted.
+        let mut unknown = "<unknown>";
         #[cfg(all(feature = "std", target_env = "sgx", target_vendor = "fortanix"))]
         {
-            let image_base = std::os::fortanix_sgx::mem::image_base();
-            frame_ip = usize::wrapping_sub(frame_ip as usize, image_base as _) as _;
+            if crate::sgx::is_untrusted(frame_ip) {
+                unknown = "<untrusted>";
+            } else {
+                let image_base = crate::sgx::enclave_image_base();
+                frame_ip = usize::wrapping_sub(frame_ip as usize, image_base) as _;
+            }
         }
 
         // Print the index of the frame as well as the optional instruction
         // pointer of the frame. If we're beyond the first symbol of this frame
         // though we just print appropriate whitespace.
         if self.symbol_index == 0 {
-            write!(self.fmt.fmt, "{:4}: ", self.fmt.frame_index)?;
+            let index = self.fmt.frame_index;
+            self.fmt
+                .print_styled(PrintElement::FrameIndex, &format_args!("{:4}", index))?;
+            self.fmt.fmt.write_str(": ")?;
             if let PrintFmt::Full = self.fmt.format {
-                write!(self.fmt.fmt, "{:1$?} - ", frame_ip, HEX_WIDTH)?;
+                self.print_address(frame_ip)?;
+                self.fmt.fmt.write_str(" - ")?;
             }
         } else {
             write!(self.fmt.fmt, "      ")?;
@@ -242,10 +623,19 @@ impl BacktraceFrameFmt<'_, '_, '_> {
         // Next up write out the symbol name, using the alternate formatting for
         // more information if we're a full backtrace. Here we also handle
         // symbols which don't have a name,
+        let function = PrintElement::Function;
         match (symbol_name, &self.fmt.format) {
-            (Some(name), PrintFmt::Short) => write!(self.fmt.fmt, "{:#}", name)?,
-            (Some(name), PrintFmt::Full) => write!(self.fmt.fmt, "{}", name)?,
-            (None, _) | (_, PrintFmt::__Nonexhaustive) => write!(self.fmt.fmt, "<unknown>")?,
+            (Some(name), PrintFmt::Short)
+            | (Some(name), PrintFmt::Compact)
+            | (Some(name), PrintFmt::Json) => self
+                .fmt
+                .print_styled(function, &format_args!("{:#}", name))?,
+            (Some(name), PrintFmt::Full) => {
+                self.fmt.print_styled(function, &format_args!("{}", name))?
+            }
+            (None, _) | (_, PrintFmt::__Nonexhaustive) => {
+                self.fmt.print_styled(function, &unknown)?
+            }
         }
         self.fmt.fmt.write_str("\n")?;
 
@@ -257,6 +647,35 @@ impl BacktraceFrameFmt<'_, '_, '_> {
         Ok(())
     }
 
+    fn print_address(&mut self, frame_ip: *mut c_void) -> fmt::Result {
+        #[cfg(feature = "std")]
+        {
+            if self.fmt.module_offsets {
+                let mut module = None;
+                {
+                    let _guard = crate::lock::lock();
+                    unsafe {
+                        crate::symbolize::with_module(frame_ip, &mut |m| {
+                            let path = std::path::Path::new(m.name);
+                            let name = path.file_name().unwrap_or(m.name);
+                            module = Some((std::path::PathBuf::from(name), m.offset));
+                        });
+                    }
+                }
+                if let Some((name, offset)) = module {
+                    return self.fmt.print_styled(
+                        PrintElement::Address,
+                        &format_args!("{}+{:#x}", name.display(), offset),
+                    );
+                }
+            }
+        }
+        self.fmt.print_styled(
+            PrintElement::Address,
+            &format_args!("{:1$?}", frame_ip, HEX_WIDTH),
+        )
+    }
+
     fn print_fileline(
         &mut self,
         file: BytesOrWideString<'_>,
@@ -270,20 +689,203 @@ impl BacktraceFrameFmt<'_, '_, '_> {
         }
         write!(self.fmt.fmt, "             at ")?;
 
+        #[cfg(feature = "std")]
+        let path = match self.fmt.source_context {
+            0 => None,
+            _ => Some(match &file {
+                BytesOrWideString::Bytes(b) => BytesOrWideString::Bytes(b).into_path_buf(),
+                BytesOrWideString::Wide(w) => BytesOrWideString::Wide(w).into_path_buf(),
+            }),
+        };
+
         // Delegate to our internal callback to print the filename and then
         // print out the line number.
-        (self.fmt.print_path)(self.fmt.fmt, file)?;
-        write!(self.fmt.fmt, ":{}", line)?;
+        match &mut self.fmt.style {
+            Some(style) => {
+                let path = PathDisplay {
+                    print_path: RefCell::new(&mut *self.fmt.print_path),
+                    file,
+                };
+                style(self.fmt.fmt, PrintElement::Path, &path)?;
+            }
+            None => (self.fmt.print_path)(self.fmt.fmt, file)?,
+        }
+        self.fmt.fmt.write_str(":")?;
+        self.fmt
+            .print_styled(PrintElement::LineNumber, &format_args!("{}", line))?;
 
         // Add column number, if available.
         if let Some(colno) = colno {
-            write!(self.fmt.fmt, ":{}", colno)?;
+            self.fmt.fmt.write_str(":")?;
+            self.fmt
+                .print_styled(PrintElement::ColumnNumber, &format_args!("{}", colno))?;
         }
 
         write!(self.fmt.fmt, "\n")?;
+
+        #[cfg(feature = "std")]
+        {
+            if let Some(path) = path {
+                self.print_source(&path, line, colno)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    fn print_source(
+        &mut self,
+        path: &std::path::Path,
+        line: u32,
+        colno: Option<u32>,
+    ) -> fmt::Result {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(_) => return Ok(()),
+        };
+        let line = line as usize;
+        let before = (self.fmt.source_context - 1) / 2;
+        let first = line.saturating_sub(before).max(1);
+        let lines = source
+            .lines()
+            .enumerate()
+            .map(|(i, text)| (i + 1, text))
+            .skip(first - 1)
+            .take(self.fmt.source_context)
+            .collect::<std::vec::Vec<_>>();
+        let width = lines.last().map_or(0, |(n, _)| std::format!("{}", n).len());
+        let indent = if let PrintFmt::Full = self.fmt.format {
+            HEX_WIDTH + 15
+        } else {
+            15
+        };
+        for (n, text) in lines {
+            let marker = if n == line { ">" } else { " " };
+            writeln!(
+                self.fmt.fmt,
+                "{:indent$}{} {:>width$} | {}",
+                "",
+                marker,
+                n,
+                text,
+                indent = indent,
+                width = width
+            )?;
+            if let (true, Some(colno)) = (n == line, colno) {
+                // Tabs are kept so that the caret lines up with the source.
+                let pad = text
+                    .chars()
+                    .take((colno as usize).saturating_sub(1))
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect::<std::string::String>();
+                writeln!(
+                    self.fmt.fmt,
+                    "{:indent$}  {:width$} | {}^",
+                    "",
+                    "",
+                    pad,
+                    indent = indent,
+                    width = width
+                )?;
+            }
+        }
         Ok(())
     }
 
+    // Returns whether `Compact` leaves out a symbol named `name`, which stops
+    // the printing if it's the marker of the start of `main` or a thread.
+    fn is_hidden(&mut self, name: &SymbolName<'_>) -> bool {
+        if contains(name.as_bytes(), BEGIN_SHORT_BACKTRACE) {
+            self.fmt.stopped = true;
+            return true;
+        }
+        if contains(name.as_bytes(), END_SHORT_BACKTRACE) {
+            return true;
+        }
+        let mut prefix = NamePrefix {
+            buf: [0; 32],
+            len: 0,
+        };
+        // This fails once the buffer is full, which is expected.
+        let _ = fmt::write(&mut prefix, format_args!("{:#}", name));
+        let prefix = &prefix.buf[..prefix.len];
+        RUNTIME_SYMBOL_PREFIXES
+            .iter()
+            .any(|p| prefix.starts_with(p.as_bytes()))
+    }
+
+    fn print_raw_json(
+        &mut self,
+        frame_ip: *mut c_void,
+        symbol_name: Option<SymbolName<'_>>,
+        filename: Option<BytesOrWideString<'_>>,
+        lineno: Option<u32>,
+        colno: Option<u32>,
+        inline_depth: Option<u32>,
+    ) -> fmt::Result {
+        let fmt = &mut *self.fmt.fmt;
+        if self.fmt.symbol_count > 0 {
+            fmt.write_str(",")?;
+        }
+        write!(
+            fmt,
+            "{{\"frame\":{},\"ip\":\"{:#x}\",",
+            self.fmt.frame_index, frame_ip as usize
+        )?;
+
+        #[allow(unused_mut)]
+        let mut module = false;
+        #[cfg(feature = "std")]
+        {
+            let _guard = crate::lock::lock();
+            unsafe {
+                crate::symbolize::with_module(frame_ip, &mut |m| {
+                    module = true;
+                    let path = std::path::Path::new(m.name);
+                    let _ = write!(
+                        fmt,
+                        "\"module\":{},\"module_offset\":\"{:#x}\",",
+                        JsonString(&path.display()),
+                        m.offset
+                    );
+                });
+            }
+        }
+        if !module {
+            fmt.write_str("\"module\":null,\"module_offset\":null,")?;
+        }
+
+        fmt.write_str("\"function\":")?;
+        match symbol_name {
+            Some(name) => write!(fmt, "{:#}", JsonString(&name))?,
+            None => fmt.write_str("null")?,
+        }
+        fmt.write_str(",\"file\":")?;
+        match filename {
+            Some(BytesOrWideString::Bytes(bytes)) => match core::str::from_utf8(bytes) {
+                Ok(file) => write!(fmt, "{}", JsonString(&file))?,
+                Err(_) => fmt.write_str("null")?,
+            },
+            Some(BytesOrWideString::Wide(wide)) => {
+                let file = core::char::decode_utf16(wide.iter().cloned())
+                    .map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER));
+                write!(fmt, "{}", JsonString(&Chars(file)))?;
+            }
+            None => fmt.write_str("null")?,
+        }
+        let number = |fmt: &mut fmt::Formatter<'_>, key: &str, value: Option<u32>| {
+            write!(fmt, ",\"{}\":", key)?;
+            match value {
+                Some(value) => write!(fmt, "{}", value),
+                None => fmt.write_str("null"),
+            }
+        };
+        number(fmt, "line", lineno)?;
+        number(fmt, "column", colno)?;
+        number(fmt, "inline_depth", inline_depth)?;
+        fmt.write_str("}")
+    }
+
     fn print_raw_fuchsia(&mut self, frame_ip: *mut c_void) -> fmt::Result {
         // We only care about the first symbol of a frame
         if self.symbol_index == 0 {
@@ -297,6 +899,125 @@ impl BacktraceFrameFmt<'_, '_, '_> {
 
 impl Drop for BacktraceFrameFmt<'_, '_, '_> {
     fn drop(&mut self) {
+        if self.skipped && self.symbol_index == 0 && !self.fmt.stopped {
+            self.fmt.omitted += 1;
+        }
         self.fmt.frame_index += 1;
     }
 }
+
+// The symbols of the functions which the standard library calls `main` and
+// the closures of threads through, and which panics start unwinding from.
+const BEGIN_SHORT_BACKTRACE: &[u8] = b"__rust_begin_short_backtrace";
+const END_SHORT_BACKTRACE: &[u8] = b"__rust_end_short_backtrace";
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+// Prefixes of symbols which belong to the runtime or to this crate rather than
+// to the application which captured the backtrace.
+const RUNTIME_SYMBOL_PREFIXES: &[&str] = &[
+    "backtrace::",
+    "std::",
+    "core::",
+    "alloc::",
+    "<std::",
+    "<core::",
+    "<alloc::",
+    "__rust_",
+    "__rustc::",
+    "rust_begin_unwind",
+    "rust_panic",
+];
+
+#[cfg(feature = "std")]
+pub(crate) fn is_runtime_symbol(name: &str) -> bool {
+    RUNTIME_SYMBOL_PREFIXES.iter().any(|p| name.starts_with(p))
+}
+
+// The start of a demangled symbol name, which is all that's needed to match it
+// against `RUNTIME_SYMBOL_PREFIXES` without allocating.
+struct NamePrefix {
+    buf: [u8; 32],
+    len: usize,
+}
+
+impl fmt::Write for NamePrefix {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        if self.len == self.buf.len() {
+            Err(fmt::Error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// Displays a value as a JSON string, quoted and escaped. The alternate flag
+// is passed on to the value.
+struct JsonString<'a, T: ?Sized>(&'a T);
+
+impl<T: fmt::Display + ?Sized> fmt::Display for JsonString<'_, T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        struct Escape<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+        impl fmt::Write for Escape<'_, '_> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                for c in s.chars() {
+                    match c {
+                        '"' => self.0.write_str("\\\"")?,
+                        '\\' => self.0.write_str("\\\\")?,
+                        '\n' => self.0.write_str("\\n")?,
+                        '\r' => self.0.write_str("\\r")?,
+                        '\t' => self.0.write_str("\\t")?,
+                        c if (c as u32) < 0x20 => write!(self.0, "\\u{:04x}", c as u32)?,
+                        c => fmt::Write::write_char(self.0, c)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        let alternate = fmt.alternate();
+        fmt.write_str("\"")?;
+        let mut escape = Escape(fmt);
+        if alternate {
+            fmt::write(&mut escape, format_args!("{:#}", self.0))?;
+        } else {
+            fmt::write(&mut escape, format_args!("{}", self.0))?;
+        }
+        fmt.write_str("\"")
+    }
+}
+
+// Displays the characters of an iterator.
+struct Chars<I>(I);
+
+impl<I: Iterator<Item = char> + Clone> fmt::Display for Chars<I> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.clone() {
+            fmt::Write::write_char(fmt, c)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints a filename with the `print_path` callback, to give it to the style
+/// callback.
+struct PathDisplay<'p, 'f, 'b> {
+    print_path: RefCell<&'p mut PrintPathFn<'b>>,
+    file: BytesOrWideString<'f>,
+}
+
+impl fmt::Display for PathDisplay<'_, '_, '_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let file = match &self.file {
+            BytesOrWideString::Bytes(b) => BytesOrWideString::Bytes(b),
+            BytesOrWideString::Wide(w) => BytesOrWideString::Wide(w),
+        };
+        (self.print_path.borrow_mut())(fmt, file)
+    }
+}
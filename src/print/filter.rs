@@ -0,0 +1,123 @@
+//! Hooks deciding which frames of backtraces are printed.
+
+use crate::{BytesOrWideString, Symbol, SymbolName};
+use std::prelude::v1::*;
+
+/// What to do with a symbol of a backtrace being printed, as decided by a
+/// filter set with `set_frame_filter`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FrameAction {
+    /// Prints the symbol.
+    Keep,
+    /// Leaves out the symbol, and its frame if it has no others.
+    Skip,
+    /// Leaves out the symbol and everything after it.
+    StopHere,
+}
+
+type Filter = Box<dyn Fn(&Symbol) -> FrameAction + Send + Sync>;
+
+// Only accessed with the crate's global lock held.
+static mut FILTER: *mut Filter = core::ptr::null_mut();
+
+/// Sets a filter deciding which frames of backtraces are printed, replacing
+/// any set before.
+///
+/// Applications can use this to hide wrapper functions of their own, such as
+/// logging shims or allocator hooks, or to cut backtraces short at a known
+/// function. The filter is called with each symbol about to be printed by
+/// `BacktraceFmt`, which includes `Backtrace`'s `Debug` output, in every
+/// format. Symbols without names, and frames without symbols, are always
+/// printed.
+///
+/// With `PrintFmt::Compact`, frames left out are noted like those of the
+/// standard library. Frames keep their numbers in every format, so that
+/// they still match `Backtrace::frames`.
+///
+/// A filter set with `BacktraceFmt::set_frame_filter` is used instead of
+/// this one. The filter is called with the same lock held as for
+/// symbolization, so it must not itself set filters.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::FrameAction;
+///
+/// backtrace::set_frame_filter(Box::new(|symbol| {
+///     let name = match symbol.name() {
+///         Some(name) => name.to_string(),
+///         None => return FrameAction::Keep,
+///     };
+///     if name.starts_with("my_app::logging::") {
+///         FrameAction::Skip
+///     } else if name.starts_with("my_app::main") {
+///         FrameAction::StopHere
+///     } else {
+///         FrameAction::Keep
+///     }
+/// }));
+/// ```
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn set_frame_filter(filter: Box<dyn Fn(&Symbol) -> FrameAction + Send + Sync>) {
+    let _guard = crate::lock::lock();
+    unsafe { replace_filter(Box::into_raw(Box::new(filter))) }
+}
+
+/// Removes the filter set with `set_frame_filter`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn clear_frame_filter() {
+    let _guard = crate::lock::lock();
+    unsafe { replace_filter(core::ptr::null_mut()) }
+}
+
+// unsafe because this is required to be externally synchronized
+unsafe fn replace_filter(filter: *mut Filter) {
+    let old = FILTER;
+    FILTER = filter;
+    if !old.is_null() {
+        drop(Box::from_raw(old));
+    }
+}
+
+/// Describes a symbol about to be printed as a `Symbol`, to give it to a
+/// filter.
+pub(super) fn symbol(
+    name: &SymbolName<'_>,
+    filename: Option<&BytesOrWideString<'_>>,
+    lineno: Option<u32>,
+    colno: Option<u32>,
+) -> Symbol {
+    let mut symbol = Symbol::new(name.as_bytes());
+    if let Some(file) = filename {
+        symbol = symbol.with_filename(match file {
+            BytesOrWideString::Bytes(b) => BytesOrWideString::Bytes(b).into_path_buf(),
+            BytesOrWideString::Wide(w) => BytesOrWideString::Wide(w).into_path_buf(),
+        });
+    }
+    if let Some(lineno) = lineno {
+        symbol = symbol.with_lineno(lineno);
+    }
+    if let Some(colno) = colno {
+        symbol = symbol.with_colno(colno);
+    }
+    symbol
+}
+
+/// Calls the filter set with `set_frame_filter` for `symbol`, if any.
+pub(super) fn filter(symbol: &Symbol) -> FrameAction {
+    let _guard = crate::lock::lock();
+    unsafe {
+        match FILTER.as_ref() {
+            Some(filter) => filter(symbol),
+            None => FrameAction::Keep,
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Rules rewriting the filenames printed in backtraces.
+
+use crate::BytesOrWideString;
+use std::path::{Path, PathBuf};
+use std::prelude::v1::*;
+
+// Only accessed with the crate's global lock held, through `remappings`.
+static mut REMAPPINGS: *mut Vec<(PathBuf, PathBuf)> = core::ptr::null_mut();
+
+// unsafe because this is required to be externally synchronized
+unsafe fn remappings() -> &'static mut Vec<(PathBuf, PathBuf)> {
+    if REMAPPINGS.is_null() {
+        REMAPPINGS = Box::into_raw(Box::new(Vec::new()));
+    }
+    &mut *REMAPPINGS
+}
+
+/// Adds a rule printing filenames which start with `from` as starting with
+/// `to` instead.
+///
+/// Binaries built elsewhere, for example in CI, contain the paths of the
+/// machine they were built on, and those of the standard library start with
+/// `/rustc/<commit hash>`. Rules can map those to a local checkout, undo a
+/// `--remap-path-prefix` the binary was built with, or strip a prefix
+/// altogether when `to` is empty.
+///
+/// Prefixes are matched by whole path components, and like with
+/// `--remap-path-prefix`, the rule added last wins when several match. The
+/// rules apply to the filenames of all backtraces printed with `BacktraceFmt`,
+/// including `Backtrace`'s `Debug` output, and source code printed with
+/// `BacktraceFmt::set_source_context` is read from the remapped file.
+/// `Symbol::filename` still returns the filename as it was found.
+///
+/// # Example
+///
+/// ```
+/// backtrace::add_path_remapping("/home/ci/build", "");
+/// backtrace::add_path_remapping(
+///     "/rustc/90c541806f23a127002de5b4038be731ba1458ca",
+///     "/home/me/rust",
+/// );
+/// ```
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn add_path_remapping(from: impl Into<PathBuf>, to: impl Into<PathBuf>) {
+    let _guard = crate::lock::lock();
+    unsafe {
+        remappings().push((from.into(), to.into()));
+    }
+}
+
+/// Removes all rules added with `add_path_remapping`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn clear_path_remappings() {
+    let _guard = crate::lock::lock();
+    unsafe {
+        remappings().clear();
+    }
+}
+
+/// Returns how `path` should be printed, if a rule matches it.
+pub(crate) fn remap(path: &Path) -> Option<PathBuf> {
+    let _guard = crate::lock::lock();
+    let remappings = unsafe { remappings() };
+    remappings.iter().rev().find_map(|(from, to)| {
+        let suffix = path.strip_prefix(from).ok()?;
+        if suffix.as_os_str().is_empty() {
+            Some(to.clone())
+        } else {
+            Some(to.join(suffix))
+        }
+    })
+}
+
+/// Returns how the filename `file` should be printed, if a rule matches it.
+pub(super) fn remap_raw(file: &BytesOrWideString<'_>) -> Option<PathBuf> {
+    let _guard = crate::lock::lock();
+    if unsafe { remappings().is_empty() } {
+        return None;
+    }
+    let path = match file {
+        BytesOrWideString::Bytes(b) => BytesOrWideString::Bytes(b).into_path_buf(),
+        BytesOrWideString::Wide(w) => BytesOrWideString::Wide(w).into_path_buf(),
+    };
+    remap(&path)
+}
+
+/// Borrows `path` as a filename for `BacktraceFmt`.
+pub(super) fn path_raw(path: &Path) -> Option<BytesOrWideString<'_>> {
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            use std::os::unix::prelude::*;
+            Some(BytesOrWideString::Bytes(path.as_os_str().as_bytes()))
+        } else {
+            path.to_str().map(|s| BytesOrWideString::Bytes(s.as_bytes()))
+        }
+    }
+}
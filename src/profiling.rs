@@ -0,0 +1,398 @@
+//! A sampling profiler driven by `SIGPROF`.
+//!
+//! Starting a `Sampler` arms a timer which interrupts whichever thread of the
+//! process is running on the CPU with `SIGPROF`, some number of times per
+//! second of CPU time used. The signal handler walks the interrupted stack
+//! with `trace_from_context` and stores the instruction pointers into a ring
+//! buffer, which takes no locks and doesn't allocate. The samples are drained
+//! from the buffer outside of the handler, and symbolized in bulk since most
+//! of them pass through the same code.
+//!
+//! # Caveats
+//!
+//! Only one `Sampler` can run at a time. It shares the handler for `SIGPROF`
+//! with `Backtrace::capture_thread` and `capture_all_threads`, which tells the
+//! signals of the timer apart from theirs, so both work at the same time. The
+//! handler stays installed after the sampler stops, dropping signals of the
+//! timer which were still pending and forwarding any other `SIGPROF` to the
+//! handler it replaced. Like `signal_safe`, this relies on the system unwinder
+//! being safe to use from a signal handler, which it is in practice on Linux
+//! with glibc and with LLVM's libunwind.
+//!
+//! # Required features
+//!
+//! This module requires the `std` feature of the `backtrace` crate to be
+//! enabled, and the `std` feature is enabled by default.
+
+use crate::{Backtrace, OsThreadId};
+use core::cell::UnsafeCell;
+use core::ffi::c_void;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering::*};
+use std::io;
+use std::prelude::v1::*;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// The number of samples the ring buffer holds until it's drained.
+const CAPACITY: usize = 1024;
+
+/// The most frames recorded for a sample.
+const MAX_FRAMES: usize = 128;
+
+// Not defined by `libc` for all targets, but the same on all of Linux.
+const ITIMER_PROF: libc::c_int = 2;
+
+const EMPTY: usize = 0;
+const WRITING: usize = 1;
+const FULL: usize = 2;
+
+/// Whether a `Sampler` is running.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// The ring buffer of the running sampler, or null once it's being stopped.
+static RING: AtomicPtr<Ring> = AtomicPtr::new(ptr::null_mut());
+
+/// The number of signal handlers using `RING`.
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+struct Ring {
+    /// The sequence number of the next sample, which picks its slot.
+    next: AtomicUsize,
+    /// The number of samples dropped because their slot was still full.
+    dropped: AtomicUsize,
+    slots: Box<[Slot]>,
+    /// Held while draining, since slots may only be emptied by one thread.
+    drain: Mutex<()>,
+}
+
+struct Slot {
+    state: AtomicUsize,
+    sample: UnsafeCell<RawSample>,
+}
+
+#[derive(Copy, Clone)]
+struct RawSample {
+    seq: usize,
+    thread: u64,
+    len: usize,
+    ips: [usize; MAX_FRAMES],
+}
+
+// `sample` is only written by the thread which moved `state` from `EMPTY` to
+// `WRITING` and only read by the thread draining once it's `FULL`.
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    fn new() -> Ring {
+        let empty = RawSample {
+            seq: 0,
+            thread: 0,
+            len: 0,
+            ips: [0; MAX_FRAMES],
+        };
+        Ring {
+            next: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+            slots: (0..CAPACITY)
+                .map(|_| Slot {
+                    state: AtomicUsize::new(EMPTY),
+                    sample: UnsafeCell::new(empty),
+                })
+                .collect(),
+            drain: Mutex::new(()),
+        }
+    }
+
+    // unsafe because `context` must be the one passed to a signal handler
+    unsafe fn record(&self, context: *mut c_void) {
+        let seq = self.next.fetch_add(1, Relaxed);
+        let slot = &self.slots[seq % self.slots.len()];
+        if slot
+            .state
+            .compare_exchange(EMPTY, WRITING, Acquire, Relaxed)
+            .is_err()
+        {
+            self.dropped.fetch_add(1, Relaxed);
+            return;
+        }
+        let sample = &mut *slot.sample.get();
+        sample.seq = seq;
+        sample.thread = libc::syscall(libc::SYS_gettid) as u64;
+        sample.len = 0;
+        crate::trace_from_context_unsynchronized(context, |frame| {
+            sample.ips[sample.len] = frame.ip() as usize;
+            sample.len += 1;
+            sample.len < MAX_FRAMES
+        });
+        slot.state.store(FULL, Release);
+    }
+
+    fn drain(&self) -> Vec<Sample> {
+        let _guard = self.drain.lock().unwrap_or_else(|e| e.into_inner());
+        let mut raw = Vec::new();
+        for slot in self.slots.iter() {
+            if slot.state.load(Acquire) == FULL {
+                let sample = unsafe { &*slot.sample.get() };
+                raw.push((sample.seq, sample.thread, sample.ips[..sample.len].to_vec()));
+                slot.state.store(EMPTY, Release);
+            }
+        }
+        raw.sort_by_key(|s| s.0);
+        raw.into_iter()
+            .map(|(_, thread, ips)| Sample {
+                thread: OsThreadId::from_raw(thread),
+                backtrace: Backtrace::from_ips(ips),
+            })
+            .collect()
+    }
+}
+
+// Called by the `SIGPROF` handler of `threads` for the signals of the timer.
+unsafe fn handler(context: *mut c_void) {
+    ACTIVE.fetch_add(1, SeqCst);
+    let ring = RING.load(SeqCst);
+    if !ring.is_null() {
+        (*ring).record(context);
+    }
+    ACTIVE.fetch_sub(1, SeqCst);
+}
+
+/// A stack sampled by a `Sampler`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug)]
+pub struct Sample {
+    thread: OsThreadId,
+    backtrace: Backtrace,
+}
+
+impl Sample {
+    /// Returns the thread which was interrupted to take the sample.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn thread(&self) -> OsThreadId {
+        self.thread
+    }
+
+    /// Returns the backtrace of the sample, whose first frame is the
+    /// instruction the thread was executing.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.backtrace
+    }
+
+    /// Returns the backtrace of the sample, consuming this value.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn into_backtrace(self) -> Backtrace {
+        self.backtrace
+    }
+}
+
+/// A running sampling profiler, which stops when dropped.
+///
+/// # Example
+///
+/// ```no_run
+/// use backtrace::profiling::Sampler;
+/// use std::time::Duration;
+///
+/// let sampler = Sampler::start_with_handler(99, Duration::from_secs(1), |samples| {
+///     for sample in samples {
+///         println!("{:?}", sample.backtrace());
+///     }
+/// })
+/// .expect("failed to start the sampler");
+/// // ... run the code to profile ...
+/// drop(sampler);
+/// ```
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub struct Sampler {
+    ring: Arc<Ring>,
+    worker: Option<(Arc<AtomicBool>, JoinHandle<()>)>,
+}
+
+impl Sampler {
+    /// Starts sampling the stacks of this process `frequency` times per
+    /// second of CPU time.
+    ///
+    /// Samples are kept in a buffer of 1024 samples until they're taken with
+    /// `drain` or `drain_resolved`, and samples taken while it's full are
+    /// dropped and counted by `dropped`.
+    ///
+    /// Returns an error if `frequency` is 0 or over a million, if another
+    /// sampler is running, or if the signal handler or timer can't be set up.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn start(frequency: u32) -> io::Result<Sampler> {
+        if frequency == 0 || frequency > 1_000_000 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "sampling frequency out of range",
+            ));
+        }
+        if RUNNING.swap(true, SeqCst) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "a sampler is already running",
+            ));
+        }
+        crate::backtrace::init_max_frames();
+        let ring = Arc::new(Ring::new());
+        RING.store(&*ring as *const Ring as *mut Ring, SeqCst);
+        unsafe {
+            let installed = {
+                let _guard = crate::lock::lock();
+                crate::threads::install_timer_handler(handler)
+            };
+            if !installed {
+                let err = io::Error::last_os_error();
+                RING.store(ptr::null_mut(), SeqCst);
+                RUNNING.store(false, SeqCst);
+                return Err(err);
+            }
+            let sampler = Sampler { ring, worker: None };
+            let period = 1_000_000 / frequency;
+            let period = libc::timeval {
+                tv_sec: (period / 1_000_000) as libc::time_t,
+                tv_usec: (period % 1_000_000) as libc::suseconds_t,
+            };
+            set_timer(period)?;
+            Ok(sampler)
+        }
+    }
+
+    /// Like `start`, but also starts a thread which every `interval` drains
+    /// the samples taken so far, symbolizes them, and passes them to
+    /// `handler` unless there are none.
+    ///
+    /// When the sampler is dropped the thread passes the last samples to
+    /// `handler` and the drop waits for it to finish.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn start_with_handler<F>(
+        frequency: u32,
+        interval: Duration,
+        mut handler: F,
+    ) -> io::Result<Sampler>
+    where
+        F: FnMut(Vec<Sample>) + Send + 'static,
+    {
+        let mut sampler = Sampler::start(frequency)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let ring = sampler.ring.clone();
+        let worker = {
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("backtrace-sampler".to_string())
+                .spawn(move || loop {
+                    thread::park_timeout(interval);
+                    let stopping = stop.load(SeqCst);
+                    let samples = resolve(ring.drain());
+                    if !samples.is_empty() {
+                        handler(samples);
+                    }
+                    if stopping {
+                        break;
+                    }
+                })?
+        };
+        sampler.worker = Some((stop, worker));
+        Ok(sampler)
+    }
+
+    /// Takes the samples taken since they were last drained, oldest first,
+    /// without symbolizing them.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn drain(&self) -> Vec<Sample> {
+        self.ring.drain()
+    }
+
+    /// Like `drain`, but symbolizes the samples, looking up each distinct
+    /// address only once.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn drain_resolved(&self) -> Vec<Sample> {
+        resolve(self.ring.drain())
+    }
+
+    /// Returns the number of samples dropped because the buffer was full.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn dropped(&self) -> usize {
+        self.ring.dropped.load(Relaxed)
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = set_timer(libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            });
+            RING.store(ptr::null_mut(), SeqCst);
+            while ACTIVE.load(SeqCst) != 0 {
+                thread::yield_now();
+            }
+        }
+        if let Some((stop, worker)) = self.worker.take() {
+            stop.store(true, SeqCst);
+            worker.thread().unpark();
+            let _ = worker.join();
+        }
+        RUNNING.store(false, SeqCst);
+    }
+}
+
+fn resolve(mut samples: Vec<Sample>) -> Vec<Sample> {
+    Backtrace::resolve_all(samples.iter_mut().map(|s| &mut s.backtrace));
+    samples
+}
+
+// unsafe because this must only be called while `SIGPROF` is handled
+unsafe fn set_timer(period: libc::timeval) -> io::Result<()> {
+    let timer = libc::itimerval {
+        it_interval: period,
+        it_value: period,
+    };
+    if libc::setitimer(ITIMER_PROF, &timer, ptr::null_mut()) != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -0,0 +1,166 @@
+//! Capturing and resolving backtraces from within allocator hooks.
+//!
+//! Allocation profilers record where allocations come from in their
+//! `GlobalAlloc`, but capturing a `Backtrace` allocates, and so does resolving
+//! one. Those allocations call the hook again, which would then capture a
+//! backtrace in the middle of capturing one, over and over. This module has
+//! what's needed to do it safely instead:
+//!
+//! * `enter` marks the current thread as being in the hook, and returns `None`
+//!   when it is already, so that the hook can pass the allocations it causes
+//!   itself straight through to the system allocator.
+//! * `capture` captures the instruction pointers of the current backtrace
+//!   into a buffer, without allocating.
+//! * `resolve` resolves an instruction pointer on Unix, in the debug
+//!   information which `prepare` loaded into memory set aside for it
+//!   beforehand. It neither allocates nor reads files, the same as the
+//!   symbolization of the `signal_safe` module.
+//!
+//! Resolution also guards itself: resolving again on a thread which is
+//! resolving already, such as from a hook which doesn't use `enter`, finds no
+//! symbols rather than using the symbolizer's caches while they're changed.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use backtrace::reentrant;
+//! use std::alloc::{GlobalAlloc, Layout, System};
+//! use std::ptr;
+//!
+//! struct Profiler;
+//!
+//! unsafe impl GlobalAlloc for Profiler {
+//!     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+//!         let mut ips = [ptr::null_mut(); 32];
+//!         if let Some(len) = reentrant::capture(&mut ips) {
+//!             // record `ips[..len]` in memory set aside for it...
+//!         }
+//!         System.alloc(layout)
+//!     }
+//!
+//!     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+//!         System.dealloc(ptr, layout)
+//!     }
+//! }
+//!
+//! #[global_allocator]
+//! static GLOBAL: Profiler = Profiler;
+//! ```
+//!
+//! # Required features
+//!
+//! This module requires the `std` feature of the `backtrace` crate to be
+//! enabled, and the `std` feature is enabled by default.
+
+#[cfg(unix)]
+use crate::Symbol;
+use core::ffi::c_void;
+
+/// Marks the current thread as being in an allocator hook until dropped, see
+/// `enter`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub struct Guard {
+    _lock: crate::lock::LockGuard,
+}
+
+/// Returns whether the current thread is within this crate, or within a
+/// `Guard` returned by `enter`.
+///
+/// An allocator hook should pass allocations through without capturing a
+/// backtrace while this is the case, as they're made by this crate or by the
+/// hook itself.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn is_active() -> bool {
+    crate::lock::is_held()
+}
+
+/// Marks the current thread as being in an allocator hook, returning `None`
+/// if it's been marked already or is within this crate.
+///
+/// Everything may be done while the returned guard is alive, including
+/// capturing and resolving a `Backtrace`: the allocations this makes call the
+/// hook again, where `enter` returns `None`. The guard holds the same lock as
+/// this crate holds while capturing and resolving, so hooks on other threads
+/// wait in `enter` until it's dropped.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn enter() -> Option<Guard> {
+    if is_active() {
+        return None;
+    }
+    Some(Guard {
+        _lock: crate::lock::lock(),
+    })
+}
+
+/// Captures the instruction pointers of the current backtrace into `ips`
+/// without allocating, returning how many were captured.
+///
+/// This is `trace_ips_into` within a `Guard` from `enter`, and returns `None`
+/// instead of capturing anything when `enter` does.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn capture(ips: &mut [*mut c_void]) -> Option<usize> {
+    let _guard = enter()?;
+    Some(crate::trace_ips_into(ips))
+}
+
+/// Loads the debug information of every loaded library into memory, so that
+/// `resolve` neither allocates nor reads files afterwards.
+///
+/// This is the same as `signal_safe::prepare`, see it for more information.
+/// It must not itself be called from an allocator hook.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default. It's only available
+/// on Unix.
+#[cfg(unix)]
+pub fn prepare() -> bool {
+    crate::signal_safe::prepare()
+}
+
+/// Resolves the instruction pointer `ip` like `backtrace::resolve`, returning
+/// whether it could.
+///
+/// Symbols are only found in the debug information loaded by `prepare`,
+/// without allocating, and only for the libraries which were loaded then.
+/// Inline frames aren't reported. This returns `false` without resolving
+/// anything if `prepare` hasn't been called, or when `enter` returns `None`.
+/// Use `backtrace::resolve` within a `Guard` from `enter` to resolve with the
+/// usual symbolizer instead, whose allocations then call the allocator hook
+/// again.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default. It's only available
+/// on Unix.
+#[cfg(unix)]
+#[allow(clippy::not_unsafe_ptr_arg_deref)] // `ip` is looked up, not dereferenced
+pub fn resolve<F: FnMut(&Symbol)>(ip: *mut c_void, mut cb: F) -> bool {
+    if !crate::signal_safe::is_prepared() {
+        return false;
+    }
+    let _guard = match enter() {
+        Some(guard) => guard,
+        None => return false,
+    };
+    unsafe { crate::symbolize::resolve_signal_safe(ip, &mut cb) };
+    true
+}
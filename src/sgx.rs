@@ -0,0 +1,60 @@
+//! Reporting of addresses in Fortanix SGX enclaves.
+//!
+//! Enclaves don't symbolize their own backtraces, since that would grow the
+//! trusted computing base. Printed backtraces instead show addresses relative
+//! to the enclave's image base, which can be symbolized outside of it. That
+//! base is the one `std` reports unless a runner which relocates enclaves sets
+//! the image with `set_enclave_image`. Frames outside of the enclave, in
+//! untrusted code such as the runner, are printed with their addresses as is
+//! and named `<untrusted>`.
+//!
+//! # Required features
+//!
+//! This module requires the `std` feature of the `backtrace` crate to be
+//! enabled, and the `std` feature is enabled by default.
+
+use core::ffi::c_void;
+use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::os::fortanix_sgx::mem;
+
+// Zero lengths mean that no image was set.
+static IMAGE_START: AtomicUsize = AtomicUsize::new(0);
+static IMAGE_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the address range the enclave image is loaded at, which is used
+/// instead of what `std` reports to rebase addresses and to tell frames in
+/// the enclave from untrusted ones. `None` goes back to what `std` reports.
+pub fn set_enclave_image(image: Option<Range<usize>>) {
+    let image = image.unwrap_or(0..0);
+    // Backtraces printed meanwhile use `std`'s image rather than half of each.
+    IMAGE_LEN.store(0, Ordering::SeqCst);
+    IMAGE_START.store(image.start, Ordering::SeqCst);
+    IMAGE_LEN.store(image.end.saturating_sub(image.start), Ordering::SeqCst);
+}
+
+fn image() -> Option<Range<usize>> {
+    let len = IMAGE_LEN.load(Ordering::SeqCst);
+    if len == 0 {
+        return None;
+    }
+    let start = IMAGE_START.load(Ordering::SeqCst);
+    Some(start..start.saturating_add(len))
+}
+
+/// Returns the base address of the enclave image which printed addresses are
+/// relative to.
+pub fn enclave_image_base() -> usize {
+    match image() {
+        Some(image) => image.start,
+        None => mem::image_base() as usize,
+    }
+}
+
+/// Returns whether `addr` is outside of the enclave, in untrusted memory.
+pub fn is_untrusted(addr: *mut c_void) -> bool {
+    match image() {
+        Some(image) => !image.contains(&(addr as usize)),
+        None => !mem::is_enclave_range(addr as *const u8, 1),
+    }
+}
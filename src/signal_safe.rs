@@ -0,0 +1,268 @@
+//! Async-signal-safe capture and symbolization.
+//!
+//! The usual `trace` and `resolve` functions of this crate take a global lock,
+//! allocate memory, and lazily read debug information from the filesystem.
+//! None of that is allowed inside of a signal handler such as one for
+//! `SIGSEGV`, so this module provides an opt-in alternative: call `prepare`
+//! once during startup (outside of any signal handler) to load and parse the
+//! debug information of every loaded library, and then from within a handler
+//! call `trace_and_print_signal_safe` to print the current backtrace to a file
+//! descriptor, or print frames one at a time with `FdWriter`.
+//!
+//! # Caveats
+//!
+//! Once prepared, symbolization performs no allocation, no file I/O, and takes
+//! no locks. It does, however, rely on the system unwinder, which is not
+//! formally async-signal-safe on all platforms (in practice it is on Linux
+//! with glibc and with LLVM's libunwind). Libraries loaded after `prepare` was
+//! called will not be symbolized, and inline frames are not reported. Only
+//! Rust names are demangled, demanglers registered with `register_demangler`
+//! are not used.
+//!
+//! # Required features
+//!
+//! This module requires the `std` feature of the `backtrace` crate to be
+//! enabled, and the `std` feature is enabled by default.
+
+use core::ffi::c_void;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+static PREPARED: AtomicBool = AtomicBool::new(false);
+
+/// Loads and parses the debug information of every library currently loaded
+/// into the process so it can later be used from within a signal handler.
+///
+/// Returns whether the current platform supports signal-safe symbolization.
+/// This function must not itself be called from a signal handler, and it's
+/// fine to call it more than once, for example after loading new libraries.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn prepare() -> bool {
+    let _guard = crate::lock::lock();
+    crate::backtrace::init_max_frames();
+    // Make sure a signal handler doesn't observe the cache while we're
+    // replacing it.
+    PREPARED.store(false, Ordering::SeqCst);
+    let ok = unsafe { crate::symbolize::prepare_signal_safe() };
+    PREPARED.store(ok, Ordering::SeqCst);
+    ok
+}
+
+/// Returns whether `prepare` has successfully been called.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn is_prepared() -> bool {
+    PREPARED.load(Ordering::SeqCst)
+}
+
+/// Captures the current backtrace and prints it to the file descriptor `fd`.
+///
+/// This performs no allocation and takes no locks, so it can be used from
+/// within a signal handler. If `prepare` hasn't been called then only the
+/// addresses of each frame are printed. Use `FdWriter` to print frames traced
+/// some other way, for example from the context given to a signal handler.
+///
+/// # Safety
+///
+/// This function is unsafe because it is unsynchronized with the rest of this
+/// crate. It must not be called concurrently with `prepare`, and `fd` must be
+/// a valid file descriptor.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub unsafe fn trace_and_print_signal_safe(fd: libc::c_int) {
+    let mut out = FdWriter::new(fd);
+    let mut index = 0;
+    crate::trace_unsynchronized(|frame| {
+        let _ = out.print_frame(index, frame.ip());
+        index += 1;
+        true
+    });
+}
+
+/// A writer to a raw file descriptor which can be used from within a signal
+/// handler.
+///
+/// Output is formatted into a fixed buffer on the stack and written with
+/// nothing but `write`, which is retried when interrupted. The buffer is
+/// written out when full, by `flush`, and when the writer is dropped. Besides
+/// printing frames with `print_frame`, anything else can be printed with
+/// `write_bytes` or with `write!` through `fmt::Write`, which never allocates
+/// for the types of `core` and of this crate.
+///
+/// # Example
+///
+/// ```no_run
+/// use backtrace::signal_safe::FdWriter;
+/// use std::fmt::Write;
+///
+/// // Installed as the handler of `SIGSEGV`, after `signal_safe::prepare`.
+/// extern "C" fn handler(signal: i32) {
+///     let mut out = FdWriter::new(2);
+///     let _ = writeln!(out, "fatal signal {}", signal);
+///     let mut index = 0;
+///     unsafe {
+///         backtrace::trace_unsynchronized(|frame| {
+///             let _ = out.print_frame(index, frame.ip());
+///             index += 1;
+///             true
+///         });
+///     }
+/// }
+/// ```
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub struct FdWriter {
+    fd: libc::c_int,
+    buf: [u8; 512],
+    len: usize,
+}
+
+impl FdWriter {
+    /// Creates a writer to the file descriptor `fd`, such as 2 for standard
+    /// error.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn new(fd: libc::c_int) -> FdWriter {
+        FdWriter {
+            fd,
+            buf: [0; 512],
+            len: 0,
+        }
+    }
+
+    /// Prints the frame with instruction pointer `ip` as the `index`th one
+    /// of a backtrace, in the short format of `Backtrace`'s `Debug` output but
+    /// with addresses.
+    ///
+    /// The frame is symbolized if `prepare` has been called, and otherwise
+    /// only its address is printed.
+    ///
+    /// # Safety
+    ///
+    /// This function is unsafe because it is unsynchronized with the rest of
+    /// this crate. It must not be called concurrently with `prepare`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub unsafe fn print_frame(&mut self, index: usize, ip: *mut c_void) -> fmt::Result {
+        let mut any = false;
+        let mut result = Ok(());
+        if is_prepared() {
+            crate::symbolize::resolve_signal_safe(ip, &mut |symbol| {
+                if result.is_err() {
+                    return;
+                }
+                result = self.print_symbol(index, ip, symbol, any);
+                any = true;
+            });
+        }
+        result?;
+        if !any {
+            writeln!(self, "{:4}: {:?} - <unknown>", index, ip)?;
+        }
+        Ok(())
+    }
+
+    fn print_symbol(
+        &mut self,
+        index: usize,
+        ip: *mut c_void,
+        symbol: &crate::Symbol,
+        continuation: bool,
+    ) -> fmt::Result {
+        if continuation {
+            self.write_str("      ")?;
+        } else {
+            write!(self, "{:4}: {:?} - ", index, ip)?;
+        }
+        match symbol.name() {
+            Some(name) => writeln!(self, "{:#}", name.display_signal_safe())?,
+            None => self.write_str("<unknown>\n")?,
+        }
+        if let (Some(crate::BytesOrWideString::Bytes(file)), Some(line)) =
+            (symbol.filename_raw(), symbol.lineno())
+        {
+            self.write_str("             at ")?;
+            self.write_bytes(file);
+            writeln!(self, ":{}", line)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `bytes` as they are, for example a path which isn't UTF-8.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn write_bytes(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            if self.len == self.buf.len() {
+                self.flush();
+            }
+            let n = bytes.len().min(self.buf.len() - self.len);
+            self.buf[self.len..][..n].copy_from_slice(&bytes[..n]);
+            self.len += n;
+            bytes = &bytes[n..];
+        }
+    }
+
+    /// Writes out everything buffered so far.
+    ///
+    /// Errors other than interruptions are ignored, as there's nothing a
+    /// signal handler could do about them.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn flush(&mut self) {
+        let mut data = &self.buf[..self.len];
+        while !data.is_empty() {
+            let n = unsafe { libc::write(self.fd, data.as_ptr() as *const _, data.len()) };
+            if n < 0 {
+                if errno() == libc::EINTR {
+                    continue;
+                }
+                break;
+            }
+            data = &data[n as usize..];
+        }
+        self.len = 0;
+    }
+}
+
+impl fmt::Write for FdWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+impl Drop for FdWriter {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+fn errno() -> libc::c_int {
+    std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
@@ -0,0 +1,345 @@
+//! Capturing backtraces of threads which overflowed their stack.
+//!
+//! A thread which overflows its stack runs into the guard page below it and
+//! gets a `SIGSEGV`, whose handler has to run on an alternate signal stack set
+//! up with `sigaltstack`, as there's no room left on the regular one. Those
+//! are small, and the regular capture and symbolization can easily need more
+//! than they have, not to mention that they allocate and take locks.
+//!
+//! The functions of this module only need a small, bounded amount of stack:
+//! `trace_bounded` stores instruction pointers into a buffer provided by the
+//! caller and leaves their symbolization for later, and `print_stack_overflow`
+//! only symbolizes them with the debug information loaded ahead of time by
+//! `signal_safe::prepare`. A few tens of kilobytes of signal stack are plenty
+//! for either.
+//!
+//! Threads call `register_thread` to record the bounds of their stack. That
+//! lets `is_stack_overflow` tell overflows apart from other segmentation
+//! faults, and lets `trace_bounded` stop at the guard page instead of
+//! following a corrupted frame into it.
+//!
+//! # Required features
+//!
+//! This module requires the `std` feature of the `backtrace` crate to be
+//! enabled, and the `std` feature is enabled by default.
+
+use crate::signal_safe::{self, FdWriter};
+use core::ffi::c_void;
+use core::fmt::Write;
+use core::mem;
+use core::ptr;
+use core::slice;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering::SeqCst};
+use std::prelude::v1::*;
+use std::sync::Once;
+
+/// The most threads which can be registered at once.
+const MAX_THREADS: usize = 256;
+
+/// The most frames printed by `print_stack_overflow`.
+const MAX_FRAMES: usize = 128;
+
+/// The stack of a registered thread. `thread` is zero for free slots, and is
+/// only set once `low` and `high` are.
+struct Slot {
+    thread: AtomicUsize,
+    /// The lowest address of the stack.
+    low: AtomicUsize,
+    /// The address just past the highest one of the stack.
+    high: AtomicUsize,
+    /// The size of the guard around `low`.
+    guard: AtomicUsize,
+}
+
+// Allocated by the first `register_thread` and never freed, so that signal
+// handlers can look slots up without any locking.
+static SLOTS: AtomicPtr<Slot> = AtomicPtr::new(ptr::null_mut());
+static INIT: Once = Once::new();
+
+fn slots() -> &'static [Slot] {
+    let slots = SLOTS.load(SeqCst);
+    if slots.is_null() {
+        &[]
+    } else {
+        unsafe { slice::from_raw_parts(slots, MAX_THREADS) }
+    }
+}
+
+/// Records the bounds of the calling thread's stack, for `is_stack_overflow`
+/// and `trace_bounded`.
+///
+/// Returns `false` if they couldn't be determined or if too many threads are
+/// registered already. Calling this again on a thread which is registered
+/// does nothing, and threads should call `unregister_thread` before they exit.
+///
+/// This doesn't set up an alternate signal stack for the thread, which the
+/// `SIGSEGV` handler needs to run on. The Rust standard library sets one up
+/// for the main thread and the threads it spawns, so long as its own handler
+/// was installed at startup.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn register_thread() -> bool {
+    crate::backtrace::init_max_frames();
+    INIT.call_once(|| {
+        let slots = (0..MAX_THREADS)
+            .map(|_| Slot {
+                thread: AtomicUsize::new(0),
+                low: AtomicUsize::new(0),
+                high: AtomicUsize::new(0),
+                guard: AtomicUsize::new(0),
+            })
+            .collect::<Vec<_>>();
+        let slots = Box::leak(slots.into_boxed_slice());
+        SLOTS.store(slots.as_mut_ptr(), SeqCst);
+    });
+    let thread = unsafe { libc::pthread_self() } as usize;
+    if find(thread).is_some() {
+        return true;
+    }
+    let (low, high, guard) = match stack_bounds() {
+        Some(bounds) => bounds,
+        None => return false,
+    };
+    for slot in slots() {
+        // Claim the slot with a placeholder so it's not used by another
+        // thread meanwhile, and only publish the thread once the bounds are
+        // in place.
+        if slot.thread.compare_exchange(0, !0, SeqCst, SeqCst).is_ok() {
+            slot.low.store(low, SeqCst);
+            slot.high.store(high, SeqCst);
+            slot.guard.store(guard, SeqCst);
+            slot.thread.store(thread, SeqCst);
+            return true;
+        }
+    }
+    false
+}
+
+/// Forgets the bounds of the calling thread's stack recorded by
+/// `register_thread`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn unregister_thread() {
+    let thread = unsafe { libc::pthread_self() } as usize;
+    if let Some(slot) = find(thread) {
+        slot.thread.store(0, SeqCst);
+    }
+}
+
+fn find(thread: usize) -> Option<&'static Slot> {
+    slots()
+        .iter()
+        .find(|slot| slot.thread.load(SeqCst) == thread)
+}
+
+/// Returns the bounds of the calling thread's stack, as recorded by
+/// `register_thread`.
+fn current_bounds() -> Option<(usize, usize, usize)> {
+    let slot = find(unsafe { libc::pthread_self() } as usize)?;
+    Some((
+        slot.low.load(SeqCst),
+        slot.high.load(SeqCst),
+        slot.guard.load(SeqCst),
+    ))
+}
+
+/// Queries the lowest and highest address of the calling thread's stack and
+/// the size of its guard.
+fn stack_bounds() -> Option<(usize, usize, usize)> {
+    unsafe {
+        let mut attr: libc::pthread_attr_t = mem::zeroed();
+        if libc::pthread_getattr_np(libc::pthread_self(), &mut attr) != 0 {
+            return None;
+        }
+        let mut addr = ptr::null_mut();
+        let mut size = 0;
+        let mut guard = 0;
+        let ok = libc::pthread_attr_getstack(&attr, &mut addr, &mut size) == 0
+            && libc::pthread_attr_getguardsize(&attr, &mut guard) == 0;
+        libc::pthread_attr_destroy(&mut attr);
+        if !ok {
+            return None;
+        }
+        let low = addr as usize;
+        Some((low, low + size, guard.max(page_size())))
+    }
+}
+
+fn page_size() -> usize {
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if size > 0 {
+        size as usize
+    } else {
+        4096
+    }
+}
+
+/// Returns whether the segmentation fault described by `info` was caused by
+/// the calling thread running into the guard page below its stack.
+///
+/// This is meant to be called from a `SIGSEGV` handler installed with
+/// `SA_SIGINFO`, and is always `false` for threads which didn't call
+/// `register_thread`. It can be used from within a signal handler.
+///
+/// # Safety
+///
+/// `info` must be null or point to a valid `siginfo_t`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub unsafe fn is_stack_overflow(info: *const libc::siginfo_t) -> bool {
+    let info = match info.as_ref() {
+        Some(info) => info,
+        None => return false,
+    };
+    if info.si_signo != libc::SIGSEGV && info.si_signo != libc::SIGBUS {
+        return false;
+    }
+    let (low, _, guard) = match current_bounds() {
+        Some(bounds) => bounds,
+        None => return false,
+    };
+    // Older versions of glibc count the guard of the threads they create as
+    // part of their stack while newer ones put it below, and the main
+    // thread's guard is the gap the kernel leaves below its stack, so faults
+    // on either side of the lowest address count.
+    let addr = info.si_addr() as usize;
+    low.saturating_sub(guard) <= addr && addr < low.saturating_add(guard)
+}
+
+/// Stores the instruction pointers of the stack interrupted by a signal into
+/// `ips`, returning how many were stored.
+///
+/// This is like `trace_from_context_unsynchronized` with a callback filling
+/// `ips`, except that the walk also stops at the first frame whose stack
+/// pointer isn't above the previous one's, and, for threads which called
+/// `register_thread`, at the first one outside of the thread's stack, so a
+/// corrupted frame doesn't lead the unwinder into the guard page. It needs
+/// little stack besides `ips` itself and no symbolization is done, so the
+/// instruction pointers can be resolved later, say after writing them out
+/// with `crash::write_crash_report` or `FdWriter::print_frame`.
+///
+/// # Safety
+///
+/// `context` must be null or point to the `ucontext_t` passed to a signal
+/// handler running on the calling thread. This is unsynchronized with the
+/// rest of this crate, see `trace_unsynchronized`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub unsafe fn trace_bounded(context: *const c_void, ips: &mut [*mut c_void]) -> usize {
+    // The frame which overflowed has its stack pointer in the guard already.
+    let (low, high) = match current_bounds() {
+        Some((low, high, guard)) => (low.saturating_sub(guard), high),
+        None => (0, !0),
+    };
+    let mut len = 0;
+    let mut prev_sp = 0;
+    crate::trace_from_context_unsynchronized(context, |frame| {
+        let sp = frame.sp() as usize;
+        // Not every unwinder knows the stack pointer of each frame, in which
+        // case there's nothing to check.
+        if sp != 0 {
+            if sp < low || sp > high || sp < prev_sp {
+                return false;
+            }
+            prev_sp = sp;
+        }
+        match ips.get_mut(len) {
+            Some(slot) => {
+                *slot = frame.ip();
+                len += 1;
+                true
+            }
+            None => false,
+        }
+    });
+    len
+}
+
+/// Prints the backtrace of the stack interrupted by a signal to the file
+/// descriptor `fd`, after a line telling whether the thread overflowed its
+/// stack.
+///
+/// Up to 128 frames are traced with `trace_bounded` and printed with
+/// `FdWriter::print_frame`, so they're only symbolized if
+/// `signal_safe::prepare` has been called. That takes some more stack, and
+/// signal stacks with little room to spare are better off without it.
+///
+/// # Example
+///
+/// ```no_run
+/// use backtrace::stack_overflow;
+///
+/// extern "C" fn handler(_: i32, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+///     unsafe {
+///         stack_overflow::print_stack_overflow(2, info, context);
+///         libc::_exit(1);
+///     }
+/// }
+///
+/// stack_overflow::register_thread();
+/// unsafe {
+///     let mut action: libc::sigaction = std::mem::zeroed();
+///     action.sa_sigaction = handler as *const () as libc::sighandler_t;
+///     action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+///     libc::sigaction(libc::SIGSEGV, &action, std::ptr::null_mut());
+/// }
+/// ```
+///
+/// # Safety
+///
+/// `info` and `context` must be null or the pointers passed to a signal
+/// handler running on the calling thread, this must not be called
+/// concurrently with `signal_safe::prepare`, and `fd` must be a valid file
+/// descriptor.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub unsafe fn print_stack_overflow(
+    fd: libc::c_int,
+    info: *const libc::siginfo_t,
+    context: *const c_void,
+) {
+    let mut ips = [ptr::null_mut(); MAX_FRAMES];
+    let len = trace_bounded(context, &mut ips);
+    let mut out = FdWriter::new(fd);
+    if is_stack_overflow(info) {
+        let _ = writeln!(out, "stack overflow at {:?}", (*info).si_addr());
+    } else if let Some(info) = info.as_ref() {
+        let _ = writeln!(out, "signal {} at {:?}", info.si_signo, info.si_addr());
+    }
+    for (index, ip) in ips[..len].iter().enumerate() {
+        if signal_safe::is_prepared() {
+            let _ = out.print_frame(index, *ip);
+        } else {
+            let _ = writeln!(out, "{:4}: {:?}", index, ip);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounds_contain_stack() {
+        let local = 0;
+        let (low, high, guard) = stack_bounds().unwrap();
+        let addr = &local as *const i32 as usize;
+        assert!(low <= addr && addr < high);
+        assert!(guard >= page_size());
+    }
+}
@@ -0,0 +1,175 @@
+//! Symbolization with Apple's CoreSymbolication framework, for code which the
+//! backend finds nothing for.
+//!
+//! The libraries and frameworks of macOS itself are in the dyld shared cache,
+//! and their symbols aren't in files on disk which the gimli symbolizer could
+//! read. CoreSymbolication, the private framework that `atos`, Instruments
+//! and crash reports use, knows about those symbols, as well as where to find
+//! debug information of other binaries, so it's asked when nothing else
+//! found a symbol for an address.
+//!
+//! The framework is loaded at runtime, and if it or any of the functions used
+//! here are missing nothing is done. As the framework is private there are no
+//! headers for it, and the functions are declared here as they have been for
+//! years in the headers of others reverse engineering it.
+
+use super::Symbol;
+use core::ffi::c_void;
+use core::mem;
+use core::ptr;
+use std::ffi::CStr;
+use std::os::unix::prelude::*;
+use std::path::PathBuf;
+use std::prelude::v1::*;
+
+const FRAMEWORK: &[u8] =
+    b"/System/Library/PrivateFrameworks/CoreSymbolication.framework/CoreSymbolication\0";
+
+/// Looks up symbols as the process is now, rather than at some point in its
+/// past.
+const NOW: u64 = 0x8000_0000;
+
+/// A reference to one of CoreSymbolication's objects, which is passed by
+/// value.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CSTypeRef {
+    _data: *mut c_void,
+    _obj: *mut c_void,
+}
+
+#[repr(C)]
+struct CSRange {
+    location: u64,
+    _length: u64,
+}
+
+type CreateWithPid = unsafe extern "C" fn(pid: libc::pid_t) -> CSTypeRef;
+type IsNull = unsafe extern "C" fn(cs: CSTypeRef) -> bool;
+type GetSymbol = unsafe extern "C" fn(symbolicator: CSTypeRef, addr: u64, time: u64) -> CSTypeRef;
+type GetSourceInfo =
+    unsafe extern "C" fn(symbolicator: CSTypeRef, addr: u64, time: u64) -> CSTypeRef;
+type GetString = unsafe extern "C" fn(cs: CSTypeRef) -> *const libc::c_char;
+type GetRange = unsafe extern "C" fn(symbol: CSTypeRef) -> CSRange;
+type GetNumber = unsafe extern "C" fn(source_info: CSTypeRef) -> u32;
+
+struct CoreSymbolication {
+    is_null: IsNull,
+    get_symbol: GetSymbol,
+    get_source_info: GetSourceInfo,
+    symbol_name: GetString,
+    symbol_mangled_name: GetString,
+    symbol_range: GetRange,
+    source_path: GetString,
+    source_line: GetNumber,
+    source_column: GetNumber,
+    /// The symbolicator for this process, which is never released.
+    symbolicator: CSTypeRef,
+}
+
+// Only accessed with the crate's global lock held. Null until first used, and
+// `LOADED` is set once loading has been tried so that it isn't tried again.
+static mut CORESYMBOLICATION: *mut CoreSymbolication = ptr::null_mut();
+static mut LOADED: bool = false;
+
+// unsafe because this is required to be externally synchronized
+unsafe fn load() -> Option<&'static CoreSymbolication> {
+    if !LOADED {
+        LOADED = true;
+        if let Some(cs) = open() {
+            CORESYMBOLICATION = Box::into_raw(Box::new(cs));
+        }
+    }
+    CORESYMBOLICATION.as_ref()
+}
+
+unsafe fn open() -> Option<CoreSymbolication> {
+    let lib = libc::dlopen(
+        FRAMEWORK.as_ptr() as *const libc::c_char,
+        libc::RTLD_NOW | libc::RTLD_LOCAL,
+    );
+    if lib.is_null() {
+        return None;
+    }
+    // The framework is never unloaded, as the functions are kept around.
+    let sym = |name: &[u8]| {
+        let sym = libc::dlsym(lib, name.as_ptr() as *const libc::c_char);
+        if sym.is_null() {
+            None
+        } else {
+            Some(sym)
+        }
+    };
+    let create =
+        mem::transmute::<*mut c_void, CreateWithPid>(sym(b"CSSymbolicatorCreateWithPid\0")?);
+    let cs = CoreSymbolication {
+        is_null: mem::transmute::<*mut c_void, IsNull>(sym(b"CSIsNull\0")?),
+        get_symbol: mem::transmute::<*mut c_void, GetSymbol>(sym(
+            b"CSSymbolicatorGetSymbolWithAddressAtTime\0",
+        )?),
+        get_source_info: mem::transmute::<*mut c_void, GetSourceInfo>(sym(
+            b"CSSymbolicatorGetSourceInfoWithAddressAtTime\0",
+        )?),
+        symbol_name: mem::transmute::<*mut c_void, GetString>(sym(b"CSSymbolGetName\0")?),
+        symbol_mangled_name: mem::transmute::<*mut c_void, GetString>(sym(
+            b"CSSymbolGetMangledName\0",
+        )?),
+        symbol_range: mem::transmute::<*mut c_void, GetRange>(sym(b"CSSymbolGetRange\0")?),
+        source_path: mem::transmute::<*mut c_void, GetString>(sym(b"CSSourceInfoGetPath\0")?),
+        source_line: mem::transmute::<*mut c_void, GetNumber>(sym(b"CSSourceInfoGetLineNumber\0")?),
+        source_column: mem::transmute::<*mut c_void, GetNumber>(sym(b"CSSourceInfoGetColumn\0")?),
+        symbolicator: create(libc::getpid()),
+    };
+    // The symbols and source infos returned belong to the symbolicator, so
+    // nothing needs to be released.
+    if (cs.is_null)(cs.symbolicator) {
+        return None;
+    }
+    Some(cs)
+}
+
+/// Calls `cb` with the symbol CoreSymbolication has for `addr`, returning
+/// whether there was one.
+// unsafe because this is required to be externally synchronized
+pub(super) unsafe fn resolve(addr: *mut c_void, cb: &mut dyn FnMut(&Symbol)) -> bool {
+    let cs = match load() {
+        Some(cs) => cs,
+        None => return false,
+    };
+    let addr = super::adjust_ip(addr) as u64;
+    let symbol = (cs.get_symbol)(cs.symbolicator, addr, NOW);
+    if (cs.is_null)(symbol) {
+        return false;
+    }
+    // Prefer the mangled name, so that Rust and C++ names are demangled like
+    // any other.
+    let name = [(cs.symbol_mangled_name)(symbol), (cs.symbol_name)(symbol)]
+        .iter()
+        .find(|name| !name.is_null())
+        .map(|name| CStr::from_ptr(*name).to_bytes().to_vec());
+    let name = match name {
+        Some(name) => name,
+        None => return false,
+    };
+    let range = (cs.symbol_range)(symbol);
+    let mut sym = Symbol::new(name).with_addr(range.location as usize as *mut c_void);
+
+    let source_info = (cs.get_source_info)(cs.symbolicator, addr, NOW);
+    if !(cs.is_null)(source_info) {
+        let path = (cs.source_path)(source_info);
+        if !path.is_null() {
+            let path = std::ffi::OsStr::from_bytes(CStr::from_ptr(path).to_bytes());
+            sym = sym.with_filename(PathBuf::from(path));
+        }
+        match (cs.source_line)(source_info) {
+            0 => {}
+            line => sym = sym.with_lineno(line),
+        }
+        match (cs.source_column)(source_info) {
+            0 => {}
+            column => sym = sym.with_colno(column),
+        }
+    }
+    cb(&sym);
+    true
+}
@@ -5,10 +5,16 @@
 //! it's dynamically loaded).
 //!
 //! This API selects its resolution strategy based on the frame provided or the
-//! information we have at hand. If a frame from `StackWalkEx` is given to us
-//! then we use similar APIs to generate correct information about inlined
-//! functions. Otherwise if all we have is an address or an older stack frame
-//! from `StackWalk64` we use the older APIs for symbolication.
+//! information we have at hand. If dbghelp is recent enough to know about
+//! inlined functions, an address or a frame from `StackWalkEx` is resolved to
+//! one symbol per inlined function plus one for the function it was inlined
+//! into, the same as with gimli. Otherwise, or if all we have is an older
+//! stack frame from `StackWalk64`, we use the older APIs for symbolication.
+//!
+//! If dbghelp is disabled or can't be loaded, addresses are instead resolved
+//! to the nearest preceding function in the export table of their module, which
+//! is read straight from memory. That only knows about exported functions, and
+//! so is a last resort.
 //!
 //! There's a good deal of support in this module, but a good chunk of it is
 //! converting back and forth between Windows types and Rust types. For example
@@ -23,14 +29,28 @@ use core::char;
 use core::ffi::c_void;
 use core::marker;
 use core::mem;
+use core::ptr;
 use core::slice;
+use object::pe::{
+    ImageDebugDirectory, ImageDosHeader, IMAGE_DEBUG_TYPE_CODEVIEW, IMAGE_DIRECTORY_ENTRY_DEBUG,
+    IMAGE_DIRECTORY_ENTRY_EXPORT,
+};
+use object::read::pe::{ExportTable, ImageNtHeaders, ImageOptionalHeader};
+use object::LittleEndian as LE;
+
+#[cfg(target_pointer_width = "32")]
+type Pe = object::pe::ImageNtHeaders32;
+#[cfg(target_pointer_width = "64")]
+type Pe = object::pe::ImageNtHeaders64;
 
 // Store an OsString on std so we can provide the symbol name and filename.
 pub struct Symbol<'a> {
     name: *const [u8],
     addr: *mut c_void,
+    size: Option<usize>,
     line: Option<u32>,
     filename: Option<*const [u16]>,
+    inline_depth: u32,
     #[cfg(feature = "std")]
     _filename_cache: Option<::std::ffi::OsString>,
     #[cfg(not(feature = "std"))]
@@ -52,6 +72,8 @@ impl Symbol<'_> {
             .map(|slice| unsafe { BytesOrWideString::Wide(&*slice) })
     }
 
+    // The line information dbghelp returns has no columns, even when the PDB
+    // records them. The `pdb` feature reads PDBs without dbghelp, and has them.
     pub fn colno(&self) -> Option<u32> {
         None
     }
@@ -60,6 +82,18 @@ impl Symbol<'_> {
         self.line
     }
 
+    pub fn inline_depth(&self) -> Option<u32> {
+        Some(self.inline_depth)
+    }
+
+    pub fn language(&self) -> Option<u16> {
+        None
+    }
+
+    pub fn comp_dir(&self) -> Option<&[u8]> {
+        None
+    }
+
     #[cfg(feature = "std")]
     pub fn filename(&self) -> Option<&::std::path::Path> {
         use std::path::Path;
@@ -72,48 +106,106 @@ impl Symbol<'_> {
 struct Aligned8<T>(T);
 
 pub unsafe fn resolve(what: ResolveWhat<'_>, cb: &mut dyn FnMut(&super::Symbol)) {
+    resolve_refreshing(what, &mut false, cb)
+}
+
+// Resolves `what`, and if nothing is found there because dbghelp doesn't know
+// the module `what` is in, has it look at the loaded modules again and tries
+// once more. `refreshed` is whether that's been done for the batch of
+// addresses `what` belongs to already, in which case it isn't done again.
+unsafe fn resolve_refreshing(
+    what: ResolveWhat<'_>,
+    refreshed: &mut bool,
+    cb: &mut dyn FnMut(&super::Symbol),
+) {
     // Ensure this process's symbols are initialized
     let dbghelp = match dbghelp::init() {
         Ok(dbghelp) => dbghelp,
-        Err(()) => return, // oh well...
+        Err(()) => return resolve_from_exports(what.address_or_ip(), cb),
     };
 
+    let mut found = false;
+    resolve_in(&dbghelp, what, &mut |symbol| {
+        found = true;
+        cb(symbol)
+    });
+    if found || *refreshed || !refresh_modules(&dbghelp, what.address_or_ip()) {
+        return;
+    }
+    *refreshed = true;
+    resolve_in(&dbghelp, what, cb);
+}
+
+unsafe fn resolve_in(
+    dbghelp: &dbghelp::Init,
+    what: ResolveWhat<'_>,
+    cb: &mut dyn FnMut(&super::Symbol),
+) {
     match what {
-        ResolveWhat::Address(_) => resolve_without_inline(&dbghelp, what.address_or_ip(), cb),
         ResolveWhat::Frame(frame) => match &frame.inner.stack_frame {
-            StackFrame::New(frame) => resolve_with_inline(&dbghelp, frame, cb),
-            StackFrame::Old(_) => resolve_without_inline(&dbghelp, frame.ip(), cb),
+            StackFrame::New(_) => resolve_with_inline(dbghelp, what.address_or_ip(), cb),
+            StackFrame::Old(_) => resolve_without_inline(dbghelp, frame.ip(), cb),
         },
+        _ => resolve_with_inline(dbghelp, what.address_or_ip(), cb),
     }
 }
 
+// dbghelp only knows about the modules which were loaded when it was
+// initialized, so addresses in DLLs loaded since then resolve to nothing. If
+// `addr` isn't in any module dbghelp knows, has it look at the loaded modules
+// again, returning whether it did.
+unsafe fn refresh_modules(dbghelp: &dbghelp::Init, addr: *mut c_void) -> bool {
+    let process = GetCurrentProcess();
+    if dbghelp.SymGetModuleBase64()(process, addr as DWORD64) != 0 {
+        return false;
+    }
+    let refresh = match (*dbghelp.dbghelp()).SymRefreshModuleList() {
+        Some(refresh) => refresh,
+        None => return false,
+    };
+    refresh(process);
+    #[cfg(feature = "std")]
+    crate::modules::modules_changed();
+    true
+}
+
+// Reports each function inlined at `addr`, innermost first, followed by the
+// function that the code physically belongs to. The walker skips the virtual
+// frames `StackWalkEx` produces for inlined functions, so this is where they
+// show up instead.
 unsafe fn resolve_with_inline(
     dbghelp: &dbghelp::Init,
-    frame: &STACKFRAME_EX,
+    addr: *mut c_void,
     cb: &mut dyn FnMut(&super::Symbol),
 ) {
-    do_resolve(
-        |info| {
-            dbghelp.SymFromInlineContextW()(
-                GetCurrentProcess(),
-                super::adjust_ip(frame.AddrPC.Offset as *mut _) as u64,
-                frame.InlineFrameContext,
-                &mut 0,
-                info,
-            )
-        },
-        |line| {
-            dbghelp.SymGetLineFromInlineContextW()(
-                GetCurrentProcess(),
-                super::adjust_ip(frame.AddrPC.Offset as *mut _) as u64,
-                frame.InlineFrameContext,
-                0,
-                &mut 0,
-                line,
-            )
-        },
-        cb,
-    )
+    let (include_inline_trace, query_inline_trace) = match (
+        (*dbghelp.dbghelp()).SymAddrIncludeInlineTrace(),
+        (*dbghelp.dbghelp()).SymQueryInlineTrace(),
+    ) {
+        (Some(include), Some(query)) => (include, query),
+        _ => return resolve_without_inline(dbghelp, addr, cb),
+    };
+    let process = GetCurrentProcess();
+    let addr = addr as DWORD64;
+
+    // The inline contexts of the frames at `addr` are consecutive, starting
+    // with the innermost inlined function.
+    let count = include_inline_trace(process, addr);
+    let mut context = 0;
+    if count == 0 || query_inline_trace(process, addr, 0, addr, addr, &mut context, &mut 0) != TRUE
+    {
+        return resolve_without_inline(dbghelp, addr as *mut c_void, cb);
+    }
+    for i in 0..=count {
+        do_resolve(
+            |info| dbghelp.SymFromInlineContextW()(process, addr, context + i, &mut 0, info),
+            |line| {
+                dbghelp.SymGetLineFromInlineContextW()(process, addr, context + i, 0, &mut 0, line)
+            },
+            count - i,
+            cb,
+        );
+    }
 }
 
 unsafe fn resolve_without_inline(
@@ -124,6 +216,7 @@ unsafe fn resolve_without_inline(
     do_resolve(
         |info| dbghelp.SymFromAddrW()(GetCurrentProcess(), addr as DWORD64, &mut 0, info),
         |line| dbghelp.SymGetLineFromAddrW64()(GetCurrentProcess(), addr as DWORD64, &mut 0, line),
+        0,
         cb,
     )
 }
@@ -131,6 +224,7 @@ unsafe fn resolve_without_inline(
 unsafe fn do_resolve(
     sym_from_addr: impl FnOnce(*mut SYMBOL_INFOW) -> BOOL,
     get_line_from_addr: impl FnOnce(&mut IMAGEHLP_LINEW64) -> BOOL,
+    inline_depth: u32,
     cb: &mut dyn FnMut(&super::Symbol),
 ) {
     const SIZE: usize = 2 * MAX_SYM_NAME + mem::size_of::<SYMBOL_INFOW>();
@@ -194,16 +288,121 @@ unsafe fn do_resolve(
         filename = Some(slice::from_raw_parts(base, len) as *const [u16]);
     }
 
-    cb(&super::Symbol {
-        inner: Symbol {
-            name,
-            addr: info.Address as *mut _,
-            line: lineno,
-            filename,
-            _filename_cache: cache(filename),
-            _marker: marker::PhantomData,
-        },
-    })
+    cb(&super::Symbol::from_imp(Symbol {
+        name,
+        addr: info.Address as *mut _,
+        size: Some(info.Size as usize).filter(|s| *s != 0),
+        line: lineno,
+        filename,
+        inline_depth,
+        _filename_cache: cache(filename),
+        _marker: marker::PhantomData,
+    }))
+}
+
+unsafe fn resolve_from_exports(addr: *mut c_void, cb: &mut dyn FnMut(&super::Symbol)) {
+    let image = match loaded_image(addr) {
+        Some(image) => image,
+        None => return,
+    };
+    let rva = (addr as usize - image.as_ptr() as usize) as u32;
+    let (export, name) = match search_exports(image, rva) {
+        Some(pair) => pair,
+        None => return,
+    };
+    cb(&super::Symbol::from_imp(Symbol {
+        name,
+        addr: image.as_ptr().add(export as usize) as *mut _,
+        size: None,
+        line: None,
+        filename: None,
+        inline_depth: 0,
+        _filename_cache: cache(None),
+        _marker: marker::PhantomData,
+    }))
+}
+
+// Returns the memory of the image loaded at `addr`, if any.
+unsafe fn loaded_image(addr: *mut c_void) -> Option<&'static [u8]> {
+    let mut module = ptr::null_mut();
+    let flags =
+        GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT;
+    if GetModuleHandleExW(flags, addr as PCWSTR, &mut module) != TRUE {
+        return None;
+    }
+
+    // The headers are mapped at the start of the image, and tell how large it
+    // is.
+    let base = module as *const u8;
+    let dos = &*(base as *const ImageDosHeader);
+    let nt = &*(base.add(dos.nt_headers_offset() as usize) as *const Pe);
+    let len = nt.optional_header().size_of_image() as usize;
+    Some(slice::from_raw_parts(base, len))
+}
+
+// Finds the export with the greatest address at or below `rva` in the same
+// section, returning its address and name.
+fn search_exports(image: &[u8], rva: u32) -> Option<(u32, &[u8])> {
+    let dos = ImageDosHeader::parse(image).ok()?;
+    let mut offset = dos.nt_headers_offset().into();
+    let (nt, data_directories) = Pe::parse(image, &mut offset).ok()?;
+    let sections = nt.sections(image, offset).ok()?;
+    let section_of = |rva: u32| {
+        sections.iter().position(|section| {
+            let start = section.virtual_address.get(LE);
+            rva.wrapping_sub(start) < section.virtual_size.get(LE)
+        })
+    };
+    let section = section_of(rva)?;
+
+    let (address, size) = data_directories
+        .get(IMAGE_DIRECTORY_ENTRY_EXPORT)?
+        .address_range();
+    let data = image.get(address as usize..)?.get(..size as usize)?;
+    let exports = ExportTable::parse(data, address).ok()?;
+    let mut best = None;
+    for (name, index) in exports.name_iter() {
+        let export = match exports.address_by_index(index.into()) {
+            Ok(export) => export,
+            Err(_) => continue,
+        };
+        if export > rva || exports.is_forward(export) || section_of(export) != Some(section) {
+            continue;
+        }
+        match best {
+            Some((best, _)) if best >= export => {}
+            _ => best = Some((export, name)),
+        }
+    }
+    let (export, name) = best?;
+    Some((export, exports.name_from_pointer(name).ok()?))
+}
+
+// Returns the GUID and age of the PDB recorded in the CodeView entry of the
+// image's debug directory, which together identify the build.
+#[cfg(feature = "std")]
+fn codeview_id(image: &[u8]) -> Option<std::vec::Vec<u8>> {
+    let dos = ImageDosHeader::parse(image).ok()?;
+    let mut offset = dos.nt_headers_offset().into();
+    let (_, data_directories) = Pe::parse(image, &mut offset).ok()?;
+    let (address, size) = data_directories
+        .get(IMAGE_DIRECTORY_ENTRY_DEBUG)?
+        .address_range();
+    let debug = image.get(address as usize..)?.get(..size as usize)?;
+    let count = debug.len() / mem::size_of::<ImageDebugDirectory>();
+    let (entries, _) = object::pod::slice_from_bytes::<ImageDebugDirectory>(debug, count).ok()?;
+    let entry = entries
+        .iter()
+        .find(|e| e.typ.get(LE) == IMAGE_DEBUG_TYPE_CODEVIEW)?;
+    // The image is mapped, so this is read at its address rather than its
+    // file offset.
+    let start = entry.address_of_raw_data.get(LE) as usize;
+    let len = entry.size_of_data.get(LE) as usize;
+    let info = image.get(start..)?.get(..len)?;
+    if info.get(..4)? != b"RSDS" {
+        return None;
+    }
+    Some(info.get(4..24)?.to_vec())
 }
 
 #[cfg(feature = "std")]
@@ -216,3 +415,177 @@ unsafe fn cache(filename: Option<*const [u16]>) -> Option<::std::ffi::OsString>
 unsafe fn cache(_filename: Option<*const [u16]>) {}
 
 pub unsafe fn clear_symbol_cache() {}
+
+#[cfg(feature = "std")]
+pub unsafe fn resolve_many(what: &[ResolveWhat<'_>], cb: &mut dyn FnMut(usize, &super::Symbol)) {
+    let mut refreshed = false;
+    for (i, what) in what.iter().enumerate() {
+        resolve_refreshing(*what, &mut refreshed, &mut |symbol| cb(i, symbol));
+    }
+}
+
+#[cfg(feature = "std")]
+pub fn loaded_modules() -> std::vec::Vec<crate::LoadedModule> {
+    use std::os::windows::prelude::*;
+
+    let mut ret = std::vec::Vec::new();
+    unsafe {
+        let snap = CreateToolhelp32Snapshot(TH32CS_SNAPMODULE, 0);
+        if snap == INVALID_HANDLE_VALUE {
+            return ret;
+        }
+        let mut me = mem::zeroed::<MODULEENTRY32W>();
+        me.dwSize = mem::size_of_val(&me) as DWORD;
+        let mut ok = Module32FirstW(snap, &mut me) == TRUE;
+        while ok {
+            let len = me
+                .szExePath
+                .iter()
+                .position(|i| *i == 0)
+                .unwrap_or(me.szExePath.len());
+            let path = std::ffi::OsString::from_wide(&me.szExePath[..len]);
+            let base = me.modBaseAddr as usize;
+
+            // The headers are mapped at the start of the image, so the
+            // preferred image base can be read from memory.
+            let image = slice::from_raw_parts(me.modBaseAddr, me.modBaseSize as usize);
+            let image_base = ImageDosHeader::parse(image).ok().and_then(|dos| {
+                let mut offset = dos.nt_headers_offset().into();
+                let (nt, _) = Pe::parse(image, &mut offset).ok()?;
+                Some(nt.optional_header().image_base() as usize)
+            });
+            let bias = base.wrapping_sub(image_base.unwrap_or(base));
+            ret.push(crate::LoadedModule::new(
+                path.into(),
+                bias,
+                bias,
+                vec![base..base + me.modBaseSize as usize],
+                codeview_id(image),
+            ));
+            ok = Module32NextW(snap, &mut me) == TRUE;
+        }
+        CloseHandle(snap);
+    }
+    ret
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn set_symbol_cache_capacity(_modules: usize, _bytes: Option<usize>) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn set_symbol_memory_limit(_limit: Option<usize>) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn set_object_file_mmap(_enabled: bool) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn set_symbol_search_path(path: Option<&std::ffi::OsStr>) {
+    if let Ok(dbghelp) = dbghelp::init() {
+        dbghelp.set_search_path(path);
+    }
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn set_symbol_server_downloads(enabled: bool) {
+    if let Ok(dbghelp) = dbghelp::init() {
+        dbghelp.set_symbol_servers(enabled);
+    }
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn symbol_cache_usage() -> (usize, usize) {
+    (0, 0)
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn prewarm_symbol_cache(modules: Option<&[&std::path::Path]>) -> usize {
+    let dbghelp = match dbghelp::init() {
+        Ok(dbghelp) => dbghelp,
+        Err(()) => return 0,
+    };
+    let mut warmed = 0;
+    for module in loaded_modules() {
+        if let Some(paths) = modules {
+            if !paths.contains(&module.path()) {
+                continue;
+            }
+        }
+        // dbghelp defers loading the symbols of a module until an address in
+        // it is first looked up, so look up its base address.
+        if let Some(range) = module.ranges().first() {
+            resolve_without_inline(&dbghelp, range.start as *mut c_void, &mut |_| {});
+            warmed += 1;
+        }
+    }
+    warmed
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn find_symbol(_name: &str) -> Option<(*mut c_void, std::path::PathBuf)> {
+    None
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn for_each_symbol(_cb: &mut dyn FnMut(usize, usize, &[u8])) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn evict_symbol_cache(_addr: usize) -> bool {
+    false
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn evict_symbol_cache_module(_module: super::ModuleKey<'_>) -> bool {
+    false
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn symbol_cache_stats() -> std::vec::Vec<super::ModuleCacheStats> {
+    std::vec::Vec::new()
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn register_module(_base: usize, _len: usize, _source: super::ModuleSource) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn unregister_module(_base: usize) -> bool {
+    false
+}
+
+#[cfg(feature = "std")]
+pub enum OfflineMapping {}
+
+#[cfg(feature = "std")]
+impl OfflineMapping {
+    pub fn from_path(_path: &std::path::Path) -> Option<OfflineMapping> {
+        None
+    }
+
+    pub fn from_bytes(_data: std::vec::Vec<u8>) -> Option<OfflineMapping> {
+        None
+    }
+
+    pub fn image_base(&self) -> Option<usize> {
+        match *self {}
+    }
+
+    pub fn resolve(&mut self, _addr: *mut c_void, _cb: &mut dyn FnMut(&super::Symbol)) {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn with_module(_addr: *mut c_void, _cb: &mut dyn FnMut(&super::ModuleRef<'_>)) {}
+
+// The symbol `SymFromAddrW` finds is the function containing `addr`, and its
+// address and size are that function's.
+#[cfg(feature = "std")]
+pub unsafe fn function_bounds(addr: *mut c_void) -> Option<(*mut c_void, Option<usize>)> {
+    let dbghelp = dbghelp::init().ok()?;
+    let mut bounds = None;
+    resolve_without_inline(&dbghelp, addr, &mut |sym| {
+        if let super::SymbolImp::Backend(sym) = &sym.inner {
+            bounds = Some((sym.addr, sym.size));
+        }
+    });
+    bounds
+}
@@ -0,0 +1,80 @@
+//! Hooks demangling names of languages the crate doesn't know about.
+
+use std::prelude::v1::*;
+
+type Demangler = Box<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+// Only accessed with the crate's global lock held, through `demanglers`.
+static mut DEMANGLERS: *mut Vec<Demangler> = core::ptr::null_mut();
+
+// unsafe because this is required to be externally synchronized
+unsafe fn demanglers() -> &'static mut Vec<Demangler> {
+    if DEMANGLERS.is_null() {
+        DEMANGLERS = Box::into_raw(Box::new(Vec::new()));
+    }
+    &mut *DEMANGLERS
+}
+
+/// Registers a demangler for names which neither `rustc-demangle`, the
+/// built-in demangler for names mangled by MSVC nor, with the `cpp_demangle`
+/// feature, `cpp_demangle` can demangle.
+///
+/// Binaries mixing languages contain names mangled by other schemes too, such
+/// as those of Swift and D, or ones particular to a code generator. When a
+/// `SymbolName` is displayed, including in backtraces, the demanglers
+/// registered here are tried in the order they were registered, and the first
+/// one returning a name is used. A demangler should return `None` for names
+/// it doesn't recognize, and is only called for names which are valid utf-8.
+///
+/// Demanglers aren't called while demangling is turned off with
+/// `set_demangling_enabled`, and `SymbolName::raw` always returns the name as
+/// it was found. They're called with the same lock held as for
+/// symbolization, so they must not themselves register demanglers. Backtraces
+/// printed from signal handlers by the `signal_safe` module don't use them.
+///
+/// # Example
+///
+/// ```
+/// backtrace::register_demangler(|name| {
+///     let rest = if name.starts_with("_my_") {
+///         &name[4..]
+///     } else {
+///         return None;
+///     };
+///     Some(rest.replace("__", "::"))
+/// });
+/// ```
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn register_demangler<F>(demangler: F)
+where
+    F: Fn(&str) -> Option<String> + Send + Sync + 'static,
+{
+    let _guard = crate::lock::lock();
+    unsafe {
+        demanglers().push(Box::new(demangler));
+    }
+}
+
+/// Removes all demanglers registered with `register_demangler`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn clear_demanglers() {
+    let _guard = crate::lock::lock();
+    unsafe {
+        demanglers().clear();
+    }
+}
+
+/// Demangles `name` with the first registered demangler which can.
+pub(super) fn demangle(name: &str) -> Option<String> {
+    let _guard = crate::lock::lock();
+    let demanglers = unsafe { demanglers() };
+    demanglers.iter().find_map(|demangler| demangler(name))
+}
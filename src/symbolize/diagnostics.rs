@@ -0,0 +1,237 @@
+//! Reporting why symbolization of a module came up short.
+//!
+//! Most failures while loading the debug information of a module just leave
+//! its frames unresolved, which gives no clue as to what went wrong. With
+//! diagnostics turned on, the symbolizer records what it ran into instead.
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::cell::RefCell;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::prelude::v1::*;
+use std::sync::{Mutex, Once};
+
+/// The most diagnostics kept until they're taken, after which further ones
+/// are dropped.
+const MAX_DIAGNOSTICS: usize = 256;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+// Diagnostics are also recorded by the threads creating mappings for
+// `resolve_many` while the global lock is held, so they have a lock of their
+// own. Created once and never freed.
+static mut DIAGNOSTICS: *mut Mutex<Vec<Diagnostic>> = core::ptr::null_mut();
+static INIT: Once = Once::new();
+
+thread_local!(static MODULE: RefCell<Option<PathBuf>> = RefCell::default());
+
+fn diagnostics() -> &'static Mutex<Vec<Diagnostic>> {
+    unsafe {
+        INIT.call_once(|| {
+            DIAGNOSTICS = Box::into_raw(Box::new(Mutex::new(Vec::new())));
+        });
+        &*DIAGNOSTICS
+    }
+}
+
+/// Something that kept a module from being symbolized as well as it could
+/// be, as returned by `take_diagnostics`.
+///
+/// # Required features
+///
+/// This type requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    module: PathBuf,
+    kind: DiagnosticKind,
+}
+
+/// What went wrong symbolizing a module, see `Diagnostic::kind`.
+///
+/// # Required features
+///
+/// This type requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DiagnosticKind {
+    /// The file at this path, the object file of the module or a separate
+    /// debug file for it, couldn't be opened.
+    OpenFailed(PathBuf),
+    /// An object file was opened but couldn't be mapped or read into memory.
+    MapFailed,
+    /// Nothing could be loaded to symbolize the module with, not even its
+    /// symbol table, so its frames are left unresolved. The diagnostics
+    /// recorded for the module before this one tell why, where that's known,
+    /// and otherwise its object file wasn't in a format the symbolizer
+    /// understands or didn't fit into the limit set with
+    /// `set_symbol_memory_limit`.
+    LoadFailed,
+    /// The module has no debug information, so only the names in its symbol
+    /// table are known and frames have no file and line numbers.
+    NoDebugInfo,
+    /// The debug information uses a version of DWARF which isn't supported.
+    UnsupportedDwarfVersion(u16),
+    /// The debug information couldn't be parsed.
+    InvalidDebugInfo,
+    /// The module was evicted from the symbol cache to stay within the limits
+    /// set with `set_symbol_cache_capacity`, and is loaded again the next time
+    /// an address in it is symbolized.
+    Evicted,
+}
+
+impl Diagnostic {
+    /// Returns the path of the module this is about, as the module is known
+    /// to the loader.
+    pub fn module(&self) -> &Path {
+        &self.module
+    }
+
+    /// Returns what went wrong.
+    pub fn kind(&self) -> &DiagnosticKind {
+        &self.kind
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.module.display())?;
+        match &self.kind {
+            DiagnosticKind::OpenFailed(path) => write!(f, "couldn't open {}", path.display()),
+            DiagnosticKind::MapFailed => f.write_str("couldn't map object file"),
+            DiagnosticKind::LoadFailed => f.write_str("couldn't load any symbols"),
+            DiagnosticKind::NoDebugInfo => f.write_str("no debug information"),
+            DiagnosticKind::UnsupportedDwarfVersion(version) => {
+                write!(f, "unsupported DWARF version {}", version)
+            }
+            DiagnosticKind::InvalidDebugInfo => f.write_str("invalid debug information"),
+            DiagnosticKind::Evicted => f.write_str("evicted from the symbol cache"),
+        }
+    }
+}
+
+/// Configures whether the symbolizer records why modules couldn't be
+/// symbolized fully, which is off by default.
+///
+/// When symbolization comes up empty, as for programs running in containers
+/// without their debug files, the frames of backtraces are simply left
+/// without names or locations. With diagnostics turned on, what happened
+/// while loading the debug information of each module is recorded, to be
+/// collected with `take_diagnostics`. Turning diagnostics off drops the ones
+/// which haven't been taken yet.
+///
+/// Modules are only loaded the first time an address in them is symbolized,
+/// so this is best called before that, or followed by `clear_symbol_cache`.
+///
+/// # Caveats
+///
+/// Like `clear_symbol_cache`, this only has an effect with the
+/// `gimli-symbolize` feature of this crate, and only for the modules of this
+/// process.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn set_diagnostics_enabled(enabled: bool) {
+    let _guard = crate::lock::lock();
+    ENABLED.store(enabled, Ordering::Relaxed);
+    if !enabled {
+        lock(diagnostics()).clear();
+    }
+}
+
+/// Returns the diagnostics recorded since the last call, oldest first, see
+/// `set_diagnostics_enabled`.
+///
+/// At most 256 diagnostics are kept between calls, and the ones after that
+/// are dropped.
+///
+/// # Example
+///
+/// ```
+/// backtrace::set_diagnostics_enabled(true);
+/// println!("{:?}", backtrace::Backtrace::new());
+/// for diagnostic in backtrace::take_diagnostics() {
+///     eprintln!("{}", diagnostic);
+/// }
+/// ```
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn take_diagnostics() -> Vec<Diagnostic> {
+    let _guard = crate::lock::lock();
+    mem::take(&mut *lock(diagnostics()))
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Calls `f`, attributing what's recorded by this thread meanwhile to
+/// `module`.
+pub(super) fn loading<R>(module: &Path, f: impl FnOnce() -> R) -> R {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+    let prev = MODULE.with(|m| m.replace(Some(module.to_path_buf())));
+    let ret = f();
+    MODULE.with(|m| *m.borrow_mut() = prev);
+    ret
+}
+
+/// Records `kind` for the module being loaded on this thread, if any.
+pub(super) fn record(kind: DiagnosticKind) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(module) = MODULE.with(|m| m.borrow().clone()) {
+        push(Diagnostic { module, kind });
+    }
+}
+
+/// Records `kind` for `module`.
+pub(super) fn record_in(module: &Path, kind: DiagnosticKind) {
+    if ENABLED.load(Ordering::Relaxed) {
+        push(Diagnostic {
+            module: module.to_path_buf(),
+            kind,
+        });
+    }
+}
+
+fn push(diagnostic: Diagnostic) {
+    let mut diagnostics = lock(diagnostics());
+    if diagnostics.len() < MAX_DIAGNOSTICS {
+        diagnostics.push(diagnostic);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let diagnostic = Diagnostic {
+            module: PathBuf::from("/lib/libfoo.so"),
+            kind: DiagnosticKind::OpenFailed(PathBuf::from("/usr/lib/debug/foo.debug")),
+        };
+        assert_eq!(
+            diagnostic.to_string(),
+            "/lib/libfoo.so: couldn't open /usr/lib/debug/foo.debug"
+        );
+        let diagnostic = Diagnostic {
+            module: PathBuf::from("/lib/libfoo.so"),
+            kind: DiagnosticKind::UnsupportedDwarfVersion(6),
+        };
+        assert_eq!(
+            diagnostic.to_string(),
+            "/lib/libfoo.so: unsupported DWARF version 6"
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Synthetic backtraces for testing code which prints them.
+
+use super::resolver::CustomSymbol;
+use super::{Symbol, SymbolImp};
+use core::cell::UnsafeCell;
+use core::ptr;
+use std::prelude::v1::*;
+use std::thread::{self, ThreadId};
+
+/// Frames and symbols to capture and resolve instead of the real ones, for
+/// tests which check how backtraces are printed.
+///
+/// What real backtraces look like depends on where the code was built, what
+/// the compiler inlined and the platform, so tests comparing printed
+/// backtraces against snapshots of them can't be reproduced elsewhere. While
+/// a fixture is installed with `install_fixture`, `Backtrace::new` and the
+/// other ways of capturing a `Backtrace` on the same thread return its frames
+/// instead, and `resolve`, `resolve_frame` and `Backtrace::resolve` only give
+/// the symbols it has for an address, and nothing for other addresses.
+///
+/// The addresses are looked up as given, without subtracting one as from the
+/// return addresses of real frames, and the symbols only have what they were
+/// created with by `Symbol::new` and its `with_*` methods. `trace` and the
+/// other functions giving a `Frame` still walk the real stack.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::{Backtrace, Fixture, Symbol};
+///
+/// let fixture = Fixture::new()
+///     .frame(0x1000, vec![Symbol::new("app::inner").with_filename("src/lib.rs").with_lineno(3)])
+///     .frame(0x2000, vec![Symbol::new("app::main").with_filename("src/main.rs").with_lineno(8)]);
+/// let _guard = backtrace::install_fixture(fixture);
+///
+/// let bt = Backtrace::new();
+/// assert_eq!(bt.frames().len(), 2);
+/// let name = bt.frames()[1].symbols()[0].name().unwrap();
+/// assert_eq!(name.to_string(), "app::main");
+/// ```
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub struct Fixture {
+    frames: Vec<usize>,
+    symbols: Vec<(usize, Vec<Symbol>)>,
+}
+
+impl Fixture {
+    /// Creates a fixture without any frames or symbols.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn new() -> Fixture {
+        Fixture {
+            frames: Vec::new(),
+            symbols: Vec::new(),
+        }
+    }
+
+    /// Adds a frame with the instruction pointer `ip` below those added
+    /// before, which resolves to `symbols`.
+    ///
+    /// Like for real frames, several symbols are for inlined functions, the
+    /// innermost one first.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn frame(mut self, ip: usize, symbols: Vec<Symbol>) -> Fixture {
+        self.frames.push(ip);
+        self.symbols(ip, symbols)
+    }
+
+    /// Makes `addr` resolve to `symbols`, without adding a frame for it.
+    ///
+    /// This replaces the symbols given for `addr` before.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn symbols(mut self, addr: usize, symbols: Vec<Symbol>) -> Fixture {
+        self.symbols.retain(|(a, _)| *a != addr);
+        self.symbols.push((addr, symbols));
+        self
+    }
+}
+
+impl Default for Fixture {
+    fn default() -> Fixture {
+        Fixture::new()
+    }
+}
+
+/// Uninstalls the fixture it was returned for when dropped, see
+/// `install_fixture`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub struct FixtureGuard {
+    id: usize,
+}
+
+impl Drop for FixtureGuard {
+    fn drop(&mut self) {
+        let _guard = crate::lock::lock();
+        unsafe {
+            if let Some(installed) = INSTALLED.as_mut() {
+                installed.retain(|(_, id, _)| *id != self.id);
+            }
+        }
+    }
+}
+
+// The fixtures installed on each thread, the last one of a thread being the
+// one in use. Only accessed with the crate's global lock held.
+static mut INSTALLED: *mut Vec<(ThreadId, usize, Fixture)> = ptr::null_mut();
+static mut NEXT_ID: usize = 0;
+
+/// Installs `fixture` on the current thread until the returned guard is
+/// dropped, see `Fixture`.
+///
+/// Only the current thread is affected, so that tests running in parallel
+/// each see their own fixture. A fixture installed while another one is
+/// replaces it until it's uninstalled.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn install_fixture(fixture: Fixture) -> FixtureGuard {
+    let _guard = crate::lock::lock();
+    unsafe {
+        if INSTALLED.is_null() {
+            INSTALLED = Box::into_raw(Box::new(Vec::new()));
+        }
+        let id = NEXT_ID;
+        NEXT_ID += 1;
+        (*INSTALLED).push((thread::current().id(), id, fixture));
+        FixtureGuard { id }
+    }
+}
+
+// unsafe because this is required to be externally synchronized
+unsafe fn current() -> Option<&'static Fixture> {
+    let installed = INSTALLED.as_ref()?;
+    let thread = thread::current().id();
+    installed
+        .iter()
+        .rev()
+        .find(|(t, _, _)| *t == thread)
+        .map(|(_, _, fixture)| fixture)
+}
+
+/// Returns the frames of the fixture installed on the current thread, if any.
+pub(crate) fn frames() -> Option<Vec<usize>> {
+    let _guard = crate::lock::lock();
+    unsafe { current().map(|fixture| fixture.frames.clone()) }
+}
+
+// unsafe because this is required to be externally synchronized
+pub(super) unsafe fn is_installed() -> bool {
+    current().is_some()
+}
+
+// Calls `cb` with the symbols of the fixture installed on the current thread
+// for `addr`, returning whether there is a fixture.
+//
+// unsafe because this is required to be externally synchronized
+pub(super) unsafe fn resolve(addr: usize, cb: &mut dyn FnMut(&Symbol)) -> bool {
+    let fixture = match current() {
+        Some(fixture) => fixture,
+        None => return false,
+    };
+    // The callback may install or uninstall fixtures, so it's given copies
+    // rather than the symbols of `fixture`.
+    let symbols = fixture
+        .symbols
+        .iter()
+        .filter(|(a, _)| *a == addr)
+        .flat_map(|(_, symbols)| symbols.iter().filter_map(copy))
+        .collect::<Vec<_>>();
+    for symbol in &symbols {
+        cb(symbol);
+    }
+    true
+}
+
+fn copy(symbol: &Symbol) -> Option<Symbol> {
+    let symbol = match &symbol.inner {
+        SymbolImp::Custom(symbol) => symbol,
+        _ => return None,
+    };
+    Some(Symbol {
+        inner: SymbolImp::Custom(CustomSymbol {
+            name: symbol.name.clone(),
+            addr: symbol.addr,
+            filename: symbol.filename.clone(),
+            lineno: symbol.lineno,
+            colno: symbol.colno,
+        }),
+        context: UnsafeCell::new(None),
+    })
+}
@@ -6,18 +6,23 @@ use self::gimli::read::EndianSlice;
 use self::gimli::NativeEndian as Endian;
 use self::mmap::Mmap;
 use self::stash::Stash;
+#[cfg(feature = "std")]
+use super::diagnostics::{self, DiagnosticKind};
 use super::BytesOrWideString;
 use super::ResolveWhat;
 use super::SymbolName;
 use addr2line::gimli;
 use core::convert::TryInto;
 use core::mem;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use core::u32;
 use libc::c_void;
 use mystd::ffi::OsString;
 use mystd::fs::File;
+use mystd::io::Read;
 use mystd::path::Path;
 use mystd::prelude::v1::*;
+use mystd::sync::Arc;
 
 #[cfg(backtrace_in_libstd)]
 mod mystd {
@@ -31,13 +36,16 @@ cfg_if::cfg_if! {
         #[path = "gimli/mmap_windows.rs"]
         mod mmap;
     } else if #[cfg(any(
+        target_os = "aix",
         target_os = "android",
+        target_os = "cygwin",
         target_os = "freebsd",
         target_os = "fuchsia",
         target_os = "haiku",
         target_os = "ios",
         target_os = "linux",
         target_os = "macos",
+        target_os = "netbsd",
         target_os = "openbsd",
         target_os = "solaris",
         target_os = "illumos",
@@ -50,15 +58,125 @@ cfg_if::cfg_if! {
     }
 }
 
+use super::image::dwarf;
 mod stash;
 
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+mod jit;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod proc_maps;
+
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+mod libs_process;
+
+#[cfg(target_os = "netbsd")]
+mod vm_map;
+
+#[cfg(all(feature = "perf-map", unix))]
+mod perf_map;
+
+#[cfg(all(feature = "kallsyms", any(target_os = "linux", target_os = "android")))]
+mod kallsyms;
+
+#[cfg(all(
+    feature = "debuginfod",
+    any(target_os = "linux", target_os = "freebsd")
+))]
+mod debuginfod;
+
+#[cfg(all(
+    feature = "minidebuginfo",
+    any(target_os = "linux", target_os = "freebsd")
+))]
+mod minidebuginfo;
+
+#[cfg(all(feature = "zstd", any(target_os = "linux", target_os = "freebsd")))]
+mod zstd;
+
+#[cfg(feature = "disk-cache")]
+mod disk_cache;
+
+#[cfg(feature = "parallel")]
+mod parallel;
+
+#[cfg(feature = "pdb")]
+mod pdb;
+
+#[cfg(feature = "std")]
+mod wasm;
+
+/// The number of mappings the cache keeps unless configured otherwise.
 const MAPPINGS_CACHE_SIZE: usize = 4;
 
+// The most memory symbolization may use, in bytes, with zero meaning no limit.
+// Only changed with the global lock held.
+static MEMORY_LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+// Whether object files are read into memory instead of mapped, as set with
+// `set_object_file_mmap`.
+static READ_FILES: AtomicBool = AtomicBool::new(false);
+
+/// Returns the limit set with `set_symbol_memory_limit`, if any.
+fn memory_limit() -> Option<usize> {
+    match MEMORY_LIMIT.load(Ordering::Relaxed) {
+        0 => None,
+        limit => Some(limit),
+    }
+}
+
 struct Mapping {
     // 'static lifetime is a lie to hack around lack of support for self-referential structs.
     cx: Context<'static>,
-    _map: Mmap,
-    _stash: Stash,
+    map: MapData,
+    // Boxed so that the `Context` can keep a reference to the stash itself,
+    // to load split DWARF on demand.
+    stash: Box<Stash>,
+}
+
+/// The bytes of the object file a `Mapping` was created from.
+enum MapData {
+    Mmap(Mmap),
+    Owned(Vec<u8>),
+    Shared(Arc<[u8]>),
+    Static(&'static [u8]),
+}
+
+impl core::ops::Deref for MapData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            MapData::Mmap(map) => map,
+            MapData::Owned(vec) => vec,
+            MapData::Shared(data) => data,
+            MapData::Static(data) => data,
+        }
+    }
+}
+
+impl From<Mmap> for MapData {
+    fn from(map: Mmap) -> MapData {
+        MapData::Mmap(map)
+    }
+}
+
+impl From<Vec<u8>> for MapData {
+    fn from(vec: Vec<u8>) -> MapData {
+        MapData::Owned(vec)
+    }
+}
+
+impl From<Arc<[u8]>> for MapData {
+    fn from(data: Arc<[u8]>) -> MapData {
+        MapData::Shared(data)
+    }
+}
+
+impl From<&'static [u8]> for MapData {
+    fn from(data: &'static [u8]) -> MapData {
+        MapData::Static(data)
+    }
 }
 
 enum Either<A, B> {
@@ -71,7 +189,7 @@ impl Mapping {
     /// Creates a `Mapping` by ensuring that the `data` specified is used to
     /// create a `Context` and it can only borrow from that or the `Stash` of
     /// decompressed sections or auxiliary data.
-    fn mk<F>(data: Mmap, mk: F) -> Option<Mapping>
+    fn mk<F>(data: impl Into<MapData>, mk: F) -> Option<Mapping>
     where
         F: for<'a> FnOnce(&'a [u8], &'a Stash) -> Option<Context<'a>>,
     {
@@ -83,11 +201,12 @@ impl Mapping {
 
     /// Creates a `Mapping` from `data`, or if the closure decides to, returns a
     /// different mapping.
-    fn mk_or_other<F>(data: Mmap, mk: F) -> Option<Mapping>
+    fn mk_or_other<F>(data: impl Into<MapData>, mk: F) -> Option<Mapping>
     where
         F: for<'a> FnOnce(&'a [u8], &'a Stash) -> Option<Either<Mapping, Context<'a>>>,
     {
-        let stash = Stash::new();
+        let data = data.into();
+        let stash = Box::new(Stash::new());
         let cx = match mk(&data, &stash)? {
             Either::A(mapping) => return Some(mapping),
             Either::B(cx) => cx,
@@ -96,18 +215,43 @@ impl Mapping {
             // Convert to 'static lifetimes since the symbols should
             // only borrow `map` and `stash` and we're preserving them below.
             cx: unsafe { core::mem::transmute::<Context<'_>, Context<'static>>(cx) },
-            _map: data,
-            _stash: stash,
+            map: data,
+            stash,
         })
     }
+
+    /// Returns roughly how much memory this mapping holds on to, which is
+    /// the object file plus whatever was decompressed or loaded besides it.
+    fn size(&self) -> usize {
+        self.map.len() + self.stash.size()
+    }
 }
 
 struct Context<'a> {
-    dwarf: addr2line::Context<EndianSlice<'a, Endian>>,
+    dwarf: dwarf::Dwarf<'a>,
     object: Object<'a>,
+    /// Split units of the skeleton units in `dwarf`, loaded on demand.
+    split: split_dwarf::SplitDwarf<'a>,
+    /// The PDB of a Windows image and the image's base address, which is
+    /// used instead of DWARF when found.
+    #[cfg(all(any(windows, target_os = "cygwin"), feature = "pdb"))]
+    pdb: Option<(pdb::Pdb<'a>, u64)>,
 }
 
 impl<'data> Context<'data> {
+    /// Returns whether there's debug information to find the locations of
+    /// addresses in, rather than just the symbol table.
+    #[cfg(feature = "std")]
+    fn has_debug_info(&self) -> bool {
+        #[cfg(all(any(windows, target_os = "cygwin"), feature = "pdb"))]
+        {
+            if self.pdb.is_some() {
+                return true;
+            }
+        }
+        self.dwarf.has_debug_info()
+    }
+
     fn new(
         stash: &'data Stash,
         object: Object<'data>,
@@ -127,21 +271,107 @@ impl<'data> Context<'data> {
                 })
                 .ok()?;
         }
-        let dwarf = addr2line::Context::from_dwarf(sections).ok()?;
+        let dwarf = dwarf::Dwarf::new(sections)?;
+
+        Some(Context {
+            dwarf,
+            object,
+            split: split_dwarf::SplitDwarf::new(stash),
+            #[cfg(all(any(windows, target_os = "cygwin"), feature = "pdb"))]
+            pdb: None,
+        })
+    }
+}
+
+/// Where the contents of an object file come from, see `open`.
+enum Source {
+    File(File),
+    Data(MapData),
+}
+
+/// Opens the object file at `path`, through the `ObjectSource` set with
+/// `set_object_source` if there is one.
+fn open(path: &Path) -> Option<Source> {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "std")] {
+            let source = unsafe { super::source::open_path(path) };
+            if source.is_none() {
+                diagnostics::record(DiagnosticKind::OpenFailed(path.to_path_buf()));
+            }
+            Source::new(source?)
+        } else {
+            File::open(path).ok().map(Source::File)
+        }
+    }
+}
+
+/// Like `open`, but for the separate debug file of the object file with the
+/// given build-id.
+#[cfg(feature = "std")]
+#[cfg_attr(
+    any(windows, target_os = "cygwin", target_os = "macos", target_os = "ios"),
+    allow(dead_code)
+)]
+fn open_build_id(build_id: &[u8]) -> Option<Source> {
+    Source::new(unsafe { super::source::open_build_id(build_id)? })
+}
+
+#[cfg(not(feature = "std"))]
+#[cfg_attr(
+    any(windows, target_os = "cygwin", target_os = "macos", target_os = "ios"),
+    allow(dead_code)
+)]
+fn open_build_id(_build_id: &[u8]) -> Option<Source> {
+    None
+}
+
+impl Source {
+    #[cfg(feature = "std")]
+    fn new(data: super::ObjectData) -> Option<Source> {
+        use super::source::ObjectDataKind;
+        match data.kind {
+            ObjectDataKind::File(file) => Some(Source::File(file)),
+            ObjectDataKind::Bytes(data) => Some(Source::Data(MapData::Shared(data))),
+            ObjectDataKind::Reader(mut reader) => {
+                let mut data = Vec::new();
+                reader.read_to_end(&mut data).ok()?;
+                Some(Source::Data(MapData::Owned(data)))
+            }
+        }
+    }
 
-        Some(Context { dwarf, object })
+    /// Returns the contents of the object file, mapped into memory if it's a
+    /// file unless that's been turned off with `set_object_file_mmap`.
+    fn map(self) -> Option<MapData> {
+        let map = match self {
+            Source::File(file) => map_file(&file),
+            Source::Data(data) => Some(data),
+        };
+        #[cfg(feature = "std")]
+        {
+            if map.is_none() {
+                diagnostics::record(DiagnosticKind::MapFailed);
+            }
+        }
+        map
     }
 }
 
-fn mmap(path: &Path) -> Option<Mmap> {
-    let file = File::open(path).ok()?;
+fn map_file(file: &File) -> Option<MapData> {
     let len = file.metadata().ok()?.len().try_into().ok()?;
-    unsafe { Mmap::map(&file, len) }
+    if READ_FILES.load(Ordering::Relaxed) {
+        let mut data = Vec::with_capacity(len);
+        (&*file).read_to_end(&mut data).ok()?;
+        return Some(MapData::Owned(data));
+    }
+    unsafe { Mmap::map(file, len) }.map(MapData::Mmap)
 }
 
 cfg_if::cfg_if! {
-    if #[cfg(windows)] {
+    if #[cfg(any(windows, target_os = "cygwin"))] {
         mod coff;
+        #[path = "gimli/split_dwarf_none.rs"]
+        mod split_dwarf;
         use self::coff::Object;
     } else if #[cfg(any(
         target_os = "macos",
@@ -150,15 +380,23 @@ cfg_if::cfg_if! {
         target_os = "watchos",
     ))] {
         mod macho;
+        #[path = "gimli/split_dwarf_none.rs"]
+        mod split_dwarf;
         use self::macho::Object;
+    } else if #[cfg(target_os = "aix")] {
+        mod xcoff;
+        #[path = "gimli/split_dwarf_none.rs"]
+        mod split_dwarf;
+        use self::xcoff::Object;
     } else {
         mod elf;
+        mod split_dwarf;
         use self::elf::Object;
     }
 }
 
 cfg_if::cfg_if! {
-    if #[cfg(windows)] {
+    if #[cfg(any(windows, target_os = "cygwin"))] {
         mod libs_windows;
         use libs_windows::native_libraries;
     } else if #[cfg(any(
@@ -169,7 +407,10 @@ cfg_if::cfg_if! {
     ))] {
         mod libs_macos;
         use libs_macos::native_libraries;
-    } else if #[cfg(target_os = "illumos")] {
+    } else if #[cfg(target_os = "aix")] {
+        mod libs_aix;
+        use libs_aix::native_libraries;
+    } else if #[cfg(any(target_os = "illumos", target_os = "solaris"))] {
         mod libs_illumos;
         use libs_illumos::native_libraries;
     } else if #[cfg(all(
@@ -178,18 +419,25 @@ cfg_if::cfg_if! {
             target_os = "fuchsia",
             target_os = "freebsd",
             target_os = "openbsd",
+            target_os = "netbsd",
             all(target_os = "android", feature = "dl_iterate_phdr"),
         ),
         not(target_env = "uclibc"),
     ))] {
         mod libs_dl_iterate_phdr;
         use libs_dl_iterate_phdr::native_libraries;
+    } else if #[cfg(target_os = "android")] {
+        mod libs_proc_maps;
+        use libs_proc_maps::native_libraries;
     } else if #[cfg(target_env = "libnx")] {
         mod libs_libnx;
         use libs_libnx::native_libraries;
     } else if #[cfg(target_os = "haiku")] {
         mod libs_haiku;
         use libs_haiku::native_libraries;
+    } else if #[cfg(any(target_os = "redox", target_os = "hermit"))] {
+        mod libs_ehdr;
+        use libs_ehdr::native_libraries;
     } else {
         // Everything else should doesn't know how to load native libraries.
         fn native_libraries() -> Vec<Library> {
@@ -198,6 +446,26 @@ cfg_if::cfg_if! {
     }
 }
 
+cfg_if::cfg_if! {
+    if #[cfg(all(
+        any(
+            target_os = "linux",
+            target_os = "fuchsia",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            all(target_os = "android", feature = "dl_iterate_phdr"),
+        ),
+        not(target_env = "uclibc"),
+    ))] {
+        use libs_dl_iterate_phdr::loader_generation;
+    } else {
+        // Elsewhere the libraries are only looked up once.
+        fn loader_generation() -> Option<(u64, u64)> {
+            None
+        }
+    }
+}
+
 #[derive(Default)]
 struct Cache {
     /// All known shared libraries that have been loaded.
@@ -205,16 +473,43 @@ struct Cache {
 
     /// Mappings cache where we retain parsed dwarf information.
     ///
-    /// This list has a fixed capacity for its entire lifetime which never
-    /// increases. The `usize` element of each pair is an index into `libraries`
-    /// above where `usize::max_value()` represents the current executable. The
-    /// `Mapping` is corresponding parsed dwarf information.
+    /// This list holds at most `max_mappings` entries, and if set at most
+    /// `max_mapping_bytes` bytes worth of them, but always at least the most
+    /// recently used one. The `usize` element of each pair is an index into
+    /// `libraries` above where `usize::max_value()` represents the current
+    /// executable. The `Mapping` is corresponding parsed dwarf information.
     ///
     /// Note that this is basically an LRU cache and we'll be shifting things
     /// around in here as we symbolize addresses.
     mappings: Vec<(usize, Mapping)>,
+    max_mappings: usize,
+    max_mapping_bytes: Option<usize>,
+
+    /// How many times the mapping of each library was found in `mappings`,
+    /// and how many times it was created, by index into `libraries`.
+    lookups: Vec<(usize, u64, u64)>,
+
+    /// Function names written by JIT compilers for `perf`, used for addresses
+    /// outside of any library.
+    #[cfg(all(feature = "perf-map", unix))]
+    perf_map: perf_map::PerfMap,
+
+    /// The names of the kernel's functions, used for kernel addresses.
+    #[cfg(all(feature = "kallsyms", any(target_os = "linux", target_os = "android")))]
+    kallsyms: kallsyms::Kallsyms,
+
+    /// The on-disk indexes opened so far, by index into `libraries`, or
+    /// `None` for libraries without one.
+    #[cfg(feature = "disk-cache")]
+    indexes: Vec<(usize, Option<disk_cache::Index>)>,
+
+    /// The loader's counts of loaded and unloaded objects as of when
+    /// `libraries` was last brought up to date, to notice `dlopen` and
+    /// `dlclose` calls since then.
+    generation: Option<(u64, u64)>,
 }
 
+#[derive(Clone)]
 struct Library {
     name: OsString,
     /// Segments of this library loaded into memory, and where they're loaded.
@@ -225,8 +520,104 @@ struct Library {
     /// this bias is subtracted from real virtual memory addresses to index into
     /// debuginfo and the symbol table.
     bias: usize,
+    /// How far the library has been moved from the addresses in its headers,
+    /// which on macOS isn't always the same as the `bias`.
+    slide: usize,
+    /// The build-id of this library (the GNU build-id note for ELF, the
+    /// `LC_UUID` for Mach-O), if known.
+    build_id: Option<Vec<u8>>,
+    /// Set for libraries added with `register_module` rather than found
+    /// through the system's loader.
+    registered: Option<Registered>,
+}
+
+/// Where the object file of a registered library comes from.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+enum Registered {
+    /// The file at the library's `name`.
+    Path,
+    /// An object file given in memory.
+    Bytes(Arc<[u8]>),
+    /// An object file given in memory for the rest of the program.
+    Static(&'static [u8]),
+    /// An object file registered through the GDB JIT interface at the given
+    /// address, and a copy of it.
+    #[cfg_attr(
+        not(any(target_os = "linux", target_os = "android", target_os = "freebsd")),
+        allow(dead_code)
+    )]
+    Jit(usize, Arc<[u8]>),
+}
+
+impl Library {
+    /// Maps the object file this library was loaded from.
+    fn map(&self) -> Option<Mapping> {
+        cfg_if::cfg_if! {
+            if #[cfg(all(
+                any(
+                    target_os = "linux",
+                    all(target_os = "android", feature = "dl_iterate_phdr"),
+                ),
+                not(target_env = "uclibc"),
+            ))] {
+                libs_dl_iterate_phdr::map_library(self)
+            } else if #[cfg(any(
+                target_os = "macos",
+                target_os = "ios",
+                target_os = "tvos",
+                target_os = "watchos",
+            ))] {
+                libs_macos::map_library(self)
+            } else {
+                Mapping::new(self.name.as_ref())
+            }
+        }
+    }
+
+    /// Creates the mapping to symbolize addresses in this library with,
+    /// from wherever its object file comes from.
+    fn load(&self) -> Option<Mapping> {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "std")] {
+                let name: &Path = self.name.as_ref();
+                let mapping = diagnostics::loading(name, || self.load_mapping());
+                match &mapping {
+                    Some(mapping) if !mapping.cx.has_debug_info() => {
+                        diagnostics::record_in(name, DiagnosticKind::NoDebugInfo)
+                    }
+                    Some(_) => {}
+                    None => diagnostics::record_in(name, DiagnosticKind::LoadFailed),
+                }
+                mapping
+            } else {
+                self.load_mapping()
+            }
+        }
+    }
+
+    fn load_mapping(&self) -> Option<Mapping> {
+        #[allow(unused_mut)]
+        let mut mapping = match &self.registered {
+            Some(Registered::Bytes(data)) | Some(Registered::Jit(_, data)) => {
+                Mapping::from_bytes(data.clone())?
+            }
+            Some(Registered::Static(data)) => Mapping::from_bytes(*data)?,
+            _ => self.map()?,
+        };
+
+        #[cfg(feature = "disk-cache")]
+        {
+            if let Some(build_id) = &self.build_id {
+                disk_cache::write(build_id, &mut mapping.cx);
+            }
+        }
+
+        Some(mapping)
+    }
 }
 
+#[derive(Clone)]
 struct LibrarySegment {
     /// The stated address of this segment in the object file. This is not
     /// actually where the segment is loaded, but rather this address plus the
@@ -236,21 +627,612 @@ struct LibrarySegment {
     len: usize,
 }
 
+#[cfg(feature = "std")]
+pub fn loaded_modules() -> Vec<crate::LoadedModule> {
+    native_libraries().into_iter().map(loaded_module).collect()
+}
+
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+pub fn process_modules(pid: u32) -> Vec<crate::LoadedModule> {
+    libs_process::process_libraries(pid)
+        .into_iter()
+        .map(loaded_module)
+        .collect()
+}
+
+#[cfg(feature = "std")]
+fn loaded_module(lib: Library) -> crate::LoadedModule {
+    // Segments are all program headers on ELF, so some of them overlap and
+    // others are empty.
+    let mut ranges = lib
+        .segments
+        .iter()
+        .filter(|s| s.len > 0)
+        .map(|s| {
+            let start = s.stated_virtual_memory_address.wrapping_add(lib.bias);
+            start..start.wrapping_add(s.len)
+        })
+        .collect::<Vec<_>>();
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<core::ops::Range<usize>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    crate::LoadedModule::new(lib.name.into(), lib.bias, lib.slide, merged, lib.build_id)
+}
+
+/// An object file loaded for `offline` symbolization.
+#[cfg(feature = "std")]
+pub struct OfflineMapping(OfflineObject);
+
+#[cfg(feature = "std")]
+enum OfflineObject {
+    Object(Box<Mapping>),
+    /// A PDB file, which can be read on any platform.
+    #[cfg(feature = "pdb")]
+    Pdb(pdb::Mapping),
+    /// A WebAssembly module, which can be read on any platform.
+    Wasm(wasm::Mapping),
+}
+
+#[cfg(feature = "std")]
+impl OfflineMapping {
+    pub fn from_path(path: &Path) -> Option<OfflineMapping> {
+        let map = open(path)?.map()?;
+        #[cfg(feature = "pdb")]
+        {
+            if pdb::is_pdb(&map) {
+                return pdb::Mapping::new(map).map(|m| OfflineMapping(OfflineObject::Pdb(m)));
+            }
+        }
+        if wasm::is_wasm(&map) {
+            return wasm::Mapping::new(map).map(|m| OfflineMapping(OfflineObject::Wasm(m)));
+        }
+        Mapping::new(path).map(|m| OfflineMapping(OfflineObject::Object(Box::new(m))))
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Option<OfflineMapping> {
+        #[cfg(feature = "pdb")]
+        {
+            if pdb::is_pdb(&data) {
+                return pdb::Mapping::new(data.into())
+                    .map(|m| OfflineMapping(OfflineObject::Pdb(m)));
+            }
+        }
+        if wasm::is_wasm(&data) {
+            return wasm::Mapping::new(data.into()).map(|m| OfflineMapping(OfflineObject::Wasm(m)));
+        }
+        Mapping::from_bytes(data).map(|m| OfflineMapping(OfflineObject::Object(Box::new(m))))
+    }
+
+    /// Returns the stated address of the start of the image, where its
+    /// headers are, which is 0 for PDB files and WebAssembly modules.
+    pub fn image_base(&self) -> Option<usize> {
+        use object::{BinaryFormat, Object, ObjectSegment};
+
+        let mapping = match &self.0 {
+            OfflineObject::Object(mapping) => mapping,
+            _ => return Some(0),
+        };
+        let file = object::File::parse(&*mapping.map).ok()?;
+        let base = match file.format() {
+            BinaryFormat::Pe => file.relative_address_base(),
+            BinaryFormat::MachO => file
+                .segments()
+                .find(|s| s.name() == Ok(Some("__TEXT")))?
+                .address(),
+            _ => file.segments().map(|s| s.address()).min()?,
+        };
+        Some(base as usize)
+    }
+
+    /// Resolves `addr`, which is relative to the object file, the same way as
+    /// `resolve` does for addresses in loaded libraries.
+    pub fn resolve(&mut self, addr: *mut c_void, cb: &mut dyn FnMut(&super::Symbol)) {
+        let addr = ResolveWhat::Address(addr).address_or_ip();
+        let mut call = |sym: Symbol<'_>| {
+            // See `resolve` for why this is fine.
+            let sym = unsafe { mem::transmute::<Symbol<'_>, Symbol<'static>>(sym) };
+            cb(&super::Symbol::from_imp(sym));
+        };
+        match &mut self.0 {
+            OfflineObject::Object(mapping) => {
+                resolve_in(&mut mapping.cx, addr as *const u8, &mut call)
+            }
+            #[cfg(feature = "pdb")]
+            OfflineObject::Pdb(mapping) => {
+                mapping.resolve(addr, addr as u64, &mut call);
+            }
+            OfflineObject::Wasm(mapping) => mapping.resolve(addr, addr as u64, &mut call),
+        }
+    }
+}
+
+/// The modules of other processes and the mappings of their object files, for
+/// `remote` symbolization.
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+pub struct RemoteSymbolizer {
+    /// The libraries of each process seen so far, by pid.
+    processes: Vec<(u32, Vec<Library>)>,
+    /// Mappings are shared between processes, as many of them load the same
+    /// libraries. `None` if the object file couldn't be loaded.
+    mappings: Vec<(MappingKey, Option<Mapping>)>,
+    #[cfg(feature = "disk-cache")]
+    indexes: Vec<(Vec<u8>, Option<disk_cache::Index>)>,
+}
+
+/// What identifies the object file of a library: its build-id or, if it
+/// hasn't one, its path.
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+#[derive(PartialEq)]
+enum MappingKey {
+    BuildId(Vec<u8>),
+    Path(OsString),
+}
+
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+impl MappingKey {
+    fn new(lib: &Library) -> MappingKey {
+        match &lib.build_id {
+            Some(id) => MappingKey::BuildId(id.clone()),
+            None => MappingKey::Path(lib.name.clone()),
+        }
+    }
+}
+
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+impl RemoteSymbolizer {
+    pub fn new() -> RemoteSymbolizer {
+        RemoteSymbolizer {
+            processes: Vec::new(),
+            mappings: Vec::new(),
+            #[cfg(feature = "disk-cache")]
+            indexes: Vec::new(),
+        }
+    }
+
+    /// Resolves `addr` in the process `pid`, reading its modules the first
+    /// time, and again if `addr` isn't in any of them, as it may have loaded
+    /// more since.
+    pub fn resolve(&mut self, pid: u32, addr: *mut c_void, cb: &mut dyn FnMut(&super::Symbol)) {
+        let addr = ResolveWhat::Address(addr).address_or_ip() as *const u8;
+        let i = match self.processes.iter().position(|(p, _)| *p == pid) {
+            Some(i) => i,
+            None => {
+                let libs = libs_process::process_libraries(pid);
+                self.processes.push((pid, libs));
+                self.processes.len() - 1
+            }
+        };
+        let (lib, svma) = match avma_to_svma(&self.processes[i].1, addr) {
+            Some(pair) => pair,
+            None => {
+                self.processes[i].1 = libs_process::process_libraries(pid);
+                match avma_to_svma(&self.processes[i].1, addr) {
+                    Some(pair) => pair,
+                    None => return,
+                }
+            }
+        };
+        let lib = &self.processes[i].1[lib];
+        let mut call = |sym: Symbol<'_>| {
+            // See `resolve` for why this is fine.
+            let sym = unsafe { mem::transmute::<Symbol<'_>, Symbol<'static>>(sym) };
+            cb(&super::Symbol::from_imp(sym));
+        };
+
+        #[cfg(feature = "disk-cache")]
+        {
+            if let Some(id) = &lib.build_id {
+                let j = match self.indexes.iter().position(|(key, _)| key == id) {
+                    Some(j) => j,
+                    None => {
+                        self.indexes.push((id.clone(), disk_cache::Index::open(id)));
+                        self.indexes.len() - 1
+                    }
+                };
+                if let Some(index) = &self.indexes[j].1 {
+                    if let Some(frames) = index.find(svma as u64) {
+                        let count = frames.len();
+                        for (k, frame) in frames.into_iter().enumerate() {
+                            call(Symbol::Frame {
+                                addr: svma as *mut c_void,
+                                location: frame.location,
+                                name: frame.name,
+                                inline_depth: (count - 1 - k) as u32,
+                                language: None,
+                                comp_dir: None,
+                            });
+                        }
+                        return;
+                    }
+                }
+            }
+        }
+
+        let key = MappingKey::new(lib);
+        let j = match self.mappings.iter().position(|(k, _)| *k == key) {
+            Some(j) => j,
+            None => {
+                // The process's libraries are read from the files they were
+                // loaded from, as `Library::map` only knows about this one.
+                #[allow(unused_mut)]
+                let mut mapping = Mapping::new(lib.name.as_ref());
+                #[cfg(feature = "disk-cache")]
+                {
+                    if let (Some(id), Some(mapping)) = (&lib.build_id, &mut mapping) {
+                        disk_cache::write(id, &mut mapping.cx);
+                    }
+                }
+                self.mappings.push((key, mapping));
+                self.mappings.len() - 1
+            }
+        };
+        if let Some(mapping) = &mut self.mappings[j].1 {
+            resolve_in(&mut mapping.cx, svma, &mut call);
+        }
+    }
+
+    /// Forgets the modules of the process `pid`, along with the mappings no
+    /// other process needs.
+    pub fn forget(&mut self, pid: u32) {
+        self.processes.retain(|(p, _)| *p != pid);
+        let processes = &self.processes;
+        self.mappings.retain(|(key, _)| {
+            processes
+                .iter()
+                .flat_map(|(_, libs)| libs)
+                .any(|lib| MappingKey::new(lib) == *key)
+        });
+        #[cfg(feature = "disk-cache")]
+        self.indexes.retain(|(id, _)| {
+            processes
+                .iter()
+                .flat_map(|(_, libs)| libs)
+                .any(|lib| lib.build_id.as_ref() == Some(id))
+        });
+    }
+}
+
+// unsafe because this is required to be externally synchronized
+#[cfg(feature = "std")]
+pub unsafe fn with_module(addr: *mut c_void, cb: &mut dyn FnMut(&super::ModuleRef<'_>)) {
+    Cache::with_global(|cache| {
+        let (lib, svma) = match cache.avma_to_svma(addr as *const u8) {
+            Some(pair) => pair,
+            None => return,
+        };
+        let lib = &cache.libraries[lib];
+        cb(&super::ModuleRef {
+            name: &lib.name,
+            build_id: lib.build_id.as_deref(),
+            offset: svma as usize,
+        });
+    });
+}
+
+// unsafe because this is required to be externally synchronized
+#[cfg(feature = "std")]
+pub unsafe fn function_bounds(addr: *mut c_void) -> Option<(*mut c_void, Option<usize>)> {
+    Cache::with_global(|cache| {
+        let (lib, svma) = cache.avma_to_svma(addr as *const u8)?;
+        // Addresses which the on-disk index covers are resolved without
+        // loading the object file, so don't load it just for their bounds.
+        #[cfg(feature = "disk-cache")]
+        {
+            let loaded = cache.mappings.iter().any(|(i, _)| *i == lib);
+            if !loaded
+                && cache
+                    .index_for_lib(lib)
+                    .map_or(false, |index| index.find(svma as u64).is_some())
+            {
+                return None;
+            }
+        }
+        let bias = cache.libraries[lib].bias;
+        let cx = cache.mapping_for_lib(lib)?;
+        let (start, size) = function_bounds_in(cx, svma as u64)?;
+        let start = (start as usize).wrapping_add(bias);
+        Some((start as *mut c_void, size.map(|size| size as usize)))
+    })
+}
+
+// Finds the function containing `addr`, which is relative to the object of
+// `cx`, returning where it starts and, if known, its size.
+#[cfg(feature = "std")]
+fn function_bounds_in(cx: &Context<'_>, addr: u64) -> Option<(u64, Option<u64>)> {
+    #[cfg(all(any(windows, target_os = "cygwin"), feature = "pdb"))]
+    {
+        if let Some((pdb, image_base)) = &cx.pdb {
+            if let Some((rva, len)) = pdb.function_bounds(addr.wrapping_sub(*image_base)) {
+                return Some((image_base + rva, Some(len)));
+            }
+        }
+    }
+    let (start, size, _name) = cx.object.search_symtab_entry(addr)?;
+    Some((start, size))
+}
+
+// unsafe because this is required to be externally synchronized
+#[cfg(feature = "std")]
+pub unsafe fn set_symbol_cache_capacity(modules: usize, bytes: Option<usize>) {
+    Cache::with_global(|cache| {
+        cache.max_mappings = modules;
+        cache.max_mapping_bytes = bytes;
+        cache.evict();
+    });
+}
+
+// unsafe because this is required to be externally synchronized
+#[cfg(feature = "std")]
+pub unsafe fn set_symbol_memory_limit(limit: Option<usize>) {
+    MEMORY_LIMIT.store(limit.map_or(0, |limit| limit.max(1)), Ordering::Relaxed);
+    Cache::with_global(|cache| cache.trim());
+}
+
+// unsafe because this is required to be externally synchronized
+#[cfg(feature = "std")]
+pub unsafe fn set_object_file_mmap(enabled: bool) {
+    READ_FILES.store(!enabled, Ordering::Relaxed);
+}
+
+// unsafe because this is required to be externally synchronized, and a no-op
+// since the search path is only used by dbghelp
+#[cfg(feature = "std")]
+pub unsafe fn set_symbol_search_path(_path: Option<&mystd::ffi::OsStr>) {}
+
+// unsafe because this is required to be externally synchronized, and a no-op
+// since symbol servers are only used by dbghelp
+#[cfg(feature = "std")]
+pub unsafe fn set_symbol_server_downloads(_enabled: bool) {}
+
+// unsafe because this is required to be externally synchronized
+#[cfg(feature = "std")]
+pub unsafe fn symbol_cache_usage() -> (usize, usize) {
+    Cache::with_global(|cache| (cache.mappings.len(), cache.mapping_bytes()))
+}
+
+// unsafe because this is required to be externally synchronized
+#[cfg(feature = "std")]
+pub unsafe fn prewarm_symbol_cache(modules: Option<&[&Path]>) -> usize {
+    // Nothing is kept between resolutions with a memory limit.
+    if memory_limit().is_some() {
+        return 0;
+    }
+    Cache::with_global(|cache| {
+        let mut libs = Vec::new();
+        match modules {
+            Some(paths) => {
+                for path in paths {
+                    let lib = cache
+                        .libraries
+                        .iter()
+                        .position(|lib| Path::new(&lib.name) == *path);
+                    libs.extend(lib);
+                }
+            }
+            None => libs.extend(0..cache.libraries.len()),
+        }
+        libs.truncate(cache.max_mappings.max(1));
+
+        // Load the last module first, so that the first one ends up as the
+        // most recently used and is evicted last.
+        for &lib in libs.iter().rev() {
+            let cx = match cache.mapping_for_lib(lib) {
+                Some(cx) => cx,
+                None => continue,
+            };
+            // `addr2line` parses line programs lazily, so walk all of them now
+            // as well.
+            if let Some(dwarf) = cx.dwarf.full() {
+                if let Ok(iter) = dwarf.find_location_range(0, !0) {
+                    iter.for_each(drop);
+                }
+            }
+        }
+        libs.iter()
+            .filter(|lib| cache.mappings.iter().any(|(i, _)| i == *lib))
+            .count()
+    })
+}
+
+// unsafe because this is required to be externally synchronized
+#[cfg(feature = "std")]
+pub unsafe fn find_symbol(name: &str) -> Option<(*mut c_void, std::path::PathBuf)> {
+    // Only demangle the symbols which could match, as most won't.
+    let last = name.rsplit("::").next().unwrap_or(name).as_bytes();
+    let contains_last = |raw: &[u8]| raw.windows(last.len().max(1)).any(|w| w == last);
+    Cache::with_global(|cache| {
+        for lib in 0..cache.libraries.len() {
+            let (bias, path) = {
+                let library = &cache.libraries[lib];
+                (library.bias, std::path::PathBuf::from(&library.name))
+            };
+            let cx = match cache.mapping_for_lib(lib) {
+                Some(cx) => cx,
+                None => continue,
+            };
+            let addr = cx.object.search_symtab_name(|raw| {
+                raw == name.as_bytes()
+                    || (contains_last(raw) && format!("{:#}", crate::SymbolName::new(raw)) == name)
+            });
+            if let Some(addr) = addr {
+                return Some(((addr as usize).wrapping_add(bias) as *mut c_void, path));
+            }
+        }
+        None
+    })
+}
+
+// unsafe because this is required to be externally synchronized
+#[cfg(feature = "std")]
+pub unsafe fn for_each_symbol(cb: &mut dyn FnMut(usize, usize, &[u8])) {
+    Cache::with_global(|cache| {
+        for lib in 0..cache.libraries.len() {
+            let bias = cache.libraries[lib].bias;
+            let cx = match cache.mapping_for_lib(lib) {
+                Some(cx) => cx,
+                None => continue,
+            };
+            let mut syms = cx
+                .object
+                .symbols()
+                .filter(|(_, _, name)| !name.is_empty())
+                .collect::<Vec<_>>();
+            syms.sort_unstable_by_key(|(addr, _, _)| *addr);
+            for (i, (addr, size, name)) in syms.iter().enumerate() {
+                // Symbols of formats without sizes are taken to extend up to
+                // the next one, and the last of them is left out as there's
+                // no telling where it ends.
+                let size = match size.or_else(|| Some(syms.get(i + 1)?.0 - addr)) {
+                    Some(size) if size > 0 => size,
+                    _ => continue,
+                };
+                cb((*addr as usize).wrapping_add(bias), size as usize, name);
+            }
+        }
+    })
+}
+
+// unsafe because this is required to be externally synchronized
+#[cfg(feature = "std")]
+pub unsafe fn evict_symbol_cache(addr: usize) -> bool {
+    Cache::with_global(|cache| {
+        let lib = match cache.avma_to_svma(addr as *const u8) {
+            Some((lib, _)) => lib,
+            None => return false,
+        };
+        let len = cache.mappings.len();
+        cache.mappings.retain(|(i, _)| *i != lib);
+        cache.mappings.len() != len
+    })
+}
+
+// unsafe because this is required to be externally synchronized
+#[cfg(feature = "std")]
+pub unsafe fn evict_symbol_cache_module(module: super::ModuleKey<'_>) -> bool {
+    Cache::with_global(|cache| {
+        let libraries = &cache.libraries;
+        let len = cache.mappings.len();
+        cache.mappings.retain(|(i, _)| {
+            let lib = &libraries[*i];
+            match module {
+                super::ModuleKey::Path(path) => Path::new(&lib.name) != path,
+                super::ModuleKey::BuildId(id) => lib.build_id.as_deref() != Some(id),
+            }
+        });
+        cache.mappings.len() != len
+    })
+}
+
+// unsafe because this is required to be externally synchronized
+#[cfg(feature = "std")]
+pub unsafe fn symbol_cache_stats() -> Vec<super::ModuleCacheStats> {
+    Cache::with_global(|cache| {
+        let mut libs = cache
+            .lookups
+            .iter()
+            .map(|(lib, _, _)| *lib)
+            .chain(cache.mappings.iter().map(|(lib, _)| *lib))
+            .collect::<Vec<_>>();
+        libs.sort_unstable();
+        libs.dedup();
+        libs.into_iter()
+            .map(|lib| {
+                let library = &cache.libraries[lib];
+                let (hits, misses) = cache
+                    .lookups
+                    .iter()
+                    .find(|(i, _, _)| *i == lib)
+                    .map_or((0, 0), |(_, hits, misses)| (*hits, *misses));
+                super::ModuleCacheStats {
+                    path: library.name.clone().into(),
+                    build_id: library.build_id.clone(),
+                    bytes: cache
+                        .mappings
+                        .iter()
+                        .find(|(i, _)| *i == lib)
+                        .map_or(0, |(_, mapping)| mapping.size()),
+                    hits,
+                    misses,
+                }
+            })
+            .collect()
+    })
+}
+
+// unsafe because this is required to be externally synchronized
+#[cfg(feature = "std")]
+pub unsafe fn register_module(base: usize, len: usize, source: super::ModuleSource) {
+    let (name, registered) = match source {
+        super::ModuleSource::Path(path) => (path.into_os_string(), Registered::Path),
+        super::ModuleSource::Bytes(data) => (OsString::new(), Registered::Bytes(data)),
+        super::ModuleSource::Static(data) => (OsString::new(), Registered::Static(data)),
+    };
+    Cache::with_global(|cache| {
+        cache.libraries.push(Library {
+            name,
+            segments: vec![LibrarySegment {
+                stated_virtual_memory_address: 0,
+                len,
+            }],
+            bias: base,
+            slide: base,
+            build_id: None,
+            registered: Some(registered),
+        });
+    });
+}
+
+// unsafe because this is required to be externally synchronized
+#[cfg(feature = "std")]
+pub unsafe fn unregister_module(base: usize) -> bool {
+    Cache::with_global(|cache| {
+        let lib = match cache
+            .libraries
+            .iter()
+            .position(|lib| lib.registered.is_some() && lib.bias == base)
+        {
+            Some(lib) => lib,
+            None => return false,
+        };
+        cache.remove_library(lib);
+        true
+    })
+}
+
 // unsafe because this is required to be externally synchronized
 pub unsafe fn clear_symbol_cache() {
-    Cache::with_global(|cache| cache.mappings.clear());
+    Cache::with_global(|cache| {
+        cache.mappings.clear();
+        #[cfg(feature = "disk-cache")]
+        cache.indexes.clear();
+    });
 }
 
 impl Cache {
     fn new() -> Cache {
         Cache {
+            generation: loader_generation(),
             mappings: Vec::with_capacity(MAPPINGS_CACHE_SIZE),
+            max_mappings: MAPPINGS_CACHE_SIZE,
+            max_mapping_bytes: None,
+            lookups: Vec::new(),
             libraries: native_libraries(),
+            #[cfg(all(feature = "perf-map", unix))]
+            perf_map: Default::default(),
+            #[cfg(all(feature = "kallsyms", any(target_os = "linux", target_os = "android")))]
+            kallsyms: Default::default(),
+            #[cfg(feature = "disk-cache")]
+            indexes: Vec::new(),
         }
     }
 
     // unsafe because this is required to be externally synchronized
-    unsafe fn with_global(f: impl FnOnce(&mut Self)) {
+    unsafe fn with_global<R>(f: impl FnOnce(&mut Self) -> R) -> R {
         // A very small, very simple LRU cache for debug info mappings.
         //
         // The hit rate should be very high, since the typical stack doesn't cross
@@ -263,41 +1245,184 @@ impl Cache {
         // never happen, and symbolicating backtraces would be ssssllllooooowwww.
         static mut MAPPINGS_CACHE: Option<Cache> = None;
 
-        f(MAPPINGS_CACHE.get_or_insert_with(|| Cache::new()))
+        let cache = MAPPINGS_CACHE.get_or_insert_with(|| Cache::new());
+        if cache.refresh_libraries() {
+            #[cfg(feature = "std")]
+            crate::modules::modules_changed();
+        }
+        f(cache)
+    }
+
+    /// Brings `libraries` up to date if objects have been loaded or unloaded
+    /// since it was last looked at. The mappings of libraries which are still
+    /// loaded are kept, only those of unloaded ones are dropped. Returns
+    /// whether anything was loaded or unloaded.
+    fn refresh_libraries(&mut self) -> bool {
+        let generation = loader_generation();
+        if generation.is_none() || generation == self.generation {
+            return false;
+        }
+        self.generation = generation;
+
+        let mut loaded = native_libraries();
+        for i in (0..self.libraries.len()).rev() {
+            // Registered libraries aren't known to the loader.
+            let lib = &self.libraries[i];
+            if lib.registered.is_some() {
+                continue;
+            }
+            match loaded
+                .iter()
+                .position(|l| l.name == lib.name && l.bias == lib.bias)
+            {
+                Some(j) => {
+                    loaded.remove(j);
+                }
+                None => self.remove_library(i),
+            }
+        }
+        // Appending keeps the indices `mappings` refers to the same.
+        self.libraries.extend(loaded);
+        true
     }
 
     fn avma_to_svma(&self, addr: *const u8) -> Option<(usize, *const u8)> {
-        self.libraries
-            .iter()
-            .enumerate()
-            .filter_map(|(i, lib)| {
-                // First up, test if this `lib` has any segment containing the
-                // `addr` (handling relocation). If this check passes then we
-                // can continue below and actually translate the address.
-                //
-                // Note that we're using `wrapping_add` here to avoid overflow
-                // checks. It's been seen in the wild that the SVMA + bias
-                // computation overflows. It seems a bit odd that would happen
-                // but there's not a huge amount we can do about it other than
-                // probably just ignore those segments since they're likely
-                // pointing off into space. This originally came up in
-                // rust-lang/backtrace-rs#329.
-                if !lib.segments.iter().any(|s| {
-                    let svma = s.stated_virtual_memory_address;
-                    let start = svma.wrapping_add(lib.bias);
-                    let end = start.wrapping_add(s.len);
-                    let address = addr as usize;
-                    start <= address && address < end
-                }) {
-                    return None;
+        avma_to_svma(&self.libraries, addr)
+    }
+
+    fn remove_library(&mut self, lib: usize) {
+        self.libraries.remove(lib);
+        // Mappings refer to libraries by index, so drop the one of the removed
+        // library and shift the ones after it.
+        self.mappings.retain(|(i, _)| *i != lib);
+        for (i, _) in self.mappings.iter_mut() {
+            if *i > lib {
+                *i -= 1;
+            }
+        }
+        self.lookups.retain(|(i, _, _)| *i != lib);
+        for (i, _, _) in self.lookups.iter_mut() {
+            if *i > lib {
+                *i -= 1;
+            }
+        }
+        #[cfg(feature = "disk-cache")]
+        {
+            self.indexes.retain(|(i, _)| *i != lib);
+            for (i, _) in self.indexes.iter_mut() {
+                if *i > lib {
+                    *i -= 1;
                 }
+            }
+        }
+    }
 
-                // Now that we know `lib` contains `addr`, we can offset with
-                // the bias to find the stated virtual memory address.
-                let svma = (addr as usize).wrapping_sub(lib.bias);
-                Some((i, svma as *const u8))
-            })
-            .next()
+    /// Returns the on-disk index of the library at index `lib`, if it has one.
+    #[cfg(feature = "disk-cache")]
+    fn index_for_lib(&mut self, lib: usize) -> Option<&disk_cache::Index> {
+        let i = match self.indexes.iter().position(|(i, _)| *i == lib) {
+            Some(i) => i,
+            None => {
+                let index = self.libraries[lib]
+                    .build_id
+                    .as_ref()
+                    .and_then(|id| disk_cache::Index::open(id));
+                self.indexes.push((lib, index));
+                self.indexes.len() - 1
+            }
+        };
+        self.indexes[i].1.as_ref()
+    }
+
+    /// Like `avma_to_svma`, but if `addr` isn't in any known library also
+    /// checks whether it's in code a JIT compiler has registered since.
+    fn find_library(&mut self, addr: *const u8) -> Option<(usize, *const u8)> {
+        if let Some(pair) = self.avma_to_svma(addr) {
+            return Some(pair);
+        }
+        cfg_if::cfg_if! {
+            if #[cfg(any(
+                target_os = "linux",
+                target_os = "android",
+                target_os = "freebsd",
+            ))] {
+                unsafe { jit::refresh(self) };
+                self.avma_to_svma(addr)
+            } else {
+                None
+            }
+        }
+    }
+
+    /// Drops the least recently used mappings until the cache is within its
+    /// configured capacity again.
+    fn evict(&mut self) {
+        while self.mappings.len() > self.max_mappings.max(1) {
+            if let Some((lib, _)) = self.mappings.pop() {
+                self.evicted(lib);
+            }
+        }
+        let max_bytes = match (self.max_mapping_bytes, memory_limit()) {
+            (Some(max), Some(limit)) => Some(max.min(limit)),
+            (max, limit) => max.or(limit),
+        };
+        if let Some(max) = max_bytes {
+            let mut bytes = self.mapping_bytes();
+            while bytes > max && self.mappings.len() > 1 {
+                if let Some((lib, mapping)) = self.mappings.pop() {
+                    bytes -= mapping.size();
+                    self.evicted(lib);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn evicted(&self, lib: usize) {
+        let name: &Path = self.libraries[lib].name.as_ref();
+        diagnostics::record_in(name, DiagnosticKind::Evicted);
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn evicted(&self, _lib: usize) {}
+
+    /// With a memory limit set, drops everything that was loaded to resolve
+    /// addresses once they're resolved, so that nothing is held on to between
+    /// resolutions.
+    fn trim(&mut self) {
+        if memory_limit().is_some() {
+            self.mappings.clear();
+            #[cfg(feature = "disk-cache")]
+            self.indexes.clear();
+        }
+    }
+
+    /// Inserts `mapping` for `lib` at the front of the cache, evicting the
+    /// oldest entries if necessary.
+    fn insert_mapping(&mut self, lib: usize, mapping: Mapping) {
+        self.lookups_for_lib(lib).2 += 1;
+        self.mappings.insert(0, (lib, mapping));
+        self.evict();
+    }
+
+    fn mapping_bytes(&self) -> usize {
+        self.mappings
+            .iter()
+            .map(|(_, mapping)| mapping.size())
+            .sum()
+    }
+
+    /// Returns the counts of cache hits and misses for the library at index
+    /// `lib`.
+    fn lookups_for_lib(&mut self, lib: usize) -> &mut (usize, u64, u64) {
+        let i = match self.lookups.iter().position(|(i, _, _)| *i == lib) {
+            Some(i) => i,
+            None => {
+                self.lookups.push((lib, 0, 0));
+                self.lookups.len() - 1
+            }
+        };
+        &mut self.lookups[i]
     }
 
     fn mapping_for_lib<'a>(&'a mut self, lib: usize) -> Option<&'a mut Context<'a>> {
@@ -307,6 +1432,7 @@ impl Cache {
         // from an error, the cache entry for this path is at index 0.
 
         if let Some(idx) = idx {
+            self.lookups_for_lib(lib).1 += 1;
             // When the mapping is already in the cache, move it to the front.
             if idx != 0 {
                 let entry = self.mappings.remove(idx);
@@ -316,14 +1442,8 @@ impl Cache {
             // When the mapping is not in the cache, create a new mapping,
             // insert it into the front of the cache, and evict the oldest cache
             // entry if necessary.
-            let name = &self.libraries[lib].name;
-            let mapping = Mapping::new(name.as_ref())?;
-
-            if self.mappings.len() == MAPPINGS_CACHE_SIZE {
-                self.mappings.pop();
-            }
-
-            self.mappings.insert(0, (lib, mapping));
+            let mapping = self.libraries[lib].load()?;
+            self.insert_mapping(lib, mapping);
         }
 
         let cx: &'a mut Context<'static> = &mut self.mappings[0].1.cx;
@@ -333,6 +1453,74 @@ impl Cache {
     }
 }
 
+/// A cache with every loaded library mapped up front, used by the
+/// `signal_safe` module. Once published this is only ever read from, and
+/// `prepare_signal_safe` leaks the cache it replaces since a signal handler
+/// may still be reading it.
+#[cfg(all(feature = "std", unix))]
+static SIGNAL_SAFE_CACHE: core::sync::atomic::AtomicPtr<Cache> =
+    core::sync::atomic::AtomicPtr::new(core::ptr::null_mut());
+
+// unsafe because this is required to be externally synchronized
+#[cfg(all(feature = "std", unix))]
+pub unsafe fn prepare_signal_safe() -> bool {
+    let mut cache = Cache::new();
+    cache.mappings = Vec::with_capacity(cache.libraries.len());
+    for (i, lib) in cache.libraries.iter().enumerate() {
+        let mut mapping = match lib.map() {
+            Some(mapping) => mapping,
+            None => continue,
+        };
+        // `addr2line` parses line programs lazily, so walk all of them now to
+        // make sure that lookups later on only read already-parsed tables.
+        if let Some(dwarf) = mapping.cx.dwarf.full() {
+            if let Ok(iter) = dwarf.find_location_range(0, !0) {
+                iter.for_each(drop);
+            }
+        }
+        cache.mappings.push((i, mapping));
+    }
+    SIGNAL_SAFE_CACHE.store(Box::into_raw(Box::new(cache)), Ordering::SeqCst);
+    true
+}
+
+// Note that this must not allocate, so unlike `resolve` this doesn't look at
+// inline frames and doesn't touch the LRU ordering of the cache.
+#[cfg(all(feature = "std", unix))]
+pub unsafe fn resolve_signal_safe(what: ResolveWhat<'_>, cb: &mut dyn FnMut(&super::Symbol)) {
+    let cache = match SIGNAL_SAFE_CACHE.load(Ordering::SeqCst).as_ref() {
+        Some(cache) => cache,
+        None => return,
+    };
+    let addr = what.address_or_ip();
+    let (lib, addr) = match cache.avma_to_svma(addr as *const u8) {
+        Some(pair) => pair,
+        None => return,
+    };
+    let cx = match cache.mappings.iter().find(|(i, _)| *i == lib) {
+        Some((_, mapping)) => &mapping.cx,
+        None => return,
+    };
+    let name = cx.object.search_symtab(addr as u64);
+    let location = cx
+        .dwarf
+        .get(addr as u64)
+        .and_then(|dwarf| dwarf.find_location(addr as u64).ok()?);
+    if name.is_none() && location.is_none() {
+        return;
+    }
+    let sym = Symbol::Frame {
+        addr: addr as *mut c_void,
+        location,
+        name,
+        inline_depth: 0,
+        language: None,
+        comp_dir: None,
+    };
+    let sym = mem::transmute::<Symbol<'_>, Symbol<'static>>(sym);
+    (cb)(&super::Symbol::from_imp(sym));
+}
+
 pub unsafe fn resolve(what: ResolveWhat<'_>, cb: &mut dyn FnMut(&super::Symbol)) {
     let addr = what.address_or_ip();
     let mut call = |sym: Symbol<'_>| {
@@ -340,59 +1528,356 @@ pub unsafe fn resolve(what: ResolveWhat<'_>, cb: &mut dyn FnMut(&super::Symbol))
         // required to here, but it's only ever going out as a reference so no
         // reference to it should be persisted beyond this frame anyway.
         let sym = mem::transmute::<Symbol<'_>, Symbol<'static>>(sym);
-        (cb)(&super::Symbol { inner: sym });
+        (cb)(&super::Symbol::from_imp(sym));
     };
 
     Cache::with_global(|cache| {
-        let (lib, addr) = match cache.avma_to_svma(addr as *const u8) {
+        cache.resolve(addr, &mut call);
+        cache.trim();
+    });
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn resolve_many(what: &[ResolveWhat<'_>], cb: &mut dyn FnMut(usize, &super::Symbol)) {
+    Cache::with_global(|cache| {
+        // Group the addresses by library and sort them within each, so that
+        // every mapping is only looked up once and its units are visited in
+        // order.
+        let mut order = what
+            .iter()
+            .enumerate()
+            .map(|(i, what)| {
+                let addr = what.address_or_ip();
+                let lib = cache.avma_to_svma(addr as *const u8).map(|(lib, _)| lib);
+                (lib, addr as usize, i)
+            })
+            .collect::<Vec<_>>();
+        order.sort_unstable();
+
+        // Creating the mappings is what takes the longest when a trace passes
+        // through many libraries for the first time, so do that for all of
+        // them at once.
+        #[cfg(feature = "parallel")]
+        let mut loaded = cache.load_mappings(&order);
+
+        for (_, addr, i) in order {
+            #[cfg(feature = "parallel")]
+            {
+                if let Some((lib, _)) = cache.avma_to_svma(addr as *const u8) {
+                    let library = &cache.libraries[lib];
+                    let found = loaded
+                        .iter()
+                        .position(|(l, _)| l.bias == library.bias && l.name == library.name);
+                    if let Some(j) = found {
+                        let (_, mapping) = loaded.swap_remove(j);
+                        cache.insert_mapping(lib, mapping);
+                    }
+                }
+            }
+            cache.resolve(addr as *mut c_void, &mut |sym| {
+                // See `resolve` for why this is fine.
+                let sym = mem::transmute::<Symbol<'_>, Symbol<'static>>(sym);
+                cb(i, &super::Symbol::from_imp(sym));
+            });
+        }
+        cache.trim();
+    });
+}
+
+impl Cache {
+    /// Creates the mappings of the libraries in `order` which aren't cached
+    /// yet, several at a time.
+    ///
+    /// The mappings are returned together with the library they're for
+    /// rather than its index, as looking up JIT code while resolving may
+    /// change the list of libraries.
+    #[cfg(feature = "parallel")]
+    fn load_mappings(
+        &mut self,
+        order: &[(Option<usize>, usize, usize)],
+    ) -> Vec<(Library, Mapping)> {
+        let mut libs = Vec::<usize>::new();
+        for &(lib, _, _) in order {
+            let lib = match lib {
+                Some(lib) => lib,
+                None => continue,
+            };
+            if libs.contains(&lib) || self.mappings.iter().any(|(i, _)| *i == lib) {
+                continue;
+            }
+            #[cfg(feature = "disk-cache")]
+            {
+                if self.index_for_lib(lib).is_some() {
+                    continue;
+                }
+            }
+            libs.push(lib);
+        }
+        // There's nothing to gain from other threads for a single library,
+        // and holding on to all of them at once may not fit a memory limit.
+        if libs.len() < 2 || memory_limit().is_some() {
+            return Vec::new();
+        }
+        let libs = libs
+            .iter()
+            .map(|&lib| self.libraries[lib].clone())
+            .collect();
+        parallel::load(libs)
+    }
+
+    /// Resolves `addr`, an address in this process, calling `call` with each
+    /// symbol found.
+    fn resolve(&mut self, addr: *mut c_void, call: &mut dyn FnMut(Symbol<'_>)) {
+        let (lib, addr) = match self.find_library(addr as *const u8) {
             Some(pair) => pair,
-            None => return,
+            None => {
+                #[cfg(all(feature = "perf-map", unix))]
+                {
+                    if let Some(name) = self.perf_map.lookup(addr as usize) {
+                        call(Symbol::Symtab { addr, name });
+                        return;
+                    }
+                }
+                #[cfg(all(feature = "kallsyms", any(target_os = "linux", target_os = "android")))]
+                {
+                    if let Some(name) = self.kallsyms.lookup(addr as usize) {
+                        call(Symbol::Symtab { addr, name });
+                    }
+                }
+                return;
+            }
         };
 
+        #[cfg(feature = "disk-cache")]
+        {
+            if let Some(index) = self.index_for_lib(lib) {
+                if let Some(frames) = index.find(addr as u64) {
+                    let count = frames.len();
+                    for (i, frame) in frames.into_iter().enumerate() {
+                        call(Symbol::Frame {
+                            addr: addr as *mut c_void,
+                            location: frame.location,
+                            name: frame.name,
+                            inline_depth: (count - 1 - i) as u32,
+                            language: None,
+                            comp_dir: None,
+                        });
+                    }
+                    return;
+                }
+            }
+        }
+
         // Finally, get a cached mapping or create a new mapping for this file, and
         // evaluate the DWARF info to find the file/line/name for this address.
-        let cx = match cache.mapping_for_lib(lib) {
+        let cx = match self.mapping_for_lib(lib) {
             Some(cx) => cx,
             None => return,
         };
-        let mut any_frames = false;
-        if let Ok(mut frames) = cx.dwarf.find_frames(addr as u64) {
-            while let Ok(Some(frame)) = frames.next() {
-                any_frames = true;
-                let name = match frame.function {
-                    Some(f) => Some(f.name.slice()),
-                    None => cx.object.search_symtab(addr as u64),
+        resolve_in(cx, addr, call);
+    }
+}
+
+/// Finds the library in `libraries` which contains `addr`, returning its index
+/// and the address translated to the library's stated addresses.
+fn avma_to_svma(libraries: &[Library], addr: *const u8) -> Option<(usize, *const u8)> {
+    libraries
+        .iter()
+        .enumerate()
+        .filter_map(|(i, lib)| {
+            // First up, test if this `lib` has any segment containing the
+            // `addr` (handling relocation). If this check passes then we
+            // can continue below and actually translate the address.
+            //
+            // Note that we're using `wrapping_add` here to avoid overflow
+            // checks. It's been seen in the wild that the SVMA + bias
+            // computation overflows. It seems a bit odd that would happen
+            // but there's not a huge amount we can do about it other than
+            // probably just ignore those segments since they're likely
+            // pointing off into space. This originally came up in
+            // rust-lang/backtrace-rs#329.
+            if !lib.segments.iter().any(|s| {
+                let svma = s.stated_virtual_memory_address;
+                let start = svma.wrapping_add(lib.bias);
+                let end = start.wrapping_add(s.len);
+                let address = addr as usize;
+                start <= address && address < end
+            }) {
+                return None;
+            }
+
+            // Now that we know `lib` contains `addr`, we can offset with
+            // the bias to find the stated virtual memory address.
+            let svma = (addr as usize).wrapping_sub(lib.bias);
+            Some((i, svma as *const u8))
+        })
+        // Registered libraries take precedence over what the loader
+        // reports for the same addresses, as their object files are what's
+        // actually in memory, like for packed executables.
+        .min_by_key(|(i, _)| libraries[*i].registered.is_none())
+}
+
+// Resolves `addr`, which is relative to the object of `cx`, calling `call`
+// with each symbol found.
+fn resolve_in(cx: &mut Context<'_>, addr: *const u8, call: &mut dyn FnMut(Symbol<'_>)) {
+    #[cfg(all(any(windows, target_os = "cygwin"), feature = "pdb"))]
+    {
+        if let Some((pdb, image_base)) = &cx.pdb {
+            let rva = (addr as u64).wrapping_sub(*image_base);
+            if pdb.resolve(addr as *mut c_void, rva, call) {
+                return;
+            }
+        }
+    }
+    let mut any_frames = false;
+    cx.dwarf.load(addr as u64);
+    if let Some(dwarf) = cx.dwarf.get(addr as u64) {
+        cx.split.load(dwarf, addr as u64);
+        if let Some(frames) = cx.split.find_frames(dwarf, addr as u64) {
+            let count = frames.len();
+            for (i, (name, location)) in frames.into_iter().enumerate() {
+                let name = name.or_else(|| cx.object.search_symtab(addr as u64));
+                let location = if any_frames {
+                    location
+                } else {
+                    await_location(dwarf, addr, name, location)
                 };
+                any_frames = true;
                 call(Symbol::Frame {
                     addr: addr as *mut c_void,
-                    location: frame.location,
+                    location,
                     name,
+                    inline_depth: (count - 1 - i) as u32,
+                    language: None,
+                    comp_dir: None,
                 });
             }
+            return;
         }
-        if !any_frames {
-            if let Some((object_cx, object_addr)) = cx.object.search_object_map(addr as u64) {
-                if let Ok(mut frames) = object_cx.dwarf.find_frames(object_addr) {
-                    while let Ok(Some(frame)) = frames.next() {
-                        any_frames = true;
-                        call(Symbol::Frame {
-                            addr: addr as *mut c_void,
-                            location: frame.location,
-                            name: frame.function.map(|f| f.name.slice()),
-                        });
-                    }
-                }
+        if let Ok(mut iter) = dwarf.find_frames(addr as u64) {
+            // Outer frames come last, so collect them all to know how deeply
+            // each one is inlined.
+            let mut frames = Vec::new();
+            while let Ok(Some(frame)) = iter.next() {
+                frames.push(frame);
             }
-        }
-        if !any_frames {
-            if let Some(name) = cx.object.search_symtab(addr as u64) {
-                call(Symbol::Symtab {
+            let count = frames.len();
+            let comp_dir = comp_dir(dwarf, &frames, addr as u64);
+            for (i, frame) in frames.into_iter().enumerate() {
+                let (name, language) = match frame.function {
+                    Some(f) => (Some(f.name.slice()), f.language),
+                    None => (cx.object.search_symtab(addr as u64), None),
+                };
+                let location = if any_frames {
+                    frame.location
+                } else {
+                    await_location(dwarf, addr, name, frame.location)
+                };
+                any_frames = true;
+                call(Symbol::Frame {
                     addr: addr as *mut c_void,
+                    location,
                     name,
+                    inline_depth: (count - 1 - i) as u32,
+                    language,
+                    comp_dir,
                 });
             }
         }
-    });
+    }
+    if !any_frames {
+        if let Some((object_cx, object_addr)) = cx.object.search_object_map(addr as u64) {
+            let object_dwarf = object_cx.dwarf.get(object_addr);
+            let frames = object_dwarf.map(|d| d.find_frames(object_addr));
+            if let (Some(object_dwarf), Some(Ok(mut iter))) = (object_dwarf, frames) {
+                let mut frames = Vec::new();
+                while let Ok(Some(frame)) = iter.next() {
+                    frames.push(frame);
+                }
+                let count = frames.len();
+                let comp_dir = comp_dir(object_dwarf, &frames, object_addr);
+                for (i, frame) in frames.into_iter().enumerate() {
+                    any_frames = true;
+                    let language = frame.function.as_ref().and_then(|f| f.language);
+                    call(Symbol::Frame {
+                        addr: addr as *mut c_void,
+                        location: frame.location,
+                        name: frame.function.map(|f| f.name.slice()),
+                        inline_depth: (count - 1 - i) as u32,
+                        language,
+                        comp_dir,
+                    });
+                }
+            }
+        }
+    }
+    if !any_frames {
+        if let Some(name) = cx.object.search_symtab(addr as u64) {
+            call(Symbol::Symtab {
+                addr: addr as *mut c_void,
+                name,
+            });
+        }
+    }
+}
+
+/// Returns the compilation directory (`DW_AT_comp_dir`) of the unit containing
+/// `probe`, which the file names of the locations of its `frames` are joined
+/// to. It's only looked up if any of them has a file name.
+fn comp_dir<'data>(
+    dwarf: &addr2line::Context<EndianSlice<'data, Endian>>,
+    frames: &[addr2line::Frame<'_, EndianSlice<'data, Endian>>],
+    probe: u64,
+) -> Option<&'data [u8]> {
+    if !frames
+        .iter()
+        .any(|f| matches!(&f.location, Some(l) if l.file.is_some()))
+    {
+        return None;
+    }
+    Some(dwarf.find_dwarf_unit(probe)?.comp_dir?.slice())
+}
+
+// The innermost frame of a suspended async state machine is frequently
+// attributed to line 0, since the code which saves state around an `.await`
+// has no source location of its own. The return address (before our
+// `adjust_ip`) typically maps to the `.await` expression though, so prefer
+// that instead. Line 0 in other functions is left alone, as is a return
+// address which is already in another function.
+fn await_location<'a>(
+    dwarf: &'a addr2line::Context<EndianSlice<'_, Endian>>,
+    addr: *const u8,
+    name: Option<&[u8]>,
+    location: Option<addr2line::Location<'a>>,
+) -> Option<addr2line::Location<'a>> {
+    choose_await_location(name, location, || {
+        let frame = dwarf.find_frames(addr as u64 + 1).ok()?.next().ok()??;
+        Some((frame.function.map(|f| f.name.slice()), frame.location))
+    })
+}
+
+// Picks the location of `await_location`, where `next` looks up the innermost
+// function and location of the return address.
+fn choose_await_location<'a, 'n>(
+    name: Option<&[u8]>,
+    location: Option<addr2line::Location<'a>>,
+    next: impl FnOnce() -> Option<(Option<&'n [u8]>, Option<addr2line::Location<'a>>)>,
+) -> Option<addr2line::Location<'a>> {
+    let name = match (name, &location) {
+        (Some(name), Some(addr2line::Location { line: Some(0), .. }))
+            if SymbolName::new(name).is_async_poll() =>
+        {
+            name
+        }
+        _ => return location,
+    };
+    match next() {
+        Some((Some(next_name), Some(next))) if next_name == name => match next.line {
+            Some(0) | None => location,
+            Some(_) => Some(next),
+        },
+        _ => location,
+    }
 }
 
 pub enum Symbol<'a> {
@@ -402,6 +1887,14 @@ pub enum Symbol<'a> {
         addr: *mut c_void,
         location: Option<addr2line::Location<'a>>,
         name: Option<&'a [u8]>,
+        /// How deeply this frame is inlined into the function containing the
+        /// code, which itself is 0.
+        inline_depth: u32,
+        /// The language of the compilation unit containing the function.
+        language: Option<gimli::DwLang>,
+        /// The compilation directory of the unit, which the file name of
+        /// `location` is relative to unless it's absolute.
+        comp_dir: Option<&'a [u8]>,
     },
     /// Couldn't find debug information, but we found it in the symbol table of
     /// the elf executable.
@@ -459,4 +1952,87 @@ impl Symbol<'_> {
             Symbol::Symtab { .. } => None,
         }
     }
+
+    pub fn inline_depth(&self) -> Option<u32> {
+        match self {
+            Symbol::Frame { inline_depth, .. } => Some(*inline_depth),
+            Symbol::Symtab { .. } => Some(0),
+        }
+    }
+
+    pub fn language(&self) -> Option<u16> {
+        match self {
+            Symbol::Frame { language, .. } => language.map(|l| l.0),
+            Symbol::Symtab { .. } => None,
+        }
+    }
+
+    pub fn comp_dir(&self) -> Option<&[u8]> {
+        match self {
+            Symbol::Frame { comp_dir, .. } => *comp_dir,
+            Symbol::Symtab { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::choose_await_location;
+
+    fn location(line: u32) -> Option<addr2line::Location<'static>> {
+        Some(addr2line::Location {
+            file: Some("src/main.rs"),
+            line: Some(line),
+            column: None,
+        })
+    }
+
+    fn line(location: Option<addr2line::Location<'_>>) -> Option<u32> {
+        location.and_then(|l| l.line)
+    }
+
+    const ASYNC_FN: &[u8] = b"app::run::{async_fn#0}";
+
+    #[test]
+    fn await_location_of_async_frames() {
+        let next = || Some((Some(ASYNC_FN), location(12)));
+        assert_eq!(
+            line(choose_await_location(Some(ASYNC_FN), location(0), next)),
+            Some(12)
+        );
+
+        // Only line 0 is replaced.
+        let next = || Some((Some(ASYNC_FN), location(12)));
+        assert_eq!(
+            line(choose_await_location(Some(ASYNC_FN), location(7), next)),
+            Some(7)
+        );
+
+        // The return address is in another function.
+        let next = || Some((Some(&b"app::other"[..]), location(12)));
+        assert_eq!(
+            line(choose_await_location(Some(ASYNC_FN), location(0), next)),
+            Some(0)
+        );
+        let next = || None;
+        assert_eq!(
+            line(choose_await_location(Some(ASYNC_FN), location(0), next)),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn await_location_of_other_frames() {
+        let name = &b"app::compute"[..];
+        let next = || -> Option<(Option<&[u8]>, _)> { panic!("looked up {:?}", name) };
+        assert_eq!(
+            line(choose_await_location(Some(name), location(0), next)),
+            Some(0)
+        );
+        let next = || -> Option<(Option<&[u8]>, _)> { panic!("looked up a nameless frame") };
+        assert_eq!(
+            line(choose_await_location(None, location(0), next)),
+            Some(0)
+        );
+    }
 }
@@ -1,5 +1,13 @@
-use super::{Context, Mapping, Path, Stash, Vec};
+#[cfg(feature = "pdb")]
+use super::pdb::Pdb;
+use super::{Context, MapData, Mapping, Path, Stash, Vec};
 use core::convert::TryFrom;
+#[cfg(feature = "pdb")]
+use core::convert::TryInto;
+#[cfg(feature = "pdb")]
+use core::{mem, str};
+#[cfg(feature = "pdb")]
+use object::pe::{ImageDebugDirectory, IMAGE_DEBUG_TYPE_CODEVIEW, IMAGE_DIRECTORY_ENTRY_DEBUG};
 use object::pe::{ImageDosHeader, ImageSymbol};
 use object::read::pe::{ImageNtHeaders, ImageOptionalHeader, SectionTable};
 use object::read::StringTable;
@@ -12,13 +20,85 @@ type Pe = object::pe::ImageNtHeaders64;
 
 impl Mapping {
     pub fn new(path: &Path) -> Option<Mapping> {
-        let map = super::mmap(path)?;
+        let map = super::open(path)?.map()?;
         Mapping::mk(map, |data, stash| {
+            #[allow(unused_mut)]
+            let mut cx = Context::new(stash, Object::parse(data)?, None)?;
+            #[cfg(feature = "pdb")]
+            {
+                cx.pdb = load_pdb(path, data, stash);
+            }
+            Some(cx)
+        })
+    }
+
+    /// Creates a mapping from the contents of an object file.
+    pub fn from_bytes(data: impl Into<MapData>) -> Option<Mapping> {
+        Mapping::mk(data, |data, stash| {
             Context::new(stash, Object::parse(data)?, None)
         })
     }
 }
 
+/// Loads the PDB file of the image in `data`, which was loaded from `path`,
+/// along with the image's base address.
+///
+/// The PDB is looked for at the path the linker recorded in the image, and
+/// then next to the image, since that's where it's usually shipped. It's only
+/// used if it's the one the image was linked with.
+#[cfg(feature = "pdb")]
+fn load_pdb<'a>(path: &Path, data: &'a [u8], stash: &'a Stash) -> Option<(Pdb<'a>, u64)> {
+    let dos_header = ImageDosHeader::parse(data).ok()?;
+    let mut offset = dos_header.nt_headers_offset().into();
+    let (nt_headers, data_directories) = Pe::parse(data, &mut offset).ok()?;
+    let sections = nt_headers.sections(data, offset).ok()?;
+    let image_base = nt_headers.optional_header().image_base();
+
+    // The CodeView entry of the debug directory records the PDB's path,
+    // GUID and age.
+    let debug = data_directories
+        .get(IMAGE_DIRECTORY_ENTRY_DEBUG)?
+        .data(data, &sections)
+        .ok()?;
+    let count = debug.len() / mem::size_of::<ImageDebugDirectory>();
+    let (entries, _) = object::pod::slice_from_bytes::<ImageDebugDirectory>(debug, count).ok()?;
+    let entry = entries
+        .iter()
+        .find(|e| e.typ.get(LE) == IMAGE_DEBUG_TYPE_CODEVIEW)?;
+    let start = usize::try_from(entry.pointer_to_raw_data.get(LE)).ok()?;
+    let len = usize::try_from(entry.size_of_data.get(LE)).ok()?;
+    let info = data.get(start..start.checked_add(len)?)?;
+    if info.get(..4)? != b"RSDS" {
+        return None;
+    }
+    let guid = info.get(4..20)?;
+    let age = u32::from_le_bytes(info.get(20..24)?.try_into().ok()?);
+    let pdb_path = info.get(24..)?.split(|b| *b == 0).next()?;
+    let pdb_path = Path::new(str::from_utf8(pdb_path).ok()?);
+
+    let candidates = [
+        pdb_path.to_path_buf(),
+        path.with_file_name(pdb_path.file_name()?),
+    ];
+    for candidate in candidates.iter() {
+        let map = match super::open(candidate).and_then(|source| source.map()) {
+            Some(map) => map,
+            None => continue,
+        };
+        if !super::pdb::is_pdb(&map) {
+            continue;
+        }
+        let pdb = match Pdb::parse(stash.cache_mmap(map), stash) {
+            Some(pdb) => pdb,
+            None => continue,
+        };
+        if pdb.guid[..] == *guid && pdb.age == age {
+            return Some((pdb, image_base));
+        }
+    }
+    None
+}
+
 pub struct Object<'a> {
     data: &'a [u8],
     sections: SectionTable<'a>,
@@ -85,6 +165,12 @@ impl<'a> Object<'a> {
     }
 
     pub fn search_symtab<'b>(&'b self, addr: u64) -> Option<&'b [u8]> {
+        self.search_symtab_entry(addr).map(|(_, _, name)| name)
+    }
+
+    /// Like `search_symtab`, but also returns the address the symbol starts
+    /// at and, if known, its size.
+    pub fn search_symtab_entry(&self, addr: u64) -> Option<(u64, Option<u64>, &[u8])> {
         // Note that unlike other formats COFF doesn't embed the size of
         // each symbol. As a last ditch effort search for the *closest*
         // symbol to a particular address and return that one. This gets
@@ -99,7 +185,27 @@ impl<'a> Object<'a> {
             // greatest less than `addr`
             Err(i) => i.checked_sub(1)?,
         };
-        self.symbols[i].1.name(self.strings).ok()
+        let (start, sym) = &self.symbols[i];
+        Some((*start as u64, None, sym.name(self.strings).ok()?))
+    }
+
+    /// Returns every symbol in the symbol table, as its address, its size if
+    /// known and its name.
+    pub fn symbols(&self) -> impl Iterator<Item = (u64, Option<u64>, &[u8])> + '_ {
+        self.symbols
+            .iter()
+            .filter_map(move |(addr, sym)| Some((*addr as u64, None, sym.name(self.strings).ok()?)))
+    }
+
+    /// Returns the address of the first symbol whose name `matches`.
+    pub fn search_symtab_name(&self, mut matches: impl FnMut(&[u8]) -> bool) -> Option<u64> {
+        self.symbols
+            .iter()
+            .find(|(_, sym)| match sym.name(self.strings) {
+                Ok(name) => matches(name),
+                Err(_) => false,
+            })
+            .map(|(addr, _)| *addr as u64)
     }
 
     pub(super) fn search_object_map(&self, _addr: u64) -> Option<(&Context<'_>, u64)> {
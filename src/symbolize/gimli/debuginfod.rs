@@ -0,0 +1,111 @@
+//! Fetching separate debug info from debuginfod servers.
+//!
+//! Distributions increasingly ship stripped binaries and serve their debug
+//! info over HTTP from debuginfod servers instead of in debug packages. The
+//! servers to use are listed in `DEBUGINFOD_URLS`, and files are cached under
+//! `$XDG_CACHE_HOME/debuginfod_client/<build-id>/debuginfo`.
+//!
+//! We look in that cache ourselves, and to download anything which isn't in
+//! it yet we use `libdebuginfod` from elfutils if it's installed, the same as
+//! gdb and the other elfutils tools do. It's loaded with `dlopen` so that
+//! there's no link-time dependency on it.
+
+use super::mystd::env;
+use super::mystd::ffi::{CStr, OsStr};
+use super::mystd::os::unix::prelude::*;
+use super::mystd::path::PathBuf;
+use super::Vec;
+use core::mem;
+
+/// Returns the path of the debug info file with the given build-id, fetching
+/// it if necessary.
+pub(super) fn locate(build_id: &[u8]) -> Option<PathBuf> {
+    match env::var_os("DEBUGINFOD_URLS") {
+        Some(urls) if !urls.is_empty() => {}
+        _ => return None,
+    }
+    if build_id.is_empty() {
+        return None;
+    }
+
+    let mut hex = Vec::with_capacity(build_id.len() * 2);
+    for byte in build_id {
+        hex.extend_from_slice(format!("{:02x}", byte).as_bytes());
+    }
+    if let Some(mut path) = cache_dir() {
+        path.push(OsStr::from_bytes(&hex));
+        path.push("debuginfo");
+        if path.is_file() {
+            return Some(path);
+        }
+    }
+    unsafe { fetch(build_id) }
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("DEBUGINFOD_CACHE_PATH") {
+        return Some(dir.into());
+    }
+    let mut dir = match env::var_os("XDG_CACHE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let mut dir = PathBuf::from(env::var_os("HOME")?);
+            dir.push(".cache");
+            dir
+        }
+    };
+    dir.push("debuginfod_client");
+    Some(dir)
+}
+
+type Begin = unsafe extern "C" fn() -> *mut libc::c_void;
+type FindDebuginfo = unsafe extern "C" fn(
+    client: *mut libc::c_void,
+    build_id: *const u8,
+    build_id_len: libc::c_int,
+    path: *mut *mut libc::c_char,
+) -> libc::c_int;
+type End = unsafe extern "C" fn(client: *mut libc::c_void);
+
+/// Downloads the debug info with `libdebuginfod`, which also puts it in the
+/// cache.
+unsafe fn fetch(build_id: &[u8]) -> Option<PathBuf> {
+    let lib = libc::dlopen(
+        b"libdebuginfod.so.1\0".as_ptr() as *const libc::c_char,
+        libc::RTLD_NOW | libc::RTLD_LOCAL,
+    );
+    if lib.is_null() {
+        return None;
+    }
+    let sym = |name: &[u8]| libc::dlsym(lib, name.as_ptr() as *const libc::c_char);
+    let begin = sym(b"debuginfod_begin\0");
+    let find = sym(b"debuginfod_find_debuginfo\0");
+    let end = sym(b"debuginfod_end\0");
+    let mut ret = None;
+    if !begin.is_null() && !find.is_null() && !end.is_null() {
+        let begin = mem::transmute::<*mut libc::c_void, Begin>(begin);
+        let find = mem::transmute::<*mut libc::c_void, FindDebuginfo>(find);
+        let end = mem::transmute::<*mut libc::c_void, End>(end);
+        let client = begin();
+        if !client.is_null() {
+            let mut path = core::ptr::null_mut();
+            let fd = find(
+                client,
+                build_id.as_ptr(),
+                build_id.len() as libc::c_int,
+                &mut path,
+            );
+            if fd >= 0 {
+                libc::close(fd);
+                if !path.is_null() {
+                    let bytes = CStr::from_ptr(path).to_bytes();
+                    ret = Some(PathBuf::from(OsStr::from_bytes(bytes)));
+                    libc::free(path as *mut libc::c_void);
+                }
+            }
+            end(client);
+        }
+    }
+    libc::dlclose(lib);
+    ret
+}
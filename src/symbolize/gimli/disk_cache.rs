@@ -0,0 +1,267 @@
+//! A persistent index of the symbols of object files, kept on disk.
+//!
+//! Parsing the DWARF of a large object file can take seconds, which every run
+//! of a program pays again the first time it symbolizes an address in it. So
+//! after an object file with a build-id has been parsed once, every row of
+//! its line tables is written to `$XDG_CACHE_HOME/backtrace-rs` (or
+//! `~/.cache/backtrace-rs`) together with the frames `addr2line` finds there.
+//! Later runs look addresses up in that file instead, which only needs a
+//! binary search over the mapped file and no parsing at all. Addresses which
+//! aren't in the index are resolved from the debug information as usual.
+//!
+//! The file starts with a header of `MAGIC` and the number of rows, frames
+//! and bytes of strings, all as little-endian `u32`s. Then come the rows
+//! sorted by address, each with the address and length of the row and the
+//! index and number of its frames. Each frame has the offsets of its function
+//! name and file name into the strings, or `NONE`, and its line and column,
+//! or 0. The strings are NUL-terminated.
+
+use super::mystd::collections::HashMap;
+use super::mystd::env;
+use super::mystd::fs;
+use super::mystd::path::PathBuf;
+use super::mystd::process;
+use super::{Context, MapData, Vec};
+use core::convert::{TryFrom, TryInto};
+use core::str;
+
+const MAGIC: &[u8; 8] = b"BTRSIDX1";
+const HEADER_LEN: usize = MAGIC.len() + 3 * 4;
+const ROW_LEN: usize = 8 + 8 + 4 + 4;
+const FRAME_LEN: usize = 4 * 4;
+const NONE: u32 = !0;
+
+pub(super) struct Index {
+    map: MapData,
+    rows: usize,
+    frames: usize,
+}
+
+/// A frame found in an `Index`, borrowing from it.
+pub(super) struct Frame<'a> {
+    pub(super) name: Option<&'a [u8]>,
+    pub(super) location: Option<addr2line::Location<'a>>,
+}
+
+impl Index {
+    /// Opens the index for the object file with the given build-id, if
+    /// there's a valid one.
+    pub(super) fn open(build_id: &[u8]) -> Option<Index> {
+        // Indexes are ours rather than object files, so they're always
+        // read from the file system.
+        let map = super::map_file(&fs::File::open(path(build_id)?).ok()?)?;
+        if map.get(..MAGIC.len())? != MAGIC {
+            return None;
+        }
+        let rows = read_u32(&map, MAGIC.len())? as usize;
+        let frames = read_u32(&map, MAGIC.len() + 4)? as usize;
+        let strings = read_u32(&map, MAGIC.len() + 8)? as usize;
+        let len = rows
+            .checked_mul(ROW_LEN)?
+            .checked_add(frames.checked_mul(FRAME_LEN)?)?
+            .checked_add(strings)?
+            .checked_add(HEADER_LEN)?;
+        if len != map.len() {
+            return None;
+        }
+        Some(Index { map, rows, frames })
+    }
+
+    /// Returns the frames for `addr`, innermost first, or `None` if `addr`
+    /// isn't covered by the index.
+    pub(super) fn find(&self, addr: u64) -> Option<Vec<Frame<'_>>> {
+        // Find the last row starting at or before `addr`.
+        let (mut lo, mut hi) = (0, self.rows);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if read_u64(&self.map, self.row(mid))? <= addr {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        let row = self.row(lo.checked_sub(1)?);
+        let start = read_u64(&self.map, row)?;
+        let len = read_u64(&self.map, row + 8)?;
+        if addr - start >= len {
+            return None;
+        }
+
+        let first = read_u32(&self.map, row + 16)? as usize;
+        let count = read_u32(&self.map, row + 20)? as usize;
+        if first.checked_add(count)? > self.frames || count == 0 {
+            return None;
+        }
+        let frames = (first..first + count)
+            .map(|i| {
+                let frame = self.frame(i);
+                let name = self.string(read_u32(&self.map, frame)?);
+                let file = self.string(read_u32(&self.map, frame + 4)?);
+                let line = read_u32(&self.map, frame + 8)?;
+                let column = read_u32(&self.map, frame + 12)?;
+                let location = if file.is_none() && line == 0 && column == 0 {
+                    None
+                } else {
+                    Some(addr2line::Location {
+                        file: file.and_then(|f| str::from_utf8(f).ok()),
+                        line: if line == 0 { None } else { Some(line) },
+                        column: if column == 0 { None } else { Some(column) },
+                    })
+                };
+                Some(Frame { name, location })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(frames)
+    }
+
+    fn row(&self, i: usize) -> usize {
+        HEADER_LEN + i * ROW_LEN
+    }
+
+    fn frame(&self, i: usize) -> usize {
+        HEADER_LEN + self.rows * ROW_LEN + i * FRAME_LEN
+    }
+
+    fn string(&self, offset: u32) -> Option<&[u8]> {
+        if offset == NONE {
+            return None;
+        }
+        let strings = &self.map[self.frame(self.frames)..];
+        let string = strings.get(offset as usize..)?;
+        let len = string.iter().position(|b| *b == 0)?;
+        Some(&string[..len])
+    }
+}
+
+/// Writes the index for the object file with the given build-id from `cx`,
+/// unless there's a valid one already.
+///
+/// This parses all of the debug information of the object file, so it takes
+/// a while for large ones.
+pub(super) fn write(build_id: &[u8], cx: &mut Context<'_>) {
+    let path = match path(build_id) {
+        Some(path) => path,
+        None => return,
+    };
+    if Index::open(build_id).is_some() {
+        return;
+    }
+    let data = match build(cx) {
+        Some(data) => data,
+        None => return,
+    };
+    if let Some(dir) = path.parent() {
+        if fs::create_dir_all(dir).is_err() {
+            return;
+        }
+    }
+    // Write to a temporary file first, so that other processes reading the
+    // index never see a partially written one.
+    let mut tmp = path.clone().into_os_string();
+    tmp.push(format!(".{}.tmp", process::id()));
+    if fs::write(&tmp, &data).is_err() || fs::rename(&tmp, &path).is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+}
+
+fn build(cx: &mut Context<'_>) -> Option<Vec<u8>> {
+    let object = &cx.object;
+    let dwarf = cx.dwarf.full()?;
+
+    let mut rows = Vec::new();
+    let mut frames = Vec::new();
+    let mut strings = Vec::new();
+    let mut offsets = HashMap::new();
+    let mut string = |s: Option<&[u8]>| -> u32 {
+        let s = match s {
+            Some(s) if !s.contains(&0) => s,
+            _ => return NONE,
+        };
+        *offsets.entry(s.to_vec()).or_insert_with(|| {
+            let offset = strings.len() as u32;
+            strings.extend_from_slice(s);
+            strings.push(0);
+            offset
+        })
+    };
+
+    for (addr, len, _) in dwarf.find_location_range(0, u64::max_value()).ok()? {
+        let first = frames.len();
+        let mut iter = match dwarf.find_frames(addr) {
+            Ok(iter) => iter,
+            Err(_) => continue,
+        };
+        while let Ok(Some(frame)) = iter.next() {
+            let name = match &frame.function {
+                Some(f) => Some(f.name.slice()),
+                None => object.search_symtab(addr),
+            };
+            let location = frame.location.as_ref();
+            frames.push([
+                string(name),
+                string(location.and_then(|l| l.file).map(str::as_bytes)),
+                location.and_then(|l| l.line).unwrap_or(0),
+                location.and_then(|l| l.column).unwrap_or(0),
+            ]);
+        }
+        if frames.len() > first {
+            rows.push((addr, len, first as u32, (frames.len() - first) as u32));
+        }
+    }
+    if rows.is_empty() {
+        return None;
+    }
+    rows.sort_by_key(|row| row.0);
+
+    let mut data = Vec::with_capacity(
+        HEADER_LEN + rows.len() * ROW_LEN + frames.len() * FRAME_LEN + strings.len(),
+    );
+    data.extend_from_slice(MAGIC);
+    for n in [rows.len(), frames.len(), strings.len()].iter() {
+        data.extend_from_slice(&u32::try_from(*n).ok()?.to_le_bytes());
+    }
+    for (addr, len, first, count) in rows {
+        data.extend_from_slice(&addr.to_le_bytes());
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(&first.to_le_bytes());
+        data.extend_from_slice(&count.to_le_bytes());
+    }
+    for frame in frames {
+        for field in frame.iter() {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+    data.extend_from_slice(&strings);
+    Some(data)
+}
+
+/// Returns where the index for the object file with the given build-id is
+/// kept.
+fn path(build_id: &[u8]) -> Option<PathBuf> {
+    let mut path = match env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if PathBuf::from(&dir).is_absolute() => PathBuf::from(dir),
+        _ => {
+            let mut home = PathBuf::from(env::var_os("HOME")?);
+            home.push(".cache");
+            home
+        }
+    };
+    path.push("backtrace-rs");
+    let mut name = build_id
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<super::String>();
+    name.push_str(".index");
+    path.push(name);
+    Some(path)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(8)?)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
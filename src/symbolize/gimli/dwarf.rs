@@ -0,0 +1,236 @@
+//! Lazily parsing the DWARF of large object files.
+//!
+//! Creating an `addr2line::Context` parses the abbreviations, root entry and
+//! line program header of every unit in `.debug_info`, which for object files
+//! with gigabytes of DWARF takes seconds before the first address can be
+//! looked up. If there's a `.debug_aranges` section with the address ranges
+//! of the compilation units though, we can instead find the unit containing
+//! an address with it, and only create a context for that one unit.
+//!
+//! `.debug_names` would tell us which units there are without reading
+//! `.debug_info` as well, but `gimli` doesn't parse it yet, so we read the
+//! unit headers to find those missing from `.debug_aranges`. Only those few
+//! units are parsed up front to get their address ranges.
+
+use super::gimli::{self, DebugInfoOffset, EndianSlice, Section};
+use super::{Box, Endian, Vec};
+
+type R<'a> = EndianSlice<'a, Endian>;
+
+/// Object files with less `.debug_info` than this are parsed up front, since
+/// that's fast enough and also resolves references between units.
+const LAZY_DEBUG_INFO_SIZE: usize = 256 * 1024 * 1024;
+
+pub(crate) enum Dwarf<'a> {
+    /// A context for all units.
+    Full(addr2line::Context<R<'a>>),
+    /// Contexts for single units, created on demand.
+    Lazy(Box<Lazy<'a>>),
+}
+
+pub(crate) struct Lazy<'a> {
+    sections: gimli::Dwarf<R<'a>>,
+    /// The ranges in `.debug_aranges` and the units they belong to, sorted by
+    /// start address.
+    aranges: Vec<(gimli::Range, DebugInfoOffset)>,
+    /// The contexts created so far, sorted by unit offset, or `None` for the
+    /// ones which couldn't be.
+    units: Vec<(DebugInfoOffset, Option<addr2line::Context<R<'a>>>)>,
+}
+
+impl<'a> Dwarf<'a> {
+    pub(crate) fn new(sections: gimli::Dwarf<R<'a>>) -> Option<Dwarf<'a>> {
+        if sections.debug_info.reader().len() >= LAZY_DEBUG_INFO_SIZE && sections.sup.is_none() {
+            if let Some(aranges) = aranges(&sections) {
+                return Some(Dwarf::Lazy(Box::new(Lazy {
+                    sections,
+                    aranges,
+                    units: Vec::new(),
+                })));
+            }
+        }
+        match addr2line::Context::from_dwarf(sections) {
+            Ok(cx) => Some(Dwarf::Full(cx)),
+            Err(e) => {
+                diagnose(e);
+                None
+            }
+        }
+    }
+
+    /// Returns whether there are any units to look addresses up in.
+    #[cfg(feature = "std")]
+    pub(crate) fn has_debug_info(&self) -> bool {
+        match self {
+            Dwarf::Full(cx) => !cx.dwarf().debug_info.reader().is_empty(),
+            Dwarf::Lazy(_) => true,
+        }
+    }
+
+    /// Creates the context for the unit containing `addr`, if it hasn't been
+    /// already.
+    pub(crate) fn load(&mut self, addr: u64) {
+        let lazy = match self {
+            Dwarf::Full(_) => return,
+            Dwarf::Lazy(lazy) => lazy,
+        };
+        let offset = match lazy.find_unit(addr) {
+            Some(offset) => offset,
+            None => return,
+        };
+        if let Err(i) = lazy.units.binary_search_by_key(&offset, |u| u.0) {
+            let cx = lazy.unit_context(offset);
+            lazy.units.insert(i, (offset, cx));
+        }
+    }
+
+    /// Returns the context to look up `addr` in, which for a lazily parsed
+    /// object file must have been created by `load` first.
+    pub(crate) fn get(&self, addr: u64) -> Option<&addr2line::Context<R<'a>>> {
+        match self {
+            Dwarf::Full(cx) => Some(cx),
+            Dwarf::Lazy(lazy) => {
+                let offset = lazy.find_unit(addr)?;
+                let i = lazy.units.binary_search_by_key(&offset, |u| u.0).ok()?;
+                lazy.units[i].1.as_ref()
+            }
+        }
+    }
+
+    /// Parses all units now if that hasn't been done already, returning a
+    /// context for all of them.
+    pub(crate) fn full(&mut self) -> Option<&addr2line::Context<R<'a>>> {
+        if let Dwarf::Lazy(lazy) = self {
+            // Keep the sections until the context is built, so that the units
+            // can still be parsed lazily if that fails. A lazy object file
+            // never has a supplementary one, see `new`.
+            let sections = gimli::Dwarf {
+                debug_aranges: lazy.sections.debug_aranges,
+                ..copy_sections(&lazy.sections, lazy.sections.debug_info)
+            };
+            *self = Dwarf::Full(addr2line::Context::from_dwarf(sections).ok()?);
+        }
+        match self {
+            Dwarf::Full(cx) => Some(cx),
+            Dwarf::Lazy(_) => None,
+        }
+    }
+}
+
+impl<'a> Lazy<'a> {
+    fn find_unit(&self, addr: u64) -> Option<DebugInfoOffset> {
+        let i = match self
+            .aranges
+            .binary_search_by_key(&addr, |(range, _)| range.begin)
+        {
+            Ok(i) => i,
+            Err(i) => i.checked_sub(1)?,
+        };
+        let (range, offset) = &self.aranges[i];
+        if addr < range.end {
+            Some(*offset)
+        } else {
+            None
+        }
+    }
+
+    /// Creates a context with just the unit at `offset` in `.debug_info`.
+    ///
+    /// Note that this doesn't resolve references to entries in other units,
+    /// so functions whose name is only given that way are left for the
+    /// symbol table to name.
+    fn unit_context(&self, offset: DebugInfoOffset) -> Option<addr2line::Context<R<'a>>> {
+        let header = self.sections.debug_info.header_from_offset(offset).ok()?;
+        let data = self.sections.debug_info.reader().slice();
+        let data = data.get(offset.0..offset.0 + header.length_including_self())?;
+        let sections = copy_sections(&self.sections, EndianSlice::new(data, Endian).into());
+        addr2line::Context::from_dwarf(sections)
+            .map_err(diagnose)
+            .ok()
+    }
+}
+
+/// Records why debug information couldn't be parsed, see
+/// `set_diagnostics_enabled`.
+#[cfg(feature = "std")]
+fn diagnose(error: gimli::Error) {
+    use crate::symbolize::diagnostics::{self, DiagnosticKind};
+
+    diagnostics::record(match error {
+        gimli::Error::UnknownVersion(version) => {
+            DiagnosticKind::UnsupportedDwarfVersion(version as u16)
+        }
+        _ => DiagnosticKind::InvalidDebugInfo,
+    });
+}
+
+#[cfg(not(feature = "std"))]
+fn diagnose(_error: gimli::Error) {}
+
+/// Reads the address ranges of all compilation units, from `.debug_aranges`
+/// where possible.
+fn aranges(sections: &gimli::Dwarf<R<'_>>) -> Option<Vec<(gimli::Range, DebugInfoOffset)>> {
+    let mut aranges = Vec::new();
+    let mut offsets = Vec::new();
+    let mut headers = sections.debug_aranges.headers();
+    while let Some(header) = headers.next().ok()? {
+        let offset = header.debug_info_offset();
+        offsets.push(offset);
+        let mut entries = header.entries();
+        while let Some(entry) = entries.next().ok()? {
+            if entry.length() != 0 {
+                aranges.push((entry.range(), offset));
+            }
+        }
+    }
+    if aranges.is_empty() {
+        return None;
+    }
+    offsets.sort_unstable();
+
+    let mut units = sections.units();
+    while let Some(header) = units.next().ok()? {
+        match header.type_() {
+            gimli::UnitType::Type { .. } | gimli::UnitType::SplitType { .. } => continue,
+            _ => {}
+        }
+        let offset = header.offset().as_debug_info_offset()?;
+        if offsets.binary_search(&offset).is_ok() {
+            continue;
+        }
+        let unit = sections.unit(header).ok()?;
+        let mut ranges = sections.unit_ranges(&unit).ok()?;
+        while let Some(range) = ranges.next().ok()? {
+            if range.begin < range.end {
+                aranges.push((range, offset));
+            }
+        }
+    }
+
+    aranges.sort_unstable_by_key(|(range, _)| range.begin);
+    Some(aranges)
+}
+
+/// Copies `sections` with `.debug_info` replaced, and without
+/// `.debug_aranges` so that `addr2line` doesn't try to match its unit offsets
+/// against those of a partial `.debug_info`.
+fn copy_sections<'a>(
+    sections: &gimli::Dwarf<R<'a>>,
+    debug_info: gimli::DebugInfo<R<'a>>,
+) -> gimli::Dwarf<R<'a>> {
+    gimli::Dwarf {
+        debug_abbrev: sections.debug_abbrev,
+        debug_addr: sections.debug_addr,
+        debug_aranges: EndianSlice::new(&[], Endian).into(),
+        debug_info,
+        debug_line: sections.debug_line,
+        debug_line_str: sections.debug_line_str,
+        debug_str: sections.debug_str,
+        debug_str_offsets: sections.debug_str_offsets,
+        debug_types: sections.debug_types,
+        locations: sections.locations,
+        ranges: sections.ranges,
+        file_type: sections.file_type,
+        sup: None,
+    }
+}
@@ -1,16 +1,29 @@
+use super::super::image::word;
 use super::mystd::ffi::{OsStr, OsString};
 use super::mystd::fs;
+#[cfg(target_os = "hermit")]
+use super::mystd::os::hermit::ffi::{OsStrExt, OsStringExt};
+#[cfg(unix)]
 use super::mystd::os::unix::ffi::{OsStrExt, OsStringExt};
+#[cfg(unix)]
+use super::mystd::os::unix::fs::FileExt;
 use super::mystd::path::{Path, PathBuf};
 use super::Either;
-use super::{Context, Mapping, Stash, Vec};
+use super::{Context, MapData, Mapping, Source, Stash, Vec};
+use alloc::vec;
 use core::convert::{TryFrom, TryInto};
+use core::mem;
+use core::ops::Range;
 use core::str;
-use object::elf::{ELFCOMPRESS_ZLIB, ELF_NOTE_GNU, NT_GNU_BUILD_ID, SHF_COMPRESSED};
+use object::elf::{ELFCOMPRESS_ZLIB, ELF_NOTE_GNU, NT_GNU_BUILD_ID, SHF_ALLOC, SHF_COMPRESSED};
+use object::elf::{SHT_DYNSYM, SHT_NOBITS, SHT_NOTE, SHT_STRTAB, SHT_SYMTAB};
 use object::read::elf::{CompressionHeader, FileHeader, SectionHeader, SectionTable, Sym};
-use object::read::StringTable;
 use object::{BigEndian, Bytes, NativeEndian};
 
+// Not defined by the `object` crate yet.
+#[cfg(all(feature = "zstd", any(target_os = "linux", target_os = "freebsd")))]
+const ELFCOMPRESS_ZSTD: u32 = 2;
+
 #[cfg(target_pointer_width = "32")]
 type Elf = object::elf::FileHeader32<NativeEndian>;
 #[cfg(target_pointer_width = "64")]
@@ -18,42 +31,130 @@ type Elf = object::elf::FileHeader64<NativeEndian>;
 
 impl Mapping {
     pub fn new(path: &Path) -> Option<Mapping> {
-        let map = super::mmap(path)?;
+        Mapping::new_from(path, path)
+    }
+
+    /// Like `new`, but maps the object file through `file`, another name of
+    /// the file at `path` which may not be there anymore. `path` is still
+    /// used to look for separate debug files.
+    pub fn new_from(path: &Path, file: &Path) -> Option<Mapping> {
+        let (map, range) = match super::open(file) {
+            Some(source) => {
+                let map = load(source)?;
+                let len = map.len();
+                (map, 0..len)
+            }
+            None => load_zip_entry(file)?,
+        };
         Mapping::mk_or_other(map, |map, stash| {
-            let object = Object::parse(&map)?;
+            #[allow(unused_mut)]
+            let mut object = Object::parse(&map[range])?;
 
-            // Try to locate an external debug file using the build ID.
-            if let Some(path_debug) = object.build_id().and_then(locate_build_id) {
-                if let Some(mapping) = Mapping::new_debug(path_debug, None) {
+            // Files with debug info of their own are used as is, and only
+            // stripped ones have their debug info looked for elsewhere.
+            if !object.has_debug_info() {
+                if let Some(mapping) = Mapping::find_debug(path, &object) {
                     return Some(Either::A(mapping));
                 }
+
+                // Without any debug info, at least use the symbols which may
+                // be embedded as MiniDebugInfo.
+                #[cfg(all(
+                    feature = "minidebuginfo",
+                    any(target_os = "linux", target_os = "freebsd")
+                ))]
+                object.add_minidebuginfo(stash);
             }
 
-            // Try to locate an external debug file using the GNU debug link section.
-            if let Some((path_debug, crc)) = object.gnu_debuglink_path(path) {
-                if let Some(mapping) = Mapping::new_debug(path_debug, Some(crc)) {
-                    return Some(Either::A(mapping));
+            let mut cx = Context::new(stash, object, None)?;
+            cx.split.path = Some(path.to_path_buf());
+            Some(Either::B(cx))
+        })
+    }
+
+    /// Creates a mapping from the contents of an object file.
+    ///
+    /// Unlike `new` this doesn't look for separate debug files.
+    pub fn from_bytes(data: impl Into<MapData>) -> Option<Mapping> {
+        Mapping::mk(data, |data, stash| {
+            Context::new(stash, Object::parse(data)?, None)
+        })
+    }
+
+    /// Looks for a separate debug file for the stripped `object` at `path`.
+    fn find_debug(path: &Path, object: &Object<'_>) -> Option<Mapping> {
+        // Try to locate an external debug file using the build ID.
+        if let Some(mapping) = object.build_id().and_then(Mapping::new_build_id) {
+            return Some(mapping);
+        }
+
+        // Try to locate an external debug file using the GNU debug link section.
+        if let Some((path_debug, crc)) = object.gnu_debuglink_path(path) {
+            if let Some(mapping) = Mapping::new_debug(path_debug, Some(crc)) {
+                return Some(mapping);
+            }
+        }
+
+        // Try to fetch the debug info from a debuginfod server.
+        #[cfg(all(
+            feature = "debuginfod",
+            any(target_os = "linux", target_os = "freebsd")
+        ))]
+        {
+            if let Some(path_debug) = object.build_id().and_then(super::debuginfod::locate) {
+                if let Some(mapping) = Mapping::new_debug(path_debug, None) {
+                    return Some(mapping);
                 }
             }
+        }
 
-            Context::new(stash, object, None).map(Either::B)
-        })
+        None
+    }
+
+    /// Loads the external debug file for the given build ID, which the
+    /// `ObjectSource` may know about itself.
+    fn new_build_id(build_id: &[u8]) -> Option<Mapping> {
+        let path = build_id_path(build_id)?;
+        match super::open_build_id(build_id) {
+            Some(source) => Mapping::new_debug_from(&path, source, None),
+            None if debug_path_exists() => Mapping::new_debug(path, None),
+            None => None,
+        }
     }
 
     /// Load debuginfo from an external debug file.
     fn new_debug(path: PathBuf, crc: Option<u32>) -> Option<Mapping> {
-        let map = super::mmap(&path)?;
-        Mapping::mk(map, |map, stash| {
-            let object = Object::parse(&map)?;
+        let source = super::open(&path)?;
+        Mapping::new_debug_from(&path, source, crc)
+    }
 
-            if let Some(_crc) = crc {
-                // TODO: check crc
+    /// Like `new_debug`, but with the contents of the file at `path` coming
+    /// from `source`.
+    fn new_debug_from(path: &Path, source: Source, mut crc: Option<u32>) -> Option<Mapping> {
+        // A debug link names a file, which may well be the debug file of a
+        // different build, so its contents are checked too. With a memory
+        // limit only parts of a file are read, so it's checked up front then.
+        if let (Some(expected), Some(_), Source::File(file)) = (crc, super::memory_limit(), &source)
+        {
+            if crc32_file(file)? != expected {
+                return None;
+            }
+            crc = None;
+        }
+
+        let map = load(source)?;
+        Mapping::mk(map, |map, stash| {
+            if let Some(crc) = crc {
+                if crc32(map) != crc {
+                    return None;
+                }
             }
 
+            let object = Object::parse(&map)?;
+
             // Try to locate a supplementary object file.
-            if let Some((path_sup, build_id_sup)) = object.gnu_debugaltlink_path(&path) {
-                if let Some(map_sup) = super::mmap(&path_sup) {
-                    let map_sup = stash.set_mmap_aux(map_sup);
+            if let Some((path_sup, build_id_sup)) = object.gnu_debugaltlink_path(path) {
+                if let Some(map_sup) = load_in(stash, &path_sup) {
                     if let Some(sup) = Object::parse(map_sup) {
                         if sup.build_id() == Some(build_id_sup) {
                             return Context::new(stash, object, Some(sup));
@@ -67,10 +168,10 @@ impl Mapping {
     }
 }
 
-struct ParsedSym {
+struct ParsedSym<'a> {
     address: u64,
     size: u64,
-    name: u32,
+    name: &'a [u8],
 }
 
 pub struct Object<'a> {
@@ -81,13 +182,12 @@ pub struct Object<'a> {
     /// The entire file data.
     data: &'a [u8],
     sections: SectionTable<'a, Elf>,
-    strings: StringTable<'a>,
     /// List of pre-parsed and sorted symbols by base address.
-    syms: Vec<ParsedSym>,
+    syms: Vec<ParsedSym<'a>>,
 }
 
 impl<'a> Object<'a> {
-    fn parse(data: &'a [u8]) -> Option<Object<'a>> {
+    pub(super) fn parse(data: &'a [u8]) -> Option<Object<'a>> {
         let elf = Elf::parse(data).ok()?;
         let endian = elf.endian().ok()?;
         let sections = elf.sections(endian, data).ok()?;
@@ -116,15 +216,15 @@ impl<'a> Object<'a> {
             // since it means it's an imported function and we're only
             // symbolicating with locally defined functions.
             .filter(|sym| sym.st_shndx(endian) != object::elf::SHN_UNDEF)
-            .map(|sym| {
+            .filter_map(|sym| {
                 let address = sym.st_value(endian).into();
                 let size = sym.st_size(endian).into();
-                let name = sym.st_name(endian);
-                ParsedSym {
+                let name = strings.get(sym.st_name(endian)).ok()?;
+                Some(ParsedSym {
                     address,
                     size,
                     name,
-                }
+                })
             })
             .collect::<Vec<_>>();
         syms.sort_unstable_by_key(|s| s.address);
@@ -132,7 +232,6 @@ impl<'a> Object<'a> {
             endian,
             data,
             sections,
-            strings,
             syms,
         })
     }
@@ -150,13 +249,14 @@ impl<'a> Object<'a> {
             }
 
             let header = data.read::<<Elf as FileHeader>::CompressionHeader>().ok()?;
-            if header.ch_type(self.endian) != ELFCOMPRESS_ZLIB {
-                // Zlib compression is the only known type.
-                return None;
-            }
             let size = usize::try_from(header.ch_size(self.endian)).ok()?;
-            let buf = stash.allocate(size);
-            decompress_zlib(data.0, buf)?;
+            let buf = self.allocate(stash, size)?;
+            match header.ch_type(self.endian) {
+                ELFCOMPRESS_ZLIB => decompress_zlib(data.0, buf)?,
+                #[cfg(all(feature = "zstd", any(target_os = "linux", target_os = "freebsd")))]
+                ELFCOMPRESS_ZSTD => super::zstd::decompress(data.0, buf)?,
+                _ => return None,
+            }
             return Some(buf);
         }
 
@@ -185,11 +285,44 @@ impl<'a> Object<'a> {
             return None;
         }
         let size = usize::try_from(data.read::<object::U32Bytes<_>>().ok()?.get(BigEndian)).ok()?;
-        let buf = stash.allocate(size);
+        let buf = self.allocate(stash, size)?;
         decompress_zlib(data.0, buf)?;
         Some(buf)
     }
 
+    /// Allocates a buffer of `size` bytes for a decompressed section, unless
+    /// that would take this object over the memory limit.
+    fn allocate(&self, stash: &'a Stash, size: usize) -> Option<&'a mut [u8]> {
+        let used = self.data.len() + stash.size() + size;
+        match super::memory_limit() {
+            Some(limit) if used > limit => None,
+            _ => Some(stash.allocate(size)),
+        }
+    }
+
+    /// Adds the symbols of the MiniDebugInfo in the `.gnu_debugdata` section,
+    /// if there is any, to those searched by `search_symtab`.
+    #[cfg(all(
+        feature = "minidebuginfo",
+        any(target_os = "linux", target_os = "freebsd")
+    ))]
+    fn add_minidebuginfo(&mut self, stash: &'a Stash) -> Option<()> {
+        let section = self.section_header(".gnu_debugdata")?;
+        let data = section.data(self.endian, self.data).ok()?;
+        let data = super::minidebuginfo::decompress(data)?;
+        let buf = stash.allocate(data.len());
+        buf.copy_from_slice(&data);
+        let mini = Object::parse(buf)?;
+        self.syms.extend(mini.syms);
+        self.syms.sort_unstable_by_key(|s| s.address);
+        Some(())
+    }
+
+    fn has_debug_info(&self) -> bool {
+        self.section_header(".debug_info").is_some()
+            || self.section_header(".zdebug_info").is_some()
+    }
+
     fn section_header(&self, name: &str) -> Option<&<Elf as FileHeader>::SectionHeader> {
         self.sections
             .section_by_name(self.endian, name.as_bytes())
@@ -197,6 +330,12 @@ impl<'a> Object<'a> {
     }
 
     pub fn search_symtab<'b>(&'b self, addr: u64) -> Option<&'b [u8]> {
+        self.search_symtab_entry(addr).map(|(_, _, name)| name)
+    }
+
+    /// Like `search_symtab`, but also returns the address the symbol starts
+    /// at and, if known, its size.
+    pub fn search_symtab_entry(&self, addr: u64) -> Option<(u64, Option<u64>, &[u8])> {
         // Same sort of binary search as Windows above
         let i = match self.syms.binary_search_by_key(&addr, |sym| sym.address) {
             Ok(i) => i,
@@ -204,12 +343,28 @@ impl<'a> Object<'a> {
         };
         let sym = self.syms.get(i)?;
         if sym.address <= addr && addr <= sym.address + sym.size {
-            self.strings.get(sym.name).ok()
+            Some((sym.address, Some(sym.size).filter(|s| *s != 0), sym.name))
         } else {
             None
         }
     }
 
+    /// Returns every symbol in the symbol table, as its address, its size if
+    /// known and its name.
+    pub fn symbols(&self) -> impl Iterator<Item = (u64, Option<u64>, &[u8])> + '_ {
+        self.syms
+            .iter()
+            .map(|sym| (sym.address, Some(sym.size).filter(|s| *s != 0), sym.name))
+    }
+
+    /// Returns the address of the first symbol whose name `matches`.
+    pub fn search_symtab_name(&self, mut matches: impl FnMut(&[u8]) -> bool) -> Option<u64> {
+        self.syms
+            .iter()
+            .find(|sym| matches(sym.name))
+            .map(|sym| sym.address)
+    }
+
     pub(super) fn search_object_map(&self, _addr: u64) -> Option<(&Context<'_>, u64)> {
         None
     }
@@ -305,6 +460,14 @@ fn debug_path_exists() -> bool {
 /// The format of build id paths is documented at:
 /// https://sourceware.org/gdb/onlinedocs/gdb/Separate-Debug-Files.html
 fn locate_build_id(build_id: &[u8]) -> Option<PathBuf> {
+    if !debug_path_exists() {
+        return None;
+    }
+    build_id_path(build_id)
+}
+
+/// Returns the path the debug file with the given build ID would be at.
+fn build_id_path(build_id: &[u8]) -> Option<PathBuf> {
     const BUILD_ID_PATH: &[u8] = b"/usr/lib/debug/.build-id/";
     const BUILD_ID_SUFFIX: &[u8] = b".debug";
 
@@ -312,10 +475,6 @@ fn locate_build_id(build_id: &[u8]) -> Option<PathBuf> {
         return None;
     }
 
-    if !debug_path_exists() {
-        return None;
-    }
-
     let mut path =
         Vec::with_capacity(BUILD_ID_PATH.len() + BUILD_ID_SUFFIX.len() + build_id.len() * 2 + 1);
     path.extend(BUILD_ID_PATH);
@@ -330,6 +489,218 @@ fn locate_build_id(build_id: &[u8]) -> Option<PathBuf> {
     Some(PathBuf::from(OsString::from_vec(path)))
 }
 
+/// Returns the contents of the object file from `source`: all of it, or with
+/// a memory limit set, only the parts of files used for symbolization.
+fn load(source: Source) -> Option<MapData> {
+    match (super::memory_limit(), source) {
+        (Some(limit), Source::File(file)) => read_sections(&file, limit).map(MapData::Owned),
+        (Some(limit), Source::Data(data)) if data.len() > limit => None,
+        (_, source) => source.map(),
+    }
+}
+
+/// Loads the object file at `path` like `load`, keeping it in `stash`.
+pub(super) fn load_in<'a>(stash: &'a Stash, path: &Path) -> Option<&'a [u8]> {
+    Some(stash.cache_mmap(load(super::open(path)?)?))
+}
+
+/// Loads an object file which is stored in a zip archive, as named by a path
+/// like `/data/app/.../base.apk!/lib/arm64-v8a/libfoo.so`.
+///
+/// This is how Android names the libraries it loads straight out of an APK,
+/// which it does with the ones stored uncompressed. Returns the data holding
+/// the object file and where in it the object file is.
+fn load_zip_entry(path: &Path) -> Option<(MapData, Range<usize>)> {
+    let path = path.as_os_str().as_bytes();
+    let separator = path.windows(2).position(|w| w == b"!/")?;
+    let archive = Path::new(OsStr::from_bytes(&path[..separator]));
+    let map = super::open(archive)?.map()?;
+    let range = zip_entry(&map, &path[separator + 2..])?;
+    match super::memory_limit() {
+        // Don't hold on to the rest of the archive with a memory limit.
+        Some(limit) if range.len() > limit => None,
+        Some(_) => {
+            let data = map[range].to_vec();
+            let len = data.len();
+            Some((MapData::Owned(data), 0..len))
+        }
+        None => Some((map, range)),
+    }
+}
+
+/// Finds the contents of the entry `name` of the zip archive `data`, if it's
+/// stored without compression.
+fn zip_entry(data: &[u8], name: &[u8]) -> Option<Range<usize>> {
+    // Offsets are kept as `u64` so that adding up the 32-bit fields of a
+    // malformed archive can't overflow.
+    let bytes = |at: u64, len: u64| -> Option<&[u8]> {
+        let start = usize::try_from(at).ok()?;
+        let end = usize::try_from(at.checked_add(len)?).ok()?;
+        data.get(start..end)
+    };
+    let u16_at = |at: u64| {
+        Some(u64::from(u16::from_le_bytes(
+            bytes(at, 2)?.try_into().ok()?,
+        )))
+    };
+    let u32_at = |at: u64| {
+        Some(u64::from(u32::from_le_bytes(
+            bytes(at, 4)?.try_into().ok()?,
+        )))
+    };
+
+    // The end of central directory record is the last thing in the file,
+    // except for a comment of up to 64k.
+    let last = (data.len() as u64).checked_sub(22)?;
+    let end = (last.saturating_sub(0xffff)..=last)
+        .rev()
+        .find(|&at| u32_at(at) == Some(0x0605_4b50))?;
+    let count = u16_at(end + 10)?;
+    let mut at = u32_at(end + 16)?;
+    for _ in 0..count {
+        if u32_at(at)? != 0x0201_4b50 {
+            return None;
+        }
+        let name_len = u16_at(at + 28)?;
+        if bytes(at + 46, name_len)? == name {
+            if u16_at(at + 10)? != 0 {
+                return None;
+            }
+            let size = u32_at(at + 20)?;
+            let header = u32_at(at + 42)?;
+            if u32_at(header)? != 0x0403_4b50 {
+                return None;
+            }
+            let start = header + 30 + u16_at(header + 26)? + u16_at(header + 28)?;
+            bytes(start, size)?;
+            return Some(start as usize..(start + size) as usize);
+        }
+        at += 46 + name_len + u16_at(at + 30)? + u16_at(at + 32)?;
+    }
+    None
+}
+
+/// Reads the parts of the ELF file `file` which are used to symbolize
+/// addresses, if they fit into `limit` bytes.
+///
+/// The result is an ELF file of its own, with the file header, the section
+/// headers, and the contents of the symbol and string tables, the notes and
+/// the sections that aren't loaded into memory, like the debug info. All
+/// other sections, like the code and data, become `SHT_NOBITS`.
+fn read_sections(file: &fs::File, limit: usize) -> Option<Vec<u8>> {
+    type SectionHeader = <Elf as FileHeader>::SectionHeader;
+
+    let mut header = vec![0; mem::size_of::<Elf>()];
+    file.read_exact_at(&mut header, 0).ok()?;
+    let elf = Elf::parse(&header[..]).ok()?;
+    let endian = elf.endian().ok()?;
+    // Files with more sections than fit into the file header aren't worth
+    // the trouble here.
+    let count = usize::from(elf.e_shnum(endian));
+    if count == 0 || usize::from(elf.e_shentsize(endian)) != mem::size_of::<SectionHeader>() {
+        return None;
+    }
+    let mut table = vec![0; count * mem::size_of::<SectionHeader>()];
+    file.read_exact_at(&mut table, word(elf.e_shoff(endian)))
+        .ok()?;
+
+    // Lay out the sections to keep after the headers, as where each is in
+    // the file, where it goes and its size.
+    let mut len = header.len() + table.len();
+    let mut ranges = Vec::with_capacity(count);
+    let (sections, _) = object::pod::slice_from_bytes::<SectionHeader>(&table, count).ok()?;
+    for section in sections {
+        let keep = match section.sh_type(endian) {
+            SHT_SYMTAB | SHT_DYNSYM | SHT_STRTAB | SHT_NOTE => true,
+            _ => {
+                let flags = word(section.sh_flags(endian));
+                flags & u64::from(SHF_ALLOC) == 0
+            }
+        };
+        // This is `None` for `SHT_NOBITS` sections.
+        let (from, size) = match section.file_range(endian) {
+            Some(range) if keep => range,
+            _ => {
+                ranges.push(None);
+                continue;
+            }
+        };
+        let size = usize::try_from(size).ok()?;
+        len = len.checked_add(7)? & !7;
+        ranges.push(Some((from, len, size)));
+        len = len.checked_add(size)?;
+    }
+    if len > limit {
+        return None;
+    }
+
+    let mut data = Vec::with_capacity(len);
+    data.extend_from_slice(&header);
+    data.extend_from_slice(&table);
+    for &(from, to, size) in ranges.iter().flatten() {
+        data.resize(to + size, 0);
+        file.read_exact_at(&mut data[to..], from).ok()?;
+    }
+
+    // Point the headers at where things are now. The program headers aren't
+    // needed to symbolize anything, so they're left out.
+    let (elf, rest) = object::pod::from_bytes_mut::<Elf>(&mut data).ok()?;
+    elf.e_phoff.set(endian, 0);
+    elf.e_phnum.set(endian, 0);
+    elf.e_shoff.set(endian, header.len().try_into().ok()?);
+    let sections = object::pod::slice_from_bytes_mut::<SectionHeader>(rest, count)
+        .ok()?
+        .0;
+    for (section, range) in sections.iter_mut().zip(&ranges) {
+        match *range {
+            Some((_, to, _)) => section.sh_offset.set(endian, to.try_into().ok()?),
+            None => section.sh_type.set(endian, SHT_NOBITS),
+        }
+    }
+    Some(data)
+}
+
+/// Returns the CRC-32 of `file`, like `crc32` does for data in memory.
+fn crc32_file(file: &fs::File) -> Option<u32> {
+    let mut buf = vec![0; 64 * 1024];
+    let mut crc = !0u32;
+    let mut offset = 0;
+    loop {
+        let n = file.read_at(&mut buf, offset).ok()?;
+        if n == 0 {
+            return Some(!crc);
+        }
+        crc = crc32_update(crc, &buf[..n]);
+        offset += n as u64;
+    }
+}
+
+/// The CRC-32 used by `.gnu_debuglink`, which is the same as zlib's.
+fn crc32(data: &[u8]) -> u32 {
+    !crc32_update(!0, data)
+}
+
+/// Adds `data` to a CRC-32 which hasn't been finished off with a final
+/// complement yet.
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut crc = i as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+        *entry = crc;
+    }
+    for byte in data {
+        crc = table[((crc ^ u32::from(*byte)) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
 fn hex(byte: u8) -> u8 {
     if byte < 10 {
         b'0' + byte
@@ -348,7 +719,7 @@ fn hex(byte: u8) -> u8 {
 ///
 /// gdb also allows the user to customize the debug search path, but we don't.
 ///
-/// gdb also supports debuginfod, but we don't yet.
+/// debuginfod is supported with the `debuginfod` feature, see `find_debug`.
 fn locate_debuglink(path: &Path, filename: &[u8]) -> Option<PathBuf> {
     let path = fs::canonicalize(path).ok()?;
     let parent = path.parent()?;
@@ -421,3 +792,101 @@ fn locate_debugaltlink(path: &Path, filename: &[u8], build_id: &[u8]) -> Option<
 
     locate_build_id(build_id)
 }
+
+/// Returns whether the file at `path` is an ELF file for the architecture
+/// this crate was compiled for, or isn't one at all.
+///
+/// The file at the name a library was loaded from isn't always the one which
+/// was loaded. Emulators such as `qemu-user` report the names in the emulated
+/// root, which outside of it are the host's libraries of the same name, and
+/// symbolizing against them only gives wrong answers.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(super) fn is_native_object(path: &Path) -> bool {
+    use object::elf;
+
+    let machine = if cfg!(target_arch = "x86") {
+        elf::EM_386
+    } else if cfg!(target_arch = "x86_64") {
+        elf::EM_X86_64
+    } else if cfg!(target_arch = "arm") {
+        elf::EM_ARM
+    } else if cfg!(target_arch = "aarch64") {
+        elf::EM_AARCH64
+    } else if cfg!(any(target_arch = "riscv32", target_arch = "riscv64")) {
+        elf::EM_RISCV
+    } else if cfg!(target_arch = "powerpc") {
+        elf::EM_PPC
+    } else if cfg!(target_arch = "powerpc64") {
+        elf::EM_PPC64
+    } else if cfg!(any(target_arch = "mips", target_arch = "mips64")) {
+        elf::EM_MIPS
+    } else if cfg!(target_arch = "s390x") {
+        elf::EM_S390
+    } else if cfg!(target_arch = "loongarch64") {
+        elf::EM_LOONGARCH
+    } else {
+        return true;
+    };
+
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(_) => return true,
+    };
+    let mut header = vec![0; mem::size_of::<Elf>()];
+    if file.read_exact_at(&mut header, 0).is_err() || !header.starts_with(&elf::ELFMAG) {
+        return true;
+    }
+    // Files of the other class or byte order don't parse at all.
+    let elf = match Elf::parse(&header[..]) {
+        Ok(elf) => elf,
+        Err(_) => return false,
+    };
+    match elf.endian() {
+        Ok(endian) => elf.e_machine(endian) == machine,
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn native_objects() {
+        assert!(is_native_object(&std::env::current_exe().unwrap()));
+        assert!(is_native_object(Path::new("Cargo.toml")));
+        assert!(is_native_object(Path::new("does-not-exist")));
+    }
+
+    #[test]
+    fn debuglink_crc() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+}
+
+/// Hermit has no positioned reads, so these seek instead. Each file is only
+/// read from one place at a time, under the lock symbolization takes.
+#[cfg(target_os = "hermit")]
+trait FileExt {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> super::mystd::io::Result<usize>;
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> super::mystd::io::Result<()>;
+}
+
+#[cfg(target_os = "hermit")]
+impl FileExt for fs::File {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> super::mystd::io::Result<usize> {
+        use super::mystd::io::{Read, Seek, SeekFrom};
+        let mut file = self;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read(buf)
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> super::mystd::io::Result<()> {
+        use super::mystd::io::{Read, Seek, SeekFrom};
+        let mut file = self;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(buf)
+    }
+}
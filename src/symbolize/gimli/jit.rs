@@ -0,0 +1,126 @@
+//! Support for code registered through the GDB JIT interface.
+//!
+//! JIT compilers such as LLVM's ORC or cranelift describe the code they emit
+//! to debuggers by registering an in-memory object file, complete with debug
+//! information, in a linked list rooted at the `__jit_debug_descriptor`
+//! symbol. Debuggers find that list by name, and so do we. The registered
+//! objects have their sections' addresses set to where the code was placed,
+//! so each of them is treated as a library with a bias of zero.
+//!
+//! The interface has no locking, a JIT may be adding or removing entries
+//! while we walk the list. This is the same race debuggers have, although
+//! they at least stop the process first.
+
+use super::super::image::word;
+use super::{Arc, Cache, Library, LibrarySegment, OsString, Registered, Vec};
+use core::convert::TryInto;
+use core::slice;
+use object::elf::SHF_ALLOC;
+use object::read::elf::{FileHeader, SectionHeader};
+use object::NativeEndian;
+
+#[cfg(target_pointer_width = "32")]
+type Elf = object::elf::FileHeader32<NativeEndian>;
+#[cfg(target_pointer_width = "64")]
+type Elf = object::elf::FileHeader64<NativeEndian>;
+
+#[repr(C)]
+struct JitCodeEntry {
+    next_entry: *const JitCodeEntry,
+    prev_entry: *const JitCodeEntry,
+    symfile_addr: *const u8,
+    symfile_size: u64,
+}
+
+#[repr(C)]
+struct JitDescriptor {
+    version: u32,
+    action_flag: u32,
+    relevant_entry: *const JitCodeEntry,
+    first_entry: *const JitCodeEntry,
+}
+
+/// Returns the address and size of each object file currently registered.
+unsafe fn entries() -> Vec<(usize, usize)> {
+    let mut ret = Vec::new();
+    // The descriptor is defined by the JIT, if there's one in the process at
+    // all, so it can't be linked against.
+    let descriptor = libc::dlsym(
+        libc::RTLD_DEFAULT,
+        b"__jit_debug_descriptor\0".as_ptr() as *const libc::c_char,
+    ) as *const JitDescriptor;
+    if descriptor.is_null() || (*descriptor).version != 1 {
+        return ret;
+    }
+    let mut entry = (*descriptor).first_entry;
+    while !entry.is_null() {
+        if let Ok(size) = (*entry).symfile_size.try_into() {
+            ret.push(((*entry).symfile_addr as usize, size));
+        }
+        entry = (*entry).next_entry;
+    }
+    ret
+}
+
+/// Returns the loaded address ranges of a registered object file, which are
+/// its allocated sections.
+fn segments(data: &[u8]) -> Option<Vec<LibrarySegment>> {
+    let elf = Elf::parse(data).ok()?;
+    let endian = elf.endian().ok()?;
+    let sections = elf.sections(endian, data).ok()?;
+    let segments = sections
+        .iter()
+        .filter(|s| {
+            let flags = word(s.sh_flags(endian));
+            flags & u64::from(SHF_ALLOC) != 0
+        })
+        .filter_map(|s| {
+            Some(LibrarySegment {
+                stated_virtual_memory_address: s.sh_addr(endian).try_into().ok()?,
+                len: s.sh_size(endian).try_into().ok()?,
+            })
+        })
+        .filter(|s| s.stated_virtual_memory_address != 0 && s.len != 0)
+        .collect::<Vec<_>>();
+    if segments.is_empty() {
+        None
+    } else {
+        Some(segments)
+    }
+}
+
+/// Brings the libraries of `cache` for JIT-registered object files up to date
+/// with the list of registered objects.
+pub(super) unsafe fn refresh(cache: &mut Cache) {
+    let entries = entries();
+    for i in (0..cache.libraries.len()).rev() {
+        if let Some(Registered::Jit(addr, data)) = &cache.libraries[i].registered {
+            if !entries.contains(&(*addr, data.len())) {
+                cache.remove_library(i);
+            }
+        }
+    }
+    for (addr, size) in entries {
+        let known = cache.libraries.iter().any(|lib| match &lib.registered {
+            Some(Registered::Jit(a, data)) => *a == addr && data.len() == size,
+            _ => false,
+        });
+        if known || addr == 0 {
+            continue;
+        }
+        // Copy the object file, since the JIT may free it at any time.
+        let data: Arc<[u8]> = slice::from_raw_parts(addr as *const u8, size).into();
+        let segments = match segments(&data) {
+            Some(segments) => segments,
+            None => continue,
+        };
+        cache.libraries.push(Library {
+            name: OsString::new(),
+            segments,
+            bias: 0,
+            slide: 0,
+            build_id: None,
+            registered: Some(Registered::Jit(addr, data)),
+        });
+    }
+}
@@ -0,0 +1,133 @@
+//! Symbolization of kernel addresses through `/proc/kallsyms`.
+//!
+//! Stacks captured by system profilers, say from `perf_event_open`, mix the
+//! frames of the program with those of the kernel it called into. The kernel
+//! lists the address and name of each of its functions, including those of
+//! loaded modules, in `/proc/kallsyms`, which is enough to name those frames.
+//!
+//! Unless `kptr_restrict` allows the reader to see them, the kernel lists all
+//! addresses as zero, in which case kernel addresses stay unresolved.
+
+use super::mystd::fs;
+use super::Vec;
+use core::ops::Range;
+use core::str;
+
+/// The lowest address of the kernel's half of the address space. Only
+/// addresses from here on are looked up, so that addresses which just aren't
+/// in any library don't cause the whole file to be read.
+#[cfg(target_pointer_width = "64")]
+const KERNEL_START: usize = 1 << 63;
+#[cfg(target_pointer_width = "32")]
+const KERNEL_START: usize = 0xc000_0000;
+
+#[derive(Default)]
+pub(super) struct Kallsyms {
+    /// Whether the file has been read, which is only done once.
+    loaded: bool,
+    /// The start of each function and where its name is in `names`, sorted
+    /// by address.
+    entries: Vec<(usize, Range<usize>)>,
+    names: Vec<u8>,
+}
+
+impl Kallsyms {
+    /// Returns the name of the kernel function containing `addr`, if any.
+    pub(super) fn lookup(&mut self, addr: usize) -> Option<&[u8]> {
+        if addr < KERNEL_START {
+            return None;
+        }
+        if !self.loaded {
+            self.loaded = true;
+            if let Ok(data) = fs::read("/proc/kallsyms") {
+                self.parse(&data);
+            }
+        }
+        let i = match self.entries.binary_search_by_key(&addr, |e| e.0) {
+            Ok(i) => i,
+            Err(i) => i.checked_sub(1)?,
+        };
+        Some(&self.names[self.entries[i].1.clone()])
+    }
+
+    fn parse(&mut self, data: &[u8]) {
+        for line in data.split(|b| *b == b'\n') {
+            if let Some((addr, name)) = parse_line(line) {
+                let start = self.names.len();
+                self.names.extend_from_slice(name);
+                self.entries.push((addr, start..self.names.len()));
+            }
+        }
+        // With addresses hidden by `kptr_restrict` all of them are zero, and
+        // nothing can be looked up.
+        if self.entries.iter().all(|e| e.0 == 0) {
+            self.entries = Vec::new();
+            self.names = Vec::new();
+        }
+        self.entries.sort_by_key(|e| e.0);
+    }
+}
+
+/// Parses a line of the form `ADDRESS TYPE name`, possibly followed by a tab
+/// and the module in brackets, returning the address and name of functions.
+fn parse_line(line: &[u8]) -> Option<(usize, &[u8])> {
+    let mut parts = line.splitn(3, |b| *b == b' ');
+    let addr = str::from_utf8(parts.next()?).ok()?;
+    let addr = usize::from_str_radix(addr, 16).ok()?;
+    match parts.next()? {
+        b"t" | b"T" | b"w" | b"W" => {}
+        _ => return None,
+    }
+    let name = parts.next()?;
+    let name = name.split(|b| *b == b'\t').next()?;
+    if name.is_empty() {
+        return None;
+    }
+    Some((addr, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        assert_eq!(
+            parse_line(b"ffffffff81000000 T _text"),
+            Some((0xffffffff81000000, &b"_text"[..]))
+        );
+        assert_eq!(
+            parse_line(b"ffffffffc0a01000 t nft_do_chain\t[nf_tables]"),
+            Some((0xffffffffc0a01000, &b"nft_do_chain"[..]))
+        );
+        assert_eq!(parse_line(b"ffffffff82a00000 D jiffies"), None);
+        assert_eq!(parse_line(b"ffffffff81000000 T"), None);
+        assert_eq!(parse_line(b""), None);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn lookup() {
+        let mut kallsyms = Kallsyms::default();
+        kallsyms.loaded = true;
+        kallsyms.parse(
+            b"ffffffff81000200 T second\n\
+              ffffffff81000100 T first\n\
+              ffffffff82a00000 D jiffies\n",
+        );
+        assert_eq!(kallsyms.lookup(0xffffffff81000180), Some(&b"first"[..]));
+        assert_eq!(kallsyms.lookup(0xffffffff81000200), Some(&b"second"[..]));
+        assert_eq!(kallsyms.lookup(0xffffffff81000000), None);
+        assert_eq!(kallsyms.lookup(0x1000), None);
+    }
+
+    #[test]
+    fn hidden_addresses() {
+        let mut kallsyms = Kallsyms::default();
+        kallsyms.parse(
+            b"0000000000000000 T first\n\
+              0000000000000000 T second\n",
+        );
+        assert!(kallsyms.entries.is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+// AIX lists the loaded modules with `loadquery(L_GETINFO)`, which fills a
+// buffer with an `ld_info` for each of them, starting with the executable.
+// Each tells where the module's text is loaded and its file name, followed
+// by the name of the archive member it was loaded from, if any.
+
+use super::mystd::ffi::{CStr, OsStr, OsString};
+use super::mystd::io;
+use super::mystd::os::unix::prelude::*;
+use super::mystd::path::Path;
+use super::xcoff;
+use super::{Library, LibrarySegment, Vec};
+use alloc::vec;
+use core::mem;
+
+#[repr(C)]
+struct LdInfo {
+    ldinfo_next: libc::c_uint,
+    ldinfo_flags: libc::c_uint,
+    ldinfo_fd: libc::c_long,
+    ldinfo_textorg: *mut libc::c_void,
+    ldinfo_textsize: libc::c_ulong,
+    ldinfo_dataorg: *mut libc::c_void,
+    ldinfo_datasize: libc::c_ulong,
+    ldinfo_filename: [libc::c_char; 2],
+}
+
+const L_GETINFO: libc::c_int = 2;
+const ENOMEM: i32 = 12;
+
+extern "C" {
+    fn loadquery(flags: libc::c_int, buf: *mut libc::c_void, buflen: libc::c_uint) -> libc::c_int;
+}
+
+pub(super) fn native_libraries() -> Vec<Library> {
+    let mut libs = Vec::new();
+
+    // The buffer is grown until everything fits. It's made of `u64`s to be
+    // aligned for `ld_info`.
+    let mut buffer = vec![0u64; 1024];
+    loop {
+        let len = (buffer.len() * mem::size_of::<u64>()) as libc::c_uint;
+        if unsafe { loadquery(L_GETINFO, buffer.as_mut_ptr().cast(), len) } != -1 {
+            break;
+        }
+        if io::Error::last_os_error().raw_os_error() != Some(ENOMEM) {
+            return libs;
+        }
+        let len = buffer.len() * 2;
+        buffer.resize(len, 0);
+    }
+
+    let mut info = buffer.as_ptr() as *const LdInfo;
+    loop {
+        let (name, textorg, next) = unsafe {
+            let file = (*info).ldinfo_filename.as_ptr();
+            let file = CStr::from_ptr(file);
+            let member = CStr::from_ptr(file.as_ptr().add(file.to_bytes().len() + 1));
+            let mut name = OsString::from(OsStr::from_bytes(file.to_bytes()));
+            if !member.to_bytes().is_empty() {
+                name.push("(");
+                name.push(OsStr::from_bytes(member.to_bytes()));
+                name.push(")");
+            }
+            (
+                name,
+                (*info).ldinfo_textorg as usize,
+                (*info).ldinfo_next as usize,
+            )
+        };
+
+        // The text is mapped along with the headers before it, so it's
+        // loaded at its offset in the file from where the mapping starts.
+        if let Some(image) = xcoff::parse_image(Path::new(&name)) {
+            let bias = (textorg + image.offset as usize).wrapping_sub(image.base as usize);
+            libs.push(Library {
+                name,
+                segments: vec![LibrarySegment {
+                    stated_virtual_memory_address: image.base as usize,
+                    len: image.size as usize,
+                }],
+                bias,
+                slide: bias,
+                build_id: None,
+                registered: None,
+            });
+        }
+
+        if next == 0 {
+            break;
+        }
+        info = unsafe { (info as *const u8).add(next) as *const LdInfo };
+    }
+    libs
+}
@@ -6,6 +6,12 @@ use super::mystd::borrow::ToOwned;
 use super::mystd::env;
 use super::mystd::ffi::{CStr, OsStr};
 use super::mystd::os::unix::prelude::*;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "netbsd"))]
+use super::mystd::path::Path;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use super::mystd::{fs, path::PathBuf};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use super::{elf, proc_maps, Mapping};
 use super::{Library, LibrarySegment, OsString, Vec};
 use core::slice;
 
@@ -14,9 +20,54 @@ pub(super) fn native_libraries() -> Vec<Library> {
     unsafe {
         libc::dl_iterate_phdr(Some(callback), &mut ret as *mut Vec<_> as *mut _);
     }
+    #[cfg(any(target_os = "android", target_os = "netbsd"))]
+    resolve_names(&mut ret);
     return ret;
 }
 
+/// Returns how many objects the loader has loaded and unloaded so far, which
+/// changes with every `dlopen` and `dlclose` that does something.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "fuchsia",
+    target_os = "netbsd",
+))]
+pub(super) fn loader_generation() -> Option<(u64, u64)> {
+    let mut ret = None;
+    unsafe {
+        libc::dl_iterate_phdr(
+            Some(generation_callback),
+            &mut ret as *mut Option<(u64, u64)> as *mut _,
+        );
+    }
+    ret
+}
+
+// `ret` should be a valid pointer to an `Option<(u64, u64)>`.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "fuchsia",
+    target_os = "netbsd",
+))]
+unsafe extern "C" fn generation_callback(
+    info: *mut libc::dl_phdr_info,
+    size: libc::size_t,
+    ret: *mut libc::c_void,
+) -> libc::c_int {
+    // Old versions of glibc pass a shorter `dl_phdr_info` without the
+    // counters.
+    if size >= core::mem::size_of::<libc::dl_phdr_info>() {
+        let info = &*info;
+        *(ret as *mut Option<(u64, u64)>) = Some((info.dlpi_adds, info.dlpi_subs));
+    }
+    // The counters are the same for every object, so stop right away.
+    1
+}
+
 // `info` should be a valid pointers.
 // `vec` should be a valid pointer to a `std::Vec`.
 unsafe extern "C" fn callback(
@@ -48,6 +99,173 @@ unsafe extern "C" fn callback(
             })
             .collect(),
         bias: info.dlpi_addr as usize,
+        slide: info.dlpi_addr as usize,
+        build_id: build_id(
+            headers
+                .iter()
+                .filter(|header| header.p_type == libc::PT_NOTE)
+                .map(|header| {
+                    let start = (info.dlpi_addr as usize).wrapping_add(header.p_vaddr as usize);
+                    (start, header.p_memsz as usize, header.p_align as usize)
+                }),
+        ),
+        registered: None,
     });
     0
 }
+
+/// Maps the object file `lib` was loaded from.
+///
+/// The file at `lib`'s name may have been deleted or replaced since, say when
+/// upgrading a running program, and files created with `memfd_create` never
+/// had a name to begin with. The kernel still has the file though, and makes
+/// it available at `/proc/self/map_files` under the address range of each of
+/// its mappings, so that's used instead whenever `/proc/self/maps` says the
+/// file has been deleted, the file at the name is for another architecture,
+/// or opening the name doesn't work.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(super) fn map_library(lib: &Library) -> Option<Mapping> {
+    let name: &Path = lib.name.as_ref();
+    let native = elf::is_native_object(name);
+    let (file, deleted) = match lib_map_file(lib) {
+        Some(found) => found,
+        None if native => return Mapping::new(name),
+        None => return None,
+    };
+    if !deleted && native {
+        if let Some(mapping) = Mapping::new(name) {
+            return Some(mapping);
+        }
+    }
+    let name = name.as_os_str().as_bytes();
+    let name = if name.ends_with(DELETED) {
+        &name[..name.len() - DELETED.len()]
+    } else {
+        name
+    };
+    Mapping::new_from(Path::new(OsStr::from_bytes(name)), &file)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const DELETED: &[u8] = b" (deleted)";
+
+/// Finds the path in `/proc/self/map_files` of the first mapping of `lib`, and
+/// whether `/proc/self/maps` says its file has been deleted.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn lib_map_file(lib: &Library) -> Option<(PathBuf, bool)> {
+    let addr = lib
+        .segments
+        .iter()
+        .filter(|s| s.len > 0)
+        .map(|s| s.stated_virtual_memory_address.wrapping_add(lib.bias))
+        .min()?;
+    let maps = fs::read("/proc/self/maps").ok()?;
+    let entry = proc_maps::parse(&maps).find(|e| e.start <= addr && addr < e.end)?;
+    let mut file = PathBuf::from("/proc/self/map_files");
+    file.push(OsStr::from_bytes(entry.range));
+    Some((file, entry.path.ends_with(DELETED)))
+}
+
+/// Replaces the names of libraries which aren't paths with the paths of the
+/// files they're mapped from.
+///
+/// Before Android 6.0 the loader only knows libraries by the name they were
+/// loaded with, which usually isn't a path, and libraries loaded into other
+/// linker namespaces may be reported that way too. NetBSD's loader names
+/// libraries by the path they were found at, which is relative if the
+/// directory in `LD_LIBRARY_PATH` or given to `dlopen` was.
+#[cfg(any(target_os = "android", target_os = "netbsd"))]
+fn resolve_names(libs: &mut [Library]) {
+    let relative = |lib: &Library| !lib.name.is_empty() && !Path::new(&lib.name).is_absolute();
+    if !libs.iter().any(relative) {
+        return;
+    }
+    let mut maps = match mapped_files() {
+        Some(maps) => maps,
+        None => return,
+    };
+    // Mappings don't overlap, so sorted by their start they're sorted by
+    // their end too, and a binary search finds the one with an address.
+    maps.sort_unstable_by_key(|(start, _, _)| *start);
+    for lib in libs.iter_mut().filter(|lib| relative(lib)) {
+        let addr = match lib
+            .segments
+            .iter()
+            .filter(|s| s.len > 0)
+            .map(|s| s.stated_virtual_memory_address.wrapping_add(lib.bias))
+            .min()
+        {
+            Some(addr) => addr,
+            None => continue,
+        };
+        let i = match maps.binary_search_by_key(&addr, |(start, _, _)| *start) {
+            Ok(i) => i,
+            Err(0) => continue,
+            Err(i) => i - 1,
+        };
+        let (_, end, path) = &maps[i];
+        if addr < *end {
+            lib.name = path.clone();
+        }
+    }
+}
+
+/// Returns the address ranges of the mappings of files, and the files' paths.
+///
+/// Only mappings of files by their absolute paths are returned. Processes can
+/// have many thousands of other mappings, like those of allocators' arenas,
+/// which would only make libraries slower to look up.
+#[cfg(target_os = "android")]
+fn mapped_files() -> Option<Vec<(usize, usize, OsString)>> {
+    let maps = fs::read("/proc/self/maps").ok()?;
+    Some(
+        proc_maps::parse(&maps)
+            .filter(|e| e.path.starts_with(b"/"))
+            .map(|e| (e.start, e.end, OsStr::from_bytes(&e.path).to_owned()))
+            .collect(),
+    )
+}
+
+/// Returns the address ranges of the mappings of files by their absolute
+/// paths, and the paths, like on Android.
+#[cfg(target_os = "netbsd")]
+fn mapped_files() -> Option<Vec<(usize, usize, OsString)>> {
+    Some(
+        super::vm_map::entries()?
+            .into_iter()
+            .filter(|e| e.path.as_bytes().starts_with(b"/"))
+            .map(|e| (e.start, e.end, e.path))
+            .collect(),
+    )
+}
+
+// Note segments are loaded into memory, so the GNU build-id can be read
+// without having to open the file. `notes` yields the address, size and
+// alignment of each note segment.
+unsafe fn build_id(notes: impl Iterator<Item = (usize, usize, usize)>) -> Option<Vec<u8>> {
+    const NT_GNU_BUILD_ID: u32 = 3;
+
+    for (start, len, align) in notes {
+        let mut data = slice::from_raw_parts(start as *const u8, len);
+        let align = if align == 8 { 8 } else { 4 };
+        let padded = |len: usize| len.checked_add(align - 1).map(|l| l & !(align - 1));
+        while data.len() >= 12 {
+            let word =
+                |i: usize| u32::from_ne_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+            let (namesz, descsz, kind) = (word(0) as usize, word(4) as usize, word(8));
+            let desc_start = match padded(namesz) {
+                Some(n) => 12 + n,
+                None => break,
+            };
+            let next = match padded(descsz).and_then(|n| desc_start.checked_add(n)) {
+                Some(next) if next <= data.len() => next,
+                _ => break,
+            };
+            if kind == NT_GNU_BUILD_ID && &data[12..12 + namesz] == b"GNU\0" {
+                return Some(data[desc_start..desc_start + descsz].to_vec());
+            }
+            data = &data[next..];
+        }
+    }
+    None
+}
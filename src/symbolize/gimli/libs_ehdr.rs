@@ -0,0 +1,69 @@
+// Redox executables are statically linked, and Hermit applications are
+// linked into a single image with the kernel's library OS. Either way the
+// only module is the executable, which is found through `__ehdr_start`, the
+// linker-provided symbol for where its ELF header is loaded. Its program
+// headers follow in memory and tell where the segments are.
+
+use super::mystd::env;
+use super::mystd::path::PathBuf;
+use super::{Library, LibrarySegment, Vec};
+use core::{mem, slice};
+use object::read::elf::{FileHeader, ProgramHeader};
+use object::NativeEndian;
+
+#[cfg(target_pointer_width = "32")]
+type Elf = object::elf::FileHeader32<NativeEndian>;
+#[cfg(target_pointer_width = "64")]
+type Elf = object::elf::FileHeader64<NativeEndian>;
+
+extern "C" {
+    static __ehdr_start: u8;
+}
+
+pub(super) fn native_libraries() -> Vec<Library> {
+    let mut ret = Vec::new();
+    ret.extend(unsafe { executable() });
+    ret
+}
+
+unsafe fn executable() -> Option<Library> {
+    let start = &__ehdr_start as *const u8;
+    let header = slice::from_raw_parts(start, mem::size_of::<Elf>());
+    let elf = Elf::parse(header).ok()?;
+    let endian = elf.endian().ok()?;
+    let phoff = elf.e_phoff(endian) as usize;
+    let phnum = usize::from(elf.e_phnum(endian));
+    let phentsize = mem::size_of::<<Elf as FileHeader>::ProgramHeader>();
+    let len = phoff.checked_add(phnum.checked_mul(phentsize)?)?;
+    let data = slice::from_raw_parts(start, len);
+    let headers = elf.program_headers(endian, data).ok()?;
+
+    // The header is at the start of the loadable segment which starts at file
+    // offset zero, so it's loaded where that segment is stated to be plus the
+    // bias.
+    let first = headers
+        .iter()
+        .find(|h| h.p_type(endian) == object::elf::PT_LOAD && h.p_offset(endian) == 0)?;
+    let bias = (start as usize).wrapping_sub(first.p_vaddr(endian) as usize);
+
+    // Hermit has no `current_exe`, but the application's path on the host
+    // is what it's started with.
+    let path = env::current_exe()
+        .ok()
+        .or_else(|| env::args_os().next().map(PathBuf::from))?;
+    Some(Library {
+        name: path.into_os_string(),
+        segments: headers
+            .iter()
+            .filter(|h| h.p_type(endian) == object::elf::PT_LOAD)
+            .map(|h| LibrarySegment {
+                len: h.p_memsz(endian) as usize,
+                stated_virtual_memory_address: h.p_vaddr(endian) as usize,
+            })
+            .collect(),
+        bias,
+        slide: bias,
+        build_id: None,
+        registered: None,
+    })
+}
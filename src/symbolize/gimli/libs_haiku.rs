@@ -38,6 +38,9 @@ pub(super) fn native_libraries() -> Vec<Library> {
                 name: name,
                 segments: segments,
                 bias: info.text as usize,
+                slide: info.text as usize,
+                build_id: None,
+                registered: None,
             });
 
             status = libc::get_next_image_info(libc::B_CURRENT_TEAM, &mut cookie, &mut info);
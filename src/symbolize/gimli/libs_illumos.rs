@@ -5,6 +5,8 @@ use super::{Library, LibrarySegment, Vec};
 use core::mem;
 use object::NativeEndian;
 
+#[cfg(target_pointer_width = "32")]
+use object::elf::{FileHeader32 as FileHeader, ProgramHeader32 as ProgramHeader};
 #[cfg(target_pointer_width = "64")]
 use object::elf::{FileHeader64 as FileHeader, ProgramHeader64 as ProgramHeader};
 
@@ -56,19 +58,33 @@ pub(super) fn native_libraries() -> Vec<Library> {
         let bytes = unsafe { CStr::from_ptr((*l).l_name) }.to_bytes();
         let name = OsStr::from_bytes(bytes).to_owned();
 
-        // The base address of the object loaded into memory:
-        let addr = unsafe { (*l).l_addr };
+        // Unlike with glibc, the base address in the link map is where the
+        // object is mapped into memory, starting with its ELF header, rather
+        // than the bias:
+        let addr = unsafe { (*l).l_addr } as usize;
 
         // Use the ELF header for this object to locate the program
         // header:
-        let e: *const EHdr = unsafe { (*l).l_addr as *const EHdr };
-        let phoff = unsafe { (*e).e_phoff }.get(NativeEndian);
+        let e: *const EHdr = addr as *const EHdr;
+        let phoff = unsafe { (*e).e_phoff }.get(NativeEndian) as usize;
         let phnum = unsafe { (*e).e_phnum }.get(NativeEndian);
-        let etype = unsafe { (*e).e_type }.get(NativeEndian);
 
         let phdr: *const PHdr = (addr + phoff) as *const PHdr;
         let phdr = unsafe { core::slice::from_raw_parts(phdr, phnum as usize) };
 
+        // The header is at the start of the loadable segment which starts at
+        // file offset zero, so the bias is the difference between where that
+        // is and where the segment is stated to be. This is zero for
+        // executables, whose addresses are absolute, and the base address for
+        // libraries linked at zero, but prelinked libraries and static PIEs
+        // are neither.
+        let first = phdr.iter().find(|p| {
+            p.p_type.get(NativeEndian) == object::elf::PT_LOAD && p.p_offset.get(NativeEndian) == 0
+        });
+        let bias = match first {
+            Some(p) => addr.wrapping_sub(p.p_vaddr.get(NativeEndian) as usize),
+            None => addr,
+        };
         libs.push(Library {
             name,
             segments: phdr
@@ -82,14 +98,10 @@ pub(super) fn native_libraries() -> Vec<Library> {
                     }
                 })
                 .collect(),
-            bias: if etype == object::elf::ET_EXEC {
-                // Program header addresses for the base executable are
-                // already absolute.
-                0
-            } else {
-                // Other addresses are relative to the object base.
-                addr as usize
-            },
+            bias,
+            slide: bias,
+            build_id: None,
+            registered: None,
         });
 
         l = unsafe { (*l).l_next };
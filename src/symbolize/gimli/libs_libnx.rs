@@ -21,6 +21,9 @@ pub(super) fn native_libraries() -> Vec<Library> {
         name: path.into(),
         segments,
         bias,
+        slide: bias,
+        build_id: None,
+        registered: None,
     });
 
     ret
@@ -1,9 +1,11 @@
 #![allow(deprecated)]
 
+use super::macho::Arch;
 use super::mystd::ffi::{CStr, OsStr};
 use super::mystd::os::unix::prelude::*;
+use super::mystd::path::Path;
 use super::mystd::prelude::v1::*;
-use super::{Library, LibrarySegment};
+use super::{Library, LibrarySegment, Mapping};
 use core::convert::TryInto;
 use core::mem;
 
@@ -68,7 +70,11 @@ fn native_library(i: u32) -> Option<Library> {
     let mut segments = Vec::new();
     let mut first_text = 0;
     let mut text_fileoff_zero = false;
+    let mut uuid = None;
     while let Some(cmd) = load_commands.next().ok()? {
+        if let Some(cmd) = cmd.uuid().ok()? {
+            uuid = Some(cmd.uuid.to_vec());
+        }
         if let Some((seg, _)) = cmd.segment_32().ok()? {
             if seg.name() == b"__TEXT" {
                 first_text = segments.len();
@@ -129,18 +135,73 @@ fn native_library(i: u32) -> Option<Library> {
     // always be able to tweak this over time if necessary.
     //
     // For some more information see #318
-    let mut slide = unsafe { libc::_dyld_get_image_vmaddr_slide(i) as usize };
+    let slide = unsafe { libc::_dyld_get_image_vmaddr_slide(i) as usize };
+    let mut bias = slide;
     if !text_fileoff_zero {
         let adjust = segments[first_text].stated_virtual_memory_address;
         for segment in segments.iter_mut() {
             segment.stated_virtual_memory_address -= adjust;
         }
-        slide += adjust;
+        bias += adjust;
     }
 
     Some(Library {
         name: OsStr::from_bytes(name.to_bytes()).to_owned(),
         segments,
-        bias: slide,
+        bias,
+        slide,
+        build_id: uuid,
+        registered: None,
     })
 }
+
+/// Maps the object file of `lib`, or if it has none, as the system libraries
+/// which are only in the dyld shared cache since macOS 11, reads its symbols
+/// from the cache in memory.
+///
+/// The file is used for the architecture `lib` was loaded as, which picks the
+/// slice of a universal binary, say arm64e rather than arm64 for the system
+/// libraries, and rules out files which have been replaced by ones for another
+/// architecture.
+pub(super) fn map_library(lib: &Library) -> Option<Mapping> {
+    let path: &Path = lib.name.as_ref();
+    let header = image_header(lib);
+    if !path.exists() {
+        if let Some(header) = header {
+            // See `native_library` for the difference between the bias and
+            // the slide.
+            let mapping = unsafe {
+                Mapping::from_shared_cache(
+                    header as *const u8,
+                    lib.slide,
+                    lib.bias.wrapping_sub(lib.slide),
+                )
+            };
+            if mapping.is_some() {
+                return mapping;
+            }
+        }
+    }
+    let arch = header
+        .map(|header| unsafe { Arch::new((*header).cputype as u32, (*header).cpusubtype as u32) });
+    Mapping::new_for_arch(path, arch)
+}
+
+/// Finds the header of the image `lib` is for in memory.
+fn image_header(lib: &Library) -> Option<*const libc::mach_header> {
+    let images = unsafe { libc::_dyld_image_count() };
+    for i in 0..images {
+        unsafe {
+            let name = libc::_dyld_get_image_name(i);
+            if name.is_null() || CStr::from_ptr(name).to_bytes() != lib.name.as_bytes() {
+                continue;
+            }
+            let header = libc::_dyld_get_image_header(i);
+            if header.is_null() {
+                return None;
+            }
+            return Some(header);
+        }
+    }
+    None
+}
@@ -0,0 +1,73 @@
+// Android before 5.0 (API level 21) doesn't have `dl_iterate_phdr`, so the
+// loaded libraries are found through `/proc/self/maps` instead, with the
+// mappings of each ELF image coalesced into one object. The start of the file
+// is usually mapped too, with the ELF header and the program headers, and
+// otherwise they're read from the file.
+
+use super::mystd::borrow::ToOwned;
+use super::mystd::ffi::OsStr;
+use super::mystd::fs;
+use super::mystd::os::unix::prelude::*;
+use super::mystd::path::Path;
+use super::{proc_maps, Library, LibrarySegment, Vec};
+use core::slice;
+use object::read::elf::{FileHeader, ProgramHeader};
+use object::NativeEndian;
+
+#[cfg(target_pointer_width = "32")]
+type Elf = object::elf::FileHeader32<NativeEndian>;
+#[cfg(target_pointer_width = "64")]
+type Elf = object::elf::FileHeader64<NativeEndian>;
+
+pub(super) fn native_libraries() -> Vec<Library> {
+    let maps = match fs::read("/proc/self/maps") {
+        Ok(maps) => maps,
+        Err(_) => return Vec::new(),
+    };
+    proc_maps::objects(&maps)
+        .iter()
+        .filter(|o| o.path.starts_with(b"/"))
+        .filter_map(|o| match o.header() {
+            Some((start, end)) => unsafe {
+                library(o, slice::from_raw_parts(start as *const u8, end - start))
+            },
+            None => {
+                let map = super::open(Path::new(OsStr::from_bytes(&o.path)))?.map()?;
+                library(o, &map)
+            }
+        })
+        .collect()
+}
+
+// `data` is the start of the file which `object` is the mappings of.
+fn library(object: &proc_maps::MapsObject<'_>, data: &[u8]) -> Option<Library> {
+    let elf = Elf::parse(data).ok()?;
+    let endian = elf.endian().ok()?;
+    let headers = elf.program_headers(endian, data).ok()?;
+    let bias = object.bias(
+        headers
+            .iter()
+            .filter(|h| h.p_type(endian) == object::elf::PT_LOAD)
+            .map(|h| {
+                (
+                    h.p_offset(endian) as usize,
+                    h.p_filesz(endian) as usize,
+                    h.p_vaddr(endian) as usize,
+                )
+            }),
+    )?;
+    Some(Library {
+        name: OsStr::from_bytes(&object.path).to_owned(),
+        segments: headers
+            .iter()
+            .map(|h| LibrarySegment {
+                len: h.p_memsz(endian) as usize,
+                stated_virtual_memory_address: h.p_vaddr(endian) as usize,
+            })
+            .collect(),
+        bias,
+        slide: bias,
+        build_id: None,
+        registered: None,
+    })
+}
@@ -0,0 +1,125 @@
+// Finding the libraries loaded into another process, for which
+// `dl_iterate_phdr` is of no help. Like on Android before 5.0, they're found
+// through the process's `/proc/<pid>/maps`, with the mappings of each ELF
+// image coalesced into one object. The headers can't be read from the
+// process's memory though, so they're read from the file instead, which is
+// also where its build-id comes from.
+
+use super::mystd::borrow::ToOwned;
+use super::mystd::ffi::OsStr;
+use super::mystd::fs;
+use super::mystd::os::unix::prelude::*;
+use super::mystd::path::{Path, PathBuf};
+use super::{proc_maps, Library, LibrarySegment, MapData, Vec};
+use object::read::elf::{FileHeader, ProgramHeader};
+use object::NativeEndian;
+
+#[cfg(target_pointer_width = "32")]
+type Elf = object::elf::FileHeader32<NativeEndian>;
+#[cfg(target_pointer_width = "64")]
+type Elf = object::elf::FileHeader64<NativeEndian>;
+
+const DELETED: &[u8] = b" (deleted)";
+
+/// Returns the libraries loaded into the process `pid`, starting with its
+/// executable, or nothing if its mappings can't be read.
+pub(super) fn process_libraries(pid: u32) -> Vec<Library> {
+    let proc = PathBuf::from(format!("/proc/{}", pid));
+    let maps = match fs::read(proc.join("maps")) {
+        Ok(maps) => maps,
+        Err(_) => return Vec::new(),
+    };
+    let mut libs = proc_maps::objects(&maps)
+        .into_iter()
+        .filter(|o| o.path.starts_with(b"/"))
+        .filter_map(|o| {
+            let deleted = o.path.ends_with(DELETED);
+            let path = if deleted {
+                &o.path[..o.path.len() - DELETED.len()]
+            } else {
+                &o.path[..]
+            };
+            let path = Path::new(OsStr::from_bytes(path));
+            // A file which has been deleted since it was mapped can still be
+            // opened through the mapping, though that takes privileges.
+            let map_file = proc.join("map_files").join(OsStr::from_bytes(o.range));
+            let source = if deleted {
+                super::open(&map_file)
+            } else {
+                super::open(path).or_else(|| super::open(&map_file))
+            };
+            library(&o, path, source?.map()?)
+        })
+        .collect::<Vec<_>>();
+
+    if let Ok(exe) = fs::read_link(proc.join("exe")) {
+        let exe = exe.as_os_str().as_bytes();
+        let exe = if exe.ends_with(DELETED) {
+            &exe[..exe.len() - DELETED.len()]
+        } else {
+            exe
+        };
+        let exe = OsStr::from_bytes(exe);
+        if let Some(i) = libs.iter().position(|lib| lib.name == exe) {
+            let exe = libs.remove(i);
+            libs.insert(0, exe);
+        }
+    }
+    libs
+}
+
+// `data` is the file at `path`, which `object` is the mappings of.
+fn library(object: &proc_maps::MapsObject<'_>, path: &Path, data: MapData) -> Option<Library> {
+    let elf = Elf::parse(&*data).ok()?;
+    let endian = elf.endian().ok()?;
+    let headers = elf.program_headers(endian, &*data).ok()?;
+    let bias = object.bias(load_segments(endian, headers))?;
+
+    let mut build_id = None;
+    for header in headers {
+        let mut notes = match header.notes(endian, &*data) {
+            Ok(Some(notes)) => notes,
+            _ => continue,
+        };
+        while let Ok(Some(note)) = notes.next() {
+            if note.name() == object::elf::ELF_NOTE_GNU
+                && note.n_type(endian) == object::elf::NT_GNU_BUILD_ID
+            {
+                build_id = Some(note.desc().to_vec());
+            }
+        }
+    }
+
+    Some(Library {
+        name: path.as_os_str().to_owned(),
+        segments: headers
+            .iter()
+            .map(|h| LibrarySegment {
+                len: h.p_memsz(endian) as usize,
+                stated_virtual_memory_address: h.p_vaddr(endian) as usize,
+            })
+            .collect(),
+        bias,
+        slide: bias,
+        build_id,
+        registered: None,
+    })
+}
+
+/// Returns the file offset, size in the file and address of each loadable
+/// segment in `headers`, for `MapsObject::bias`.
+fn load_segments<'a>(
+    endian: NativeEndian,
+    headers: &'a [<Elf as FileHeader>::ProgramHeader],
+) -> impl Iterator<Item = (usize, usize, usize)> + 'a {
+    headers
+        .iter()
+        .filter(move |h| h.p_type(endian) == object::elf::PT_LOAD)
+        .map(move |h| {
+            (
+                h.p_offset(endian) as usize,
+                h.p_filesz(endian) as usize,
+                h.p_vaddr(endian) as usize,
+            )
+        })
+}
@@ -1,6 +1,9 @@
 use super::super::super::windows::*;
+#[cfg(target_os = "cygwin")]
+use super::mystd::os::unix::prelude::*;
+#[cfg(windows)]
 use super::mystd::os::windows::prelude::*;
-use super::{coff, mmap, Library, LibrarySegment, OsString};
+use super::{coff, open, Library, LibrarySegment, OsString};
 use alloc::vec;
 use alloc::vec::Vec;
 use core::mem;
@@ -45,7 +48,7 @@ unsafe fn load_library(me: &MODULEENTRY32W) -> Option<Library> {
         .iter()
         .position(|i| *i == 0)
         .unwrap_or(me.szExePath.len());
-    let name = OsString::from_wide(&me.szExePath[..pos]);
+    let name = module_path(&me.szExePath[..pos])?;
 
     // MinGW libraries currently don't support ASLR
     // (rust-lang/rust#16514), but DLLs can still be relocated around in
@@ -75,15 +78,60 @@ unsafe fn load_library(me: &MODULEENTRY32W) -> Option<Library> {
     //
     // For now it appears that unlike ELF/MachO we can make do with one
     // segment per library, using `modBaseSize` as the whole size.
-    let mmap = mmap(name.as_ref())?;
+    let mmap = open(name.as_ref())?.map()?;
     let image_base = coff::get_image_base(&mmap)?;
     let base_addr = me.modBaseAddr as usize;
+    let bias = base_addr.wrapping_sub(image_base);
     Some(Library {
         name,
-        bias: base_addr.wrapping_sub(image_base),
+        bias,
+        slide: bias,
+        build_id: None,
+        registered: None,
         segments: vec![LibrarySegment {
             stated_virtual_memory_address: image_base,
             len: me.modBaseSize as usize,
         }],
     })
 }
+
+#[cfg(windows)]
+fn module_path(path: &[u16]) -> Option<OsString> {
+    Some(OsString::from_wide(path))
+}
+
+// Cygwin programs open files by POSIX paths, which the Windows paths of
+// modules are converted to.
+#[cfg(target_os = "cygwin")]
+fn module_path(path: &[u16]) -> Option<OsString> {
+    const CCP_WIN_W_TO_POSIX: libc::c_uint = 3;
+
+    extern "C" {
+        fn cygwin_conv_path(
+            what: libc::c_uint,
+            from: *const libc::c_void,
+            to: *mut libc::c_void,
+            size: libc::size_t,
+        ) -> libc::ssize_t;
+    }
+
+    let mut from = path.to_vec();
+    from.push(0);
+    let from = from.as_ptr() as *const libc::c_void;
+    unsafe {
+        // Called without a buffer, this returns the size needed for the
+        // converted path and its terminating NUL.
+        let size = cygwin_conv_path(CCP_WIN_W_TO_POSIX, from, core::ptr::null_mut(), 0);
+        if size <= 0 {
+            return None;
+        }
+        let mut to = vec![0u8; size as usize];
+        let to_ptr = to.as_mut_ptr() as *mut libc::c_void;
+        if cygwin_conv_path(CCP_WIN_W_TO_POSIX, from, to_ptr, to.len()) != 0 {
+            return None;
+        }
+        let len = to.iter().position(|b| *b == 0).unwrap_or(to.len());
+        to.truncate(len);
+        Some(OsString::from_vec(to))
+    }
+}
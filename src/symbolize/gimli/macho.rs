@@ -1,5 +1,10 @@
-use super::{Box, Context, Mapping, Path, Stash, Vec};
+use super::mystd::env;
+use super::mystd::path::PathBuf;
+use super::{Box, Context, MapData, Mapping, Path, Stash, Vec};
+use alloc::vec;
 use core::convert::TryInto;
+use core::mem;
+use core::slice;
 use object::macho;
 use object::read::macho::{MachHeader, Nlist, Section, Segment as _};
 use object::{Bytes, NativeEndian};
@@ -17,39 +22,249 @@ impl Mapping {
     // different implementation of the function here. On OSX we need to go
     // probing the filesystem for a bunch of files.
     pub fn new(path: &Path) -> Option<Mapping> {
+        Mapping::new_for_arch(path, None)
+    }
+
+    /// Like `new`, but for an image of the architecture `arch`, whose slice is
+    /// the one used if the file is a universal binary, and which the file has
+    /// to be for otherwise.
+    pub(super) fn new_for_arch(path: &Path, arch: Option<Arch>) -> Option<Mapping> {
         // First up we need to load the unique UUID which is stored in the macho
         // header of the file we're reading, specified at `path`.
-        let map = super::mmap(path)?;
-        let (macho, data) = find_header(&map)?;
+        let map = super::open(path)?.map()?;
+        let (macho, data) = find_header(&map, arch)?;
         let endian = macho.endian().ok()?;
         let uuid = macho.uuid(endian, data, 0).ok()?;
 
-        // Next we need to look for a `*.dSYM` file. For now we just probe the
-        // containing directory and look around for something that matches
-        // `*.dSYM`. Once it's found we root through the dwarf resources that it
-        // contains and try to find a macho file which has a matching UUID as
-        // the one of our own file. If we find a match that's the dwarf file we
-        // want to return.
+        // Next we need to look for a `*.dSYM` bundle. Once it's found we root
+        // through the dwarf resources that it contains and try to find a macho
+        // file which has a matching UUID as the one of our own file. If we
+        // find a match that's the dwarf file we want to return.
         if let Some(uuid) = uuid {
-            if let Some(parent) = path.parent() {
-                if let Some(mapping) = Mapping::load_dsym(parent, uuid) {
-                    return Some(mapping);
-                }
+            if let Some(mapping) = Mapping::find_dsym(path, uuid) {
+                return Some(mapping);
             }
         }
 
         // Looks like nothing matched our UUID, so let's at least return our own
         // file. This should have the symbol table for at least some
         // symbolication purposes.
-        Mapping::mk(map, |data, stash| {
-            let (macho, data) = find_header(data)?;
+        Mapping::mk(map, move |data, stash| {
+            let (macho, data) = find_header(data, arch)?;
             let endian = macho.endian().ok()?;
             let obj = Object::parse(macho, endian, data)?;
             Context::new(stash, obj, None)
         })
     }
 
-    fn load_dsym(dir: &Path, uuid: [u8; 16]) -> Option<Mapping> {
+    /// Creates a mapping from the contents of an object file.
+    ///
+    /// Unlike `new` this doesn't look for a `*.dSYM` bundle.
+    pub fn from_bytes(data: impl Into<MapData>) -> Option<Mapping> {
+        Mapping::mk(data, |data, stash| {
+            let (macho, data) = find_header(data, None)?;
+            let endian = macho.endian().ok()?;
+            let obj = Object::parse(macho, endian, data)?;
+            Context::new(stash, obj, None)
+        })
+    }
+
+    /// Creates a mapping for an image in the dyld shared cache from its symbol
+    /// table in memory, for the system libraries which have no file on disk.
+    ///
+    /// Only the symbols which the image exports are there, as the others are
+    /// kept in the `.symbols` file of the cache, which isn't mapped, and
+    /// there's no debug information. `slide` is how far the cache has been
+    /// moved from its stated addresses, and `adjust` is subtracted from the
+    /// addresses of the symbols, as it was from those of the segments of the
+    /// library.
+    ///
+    /// # Safety
+    ///
+    /// `header` must be the header of an image loaded into this process.
+    pub unsafe fn from_shared_cache(
+        header: *const u8,
+        slide: usize,
+        adjust: usize,
+    ) -> Option<Mapping> {
+        let endian = NativeEndian;
+        let mach = &*(header as *const Mach);
+        if !mach.is_supported() || mach.flags(endian) & macho::MH_DYLIB_IN_CACHE == 0 {
+            return None;
+        }
+        let data = slice::from_raw_parts(
+            header,
+            mem::size_of::<Mach>() + mach.sizeofcmds(endian) as usize,
+        );
+        let mut commands = mach.load_commands(endian, data, 0).ok()?;
+        let mut linkedit = None;
+        let mut symtab = None;
+        while let Ok(Some(command)) = commands.next() {
+            if let Some((segment, _)) = MachSegment::from_command(command).ok()? {
+                if segment.name() == b"__LINKEDIT" {
+                    linkedit = Some((
+                        u64::from(segment.vmaddr(endian)),
+                        u64::from(segment.fileoff(endian)),
+                        u64::from(segment.filesize(endian)),
+                    ));
+                }
+            } else if let Some(command) = command.symtab().ok()? {
+                symtab = Some(command);
+            }
+        }
+        let (vmaddr, fileoff, filesize) = linkedit?;
+        let symtab = symtab?;
+
+        // The offsets in the symbol table command are into the file of the
+        // cache which has the `__LINKEDIT` segment, shared by all images of
+        // the cache, and which is mapped at its address plus the slide. Make
+        // sure that what's read is within it.
+        let linkedit_start = (vmaddr as usize).wrapping_add(slide);
+        let within = |offset: u32, len: usize| -> Option<*const u8> {
+            let start = u64::from(offset).checked_sub(fileoff)?;
+            if start.checked_add(len as u64)? > filesize {
+                return None;
+            }
+            Some(linkedit_start.wrapping_add(start as usize) as *const u8)
+        };
+        let nsyms = symtab.nsyms.get(endian) as usize;
+        let strsize = symtab.strsize.get(endian);
+        let nlists = within(
+            symtab.symoff.get(endian),
+            nsyms.checked_mul(mem::size_of::<MachNlist>())?,
+        )?;
+        let strings = within(symtab.stroff.get(endian), strsize as usize)?;
+        // The cache stays mapped for as long as the process runs.
+        let nlists: &'static [MachNlist] = slice::from_raw_parts(nlists as *const MachNlist, nsyms);
+        let strings: &'static [u8] = slice::from_raw_parts(strings, strsize as usize);
+        let strings = object::read::StringTable::new(strings, 0, u64::from(strsize));
+
+        let mut syms = nlists
+            .iter()
+            .filter_map(|nlist| {
+                let name = nlist.name(endian, strings).ok()?;
+                if !name.is_empty() && nlist.is_definition() {
+                    let addr = u64::from(nlist.n_value(endian)).wrapping_sub(adjust as u64);
+                    Some((name, addr))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        syms.sort_unstable_by_key(|(_, addr)| *addr);
+        Mapping::mk(MapData::Static(&[]), move |_, stash| {
+            Context::new(stash, Object::from_symbols(syms), None)
+        })
+    }
+
+    /// Looks for the `*.dSYM` bundle of the binary at `path`, in the places
+    /// where the build tools put them.
+    fn find_dsym(path: &Path, uuid: [u8; 16]) -> Option<Mapping> {
+        // `dsymutil` names the bundle after the binary and puts it next to
+        // it, except that for binaries inside of an `.app` or other bundle
+        // Xcode puts `Foo.app.dSYM` next to the outermost bundle instead.
+        let mut names = Vec::new();
+        for ancestor in path.ancestors() {
+            let name = match ancestor.file_name() {
+                Some(name) => name,
+                None => break,
+            };
+            if ancestor != path && !is_bundle(ancestor) {
+                continue;
+            }
+            let mut dsym = name.to_os_string();
+            dsym.push(".dSYM");
+            if let Some(parent) = ancestor.parent() {
+                if let Some(mapping) = Mapping::load_dsym(&parent.join(&dsym), uuid) {
+                    return Some(mapping);
+                }
+            }
+            names.push(dsym);
+        }
+
+        // Failing that the bundle may have been renamed, so probe the
+        // containing directory for anything that matches `*.dSYM`.
+        if let Some(parent) = path.parent() {
+            if let Some(mapping) = Mapping::search_dsyms(parent, uuid) {
+                return Some(mapping);
+            }
+        }
+
+        // Binaries may also have been copied out of Xcode's build directories,
+        // which keep their bundles in
+        // `DerivedData/<project>/Build/Products/<configuration>`, or out of an
+        // archive which keeps them in `<name>.xcarchive/dSYMs`.
+        if let Some(xcode) = xcode_dir() {
+            let products = subdirs(&xcode.join("DerivedData"))
+                .into_iter()
+                .flat_map(|project| subdirs(&project.join("Build/Products")));
+            let archives = subdirs(&xcode.join("Archives"))
+                .into_iter()
+                .flat_map(|day| subdirs(&day))
+                .map(|archive| archive.join("dSYMs"));
+            for dir in products.chain(archives) {
+                for name in names.iter() {
+                    if let Some(mapping) = Mapping::load_dsym(&dir.join(name), uuid) {
+                        return Some(mapping);
+                    }
+                }
+            }
+        }
+
+        #[cfg(feature = "mdfind")]
+        {
+            if let Some(mapping) = Mapping::spotlight_dsym(uuid) {
+                return Some(mapping);
+            }
+        }
+
+        None
+    }
+
+    /// Asks Spotlight, which indexes the UUIDs of the `*.dSYM` bundles it
+    /// finds, where the bundle for `uuid` is.
+    #[cfg(feature = "mdfind")]
+    fn spotlight_dsym(uuid: [u8; 16]) -> Option<Mapping> {
+        use super::mystd::ffi::OsStr;
+        use super::mystd::os::unix::prelude::*;
+        use super::mystd::process::{Command, Stdio};
+        use super::String;
+
+        let mut query = String::from("com_apple_xcode_dsym_uuids == ");
+        for (i, byte) in uuid.iter().enumerate() {
+            if i == 4 || i == 6 || i == 8 || i == 10 {
+                query.push('-');
+            }
+            query.push_str(&format!("{:02X}", byte));
+        }
+        let output = Command::new("mdfind")
+            .arg("-0")
+            .arg(&query)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        // The results are either the bundles themselves or the archives
+        // containing them.
+        for result in output.stdout.split(|b| *b == 0) {
+            if result.is_empty() {
+                continue;
+            }
+            let result = Path::new(OsStr::from_bytes(result));
+            if let Some(mapping) = Mapping::load_dsym(result, uuid) {
+                return Some(mapping);
+            }
+            if let Some(mapping) = Mapping::search_dsyms(&result.join("dSYMs"), uuid) {
+                return Some(mapping);
+            }
+        }
+        None
+    }
+
+    fn search_dsyms(dir: &Path, uuid: [u8; 16]) -> Option<Mapping> {
         for entry in dir.read_dir().ok()? {
             let entry = entry.ok()?;
             let filename = match entry.file_name().into_string() {
@@ -59,28 +274,36 @@ impl Mapping {
             if !filename.ends_with(".dSYM") {
                 continue;
             }
-            let candidates = entry.path().join("Contents/Resources/DWARF");
-            if let Some(mapping) = Mapping::try_dsym_candidate(&candidates, uuid) {
+            if let Some(mapping) = Mapping::load_dsym(&entry.path(), uuid) {
                 return Some(mapping);
             }
         }
         None
     }
 
+    fn load_dsym(bundle: &Path, uuid: [u8; 16]) -> Option<Mapping> {
+        Mapping::try_dsym_candidate(&bundle.join("Contents/Resources/DWARF"), uuid)
+    }
+
     fn try_dsym_candidate(dir: &Path, uuid: [u8; 16]) -> Option<Mapping> {
         // Look for files in the `DWARF` directory which have a matching uuid to
         // the original object file. If we find one then we found the debug
-        // information.
+        // information. The `*.dSYM` bundles of universal binaries are universal
+        // too, so every slice is looked at rather than the one of this
+        // architecture, which for the likes of arm64 and arm64e is ambiguous.
         for entry in dir.read_dir().ok()? {
             let entry = entry.ok()?;
-            let map = super::mmap(&entry.path())?;
+            let map = super::open(&entry.path())?.map()?;
             let candidate = Mapping::mk(map, |data, stash| {
-                let (macho, data) = find_header(data)?;
-                let endian = macho.endian().ok()?;
-                let entry_uuid = macho.uuid(endian, data, 0).ok()??;
-                if entry_uuid != uuid {
-                    return None;
-                }
+                let (macho, endian, data) = slices(data).into_iter().find_map(|data| {
+                    let macho = Mach::parse(data, 0).ok()?;
+                    let endian = macho.endian().ok()?;
+                    let entry_uuid = macho.uuid(endian, data, 0).ok()??;
+                    if entry_uuid != uuid {
+                        return None;
+                    }
+                    Some((macho, endian, data))
+                })?;
                 let obj = Object::parse(macho, endian, data)?;
                 Context::new(stash, obj, None)
             });
@@ -93,66 +316,172 @@ impl Mapping {
     }
 }
 
-fn find_header(data: &'_ [u8]) -> Option<(&'_ Mach, &'_ [u8])> {
-    use object::endian::BigEndian;
+/// Returns whether `path` is an application, framework or other bundle, which
+/// have their `*.dSYM` bundle named after them rather than after the binary.
+fn is_bundle(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ["app", "appex", "bundle", "framework", "xpc"].contains(&ext),
+        None => false,
+    }
+}
 
-    let desired_cpu = || {
-        if cfg!(target_arch = "x86") {
-            Some(macho::CPU_TYPE_X86)
+fn xcode_dir() -> Option<PathBuf> {
+    let mut dir = PathBuf::from(env::var_os("HOME")?);
+    dir.push("Library/Developer/Xcode");
+    Some(dir)
+}
+
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    let entries = match dir.read_dir() {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.is_dir())
+        .collect()
+}
+
+/// The architecture of an image, which picks the slice of a universal binary
+/// to use for it.
+#[derive(Clone, Copy)]
+pub(super) struct Arch {
+    cputype: u32,
+    // Any subtype of `cputype` will do if this is `None`.
+    cpusubtype: Option<u32>,
+}
+
+impl Arch {
+    /// The architecture of an image with the given `cputype` and `cpusubtype`
+    /// in its header.
+    pub(super) fn new(cputype: u32, cpusubtype: u32) -> Arch {
+        Arch {
+            cputype,
+            cpusubtype: Some(cpusubtype),
+        }
+    }
+
+    /// The architecture this crate was compiled for, if it has Mach-O images.
+    fn host() -> Option<Arch> {
+        let cputype = if cfg!(target_arch = "x86") {
+            macho::CPU_TYPE_X86
         } else if cfg!(target_arch = "x86_64") {
-            Some(macho::CPU_TYPE_X86_64)
+            macho::CPU_TYPE_X86_64
         } else if cfg!(target_arch = "arm") {
-            Some(macho::CPU_TYPE_ARM)
+            macho::CPU_TYPE_ARM
         } else if cfg!(target_arch = "aarch64") {
-            Some(macho::CPU_TYPE_ARM64)
+            macho::CPU_TYPE_ARM64
         } else {
-            None
+            return None;
+        };
+        Some(Arch {
+            cputype,
+            cpusubtype: None,
+        })
+    }
+
+    fn has_subtype(&self, cpusubtype: u32) -> bool {
+        // The high bits are capabilities, such as the version of the pointer
+        // authentication ABI of arm64e, and not part of the subtype proper.
+        match self.cpusubtype {
+            Some(subtype) => {
+                subtype & !macho::CPU_SUBTYPE_MASK == cpusubtype & !macho::CPU_SUBTYPE_MASK
+            }
+            None => true,
+        }
+    }
+}
+
+/// Finds the object in `data` for `arch`.
+///
+/// Of a universal binary, that's the slice with the same subtype as `arch`, or
+/// failing that the first one of its cputype. A thin object has to be of the
+/// cputype of `arch` too, as symbolizing with another architecture's file only
+/// gives wrong answers. Without `arch` a thin object is used whatever it's
+/// for, so that those of other architectures can be symbolized offline, and
+/// universal binaries use the slice of the architecture of this crate.
+fn find_header(data: &'_ [u8], arch: Option<Arch>) -> Option<(&'_ Mach, &'_ [u8])> {
+    let slices = match fat_slices(data) {
+        Some(slices) => slices,
+        None => {
+            let macho = Mach::parse(data, 0).ok()?;
+            if let Some(arch) = arch {
+                if macho.cputype(macho.endian().ok()?) != arch.cputype {
+                    return None;
+                }
+            }
+            return Some((macho, data));
         }
     };
+    let arch = arch.or_else(Arch::host)?;
+    let mut found = None;
+    for data in slices {
+        let macho = match Mach::parse(data, 0) {
+            Ok(macho) => macho,
+            Err(_) => continue,
+        };
+        let endian = match macho.endian() {
+            Ok(endian) => endian,
+            Err(_) => continue,
+        };
+        if macho.cputype(endian) != arch.cputype {
+            continue;
+        }
+        if arch.has_subtype(macho.cpusubtype(endian)) {
+            return Some((macho, data));
+        }
+        if found.is_none() {
+            found = Some((macho, data));
+        }
+    }
+    found
+}
+
+/// Returns the objects in `data`, which are the slices of a universal binary
+/// or otherwise just `data`.
+fn slices(data: &[u8]) -> Vec<&[u8]> {
+    fat_slices(data).unwrap_or_else(|| vec![data])
+}
+
+/// Returns the slices of the universal binary `data`, or `None` if it isn't
+/// one.
+fn fat_slices(data: &[u8]) -> Option<Vec<&[u8]>> {
+    use object::endian::BigEndian;
 
-    let mut data = Bytes(data);
-    match data
+    let data = Bytes(data);
+    let is_64 = match data
         .clone()
         .read::<object::endian::U32<NativeEndian>>()
         .ok()?
         .get(NativeEndian)
     {
-        macho::MH_MAGIC_64 | macho::MH_CIGAM_64 | macho::MH_MAGIC | macho::MH_CIGAM => {}
-
-        macho::FAT_MAGIC | macho::FAT_CIGAM => {
-            let mut header_data = data;
-            let endian = BigEndian;
-            let header = header_data.read::<macho::FatHeader>().ok()?;
-            let nfat = header.nfat_arch.get(endian);
-            let arch = (0..nfat)
-                .filter_map(|_| header_data.read::<macho::FatArch32>().ok())
-                .find(|arch| desired_cpu() == Some(arch.cputype.get(endian)))?;
-            let offset = arch.offset.get(endian);
-            let size = arch.size.get(endian);
-            data = data
-                .read_bytes_at(offset.try_into().ok()?, size.try_into().ok()?)
-                .ok()?;
-        }
-
-        macho::FAT_MAGIC_64 | macho::FAT_CIGAM_64 => {
-            let mut header_data = data;
-            let endian = BigEndian;
-            let header = header_data.read::<macho::FatHeader>().ok()?;
-            let nfat = header.nfat_arch.get(endian);
-            let arch = (0..nfat)
-                .filter_map(|_| header_data.read::<macho::FatArch64>().ok())
-                .find(|arch| desired_cpu() == Some(arch.cputype.get(endian)))?;
-            let offset = arch.offset.get(endian);
-            let size = arch.size.get(endian);
-            data = data
-                .read_bytes_at(offset.try_into().ok()?, size.try_into().ok()?)
-                .ok()?;
-        }
-
+        macho::FAT_MAGIC | macho::FAT_CIGAM => false,
+        macho::FAT_MAGIC_64 | macho::FAT_CIGAM_64 => true,
         _ => return None,
+    };
+    let mut header_data = data;
+    let endian = BigEndian;
+    let header = header_data.read::<macho::FatHeader>().ok()?;
+    let nfat = header.nfat_arch.get(endian);
+    let mut slices = Vec::new();
+    for _ in 0..nfat {
+        let (offset, size) = if is_64 {
+            let arch = header_data.read::<macho::FatArch64>().ok()?;
+            (arch.offset.get(endian), arch.size.get(endian))
+        } else {
+            let arch = header_data.read::<macho::FatArch32>().ok()?;
+            (
+                u64::from(arch.offset.get(endian)),
+                u64::from(arch.size.get(endian)),
+            )
+        };
+        if let (Ok(offset), Ok(size)) = (offset.try_into(), size.try_into()) {
+            if let Ok(slice) = data.read_bytes_at(offset, size) {
+                slices.push(slice.0);
+            }
+        }
     }
-
-    Mach::parse(data.0, 0).ok().map(|h| (h, data.0))
+    Some(slices)
 }
 
 // This is used both for executables/libraries and source object files.
@@ -164,6 +493,9 @@ pub struct Object<'a> {
     syms_sort_by_name: bool,
     // Only set for executables/libraries, and not the source object files.
     object_map: Option<object::ObjectMap<'a>>,
+    // The modification times the linker recorded for each object in
+    // `object_map`.
+    object_mtimes: Vec<u64>,
     // The outer Option is for lazy loading, and the inner Option allows load errors to be cached.
     object_mappings: Box<[Option<Option<Mapping>>]>,
 }
@@ -176,6 +508,7 @@ impl<'a> Object<'a> {
         let mut syms_sort_by_name = false;
         let mut commands = mach.load_commands(endian, data, 0).ok()?;
         let mut object_map = None;
+        let mut object_mtimes = Vec::new();
         let mut object_mappings = Vec::new();
         while let Ok(Some(command)) = commands.next() {
             if let Some((segment, section_data)) = MachSegment::from_command(command).ok()? {
@@ -205,6 +538,17 @@ impl<'a> Object<'a> {
                 } else {
                     syms.sort_unstable_by_key(|(_, addr)| *addr);
                     let map = symbols.object_map(endian);
+                    // These are in the same order as the objects in the map,
+                    // which come from the same `N_OSO` entries.
+                    object_mtimes = symbols
+                        .iter()
+                        .filter(|nlist: &&MachNlist| nlist.n_type() == macho::N_OSO)
+                        .filter(|nlist| match nlist.name(endian, symbols.strings()) {
+                            Ok(name) => !name.is_empty(),
+                            Err(_) => false,
+                        })
+                        .map(|nlist| u64::from(nlist.n_value(endian)))
+                        .collect();
                     object_mappings.resize_with(map.objects().len(), || None);
                     object_map = Some(map);
                 }
@@ -218,10 +562,25 @@ impl<'a> Object<'a> {
             syms,
             syms_sort_by_name,
             object_map,
+            object_mtimes,
             object_mappings: object_mappings.into_boxed_slice(),
         })
     }
 
+    /// Creates an object which only has the given symbols, sorted by address.
+    fn from_symbols(syms: Vec<(&'a [u8], u64)>) -> Object<'a> {
+        Object {
+            endian: NativeEndian,
+            data: &[],
+            dwarf: None,
+            syms,
+            syms_sort_by_name: false,
+            object_map: None,
+            object_mtimes: Vec::new(),
+            object_mappings: Vec::new().into_boxed_slice(),
+        }
+    }
+
     pub fn section(&self, _: &Stash, name: &str) -> Option<&'a [u8]> {
         let name = name.as_bytes();
         let dwarf = self.dwarf?;
@@ -237,13 +596,34 @@ impl<'a> Object<'a> {
     }
 
     pub fn search_symtab<'b>(&'b self, addr: u64) -> Option<&'b [u8]> {
+        self.search_symtab_entry(addr).map(|(_, _, name)| name)
+    }
+
+    /// Like `search_symtab`, but also returns the address the symbol starts
+    /// at and, if known, its size.
+    pub fn search_symtab_entry(&self, addr: u64) -> Option<(u64, Option<u64>, &[u8])> {
         debug_assert!(!self.syms_sort_by_name);
         let i = match self.syms.binary_search_by_key(&addr, |(_, addr)| *addr) {
             Ok(i) => i,
             Err(i) => i.checked_sub(1)?,
         };
-        let (sym, _addr) = self.syms.get(i)?;
-        Some(sym)
+        let (sym, addr) = self.syms.get(i)?;
+        Some((*addr, None, *sym))
+    }
+
+    /// Returns every symbol in the symbol table, as its address, its size if
+    /// known and its name.
+    pub fn symbols(&self) -> impl Iterator<Item = (u64, Option<u64>, &[u8])> + '_ {
+        self.syms.iter().map(|(name, addr)| (*addr, None, *name))
+    }
+
+    /// Returns the address of the first symbol whose name `matches`, trying
+    /// names both with and without the leading underscore of C symbols.
+    pub fn search_symtab_name(&self, mut matches: impl FnMut(&[u8]) -> bool) -> Option<u64> {
+        self.syms
+            .iter()
+            .find(|(name, _)| matches(name) || (name.first() == Some(&b'_') && matches(&name[1..])))
+            .map(|(_, addr)| *addr)
     }
 
     /// Try to load a context for an object file.
@@ -258,7 +638,11 @@ impl<'a> Object<'a> {
         let mapping = self.object_mappings.get_mut(object_index)?;
         if mapping.is_none() {
             // No cached mapping, so create it.
-            *mapping = Some(object_mapping(object_map.objects().get(object_index)?));
+            let mtime = self.object_mtimes.get(object_index).cloned().unwrap_or(0);
+            *mapping = Some(object_mapping(
+                object_map.objects().get(object_index)?,
+                mtime,
+            ));
         }
         let cx: &'b Context<'static> = &mapping.as_ref()?.as_ref()?.cx;
         // Don't leak the `'static` lifetime, make sure it's scoped to just ourselves.
@@ -280,18 +664,32 @@ impl<'a> Object<'a> {
     }
 }
 
-fn object_mapping(path: &[u8]) -> Option<Mapping> {
+/// Loads an object file named by an `N_OSO` entry, as long as it hasn't been
+/// modified since the linker recorded `mtime`.
+///
+/// Like `dsymutil` we don't trust an object that has been rebuilt since, as
+/// its debug information would no longer match the executable. A `mtime` of
+/// zero is what the linker records when `ZERO_AR_DATE` is set, which rustc
+/// always does, so that can't be checked.
+fn object_mapping(path: &[u8], mtime: u64) -> Option<Mapping> {
     use super::mystd::ffi::OsStr;
+    use super::mystd::fs;
     use super::mystd::os::unix::prelude::*;
 
     let map;
 
     // `N_OSO` symbol names can be either `/path/to/object.o` or `/path/to/archive.a(object.o)`.
     let member_name = if let Some((archive_path, member_name)) = split_archive_path(path) {
-        map = super::mmap(Path::new(OsStr::from_bytes(archive_path)))?;
+        map = super::open(Path::new(OsStr::from_bytes(archive_path)))?.map()?;
         Some(member_name)
     } else {
-        map = super::mmap(Path::new(OsStr::from_bytes(path)))?;
+        let path = Path::new(OsStr::from_bytes(path));
+        if let Ok(metadata) = fs::metadata(path) {
+            if mtime != 0 && metadata.mtime() as u64 != mtime {
+                return None;
+            }
+        }
+        map = super::open(path)?.map()?;
         None
     };
     Mapping::mk(map, |data, stash| {
@@ -302,11 +700,17 @@ fn object_mapping(path: &[u8]) -> Option<Mapping> {
                     .members()
                     .filter_map(Result::ok)
                     .find(|m| m.name() == member_name)?;
+                // For archive members the linker records the date in the
+                // member's header.
+                match member.date() {
+                    Some(date) if mtime != 0 && date != mtime => return None,
+                    _ => {}
+                }
                 member.data(data).ok()?
             }
             None => data,
         };
-        let (macho, data) = find_header(data)?;
+        let (macho, data) = find_header(data, None)?;
         let endian = macho.endian().ok()?;
         let obj = Object::parse(macho, endian, data)?;
         Context::new(stash, obj, None)
@@ -0,0 +1,85 @@
+//! Decompressing MiniDebugInfo.
+//!
+//! Fedora, RHEL and a few other distributions strip the full symbol table
+//! from their binaries, but keep a minimal one for use in backtraces. It's an
+//! ELF file with just a `.symtab` of the functions which aren't in `.dynsym`
+//! already, compressed with xz and stored in the `.gnu_debugdata` section, see
+//! https://sourceware.org/gdb/onlinedocs/gdb/MiniDebugInfo.html
+//!
+//! To decompress it we use `liblzma` from xz-utils, which is installed on
+//! every system which ships MiniDebugInfo. It's loaded with `dlopen` so that
+//! there's no link-time dependency on it.
+
+use super::Vec;
+use core::mem;
+
+type StreamBufferDecode = unsafe extern "C" fn(
+    memlimit: *mut u64,
+    flags: u32,
+    allocator: *const libc::c_void,
+    input: *const u8,
+    in_pos: *mut usize,
+    in_size: usize,
+    out: *mut u8,
+    out_pos: *mut usize,
+    out_size: usize,
+) -> libc::c_int;
+
+const LZMA_OK: libc::c_int = 0;
+const LZMA_BUF_ERROR: libc::c_int = 10;
+
+/// Decompresses the xz stream in `input`.
+pub(super) fn decompress(input: &[u8]) -> Option<Vec<u8>> {
+    unsafe {
+        let lib = libc::dlopen(
+            b"liblzma.so.5\0".as_ptr() as *const libc::c_char,
+            libc::RTLD_NOW | libc::RTLD_LOCAL,
+        );
+        if lib.is_null() {
+            return None;
+        }
+        let decode = libc::dlsym(
+            lib,
+            b"lzma_stream_buffer_decode\0".as_ptr() as *const libc::c_char,
+        );
+        let ret = if decode.is_null() {
+            None
+        } else {
+            let decode = mem::transmute::<*mut libc::c_void, StreamBufferDecode>(decode);
+            decompress_with(decode, input)
+        };
+        libc::dlclose(lib);
+        ret
+    }
+}
+
+unsafe fn decompress_with(decode: StreamBufferDecode, input: &[u8]) -> Option<Vec<u8>> {
+    // The decompressed size is only recorded at the end of the stream, so
+    // start with a guess and grow the buffer until everything fits.
+    let mut out = Vec::new();
+    let mut size = input.len().checked_mul(4)?;
+    loop {
+        out.resize(size, 0);
+        let mut memlimit = u64::max_value();
+        let mut in_pos = 0;
+        let mut out_pos = 0;
+        match decode(
+            &mut memlimit,
+            0,
+            core::ptr::null(),
+            input.as_ptr(),
+            &mut in_pos,
+            input.len(),
+            out.as_mut_ptr(),
+            &mut out_pos,
+            out.len(),
+        ) {
+            LZMA_OK => {
+                out.truncate(out_pos);
+                return Some(out);
+            }
+            LZMA_BUF_ERROR => size = size.checked_mul(2)?,
+            _ => return None,
+        }
+    }
+}
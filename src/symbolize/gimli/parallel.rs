@@ -0,0 +1,69 @@
+//! Creating the mappings of several libraries at once.
+//!
+//! Parsing the object file of a library is what takes the longest the first
+//! time an address in it is symbolized, and a backtrace in a process with
+//! hundreds of shared libraries can pass through a lot of them. These are all
+//! independent of each other, so they're created on a few threads instead of
+//! one after the other.
+
+use super::mystd::sync::{Arc, Mutex};
+use super::mystd::thread;
+use super::{Library, Mapping, Vec};
+use core::mem;
+
+/// The most threads to create mappings on, including the calling one.
+const MAX_THREADS: usize = 8;
+
+/// A mapping created on another thread, with the library it's for.
+struct Loaded(Library, Mapping);
+
+// SAFETY: `Mapping` is only `!Send` because of the raw pointers of the `Mmap`
+// it may hold and the references its `Context` has into its own data. All of
+// these point to memory that the `Mapping` owns, so moving it to another
+// thread as a whole is fine.
+unsafe impl Send for Loaded {}
+
+/// Creates the mappings of `libs`, returning the ones that could be created
+/// with the library each is for.
+pub(super) fn load(libs: Vec<Library>) -> Vec<(Library, Mapping)> {
+    let threads = libs.len().min(MAX_THREADS);
+    let queue = Arc::new(Mutex::new(libs));
+    let loaded = Arc::new(Mutex::new(Vec::new()));
+
+    // If a thread can't be spawned the remaining work just falls to the ones
+    // which could, and at least to the calling thread.
+    let handles = (1..threads)
+        .filter_map(|_| {
+            let queue = queue.clone();
+            let loaded = loaded.clone();
+            thread::Builder::new()
+                .spawn(move || work(&queue, &loaded))
+                .ok()
+        })
+        .collect::<Vec<_>>();
+    work(&queue, &loaded);
+    for handle in handles {
+        let _ = handle.join();
+    }
+
+    let mut loaded = loaded.lock().unwrap_or_else(|e| e.into_inner());
+    mem::take(&mut *loaded)
+        .into_iter()
+        .map(|Loaded(lib, mapping)| (lib, mapping))
+        .collect()
+}
+
+fn work(queue: &Mutex<Vec<Library>>, loaded: &Mutex<Vec<Loaded>>) {
+    loop {
+        let lib = match queue.lock().unwrap_or_else(|e| e.into_inner()).pop() {
+            Some(lib) => lib,
+            None => break,
+        };
+        if let Some(mapping) = lib.load() {
+            loaded
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(Loaded(lib, mapping));
+        }
+    }
+}
@@ -0,0 +1,494 @@
+//! Reading function names and line numbers from PDB files.
+//!
+//! MSVC toolchains put the debug information of an executable or DLL into a
+//! separate `.pdb` file, which the image refers to with the path, GUID and age
+//! in its CodeView debug directory entry. Only the parts of the format needed
+//! to symbolize addresses are read here: the procedures and line tables of
+//! each module (compilation unit), and the public symbols as a fallback for
+//! code without debug information.
+//!
+//! Addresses in a PDB are given as a section number and an offset, which are
+//! turned into offsets from the image base (RVAs) with the section headers
+//! that the linker copies into the PDB. Inlined functions aren't expanded,
+//! their code is attributed to the line they were called from. Images that
+//! were rewritten after linking and come with an address map (OMAP) in their
+//! PDB aren't supported.
+
+use super::{MapData, Stash, Symbol, Vec};
+use core::convert::{TryFrom, TryInto};
+use core::str;
+use libc::c_void;
+
+const MAGIC: &[u8] = b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0";
+
+const PDB_STREAM: usize = 1;
+const DBI_STREAM: usize = 3;
+const NIL_STREAM: u16 = 0xffff;
+
+// Indices of streams in the optional debug header of the DBI stream.
+const DBG_OMAP_FROM_SRC: usize = 4;
+const DBG_SECTION_HEADERS: usize = 5;
+
+const S_PUB32: u16 = 0x110e;
+const S_LPROC32: u16 = 0x110f;
+const S_GPROC32: u16 = 0x1110;
+const S_LPROC32_ID: u16 = 0x1146;
+const S_GPROC32_ID: u16 = 0x1147;
+
+const DEBUG_S_LINES: u32 = 0xf2;
+const DEBUG_S_FILECHKSMS: u32 = 0xf4;
+
+/// Returns whether `data` looks like a PDB file.
+pub(super) fn is_pdb(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub(super) struct Pdb<'a> {
+    /// The GUID and age which images record to refer to this PDB.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub guid: [u8; 16],
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub age: u32,
+    /// The C13 line information of each module.
+    lines: Vec<&'a [u8]>,
+    /// The procedures of all modules, sorted by address.
+    procs: Vec<Proc<'a>>,
+    /// The addresses and (mangled) names of public functions, sorted by
+    /// address.
+    publics: Vec<(u32, &'a [u8])>,
+    /// The string table which source file names are stored in.
+    names: &'a [u8],
+    /// The image's section headers.
+    sections: &'a [u8],
+}
+
+struct Proc<'a> {
+    rva: u32,
+    len: u32,
+    name: &'a [u8],
+    module: usize,
+}
+
+impl<'a> Pdb<'a> {
+    /// Parses the PDB file in `data`, copying the streams which aren't stored
+    /// contiguously in it into `stash`.
+    pub fn parse(data: &'a [u8], stash: &'a Stash) -> Option<Pdb<'a>> {
+        let msf = Msf::parse(data)?;
+
+        let info = msf.stream(stash, PDB_STREAM)?;
+        let guid = info.get(12..28)?.try_into().ok()?;
+        let names = match named_stream(info, b"/names") {
+            Some(i) => msf.stream(stash, i)?,
+            None => &[],
+        };
+        let names = match u32_at(names, 0) {
+            Some(0xeffe_effe) => names.get(12..12 + u32_at(names, 8)? as usize)?,
+            _ => &[],
+        };
+
+        // The DBI stream starts with a header, followed by the substreams
+        // whose sizes it lists.
+        let dbi = msf.stream(stash, DBI_STREAM)?;
+        let age = u32_at(dbi, 8)?;
+        let symbol_records = u16_at(dbi, 20)?;
+        let size = |offset| u32_at(dbi, offset).map(|size| size as usize);
+        let modules = dbi.get(64..64 + size(24)?)?;
+        let skipped = size(28)? + size(32)? + size(36)? + size(40)? + size(52)?;
+        let debug_header = 64 + modules.len() + skipped;
+        let debug_header = dbi.get(debug_header..debug_header + size(48)?)?;
+        let debug_stream = |i: usize| match u16_at(debug_header, i * 2) {
+            Some(NIL_STREAM) | None => None,
+            Some(stream) => Some(stream as usize),
+        };
+        if debug_stream(DBG_OMAP_FROM_SRC).is_some() {
+            return None;
+        }
+        let sections = msf.stream(stash, debug_stream(DBG_SECTION_HEADERS)?)?;
+
+        let mut pdb = Pdb {
+            guid,
+            age,
+            lines: Vec::new(),
+            procs: Vec::new(),
+            publics: Vec::new(),
+            names,
+            sections,
+        };
+
+        let mut rest = modules;
+        while rest.len() >= 64 {
+            let stream = u16_at(rest, 34)?;
+            let symbols_size = u32_at(rest, 36)? as usize;
+            let c11_size = u32_at(rest, 40)? as usize;
+            let c13_size = u32_at(rest, 44)? as usize;
+            let module_name = cstr_at(rest, 64)?;
+            let object_name = cstr_at(rest, 64 + module_name.len() + 1)?;
+            let len = 64 + module_name.len() + object_name.len() + 2;
+            rest = rest.get((len + 3) & !3..).unwrap_or(&[]);
+
+            // A module without a stream (or a broken one) has no procedures,
+            // but still takes up its index.
+            let module = pdb.lines.len();
+            let data = match stream {
+                NIL_STREAM => &[],
+                stream => msf.stream(stash, stream as usize).unwrap_or(&[]),
+            };
+            let lines = symbols_size + c11_size;
+            pdb.lines
+                .push(data.get(lines..lines + c13_size).unwrap_or(&[]));
+            // The symbols start with a signature, for the format they're in.
+            for (kind, record) in records(data.get(4..symbols_size).unwrap_or(&[])) {
+                match kind {
+                    S_GPROC32 | S_LPROC32 | S_GPROC32_ID | S_LPROC32_ID => {}
+                    _ => continue,
+                }
+                let len = u32_at(record, 12);
+                let rva =
+                    u32_at(record, 28).and_then(|offset| pdb.rva(u16_at(record, 32)?, offset));
+                if let (Some(len), Some(rva), Some(name)) = (len, rva, cstr_at(record, 35)) {
+                    pdb.procs.push(Proc {
+                        rva,
+                        len,
+                        name,
+                        module,
+                    });
+                }
+            }
+        }
+        pdb.procs.sort_unstable_by_key(|p| p.rva);
+
+        if symbol_records != NIL_STREAM {
+            let data = msf.stream(stash, symbol_records as usize).unwrap_or(&[]);
+            for (kind, record) in records(data) {
+                // Only public symbols flagged as code or functions.
+                if kind != S_PUB32 || !matches!(u32_at(record, 0), Some(flags) if flags & 3 != 0) {
+                    continue;
+                }
+                let rva = u32_at(record, 4).and_then(|offset| pdb.rva(u16_at(record, 8)?, offset));
+                if let (Some(rva), Some(name)) = (rva, cstr_at(record, 10)) {
+                    pdb.publics.push((rva, name));
+                }
+            }
+            pdb.publics.sort_unstable_by_key(|p| p.0);
+        }
+
+        Some(pdb)
+    }
+
+    /// Calls `call` with the symbol for `rva`, an offset from the image base,
+    /// returning whether there was one. `addr` is the address to report for
+    /// it.
+    pub fn resolve(&self, addr: *mut c_void, rva: u64, call: &mut dyn FnMut(Symbol<'a>)) -> bool {
+        let rva = match u32::try_from(rva) {
+            Ok(rva) => rva,
+            Err(_) => return false,
+        };
+        if let Some(proc) = self.find_proc(rva) {
+            call(Symbol::Frame {
+                addr,
+                location: self.find_location(proc.module, rva),
+                name: Some(proc.name),
+                inline_depth: 0,
+                language: None,
+                comp_dir: None,
+            });
+        } else if let Some(name) = self.search_publics(rva) {
+            call(Symbol::Symtab { addr, name });
+        } else {
+            return false;
+        }
+        true
+    }
+
+    fn find_proc(&self, rva: u32) -> Option<&Proc<'a>> {
+        let i = match self.procs.binary_search_by_key(&rva, |p| p.rva) {
+            Ok(i) => i,
+            Err(i) => i.checked_sub(1)?,
+        };
+        let proc = &self.procs[i];
+        if rva - proc.rva < proc.len {
+            Some(proc)
+        } else {
+            None
+        }
+    }
+
+    fn search_publics(&self, rva: u32) -> Option<&'a [u8]> {
+        // Like the COFF symbol table, public symbols don't have a size, so
+        // this can only go with the closest one before `rva`, as long as it's
+        // in the same section.
+        let i = match self.publics.binary_search_by_key(&rva, |p| p.0) {
+            Ok(i) => i,
+            Err(i) => i.checked_sub(1)?,
+        };
+        let (public, name) = self.publics[i];
+        if self.section_of(public)? != self.section_of(rva)? {
+            return None;
+        }
+        Some(name)
+    }
+
+    /// Returns the index of the section containing `rva`.
+    fn section_of(&self, rva: u32) -> Option<usize> {
+        self.sections.chunks_exact(40).position(|header| {
+            let start = u32_at(header, 12).unwrap_or(0);
+            let size = u32_at(header, 8).unwrap_or(0);
+            rva.wrapping_sub(start) < size
+        })
+    }
+
+    /// Looks up `rva` in the line information of the given module.
+    fn find_location(&self, module: usize, rva: u32) -> Option<addr2line::Location<'a>> {
+        let data = *self.lines.get(module)?;
+        let checksums = subsections(data)
+            .find(|(kind, _)| *kind == DEBUG_S_FILECHKSMS)
+            .map_or(&[][..], |(_, checksums)| checksums);
+
+        // Each lines subsection covers a range of code, typically one
+        // function, with blocks of lines for each file its code comes from.
+        for (kind, lines) in subsections(data) {
+            if kind != DEBUG_S_LINES {
+                continue;
+            }
+            let start = match self.rva(u16_at(lines, 4)?, u32_at(lines, 0)?) {
+                Some(start) => start,
+                None => continue,
+            };
+            let offset = rva.wrapping_sub(start);
+            if offset >= u32_at(lines, 8)? {
+                continue;
+            }
+            let has_columns = u16_at(lines, 6)? & 1 != 0;
+
+            let mut best: Option<(u32, u32, u32, Option<u16>)> = None;
+            let mut blocks = lines.get(12..)?;
+            while blocks.len() >= 12 {
+                let file = u32_at(blocks, 0)?;
+                let count = u32_at(blocks, 4)? as usize;
+                let block = blocks.get(..u32_at(blocks, 8)? as usize)?;
+                if block.len() < 12 {
+                    break;
+                }
+                blocks = &blocks[block.len()..];
+                for i in 0..count {
+                    let line_offset = u32_at(block, 12 + i * 8)?;
+                    match best {
+                        _ if line_offset > offset => continue,
+                        Some((best_offset, ..)) if best_offset > line_offset => continue,
+                        _ => {}
+                    }
+                    let line = u32_at(block, 16 + i * 8)? & 0xff_ffff;
+                    let column = if has_columns {
+                        u16_at(block, 12 + count * 8 + i * 4)
+                    } else {
+                        None
+                    };
+                    best = Some((line_offset, file, line, column));
+                }
+            }
+
+            let (_, file, line, column) = best?;
+            let file = u32_at(checksums, file as usize)
+                .and_then(|name| cstr_at(self.names, name as usize))
+                .and_then(|name| str::from_utf8(name).ok());
+            return Some(addr2line::Location {
+                file,
+                // Lines which aren't supposed to be stepped into are marked
+                // with these special values.
+                line: if line == 0xfe_efee || line == 0xf0_0f00 {
+                    None
+                } else {
+                    Some(line)
+                },
+                column: column.filter(|c| *c != 0).map(u32::from),
+            });
+        }
+        None
+    }
+
+    /// Converts an address given as a (1-based) section number and offset to
+    /// an offset from the image base.
+    fn rva(&self, section: u16, offset: u32) -> Option<u32> {
+        let header = usize::from(section).checked_sub(1)? * 40;
+        let virtual_address = u32_at(self.sections, header + 12)?;
+        virtual_address.checked_add(offset)
+    }
+}
+
+/// A PDB file and the data parsed from it, for `offline` symbolization.
+pub(super) struct Mapping {
+    // 'static lifetime is a lie, as for `super::Mapping`; the `Pdb` only
+    // borrows from the other two fields, and comes first to be dropped first.
+    pdb: Pdb<'static>,
+    _map: MapData,
+    _stash: super::Box<Stash>,
+}
+
+impl Mapping {
+    pub fn new(map: MapData) -> Option<Mapping> {
+        let stash = super::Box::new(Stash::new());
+        let pdb = Pdb::parse(&map, &stash)?;
+        // The data `map` and `stash` hold stays in place when they're moved.
+        let pdb = unsafe { core::mem::transmute::<Pdb<'_>, Pdb<'static>>(pdb) };
+        Some(Mapping {
+            pdb,
+            _map: map,
+            _stash: stash,
+        })
+    }
+
+    pub fn resolve(&self, addr: *mut c_void, rva: u64, call: &mut dyn FnMut(Symbol<'_>)) -> bool {
+        self.pdb.resolve(addr, rva, call)
+    }
+}
+
+/// The container format of PDB files, which splits them into streams made of
+/// blocks that can be anywhere in the file.
+struct Msf<'a> {
+    data: &'a [u8],
+    block_size: usize,
+    /// The stream directory, which lists the size and blocks of each stream.
+    directory: Vec<u8>,
+    /// The size of each stream, and where its blocks are in `directory`.
+    streams: Vec<(usize, usize)>,
+}
+
+impl<'a> Msf<'a> {
+    fn parse(data: &'a [u8]) -> Option<Msf<'a>> {
+        if !is_pdb(data) {
+            return None;
+        }
+        let block_size = u32_at(data, 32)? as usize;
+        let directory_size = u32_at(data, 44)? as usize;
+        let block_map = u32_at(data, 52)? as usize;
+        if block_size == 0 || directory_size > data.len() {
+            return None;
+        }
+
+        // The directory itself is stored in blocks, which are listed in the
+        // block at `block_map`.
+        let block_map = data.get(block_map.checked_mul(block_size)?..)?;
+        let mut directory = Vec::with_capacity(directory_size);
+        for i in 0..blocks(directory_size, block_size) {
+            let start = (u32_at(block_map, i * 4)? as usize).checked_mul(block_size)?;
+            let len = block_size.min(directory_size - directory.len());
+            directory.extend_from_slice(data.get(start..start.checked_add(len)?)?);
+        }
+
+        let count = u32_at(&directory, 0)? as usize;
+        let mut streams = Vec::new();
+        let mut offset = 4 + count.checked_mul(4)?;
+        for i in 0..count {
+            let size = match u32_at(&directory, 4 + i * 4)? {
+                0xffff_ffff => 0,
+                size => size as usize,
+            };
+            if size > data.len() {
+                return None;
+            }
+            streams.push((size, offset));
+            offset += blocks(size, block_size) * 4;
+        }
+        if offset > directory.len() {
+            return None;
+        }
+
+        Some(Msf {
+            data,
+            block_size,
+            directory,
+            streams,
+        })
+    }
+
+    /// Returns the contents of a stream, copied into `stash` if its blocks
+    /// aren't consecutive.
+    fn stream(&self, stash: &'a Stash, index: usize) -> Option<&'a [u8]> {
+        let (size, offset) = *self.streams.get(index)?;
+        let block = |i: usize| {
+            let block = u32_at(&self.directory, offset + i * 4)? as usize;
+            block.checked_mul(self.block_size)
+        };
+        if size == 0 {
+            return Some(&[]);
+        }
+
+        let first = block(0)?;
+        if (1..blocks(size, self.block_size)).all(|i| block(i) == Some(first + i * self.block_size))
+        {
+            return self.data.get(first..first.checked_add(size)?);
+        }
+        let data = stash.allocate(size);
+        for (i, chunk) in data.chunks_mut(self.block_size).enumerate() {
+            let start = block(i)?;
+            chunk.copy_from_slice(self.data.get(start..start.checked_add(chunk.len())?)?);
+        }
+        Some(data)
+    }
+}
+
+fn blocks(size: usize, block_size: usize) -> usize {
+    (size + block_size - 1) / block_size
+}
+
+/// Looks up the stream with the given name in the map of named streams that
+/// follows the header of the PDB info stream.
+fn named_stream(info: &[u8], name: &[u8]) -> Option<usize> {
+    let strings_len = u32_at(info, 28)? as usize;
+    let strings = info.get(32..32 + strings_len)?;
+    let mut offset = 32 + strings_len;
+    // The map is a hash table with its size, capacity, and bit vectors of
+    // the buckets which are present and deleted, followed by the entries of
+    // the present ones.
+    let count = u32_at(info, offset)? as usize;
+    offset += 8;
+    for _ in 0..2 {
+        offset += 4 + 4 * u32_at(info, offset)? as usize;
+    }
+    for i in 0..count {
+        let key = u32_at(info, offset + i * 8)?;
+        if cstr_at(strings, key as usize)? == name {
+            return Some(u32_at(info, offset + i * 8 + 4)? as usize);
+        }
+    }
+    None
+}
+
+/// Iterates over the kind and contents of CodeView symbol records.
+fn records(mut data: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    core::iter::from_fn(move || {
+        let len = u16_at(data, 0)? as usize;
+        let kind = u16_at(data, 2)?;
+        let record = data.get(4..2 + len)?;
+        data = &data[2 + len..];
+        Some((kind, record))
+    })
+}
+
+/// Iterates over the kind and contents of the subsections of C13 line
+/// information.
+fn subsections(mut data: &[u8]) -> impl Iterator<Item = (u32, &[u8])> {
+    core::iter::from_fn(move || {
+        let kind = u32_at(data, 0)?;
+        let len = u32_at(data, 4)? as usize;
+        let subsection = data.get(8..8 + len)?;
+        data = data.get((8 + len + 3) & !3..).unwrap_or(&[]);
+        Some((kind, subsection))
+    })
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn cstr_at(data: &[u8], offset: usize) -> Option<&[u8]> {
+    let data = data.get(offset..)?;
+    let len = data.iter().position(|b| *b == 0)?;
+    Some(&data[..len])
+}
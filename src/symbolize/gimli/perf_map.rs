@@ -0,0 +1,111 @@
+//! Symbolization of JIT code through `/tmp/perf-<pid>.map`.
+//!
+//! JIT compilers such as V8, LuaJIT and wasmtime can write the address, size
+//! and name of each function they emit to this file for `perf` to pick up.
+//! There's no debug information involved so all we get are function names,
+//! but that's much better than nothing for addresses which aren't in any
+//! object file.
+
+use super::mystd::fs;
+use super::mystd::process;
+use super::Vec;
+use core::str;
+
+#[derive(Default)]
+pub(super) struct PerfMap {
+    /// The length of the file when it was last read. JITs append to it as
+    /// they emit more code, so it's read again when that changes.
+    len: u64,
+    /// The entries in the order they appear in the file.
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    start: usize,
+    len: usize,
+    name: Vec<u8>,
+}
+
+impl PerfMap {
+    /// Returns the name of the JIT function containing `addr`, if any.
+    pub(super) fn lookup(&mut self, addr: usize) -> Option<&[u8]> {
+        if self.find(addr).is_none() {
+            self.reload();
+        }
+        let i = self.find(addr)?;
+        Some(&self.entries[i].name)
+    }
+
+    fn find(&self, addr: usize) -> Option<usize> {
+        // Later entries take precedence, since a JIT may reuse the memory of
+        // code it has freed.
+        self.entries
+            .iter()
+            .rposition(|e| e.start <= addr && addr - e.start < e.len)
+    }
+
+    fn reload(&mut self) {
+        let path = format!("/tmp/perf-{}.map", process::id());
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.len() != self.len => {}
+            _ => return,
+        }
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(_) => return,
+        };
+        self.len = data.len() as u64;
+        self.entries = data.split(|b| *b == b'\n').filter_map(parse_line).collect();
+    }
+}
+
+/// Parses a line of the form `START SIZE name`, with the numbers in hex.
+fn parse_line(line: &[u8]) -> Option<Entry> {
+    let mut parts = line.splitn(3, |b| *b == b' ');
+    let start = parse_hex(parts.next()?)?;
+    let len = parse_hex(parts.next()?)?;
+    let name = parts.next()?;
+    if name.is_empty() {
+        return None;
+    }
+    Some(Entry {
+        start,
+        len,
+        name: name.to_vec(),
+    })
+}
+
+fn parse_hex(s: &[u8]) -> Option<usize> {
+    let s = str::from_utf8(s).ok()?;
+    let s = s.trim_start_matches("0x");
+    usize::from_str_radix(s, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        let entry = parse_line(b"7f1234 a0 LazyCompile:~foo bar.js:1").unwrap();
+        assert_eq!((entry.start, entry.len), (0x7f1234, 0xa0));
+        assert_eq!(entry.name, b"LazyCompile:~foo bar.js:1");
+        let entry = parse_line(b"0x10 0x8 wasm-function[3]").unwrap();
+        assert_eq!((entry.start, entry.len), (0x10, 0x8));
+        assert!(parse_line(b"").is_none());
+        assert!(parse_line(b"zz 10 foo").is_none());
+        assert!(parse_line(b"10 10").is_none());
+    }
+
+    #[test]
+    fn later_entries_win() {
+        let mut map = PerfMap::default();
+        map.entries = vec![
+            parse_line(b"1000 100 old").unwrap(),
+            parse_line(b"1000 80 new").unwrap(),
+        ];
+        assert_eq!(map.find(0x1010), Some(1));
+        assert_eq!(map.find(0x1090), Some(0));
+        assert_eq!(map.find(0x2000), None);
+    }
+}
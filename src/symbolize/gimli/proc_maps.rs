@@ -0,0 +1,257 @@
+//! Parsing of `/proc/self/maps`, which lists the memory mappings of the
+//! process along with the files they map.
+
+use super::mystd::borrow::Cow;
+use super::Vec;
+use alloc::vec;
+use core::str;
+
+/// How the kernel writes newlines in paths, which would otherwise end the
+/// line. Nothing else is escaped, not even backslashes.
+const ESCAPED_NEWLINE: &[u8] = b"\\012";
+
+/// One line of `/proc/self/maps`.
+pub(super) struct MapsEntry<'a> {
+    /// The address range as written in the file, `start-end` in hex, which
+    /// is also the name of the mapping in `/proc/self/map_files`.
+    pub range: &'a [u8],
+    pub start: usize,
+    pub end: usize,
+    /// The permissions, like `r-xp`.
+    pub perms: &'a [u8],
+    /// The offset in the file the mapping starts at.
+    pub offset: u64,
+    /// The device and inode of the mapped file, with an inode of zero for
+    /// anonymous mappings.
+    pub dev: &'a [u8],
+    pub inode: u64,
+    /// The path of the mapped file, or a name like `[stack]`, or empty. The
+    /// kernel appends ` (deleted)` to the paths of files which have been
+    /// deleted.
+    pub path: Cow<'a, [u8]>,
+}
+
+/// Parses the contents of `/proc/self/maps`, skipping lines which can't be
+/// parsed.
+pub(super) fn parse(maps: &[u8]) -> impl Iterator<Item = MapsEntry<'_>> {
+    maps.split(|b| *b == b'\n').filter_map(parse_line)
+}
+
+fn parse_line(line: &[u8]) -> Option<MapsEntry<'_>> {
+    // Each line is `start-end perms offset dev inode path`, with the path
+    // padded by spaces and possibly containing spaces itself.
+    let mut fields = line.splitn(6, |b| *b == b' ');
+    let range = fields.next()?;
+    let perms = fields.next()?;
+    let offset = fields.next()?;
+    let dev = fields.next()?;
+    let inode = fields.next()?;
+    let path = fields.next().unwrap_or(&[]);
+    let start_of_path = path.iter().position(|b| *b != b' ').unwrap_or(path.len());
+
+    let range_str = str::from_utf8(range).ok()?;
+    let dash = range_str.find('-')?;
+    Some(MapsEntry {
+        range,
+        start: usize::from_str_radix(&range_str[..dash], 16).ok()?,
+        end: usize::from_str_radix(&range_str[dash + 1..], 16).ok()?,
+        perms,
+        offset: u64::from_str_radix(str::from_utf8(offset).ok()?, 16).ok()?,
+        dev,
+        inode: str::from_utf8(inode).ok()?.parse().ok()?,
+        path: unescape(&path[start_of_path..]),
+    })
+}
+
+/// The mappings of one file which has been loaded, like a shared object.
+///
+/// A loaded object is mapped once for each of its segments, like `r--` for its
+/// headers and read-only data, `r-x` for its code and `rw-` for its data, each
+/// from a different offset in the file. How many mappings there are and which
+/// one comes first depends on how the object was linked, like with
+/// `-z separate-code`, or with segments aligned for huge pages.
+pub(super) struct MapsObject<'a> {
+    pub path: Cow<'a, [u8]>,
+    /// The `range` of the first mapping, which is enough to find the file in
+    /// `/proc/<pid>/map_files`.
+    pub range: &'a [u8],
+    dev: &'a [u8],
+    inode: u64,
+    /// The start, end, offset and permissions of each mapping, by address.
+    pub mappings: Vec<(usize, usize, u64, &'a [u8])>,
+}
+
+impl MapsObject<'_> {
+    /// Returns the start and end of the mapping of the start of the file, if
+    /// it's readable.
+    #[cfg_attr(not(target_os = "android"), allow(dead_code))]
+    pub fn header(&self) -> Option<(usize, usize)> {
+        self.mappings
+            .iter()
+            .find(|(_, _, offset, perms)| *offset == 0 && perms.starts_with(b"r"))
+            .map(|(start, end, _, _)| (*start, *end))
+    }
+
+    /// Returns the bias of the object, what's added to the addresses in its
+    /// headers to get the addresses it's loaded at.
+    ///
+    /// `segments` are the loadable segments of the object, given by the file
+    /// offset, size in the file and address of each. The first of them which
+    /// a mapping is of determines the bias, so that it doesn't matter whether
+    /// the start of the file is mapped.
+    pub fn bias(&self, segments: impl Iterator<Item = (usize, usize, usize)>) -> Option<usize> {
+        for (file_offset, file_size, vaddr) in segments {
+            for (start, end, offset, _) in self.mappings.iter() {
+                // Mappings start at page boundaries, so the segment can start
+                // within one, but never before one starts.
+                let offset = *offset as usize;
+                if file_size == 0 || offset > file_offset || file_offset - offset >= end - start {
+                    continue;
+                }
+                let addr = start.wrapping_add(file_offset - offset);
+                return Some(addr.wrapping_sub(vaddr));
+            }
+        }
+        None
+    }
+}
+
+/// Parses the contents of `/proc/self/maps` into the objects mapped from
+/// files, coalescing the mappings of each.
+///
+/// The mappings of a file are those with its device, inode and path, and each
+/// object of it starts at a mapping of a lower offset than the last one, as
+/// when a file is loaded twice. Anonymous mappings, like those of `.bss`, are
+/// skipped.
+#[cfg_attr(all(target_os = "linux", not(feature = "std")), allow(dead_code))]
+pub(super) fn objects(maps: &[u8]) -> Vec<MapsObject<'_>> {
+    let mut objects: Vec<MapsObject<'_>> = Vec::new();
+    for e in parse(maps) {
+        if e.inode == 0 {
+            continue;
+        }
+        let mapping = (e.start, e.end, e.offset, e.perms);
+        // Other files can be mapped in between, say with a segment aligned
+        // for huge pages, so look further back than the last object.
+        let continued = objects.iter_mut().rev().find(|o| {
+            let follows = match o.mappings.last() {
+                Some((_, end, offset, _)) => *end <= e.start && *offset < e.offset,
+                None => false,
+            };
+            follows && o.dev == e.dev && o.inode == e.inode && o.path == e.path
+        });
+        match continued {
+            Some(object) => object.mappings.push(mapping),
+            None => {
+                objects.push(MapsObject {
+                    path: e.path,
+                    range: e.range,
+                    dev: e.dev,
+                    inode: e.inode,
+                    mappings: vec![mapping],
+                });
+            }
+        }
+    }
+    objects
+}
+
+fn unescape(path: &[u8]) -> Cow<'_, [u8]> {
+    if !path
+        .windows(ESCAPED_NEWLINE.len())
+        .any(|w| w == ESCAPED_NEWLINE)
+    {
+        return Cow::Borrowed(path);
+    }
+    let mut unescaped = Vec::with_capacity(path.len());
+    let mut rest = path;
+    while !rest.is_empty() {
+        if rest.starts_with(ESCAPED_NEWLINE) {
+            unescaped.push(b'\n');
+            rest = &rest[ESCAPED_NEWLINE.len()..];
+        } else {
+            unescaped.push(rest[0]);
+            rest = &rest[1..];
+        }
+    }
+    Cow::Owned(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(line: &[u8]) -> Vec<u8> {
+        parse_line(line).unwrap().path.into_owned()
+    }
+
+    #[test]
+    fn paths_keep_spaces() {
+        assert_eq!(
+            path(b"7f1c2a000000-7f1c2a021000 r-xp 00000000 08:01 1234                       /opt/My App/libfoo.so"),
+            b"/opt/My App/libfoo.so",
+        );
+        assert_eq!(
+            path(b"7f1c2a000000-7f1c2a021000 r-xp 00000000 08:01 1234 /tmp/libfoo.so (deleted)"),
+            b"/tmp/libfoo.so (deleted)",
+        );
+        assert_eq!(
+            path(b"7ffd5e1f0000-7ffd5e211000 rw-p 00000000 00:00 0"),
+            b""
+        );
+    }
+
+    // Linked with `-z separate-code`, with another object loaded in between
+    // and a second copy of the first loaded after it.
+    const SEPARATE_CODE: &[u8] = b"\
+55d0c0a00000-55d0c0a02000 r--p 00000000 08:01 100 /usr/bin/app
+55d0c0a02000-55d0c0a05000 r-xp 00002000 08:01 100 /usr/bin/app
+7f0000000000-7f0000001000 r--p 00000000 08:01 200 /usr/lib/libb.so
+55d0c0a05000-55d0c0a06000 r--p 00005000 08:01 100 /usr/bin/app
+55d0c0a06000-55d0c0a07000 rw-p 00006000 08:01 100 /usr/bin/app
+55d0c0a07000-55d0c0a08000 rw-p 00000000 00:00 0
+7f1000000000-7f1000002000 r--p 00000000 08:01 100 /usr/bin/app
+7f1000002000-7f1000005000 r-xp 00002000 08:01 100 /usr/bin/app
+7ffd5e1f0000-7ffd5e211000 rw-p 00000000 00:00 0 [stack]
+";
+
+    #[test]
+    fn objects_coalesce_mappings() {
+        let objects = objects(SEPARATE_CODE);
+        let paths = objects.iter().map(|o| &o.path[..]).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [&b"/usr/bin/app"[..], b"/usr/lib/libb.so", b"/usr/bin/app"]
+        );
+        assert_eq!(objects[0].mappings.len(), 4);
+        assert_eq!(objects[0].range, b"55d0c0a00000-55d0c0a02000");
+        assert_eq!(objects[0].header(), Some((0x55d0c0a00000, 0x55d0c0a02000)));
+        assert_eq!(objects[2].mappings.len(), 2);
+        assert_eq!(objects[2].mappings[0].0, 0x7f1000000000);
+    }
+
+    #[test]
+    fn objects_bias() {
+        let objects = objects(SEPARATE_CODE);
+        // The code segment, which starts within a page, is the first which is
+        // mapped.
+        let segments = [(0x10_0000, 0x1000, 0x10_0000), (0x2010, 0x100, 0x3010)];
+        assert_eq!(
+            objects[0].bias(segments.iter().cloned()),
+            Some(0x55d0c0a00000 - 0x1000)
+        );
+        assert_eq!(
+            objects[2].bias(segments.iter().cloned()),
+            Some(0x7f1000000000 - 0x1000)
+        );
+        assert_eq!(objects[1].bias(segments[1..].iter().cloned()), None);
+    }
+
+    #[test]
+    fn paths_unescape_newlines() {
+        assert_eq!(
+            path(b"7f1c2a000000-7f1c2a021000 r-xp 00000000 08:01 1234 /tmp/a\\012b\\x.so"),
+            b"/tmp/a\nb\\x.so",
+        );
+    }
+}
@@ -0,0 +1,362 @@
+//! Support for split DWARF.
+//!
+//! With `-gsplit-dwarf` or `-Csplit-debuginfo=unpacked` most of the debug
+//! info of a compilation unit isn't linked into the object file, but left in
+//! a separate `.dwo` file. All that's in the object file is a skeleton unit
+//! with the line table and the name of the `.dwo` file. Packed split DWARF
+//! combines all `.dwo` files into one `.dwp` package next to the object file
+//! instead.
+//!
+//! `addr2line` only sees the skeleton units, which is enough for file and
+//! line information but not for function names and inlined frames. Those are
+//! found here by loading the split unit of the skeleton unit containing an
+//! address, and looking up the functions containing that address in it.
+
+use super::gimli::{self, AttributeValue, DwarfPackage, DwoId, EndianSlice};
+use super::mystd::ffi::OsStr;
+#[cfg(target_os = "hermit")]
+use super::mystd::os::hermit::ffi::OsStrExt;
+#[cfg(unix)]
+use super::mystd::os::unix::ffi::OsStrExt;
+use super::mystd::path::{Path, PathBuf};
+use super::{Endian, Object, Stash};
+use super::{String, ToOwned, Vec};
+
+type R<'a> = EndianSlice<'a, Endian>;
+
+/// The name and location of a frame.
+type Frame<'a> = (Option<&'a [u8]>, Option<addr2line::Location<'a>>);
+
+pub(super) struct SplitDwarf<'a> {
+    stash: &'a Stash,
+    /// The object file which the skeleton units are from, if known.
+    pub(super) path: Option<PathBuf>,
+    /// The `.dwp` package of the object file, loaded the first time it's
+    /// needed.
+    package: Option<Option<DwarfPackage<R<'a>>>>,
+    /// The split units loaded so far, or `None` for those that couldn't be
+    /// found.
+    units: Vec<(DwoId, Option<SplitUnit<'a>>)>,
+}
+
+struct SplitUnit<'a> {
+    dwarf: gimli::Dwarf<R<'a>>,
+    unit: gimli::Unit<R<'a>>,
+    /// The paths of the files in the line table of the skeleton unit, by
+    /// index, which is what `DW_AT_call_file` refers to.
+    files: Vec<Option<String>>,
+}
+
+/// A function containing the address being looked up.
+struct Function<'a> {
+    name: Option<&'a [u8]>,
+    call_file: u64,
+    call_line: u32,
+    call_column: u32,
+}
+
+impl<'a> SplitDwarf<'a> {
+    pub(super) fn new(stash: &'a Stash) -> SplitDwarf<'a> {
+        SplitDwarf {
+            stash,
+            path: None,
+            package: None,
+            units: Vec::new(),
+        }
+    }
+
+    /// Loads the split unit for the skeleton unit containing `addr`, if it
+    /// hasn't been already.
+    pub(super) fn load(&mut self, dwarf: &addr2line::Context<R<'a>>, addr: u64) {
+        let skeleton = match dwarf.find_dwarf_unit(addr) {
+            Some(unit) => unit,
+            None => return,
+        };
+        let dwo_id = match skeleton.dwo_id {
+            Some(dwo_id) => dwo_id,
+            None => return,
+        };
+        if self.units.iter().any(|(id, _)| *id == dwo_id) {
+            return;
+        }
+        let unit = self.load_unit(dwarf.dwarf(), skeleton, dwo_id);
+        self.units.push((dwo_id, unit));
+    }
+
+    fn load_unit(
+        &mut self,
+        parent: &gimli::Dwarf<R<'a>>,
+        skeleton: &gimli::Unit<R<'a>>,
+        dwo_id: DwoId,
+    ) -> Option<SplitUnit<'a>> {
+        let dwarf = match self.package().and_then(|p| p.find_cu(dwo_id, parent).ok()?) {
+            Some(dwarf) => dwarf,
+            None => self.load_dwo(parent, skeleton)?,
+        };
+
+        let mut units = dwarf.units();
+        while let Ok(Some(header)) = units.next() {
+            let mut unit = match dwarf.unit(header) {
+                Ok(unit) => unit,
+                Err(_) => continue,
+            };
+            if unit.dwo_id != Some(dwo_id) {
+                continue;
+            }
+            unit.copy_relocated_attributes(skeleton);
+            let files = render_files(parent, skeleton);
+            return Some(SplitUnit { dwarf, unit, files });
+        }
+        None
+    }
+
+    fn package(&mut self) -> Option<&DwarfPackage<R<'a>>> {
+        if self.package.is_none() {
+            self.package = Some(self.load_package());
+        }
+        self.package.as_ref().and_then(|p| p.as_ref())
+    }
+
+    /// Loads the `.dwp` package next to the object file, which is named after
+    /// it with `.dwp` appended.
+    fn load_package(&self) -> Option<DwarfPackage<R<'a>>> {
+        let mut path = self.path.as_ref()?.as_os_str().to_owned();
+        path.push(".dwp");
+        let object = self.parse(Path::new(&path))?;
+        DwarfPackage::load(
+            |id| -> Result<_, gimli::Error> { Ok(self.section(&object, id)) },
+            EndianSlice::new(&[], Endian),
+        )
+        .ok()
+    }
+
+    /// Loads the `.dwo` file named by the skeleton unit.
+    fn load_dwo(
+        &self,
+        parent: &gimli::Dwarf<R<'a>>,
+        skeleton: &gimli::Unit<R<'a>>,
+    ) -> Option<gimli::Dwarf<R<'a>>> {
+        let mut entries = skeleton.entries();
+        let (_, root) = entries.next_dfs().ok()??;
+        let name = root
+            .attr_value(gimli::DW_AT_dwo_name)
+            .ok()
+            .flatten()
+            .or_else(|| root.attr_value(gimli::DW_AT_GNU_dwo_name).ok().flatten())?;
+        let name = parent.attr_string(skeleton, name).ok()?;
+        let name = Path::new(OsStr::from_bytes(name.slice()));
+
+        let object = self.locate_dwo(name, skeleton)?;
+        let mut dwarf =
+            gimli::Dwarf::load(|id| -> Result<_, ()> { Ok(self.section(&object, id)) }).ok()?;
+        // The addresses and DWARF 4 range lists that split units refer to
+        // are left in the object file.
+        dwarf.file_type = gimli::DwarfFileType::Dwo;
+        dwarf.debug_addr = parent.debug_addr;
+        dwarf.ranges = gimli::RangeLists::new(
+            *parent.ranges.debug_ranges(),
+            *dwarf.ranges.debug_rnglists(),
+        );
+        Some(dwarf)
+    }
+
+    /// Finds the `.dwo` file called `name`, which is relative to the
+    /// `DW_AT_comp_dir` of the skeleton unit unless it's absolute. If the
+    /// build tree was moved it's also looked for relative to the directory of
+    /// the object file.
+    fn locate_dwo(&self, name: &Path, skeleton: &gimli::Unit<R<'a>>) -> Option<Object<'a>> {
+        if name.is_absolute() {
+            return self.parse(name);
+        }
+        if let Some(comp_dir) = &skeleton.comp_dir {
+            let path = Path::new(OsStr::from_bytes(comp_dir.slice())).join(name);
+            if let Some(object) = self.parse(&path) {
+                return Some(object);
+            }
+        }
+        let dir = self.path.as_ref()?.parent()?;
+        self.parse(&dir.join(name))
+    }
+
+    fn parse(&self, path: &Path) -> Option<Object<'a>> {
+        Object::parse(super::elf::load_in(self.stash, path)?)
+    }
+
+    fn section(&self, object: &Object<'a>, id: gimli::SectionId) -> R<'a> {
+        let data = id
+            .dwo_name()
+            .and_then(|name| object.section(self.stash, name));
+        EndianSlice::new(data.unwrap_or(&[]), Endian)
+    }
+
+    /// Returns the name and location of each frame at `addr`, innermost
+    /// first, if it's in a split unit.
+    pub(super) fn find_frames<'s>(
+        &'s self,
+        dwarf: &'s addr2line::Context<R<'a>>,
+        addr: u64,
+    ) -> Option<Vec<Frame<'s>>> {
+        let dwo_id = dwarf.find_dwarf_unit(addr)?.dwo_id?;
+        let split = self
+            .units
+            .iter()
+            .find(|(id, _)| *id == dwo_id)?
+            .1
+            .as_ref()?;
+
+        let mut functions = Vec::new();
+        let mut tree = split.unit.entries_tree(None).ok()?;
+        find_functions(split, tree.root().ok()?, addr, &mut functions).ok()?;
+
+        // Each function is attributed to the call site of the function
+        // inlined into it, and the innermost one to the line table entry of
+        // the address itself.
+        let mut frames = Vec::with_capacity(functions.len());
+        let mut location = dwarf.find_location(addr).ok()?;
+        for function in functions.iter().rev() {
+            frames.push((function.name, location));
+            location = Some(addr2line::Location {
+                file: split
+                    .files
+                    .get(function.call_file as usize)
+                    .and_then(|f| f.as_deref()),
+                line: Some(function.call_line).filter(|l| *l != 0),
+                column: Some(function.call_column).filter(|c| *c != 0),
+            });
+        }
+        if frames.is_empty() {
+            None
+        } else {
+            Some(frames)
+        }
+    }
+}
+
+/// Appends the functions containing `addr` among the descendants of `node`
+/// to `functions`, outermost first. Returns whether any were found.
+fn find_functions<'a>(
+    split: &SplitUnit<'a>,
+    node: gimli::EntriesTreeNode<'_, '_, '_, R<'a>>,
+    addr: u64,
+    functions: &mut Vec<Function<'a>>,
+) -> gimli::Result<bool> {
+    let mut children = node.children();
+    while let Some(child) = children.next()? {
+        let entry = child.entry();
+        match entry.tag() {
+            gimli::DW_TAG_subprogram | gimli::DW_TAG_inlined_subroutine => {
+                let mut ranges = split.dwarf.die_ranges(&split.unit, entry)?;
+                let mut contains = false;
+                while let Some(range) = ranges.next()? {
+                    if range.begin <= addr && addr < range.end {
+                        contains = true;
+                        break;
+                    }
+                }
+                if !contains {
+                    continue;
+                }
+                let call_attr = |name| match entry.attr_value(name) {
+                    Ok(Some(AttributeValue::FileIndex(index))) => index,
+                    Ok(Some(value)) => value.udata_value().unwrap_or(0),
+                    _ => 0,
+                };
+                functions.push(Function {
+                    name: function_name(split, entry, 0),
+                    call_file: call_attr(gimli::DW_AT_call_file),
+                    call_line: call_attr(gimli::DW_AT_call_line) as u32,
+                    call_column: call_attr(gimli::DW_AT_call_column) as u32,
+                });
+                find_functions(split, child, addr, functions)?;
+                return Ok(true);
+            }
+            // Functions can be nested in namespaces, types and lexical
+            // blocks.
+            _ => {
+                if find_functions(split, child, addr, functions)? {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Returns the linkage name of a function, or its plain name if it has none,
+/// following `DW_AT_abstract_origin` and `DW_AT_specification`.
+fn function_name<'a>(
+    split: &SplitUnit<'a>,
+    entry: &gimli::DebuggingInformationEntry<'_, '_, R<'a>>,
+    depth: usize,
+) -> Option<&'a [u8]> {
+    let mut name = None;
+    let mut next = None;
+    let mut attrs = entry.attrs();
+    while let Ok(Some(attr)) = attrs.next() {
+        match attr.name() {
+            gimli::DW_AT_linkage_name | gimli::DW_AT_MIPS_linkage_name => {
+                if let Ok(val) = split.dwarf.attr_string(&split.unit, attr.value()) {
+                    return Some(val.slice());
+                }
+            }
+            gimli::DW_AT_name => {
+                if let Ok(val) = split.dwarf.attr_string(&split.unit, attr.value()) {
+                    name = Some(val.slice());
+                }
+            }
+            gimli::DW_AT_abstract_origin | gimli::DW_AT_specification => {
+                next = Some(attr.value());
+            }
+            _ => {}
+        }
+    }
+    if name.is_some() {
+        return name;
+    }
+    // Guard against reference cycles in malformed debug info.
+    match next {
+        Some(AttributeValue::UnitRef(offset)) if depth < 16 => {
+            let entry = split.unit.entry(offset).ok()?;
+            function_name(split, &entry, depth + 1)
+        }
+        _ => None,
+    }
+}
+
+/// Renders the paths of all files in the line table of `unit` the same way
+/// as `addr2line` does.
+fn render_files(dwarf: &gimli::Dwarf<R<'_>>, unit: &gimli::Unit<R<'_>>) -> Vec<Option<String>> {
+    let header = match &unit.line_program {
+        Some(program) => program.header(),
+        None => return Vec::new(),
+    };
+    (0..=header.file_names().len() as u64)
+        .map(|index| {
+            let file = header.file(index)?;
+            let mut path = match &unit.comp_dir {
+                Some(dir) => dir.to_string_lossy().into_owned(),
+                None => String::new(),
+            };
+            if let Some(dir) = file.directory(header) {
+                path_push(
+                    &mut path,
+                    &dwarf.attr_string(unit, dir).ok()?.to_string_lossy(),
+                );
+            }
+            let name = dwarf.attr_string(unit, file.path_name()).ok()?;
+            path_push(&mut path, &name.to_string_lossy());
+            Some(path)
+        })
+        .collect()
+}
+
+fn path_push(path: &mut String, p: &str) {
+    if p.starts_with('/') {
+        *path = p.to_owned();
+    } else {
+        if !path.ends_with('/') {
+            path.push('/');
+        }
+        *path += p;
+    }
+}
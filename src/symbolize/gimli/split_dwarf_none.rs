@@ -0,0 +1,31 @@
+//! Split DWARF only exists for ELF objects, so with other object formats
+//! there are never any split units to find.
+
+use super::gimli::EndianSlice;
+use super::Vec;
+use super::{Endian, Stash};
+
+type R<'a> = EndianSlice<'a, Endian>;
+
+/// The name and location of a frame.
+type Frame<'a> = (Option<&'a [u8]>, Option<addr2line::Location<'a>>);
+
+pub(super) struct SplitDwarf<'a> {
+    _stash: &'a Stash,
+}
+
+impl<'a> SplitDwarf<'a> {
+    pub(super) fn new(stash: &'a Stash) -> SplitDwarf<'a> {
+        SplitDwarf { _stash: stash }
+    }
+
+    pub(super) fn load(&mut self, _dwarf: &addr2line::Context<R<'a>>, _addr: u64) {}
+
+    pub(super) fn find_frames<'s>(
+        &'s self,
+        _dwarf: &'s addr2line::Context<R<'a>>,
+        _addr: u64,
+    ) -> Option<Vec<Frame<'s>>> {
+        None
+    }
+}
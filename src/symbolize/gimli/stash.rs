@@ -1,7 +1,7 @@
 // only used on Linux right now, so allow dead code elsewhere
 #![cfg_attr(not(target_os = "linux"), allow(dead_code))]
 
-use super::Mmap;
+use super::MapData;
 use alloc::vec;
 use alloc::vec::Vec;
 use core::cell::UnsafeCell;
@@ -9,14 +9,14 @@ use core::cell::UnsafeCell;
 /// A simple arena allocator for byte buffers.
 pub struct Stash {
     buffers: UnsafeCell<Vec<Vec<u8>>>,
-    mmap_aux: UnsafeCell<Option<Mmap>>,
+    mmaps: UnsafeCell<Vec<MapData>>,
 }
 
 impl Stash {
     pub fn new() -> Stash {
         Stash {
             buffers: UnsafeCell::new(Vec::new()),
-            mmap_aux: UnsafeCell::new(None),
+            mmaps: UnsafeCell::new(Vec::new()),
         }
     }
 
@@ -33,20 +33,32 @@ impl Stash {
         &mut buffers[i]
     }
 
-    /// Stores a `Mmap` for the lifetime of this `Stash`, returning a pointer
-    /// which is scoped to just this lifetime.
-    pub fn set_mmap_aux(&self, map: Mmap) -> &[u8] {
+    /// Returns the number of bytes held, both allocated and mapped.
+    pub fn size(&self) -> usize {
+        // SAFETY: the references handed out by the other methods point to the
+        // data of the buffers and maps rather than to the vectors holding
+        // them, and those vectors are only mutated within those methods.
+        unsafe {
+            let buffers = &*self.buffers.get();
+            let mmaps = &*self.mmaps.get();
+            buffers.iter().map(|b| b.len()).sum::<usize>()
+                + mmaps.iter().map(|m| m.len()).sum::<usize>()
+        }
+    }
+
+    /// Stores any number of additional files, mapped or read, for the
+    /// lifetime of this `Stash`, returning a pointer to the data of the one
+    /// just added.
+    pub fn cache_mmap(&self, map: MapData) -> &[u8] {
         // SAFETY: this is the only location for a mutable pointer to
-        // `mmap_aux`, and this structure isn't threadsafe to shared across
-        // threads either. This also is careful to store at most one `mmap_aux`
-        // since overwriting a previous one would invalidate the previous
-        // pointer. Given that though we can safely return a pointer to our
-        // interior-owned contents.
+        // `mmaps`, and we never remove elements from it. Moving a `MapData`
+        // around inside the vector doesn't move the memory holding its data,
+        // so the returned pointer stays valid for as long as `self` does.
         unsafe {
-            let mmap_aux = &mut *self.mmap_aux.get();
-            assert!(mmap_aux.is_none());
-            *mmap_aux = Some(map);
-            mmap_aux.as_ref().unwrap()
+            let mmaps = &mut *self.mmaps.get();
+            mmaps.push(map);
+            let map = mmaps.last().unwrap();
+            core::slice::from_raw_parts(map.as_ptr(), map.len())
         }
     }
 }
@@ -0,0 +1,89 @@
+//! Listing the memory mappings of the process on NetBSD, which doesn't mount
+//! `/proc` by default, with the `vm.proc.map` sysctl. Each mapping of a file
+//! comes with the file's path, as far as the kernel still knows it.
+//!
+//! OpenBSD has a similar `KERN_PROC_VMMAP` sysctl, but its entries don't name
+//! the mapped files, so there's nothing for symbolization to get from it.
+
+use super::mystd::ffi::{CStr, OsStr, OsString};
+use super::mystd::io;
+use super::mystd::os::unix::prelude::*;
+use super::Vec;
+use core::{mem, ptr};
+
+/// One mapping of a file.
+pub(super) struct VmEntry {
+    pub start: usize,
+    pub end: usize,
+    pub path: OsString,
+}
+
+/// Returns the mappings of files into the current process, or `None` if
+/// they can't be listed.
+pub(super) fn entries() -> Option<Vec<VmEntry>> {
+    let size = mem::size_of::<libc::kinfo_vmentry>();
+    let mib = [
+        libc::CTL_VM,
+        libc::VM_PROC,
+        libc::VM_PROC_MAP,
+        unsafe { libc::getpid() },
+        size as libc::c_int,
+    ];
+    let mut entries: Vec<libc::kinfo_vmentry> = Vec::new();
+    // The mappings can change between asking for their number and listing
+    // them, so the listing is retried with more room when it doesn't fit.
+    let mut tries = 0;
+    loop {
+        let mut len = 0;
+        let ret = unsafe {
+            libc::sysctl(
+                mib.as_ptr(),
+                mib.len() as libc::c_uint,
+                ptr::null_mut(),
+                &mut len,
+                ptr::null(),
+                0,
+            )
+        };
+        if ret != 0 {
+            return None;
+        }
+        // Leave room for a few mappings to be added in the meantime.
+        let capacity = len / size + 8;
+        entries.reserve(capacity);
+        let mut len = capacity * size;
+        let ret = unsafe {
+            libc::sysctl(
+                mib.as_ptr(),
+                mib.len() as libc::c_uint,
+                entries.as_mut_ptr().cast(),
+                &mut len,
+                ptr::null(),
+                0,
+            )
+        };
+        if ret == 0 {
+            unsafe { entries.set_len(len / size) };
+            break;
+        }
+        tries += 1;
+        if tries == 4 || io::Error::last_os_error().raw_os_error() != Some(libc::ENOMEM) {
+            return None;
+        }
+    }
+
+    Some(
+        entries
+            .iter()
+            .filter(|e| e.kve_path[0] != 0)
+            .map(|e| {
+                let path = unsafe { CStr::from_ptr(e.kve_path.as_ptr()) };
+                VmEntry {
+                    start: e.kve_start as usize,
+                    end: e.kve_end as usize,
+                    path: OsStr::from_bytes(path.to_bytes()).to_owned(),
+                }
+            })
+            .collect(),
+    )
+}
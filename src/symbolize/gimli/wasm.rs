@@ -0,0 +1,311 @@
+//! Reading function names and line numbers from WebAssembly modules.
+//!
+//! WebAssembly programs have no way to walk their own stack yet, so nothing
+//! can be captured on wasm targets. Engines and browsers do report where each
+//! frame of a trap or an `Error().stack` is though, as an offset into the
+//! module file, and this lets those offsets be symbolized with `offline`.
+//!
+//! Debug information is stored as DWARF in custom sections named after the
+//! ELF sections, with addresses that are offsets from the start of the
+//! contents of the code section. Function names, which are there even
+//! without debug information, are in the `name` custom section.
+
+use super::dwarf::Dwarf;
+use super::gimli;
+use super::gimli::Section;
+use super::{Endian, EndianSlice, MapData, Symbol, Vec};
+use core::convert::TryFrom;
+use libc::c_void;
+
+const MAGIC: &[u8] = b"\0asm\x01\0\0\0";
+
+const CUSTOM_SECTION: u8 = 0;
+const IMPORT_SECTION: u8 = 2;
+const CODE_SECTION: u8 = 10;
+
+/// The subsection of the `name` section with the names of functions.
+const FUNCTION_NAMES: u8 = 1;
+
+/// Returns whether `data` looks like a WebAssembly module.
+pub(super) fn is_wasm(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub(super) struct Wasm<'a> {
+    dwarf: Option<Dwarf<'a>>,
+    /// Where the contents of the code section start in the module.
+    code_start: u64,
+    /// Where the body of each named function is in the module, and its
+    /// (mangled) name, sorted by offset.
+    functions: Vec<(u64, u64, &'a [u8])>,
+}
+
+impl<'a> Wasm<'a> {
+    pub fn parse(data: &'a [u8]) -> Option<Wasm<'a>> {
+        // The DWARF is little-endian, like everything else in the module, and
+        // is read with the native endianness.
+        if cfg!(target_endian = "big") || !is_wasm(data) {
+            return None;
+        }
+
+        let mut custom = Vec::new();
+        let mut imported = 0;
+        let mut code_start = 0;
+        let mut bodies = Vec::new();
+        let mut module = Reader::new(data, MAGIC.len());
+        while !module.is_empty() {
+            let id = module.u8()?;
+            let len = module.leb()?;
+            let start = module.pos;
+            let mut section = Reader::new(module.bytes(len)?, 0);
+            match id {
+                CUSTOM_SECTION => {
+                    let name = section.name()?;
+                    custom.push((name, section.rest()));
+                }
+                IMPORT_SECTION => imported = function_imports(section)?,
+                CODE_SECTION => {
+                    code_start = start as u64;
+                    for _ in 0..section.leb()? {
+                        let len = section.leb()?;
+                        let body = (start + section.pos) as u64;
+                        section.bytes(len)?;
+                        bodies.push((body, body + len as u64));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut names = custom
+            .iter()
+            .find(|(name, _)| *name == b"name")
+            .and_then(|(_, data)| function_names(data))
+            .unwrap_or_default();
+        names.sort_unstable_by_key(|(index, _)| *index);
+        let functions = bodies
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, (start, end))| {
+                let index = imported + i as u32;
+                let name = names.binary_search_by_key(&index, |(i, _)| *i).ok()?;
+                Some((start, end, names[name].1))
+            })
+            .collect();
+
+        let sections = gimli::Dwarf::load(|id| -> Result<_, ()> {
+            let data = custom
+                .iter()
+                .find(|(name, _)| *name == id.name().as_bytes())
+                .map_or(&[][..], |(_, data)| data);
+            Ok(EndianSlice::new(data, Endian))
+        })
+        .ok()?;
+        let dwarf = if sections.debug_info.reader().is_empty() {
+            None
+        } else {
+            Dwarf::new(sections)
+        };
+
+        Some(Wasm {
+            dwarf,
+            code_start,
+            functions,
+        })
+    }
+
+    /// Resolves `offset`, an offset into the module file.
+    pub fn resolve(&mut self, addr: *mut c_void, offset: u64, call: &mut dyn FnMut(Symbol<'_>)) {
+        let function = self.search_functions(offset);
+        if let (Some(dwarf), Some(code_offset)) =
+            (&mut self.dwarf, offset.checked_sub(self.code_start))
+        {
+            dwarf.load(code_offset);
+            let context = dwarf.get(code_offset);
+            let frames = context.map(|d| d.find_frames(code_offset));
+            if let (Some(context), Some(Ok(mut iter))) = (context, frames) {
+                let mut frames = Vec::new();
+                while let Ok(Some(frame)) = iter.next() {
+                    frames.push(frame);
+                }
+                if !frames.is_empty() {
+                    let count = frames.len();
+                    let comp_dir = super::comp_dir(context, &frames, code_offset);
+                    for (i, frame) in frames.into_iter().enumerate() {
+                        let (name, language) = match frame.function {
+                            Some(f) => (Some(f.name.slice()), f.language),
+                            None => (function, None),
+                        };
+                        call(Symbol::Frame {
+                            addr,
+                            location: frame.location,
+                            name,
+                            inline_depth: (count - 1 - i) as u32,
+                            language,
+                            comp_dir,
+                        });
+                    }
+                    return;
+                }
+            }
+        }
+        if let Some(name) = function {
+            call(Symbol::Symtab { addr, name });
+        }
+    }
+
+    fn search_functions(&self, offset: u64) -> Option<&'a [u8]> {
+        let i = match self.functions.binary_search_by_key(&offset, |f| f.0) {
+            Ok(i) => i,
+            Err(i) => i.checked_sub(1)?,
+        };
+        let (_, end, name) = self.functions[i];
+        if offset < end {
+            Some(name)
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns how many functions the import section `section` imports, which
+/// come before the module's own functions in the function index space.
+fn function_imports(mut section: Reader<'_>) -> Option<u32> {
+    let mut functions = 0;
+    for _ in 0..section.leb()? {
+        section.name()?;
+        section.name()?;
+        match section.u8()? {
+            // A function, with its type index.
+            0 => {
+                section.leb()?;
+                functions += 1;
+            }
+            // A table, with its element type and limits.
+            1 => {
+                section.u8()?;
+                section.limits()?;
+            }
+            // A memory, with its limits.
+            2 => section.limits()?,
+            // A global, with its type and mutability.
+            3 => {
+                section.u8()?;
+                section.u8()?;
+            }
+            // A tag, with its attribute and type index.
+            4 => {
+                section.u8()?;
+                section.leb()?;
+            }
+            _ => return None,
+        }
+    }
+    Some(functions)
+}
+
+/// Reads the function names from the `name` section `data`, as pairs of the
+/// function index and the name.
+fn function_names(data: &[u8]) -> Option<Vec<(u32, &[u8])>> {
+    let mut reader = Reader::new(data, 0);
+    while !reader.is_empty() {
+        let id = reader.u8()?;
+        let len = reader.leb()?;
+        let mut subsection = Reader::new(reader.bytes(len)?, 0);
+        if id != FUNCTION_NAMES {
+            continue;
+        }
+        let mut names = Vec::new();
+        for _ in 0..subsection.leb()? {
+            let index = u32::try_from(subsection.leb()?).ok()?;
+            names.push((index, subsection.name()?));
+        }
+        return Some(names);
+    }
+    None
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Reader<'a> {
+        Reader { data, pos }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        self.data.get(self.pos..).unwrap_or(&[])
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        let byte = *self.data.get(self.pos)?;
+        self.pos += 1;
+        Some(byte)
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    /// Reads an unsigned LEB128 number, which is how all integers in the
+    /// module structure are stored.
+    fn leb(&mut self) -> Option<usize> {
+        let mut value = 0usize;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift >= 32 {
+                return None;
+            }
+            value |= usize::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+            shift += 7;
+        }
+    }
+
+    fn name(&mut self) -> Option<&'a [u8]> {
+        let len = self.leb()?;
+        self.bytes(len)
+    }
+
+    fn limits(&mut self) -> Option<()> {
+        let flags = self.u8()?;
+        self.leb()?;
+        if flags & 1 != 0 {
+            self.leb()?;
+        }
+        Some(())
+    }
+}
+
+/// A WebAssembly module and the data parsed from it, for `offline`
+/// symbolization.
+pub(super) struct Mapping {
+    // 'static lifetime is a lie, as for `super::Mapping`; the `Wasm` only
+    // borrows from `_map`, and comes first to be dropped first.
+    wasm: Wasm<'static>,
+    _map: MapData,
+}
+
+impl Mapping {
+    pub fn new(map: MapData) -> Option<Mapping> {
+        let wasm = Wasm::parse(&map)?;
+        // The data `map` holds stays in place when it's moved.
+        let wasm = unsafe { core::mem::transmute::<Wasm<'_>, Wasm<'static>>(wasm) };
+        Some(Mapping { wasm, _map: map })
+    }
+
+    pub fn resolve(&mut self, addr: *mut c_void, offset: u64, call: &mut dyn FnMut(Symbol<'_>)) {
+        self.wasm.resolve(addr, offset, call)
+    }
+}
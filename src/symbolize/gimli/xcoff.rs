@@ -0,0 +1,385 @@
+//! Reading XCOFF, the object file format of AIX.
+//!
+//! The `object` crate can't read XCOFF yet, so the few structures needed are
+//! read here directly. Only 64-bit XCOFF is supported, as AIX is only a
+//! 64-bit target. Shared libraries on AIX are usually members of archives,
+//! which the loader names like `/usr/lib/libc.a(shr_64.o)`, so those are
+//! found in "big" archives too.
+
+use super::mystd::ffi::OsStr;
+use super::mystd::os::unix::ffi::OsStrExt;
+use super::mystd::path::Path;
+use super::{Context, MapData, Mapping, Stash, Vec};
+use core::convert::TryInto;
+use core::ops::Range;
+use core::str;
+
+const MAGIC_64: u16 = 0x01f7;
+
+const FILE_HEADER_SIZE: usize = 24;
+const SECTION_HEADER_SIZE: usize = 72;
+const SYMBOL_SIZE: usize = 18;
+
+const STYP_TEXT: u32 = 0x20;
+
+const C_EXT: u8 = 2;
+const C_HIDEXT: u8 = 107;
+const C_WEAKEXT: u8 = 111;
+
+/// The `x_auxtype` of a csect auxiliary entry.
+const AUX_CSECT: u8 = 251;
+const XTY_SD: u8 = 1;
+const XTY_LD: u8 = 2;
+/// The storage mapping class of program code.
+const XMC_PR: u8 = 0;
+
+const BIG_ARCHIVE_MAGIC: &[u8] = b"<bigaf>\n";
+
+impl Mapping {
+    pub fn new(path: &Path) -> Option<Mapping> {
+        let (map, range) = match super::open(path) {
+            Some(source) => {
+                let map = source.map()?;
+                let len = map.len();
+                (map, 0..len)
+            }
+            None => load_member(path)?,
+        };
+        Mapping::mk(map, |map, stash| {
+            Context::new(stash, Object::parse(&map[range])?, None)
+        })
+    }
+
+    /// Creates a mapping from the contents of an object file.
+    pub fn from_bytes(data: impl Into<MapData>) -> Option<Mapping> {
+        Mapping::mk(data, |data, stash| {
+            Context::new(stash, Object::parse(data)?, None)
+        })
+    }
+}
+
+/// Where the text section of an image is, as needed to find where it's been
+/// loaded.
+pub(super) struct Image {
+    /// The stated address of the text section.
+    pub base: u64,
+    /// The size of the text section.
+    pub size: u64,
+    /// Where the text section is in the file.
+    pub offset: u64,
+}
+
+/// Reads where the text section is in the object file at `path`, which can
+/// be an archive member.
+pub(super) fn parse_image(path: &Path) -> Option<Image> {
+    let (map, range) = match super::open(path) {
+        Some(source) => {
+            let map = source.map()?;
+            let len = map.len();
+            (map, 0..len)
+        }
+        None => load_member(path)?,
+    };
+    let data = &map[range];
+    let header = FileHeader::parse(data)?;
+    let text = header
+        .sections(data)?
+        .find(|s| s.flags & 0xffff == STYP_TEXT)?;
+    Some(Image {
+        base: text.vaddr,
+        size: text.size,
+        offset: text.offset,
+    })
+}
+
+/// Loads an object file which is a member of an archive, as named by a path
+/// like `/usr/lib/libc.a(shr_64.o)`. Returns the data of the archive and
+/// where in it the object file is.
+fn load_member(path: &Path) -> Option<(MapData, Range<usize>)> {
+    let path = path.as_os_str().as_bytes();
+    if path.last() != Some(&b')') {
+        return None;
+    }
+    let open = path.iter().rposition(|b| *b == b'(')?;
+    let archive = Path::new(OsStr::from_bytes(&path[..open]));
+    let map = super::open(archive)?.map()?;
+    let range = member(&map, &path[open + 1..path.len() - 1])?;
+    Some((map, range))
+}
+
+/// Finds the contents of the member `name` of the big archive `data`.
+///
+/// Big archives start with a fixed header of decimal offsets, which include
+/// the first member's. Each member has a header with the offset of the next,
+/// its size and its name, followed by its contents.
+fn member(data: &[u8], name: &[u8]) -> Option<Range<usize>> {
+    if !data.starts_with(BIG_ARCHIVE_MAGIC) {
+        return None;
+    }
+    let first = decimal(data.get(68..88)?)?;
+    let last = decimal(data.get(88..108)?)?;
+    let mut offset = first;
+    loop {
+        let header = data.get(offset..offset.checked_add(112)?)?;
+        let size = decimal(&header[0..20])?;
+        let next = decimal(&header[20..40])?;
+        let name_len = decimal(&header[108..112])?;
+        let name_start = offset + 112;
+        let member_name = data.get(name_start..name_start.checked_add(name_len)?)?;
+        // The name is padded to an even length and followed by "`\n".
+        let start = name_start + name_len + name_len % 2 + 2;
+        if member_name == name {
+            let end = start.checked_add(size)?;
+            data.get(start..end)?;
+            return Some(start..end);
+        }
+        // Members are chained in order, which stops at the last one.
+        if offset == last || next <= offset {
+            return None;
+        }
+        offset = next;
+    }
+}
+
+/// Parses a number in an archive header, written in decimal and padded with
+/// spaces.
+fn decimal(field: &[u8]) -> Option<usize> {
+    let field = str::from_utf8(field)
+        .ok()?
+        .trim_end_matches(&[' ', '\0'][..]);
+    if field.is_empty() {
+        return Some(0);
+    }
+    field.parse().ok()
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+struct FileHeader {
+    nscns: u16,
+    symptr: u64,
+    opthdr: u16,
+    nsyms: u32,
+}
+
+impl FileHeader {
+    fn parse(data: &[u8]) -> Option<FileHeader> {
+        if u16_at(data, 0)? != MAGIC_64 {
+            return None;
+        }
+        Some(FileHeader {
+            nscns: u16_at(data, 2)?,
+            symptr: u64_at(data, 8)?,
+            opthdr: u16_at(data, 16)?,
+            nsyms: u32_at(data, 20)?,
+        })
+    }
+
+    fn sections<'a>(&self, data: &'a [u8]) -> Option<impl Iterator<Item = SectionHeader<'a>>> {
+        let start = FILE_HEADER_SIZE + usize::from(self.opthdr);
+        let len = usize::from(self.nscns) * SECTION_HEADER_SIZE;
+        let headers = data.get(start..start + len)?;
+        Some(
+            headers
+                .chunks_exact(SECTION_HEADER_SIZE)
+                .filter_map(SectionHeader::parse),
+        )
+    }
+}
+
+struct SectionHeader<'a> {
+    name: &'a [u8],
+    vaddr: u64,
+    size: u64,
+    offset: u64,
+    flags: u32,
+}
+
+impl<'a> SectionHeader<'a> {
+    fn parse(header: &'a [u8]) -> Option<SectionHeader<'a>> {
+        let name = &header[..8];
+        let len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+        Some(SectionHeader {
+            name: &name[..len],
+            vaddr: u64_at(header, 16)?,
+            size: u64_at(header, 24)?,
+            offset: u64_at(header, 32)?,
+            flags: u32_at(header, 64)?,
+        })
+    }
+
+    fn data(&self, data: &'a [u8]) -> Option<&'a [u8]> {
+        let start: usize = self.offset.try_into().ok()?;
+        let end = start.checked_add(self.size.try_into().ok()?)?;
+        data.get(start..end)
+    }
+}
+
+struct ParsedSym<'a> {
+    address: u64,
+    size: u64,
+    name: &'a [u8],
+}
+
+pub struct Object<'a> {
+    data: &'a [u8],
+    sections: Vec<SectionHeader<'a>>,
+    /// The function symbols, sorted by address.
+    syms: Vec<ParsedSym<'a>>,
+}
+
+impl<'a> Object<'a> {
+    fn parse(data: &'a [u8]) -> Option<Object<'a>> {
+        let header = FileHeader::parse(data)?;
+        let sections = header.sections(data)?.collect();
+
+        let symptr: usize = header.symptr.try_into().ok()?;
+        let symbols_len = (header.nsyms as usize).checked_mul(SYMBOL_SIZE)?;
+        let symbols = data.get(symptr..symptr.checked_add(symbols_len)?)?;
+        let strings = data.get(symptr + symbols_len..).unwrap_or(&[]);
+
+        // Functions are the symbols of csects of code, and of labels within
+        // them. Each symbol is followed by its auxiliary entries, the last of
+        // which describes its csect.
+        let mut syms = Vec::new();
+        let mut entries = symbols.chunks_exact(SYMBOL_SIZE);
+        while let Some(symbol) = entries.next() {
+            let numaux = usize::from(symbol[17]);
+            let aux = entries.by_ref().take(numaux).last();
+            let sclass = symbol[16];
+            if sclass != C_EXT && sclass != C_HIDEXT && sclass != C_WEAKEXT {
+                continue;
+            }
+            let csect = match aux {
+                Some(aux) if aux[17] == AUX_CSECT => aux,
+                _ => continue,
+            };
+            let smtyp = csect[10] & 0x7;
+            if csect[11] != XMC_PR || (smtyp != XTY_SD && smtyp != XTY_LD) {
+                continue;
+            }
+            let name = match string(strings, u32_at(symbol, 8)?) {
+                Some(name) => name,
+                None => continue,
+            };
+            // Entry points are named after the function with a leading dot,
+            // while the plain name is its descriptor.
+            let name = if name.starts_with(b".") {
+                &name[1..]
+            } else {
+                name
+            };
+            // The length of a label's csect is that of the csect it's in,
+            // which tells nothing about the function itself.
+            let size = if smtyp == XTY_SD {
+                (u64::from(u32_at(csect, 12)?) << 32) | u64::from(u32_at(csect, 0)?)
+            } else {
+                0
+            };
+            syms.push((
+                smtyp == XTY_SD,
+                ParsedSym {
+                    address: u64_at(symbol, 0)?,
+                    size,
+                    name,
+                },
+            ));
+        }
+        // Labels come before the csects at the same address, to be preferred.
+        syms.sort_unstable_by_key(|(csect, sym)| (sym.address, *csect));
+        let mut syms: Vec<_> = syms.into_iter().map(|(_, sym)| sym).collect();
+        syms.dedup_by_key(|sym| sym.address);
+
+        Some(Object {
+            data,
+            sections,
+            syms,
+        })
+    }
+
+    pub fn section(&self, _: &Stash, name: &str) -> Option<&'a [u8]> {
+        // DWARF sections have names of their own, which fit in 8 bytes.
+        let name: &[u8] = match name {
+            ".debug_abbrev" => b".dwabrev",
+            ".debug_aranges" => b".dwarnge",
+            ".debug_frame" => b".dwframe",
+            ".debug_info" => b".dwinfo",
+            ".debug_line" => b".dwline",
+            ".debug_loc" => b".dwloc",
+            ".debug_macinfo" => b".dwmac",
+            ".debug_pubnames" => b".dwpbnms",
+            ".debug_pubtypes" => b".dwpbtyp",
+            ".debug_ranges" => b".dwrnges",
+            ".debug_str" => b".dwstr",
+            _ => return None,
+        };
+        self.sections
+            .iter()
+            .find(|s| s.name == name)?
+            .data(self.data)
+    }
+
+    pub fn search_symtab<'b>(&'b self, addr: u64) -> Option<&'b [u8]> {
+        self.search_symtab_entry(addr).map(|(_, _, name)| name)
+    }
+
+    /// Like `search_symtab`, but also returns the address the symbol starts
+    /// at and, if known, its size.
+    pub fn search_symtab_entry(&self, addr: u64) -> Option<(u64, Option<u64>, &[u8])> {
+        let i = match self.syms.binary_search_by_key(&addr, |sym| sym.address) {
+            Ok(i) => i,
+            Err(i) => i.checked_sub(1)?,
+        };
+        let sym = self.syms.get(i)?;
+        // Labels have no size, so like with COFF the closest one is taken.
+        if sym.size == 0 || addr <= sym.address + sym.size {
+            Some((sym.address, Some(sym.size).filter(|s| *s != 0), sym.name))
+        } else {
+            None
+        }
+    }
+
+    /// Returns every symbol in the symbol table, as its address, its size if
+    /// known and its name.
+    pub fn symbols(&self) -> impl Iterator<Item = (u64, Option<u64>, &[u8])> + '_ {
+        self.syms
+            .iter()
+            .map(|sym| (sym.address, Some(sym.size).filter(|s| *s != 0), sym.name))
+    }
+
+    /// Returns the address of the first symbol whose name `matches`.
+    pub fn search_symtab_name(&self, mut matches: impl FnMut(&[u8]) -> bool) -> Option<u64> {
+        self.syms
+            .iter()
+            .find(|sym| matches(sym.name))
+            .map(|sym| sym.address)
+    }
+
+    pub(super) fn search_object_map(&self, _addr: u64) -> Option<(&Context<'_>, u64)> {
+        None
+    }
+}
+
+/// Returns the string at `offset` in the string table `strings`, which starts
+/// with its length.
+fn string(strings: &[u8], offset: u32) -> Option<&[u8]> {
+    let string = strings.get(offset as usize..)?;
+    let len = string.iter().position(|b| *b == 0)?;
+    Some(&string[..len])
+}
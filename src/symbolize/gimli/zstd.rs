@@ -0,0 +1,49 @@
+//! Decompressing zstd-compressed debug sections.
+//!
+//! Recent versions of binutils and lld can compress debug sections with zstd
+//! (`--compress-debug-sections=zstd`) instead of zlib. To decompress them we
+//! use `libzstd` if it's installed, which it is on any system where those
+//! tools are. It's loaded with `dlopen` so that there's no link-time
+//! dependency on it.
+
+use core::mem;
+
+type Decompress = unsafe extern "C" fn(
+    dst: *mut libc::c_void,
+    dst_capacity: libc::size_t,
+    src: *const libc::c_void,
+    src_size: libc::size_t,
+) -> libc::size_t;
+type IsError = unsafe extern "C" fn(code: libc::size_t) -> libc::c_uint;
+
+/// Decompresses the zstd frames in `input`, which must exactly fill `output`.
+pub(super) fn decompress(input: &[u8], output: &mut [u8]) -> Option<()> {
+    unsafe {
+        let lib = libc::dlopen(
+            b"libzstd.so.1\0".as_ptr() as *const libc::c_char,
+            libc::RTLD_NOW | libc::RTLD_LOCAL,
+        );
+        if lib.is_null() {
+            return None;
+        }
+        let sym = |name: &[u8]| libc::dlsym(lib, name.as_ptr() as *const libc::c_char);
+        let decompress = sym(b"ZSTD_decompress\0");
+        let is_error = sym(b"ZSTD_isError\0");
+        let mut ret = None;
+        if !decompress.is_null() && !is_error.is_null() {
+            let decompress = mem::transmute::<*mut libc::c_void, Decompress>(decompress);
+            let is_error = mem::transmute::<*mut libc::c_void, IsError>(is_error);
+            let size = decompress(
+                output.as_mut_ptr() as *mut libc::c_void,
+                output.len(),
+                input.as_ptr() as *const libc::c_void,
+                input.len(),
+            );
+            if is_error(size) == 0 && size == output.len() {
+                ret = Some(());
+            }
+        }
+        libc::dlclose(lib);
+        ret
+    }
+}
@@ -0,0 +1,352 @@
+//! Symbolization against ELF images held in memory, without `std`.
+//!
+//! The `resolve` function of this crate finds the libraries loaded into the
+//! current process and reads their files, which needs an operating system.
+//! Kernels, RTOSes and other `no_std` programs usually have the ELF image
+//! they were loaded from in memory anyway, and this module symbolizes
+//! addresses using images which the caller describes instead, needing only
+//! `alloc`.
+//!
+//! ```no_run
+//! use backtrace::image::{Image, Images, Symbolizer};
+//!
+//! struct Kernel {
+//!     elf: &'static [u8],
+//! }
+//!
+//! impl Images for Kernel {
+//!     fn images(&self, f: &mut dyn FnMut(Image)) {
+//!         f(Image { id: 0, start: 0x10_0000, len: 0x20_0000, bias: 0 });
+//!     }
+//!
+//!     fn read(&self, _image: &Image) -> Option<&[u8]> {
+//!         Some(self.elf)
+//!     }
+//! }
+//!
+//! # let elf = &[][..];
+//! let kernel = Kernel { elf };
+//! let mut symbolizer = Symbolizer::new(&kernel);
+//! unsafe {
+//!     backtrace::trace_unsynchronized(|frame| {
+//!         symbolizer.resolve_frame(frame, &mut |symbol| {
+//!             // ...
+//!             # let _ = symbol;
+//!         });
+//!         true
+//!     });
+//! }
+//! ```
+//!
+//! Function names come from the symbol table, and file and line information
+//! from DWARF, which is only read from sections that aren't compressed.
+
+use super::{adjust_ip, BytesOrWideString, Frame, SymbolName};
+use addr2line::gimli;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::mem;
+use object::elf::SHF_COMPRESSED;
+use object::read::elf::{FileHeader, SectionHeader, SectionTable, Sym};
+use object::NativeEndian;
+
+use self::gimli::read::EndianSlice;
+use self::gimli::NativeEndian as Endian;
+
+// Also used by the gimli backend, which uses more of it, through this module
+// since this one is compiled on every platform.
+#[path = "gimli/dwarf.rs"]
+#[allow(dead_code)]
+pub(super) mod dwarf;
+
+use self::dwarf::Dwarf;
+
+#[cfg(target_pointer_width = "32")]
+type Elf = object::elf::FileHeader32<NativeEndian>;
+#[cfg(target_pointer_width = "64")]
+type Elf = object::elf::FileHeader64<NativeEndian>;
+
+// Widens an address, size or flags read from a native ELF header, which are
+// `u32` on 32-bit targets and already `u64` on 64-bit ones.
+pub(super) fn word(word: impl Into<u64>) -> u64 {
+    word.into()
+}
+
+/// An ELF image loaded into the address space, as described by `Images`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Image {
+    /// An identifier for the image chosen by the caller, which is used to
+    /// cache what's been parsed from it.
+    pub id: usize,
+    /// The first address the image is loaded at.
+    pub start: usize,
+    /// How many bytes of the address space the image covers from `start`.
+    pub len: usize,
+    /// The difference between the addresses the image is loaded at and the
+    /// addresses stated in its ELF file, which is 0 unless it was relocated.
+    pub bias: usize,
+}
+
+/// The images that addresses can be in, and access to their ELF files.
+pub trait Images {
+    /// Calls `f` with each image that's loaded.
+    fn images(&self, f: &mut dyn FnMut(Image));
+
+    /// Returns the contents of the ELF file of `image`, which needs to include
+    /// its symbol table and debug sections to be of use.
+    fn read(&self, image: &Image) -> Option<&[u8]>;
+}
+
+/// Symbolizes addresses in the images of an `Images`, keeping what it parses
+/// from them until it's dropped.
+pub struct Symbolizer<'a, I: ?Sized> {
+    images: &'a I,
+    /// The images parsed so far, or `None` for those which couldn't be.
+    parsed: Vec<(usize, Option<Parsed<'a>>)>,
+}
+
+impl<'a, I: Images + ?Sized> Symbolizer<'a, I> {
+    /// Creates a symbolizer for the images of `images`.
+    pub fn new(images: &'a I) -> Symbolizer<'a, I> {
+        Symbolizer {
+            images,
+            parsed: Vec::new(),
+        }
+    }
+
+    /// Resolves an address, passing each symbol found for it to `cb`, the
+    /// same way as `backtrace::resolve` does for the current process.
+    pub fn resolve(&mut self, addr: *mut c_void, cb: &mut dyn FnMut(&super::Symbol)) {
+        self.resolve_adjusted(adjust_ip(addr), cb)
+    }
+
+    /// Resolves a frame captured with `trace` the same way as
+    /// `backtrace::resolve_frame` does.
+    pub fn resolve_frame(&mut self, frame: &Frame, cb: &mut dyn FnMut(&super::Symbol)) {
+        let addr = super::ResolveWhat::Frame(frame).address_or_ip();
+        self.resolve_adjusted(addr, cb)
+    }
+
+    fn resolve_adjusted(&mut self, addr: *mut c_void, cb: &mut dyn FnMut(&super::Symbol)) {
+        let mut found = None;
+        self.images.images(&mut |image| {
+            if found.is_none()
+                && image.start <= addr as usize
+                && addr as usize - image.start < image.len
+            {
+                found = Some(image);
+            }
+        });
+        let image = match found {
+            Some(image) => image,
+            None => return,
+        };
+
+        let i = match self.parsed.iter().position(|(id, _)| *id == image.id) {
+            Some(i) => i,
+            None => {
+                let parsed = self.images.read(&image).and_then(Parsed::parse);
+                self.parsed.push((image.id, parsed));
+                self.parsed.len() - 1
+            }
+        };
+        if let Some(parsed) = &mut self.parsed[i].1 {
+            let svma = (addr as usize).wrapping_sub(image.bias) as u64;
+            parsed.resolve(addr, svma, &mut |sym| {
+                // Symbols are only handed out by reference, see `resolve` in
+                // the gimli backend.
+                let sym = unsafe { mem::transmute::<ImageSymbol<'_>, ImageSymbol<'static>>(sym) };
+                cb(&super::Symbol::from_image(sym));
+            });
+        }
+    }
+}
+
+struct Parsed<'a> {
+    /// The function and object symbols, sorted by address.
+    syms: Vec<(u64, u64, &'a [u8])>,
+    dwarf: Option<Dwarf<'a>>,
+}
+
+impl<'a> Parsed<'a> {
+    fn parse(data: &'a [u8]) -> Option<Parsed<'a>> {
+        let elf = Elf::parse(data).ok()?;
+        let endian = elf.endian().ok()?;
+        let sections = elf.sections(endian, data).ok()?;
+
+        let mut syms = sections
+            .symbols(endian, data, object::elf::SHT_SYMTAB)
+            .ok()?;
+        if syms.is_empty() {
+            syms = sections
+                .symbols(endian, data, object::elf::SHT_DYNSYM)
+                .ok()?;
+        }
+        let strings = syms.strings();
+        let mut syms = syms
+            .iter()
+            .filter(|sym| {
+                let st_type = sym.st_type();
+                st_type == object::elf::STT_FUNC || st_type == object::elf::STT_OBJECT
+            })
+            .filter(|sym| sym.st_shndx(endian) != object::elf::SHN_UNDEF)
+            .filter_map(|sym| {
+                let name = strings.get(sym.st_name(endian)).ok()?;
+                Some((word(sym.st_value(endian)), word(sym.st_size(endian)), name))
+            })
+            .collect::<Vec<_>>();
+        syms.sort_unstable_by_key(|s| s.0);
+
+        let dwarf = gimli::Dwarf::load(|id| -> Result<_, ()> {
+            let data = section(&sections, data, id.name()).unwrap_or(&[]);
+            Ok(EndianSlice::new(data, Endian))
+        })
+        .ok()
+        .and_then(Dwarf::new);
+        Some(Parsed { syms, dwarf })
+    }
+
+    fn resolve(&mut self, addr: *mut c_void, svma: u64, call: &mut dyn FnMut(ImageSymbol<'_>)) {
+        let function = self.search_symtab(svma);
+        if let Some(dwarf) = &mut self.dwarf {
+            dwarf.load(svma);
+            if let Some(Ok(mut iter)) = dwarf.get(svma).map(|d| d.find_frames(svma)) {
+                // Outer frames come last, so collect them all to know how
+                // deeply each one is inlined.
+                let mut frames = Vec::new();
+                while let Ok(Some(frame)) = iter.next() {
+                    frames.push(frame);
+                }
+                if !frames.is_empty() {
+                    let count = frames.len();
+                    for (i, frame) in frames.into_iter().enumerate() {
+                        let location = frame.location.as_ref();
+                        let language = frame.function.as_ref().and_then(|f| f.language);
+                        call(ImageSymbol {
+                            addr,
+                            name: frame.function.map(|f| f.name.slice()).or(function),
+                            filename: location.and_then(|l| l.file),
+                            lineno: location.and_then(|l| l.line),
+                            colno: location.and_then(|l| l.column),
+                            inline_depth: Some((count - 1 - i) as u32),
+                            language,
+                        });
+                    }
+                    return;
+                }
+            }
+        }
+        if let Some(name) = function {
+            call(ImageSymbol {
+                addr,
+                name: Some(name),
+                filename: None,
+                lineno: None,
+                colno: None,
+                inline_depth: None,
+                language: None,
+            });
+        }
+    }
+
+    fn search_symtab(&self, addr: u64) -> Option<&'a [u8]> {
+        let i = match self.syms.binary_search_by_key(&addr, |sym| sym.0) {
+            Ok(i) => i,
+            Err(i) => i.checked_sub(1)?,
+        };
+        let (address, size, name) = *self.syms.get(i)?;
+        // A symbol of unknown size only covers its own address.
+        if addr == address || (address < addr && addr < address + size) {
+            Some(name)
+        } else {
+            None
+        }
+    }
+}
+
+fn section<'a>(sections: &SectionTable<'a, Elf>, data: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    let endian = NativeEndian;
+    let (_, header) = sections.section_by_name(endian, name.as_bytes())?;
+    let flags = word(header.sh_flags(endian));
+    if flags & u64::from(SHF_COMPRESSED) != 0 {
+        return None;
+    }
+    header.data(endian, data).ok()
+}
+
+/// A symbol found by a `Symbolizer`.
+pub(super) struct ImageSymbol<'a> {
+    addr: *mut c_void,
+    name: Option<&'a [u8]>,
+    filename: Option<&'a str>,
+    lineno: Option<u32>,
+    colno: Option<u32>,
+    inline_depth: Option<u32>,
+    language: Option<gimli::DwLang>,
+}
+
+impl ImageSymbol<'_> {
+    pub fn name(&self) -> Option<SymbolName<'_>> {
+        self.name.map(SymbolName::new)
+    }
+
+    pub fn addr(&self) -> Option<*mut c_void> {
+        Some(self.addr)
+    }
+
+    pub fn filename_raw(&self) -> Option<BytesOrWideString<'_>> {
+        self.filename
+            .map(|f| BytesOrWideString::Bytes(f.as_bytes()))
+    }
+
+    #[cfg(feature = "std")]
+    pub fn filename(&self) -> Option<&std::path::Path> {
+        self.filename.map(std::path::Path::new)
+    }
+
+    pub fn lineno(&self) -> Option<u32> {
+        self.lineno
+    }
+
+    pub fn colno(&self) -> Option<u32> {
+        self.colno
+    }
+
+    pub fn inline_depth(&self) -> Option<u32> {
+        self.inline_depth
+    }
+
+    pub fn language(&self) -> Option<u16> {
+        self.language.map(|l| l.0)
+    }
+
+    pub fn comp_dir(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symtab_bounds() {
+        let parsed = Parsed {
+            syms: vec![
+                (0x1000, 0x10, &b"first"[..]),
+                (0x1010, 0x8, &b"second"[..]),
+                (0x1020, 0, &b"unsized"[..]),
+            ],
+            dwarf: None,
+        };
+        assert_eq!(parsed.search_symtab(0xfff), None);
+        assert_eq!(parsed.search_symtab(0x1000), Some(&b"first"[..]));
+        assert_eq!(parsed.search_symtab(0x100f), Some(&b"first"[..]));
+        assert_eq!(parsed.search_symtab(0x1010), Some(&b"second"[..]));
+        assert_eq!(parsed.search_symtab(0x1017), Some(&b"second"[..]));
+        assert_eq!(parsed.search_symtab(0x1018), None);
+        assert_eq!(parsed.search_symtab(0x1020), Some(&b"unsized"[..]));
+        assert_eq!(parsed.search_symtab(0x1021), None);
+    }
+}
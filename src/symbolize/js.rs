@@ -0,0 +1,166 @@
+//! Symbolization of frames captured from the JS host, which already carry
+//! the names and locations the engine reported. Bare addresses can't be
+//! resolved.
+
+use super::super::backtrace::js::Frame;
+use super::{BytesOrWideString, ResolveWhat, SymbolName};
+use core::ffi::c_void;
+use core::marker::PhantomData;
+
+pub unsafe fn resolve(what: ResolveWhat<'_>, cb: &mut dyn FnMut(&super::Symbol)) {
+    if let ResolveWhat::Frame(frame) = what.unadjusted() {
+        if frame.inner.name.is_some() || frame.inner.filename.is_some() {
+            cb(&super::Symbol::from_imp(Symbol {
+                inner: frame.inner.clone(),
+                _unused: PhantomData,
+            }));
+        }
+    }
+}
+
+pub struct Symbol<'a> {
+    inner: Frame,
+    _unused: PhantomData<&'a ()>,
+}
+
+impl<'a> Symbol<'a> {
+    pub fn name(&self) -> Option<SymbolName<'_>> {
+        let name = self.inner.name.as_ref()?;
+        Some(SymbolName::new(name.as_bytes()))
+    }
+
+    pub fn addr(&self) -> Option<*mut c_void> {
+        None
+    }
+
+    pub fn filename_raw(&self) -> Option<BytesOrWideString<'_>> {
+        let filename = self.inner.filename.as_ref()?;
+        Some(BytesOrWideString::Bytes(filename.as_bytes()))
+    }
+
+    pub fn lineno(&self) -> Option<u32> {
+        self.inner.lineno
+    }
+
+    pub fn colno(&self) -> Option<u32> {
+        self.inner.colno
+    }
+
+    pub fn inline_depth(&self) -> Option<u32> {
+        None
+    }
+
+    pub fn language(&self) -> Option<u16> {
+        None
+    }
+
+    pub fn comp_dir(&self) -> Option<&[u8]> {
+        None
+    }
+
+    #[cfg(feature = "std")]
+    pub fn filename(&self) -> Option<&std::path::Path> {
+        let filename = self.inner.filename.as_ref()?;
+        Some(std::path::Path::new(&**filename))
+    }
+}
+
+pub unsafe fn clear_symbol_cache() {}
+
+#[cfg(feature = "std")]
+pub unsafe fn resolve_many(what: &[ResolveWhat<'_>], cb: &mut dyn FnMut(usize, &super::Symbol)) {
+    for (i, what) in what.iter().enumerate() {
+        resolve(*what, &mut |symbol| cb(i, symbol));
+    }
+}
+
+#[cfg(feature = "std")]
+pub fn loaded_modules() -> std::vec::Vec<crate::LoadedModule> {
+    std::vec::Vec::new()
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn set_symbol_cache_capacity(_modules: usize, _bytes: Option<usize>) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn set_symbol_memory_limit(_limit: Option<usize>) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn set_object_file_mmap(_enabled: bool) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn set_symbol_search_path(_path: Option<&std::ffi::OsStr>) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn set_symbol_server_downloads(_enabled: bool) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn symbol_cache_usage() -> (usize, usize) {
+    (0, 0)
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn prewarm_symbol_cache(_modules: Option<&[&std::path::Path]>) -> usize {
+    0
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn find_symbol(_name: &str) -> Option<(*mut c_void, std::path::PathBuf)> {
+    None
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn for_each_symbol(_cb: &mut dyn FnMut(usize, usize, &[u8])) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn evict_symbol_cache(_addr: usize) -> bool {
+    false
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn evict_symbol_cache_module(_module: super::ModuleKey<'_>) -> bool {
+    false
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn symbol_cache_stats() -> std::vec::Vec<super::ModuleCacheStats> {
+    std::vec::Vec::new()
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn register_module(_base: usize, _len: usize, _source: super::ModuleSource) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn unregister_module(_base: usize) -> bool {
+    false
+}
+
+#[cfg(feature = "std")]
+pub enum OfflineMapping {}
+
+#[cfg(feature = "std")]
+impl OfflineMapping {
+    pub fn from_path(_path: &std::path::Path) -> Option<OfflineMapping> {
+        None
+    }
+
+    pub fn from_bytes(_data: std::vec::Vec<u8>) -> Option<OfflineMapping> {
+        None
+    }
+
+    pub fn image_base(&self) -> Option<usize> {
+        match *self {}
+    }
+
+    pub fn resolve(&mut self, _addr: *mut c_void, _cb: &mut dyn FnMut(&super::Symbol)) {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn with_module(_addr: *mut c_void, _cb: &mut dyn FnMut(&super::ModuleRef<'_>)) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn function_bounds(_addr: *mut c_void) -> Option<(*mut c_void, Option<usize>)> {
+    None
+}
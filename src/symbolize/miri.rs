@@ -6,17 +6,17 @@ use super::BytesOrWideString;
 use super::{ResolveWhat, SymbolName};
 
 pub unsafe fn resolve(what: ResolveWhat<'_>, cb: &mut dyn FnMut(&super::Symbol)) {
-    let sym = match what {
-        ResolveWhat::Address(addr) => Symbol {
-            inner: resolve_addr(addr),
-            _unused: PhantomData,
-        },
+    let sym = match what.unadjusted() {
         ResolveWhat::Frame(frame) => Symbol {
             inner: frame.inner.clone(),
             _unused: PhantomData,
         },
+        _ => Symbol {
+            inner: resolve_addr(what.address()),
+            _unused: PhantomData,
+        },
     };
-    cb(&super::Symbol { inner: sym })
+    cb(&super::Symbol::from_imp(sym))
 }
 
 pub struct Symbol<'a> {
@@ -45,6 +45,18 @@ impl<'a> Symbol<'a> {
         Some(self.inner.inner.colno)
     }
 
+    pub fn inline_depth(&self) -> Option<u32> {
+        None
+    }
+
+    pub fn language(&self) -> Option<u16> {
+        None
+    }
+
+    pub fn comp_dir(&self) -> Option<&[u8]> {
+        None
+    }
+
     #[cfg(feature = "std")]
     pub fn filename(&self) -> Option<&std::path::Path> {
         Some(std::path::Path::new(
@@ -54,3 +66,128 @@ impl<'a> Symbol<'a> {
 }
 
 pub unsafe fn clear_symbol_cache() {}
+
+#[cfg(feature = "std")]
+pub unsafe fn resolve_many(what: &[ResolveWhat<'_>], cb: &mut dyn FnMut(usize, &super::Symbol)) {
+    for (i, what) in what.iter().enumerate() {
+        resolve(*what, &mut |symbol| cb(i, symbol));
+    }
+}
+
+#[cfg(feature = "std")]
+pub fn loaded_modules() -> std::vec::Vec<crate::LoadedModule> {
+    std::vec::Vec::new()
+}
+
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+pub fn process_modules(_pid: u32) -> std::vec::Vec<crate::LoadedModule> {
+    std::vec::Vec::new()
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn set_symbol_cache_capacity(_modules: usize, _bytes: Option<usize>) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn set_symbol_memory_limit(_limit: Option<usize>) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn set_object_file_mmap(_enabled: bool) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn set_symbol_search_path(_path: Option<&std::ffi::OsStr>) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn set_symbol_server_downloads(_enabled: bool) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn symbol_cache_usage() -> (usize, usize) {
+    (0, 0)
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn prewarm_symbol_cache(_modules: Option<&[&std::path::Path]>) -> usize {
+    0
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn find_symbol(_name: &str) -> Option<(*mut c_void, std::path::PathBuf)> {
+    None
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn for_each_symbol(_cb: &mut dyn FnMut(usize, usize, &[u8])) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn evict_symbol_cache(_addr: usize) -> bool {
+    false
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn evict_symbol_cache_module(_module: super::ModuleKey<'_>) -> bool {
+    false
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn symbol_cache_stats() -> std::vec::Vec<super::ModuleCacheStats> {
+    std::vec::Vec::new()
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn register_module(_base: usize, _len: usize, _source: super::ModuleSource) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn unregister_module(_base: usize) -> bool {
+    false
+}
+
+#[cfg(feature = "std")]
+pub enum OfflineMapping {}
+
+#[cfg(feature = "std")]
+impl OfflineMapping {
+    pub fn from_path(_path: &std::path::Path) -> Option<OfflineMapping> {
+        None
+    }
+
+    pub fn from_bytes(_data: std::vec::Vec<u8>) -> Option<OfflineMapping> {
+        None
+    }
+
+    pub fn image_base(&self) -> Option<usize> {
+        match *self {}
+    }
+
+    pub fn resolve(&mut self, _addr: *mut c_void, _cb: &mut dyn FnMut(&super::Symbol)) {
+        match *self {}
+    }
+}
+
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+pub struct RemoteSymbolizer;
+
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+impl RemoteSymbolizer {
+    pub fn new() -> RemoteSymbolizer {
+        RemoteSymbolizer
+    }
+
+    pub fn resolve(&mut self, _pid: u32, _addr: *mut c_void, _cb: &mut dyn FnMut(&super::Symbol)) {}
+
+    pub fn forget(&mut self, _pid: u32) {}
+}
+
+#[cfg(feature = "std")]
+pub unsafe fn with_module(_addr: *mut c_void, _cb: &mut dyn FnMut(&super::ModuleRef<'_>)) {}
+
+#[cfg(feature = "std")]
+pub unsafe fn function_bounds(_addr: *mut c_void) -> Option<(*mut c_void, Option<usize>)> {
+    None
+}
+
+#[cfg(all(feature = "std", unix))]
+pub unsafe fn prepare_signal_safe() -> bool {
+    false
+}
+
+#[cfg(all(feature = "std", unix))]
+pub unsafe fn resolve_signal_safe(_what: ResolveWhat<'_>, _cb: &mut dyn FnMut(&super::Symbol)) {}
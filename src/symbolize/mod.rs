@@ -2,14 +2,16 @@ use core::{fmt, str};
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "std")] {
-        use std::path::Path;
+        use core::cell::UnsafeCell;
+        use std::path::{Path, PathBuf};
         use std::prelude::v1::*;
     }
 }
 
-use super::backtrace::Frame;
+use super::backtrace::{Frame, FrameTrust};
 use super::types::BytesOrWideString;
 use core::ffi::c_void;
+use core::sync::atomic::{AtomicBool, Ordering};
 use rustc_demangle::{try_demangle, Demangle};
 
 /// Resolve an address to a symbol, passing the symbol to the specified
@@ -63,6 +65,102 @@ pub fn resolve<F: FnMut(&Symbol)>(addr: *mut c_void, cb: F) {
     unsafe { resolve_unsynchronized(addr, cb) }
 }
 
+/// Resolves many addresses at once, passing each symbol to the specified
+/// closure along with the index in `addrs` of the address it's for.
+///
+/// This works the same as calling `resolve` for each address, but is faster
+/// for larger numbers of addresses. Where possible the addresses are grouped
+/// by the module containing them and resolved in order, so that each module's
+/// debug information is looked up only once. The symbols of each address are
+/// passed in the same order `resolve` would pass them, but the addresses are
+/// not necessarily visited in the order they're given in.
+///
+/// The addresses don't have to come from `trace`: return addresses read out
+/// of a ring buffer, or the saved link registers of another thread, work just
+/// as well. Like with `resolve`, each address is looked up as a return
+/// address, at the byte before it, so that the call instruction it returns
+/// from is what's reported. Null addresses and addresses outside of any module
+/// are skipped.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Panics
+///
+/// See information on `resolve` for caveats on `cb` panicking.
+///
+/// # Example
+///
+/// ```
+/// extern crate backtrace;
+///
+/// fn main() {
+///     let mut ips = Vec::new();
+///     backtrace::trace(|frame| {
+///         ips.push(frame.ip());
+///         true
+///     });
+///
+///     backtrace::resolve_many(&ips, |i, symbol| {
+///         // `symbol` is for `ips[i]`...
+///     });
+/// }
+/// ```
+#[cfg(feature = "std")]
+pub fn resolve_many<F: FnMut(usize, &Symbol)>(addrs: &[*mut c_void], mut cb: F) {
+    let what = addrs
+        .iter()
+        .map(|addr| ResolveWhat::Address(*addr))
+        .collect::<std::vec::Vec<_>>();
+    resolve_what_many(&what, &mut cb)
+}
+
+// Like `resolve_many`, but for a mix of addresses and frames.
+#[cfg(feature = "std")]
+pub(crate) fn resolve_what_many(what: &[ResolveWhat<'_>], cb: &mut dyn FnMut(usize, &Symbol)) {
+    let _guard = crate::lock::lock();
+    unsafe {
+        let _resolving = match Resolving::enter() {
+            Some(resolving) => resolving,
+            None => return,
+        };
+        if fixture::is_installed() {
+            for (i, what) in what.iter().enumerate() {
+                fixture::resolve(what.address() as usize, &mut |sym| cb(i, sym));
+            }
+            return;
+        }
+        let contexts = what
+            .iter()
+            .map(|what| SymbolContext::find(what))
+            .collect::<Vec<_>>();
+        let mut found = vec![false; what.len()];
+        imp::resolve_many(what, &mut |i, sym| {
+            found[i] = true;
+            sym.set_context(&contexts[i]);
+            cb(i, sym)
+        });
+        // Like in `resolve_imp`, what the backend found nothing for is left
+        // to CoreSymbolication and then to the hooks from `register_resolver`.
+        for (i, what) in what.iter().enumerate().filter(|(i, _)| !found[*i]) {
+            let addr = what.address_or_ip();
+            #[cfg(all(feature = "coresymbolication", target_os = "macos"))]
+            {
+                let found = coresymbolication::resolve(addr, &mut |sym| {
+                    sym.set_context(&contexts[i]);
+                    cb(i, sym)
+                });
+                if found {
+                    continue;
+                }
+            }
+            resolver::resolve(addr, &mut |sym| cb(i, sym));
+        }
+    }
+}
+
 /// Resolve a previously capture frame to a symbol, passing the symbol to the
 /// specified closure.
 ///
@@ -105,17 +203,159 @@ pub fn resolve_frame<F: FnMut(&Symbol)>(frame: &Frame, cb: F) {
     unsafe { resolve_frame_unsynchronized(frame, cb) }
 }
 
+/// Same as `resolve`, but with control over how `addr` is adjusted before it's
+/// looked up.
+///
+/// `resolve` takes `addr` to be a return address and looks up the byte before
+/// it, which is right for all but the innermost frame of a trace. Profilers
+/// sampling the ip of an interrupted thread, or symbolizing the leaf frame of
+/// a trace, want `IpAdjustment::Never` instead so that the instruction at
+/// `addr` itself is reported.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Panics
+///
+/// See information on `resolve` for caveats on `cb` panicking.
+///
+/// # Example
+///
+/// ```
+/// extern crate backtrace;
+///
+/// use backtrace::IpAdjustment;
+///
+/// fn main() {
+///     let mut leaf = true;
+///     backtrace::trace(|frame| {
+///         let adjustment = if leaf {
+///             IpAdjustment::Never
+///         } else {
+///             IpAdjustment::Default
+///         };
+///         leaf = false;
+///         backtrace::resolve_with_ip_adjustment(frame.ip(), adjustment, |symbol| {
+///             // ...
+///         });
+///         true
+///     });
+/// }
+/// ```
+#[cfg(feature = "std")]
+#[allow(clippy::not_unsafe_ptr_arg_deref)] // `addr` is looked up, not dereferenced
+pub fn resolve_with_ip_adjustment<F: FnMut(&Symbol)>(
+    addr: *mut c_void,
+    adjustment: IpAdjustment,
+    cb: F,
+) {
+    let _guard = crate::lock::lock();
+    unsafe { resolve_with_ip_adjustment_unsynchronized(addr, adjustment, cb) }
+}
+
+/// Same as `resolve_frame`, but with control over how the ip of `frame` is
+/// adjusted before it's looked up.
+///
+/// See `resolve_with_ip_adjustment` and `IpAdjustment` for more information.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+///
+/// # Panics
+///
+/// See information on `resolve` for caveats on `cb` panicking.
+#[cfg(feature = "std")]
+pub fn resolve_frame_with_ip_adjustment<F: FnMut(&Symbol)>(
+    frame: &Frame,
+    adjustment: IpAdjustment,
+    cb: F,
+) {
+    let _guard = crate::lock::lock();
+    unsafe { resolve_frame_with_ip_adjustment_unsynchronized(frame, adjustment, cb) }
+}
+
+/// How an ip is adjusted before it's looked up, see
+/// `resolve_with_ip_adjustment`.
+///
+/// The ips of all but the innermost frame of a trace are return addresses,
+/// which point after the call instruction the frame is in, possibly into the
+/// next line or even the next function. So by default one is subtracted from
+/// them to look up the call instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum IpAdjustment {
+    /// What `resolve` and `resolve_frame` do: one is subtracted from bare
+    /// addresses, and from the ips of frames unless they were interrupted by
+    /// a signal, see `Frame::is_signal_frame`.
+    Default,
+    /// Like `Default`, but frames taken from a saved register state
+    /// (`FrameTrust::Context`), such as the innermost frame of
+    /// `trace_from_context`, are looked up at their ip too, as it's the
+    /// instruction which was interrupted rather than a return address.
+    ReturnAddressesOnly,
+    /// One is always subtracted.
+    Always,
+    /// The ip is looked up as it is, for ips which are known to not be
+    /// return addresses, like the leaf frame of a trace.
+    Never,
+}
+
+impl IpAdjustment {
+    fn is_return_address(self, what: &ResolveWhat<'_>) -> bool {
+        match (self, what) {
+            (IpAdjustment::Never, _) => false,
+            (IpAdjustment::Always, _) => true,
+            (_, ResolveWhat::Frame(f)) if f.is_signal_frame() == Some(true) => false,
+            (IpAdjustment::ReturnAddressesOnly, ResolveWhat::Frame(f)) => {
+                f.trust() != FrameTrust::Context
+            }
+            (_, ResolveWhat::Adjusted(what, adjustment)) => adjustment.is_return_address(what),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
 pub enum ResolveWhat<'a> {
     Address(*mut c_void),
     Frame(&'a Frame),
+    /// An address or frame which is adjusted otherwise than by default.
+    Adjusted(&'a ResolveWhat<'a>, IpAdjustment),
 }
 
 impl<'a> ResolveWhat<'a> {
     #[allow(dead_code)]
-    fn address_or_ip(&self) -> *mut c_void {
+    pub(crate) fn address_or_ip(&self) -> *mut c_void {
+        let adjustment = match self {
+            ResolveWhat::Adjusted(_, adjustment) => *adjustment,
+            _ => IpAdjustment::Default,
+        };
+        if adjustment.is_return_address(self) {
+            adjust_ip(self.address())
+        } else {
+            self.address()
+        }
+    }
+
+    /// The address or ip as given, before `address_or_ip` adjusts it.
+    fn address(&self) -> *mut c_void {
+        match self {
+            ResolveWhat::Address(a) => *a,
+            ResolveWhat::Frame(f) => f.ip(),
+            ResolveWhat::Adjusted(what, _) => what.address(),
+        }
+    }
+
+    /// What is resolved, without any non-default adjustment.
+    #[allow(dead_code)]
+    fn unadjusted(&self) -> ResolveWhat<'a> {
         match self {
-            ResolveWhat::Address(a) => adjust_ip(*a),
-            ResolveWhat::Frame(f) => adjust_ip(f.ip()),
+            ResolveWhat::Adjusted(what, _) => **what,
+            what => *what,
         }
     }
 }
@@ -135,9 +375,10 @@ impl<'a> ResolveWhat<'a> {
 // also expose on `Frame` if we are indeed the address of the next instruction
 // or the current.
 //
-// For now though this is a pretty niche concern so we just internally always
-// subtract one. Consumers should keep working and getting pretty good results,
-// so we should be good enough.
+// For now though this is a pretty niche concern so by default we just
+// internally always subtract one. Consumers should keep working and getting
+// pretty good results, so we should be good enough. Those who need precise
+// attribution of the leaf frame can choose otherwise with `IpAdjustment`.
 fn adjust_ip(a: *mut c_void) -> *mut c_void {
     if a.is_null() {
         a
@@ -159,7 +400,7 @@ pub unsafe fn resolve_unsynchronized<F>(addr: *mut c_void, mut cb: F)
 where
     F: FnMut(&Symbol),
 {
-    imp::resolve(ResolveWhat::Address(addr), &mut cb)
+    resolve_imp(ResolveWhat::Address(addr), &mut cb)
 }
 
 /// Same as `resolve_frame`, only unsafe as it's unsynchronized.
@@ -175,7 +416,123 @@ pub unsafe fn resolve_frame_unsynchronized<F>(frame: &Frame, mut cb: F)
 where
     F: FnMut(&Symbol),
 {
-    imp::resolve(ResolveWhat::Frame(frame), &mut cb)
+    resolve_imp(ResolveWhat::Frame(frame), &mut cb)
+}
+
+/// Same as `resolve_with_ip_adjustment`, only unsafe as it's unsynchronized.
+///
+/// This function does not have synchronization guarantees but is available
+/// when the `std` feature of this crate isn't compiled in. See the
+/// `resolve_with_ip_adjustment` function for more documentation.
+///
+/// # Safety
+///
+/// No other thread may be resolving symbols or tracing at the same time.
+///
+/// # Panics
+///
+/// See information on `resolve` for caveats on `cb` panicking.
+pub unsafe fn resolve_with_ip_adjustment_unsynchronized<F>(
+    addr: *mut c_void,
+    adjustment: IpAdjustment,
+    mut cb: F,
+) where
+    F: FnMut(&Symbol),
+{
+    let what = ResolveWhat::Address(addr);
+    resolve_imp(ResolveWhat::Adjusted(&what, adjustment), &mut cb)
+}
+
+/// Same as `resolve_frame_with_ip_adjustment`, only unsafe as it's
+/// unsynchronized.
+///
+/// This function does not have synchronization guarantees but is available
+/// when the `std` feature of this crate isn't compiled in. See the
+/// `resolve_frame_with_ip_adjustment` function for more documentation.
+///
+/// # Safety
+///
+/// No other thread may be resolving symbols or tracing at the same time.
+///
+/// # Panics
+///
+/// See information on `resolve` for caveats on `cb` panicking.
+pub unsafe fn resolve_frame_with_ip_adjustment_unsynchronized<F>(
+    frame: &Frame,
+    adjustment: IpAdjustment,
+    mut cb: F,
+) where
+    F: FnMut(&Symbol),
+{
+    let what = ResolveWhat::Frame(frame);
+    resolve_imp(ResolveWhat::Adjusted(&what, adjustment), &mut cb)
+}
+
+// Resolves with the backend, and if that doesn't find anything with the
+// hooks from `register_resolver`. With a fixture installed on this thread
+// only the fixture is used.
+unsafe fn resolve_imp(what: ResolveWhat<'_>, cb: &mut dyn FnMut(&Symbol)) {
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "std")] {
+            let _resolving = match Resolving::enter() {
+                Some(resolving) => resolving,
+                None => return,
+            };
+            if fixture::resolve(what.address() as usize, cb) {
+                return;
+            }
+            let addr = what.address_or_ip();
+            let context = SymbolContext::find(&what);
+            let mut any = false;
+            imp::resolve(what, &mut |sym: &Symbol| {
+                any = true;
+                sym.set_context(&context);
+                cb(sym)
+            });
+            #[cfg(all(feature = "coresymbolication", target_os = "macos"))]
+            {
+                if !any {
+                    any = coresymbolication::resolve(addr, cb);
+                }
+            }
+            if !any {
+                resolver::resolve(addr, cb);
+            }
+        } else {
+            imp::resolve(what, cb)
+        }
+    }
+}
+
+// Set while symbols are resolved, which is with the global lock held, so that
+// resolving again on the same thread from within, such as from an allocator
+// hook called for an allocation of the symbolizer, finds nothing instead of
+// using the caches the outer resolution is in the middle of changing.
+#[cfg(feature = "std")]
+static mut RESOLVING: bool = false;
+
+#[cfg(feature = "std")]
+struct Resolving;
+
+#[cfg(feature = "std")]
+impl Resolving {
+    // unsafe because this is required to be externally synchronized
+    unsafe fn enter() -> Option<Resolving> {
+        if RESOLVING {
+            return None;
+        }
+        RESOLVING = true;
+        Some(Resolving)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for Resolving {
+    fn drop(&mut self) {
+        unsafe {
+            RESOLVING = false;
+        }
+    }
 }
 
 /// A trait representing the resolution of a symbol in a file.
@@ -188,13 +545,113 @@ where
 /// name, filename, line number, precise address, etc. Not all information is
 /// always available in a symbol, however, so all methods return an `Option`.
 pub struct Symbol {
+    inner: SymbolImp,
+    // Only written by `set_context`, before the symbol is handed out.
+    #[cfg(feature = "std")]
+    context: UnsafeCell<Option<SymbolContext>>,
+}
+
+/// Where the address a symbol was resolved for is.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+struct SymbolContext {
+    /// The address as given, rather than the one looked up.
+    addr: usize,
+    module: Option<SymbolModule>,
+    function_start: Option<usize>,
+    function_size: Option<usize>,
+}
+
+#[cfg(feature = "std")]
+impl SymbolContext {
+    // unsafe because this is required to be externally synchronized
+    unsafe fn find(what: &ResolveWhat<'_>) -> SymbolContext {
+        let addr = what.address_or_ip();
+        let bounds = imp::function_bounds(addr);
+        SymbolContext {
+            addr: what.address() as usize,
+            module: SymbolModule::find(addr),
+            function_start: bounds.map(|(start, _)| start as usize),
+            function_size: bounds.and_then(|(_, size)| size),
+        }
+    }
+}
+
+/// The module containing the address a symbol was resolved for.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+struct SymbolModule {
+    path: PathBuf,
+    base: usize,
+}
+
+#[cfg(feature = "std")]
+impl SymbolModule {
+    // unsafe because this is required to be externally synchronized
+    unsafe fn find(addr: *mut c_void) -> Option<SymbolModule> {
+        // Like for resolution, the address is taken to be a return address,
+        // which can be just past the end of the module.
+        let addr = (addr as usize).wrapping_sub(1);
+        let mut module = None;
+        with_module(addr as *mut c_void, &mut |m| {
+            module = Some(SymbolModule {
+                path: PathBuf::from(m.name),
+                base: addr.wrapping_sub(m.offset),
+            });
+        });
+        module
+    }
+}
+
+enum SymbolImp {
     // TODO: this lifetime bound needs to be persisted eventually to `Symbol`,
     // but that's currently a breaking change. For now this is safe since
-    // `Symbol` is only ever handed out by reference and can't be cloned.
-    inner: imp::Symbol<'static>,
+    // backend symbols are only ever handed out by reference and can't be
+    // cloned.
+    Backend(imp::Symbol<'static>),
+    // Same as for `Backend`.
+    Image(image::ImageSymbol<'static>),
+    #[cfg(feature = "std")]
+    Custom(resolver::CustomSymbol),
 }
 
 impl Symbol {
+    fn from_imp(inner: imp::Symbol<'static>) -> Symbol {
+        Symbol {
+            inner: SymbolImp::Backend(inner),
+            #[cfg(feature = "std")]
+            context: UnsafeCell::new(None),
+        }
+    }
+
+    fn from_image(inner: image::ImageSymbol<'static>) -> Symbol {
+        Symbol {
+            inner: SymbolImp::Image(inner),
+            #[cfg(feature = "std")]
+            context: UnsafeCell::new(None),
+        }
+    }
+
+    // Records where the symbol was resolved. Symbols are only handed out by
+    // reference, and this is called before they are, so nothing can be
+    // borrowing the context yet.
+    #[cfg(feature = "std")]
+    fn set_context(&self, context: &SymbolContext) {
+        unsafe {
+            *self.context.get() = Some(context.clone());
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn context(&self) -> Option<&SymbolContext> {
+        unsafe { (*self.context.get()).as_ref() }
+    }
+
+    #[cfg(feature = "std")]
+    fn module(&self) -> Option<&SymbolModule> {
+        self.context()?.module.as_ref()
+    }
+
     /// Returns the name of this function.
     ///
     /// The returned structure can be used to query various properties about the
@@ -205,26 +662,57 @@ impl Symbol {
     ///   utf-8).
     /// * The raw bytes for the symbol name can be accessed.
     pub fn name(&self) -> Option<SymbolName<'_>> {
-        self.inner.name()
+        match &self.inner {
+            SymbolImp::Backend(s) => s.name(),
+            SymbolImp::Image(s) => s.name(),
+            #[cfg(feature = "std")]
+            SymbolImp::Custom(s) => Some(SymbolName::new(&s.name)),
+        }
+    }
+
+    /// Returns the name of this function as it was found, without demangling
+    /// it, if it's valid utf-8.
+    ///
+    /// See `SymbolName::raw` for more information.
+    pub fn raw_name(&self) -> Option<&str> {
+        self.name()?.raw()
     }
 
     /// Returns the starting address of this function.
     pub fn addr(&self) -> Option<*mut c_void> {
-        self.inner.addr().map(|p| p as *mut _)
+        match &self.inner {
+            SymbolImp::Backend(s) => s.addr().map(|p| p as *mut _),
+            SymbolImp::Image(s) => s.addr(),
+            #[cfg(feature = "std")]
+            SymbolImp::Custom(s) => s.addr,
+        }
     }
 
     /// Returns the raw filename as a slice. This is mainly useful for `no_std`
     /// environments.
     pub fn filename_raw(&self) -> Option<BytesOrWideString<'_>> {
-        self.inner.filename_raw()
+        match &self.inner {
+            SymbolImp::Backend(s) => s.filename_raw(),
+            SymbolImp::Image(s) => s.filename_raw(),
+            #[cfg(feature = "std")]
+            SymbolImp::Custom(s) => s.filename_raw(),
+        }
     }
 
     /// Returns the column number for where this symbol is currently executing.
     ///
     /// Only gimli currently provides a value here and even then only if `filename`
     /// returns `Some`, and so it is then consequently subject to similar caveats.
+    /// On MSVC targets, where dbghelp is used by default, this means that the
+    /// `pdb` feature has to be enabled to get columns, which dbghelp doesn't
+    /// report.
     pub fn colno(&self) -> Option<u32> {
-        self.inner.colno()
+        match &self.inner {
+            SymbolImp::Backend(s) => s.colno(),
+            SymbolImp::Image(s) => s.colno(),
+            #[cfg(feature = "std")]
+            SymbolImp::Custom(s) => s.colno,
+        }
     }
 
     /// Returns the line number for where this symbol is currently executing.
@@ -232,7 +720,49 @@ impl Symbol {
     /// This return value is typically `Some` if `filename` returns `Some`, and
     /// is consequently subject to similar caveats.
     pub fn lineno(&self) -> Option<u32> {
-        self.inner.lineno()
+        match &self.inner {
+            SymbolImp::Backend(s) => s.lineno(),
+            SymbolImp::Image(s) => s.lineno(),
+            #[cfg(feature = "std")]
+            SymbolImp::Custom(s) => s.lineno,
+        }
+    }
+
+    /// Returns how deeply this symbol's function is inlined.
+    ///
+    /// When an address resolves to several symbols because of inlining, the
+    /// innermost inlined function is reported first and the function which the
+    /// code physically belongs to last. That last symbol has a depth of 0, a
+    /// function inlined into it has a depth of 1, and so on.
+    ///
+    /// This returns `None` if the depth isn't known, for example for symbols
+    /// from a custom resolver.
+    pub fn inline_depth(&self) -> Option<u32> {
+        match &self.inner {
+            SymbolImp::Backend(s) => s.inline_depth(),
+            SymbolImp::Image(s) => s.inline_depth(),
+            #[cfg(feature = "std")]
+            SymbolImp::Custom(_) => None,
+        }
+    }
+
+    /// Returns whether this symbol is a function inlined into another one,
+    /// rather than the function which the code physically belongs to.
+    ///
+    /// This is `false` when `inline_depth` isn't known.
+    pub fn is_inline(&self) -> bool {
+        self.inline_depth().unwrap_or(0) > 0
+    }
+
+    /// Returns whether this symbol is part of a compiler-generated async state
+    /// machine.
+    ///
+    /// See `SymbolName::is_async_poll` for more information.
+    pub fn is_async_poll(&self) -> bool {
+        match self.name() {
+            Some(name) => name.is_async_poll(),
+            None => false,
+        }
     }
 
     /// Returns the file name where this function was defined.
@@ -249,43 +779,274 @@ impl Symbol {
     #[cfg(feature = "std")]
     #[allow(unreachable_code)]
     pub fn filename(&self) -> Option<&Path> {
-        self.inner.filename()
-    }
-}
-
-impl fmt::Debug for Symbol {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut d = f.debug_struct("Symbol");
-        if let Some(name) = self.name() {
-            d.field("name", &name);
-        }
-        if let Some(addr) = self.addr() {
-            d.field("addr", &addr);
+        match &self.inner {
+            SymbolImp::Backend(s) => s.filename(),
+            SymbolImp::Image(s) => s.filename(),
+            SymbolImp::Custom(s) => s.filename.as_deref(),
         }
+    }
 
-        #[cfg(feature = "std")]
-        {
-            if let Some(filename) = self.filename() {
-                d.field("filename", &filename);
-            }
-        }
+    /// Returns the compilation directory (`DW_AT_comp_dir`) of the DWARF
+    /// unit this symbol is from, which `filename` is the file name of the line
+    /// table joined to.
+    ///
+    /// Tools which remap the paths of a build, or fetch sources from version
+    /// control, need to tell the directory the compiler ran in apart from the
+    /// path of the file within it, see `file_entry`. This is only known for
+    /// symbols found in DWARF by the gimli symbolizer, and only if `filename`
+    /// is known too.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[cfg(feature = "std")]
+    pub fn compilation_dir(&self) -> Option<&Path> {
+        let dir = match &self.inner {
+            SymbolImp::Backend(s) => s.comp_dir(),
+            SymbolImp::Image(s) => s.comp_dir(),
+            SymbolImp::Custom(_) => None,
+        }?;
+        str::from_utf8(dir).ok().map(Path::new)
+    }
 
-        if let Some(lineno) = self.lineno() {
-            d.field("lineno", &lineno);
+    /// Returns the file name of this symbol as stated in the line table of the
+    /// debug information, before it's joined to `compilation_dir`.
+    ///
+    /// This is the directory entry of the line table joined with the file
+    /// entry, relative to `compilation_dir`, such as `src/lib.rs`. Files
+    /// outside of it, like those of the standard library, have absolute paths.
+    /// If `compilation_dir` isn't known this is the same as `filename`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[cfg(feature = "std")]
+    pub fn file_entry(&self) -> Option<&Path> {
+        let filename = self.filename()?;
+        match self.compilation_dir() {
+            Some(dir) => Some(filename.strip_prefix(dir).unwrap_or(filename)),
+            None => Some(filename),
         }
-        d.finish()
     }
-}
 
-cfg_if::cfg_if! {
-    if #[cfg(feature = "cpp_demangle")] {
-        // Maybe a parsed C++ symbol, if parsing the mangled symbol as Rust
-        // failed.
-        struct OptionCppSymbol<'a>(Option<::cpp_demangle::BorrowedSymbol<'a>>);
+    /// Returns the path of the module, that is the executable or shared
+    /// library, containing the address this symbol was resolved for.
+    ///
+    /// This is known for symbols resolved in this process by `resolve`,
+    /// `resolve_frame` and `resolve_many`, on platforms where `modules` lists
+    /// the module, other than Windows. It's `None` for symbols created with
+    /// `Symbol::new` and for those of other processes and offline modules.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[cfg(feature = "std")]
+    pub fn module_path(&self) -> Option<&Path> {
+        self.module().map(|m| &*m.path)
+    }
 
-        impl<'a> OptionCppSymbol<'a> {
-            fn parse(input: &'a [u8]) -> OptionCppSymbol<'a> {
-                OptionCppSymbol(::cpp_demangle::BorrowedSymbol::new(input).ok())
+    /// Returns the base address of the module containing the address this
+    /// symbol was resolved for, which is known under the same conditions as
+    /// `module_path`.
+    ///
+    /// The base is what the addresses stated in the module are relative to,
+    /// so subtracting it from an address gives the address in the module's
+    /// file, as used by its debug information and by tools like `addr2line`.
+    /// This is the load bias, see `LoadedModule::bias`, which for
+    /// position-independent modules is where they start in memory.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[cfg(feature = "std")]
+    pub fn module_base(&self) -> Option<*mut c_void> {
+        self.module().map(|m| m.base as *mut c_void)
+    }
+
+    /// Returns the address where the function containing the code this
+    /// symbol was resolved for starts, as found in the module's symbol table.
+    ///
+    /// For inlined functions this is the start of the function they were
+    /// inlined into, since inlined code has no entry point of its own. Unlike
+    /// `addr`, which for some backends is the address that was looked up,
+    /// this is always the start of a function.
+    ///
+    /// This is known for symbols resolved in this process by `resolve`,
+    /// `resolve_frame` and `resolve_many`, when the module still has its
+    /// symbol table or, on Windows, its debug information. It's `None` for
+    /// symbols created with `Symbol::new` and for those of other processes
+    /// and offline modules. With the `disk-cache` feature it's also `None`
+    /// for symbols found in the on-disk index, unless the module has been
+    /// loaded anyway, as the index doesn't record where functions start.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[cfg(feature = "std")]
+    pub fn function_start(&self) -> Option<*mut c_void> {
+        self.context()?
+            .function_start
+            .map(|start| start as *mut c_void)
+    }
+
+    /// Returns how far into the function starting at `function_start` the
+    /// address this symbol was resolved for is, which is known whenever
+    /// `function_start` is.
+    ///
+    /// The offset is from the address passed to `resolve`, or the instruction
+    /// pointer of the frame passed to `resolve_frame`, before it's adjusted
+    /// to point into the call instruction. That makes it what tools print as
+    /// `name+0x42`, and with the address of the start, what's needed to find
+    /// the instruction in a disassembly.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[cfg(feature = "std")]
+    pub fn offset_in_function(&self) -> Option<usize> {
+        let context = self.context()?;
+        context.addr.checked_sub(context.function_start?)
+    }
+
+    /// Returns the size in bytes of the function starting at
+    /// `function_start`, as recorded in the module's symbol table.
+    ///
+    /// This is known when `function_start` is, except for formats whose
+    /// symbol tables don't record sizes, such as Mach-O and COFF without a
+    /// PDB. Together with `function_start` it gives the bounds of the
+    /// function, for example to attribute profiler samples to it.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    #[cfg(feature = "std")]
+    pub fn function_size(&self) -> Option<usize> {
+        self.context()?.function_size
+    }
+
+    /// Returns the source language of this symbol's function, as recorded in
+    /// the debug information of the compilation unit containing it.
+    ///
+    /// This can be used to pick a demangler for the name, or to tell apart
+    /// frames of different languages in mixed-language programs. It's `None`
+    /// when there's no DWARF debug information for the function, which
+    /// includes symbols only found in a symbol table and those resolved with
+    /// dbghelp on Windows.
+    pub fn language(&self) -> Option<SymbolLanguage> {
+        self.language_code().map(SymbolLanguage::from_dwarf)
+    }
+
+    /// The DWARF `DW_LANG_*` code of `language`.
+    pub(crate) fn language_code(&self) -> Option<u16> {
+        match &self.inner {
+            SymbolImp::Backend(s) => s.language(),
+            SymbolImp::Image(s) => s.language(),
+            #[cfg(feature = "std")]
+            SymbolImp::Custom(_) => None,
+        }
+    }
+}
+
+/// The source language of a symbol's function, see `Symbol::language`.
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SymbolLanguage {
+    /// C, in any version.
+    C,
+    /// C++, in any version.
+    Cpp,
+    /// Objective-C.
+    ObjC,
+    /// Objective-C++.
+    ObjCpp,
+    /// Rust.
+    Rust,
+    /// Swift.
+    Swift,
+    /// Go.
+    Go,
+    /// D.
+    D,
+    /// Fortran, in any version.
+    Fortran,
+    /// Another language, with its `DW_LANG_*` code from the DWARF standard.
+    Other(u16),
+}
+
+impl SymbolLanguage {
+    /// Returns the language with the DWARF `DW_LANG_*` code `code`.
+    pub(crate) fn from_dwarf(code: u16) -> SymbolLanguage {
+        use addr2line::gimli;
+
+        match gimli::DwLang(code) {
+            gimli::DW_LANG_C89
+            | gimli::DW_LANG_C
+            | gimli::DW_LANG_C99
+            | gimli::DW_LANG_C11
+            | gimli::DW_LANG_C17 => SymbolLanguage::C,
+            gimli::DW_LANG_C_plus_plus
+            | gimli::DW_LANG_C_plus_plus_03
+            | gimli::DW_LANG_C_plus_plus_11
+            | gimli::DW_LANG_C_plus_plus_14
+            | gimli::DW_LANG_C_plus_plus_17
+            | gimli::DW_LANG_C_plus_plus_20 => SymbolLanguage::Cpp,
+            gimli::DW_LANG_ObjC => SymbolLanguage::ObjC,
+            gimli::DW_LANG_ObjC_plus_plus => SymbolLanguage::ObjCpp,
+            gimli::DW_LANG_Rust => SymbolLanguage::Rust,
+            gimli::DW_LANG_Swift => SymbolLanguage::Swift,
+            gimli::DW_LANG_Go => SymbolLanguage::Go,
+            gimli::DW_LANG_D => SymbolLanguage::D,
+            gimli::DW_LANG_Fortran77
+            | gimli::DW_LANG_Fortran90
+            | gimli::DW_LANG_Fortran95
+            | gimli::DW_LANG_Fortran03
+            | gimli::DW_LANG_Fortran08
+            | gimli::DW_LANG_Fortran18 => SymbolLanguage::Fortran,
+            _ => SymbolLanguage::Other(code),
+        }
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Symbol");
+        if let Some(name) = self.name() {
+            d.field("name", &name);
+        }
+        if let Some(addr) = self.addr() {
+            d.field("addr", &addr);
+        }
+
+        #[cfg(feature = "std")]
+        {
+            if let Some(filename) = self.filename() {
+                d.field("filename", &filename);
+            }
+        }
+
+        if let Some(lineno) = self.lineno() {
+            d.field("lineno", &lineno);
+        }
+        d.finish()
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "cpp_demangle")] {
+        // Maybe a parsed C++ symbol, if parsing the mangled symbol as Rust
+        // failed.
+        struct OptionCppSymbol<'a>(Option<::cpp_demangle::BorrowedSymbol<'a>>);
+
+        impl<'a> OptionCppSymbol<'a> {
+            fn parse(input: &'a [u8]) -> OptionCppSymbol<'a> {
+                OptionCppSymbol(::cpp_demangle::BorrowedSymbol::new(input).ok())
             }
 
             fn none() -> OptionCppSymbol<'a> {
@@ -319,11 +1080,22 @@ pub struct SymbolName<'a> {
     bytes: &'a [u8],
     demangled: Option<Demangle<'a>>,
     cpp_demangled: OptionCppSymbol<'a>,
+    // Whether demangling was enabled when the name was created, which
+    // registered demanglers only find out about when it's printed.
+    demangle: bool,
 }
 
 impl<'a> SymbolName<'a> {
     /// Creates a new symbol name from the raw underlying bytes.
     pub fn new(bytes: &'a [u8]) -> SymbolName<'a> {
+        if DEMANGLING_DISABLED.load(Ordering::Relaxed) {
+            return SymbolName {
+                bytes,
+                demangled: None,
+                cpp_demangled: OptionCppSymbol::none(),
+                demangle: false,
+            };
+        }
         let str_bytes = str::from_utf8(bytes).ok();
         let demangled = str_bytes.and_then(|s| try_demangle(s).ok());
 
@@ -337,6 +1109,7 @@ impl<'a> SymbolName<'a> {
             bytes: bytes,
             demangled: demangled,
             cpp_demangled: cpp,
+            demangle: true,
         }
     }
 
@@ -354,6 +1127,125 @@ impl<'a> SymbolName<'a> {
     pub fn as_bytes(&self) -> &'a [u8] {
         self.bytes
     }
+
+    /// Returns the symbol name exactly as it was found in the symbol table or
+    /// debug information, if it's valid utf-8.
+    ///
+    /// This is never demangled, whatever the mangling scheme and whether or
+    /// not `set_demangling_enabled` has turned demangling off, so it's what
+    /// should be handed to other demanglers or to symbol servers.
+    pub fn raw(&self) -> Option<&'a str> {
+        str::from_utf8(self.bytes).ok()
+    }
+
+    /// Returns whether this symbol looks like part of the state machine that
+    /// the compiler generates for an `async fn` or `async` block.
+    ///
+    /// Async code is lowered into generator types whose bodies show up in
+    /// backtraces as `{{closure}}`-style symbols nested under the `async fn`,
+    /// alongside `Future::poll` shims that drive them. This is a heuristic
+    /// based on the demangled name, intended for formatters which want to
+    /// present async stacks more meaningfully.
+    pub fn is_async_poll(&self) -> bool {
+        use alloc::string::String;
+        use core::fmt::Write;
+
+        let mut name = String::new();
+        if write!(name, "{:#}", self).is_err() {
+            return false;
+        }
+        is_async_poll_name(&name)
+    }
+}
+
+static DEMANGLING_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Configures whether symbol names are demangled, which they are by default.
+///
+/// With demangling turned off, `SymbolName`'s `Display` and `Debug`
+/// implementations print names as they were found, and so do backtraces,
+/// which is useful when their output is fed to another demangler or to
+/// tools matching mangled names. Filters and formats which look at names,
+/// such as `PrintFmt::Compact` hiding the frames of the standard library,
+/// then see the mangled names too. Only names created afterwards are
+/// affected.
+///
+/// To get the mangled name of a single symbol instead, use `SymbolName::raw`.
+pub fn set_demangling_enabled(enabled: bool) {
+    DEMANGLING_DISABLED.store(!enabled, Ordering::Relaxed);
+}
+
+// Fragments of demangled names which only show up in compiler-generated async
+// state machines. The legacy mangling scheme can't distinguish an async body
+// from a regular closure, so for that case we rely on the `GenFuture` (or
+// newer `async_fn`) wrapper which polls it.
+const ASYNC_POLL_FRAGMENTS: &[&str] = &[
+    "{async_fn_env#",
+    "{async_block_env#",
+    "{async_fn#",
+    "{async_block#",
+    "GenFuture<",
+    "from_generator::",
+];
+
+fn is_async_poll_name(name: &str) -> bool {
+    if ASYNC_POLL_FRAGMENTS.iter().any(|f| name.contains(f)) {
+        return true;
+    }
+    // `<F as core::future::future::Future>::poll` for a closure type `F` is
+    // the poll shim of an async block.
+    name.ends_with("as core::future::future::Future>::poll")
+        && (name.contains("{{closure}}") || name.contains("{closure#"))
+}
+
+impl SymbolName<'_> {
+    // Formats a name which neither rustc-demangle nor cpp_demangle could
+    // demangle, as an MSVC-mangled name or with a demangler registered with
+    // `register_demangler` if one can.
+    fn format_undemangled(
+        &self,
+        fmt: fn(&str, &mut fmt::Formatter<'_>) -> fmt::Result,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        if self.demangle {
+            let name = str::from_utf8(self.bytes)
+                .ok()
+                .and_then(|name| msvc_demangle::demangle(name, f.alternate()));
+            if let Some(name) = name {
+                return fmt(&name, f);
+            }
+        }
+        #[cfg(feature = "std")]
+        {
+            if self.demangle {
+                let name = str::from_utf8(self.bytes)
+                    .ok()
+                    .and_then(demangler::demangle);
+                if let Some(name) = name {
+                    return fmt(&name, f);
+                }
+            }
+        }
+        format_symbol_name(fmt, self.bytes, f)
+    }
+
+    // Formats the name as rustc-demangle demangled it, or else as it was
+    // found, without the MSVC demangler and registered demanglers, which
+    // allocate and take the crate's global lock. Used from signal handlers.
+    pub(crate) fn display_signal_safe(&self) -> SignalSafeSymbolName<'_, '_> {
+        SignalSafeSymbolName(self)
+    }
+}
+
+pub(crate) struct SignalSafeSymbolName<'a, 'b>(&'a SymbolName<'b>);
+
+impl fmt::Display for SignalSafeSymbolName<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.demangled {
+            Some(ref s) => s.fmt(f),
+            None => format_symbol_name(fmt::Display::fmt, self.0.bytes, f),
+        }
+    }
 }
 
 fn format_symbol_name(
@@ -389,7 +1281,7 @@ cfg_if::cfg_if! {
                 } else if let Some(ref cpp) = self.cpp_demangled.0 {
                     cpp.fmt(f)
                 } else {
-                    format_symbol_name(fmt::Display::fmt, self.bytes, f)
+                    self.format_undemangled(fmt::Display::fmt, f)
                 }
             }
         }
@@ -399,7 +1291,7 @@ cfg_if::cfg_if! {
                 if let Some(ref s) = self.demangled {
                     s.fmt(f)
                 } else {
-                    format_symbol_name(fmt::Display::fmt, self.bytes, f)
+                    self.format_undemangled(fmt::Display::fmt, f)
                 }
             }
         }
@@ -426,7 +1318,7 @@ cfg_if::cfg_if! {
                     }
                 }
 
-                format_symbol_name(fmt::Debug::fmt, self.bytes, f)
+                self.format_undemangled(fmt::Debug::fmt, f)
             }
         }
     } else {
@@ -435,7 +1327,7 @@ cfg_if::cfg_if! {
                 if let Some(ref s) = self.demangled {
                     s.fmt(f)
                 } else {
-                    format_symbol_name(fmt::Debug::fmt, self.bytes, f)
+                    self.format_undemangled(fmt::Debug::fmt, f)
                 }
             }
         }
@@ -463,15 +1355,648 @@ pub fn clear_symbol_cache() {
     }
 }
 
+/// Configures how much the symbol cache keeps around.
+///
+/// Parsed debug information is cached for the `modules` most recently used
+/// modules, which is 4 by default. Programs with many shared libraries in
+/// their backtraces may want to raise this, while ones with giant binaries may
+/// want to limit the cache by size with `bytes` instead, which bounds the
+/// approximate memory used by the cached object files and the data loaded
+/// alongside them. The most recently used module is always kept regardless,
+/// and modules over the new limits are evicted right away.
+///
+/// # Caveats
+///
+/// Like `clear_symbol_cache`, this only has an effect with the
+/// `gimli-symbolize` feature of this crate.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn set_symbol_cache_capacity(modules: usize, bytes: Option<usize>) {
+    let _guard = crate::lock::lock();
+    unsafe { imp::set_symbol_cache_capacity(modules, bytes) }
+}
+
+/// Bounds the memory used to symbolize addresses to roughly `limit` bytes, or
+/// removes the bound again with `None`.
+///
+/// Normally the whole object file of a module is mapped into memory and its
+/// parsed debug information is cached for later lookups. With a limit set,
+/// only the parts of ELF object files which are needed for symbolization, like
+/// the symbol tables and debug sections, are read from them. Modules whose
+/// debug information doesn't fit into the limit, even decompressed, aren't
+/// symbolized at all, and everything loaded for a call to `resolve` or
+/// similar functions is dropped again once it returns. This trades speed for
+/// a predictable footprint, as for programs running with tight memory limits.
+///
+/// # Caveats
+///
+/// Like `clear_symbol_cache`, this only has an effect with the
+/// `gimli-symbolize` feature of this crate. Object files other than ELF are
+/// still mapped in full.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn set_symbol_memory_limit(limit: Option<usize>) {
+    let _guard = crate::lock::lock();
+    unsafe { imp::set_symbol_memory_limit(limit) }
+}
+
+/// Configures whether object files are mapped into memory to symbolize
+/// addresses in them, which is the default, or read into memory instead.
+///
+/// Some sandboxes, like certain seccomp profiles or SELinux domains, deny
+/// mapping files into memory, in which case symbolization fails outright.
+/// Reading the files instead works there at the cost of memory, since all of
+/// every object file is read up front rather than just the parts in use.
+/// Files already loaded into the symbol cache aren't affected.
+///
+/// # Caveats
+///
+/// Like `clear_symbol_cache`, this only has an effect with the
+/// `gimli-symbolize` feature of this crate.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn set_object_file_mmap(enabled: bool) {
+    let _guard = crate::lock::lock();
+    unsafe { imp::set_object_file_mmap(enabled) }
+}
+
+/// Sets the directories and symbol stores searched for PDB files, or goes back
+/// to the default for `None`.
+///
+/// The path has the format of dbghelp's symbol search path, a list separated
+/// by semicolons, which can include symbol servers like
+/// `srv*C:\symbols*https://symbols.example.com`. The default is the current
+/// directory followed by the `_NT_SYMBOL_PATH` and `_NT_ALTERNATE_SYMBOL_PATH`
+/// environment variables, and the directory of each module and the path of the
+/// PDB recorded in it are always searched. PDB files are only looked for the
+/// first time an address in a module is symbolized, so this is best called
+/// before that.
+///
+/// # Caveats
+///
+/// This only has an effect when symbolizing with dbghelp, which is the
+/// default for MSVC targets.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn set_symbol_search_path(path: Option<&std::ffi::OsStr>) {
+    let _guard = crate::lock::lock();
+    unsafe { imp::set_symbol_search_path(path) }
+}
+
+/// Configures whether PDB files may be downloaded from symbol servers, which
+/// is allowed by default.
+///
+/// Symbol servers in the symbol search path (see `set_symbol_search_path`)
+/// are used by dbghelp to download PDB files over the network, which can make
+/// symbolization block for a long time. When disabled they are left out of the
+/// search path, along with anything else that looks like a URL.
+///
+/// # Caveats
+///
+/// This only has an effect when symbolizing with dbghelp, which is the
+/// default for MSVC targets.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn set_symbol_server_downloads(enabled: bool) {
+    let _guard = crate::lock::lock();
+    unsafe { imp::set_symbol_server_downloads(enabled) }
+}
+
+/// Configures whether `dbghelp.dll` may be used on Windows, which is allowed
+/// by default.
+///
+/// Some environments, such as AppContainer sandboxes or processes guarded by
+/// anti-cheat software, don't allow loading dbghelp. When it's disabled, or
+/// can't be loaded, stacks are walked using only the unwind data of the loaded
+/// images, and addresses are resolved to the nearest preceding function that
+/// their module exports. No file and line information is available then, but
+/// `modules` still reports the GUID and age of each module's PDB as its
+/// build-id, so that the addresses can be symbolized elsewhere later.
+///
+/// Disabling dbghelp before the first backtrace is captured ensures that it's
+/// never loaded into the process.
+///
+/// # Caveats
+///
+/// This only has an effect on Windows. Without dbghelp, stacks can't be walked
+/// on 32-bit targets, and exported functions are only used by the dbghelp
+/// symbolizer, which is the default for MSVC targets.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn set_dbghelp_enabled(enabled: bool) {
+    #[cfg(all(windows, not(target_vendor = "uwp")))]
+    crate::dbghelp::set_enabled(enabled);
+    #[cfg(not(all(windows, not(target_vendor = "uwp"))))]
+    let _ = enabled;
+}
+
+/// Returns how much the symbol cache is currently holding on to.
+///
+/// See `set_symbol_cache_capacity` for how to limit this.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn symbol_cache_usage() -> SymbolCacheUsage {
+    let _guard = crate::lock::lock();
+    let (modules, bytes) = unsafe { imp::symbol_cache_usage() };
+    SymbolCacheUsage { modules, bytes }
+}
+
+/// Loads the debug information of the given modules into the symbol cache, or
+/// of all loaded modules for `None`, returning how many of them it holds
+/// afterwards.
+///
+/// The first time an address in a module is symbolized, its debug information
+/// has to be found, loaded and parsed, which can take seconds for large
+/// binaries. Calling this ahead of time, like at startup, keeps that delay out
+/// of the first backtrace printed, which is often one printed while handling
+/// an error. Modules are named by their path, as returned by
+/// `LoadedModule::path`, and those which aren't loaded are ignored.
+///
+/// # Caveats
+///
+/// With the `gimli-symbolize` feature, the symbol cache only keeps as many
+/// modules as `set_symbol_cache_capacity` allows, so only that many are loaded,
+/// the ones listed first taking precedence. Nothing is loaded while a limit is
+/// set with `set_symbol_memory_limit`. With dbghelp, which is the default for
+/// MSVC targets, the symbols of each module are loaded and kept until
+/// `cleanup_dbghelp` is called. Other symbolizers do nothing.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn prewarm_symbol_cache(modules: Option<&[&Path]>) -> usize {
+    let _guard = crate::lock::lock();
+    unsafe { imp::prewarm_symbol_cache(modules) }
+}
+
+/// The contents of the symbol cache, as returned by `symbol_cache_usage`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SymbolCacheUsage {
+    modules: usize,
+    bytes: usize,
+}
+
+#[cfg(feature = "std")]
+impl SymbolCacheUsage {
+    /// Returns the number of modules with cached debug information.
+    pub fn modules(&self) -> usize {
+        self.modules
+    }
+
+    /// Returns approximately how many bytes the cached modules take up.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+/// Evicts the module containing `addr` from the symbol cache, returning
+/// whether it was cached.
+///
+/// This frees the memory used for the module's debug information, which is
+/// loaded again if an address in it is symbolized later on.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn evict_symbol_cache(addr: *mut c_void) -> bool {
+    let _guard = crate::lock::lock();
+    unsafe { imp::evict_symbol_cache(addr as usize) }
+}
+
+/// Evicts the module identified by `module` from the symbol cache, returning
+/// whether it was cached.
+///
+/// This is like `evict_symbol_cache`, for modules which may not have any
+/// address to name them by anymore. The debug information of modules which
+/// are unloaded with `dlclose` is dropped from the cache on its own the next
+/// time it's used on platforms where the loader reports that, but not
+/// otherwise.
+///
+/// # Caveats
+///
+/// Like `clear_symbol_cache`, this only has an effect with the
+/// `gimli-symbolize` feature of this crate.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn evict_symbol_cache_module(module: ModuleKey<'_>) -> bool {
+    let _guard = crate::lock::lock();
+    unsafe { imp::evict_symbol_cache_module(module) }
+}
+
+/// A way to name a module, as taken by `evict_symbol_cache_module`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug)]
+pub enum ModuleKey<'a> {
+    /// The path the module was loaded from, as returned by
+    /// `LoadedModule::path`.
+    Path(&'a Path),
+    /// The build-id of the module, as returned by `LoadedModule::build_id`.
+    BuildId(&'a [u8]),
+}
+
+/// Returns the state of the symbol cache for each module which is cached or
+/// has been looked up in it.
+///
+/// Unlike `symbol_cache_usage` this tells which modules the memory is spent
+/// on, and how well the cache works for each of them.
+///
+/// # Caveats
+///
+/// Like `clear_symbol_cache`, this only has an effect with the
+/// `gimli-symbolize` feature of this crate, and returns nothing otherwise.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn symbol_cache_stats() -> Vec<ModuleCacheStats> {
+    let _guard = crate::lock::lock();
+    unsafe { imp::symbol_cache_stats() }
+}
+
+/// Looks up a symbol by name, returning its address and the path of the module
+/// defining it.
+///
+/// This is the reverse of `resolve`, for things like hot-patching or
+/// intercepting functions which need the address of a function they only know
+/// by name. The name is compared both as it is in the symbol table, like
+/// `"malloc"` or a mangled Rust name, and as demangled without the hash, like
+/// `"my_crate::module::function"`. The modules are searched in the order of
+/// `loaded_modules`, and the first match is returned.
+///
+/// # Caveats
+///
+/// Like `clear_symbol_cache`, this only has an effect with the
+/// `gimli-symbolize` feature of this crate, and returns `None` otherwise.
+/// Only the symbol tables of the modules are searched, not their debug
+/// information, so stripped modules and inlined functions won't be found. Each
+/// module searched is loaded into the symbol cache, which can take a while the
+/// first time.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn find_symbol(name: &str) -> Option<(*mut c_void, PathBuf)> {
+    let _guard = crate::lock::lock();
+    unsafe { imp::find_symbol(name) }
+}
+
+/// Writes the symbols of every loaded module to `out` in the format of
+/// `/tmp/perf-<pid>.map` files, returning how many were written.
+///
+/// Each symbol is a line of its address in this process and its size, both in
+/// hex, and its name demangled without the hash, like
+/// `55d0c1a2b3c0 4f my_crate::module::function`. This lets `perf` and
+/// eBPF-based profilers attribute samples in this process to functions without
+/// symbolizing them on their own, which they can't when the modules aren't
+/// readable from where they run, say from outside of a container. Use
+/// `write_perf_map_file` to write them to where `perf` looks for them.
+///
+/// # Caveats
+///
+/// Like `find_symbol`, this only has an effect with the `gimli-symbolize`
+/// feature of this crate, and writes nothing otherwise. Only the symbol tables
+/// of the modules are used, not their debug information, so stripped modules
+/// only have their exported symbols written. The symbol tables of Mach-O and
+/// COFF don't record sizes, so there each symbol is taken to extend up to the
+/// next one. Every module is loaded into the symbol cache, which can take a
+/// while the first time.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn write_perf_map(out: &mut dyn std::io::Write) -> std::io::Result<usize> {
+    let _guard = crate::lock::lock();
+    let mut result = Ok(0);
+    unsafe {
+        imp::for_each_symbol(&mut |addr, size, name| {
+            if result.is_err() {
+                return;
+            }
+            match writeln!(out, "{:x} {:x} {:#}", addr, size, SymbolName::new(name)) {
+                Ok(()) => {
+                    if let Ok(count) = &mut result {
+                        *count += 1;
+                    }
+                }
+                Err(e) => result = Err(e),
+            }
+        });
+    }
+    result
+}
+
+/// Appends the symbols of every loaded module to `/tmp/perf-<pid>.map`, where
+/// `perf` looks for the symbols of code which isn't in any file, returning the
+/// path of the file.
+///
+/// The symbols are written as by `write_perf_map`. The file is appended to
+/// rather than replaced so that a JIT compiler in this process can share it,
+/// which means that calling this again, say after loading more libraries,
+/// writes the symbols of the modules already there once more. `perf` doesn't
+/// mind duplicates.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(all(feature = "std", unix))]
+pub fn write_perf_map_file() -> std::io::Result<PathBuf> {
+    use std::io::Write;
+
+    let path = PathBuf::from(format!("/tmp/perf-{}.map", std::process::id()));
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    let mut out = std::io::BufWriter::new(file);
+    write_perf_map(&mut out)?;
+    out.flush()?;
+    Ok(path)
+}
+
+/// The state of the symbol cache for one module, as returned by
+/// `symbol_cache_stats`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct ModuleCacheStats {
+    path: PathBuf,
+    build_id: Option<Vec<u8>>,
+    bytes: usize,
+    hits: u64,
+    misses: u64,
+}
+
+#[cfg(feature = "std")]
+impl ModuleCacheStats {
+    /// Returns the path the module was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the build-id of the module, if known.
+    pub fn build_id(&self) -> Option<&[u8]> {
+        self.build_id.as_deref()
+    }
+
+    /// Returns approximately how many bytes the module's cached debug
+    /// information takes up, which is 0 if it's not cached right now.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Returns how many times an address in the module was looked up with its
+    /// debug information already in the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Returns how many times the module's debug information had to be loaded
+    /// into the cache.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+}
+
+/// The object file of a module registered with `register_module`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub enum ModuleSource {
+    /// The object file at this path.
+    Path(std::path::PathBuf),
+    /// The contents of an object file, e.g. as read by a plugin loader which
+    /// maps the image itself.
+    Bytes(std::sync::Arc<[u8]>),
+    /// The contents of an object file which stay around for the rest of the
+    /// program, e.g. an image embedded in a packed executable.
+    Static(&'static [u8]),
+}
+
+#[cfg(feature = "std")]
+impl From<std::vec::Vec<u8>> for ModuleSource {
+    fn from(data: std::vec::Vec<u8>) -> ModuleSource {
+        ModuleSource::Bytes(data.into())
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<&'static [u8]> for ModuleSource {
+    fn from(data: &'static [u8]) -> ModuleSource {
+        ModuleSource::Static(data)
+    }
+}
+
+/// Registers an image which was loaded into this process without going
+/// through the system's dynamic loader, so that addresses inside of it can be
+/// symbolized.
+///
+/// Images mapped manually, for example by a plugin system, aren't reported
+/// by functions like `dl_iterate_phdr` and addresses in them would otherwise
+/// resolve to nothing. After this call addresses in `base_addr..base_addr +
+/// len` are looked up in the object file given by `source`.
+///
+/// The `base_addr` is the load bias of the image, that is the address which
+/// its virtual address 0 was mapped to. For position-independent images this
+/// is the address the image starts at.
+///
+/// Registered images take precedence over those reported by the loader. So
+/// for images whose object file on disk isn't what's actually mapped, as for
+/// self-extracting or packed executables, the real image can be registered
+/// for the same addresses to symbolize against that instead.
+///
+/// # Caveats
+///
+/// Like `clear_symbol_cache`, this only has an effect with the
+/// `gimli-symbolize` feature of this crate.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn register_module(base_addr: *mut c_void, len: usize, source: ModuleSource) {
+    let _guard = crate::lock::lock();
+    unsafe { imp::register_module(base_addr as usize, len, source) }
+}
+
+/// Removes a registration made with `register_module` for the image with the
+/// given `base_addr`, returning whether there was one.
+///
+/// This should be called before the image is unmapped, since a later image
+/// mapped at the same address would otherwise be symbolized incorrectly.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[cfg(feature = "std")]
+pub fn unregister_module(base_addr: *mut c_void) -> bool {
+    let _guard = crate::lock::lock();
+    unsafe { imp::unregister_module(base_addr as usize) }
+}
+
+pub mod image;
+
+#[cfg(feature = "std")]
+pub mod offline;
+
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+pub mod remote;
+
+#[cfg(all(feature = "coresymbolication", target_os = "macos"))]
+mod coresymbolication;
+#[cfg(feature = "std")]
+mod resolver;
+#[cfg(feature = "std")]
+pub use self::resolver::register_resolver;
+
+#[cfg(feature = "std")]
+mod fixture;
+#[cfg(feature = "std")]
+pub(crate) use self::fixture::frames as fixture_frames;
+#[cfg(feature = "std")]
+pub use self::fixture::{install_fixture, Fixture, FixtureGuard};
+
+#[cfg(feature = "std")]
+mod demangler;
+#[cfg(feature = "std")]
+pub use self::demangler::{clear_demanglers, register_demangler};
+
+mod msvc_demangle;
+
+#[cfg(feature = "std")]
+mod diagnostics;
+#[cfg(feature = "std")]
+pub use self::diagnostics::{
+    set_diagnostics_enabled, take_diagnostics, Diagnostic, DiagnosticKind,
+};
+
+#[cfg(feature = "std")]
+mod source;
+#[cfg(feature = "std")]
+pub use self::source::{set_object_source, FileSystemSource, ObjectData, ObjectSource};
+
+/// The loaded module containing an address, see `with_module`.
+#[cfg(feature = "std")]
+pub(crate) struct ModuleRef<'a> {
+    /// The path the module was loaded from.
+    pub(crate) name: &'a std::ffi::OsStr,
+    /// The build-id or UUID of the module, if known.
+    pub(crate) build_id: Option<&'a [u8]>,
+    /// The address relative to the module, as used by its debug information.
+    pub(crate) offset: usize,
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn loaded_modules() -> std::vec::Vec<crate::LoadedModule> {
+    imp::loaded_modules()
+}
+
+#[cfg(all(feature = "std", any(target_os = "linux", target_os = "android")))]
+pub(crate) fn process_modules(pid: u32) -> std::vec::Vec<crate::LoadedModule> {
+    imp::process_modules(pid)
+}
+
+// Calls `cb` with the module containing `addr`, if the backend knows it.
+//
+// unsafe because this is required to be externally synchronized
+#[cfg(feature = "std")]
+pub(crate) unsafe fn with_module(addr: *mut c_void, cb: &mut dyn FnMut(&ModuleRef<'_>)) {
+    imp::with_module(addr, cb)
+}
+
+// unsafe because this is required to be externally synchronized
+#[cfg(all(feature = "std", unix))]
+pub(crate) unsafe fn prepare_signal_safe() -> bool {
+    imp::prepare_signal_safe()
+}
+
+// unsafe because this must only be called after `prepare_signal_safe` has
+// returned, and never concurrently with it.
+#[cfg(all(feature = "std", unix))]
+pub(crate) unsafe fn resolve_signal_safe(addr: *mut c_void, cb: &mut dyn FnMut(&Symbol)) {
+    imp::resolve_signal_safe(ResolveWhat::Address(addr), cb)
+}
+
 cfg_if::cfg_if! {
     if #[cfg(miri)] {
         mod miri;
         use miri as imp;
-    } else if #[cfg(all(windows, target_env = "msvc", not(target_vendor = "uwp")))] {
+    } else if #[cfg(all(feature = "js", target_arch = "wasm32", target_os = "unknown"))] {
+        mod js;
+        use js as imp;
+    } else if #[cfg(all(
+        windows,
+        target_env = "msvc",
+        not(target_vendor = "uwp"),
+        not(feature = "pdb"),
+    ))] {
         mod dbghelp;
         use dbghelp as imp;
     } else if #[cfg(all(
-        any(unix, windows),
+        any(unix, windows, target_os = "hermit"),
         not(target_vendor = "uwp"),
         not(target_os = "emscripten"),
         any(not(backtrace_in_libstd), feature = "backtrace"),
@@ -483,3 +2008,23 @@ cfg_if::cfg_if! {
         use noop as imp;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn async_poll_names() {
+        assert!(is_async_poll_name(
+            "<core::future::from_generator::GenFuture<T> as core::future::future::Future>::poll"
+        ));
+        assert!(is_async_poll_name("app::main::{async_fn#0}"));
+        assert!(is_async_poll_name(
+            "<app::run::{closure#0} as core::future::future::Future>::poll"
+        ));
+        assert!(!is_async_poll_name("app::main::{{closure}}"));
+        assert!(!is_async_poll_name(
+            "<app::Timer as core::future::future::Future>::poll"
+        ));
+    }
+}
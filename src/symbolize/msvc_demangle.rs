@@ -0,0 +1,740 @@
+//! A demangler for names mangled by the MSVC toolchain, printing them the way
+//! `undname` does.
+//!
+//! dbghelp demangles these names itself, but the other ways of symbolizing
+//! PE images, the gimli symbolizer and the fallback to their export tables,
+//! only find the mangled names. This covers the functions, variables and
+//! virtual function tables which show up in backtraces. Names using the parts
+//! of the scheme which aren't supported, such as member function pointers and
+//! function-local names, are left as they are, and `__ptr64` annotations are
+//! left out.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::str;
+
+/// Demangles `name`, returning `None` if it isn't a name mangled by MSVC or
+/// uses parts of the scheme which aren't supported.
+///
+/// With `name_only`, only the qualified name is returned, like
+/// `backtrace::resolve`'s names are printed with `{:#}`.
+pub(super) fn demangle(name: &str, name_only: bool) -> Option<String> {
+    if !name.starts_with('?') {
+        return None;
+    }
+    let mut parser = Parser {
+        rest: name.as_bytes(),
+        names: Vec::new(),
+        types: Vec::new(),
+    };
+    let symbol = parser.symbol()?;
+    if !parser.rest.is_empty() {
+        return None;
+    }
+    Some(if name_only { symbol.name } else { symbol.full })
+}
+
+struct Symbol {
+    name: String,
+    full: String,
+}
+
+struct Parser<'a> {
+    rest: &'a [u8],
+    // The names and function parameter types which can be referred back to
+    // by index, up to 10 each.
+    names: Vec<String>,
+    types: Vec<String>,
+}
+
+/// A name as it's mangled, innermost part first.
+struct Name {
+    parts: Vec<String>,
+    special: Special,
+}
+
+#[derive(PartialEq)]
+enum Special {
+    None,
+    Constructor,
+    Destructor,
+    Conversion,
+}
+
+enum Type {
+    /// A type without a declarator, like `int` or `class Foo const`.
+    Plain(String),
+    Pointer {
+        pointee: Box<Type>,
+        /// `*`, `&` or `&&`, with the qualifiers of the pointer itself.
+        op: String,
+    },
+    Function {
+        cc: &'static str,
+        ret: Box<Type>,
+        params: String,
+    },
+}
+
+impl Type {
+    /// Renders the type of a declaration of `decl`, which may be empty.
+    fn render(&self, decl: &str) -> String {
+        match self {
+            Type::Plain(s) if decl.is_empty() => s.clone(),
+            Type::Plain(s) => format!("{} {}", s, decl),
+            Type::Pointer { pointee, op } => {
+                let decl = if decl.is_empty() {
+                    op.clone()
+                } else {
+                    format!("{} {}", op, decl)
+                };
+                match &**pointee {
+                    Type::Function { cc, .. } => pointee.render(&format!("({}{})", cc, decl)),
+                    _ => pointee.render(&decl),
+                }
+            }
+            Type::Function { cc, ret, params } => {
+                let decl = if decl.is_empty() { cc } else { decl };
+                format!("{} {}({})", ret.render(""), decl, params)
+            }
+        }
+    }
+
+    /// Applies the qualifiers `cv`, which may be empty, to the type.
+    fn qualify(self, cv: &str) -> Type {
+        if cv.is_empty() {
+            return self;
+        }
+        match self {
+            Type::Plain(s) => Type::Plain(format!("{} {}", s, cv)),
+            Type::Pointer { pointee, op } => Type::Pointer {
+                pointee,
+                op: format!("{} {}", op, cv),
+            },
+            function => function,
+        }
+    }
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.rest.first().cloned()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let c = self.peek()?;
+        self.rest = &self.rest[1..];
+        Some(c)
+    }
+
+    fn eat(&mut self, prefix: &[u8]) -> bool {
+        if self.rest.starts_with(prefix) {
+            self.rest = &self.rest[prefix.len()..];
+            true
+        } else {
+            false
+        }
+    }
+
+    fn symbol(&mut self) -> Option<Symbol> {
+        if !self.eat(b"?") {
+            return None;
+        }
+        if self.eat(b"?_C@_") {
+            // String literals are named after a hash of their contents.
+            self.rest = &[];
+            return Some(Symbol {
+                name: "`string'".into(),
+                full: "`string'".into(),
+            });
+        }
+        let name = self.qualified_name()?;
+        match self.next()? {
+            c @ b'0'..=b'4' => self.variable(c, &name),
+            b'6' | b'7' => self.table(&name),
+            c => self.function(c, name),
+        }
+    }
+
+    fn variable(&mut self, kind: u8, name: &Name) -> Option<Symbol> {
+        let access = match kind {
+            b'0' => "private: static ",
+            b'1' => "protected: static ",
+            b'2' => "public: static ",
+            _ => "",
+        };
+        let ty = self.ty()?;
+        self.pointer_modifiers();
+        let cv = self.cv()?;
+        let name = name.render();
+        let full = format!("{}{}", access, ty.qualify(cv).render(&name));
+        Some(Symbol { name, full })
+    }
+
+    fn table(&mut self, name: &Name) -> Option<Symbol> {
+        let cv = self.cv()?;
+        let name = name.render();
+        let mut full = format!("{} {}", cv, name);
+        if !self.eat(b"@") {
+            let base = self.qualified_name()?.render();
+            if !self.eat(b"@") {
+                return None;
+            }
+            full = format!("{}{{for `{}'}}", full, base);
+        }
+        Some(Symbol { name, full })
+    }
+
+    fn function(&mut self, kind: u8, mut name: Name) -> Option<Symbol> {
+        let (access, member) = match kind {
+            b'A' | b'B' => ("private: ", true),
+            b'C' | b'D' => ("private: static ", false),
+            b'E' | b'F' => ("private: virtual ", true),
+            b'I' | b'J' => ("protected: ", true),
+            b'K' | b'L' => ("protected: static ", false),
+            b'M' | b'N' => ("protected: virtual ", true),
+            b'Q' | b'R' => ("public: ", true),
+            b'S' | b'T' => ("public: static ", false),
+            b'U' | b'V' => ("public: virtual ", true),
+            b'Y' | b'Z' => ("", false),
+            _ => return None,
+        };
+        let mut this_cv = "";
+        if member {
+            self.pointer_modifiers();
+            this_cv = self.cv()?;
+        }
+        let cc = self.calling_convention()?;
+        let ret = if self.eat(b"@") {
+            None
+        } else if self.eat(b"?") {
+            let cv = self.cv()?;
+            Some(self.ty()?.qualify(cv))
+        } else {
+            Some(self.ty()?)
+        };
+        let params = self.params()?;
+        if !self.eat(b"Z") {
+            return None;
+        }
+
+        let mut ret = ret.map(|ret| ret.render(""));
+        if name.special == Special::Conversion {
+            let ty = ret.take()?;
+            name.parts[0] = format!("operator {}", ty);
+        }
+        let name = name.render();
+        let mut full = String::from(access);
+        if let Some(ret) = ret {
+            full.push_str(&ret);
+            full.push(' ');
+        }
+        full.push_str(&format!("{} {}({})", cc, name, params));
+        if !this_cv.is_empty() {
+            full.push(' ');
+            full.push_str(this_cv);
+        }
+        Some(Symbol { name, full })
+    }
+
+    fn qualified_name(&mut self) -> Option<Name> {
+        let (first, special) = self.unqualified_name()?;
+        let mut parts = vec![first];
+        while !self.eat(b"@") {
+            let part = self.name_fragment()?;
+            parts.push(part);
+        }
+        match special {
+            Special::Constructor => parts[0] = parts.get(1)?.clone(),
+            Special::Destructor => parts[0] = format!("~{}", parts.get(1)?),
+            _ => {}
+        }
+        Some(Name { parts, special })
+    }
+
+    // The innermost part of a name, which can also be an operator.
+    fn unqualified_name(&mut self) -> Option<(String, Special)> {
+        if self.rest.starts_with(b"?$") || !self.rest.starts_with(b"?") {
+            return Some((self.name_fragment()?, Special::None));
+        }
+        self.next();
+        let special = match self.peek()? {
+            b'0' => Special::Constructor,
+            b'1' => Special::Destructor,
+            b'B' => Special::Conversion,
+            _ => Special::None,
+        };
+        if special != Special::None {
+            self.next();
+            return Some((String::new(), special));
+        }
+        Some((self.operator()?.into(), Special::None))
+    }
+
+    // A part of a name other than an operator.
+    fn name_fragment(&mut self) -> Option<String> {
+        match self.peek()? {
+            c @ b'0'..=b'9' => {
+                self.next();
+                self.names.get(usize::from(c - b'0')).cloned()
+            }
+            b'?' => {
+                self.next();
+                if self.eat(b"$") {
+                    let name = self.template()?;
+                    self.memorize_name(&name);
+                    Some(name)
+                } else if self.eat(b"A") {
+                    self.identifier()?;
+                    Some("`anonymous namespace'".into())
+                } else {
+                    None
+                }
+            }
+            _ => {
+                let name = self.identifier()?;
+                self.memorize_name(&name);
+                Some(name)
+            }
+        }
+    }
+
+    fn identifier(&mut self) -> Option<String> {
+        let len = self.rest.iter().position(|c| *c == b'@')?;
+        let name = str::from_utf8(&self.rest[..len]).ok()?;
+        self.rest = &self.rest[len + 1..];
+        if name.is_empty() {
+            None
+        } else {
+            Some(name.into())
+        }
+    }
+
+    fn memorize_name(&mut self, name: &str) {
+        if self.names.len() < 10 && !self.names.iter().any(|n| n == name) {
+            self.names.push(name.into());
+        }
+    }
+
+    fn template(&mut self) -> Option<String> {
+        let name = if self.rest.starts_with(b"?") {
+            self.next();
+            self.operator()?.into()
+        } else {
+            self.identifier()?
+        };
+        // Template arguments refer back to names and types of their own.
+        let names = core::mem::take(&mut self.names);
+        let types = core::mem::take(&mut self.types);
+        let args = self.template_args();
+        self.names = names;
+        self.types = types;
+        let args = args?;
+        let space = if args.ends_with('>') { " " } else { "" };
+        Some(format!("{}<{}{}>", name, args, space))
+    }
+
+    fn template_args(&mut self) -> Option<String> {
+        let mut args = Vec::new();
+        while !self.eat(b"@") {
+            if self.eat(b"$0") {
+                args.push(format!("{}", self.number()?));
+            } else if self.rest.starts_with(b"$1?") {
+                self.rest = &self.rest[2..];
+                let names = core::mem::take(&mut self.names);
+                let symbol = self.symbol();
+                self.names = names;
+                args.push(format!("&{}", symbol?.name));
+            } else if self.eat(b"$$V") || self.eat(b"$$Z") {
+                // Empty parameter packs and the ends of packs.
+            } else if self.eat(b"$$C") {
+                let cv = self.cv()?;
+                args.push(self.ty()?.qualify(cv).render(""));
+            } else {
+                args.push(self.ty()?.render(""));
+            }
+        }
+        Some(args.join(","))
+    }
+
+    fn operator(&mut self) -> Option<&'static str> {
+        let op = match self.next()? {
+            b'2' => "operator new",
+            b'3' => "operator delete",
+            b'4' => "operator=",
+            b'5' => "operator>>",
+            b'6' => "operator<<",
+            b'7' => "operator!",
+            b'8' => "operator==",
+            b'9' => "operator!=",
+            b'A' => "operator[]",
+            b'C' => "operator->",
+            b'D' => "operator*",
+            b'E' => "operator++",
+            b'F' => "operator--",
+            b'G' => "operator-",
+            b'H' => "operator+",
+            b'I' => "operator&",
+            b'J' => "operator->*",
+            b'K' => "operator/",
+            b'L' => "operator%",
+            b'M' => "operator<",
+            b'N' => "operator<=",
+            b'O' => "operator>",
+            b'P' => "operator>=",
+            b'Q' => "operator,",
+            b'R' => "operator()",
+            b'S' => "operator~",
+            b'T' => "operator^",
+            b'U' => "operator|",
+            b'V' => "operator&&",
+            b'W' => "operator||",
+            b'X' => "operator*=",
+            b'Y' => "operator+=",
+            b'Z' => "operator-=",
+            b'_' => match self.next()? {
+                b'0' => "operator/=",
+                b'1' => "operator%=",
+                b'2' => "operator>>=",
+                b'3' => "operator<<=",
+                b'4' => "operator&=",
+                b'5' => "operator|=",
+                b'6' => "operator^=",
+                b'7' => "`vftable'",
+                b'8' => "`vbtable'",
+                b'9' => "`vcall'",
+                b'A' => "`typeof'",
+                b'B' => "`local static guard'",
+                b'D' => "`vbase destructor'",
+                b'E' => "`vector deleting destructor'",
+                b'F' => "`default constructor closure'",
+                b'G' => "`scalar deleting destructor'",
+                b'H' => "`vector constructor iterator'",
+                b'I' => "`vector destructor iterator'",
+                b'J' => "`vector vbase constructor iterator'",
+                b'K' => "`virtual displacement map'",
+                b'L' => "`eh vector constructor iterator'",
+                b'M' => "`eh vector destructor iterator'",
+                b'N' => "`eh vector vbase constructor iterator'",
+                b'O' => "`copy constructor closure'",
+                b'S' => "`local vftable'",
+                b'T' => "`local vftable constructor closure'",
+                b'U' => "operator new[]",
+                b'V' => "operator delete[]",
+                b'_' => match self.next()? {
+                    b'L' => "operator co_await",
+                    b'M' => "operator<=>",
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        let negative = self.eat(b"?");
+        let value = match self.next()? {
+            c @ b'0'..=b'9' => i64::from(c - b'0') + 1,
+            c @ b'A'..=b'P' => {
+                let mut value = i64::from(c - b'A');
+                loop {
+                    match self.next()? {
+                        b'@' => break,
+                        c @ b'A'..=b'P' => {
+                            value = value.checked_mul(16)? + i64::from(c - b'A');
+                        }
+                        _ => return None,
+                    }
+                }
+                value
+            }
+            b'@' => 0,
+            _ => return None,
+        };
+        Some(if negative { -value } else { value })
+    }
+
+    fn params(&mut self) -> Option<String> {
+        if self.eat(b"X") {
+            return Some("void".into());
+        }
+        let mut params = Vec::new();
+        loop {
+            if self.eat(b"@") {
+                break;
+            }
+            if self.eat(b"Z") {
+                params.push(String::from("..."));
+                break;
+            }
+            match self.peek()? {
+                c @ b'0'..=b'9' => {
+                    self.next();
+                    params.push(self.types.get(usize::from(c - b'0'))?.clone());
+                }
+                _ => {
+                    let len = self.rest.len();
+                    let param = self.ty()?.render("");
+                    // Only types taking more than a character to mangle are
+                    // worth referring back to.
+                    if len - self.rest.len() > 1 && self.types.len() < 10 {
+                        self.types.push(param.clone());
+                    }
+                    params.push(param);
+                }
+            }
+        }
+        Some(params.join(","))
+    }
+
+    fn ty(&mut self) -> Option<Type> {
+        let plain = match self.next()? {
+            b'C' => "signed char",
+            b'D' => "char",
+            b'E' => "unsigned char",
+            b'F' => "short",
+            b'G' => "unsigned short",
+            b'H' => "int",
+            b'I' => "unsigned int",
+            b'J' => "long",
+            b'K' => "unsigned long",
+            b'M' => "float",
+            b'N' => "double",
+            b'O' => "long double",
+            b'X' => "void",
+            b'_' => match self.next()? {
+                b'J' => "__int64",
+                b'K' => "unsigned __int64",
+                b'L' => "__int128",
+                b'M' => "unsigned __int128",
+                b'N' => "bool",
+                b'Q' => "char8_t",
+                b'S' => "char16_t",
+                b'U' => "char32_t",
+                b'W' => "wchar_t",
+                _ => return None,
+            },
+            c @ b'T' | c @ b'U' | c @ b'V' => {
+                let keyword = match c {
+                    b'T' => "union",
+                    b'U' => "struct",
+                    _ => "class",
+                };
+                let name = self.qualified_name()?.render();
+                return Some(Type::Plain(format!("{} {}", keyword, name)));
+            }
+            b'W' => {
+                if !self.eat(b"4") {
+                    return None;
+                }
+                let name = self.qualified_name()?.render();
+                return Some(Type::Plain(format!("enum {}", name)));
+            }
+            c @ b'P' | c @ b'Q' | c @ b'R' | c @ b'S' => {
+                let op = match c {
+                    b'P' => "*",
+                    b'Q' => "* const",
+                    b'R' => "* volatile",
+                    _ => "* const volatile",
+                };
+                return self.pointer(op);
+            }
+            b'A' => return self.pointer("&"),
+            b'B' => return self.pointer("& volatile"),
+            b'?' => {
+                let cv = self.cv()?;
+                return Some(self.ty()?.qualify(cv));
+            }
+            b'$' => {
+                if self.eat(b"$Q") {
+                    return self.pointer("&&");
+                } else if self.eat(b"$R") {
+                    return self.pointer("&& volatile");
+                } else if self.eat(b"$T") {
+                    "std::nullptr_t"
+                } else {
+                    return None;
+                }
+            }
+            _ => return None,
+        };
+        Some(Type::Plain(plain.into()))
+    }
+
+    fn pointer(&mut self, op: &str) -> Option<Type> {
+        self.pointer_modifiers();
+        let pointee = if self.eat(b"6") {
+            let cc = self.calling_convention()?;
+            let ret = self.ty()?;
+            let params = self.params()?;
+            if !self.eat(b"Z") {
+                return None;
+            }
+            Type::Function {
+                cc,
+                ret: Box::new(ret),
+                params,
+            }
+        } else {
+            let cv = self.cv()?;
+            self.ty()?.qualify(cv)
+        };
+        Some(Type::Pointer {
+            pointee: Box::new(pointee),
+            op: op.into(),
+        })
+    }
+
+    // Skips `__ptr64`, `__unaligned` and `__restrict`.
+    fn pointer_modifiers(&mut self) {
+        while self.eat(b"E") || self.eat(b"F") || self.eat(b"I") {}
+    }
+
+    fn cv(&mut self) -> Option<&'static str> {
+        match self.next()? {
+            b'A' => Some(""),
+            b'B' => Some("const"),
+            b'C' => Some("volatile"),
+            b'D' => Some("const volatile"),
+            _ => None,
+        }
+    }
+
+    fn calling_convention(&mut self) -> Option<&'static str> {
+        match self.next()? {
+            b'A' | b'B' => Some("__cdecl"),
+            b'C' | b'D' => Some("__pascal"),
+            b'E' | b'F' => Some("__thiscall"),
+            b'G' | b'H' => Some("__stdcall"),
+            b'I' | b'J' => Some("__fastcall"),
+            b'M' | b'N' => Some("__clrcall"),
+            b'Q' => Some("__vectorcall"),
+            _ => None,
+        }
+    }
+}
+
+impl Name {
+    fn render(&self) -> String {
+        let parts: Vec<&str> = self.parts.iter().rev().map(|p| &**p).collect();
+        parts.join("::")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::demangle;
+    use alloc::string::String;
+
+    fn full(name: &str) -> String {
+        demangle(name, false).unwrap()
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(full("?foo@@YAXXZ"), "void __cdecl foo(void)");
+        assert_eq!(demangle("?foo@@YAXXZ", true).unwrap(), "foo");
+        assert_eq!(
+            full("?bar@Foo@@QEAAHH@Z"),
+            "public: int __cdecl Foo::bar(int)"
+        );
+        assert_eq!(
+            full("?get@Foo@@QEBAHXZ"),
+            "public: int __cdecl Foo::get(void) const"
+        );
+        assert_eq!(
+            full("?f@@YAXPEBDAEAVBar@@@Z"),
+            "void __cdecl f(char const *,class Bar &)"
+        );
+        assert_eq!(
+            full("?printf@@YAHPEBDZZ"),
+            "int __cdecl printf(char const *,...)"
+        );
+        assert_eq!(
+            full("?g@@YAXP6AXH@Z@Z"),
+            "void __cdecl g(void (__cdecl*)(int))"
+        );
+        assert_eq!(
+            full("?h@ns@@YGXW4Color@1@@Z"),
+            "void __stdcall ns::h(enum ns::Color)"
+        );
+    }
+
+    #[test]
+    fn special_members() {
+        assert_eq!(full("??0Foo@@QEAA@XZ"), "public: __cdecl Foo::Foo(void)");
+        assert_eq!(
+            full("??1Foo@@UEAA@XZ"),
+            "public: virtual __cdecl Foo::~Foo(void)"
+        );
+        assert_eq!(
+            full("??4Foo@@QEAAAEAV0@AEBV0@@Z"),
+            "public: class Foo & __cdecl Foo::operator=(class Foo const &)"
+        );
+        assert_eq!(
+            full("??BFoo@@QEBAHXZ"),
+            "public: __cdecl Foo::operator int(void) const"
+        );
+        assert_eq!(full("??_7Foo@@6B@"), "const Foo::`vftable'");
+        assert_eq!(
+            full("??_7Derived@@6BBase@@@"),
+            "const Derived::`vftable'{for `Base'}"
+        );
+    }
+
+    #[test]
+    fn variables() {
+        assert_eq!(full("?x@ns@@3HA"), "int ns::x");
+        assert_eq!(
+            full("?count@Foo@@2HB"),
+            "public: static int const Foo::count"
+        );
+        assert_eq!(full("?p@@3PEBDEB"), "char const * const p");
+        assert_eq!(
+            demangle("??_C@_05ABCDEFGH@hello?$AA@", true).unwrap(),
+            "`string'"
+        );
+    }
+
+    #[test]
+    fn back_references() {
+        assert_eq!(
+            full("?f@@YAXPEAVBar@@0@Z"),
+            "void __cdecl f(class Bar *,class Bar *)"
+        );
+        assert_eq!(
+            full("?f@Bar@@SAXPEAV1@@Z"),
+            "public: static void __cdecl Bar::f(class Bar *)"
+        );
+    }
+
+    #[test]
+    fn templates() {
+        assert_eq!(
+            full("??$max@H@std@@YAAEBHAEBH0@Z"),
+            "int const & __cdecl std::max<int>(int const &,int const &)"
+        );
+        assert_eq!(
+            full("?f@@YAXV?$vector@HV?$allocator@H@std@@@std@@@Z"),
+            "void __cdecl f(class std::vector<int,class std::allocator<int> >)"
+        );
+        assert_eq!(
+            demangle("?get@?$Array@H$0BA@@@QEAAHXZ", true).unwrap(),
+            "Array<int,16>::get"
+        );
+    }
+
+    #[test]
+    fn not_msvc() {
+        assert_eq!(demangle("_ZN3foo3barEv", false), None);
+        assert_eq!(demangle("main", false), None);
+        assert_eq!(demangle("?", false), None);
+        assert_eq!(demangle("?foo@@YAXXZtrailing", false), None);
+        assert_eq!(demangle("?foo@@YAX", false), None);
+    }
+}
@@ -0,0 +1,355 @@
+//! Symbolization of addresses from other processes.
+//!
+//! The `resolve` function of this crate only works for addresses in the
+//! current process, since it looks up the libraries loaded into it. This
+//! module instead symbolizes addresses using an explicit list of modules,
+//! which makes it possible to capture raw backtraces in production (for
+//! example with `Backtrace::to_raw_bytes`) and symbolize them later on a
+//! machine which has the same binaries and their debug information.
+//!
+//! ```no_run
+//! use backtrace::offline::{Module, Symbolizer};
+//!
+//! let mut symbolizer = Symbolizer::new();
+//! symbolizer.add_module(Module::from_path("/usr/bin/app", 0x5555_5555_4000));
+//! symbolizer.resolve(0x5555_5555_9a2b, &mut |symbol| {
+//!     println!("{:?}", symbol);
+//! });
+//! ```
+//!
+//! Object files are parsed with the same machinery as used for the current
+//! process, so only the object file format of the host platform is
+//! supported (ELF on Linux, Mach-O on macOS, PE on Windows with the gimli
+//! backend). On platforms where this crate doesn't use `gimli` nothing is
+//! ever resolved.
+//!
+//! With the `pdb` feature of this crate, a module can also be the PDB file of
+//! a Windows executable or DLL, on any platform. Its base is then the address
+//! the image was loaded at.
+//!
+//! A module can also be a WebAssembly module, on any platform, in which case
+//! addresses are offsets into the module file (as reported by engines and
+//! browsers for wasm frames) plus its base. Functions are named from the
+//! `name` section, and DWARF in custom sections is used if it's there.
+//!
+//! Minidumps describe modules by where their image starts and how large it
+//! is rather than by load bias, which `Module::from_image` and
+//! `MinidumpModule` take care of, so that `resolve_minidump` can symbolize the
+//! frames of a minidump directly.
+//!
+//! # Required features
+//!
+//! This module requires the `std` feature of the `backtrace` crate to be
+//! enabled, and the `std` feature is enabled by default.
+
+use super::imp::OfflineMapping;
+use super::Symbol;
+use crate::RawModule;
+use core::ffi::c_void;
+use core::mem;
+use std::path::{Path, PathBuf};
+use std::prelude::v1::*;
+
+/// A module of the process whose addresses are being symbolized.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug)]
+pub struct Module {
+    source: Source,
+    base: usize,
+    /// Set if `base` is where the image starts rather than its load bias,
+    /// to the number of bytes the image covers.
+    image_len: Option<usize>,
+    build_id: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Debug)]
+enum Source {
+    Path(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+impl Module {
+    /// Creates a module from the object file at `path`, which was loaded with
+    /// a load bias of `base` in the process the addresses came from.
+    ///
+    /// Separate debug information for the object file is located the same way
+    /// as for the current process, e.g. through `.gnu_debuglink` on Linux or
+    /// a `.dSYM` bundle next to it on macOS.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn from_path<P: Into<PathBuf>>(path: P, base: usize) -> Module {
+        Module {
+            source: Source::Path(path.into()),
+            base,
+            image_len: None,
+            build_id: None,
+        }
+    }
+
+    /// Creates a module from the object file at `path`, whose image started
+    /// at `start` and covered `len` bytes in the process the addresses came
+    /// from, as modules are described in minidumps.
+    ///
+    /// The load bias is worked out from where the object file states its
+    /// image starts, once it's loaded. Addresses from `start + len` on aren't
+    /// attributed to this module.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn from_image<P: Into<PathBuf>>(path: P, start: usize, len: usize) -> Module {
+        Module {
+            source: Source::Path(path.into()),
+            base: start,
+            image_len: Some(len),
+            build_id: None,
+        }
+    }
+
+    /// Creates a module from the contents of an object file, which was loaded
+    /// with a load bias of `base` in the process the addresses came from.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn from_bytes(data: Vec<u8>, base: usize) -> Module {
+        Module {
+            source: Source::Bytes(data),
+            base,
+            image_len: None,
+            build_id: None,
+        }
+    }
+
+    /// Records the build-id (or UUID on macOS) of this module.
+    ///
+    /// This is informational only, the object file isn't checked against it.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn with_build_id(mut self, build_id: Vec<u8>) -> Module {
+        self.build_id = Some(build_id);
+        self
+    }
+
+    /// Returns the path of this module, if it was created from one.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn path(&self) -> Option<&Path> {
+        match &self.source {
+            Source::Path(path) => Some(path),
+            Source::Bytes(_) => None,
+        }
+    }
+
+    /// Returns the load bias of this module, or where its image starts if it
+    /// was created with `from_image`.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Returns the build-id of this module, if known.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn build_id(&self) -> Option<&[u8]> {
+        self.build_id.as_ref().map(|id| &id[..])
+    }
+}
+
+impl From<&MinidumpModule> for Module {
+    fn from(minidump: &MinidumpModule) -> Module {
+        let module = Module::from_image(&minidump.name, minidump.base, minidump.size);
+        match &minidump.debug_id {
+            Some(id) => module.with_build_id(id.clone()),
+            None => module,
+        }
+    }
+}
+
+impl From<&RawModule> for Module {
+    fn from(raw: &RawModule) -> Module {
+        let module = Module::from_path(raw.path(), raw.base());
+        match raw.build_id() {
+            Some(id) => module.with_build_id(id.to_vec()),
+            None => module,
+        }
+    }
+}
+
+/// Symbolizes addresses using a fixed list of modules.
+///
+/// Object files are loaded and parsed lazily the first time an address in
+/// them is resolved, and kept around for subsequent lookups.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub struct Symbolizer {
+    // Sorted by base address.
+    modules: Vec<(Module, Loaded)>,
+}
+
+enum Loaded {
+    NotYet,
+    Failed,
+    /// The mapping and the load bias of the module.
+    Mapping(Box<OfflineMapping>, usize),
+}
+
+impl Symbolizer {
+    /// Creates a symbolizer without any modules.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn new() -> Symbolizer {
+        Symbolizer {
+            modules: Vec::new(),
+        }
+    }
+
+    /// Adds a module to look up addresses in.
+    ///
+    /// Addresses are attributed to the module with the highest base which
+    /// isn't above the address.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn add_module(&mut self, module: Module) {
+        let i = self
+            .modules
+            .iter()
+            .position(|(m, _)| m.base > module.base)
+            .unwrap_or(self.modules.len());
+        self.modules.insert(i, (module, Loaded::NotYet));
+    }
+
+    /// Returns the modules added to this symbolizer, sorted by base address.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn modules(&self) -> impl Iterator<Item = &Module> {
+        self.modules.iter().map(|(m, _)| m)
+    }
+
+    /// Resolves an address of the original process, calling `cb` for each
+    /// symbol found (there can be several for inlined functions).
+    ///
+    /// Like `backtrace::resolve`, `addr` is assumed to be a return address as
+    /// found in a backtrace, so the instruction before it is looked up.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn resolve(&mut self, addr: usize, cb: &mut dyn FnMut(&Symbol)) {
+        let (module, loaded) = match self.modules.iter_mut().rev().find(|(m, _)| m.base <= addr) {
+            Some(pair) => pair,
+            None => return,
+        };
+        if let Some(len) = module.image_len {
+            if addr - module.base >= len {
+                return;
+            }
+        }
+        if let Loaded::NotYet = loaded {
+            let mapping = match &mut module.source {
+                Source::Path(path) => OfflineMapping::from_path(path),
+                // The mapping keeps the data alive from now on.
+                Source::Bytes(data) => OfflineMapping::from_bytes(mem::take(data)),
+            };
+            let image_len = module.image_len;
+            let base = module.base;
+            *loaded = mapping
+                .and_then(|mapping| {
+                    let bias = match image_len {
+                        Some(_) => base.wrapping_sub(mapping.image_base()?),
+                        None => base,
+                    };
+                    Some(Loaded::Mapping(Box::new(mapping), bias))
+                })
+                .unwrap_or(Loaded::Failed);
+        }
+        if let Loaded::Mapping(mapping, bias) = loaded {
+            mapping.resolve(addr.wrapping_sub(*bias) as *mut c_void, cb);
+        }
+    }
+}
+
+impl Default for Symbolizer {
+    fn default() -> Symbolizer {
+        Symbolizer::new()
+    }
+}
+
+/// A module as listed in the module list of a minidump.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MinidumpModule {
+    /// The path of the module's object file on this machine, which is usually
+    /// where its code file name from the minidump has been looked up.
+    pub name: PathBuf,
+    /// The address the module's image starts at.
+    pub base: usize,
+    /// How many bytes the module's image covers from `base`.
+    pub size: usize,
+    /// The module's debug identifier, recorded as the build-id of the
+    /// `Module`, if known.
+    pub debug_id: Option<Vec<u8>>,
+}
+
+/// Symbolizes the frame addresses of a minidump with its module list, calling
+/// `cb` with the index of the address in `addrs` and each symbol found for it.
+///
+/// Like `Symbolizer::resolve`, addresses are assumed to be return addresses
+/// as found in a backtrace.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn resolve_minidump(
+    modules: &[MinidumpModule],
+    addrs: &[usize],
+    cb: &mut dyn FnMut(usize, &Symbol),
+) {
+    let mut symbolizer = Symbolizer::new();
+    for module in modules {
+        symbolizer.add_module(module.into());
+    }
+    for (i, addr) in addrs.iter().enumerate() {
+        symbolizer.resolve(*addr, &mut |symbol| cb(i, symbol));
+    }
+}
@@ -0,0 +1,106 @@
+//! Symbolization of addresses in other running processes.
+//!
+//! Sampling profilers and out-of-process crash handlers capture addresses
+//! from another process, for example with `ptrace`, and need them symbolized
+//! with that process's modules rather than their own. This module finds the
+//! modules of a process by its pid (see `process_modules`) and symbolizes
+//! addresses with the object files they were loaded from.
+//!
+//! ```no_run
+//! use backtrace::remote::Symbolizer;
+//!
+//! # let pid = 1234;
+//! # let addrs: Vec<usize> = Vec::new();
+//! let mut symbolizer = Symbolizer::new();
+//! symbolizer.resolve_many(pid, &addrs, &mut |i, symbol| {
+//!     println!("{:#x}: {:?}", addrs[i], symbol);
+//! });
+//! ```
+//!
+//! Parsed object files are kept by build-id (or by path for those without
+//! one) and shared between processes, so libraries which many processes load
+//! are only parsed once. With the `disk-cache` feature, the same on-disk
+//! indexes as for the current process are used.
+//!
+//! # Required features
+//!
+//! This module requires the `std` feature of the `backtrace` crate to be
+//! enabled, and the `std` feature is enabled by default. It's only available
+//! on Linux and Android.
+
+use super::imp::RemoteSymbolizer;
+use super::Symbol;
+use core::ffi::c_void;
+
+/// Symbolizes addresses of other processes, identified by their pid.
+///
+/// The modules of a process are read the first time one of its addresses is
+/// resolved, which takes the same permission as attaching to it with
+/// `ptrace`. They're read again if an address isn't in any of them, as the
+/// process may have loaded more since.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub struct Symbolizer {
+    inner: RemoteSymbolizer,
+}
+
+impl Symbolizer {
+    /// Creates a symbolizer which hasn't seen any processes yet.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn new() -> Symbolizer {
+        Symbolizer {
+            inner: RemoteSymbolizer::new(),
+        }
+    }
+
+    /// Resolves an address of the process `pid`, calling `cb` for each symbol
+    /// found (there can be several for inlined functions).
+    ///
+    /// Like `backtrace::resolve`, `addr` is assumed to be a return address as
+    /// found in a backtrace, so the instruction before it is looked up.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn resolve(&mut self, pid: u32, addr: usize, cb: &mut dyn FnMut(&Symbol)) {
+        self.inner.resolve(pid, addr as *mut c_void, cb);
+    }
+
+    /// Resolves several addresses of the process `pid`, calling `cb` with the
+    /// index of the address in `addrs` and each symbol found for it.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn resolve_many(&mut self, pid: u32, addrs: &[usize], cb: &mut dyn FnMut(usize, &Symbol)) {
+        for (i, addr) in addrs.iter().enumerate() {
+            self.resolve(pid, *addr, &mut |symbol| cb(i, symbol));
+        }
+    }
+
+    /// Forgets the modules of the process `pid`, for example once it has
+    /// exited, and drops the object files no other process needs.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn forget(&mut self, pid: u32) {
+        self.inner.forget(pid);
+    }
+}
+
+impl Default for Symbolizer {
+    fn default() -> Symbolizer {
+        Symbolizer::new()
+    }
+}
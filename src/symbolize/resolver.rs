@@ -0,0 +1,172 @@
+//! Hooks supplying symbols for code the symbolizer doesn't know about.
+
+use super::{Symbol, SymbolImp};
+use crate::BytesOrWideString;
+use core::ffi::c_void;
+use core::ops::Range;
+use core::ptr;
+use std::path::PathBuf;
+use std::prelude::v1::*;
+
+type Hook = Box<dyn Fn(*mut c_void, &mut dyn FnMut(&Symbol)) + Send + Sync>;
+
+// Only accessed with the crate's global lock held.
+static mut RESOLVERS: *mut Vec<(Range<usize>, Hook)> = ptr::null_mut();
+
+/// Registers a hook which resolves addresses in `range`.
+///
+/// Language runtimes which generate code of their own, such as interpreters
+/// and JIT compilers, can use this to supply names for that code. Whenever
+/// `resolve` (or `resolve_frame`, and so `Backtrace` too) finds nothing for an
+/// address in `range` it calls `hook` with the address, which should then
+/// call the callback it's given with each symbol for the address, typically
+/// created with `Symbol::new`.
+///
+/// Like for the symbolizer itself, the address is the instruction before the
+/// return address of a frame. If the ranges of several hooks contain an
+/// address only the one registered last is called.
+///
+/// The hook is called with the same lock held as for the rest of
+/// symbolization, so it must not itself register resolvers.
+///
+/// # Example
+///
+/// ```
+/// use backtrace::Symbol;
+///
+/// let region = 0x1000_0000..0x1001_0000;
+/// backtrace::register_resolver(region, Box::new(|addr, cb| {
+///     let name = format!("jit_function_{:x}", addr as usize >> 8);
+///     cb(&Symbol::new(name).with_lineno(1));
+/// }));
+/// ```
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn register_resolver(range: Range<usize>, hook: Hook) {
+    let _guard = crate::lock::lock();
+    unsafe {
+        if RESOLVERS.is_null() {
+            RESOLVERS = Box::into_raw(Box::new(Vec::new()));
+        }
+        (*RESOLVERS).push((range, hook));
+    }
+}
+
+// unsafe because this is required to be externally synchronized
+pub(super) unsafe fn resolve(addr: *mut c_void, cb: &mut dyn FnMut(&Symbol)) {
+    let resolvers = match RESOLVERS.as_ref() {
+        Some(resolvers) => resolvers,
+        None => return,
+    };
+    let hook = resolvers
+        .iter()
+        .rev()
+        .find(|(range, _)| range.start <= addr as usize && (addr as usize) < range.end);
+    if let Some((_, hook)) = hook {
+        hook(addr, cb);
+    }
+}
+
+/// A symbol created with `Symbol::new`.
+pub(super) struct CustomSymbol {
+    pub(super) name: Vec<u8>,
+    pub(super) addr: Option<*mut c_void>,
+    pub(super) filename: Option<PathBuf>,
+    pub(super) lineno: Option<u32>,
+    pub(super) colno: Option<u32>,
+}
+
+impl CustomSymbol {
+    pub(super) fn filename_raw(&self) -> Option<BytesOrWideString<'_>> {
+        let filename = self.filename.as_ref()?;
+        cfg_if::cfg_if! {
+            if #[cfg(unix)] {
+                use std::os::unix::prelude::*;
+                Some(BytesOrWideString::Bytes(filename.as_os_str().as_bytes()))
+            } else {
+                filename.to_str().map(|s| BytesOrWideString::Bytes(s.as_bytes()))
+            }
+        }
+    }
+}
+
+impl Symbol {
+    /// Creates a symbol with the given name.
+    ///
+    /// Symbols are normally only handed out by `resolve`, this is for hooks
+    /// registered with `register_resolver` to describe the code they know
+    /// about. The other properties of the symbol can be filled in with the
+    /// `with_*` methods.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn new(name: impl Into<Vec<u8>>) -> Symbol {
+        Symbol {
+            inner: SymbolImp::Custom(CustomSymbol {
+                name: name.into(),
+                addr: None,
+                filename: None,
+                lineno: None,
+                colno: None,
+            }),
+            context: core::cell::UnsafeCell::new(None),
+        }
+    }
+
+    /// Sets the starting address of the function of this symbol.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn with_addr(mut self, addr: *mut c_void) -> Symbol {
+        if let SymbolImp::Custom(s) = &mut self.inner {
+            s.addr = Some(addr);
+        }
+        self
+    }
+
+    /// Sets the file name of this symbol.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn with_filename(mut self, filename: impl Into<PathBuf>) -> Symbol {
+        if let SymbolImp::Custom(s) = &mut self.inner {
+            s.filename = Some(filename.into());
+        }
+        self
+    }
+
+    /// Sets the line number of this symbol.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn with_lineno(mut self, lineno: u32) -> Symbol {
+        if let SymbolImp::Custom(s) = &mut self.inner {
+            s.lineno = Some(lineno);
+        }
+        self
+    }
+
+    /// Sets the column number of this symbol.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn with_colno(mut self, colno: u32) -> Symbol {
+        if let SymbolImp::Custom(s) = &mut self.inner {
+            s.colno = Some(colno);
+        }
+        self
+    }
+}
@@ -0,0 +1,162 @@
+//! Pluggable access to the object files and debug files used to symbolize.
+
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering::SeqCst};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::prelude::v1::*;
+use std::sync::Arc;
+
+/// Supplies the object files that symbolization reads debug information from.
+///
+/// By default object files are read from the file system, which is what
+/// `FileSystemSource` does. Programs which keep their debug information
+/// somewhere else, like a flash partition, an archive or an artifact store,
+/// can serve it with their own implementation of this trait, set with
+/// `set_object_source`.
+///
+/// Object files are still looked for at the same paths, like the path a
+/// library was loaded from or the path of a separate debug file, only their
+/// contents come from here. Implementations should return `None` for files
+/// they don't have, in which case those are treated as not existing at all.
+///
+/// # Required features
+///
+/// This trait requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub trait ObjectSource: Send + Sync {
+    /// Returns the contents of the object file at `path`.
+    fn open_path(&self, path: &Path) -> Option<ObjectData>;
+
+    /// Returns the contents of the separate debug file for the object file
+    /// with the given build-id, if there is one.
+    ///
+    /// This is asked before looking for the debug file at the paths that
+    /// debuggers use for build-ids, like `/usr/lib/debug/.build-id`, which
+    /// are then opened with `open_path`. The default implementation returns
+    /// `None`.
+    fn open_build_id(&self, build_id: &[u8]) -> Option<ObjectData> {
+        let _ = build_id;
+        None
+    }
+}
+
+/// The contents of an object file, as returned by an `ObjectSource`.
+///
+/// # Required features
+///
+/// This type requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub struct ObjectData {
+    pub(crate) kind: ObjectDataKind,
+}
+
+#[allow(dead_code)]
+pub(crate) enum ObjectDataKind {
+    File(File),
+    Bytes(Arc<[u8]>),
+    Reader(Box<dyn Read + Send>),
+}
+
+impl ObjectData {
+    /// Creates `ObjectData` for an open file, which is mapped into memory
+    /// like the files found on the file system are.
+    pub fn from_file(file: File) -> ObjectData {
+        ObjectData {
+            kind: ObjectDataKind::File(file),
+        }
+    }
+
+    /// Creates `ObjectData` for an object file already in memory.
+    pub fn from_bytes(data: impl Into<Arc<[u8]>>) -> ObjectData {
+        ObjectData {
+            kind: ObjectDataKind::Bytes(data.into()),
+        }
+    }
+
+    /// Creates `ObjectData` which is read from `reader` until its end.
+    pub fn from_reader(reader: impl Read + Send + 'static) -> ObjectData {
+        ObjectData {
+            kind: ObjectDataKind::Reader(Box::new(reader)),
+        }
+    }
+}
+
+/// The `ObjectSource` used unless another one is set, reading object files
+/// from the file system.
+///
+/// # Required features
+///
+/// This type requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileSystemSource;
+
+impl ObjectSource for FileSystemSource {
+    fn open_path(&self, path: &Path) -> Option<ObjectData> {
+        File::open(path).ok().map(ObjectData::from_file)
+    }
+}
+
+// Only replaced with the crate's global lock held, and only read with it held
+// or from the threads creating mappings for `resolve_many` while that's held,
+// so the source it replaces can be dropped right away.
+static SOURCE: AtomicPtr<Box<dyn ObjectSource>> = AtomicPtr::new(ptr::null_mut());
+
+/// Sets where object files are read from when symbolizing addresses.
+///
+/// See `ObjectSource` for more information. Object files which have been
+/// loaded already stay in the symbol cache, so this is best called before the
+/// first symbolization or followed by `clear_symbol_cache`. To go back to the
+/// file system, set a `FileSystemSource`.
+///
+/// # Caveats
+///
+/// Like `clear_symbol_cache`, this only has an effect with the
+/// `gimli-symbolize` feature of this crate.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+pub fn set_object_source(source: Box<dyn ObjectSource>) {
+    let _guard = crate::lock::lock();
+    let previous = SOURCE.swap(Box::into_raw(Box::new(source)), SeqCst);
+    if !previous.is_null() {
+        unsafe {
+            drop(Box::from_raw(previous));
+        }
+    }
+}
+
+// unsafe because this is required to be externally synchronized
+#[allow(dead_code)]
+unsafe fn source() -> Option<&'static dyn ObjectSource> {
+    let source = SOURCE.load(SeqCst);
+    if source.is_null() {
+        None
+    } else {
+        Some(&**source)
+    }
+}
+
+// unsafe because this is required to be externally synchronized, and only
+// used by the gimli backend
+#[allow(dead_code)]
+pub(crate) unsafe fn open_path(path: &Path) -> Option<ObjectData> {
+    match source() {
+        Some(source) => source.open_path(path),
+        None => FileSystemSource.open_path(path),
+    }
+}
+
+// unsafe because this is required to be externally synchronized, and only
+// used by the gimli backend for ELF
+#[allow(dead_code)]
+pub(crate) unsafe fn open_build_id(build_id: &[u8]) -> Option<ObjectData> {
+    match source() {
+        Some(source) => source.open_build_id(build_id),
+        None => None,
+    }
+}
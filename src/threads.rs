@@ -0,0 +1,532 @@
+//! Support for capturing the stack of threads other than the calling one.
+//!
+//! The unwinders used by this crate can only walk the stack of the thread
+//! they're running on, so to trace another thread we need its cooperation:
+//!
+//! * On Linux and Android the target thread is sent a `SIGPROF` signal, and
+//!   the signal handler walks the interrupted stack with `trace_from_context`
+//!   into a static buffer while the requesting thread waits for it.
+//! * On Windows the target thread is suspended and its stack is walked with
+//!   the register state returned by `GetThreadContext`.
+//!
+//! Other platforms are not supported and only the calling thread can be
+//! traced there.
+
+use crate::Backtrace;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::prelude::v1::*;
+
+/// Maximum number of frames recorded when tracing another thread.
+const MAX_FRAMES: usize = 256;
+
+/// An operating system identifier of a thread in the current process.
+///
+/// This is the kernel thread id on Linux and Android (as returned by
+/// `gettid`), and the thread id on Windows (as returned by
+/// `GetCurrentThreadId`). It is used with `Backtrace::capture_thread` to
+/// select the thread to trace.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OsThreadId(u64);
+
+impl OsThreadId {
+    /// Returns the identifier of the calling thread.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn current() -> OsThreadId {
+        OsThreadId(imp::current())
+    }
+
+    /// Creates an identifier from its raw operating system value.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn from_raw(raw: u64) -> OsThreadId {
+        OsThreadId(raw)
+    }
+
+    /// Returns the raw operating system value of this identifier.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn as_raw(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Debug for OsThreadId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("OsThreadId").field(&self.0).finish()
+    }
+}
+
+impl fmt::Display for OsThreadId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A backtrace of one thread, as returned by `capture_all_threads`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug)]
+pub struct ThreadBacktrace {
+    id: OsThreadId,
+    name: Option<String>,
+    backtrace: Backtrace,
+}
+
+impl ThreadBacktrace {
+    /// Returns the identifier of the thread.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn id(&self) -> OsThreadId {
+        self.id
+    }
+
+    /// Returns the name of the thread, if the platform keeps track of one.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the backtrace of the thread.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.backtrace
+    }
+
+    /// Returns the backtrace of the thread, consuming this value.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn into_backtrace(self) -> Backtrace {
+        self.backtrace
+    }
+}
+
+/// Captures a backtrace of every thread in this process.
+///
+/// The threads of the process are enumerated (through `/proc/self/task` on
+/// Linux and Android, and a toolhelp snapshot on Windows) and each of them is
+/// traced in the same way as `Backtrace::capture_thread`. To keep the
+/// snapshot as coherent as possible, all stacks are captured before any
+/// symbols are resolved.
+///
+/// Threads which exit during enumeration, or which can't be traced, are
+/// omitted. On platforms where other threads can't be traced the returned
+/// map only contains the calling thread.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[inline(never)] // want to make sure there's a frame here to remove
+pub fn capture_all_threads() -> BTreeMap<OsThreadId, ThreadBacktrace> {
+    let current = OsThreadId::current();
+    let mut threads = imp::list_threads();
+    if !threads.iter().any(|(id, _)| *id == current) {
+        threads.push((current, None));
+    }
+
+    let mut ret = BTreeMap::new();
+    for (id, name) in threads {
+        let backtrace = if id == current {
+            Backtrace::create(capture_all_threads as *const () as usize)
+        } else {
+            match trace_thread(id) {
+                Some(ips) => Backtrace::from_ips(ips),
+                None => continue,
+            }
+        };
+        ret.insert(
+            id,
+            ThreadBacktrace {
+                id,
+                name,
+                backtrace,
+            },
+        );
+    }
+    for thread in ret.values_mut() {
+        thread.backtrace.resolve();
+    }
+    ret
+}
+
+/// Captures the instruction pointers of the stack of another thread,
+/// top-to-bottom, or returns `None` if that isn't possible.
+pub(crate) fn trace_thread(id: OsThreadId) -> Option<Vec<usize>> {
+    let _guard = crate::lock::lock();
+    crate::backtrace::init_max_frames();
+    let mut buf = [0usize; MAX_FRAMES];
+    let len = unsafe { imp::trace_thread(id.0, &mut buf)? };
+    Some(buf[..len].to_vec())
+}
+
+#[cfg(all(any(target_os = "linux", target_os = "android"), not(miri)))]
+pub(crate) use self::imp::install_timer_handler;
+
+cfg_if::cfg_if! {
+    if #[cfg(all(any(target_os = "linux", target_os = "android"), not(miri)))] {
+        mod imp {
+            use core::ffi::c_void;
+            use core::mem;
+            use core::ptr;
+            use core::sync::atomic::{AtomicI32, AtomicUsize, Ordering::SeqCst};
+            use std::fs;
+            use std::prelude::v1::*;
+            use std::thread;
+            use std::time::{Duration, Instant};
+
+            const IDLE: usize = 0;
+            const REQUESTED: usize = 1;
+            const RUNNING: usize = 2;
+            const DONE: usize = 3;
+            const PHASE: usize = 3;
+
+            /// How long to wait for the target thread to handle our signal,
+            /// and then for a handler which already started to finish.
+            const TIMEOUT: Duration = Duration::from_secs(1);
+
+            // The phase of the last request in the low bits, and its sequence
+            // number in the others, so that a handler can't complete a request
+            // other than the one it started on.
+            static STATE: AtomicUsize = AtomicUsize::new(IDLE);
+            static TARGET: AtomicI32 = AtomicI32::new(0);
+            static LEN: AtomicUsize = AtomicUsize::new(0);
+            static mut FRAMES: [usize; super::MAX_FRAMES] = [0; super::MAX_FRAMES];
+
+            // The `SIGPROF` action our handler replaced, which signals that
+            // aren't one of our requests are forwarded to.
+            static PREVIOUS_ACTION: AtomicUsize = AtomicUsize::new(libc::SIG_DFL);
+            static PREVIOUS_FLAGS: AtomicI32 = AtomicI32::new(0);
+
+            // The handler for the signals of the timer of `profiling::Sampler`,
+            // or 0 if no sampler was ever started. It stays set once one was,
+            // so that signals of the timer still pending after the sampler
+            // stopped are dropped rather than forwarded.
+            static TIMER_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+            fn gettid() -> libc::pid_t {
+                unsafe { libc::syscall(libc::SYS_gettid) as libc::pid_t }
+            }
+
+            pub fn current() -> u64 {
+                gettid() as u64
+            }
+
+            pub fn list_threads() -> Vec<(super::OsThreadId, Option<String>)> {
+                let mut ret = Vec::new();
+                let dir = match fs::read_dir("/proc/self/task") {
+                    Ok(dir) => dir,
+                    Err(_) => return ret,
+                };
+                for entry in dir.filter_map(|e| e.ok()) {
+                    let tid = match entry.file_name().to_str().and_then(|s| s.parse().ok()) {
+                        Some(tid) => tid,
+                        None => continue,
+                    };
+                    let name = fs::read_to_string(entry.path().join("comm"))
+                        .ok()
+                        .map(|s| s.trim_end_matches('\n').to_string());
+                    ret.push((super::OsThreadId(tid), name));
+                }
+                ret
+            }
+
+            // The one handler for `SIGPROF`, shared by the requests of
+            // `trace_thread` and the timer of `profiling::Sampler`.
+            extern "C" fn handler(signum: libc::c_int, info: *mut libc::siginfo_t, context: *mut c_void) {
+                unsafe {
+                    let errno = errno();
+                    let saved = *errno;
+                    dispatch(signum, info, context);
+                    *errno = saved;
+                }
+            }
+
+            unsafe fn dispatch(signum: libc::c_int, info: *mut libc::siginfo_t, context: *mut c_void) {
+                let request = if is_ours(info) { start_request() } else { None };
+                if let Some(seq) = request {
+                    let mut len = 0;
+                    crate::trace_from_context_unsynchronized(context, |frame| {
+                        FRAMES[len] = frame.ip() as usize;
+                        len += 1;
+                        len < super::MAX_FRAMES
+                    });
+                    LEN.store(len, SeqCst);
+                    STATE.store(seq | DONE, SeqCst);
+                    return;
+                }
+                let timer = TIMER_HANDLER.load(SeqCst);
+                if timer != 0 && (*info).si_code == libc::SI_KERNEL {
+                    let timer: unsafe fn(*mut c_void) = mem::transmute(timer);
+                    timer(context);
+                    return;
+                }
+                // Someone else's signal, or one of ours which arrived after
+                // we stopped waiting for it.
+                forward(signum, info, context)
+            }
+
+            // Moves the request for the current thread, if there is one, to
+            // `RUNNING`, returning its sequence number.
+            fn start_request() -> Option<usize> {
+                let state = STATE.load(SeqCst);
+                let seq = state & !PHASE;
+                if state & PHASE != REQUESTED
+                    || TARGET.load(SeqCst) != gettid()
+                    || STATE.compare_exchange(state, seq | RUNNING, SeqCst, SeqCst).is_err()
+                {
+                    return None;
+                }
+                Some(seq)
+            }
+
+            unsafe fn errno() -> *mut libc::c_int {
+                cfg_if::cfg_if! {
+                    if #[cfg(target_os = "android")] {
+                        libc::__errno()
+                    } else {
+                        libc::__errno_location()
+                    }
+                }
+            }
+
+            unsafe fn forward(signum: libc::c_int, info: *mut libc::siginfo_t, context: *mut c_void) {
+                let action = PREVIOUS_ACTION.load(SeqCst);
+                if action == libc::SIG_IGN {
+                    return;
+                }
+                if action == libc::SIG_DFL {
+                    // Late signals of ours are dropped, but anyone else's gets
+                    // the default action, terminating the process, as it
+                    // would have without our handler. It's raised again once
+                    // the handler returns, as `SIGPROF` is blocked until then.
+                    if !is_ours(info) {
+                        let mut default: libc::sigaction = mem::zeroed();
+                        default.sa_sigaction = libc::SIG_DFL;
+                        libc::sigemptyset(&mut default.sa_mask);
+                        libc::sigaction(signum, &default, ptr::null_mut());
+                        libc::raise(signum);
+                    }
+                    return;
+                }
+                if PREVIOUS_FLAGS.load(SeqCst) & libc::SA_SIGINFO != 0 {
+                    let action: extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut c_void) =
+                        mem::transmute(action);
+                    action(signum, info, context)
+                } else {
+                    let action: extern "C" fn(libc::c_int) = mem::transmute(action);
+                    action(signum)
+                }
+            }
+
+            // Installs our handler for `SIGPROF`, passing the signals of the
+            // `ITIMER_PROF` timer to `timer`, which is called with the context
+            // of the interrupted thread and must be async-signal-safe.
+            //
+            // unsafe because this is required to be externally synchronized
+            pub unsafe fn install_timer_handler(timer: unsafe fn(*mut c_void)) -> bool {
+                TIMER_HANDLER.store(timer as usize, SeqCst);
+                install()
+            }
+
+            // Whether a signal was sent by `trace_thread`, rather than by
+            // another process, a timer or `kill`.
+            unsafe fn is_ours(info: *mut libc::siginfo_t) -> bool {
+                (*info).si_code == libc::SI_TKILL && (*info).si_pid() == libc::getpid()
+            }
+
+            // Installs our handler for `SIGPROF` unless it already is.
+            //
+            // It's never uninstalled: a signal we gave up waiting for may
+            // still be pending, and if `SIGPROF` had its default action again
+            // by then it would terminate the process.
+            unsafe fn install() -> bool {
+                let ours = handler as *const () as libc::sighandler_t;
+                let mut current: libc::sigaction = mem::zeroed();
+                if libc::sigaction(libc::SIGPROF, ptr::null(), &mut current) != 0 {
+                    return false;
+                }
+                if current.sa_sigaction == ours {
+                    return true;
+                }
+                PREVIOUS_FLAGS.store(current.sa_flags, SeqCst);
+                PREVIOUS_ACTION.store(current.sa_sigaction, SeqCst);
+
+                let mut action: libc::sigaction = mem::zeroed();
+                action.sa_sigaction = ours;
+                action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+                libc::sigaction(libc::SIGPROF, &action, ptr::null_mut()) == 0
+            }
+
+            // Waits until `STATE` satisfies `f`, returning its value, or
+            // `None` if that takes longer than `TIMEOUT`.
+            fn wait_for(f: impl Fn(usize) -> bool) -> Option<usize> {
+                let start = Instant::now();
+                loop {
+                    let state = STATE.load(SeqCst);
+                    if f(state) {
+                        return Some(state);
+                    }
+                    if start.elapsed() >= TIMEOUT {
+                        return None;
+                    }
+                    thread::yield_now();
+                }
+            }
+
+            // unsafe because this is required to be externally synchronized
+            pub unsafe fn trace_thread(id: u64, buf: &mut [usize]) -> Option<usize> {
+                let tid = id as libc::pid_t;
+
+                // The handler of an earlier request which we gave up on may
+                // still be writing to `FRAMES`.
+                let state = wait_for(|state| state & PHASE != RUNNING)?;
+                if !install() {
+                    return None;
+                }
+
+                let seq = (state & !PHASE).wrapping_add(PHASE + 1);
+                TARGET.store(tid, SeqCst);
+                LEN.store(0, SeqCst);
+                STATE.store(seq | REQUESTED, SeqCst);
+
+                let mut done = false;
+                if libc::syscall(libc::SYS_tgkill, libc::getpid(), tid, libc::SIGPROF) == 0 {
+                    done = wait_for(|state| state == seq | DONE).is_some();
+                }
+
+                // If the handler never started we can just give up, but if
+                // it's in the middle of running it gets a while longer to
+                // finish. Should it not, it's left `RUNNING` for the next
+                // request to wait on.
+                if !done
+                    && STATE
+                        .compare_exchange(seq | REQUESTED, seq | IDLE, SeqCst, SeqCst)
+                        .is_err()
+                {
+                    done = wait_for(|state| state == seq | DONE).is_some();
+                }
+
+                let mut ret = None;
+                if done {
+                    let len = LEN.load(SeqCst).min(buf.len());
+                    buf[..len].copy_from_slice(&FRAMES[..len]);
+                    ret = Some(len);
+                    STATE.store(seq | IDLE, SeqCst);
+                }
+                TARGET.store(0, SeqCst);
+                ret
+            }
+        }
+    } else if #[cfg(all(windows, not(target_vendor = "uwp"), not(miri)))] {
+        mod imp {
+            use crate::windows::*;
+            use core::mem;
+            use std::prelude::v1::*;
+
+            pub fn current() -> u64 {
+                unsafe { GetCurrentThreadId() as u64 }
+            }
+
+            pub fn list_threads() -> Vec<(super::OsThreadId, Option<String>)> {
+                let mut ret = Vec::new();
+                unsafe {
+                    let snap = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+                    if snap == INVALID_HANDLE_VALUE {
+                        return ret;
+                    }
+                    let pid = GetCurrentProcessId();
+                    let mut entry: THREADENTRY32 = mem::zeroed();
+                    entry.dwSize = mem::size_of::<THREADENTRY32>() as DWORD;
+                    if Thread32First(snap, &mut entry) == TRUE {
+                        loop {
+                            // The snapshot includes the threads of every
+                            // process in the system.
+                            if entry.th32OwnerProcessID == pid {
+                                ret.push((super::OsThreadId(entry.th32ThreadID as u64), None));
+                            }
+                            if Thread32Next(snap, &mut entry) != TRUE {
+                                break;
+                            }
+                        }
+                    }
+                    CloseHandle(snap);
+                }
+                ret
+            }
+
+            // unsafe because this is required to be externally synchronized
+            pub unsafe fn trace_thread(id: u64, buf: &mut [usize]) -> Option<usize> {
+                let mut len = 0;
+                // Note that the target thread is suspended while this callback
+                // runs, so it mustn't allocate.
+                let ok = crate::backtrace::trace_thread(id as DWORD, &mut |frame: &crate::Frame| {
+                    buf[len] = frame.ip() as usize;
+                    len += 1;
+                    len < buf.len()
+                });
+                if ok {
+                    Some(len)
+                } else {
+                    None
+                }
+            }
+        }
+    } else {
+        mod imp {
+            use std::prelude::v1::*;
+
+            pub fn current() -> u64 {
+                #[cfg(unix)]
+                {
+                    unsafe { libc::pthread_self() as u64 }
+                }
+                #[cfg(not(unix))]
+                {
+                    0
+                }
+            }
+
+            pub fn list_threads() -> Vec<(super::OsThreadId, Option<String>)> {
+                Vec::new()
+            }
+
+            pub unsafe fn trace_thread(_id: u64, _buf: &mut [usize]) -> Option<usize> {
+                None
+            }
+        }
+    }
+}
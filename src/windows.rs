@@ -15,6 +15,7 @@ cfg_if::cfg_if! {
         pub use self::winapi::HINSTANCE;
         pub use self::winapi::FARPROC;
         pub use self::winapi::LPSECURITY_ATTRIBUTES;
+        pub use self::winapi::PEXCEPTION_RECORD;
         #[cfg(target_pointer_width = "64")]
         pub use self::winapi::PUNWIND_HISTORY_TABLE;
         #[cfg(target_pointer_width = "64")]
@@ -41,6 +42,7 @@ cfg_if::cfg_if! {
         pub type HINSTANCE = *mut c_void;
         pub type FARPROC = *mut c_void;
         pub type LPSECURITY_ATTRIBUTES = *mut c_void;
+        pub type PEXCEPTION_RECORD = *mut c_void;
         #[cfg(target_pointer_width = "64")]
         pub type PRUNTIME_FUNCTION = *mut c_void;
         #[cfg(target_pointer_width = "64")]
@@ -326,6 +328,23 @@ ffi! {
         pub szExePath: [WCHAR; MAX_PATH],
     }
 
+    #[repr(C)]
+    pub struct THREADENTRY32 {
+        pub dwSize: DWORD,
+        pub cntUsage: DWORD,
+        pub th32ThreadID: DWORD,
+        pub th32OwnerProcessID: DWORD,
+        pub tpBasePri: LONG,
+        pub tpDeltaPri: LONG,
+        pub dwFlags: DWORD,
+    }
+
+    #[repr(C)]
+    pub struct EXCEPTION_POINTERS {
+        pub ExceptionRecord: PEXCEPTION_RECORD,
+        pub ContextRecord: PCONTEXT,
+    }
+
     pub const MAX_SYM_NAME: usize = 2000;
     pub const AddrModeFlat: ADDRESS_MODE = 3;
     pub const TRUE: BOOL = 1;
@@ -343,9 +362,15 @@ ffi! {
     pub const PAGE_READONLY: DWORD = 2;
     pub const FILE_MAP_READ: DWORD = 4;
     pub const TH32CS_SNAPMODULE: DWORD = 0x00000008;
+    pub const TH32CS_SNAPTHREAD: DWORD = 0x00000004;
     pub const INVALID_HANDLE_VALUE: HANDLE = -1isize as HANDLE;
     pub const MAX_MODULE_NAME32: usize = 255;
     pub const MAX_PATH: usize = 260;
+    pub const THREAD_SUSPEND_RESUME: DWORD = 0x0002;
+    pub const THREAD_GET_CONTEXT: DWORD = 0x0008;
+    pub const THREAD_QUERY_INFORMATION: DWORD = 0x0040;
+    pub const GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT: DWORD = 0x00000002;
+    pub const GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS: DWORD = 0x00000004;
 
     pub type DWORD = u32;
     pub type PDWORD = *mut u32;
@@ -370,15 +395,31 @@ ffi! {
     pub type LPVOID = *mut c_void;
     pub type LPCVOID = *const c_void;
     pub type LPMODULEENTRY32W = *mut MODULEENTRY32W;
+    pub type LPTHREADENTRY32 = *mut THREADENTRY32;
+    pub type LONG = i32;
 
     #[link(name = "kernel32")]
     extern "system" {
         pub fn GetCurrentProcess() -> HANDLE;
         pub fn GetCurrentThread() -> HANDLE;
+        pub fn GetCurrentThreadId() -> DWORD;
+        pub fn OpenThread(
+            dwDesiredAccess: DWORD,
+            bInheritHandle: BOOL,
+            dwThreadId: DWORD,
+        ) -> HANDLE;
+        pub fn SuspendThread(hThread: HANDLE) -> DWORD;
+        pub fn ResumeThread(hThread: HANDLE) -> DWORD;
+        pub fn GetThreadContext(hThread: HANDLE, lpContext: PCONTEXT) -> BOOL;
         pub fn RtlCaptureContext(ContextRecord: PCONTEXT) -> ();
         pub fn LoadLibraryA(a: *const i8) -> HMODULE;
         pub fn GetProcAddress(h: HMODULE, name: *const i8) -> FARPROC;
         pub fn GetModuleHandleA(name: *const i8) -> HMODULE;
+        pub fn GetModuleHandleExW(
+            dwFlags: DWORD,
+            lpModuleName: PCWSTR,
+            phModule: *mut HMODULE,
+        ) -> BOOL;
         pub fn OpenProcess(
             dwDesiredAccess: DWORD,
             bInheitHandle: BOOL,
@@ -434,6 +475,14 @@ ffi! {
             hSnapshot: HANDLE,
             lpme: LPMODULEENTRY32W,
         ) -> BOOL;
+        pub fn Thread32First(
+            hSnapshot: HANDLE,
+            lpte: LPTHREADENTRY32,
+        ) -> BOOL;
+        pub fn Thread32Next(
+            hSnapshot: HANDLE,
+            lpte: LPTHREADENTRY32,
+        ) -> BOOL;
     }
 }
 
@@ -449,6 +498,23 @@ ffi! {
     }
 }
 
+// This isn't checked against winapi, which only declares it for some
+// architectures and with architecture specific types for the last arguments.
+#[cfg(target_pointer_width = "64")]
+#[link(name = "kernel32")]
+extern "system" {
+    pub fn RtlVirtualUnwind(
+        HandlerType: DWORD,
+        ImageBase: DWORD64,
+        ControlPc: DWORD64,
+        FunctionEntry: PRUNTIME_FUNCTION,
+        ContextRecord: PCONTEXT,
+        HandlerData: *mut PVOID,
+        EstablisherFrame: PDWORD64,
+        ContextPointers: PVOID,
+    ) -> PVOID;
+}
+
 #[cfg(target_arch = "aarch64")]
 ffi! {
     #[repr(C, align(16))]
@@ -513,6 +579,7 @@ ffi! {
         pub Lr: u64,
     }
 
+    pub const CONTEXT_FULL: DWORD = 0x00400007;
     pub const ARM64_MAX_BREAKPOINTS: usize = 8;
     pub const ARM64_MAX_WATCHPOINTS: usize = 2;
 
@@ -524,6 +591,8 @@ ffi! {
 
 #[cfg(target_arch = "x86")]
 ffi! {
+    pub const CONTEXT_FULL: DWORD = 0x00010007;
+
     #[repr(C)]
     pub struct CONTEXT {
         pub ContextFlags: DWORD,
@@ -567,8 +636,12 @@ ffi! {
     }
 }
 
-#[cfg(target_arch = "x86_64")]
+// ARM64EC code sees the x64 layout of `CONTEXT`, with the native ARM64
+// registers mapped onto the x64 ones.
+#[cfg(any(target_arch = "x86_64", target_arch = "arm64ec"))]
 ffi! {
+    pub const CONTEXT_FULL: DWORD = 0x0010000B;
+
     #[repr(C, align(8))]
     pub struct CONTEXT {
         pub P1Home: DWORDLONG,
@@ -635,7 +708,7 @@ ffi! {
 }
 
 #[repr(C)]
-#[cfg(target_arch = "x86_64")]
+#[cfg(any(target_arch = "x86_64", target_arch = "arm64ec"))]
 #[derive(Copy, Clone)]
 pub struct FLOATING_SAVE_AREA {
     _Dummy: [u8; 512],
@@ -658,6 +731,7 @@ ffi! {
         // pub S: [DWORD; 32],
     }
 
+    pub const CONTEXT_FULL: DWORD = 0x00200007;
     pub const ARM_MAX_BREAKPOINTS: usize = 8;
     pub const ARM_MAX_WATCHPOINTS: usize = 1;
 
@@ -0,0 +1,285 @@
+//! A compact binary encoding of unresolved backtraces.
+//!
+//! This is the format produced by `Backtrace::to_raw_bytes` and read back by
+//! `Backtrace::from_raw_bytes`. It's intended for a crashing process to ship
+//! its backtrace to a supervisor which then symbolizes it, so along with the
+//! instruction pointers it records the modules they belong to.
+//!
+//! All integers are little-endian. The layout is:
+//!
+//! ```text
+//! magic        b"BTRW"
+//! version      u8 (currently 1)
+//! modules      u32 count, then for each:
+//!                u32 path length, path bytes
+//!                u64 base address
+//!                u8 build-id length, build-id bytes
+//! frames       u32 count, then for each:
+//!                u64 instruction pointer
+//!                u32 index into the module table, or u32::MAX if unknown
+//! ```
+//!
+//! Paths are encoded as raw bytes on Unix and as UTF-8 elsewhere.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::prelude::v1::*;
+
+pub(crate) const MAGIC: &[u8; 4] = b"BTRW";
+pub(crate) const VERSION: u8 = 1;
+pub(crate) const NO_MODULE: u32 = !0;
+
+/// A module referenced by a backtrace decoded with `Backtrace::from_raw_bytes`.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawModule {
+    path: PathBuf,
+    base: usize,
+    build_id: Option<Vec<u8>>,
+}
+
+impl RawModule {
+    /// Creates a new module record.
+    ///
+    /// The `base` is the module's load bias: subtracting it from an address
+    /// inside of the module yields the address used by the module's symbol
+    /// table and debug information.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn new(path: PathBuf, base: usize, build_id: Option<Vec<u8>>) -> RawModule {
+        RawModule {
+            path,
+            base,
+            build_id,
+        }
+    }
+
+    /// Returns the path the module was loaded from.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the load bias of the module.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Returns the build-id (or UUID on macOS) of the module, if known.
+    ///
+    /// # Required features
+    ///
+    /// This function requires the `std` feature of the `backtrace` crate to be
+    /// enabled, and the `std` feature is enabled by default.
+    pub fn build_id(&self) -> Option<&[u8]> {
+        self.build_id.as_ref().map(|id| &id[..])
+    }
+}
+
+/// The error returned by `Backtrace::from_raw_bytes` for malformed input.
+///
+/// # Required features
+///
+/// This function requires the `std` feature of the `backtrace` crate to be
+/// enabled, and the `std` feature is enabled by default.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawBytesError {
+    kind: ErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ErrorKind {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    BadModuleIndex,
+}
+
+impl fmt::Display for RawBytesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ErrorKind::BadMagic => f.write_str("not a raw backtrace"),
+            ErrorKind::UnsupportedVersion(v) => {
+                write!(f, "unsupported raw backtrace version {}", v)
+            }
+            ErrorKind::Truncated => f.write_str("raw backtrace is truncated"),
+            ErrorKind::BadModuleIndex => f.write_str("raw backtrace has an invalid module index"),
+        }
+    }
+}
+
+impl std::error::Error for RawBytesError {}
+
+pub(crate) fn error(kind: ErrorKind) -> RawBytesError {
+    RawBytesError { kind }
+}
+
+/// A frame in the encoding: its instruction pointer and the index of its
+/// module, if any.
+pub(crate) type RawFrame = (usize, Option<usize>);
+
+pub(crate) fn encode(frames: &[RawFrame], modules: &[RawModule]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.push(VERSION);
+    encode_modules(&mut out, modules);
+
+    out.extend_from_slice(&(frames.len() as u32).to_le_bytes());
+    for (ip, module) in frames {
+        out.extend_from_slice(&(*ip as u64).to_le_bytes());
+        let index = module.map_or(NO_MODULE, |i| i as u32);
+        out.extend_from_slice(&index.to_le_bytes());
+    }
+    out
+}
+
+/// Appends the module table of the encoding to `out`.
+pub(crate) fn encode_modules(out: &mut Vec<u8>, modules: &[RawModule]) {
+    out.extend_from_slice(&(modules.len() as u32).to_le_bytes());
+    for module in modules {
+        let path = path_bytes(&module.path);
+        out.extend_from_slice(&(path.len() as u32).to_le_bytes());
+        out.extend_from_slice(&path);
+        out.extend_from_slice(&(module.base as u64).to_le_bytes());
+        let build_id = module.build_id().unwrap_or(&[]);
+        // Build-ids are at most a few dozen bytes in practice.
+        let len = build_id.len().min(0xff);
+        out.push(len as u8);
+        out.extend_from_slice(&build_id[..len]);
+    }
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> Result<(Vec<RawFrame>, Vec<RawModule>), RawBytesError> {
+    let mut r = Reader(bytes);
+    if r.take(MAGIC.len())? != MAGIC {
+        return Err(error(ErrorKind::BadMagic));
+    }
+    match r.take(1)?[0] {
+        VERSION => {}
+        v => return Err(error(ErrorKind::UnsupportedVersion(v))),
+    }
+
+    let count = r.u32()?;
+    let mut modules = Vec::new();
+    for _ in 0..count {
+        let len = r.u32()? as usize;
+        let path = path_from_bytes(r.take(len)?);
+        let base = r.u64()? as usize;
+        let len = r.take(1)?[0] as usize;
+        let build_id = match r.take(len)? {
+            [] => None,
+            id => Some(id.to_vec()),
+        };
+        modules.push(RawModule::new(path, base, build_id));
+    }
+
+    let count = r.u32()?;
+    let mut frames = Vec::new();
+    for _ in 0..count {
+        let ip = r.u64()? as usize;
+        let module = match r.u32()? {
+            NO_MODULE => None,
+            i if (i as usize) < modules.len() => Some(i as usize),
+            _ => return Err(error(ErrorKind::BadModuleIndex)),
+        };
+        frames.push((ip, module));
+    }
+    Ok((frames, modules))
+}
+
+pub(crate) struct Reader<'a>(pub(crate) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], RawBytesError> {
+        if self.0.len() < n {
+            return Err(error(ErrorKind::Truncated));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, RawBytesError> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, RawBytesError> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::prelude::*;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::ffi::OsStr;
+    use std::os::unix::prelude::*;
+    OsStr::from_bytes(bytes).into()
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    String::from_utf8_lossy(bytes).into_owned().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let modules = vec![
+            RawModule::new(
+                "/usr/lib/libfoo.so".into(),
+                0x7f00_0000,
+                Some(vec![1, 2, 3]),
+            ),
+            RawModule::new("a.out".into(), 0, None),
+        ];
+        let frames = vec![(0x7f00_1234, Some(0)), (0x4000, Some(1)), (0x10, None)];
+        let bytes = encode(&frames, &modules);
+        assert_eq!(decode(&bytes), Ok((frames, modules)));
+    }
+
+    #[test]
+    fn malformed() {
+        let bytes = encode(&[(1, None)], &[]);
+        assert_eq!(decode(b"nope"), Err(error(ErrorKind::BadMagic)));
+        assert_eq!(
+            decode(&bytes[..bytes.len() - 1]),
+            Err(error(ErrorKind::Truncated))
+        );
+        let mut bad = bytes.clone();
+        bad[4] = 2;
+        assert_eq!(decode(&bad), Err(error(ErrorKind::UnsupportedVersion(2))));
+    }
+}
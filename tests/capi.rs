@@ -0,0 +1,57 @@
+use backtrace::capi::*;
+use std::ffi::{c_void, CStr};
+use std::ptr;
+
+#[test]
+fn capture_and_resolve() {
+    let mut ips = [ptr::null_mut(); 64];
+    let len = unsafe { backtrace_rs_capture(ips.as_mut_ptr(), ips.len()) };
+    assert!(len > 1);
+    assert_eq!(unsafe { backtrace_rs_capture(ptr::null_mut(), 0) }, 0);
+
+    unsafe extern "C" fn collect(symbol: *const BacktraceRsSymbol, data: *mut c_void) {
+        let names = &mut *(data as *mut Vec<String>);
+        let name = (*symbol).name;
+        if !name.is_null() {
+            names.push(CStr::from_ptr(name).to_string_lossy().into_owned());
+        }
+    }
+    let mut names = Vec::<String>::new();
+    for ip in &ips[..len] {
+        unsafe {
+            backtrace_rs_resolve(*ip, Some(collect), &mut names as *mut _ as *mut c_void);
+        }
+    }
+    assert!(
+        names
+            .iter()
+            .any(|name| name.contains("capture_and_resolve")),
+        "{:?}",
+        names
+    );
+
+    // A missing callback is ignored.
+    unsafe { backtrace_rs_resolve(ips[0], None, ptr::null_mut()) };
+}
+
+#[test]
+#[cfg(unix)]
+fn print_to_fd() {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    assert_eq!(unsafe { backtrace_rs_print(fds[1]) }, 0);
+    unsafe { libc::close(fds[1]) };
+
+    let mut output = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = unsafe { libc::read(fds[0], buf.as_mut_ptr() as *mut _, buf.len()) };
+        if n <= 0 {
+            break;
+        }
+        output.extend_from_slice(&buf[..n as usize]);
+    }
+    unsafe { libc::close(fds[0]) };
+    let output = String::from_utf8_lossy(&output);
+    assert!(output.contains("print_to_fd"), "{}", output);
+}
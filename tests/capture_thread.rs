@@ -0,0 +1,104 @@
+use backtrace::{Backtrace, OsThreadId};
+use std::sync::mpsc;
+use std::thread;
+
+#[test]
+fn current_thread() {
+    let bt = Backtrace::capture_thread(OsThreadId::current()).unwrap();
+    assert!(!bt.frames().is_empty());
+}
+
+#[test]
+#[cfg(any(target_os = "linux", windows))]
+fn other_thread() {
+    let (tx, rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    let handle = thread::spawn(move || parked_in_a_recognizable_function(tx, done_rx));
+    let id = rx.recv().unwrap();
+
+    let bt = Backtrace::capture_thread(id).unwrap();
+    let found = bt.frames().iter().flat_map(|f| f.symbols()).any(|s| {
+        s.name()
+            .map(|n| n.to_string().contains("parked_in_a_recognizable_function"))
+            .unwrap_or(false)
+    });
+
+    done_tx.send(()).unwrap();
+    handle.join().unwrap();
+    assert!(found, "{:?}", bt);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn handler_outlives_capture() {
+    let (tx, rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    let handle = thread::spawn(move || parked_in_a_recognizable_function(tx, done_rx));
+    let id = rx.recv().unwrap();
+    Backtrace::capture_thread(id).unwrap();
+    done_tx.send(()).unwrap();
+    handle.join().unwrap();
+
+    // A signal arriving after a capture gave up on it mustn't kill the
+    // process, which `SIGPROF` would if its default action was restored.
+    assert_eq!(unsafe { libc::raise(libc::SIGPROF) }, 0);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn foreign_signals_keep_their_default_action() {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Command;
+    use std::time::Duration;
+
+    const VAR: &str = "__BACKTRACE_FOREIGN_SIGPROF";
+
+    if std::env::var(VAR).is_ok() {
+        let (tx, rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || parked_in_a_recognizable_function(tx, done_rx));
+        Backtrace::capture_thread(rx.recv().unwrap()).unwrap();
+        done_tx.send(()).unwrap();
+        handle.join().unwrap();
+
+        // Sent as if by another process, which terminates this one.
+        unsafe { libc::kill(libc::getpid(), libc::SIGPROF) };
+        thread::sleep(Duration::from_secs(10));
+        return;
+    }
+
+    let status = Command::new(std::env::current_exe().unwrap())
+        .args(&["foreign_signals_keep_their_default_action", "--exact"])
+        .env(VAR, "1")
+        .status()
+        .unwrap();
+    assert_eq!(status.signal(), Some(libc::SIGPROF), "{:?}", status);
+}
+
+#[inline(never)]
+fn parked_in_a_recognizable_function(tx: mpsc::Sender<OsThreadId>, done: mpsc::Receiver<()>) {
+    tx.send(OsThreadId::current()).unwrap();
+    done.recv().unwrap();
+}
+
+#[test]
+fn all_threads() {
+    let (tx, rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    let handle = thread::Builder::new()
+        .name("snapshot-target".to_string())
+        .spawn(move || parked_in_a_recognizable_function(tx, done_rx))
+        .unwrap();
+    let id = rx.recv().unwrap();
+
+    let threads = backtrace::capture_all_threads();
+
+    done_tx.send(()).unwrap();
+    handle.join().unwrap();
+
+    let me = &threads[&OsThreadId::current()];
+    assert!(!me.backtrace().frames().is_empty());
+    if cfg!(target_os = "linux") {
+        assert_eq!(threads[&id].name(), Some("snapshot-target"));
+    }
+}
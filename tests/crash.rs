@@ -0,0 +1,63 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use backtrace::crash::{self, CrashReport};
+use std::fs::{self, File};
+use std::os::unix::prelude::*;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+static FD: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn handler(signal: libc::c_int, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+    unsafe { crash::write_crash_report(FD.load(Ordering::SeqCst), signal, info, context) };
+}
+
+#[inline(never)]
+fn crashing_function() {
+    unsafe { libc::raise(libc::SIGUSR1) };
+}
+
+#[test]
+fn report_round_trips() {
+    crash::prepare();
+
+    let path = std::env::temp_dir().join(format!("backtrace-crash-{}", std::process::id()));
+    let file = File::create(&path).unwrap();
+    FD.store(file.as_raw_fd(), Ordering::SeqCst);
+
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_SIGINFO;
+        assert_eq!(
+            libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut()),
+            0
+        );
+    }
+    crashing_function();
+    drop(file);
+
+    let mut report = CrashReport::read_from(File::open(&path).unwrap()).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(report.signal(), libc::SIGUSR1);
+    assert!(!report.modules().is_empty());
+    assert!(!report.backtrace().frames().is_empty());
+    if cfg!(all(target_os = "linux", target_arch = "x86_64")) {
+        assert!(report.register("rip").is_some());
+    }
+
+    report.resolve();
+    let names = report
+        .backtrace()
+        .frames()
+        .iter()
+        .flat_map(|f| f.symbols())
+        .filter_map(|s| s.name())
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>();
+    assert!(
+        names.iter().any(|n| n.contains("crashing_function")),
+        "{:?}",
+        names
+    );
+}
@@ -0,0 +1,41 @@
+use backtrace::{Symbol, SymbolName};
+
+fn demangle_test_scheme(name: &str) -> Option<String> {
+    if name.starts_with("$t$") {
+        Some(name[3..].replace('$', "::"))
+    } else {
+        None
+    }
+}
+
+#[test]
+fn registered_demanglers_are_consulted() {
+    let mangled = "$t$module$function";
+    assert_eq!(SymbolName::new(mangled.as_bytes()).to_string(), mangled);
+
+    backtrace::register_demangler(|_| None);
+    backtrace::register_demangler(demangle_test_scheme);
+    backtrace::register_demangler(|_| Some("unreachable".to_string()));
+    let name = SymbolName::new(mangled.as_bytes());
+    assert_eq!(name.to_string(), "module::function");
+    assert_eq!(format!("{:?}", name), "\"module::function\"");
+    assert_eq!(name.raw(), Some(mangled));
+
+    // Names rustc-demangle knows about don't get to the registered ones.
+    let rust = "_ZN9backtrace4test17h0123456789abcdefE";
+    assert_eq!(
+        format!("{:#}", SymbolName::new(rust.as_bytes())),
+        "backtrace::test"
+    );
+
+    let symbol = Symbol::new(mangled);
+    assert_eq!(symbol.name().unwrap().to_string(), "module::function");
+    assert_eq!(symbol.raw_name(), Some(mangled));
+
+    backtrace::set_demangling_enabled(false);
+    assert_eq!(SymbolName::new(mangled.as_bytes()).to_string(), mangled);
+    backtrace::set_demangling_enabled(true);
+
+    backtrace::clear_demanglers();
+    assert_eq!(SymbolName::new(mangled.as_bytes()).to_string(), mangled);
+}
@@ -0,0 +1,24 @@
+use backtrace::{Symbol, SymbolName};
+
+const MANGLED: &str = "_ZN9backtrace4test17h0123456789abcdefE";
+
+#[test]
+fn demangling_can_be_disabled() {
+    let name = SymbolName::new(MANGLED.as_bytes());
+    assert_eq!(format!("{:#}", name), "backtrace::test");
+    assert_eq!(name.raw(), Some(MANGLED));
+    assert_eq!(Symbol::new(MANGLED).raw_name(), Some(MANGLED));
+
+    backtrace::set_demangling_enabled(false);
+    let name = SymbolName::new(MANGLED.as_bytes());
+    assert_eq!(format!("{:#}", name), MANGLED);
+    assert_eq!(format!("{:?}", name), format!("{:?}", MANGLED));
+    assert_eq!(name.raw(), Some(MANGLED));
+    let symbol = Symbol::new(MANGLED);
+    assert_eq!(symbol.name().unwrap().to_string(), MANGLED);
+    assert_eq!(symbol.raw_name(), Some(MANGLED));
+
+    backtrace::set_demangling_enabled(true);
+    let name = SymbolName::new(MANGLED.as_bytes());
+    assert_eq!(format!("{:#}", name), "backtrace::test");
+}
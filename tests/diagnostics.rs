@@ -0,0 +1,49 @@
+use backtrace::{DiagnosticKind, ModuleSource};
+use std::ffi::c_void;
+use std::path::PathBuf;
+
+fn resolve(addr: usize) {
+    backtrace::resolve(addr as *mut c_void, |_| {});
+}
+
+#[test]
+fn missing_object_files_are_reported() {
+    // Somewhere nothing is mapped, so the registered module is all there is.
+    let base = 1usize << 44;
+    let path = PathBuf::from("/nonexistent/libmissing.so");
+    backtrace::register_module(
+        base as *mut c_void,
+        0x1000,
+        ModuleSource::Path(path.clone()),
+    );
+
+    resolve(base + 0x10);
+    assert!(backtrace::take_diagnostics().is_empty());
+
+    backtrace::set_diagnostics_enabled(true);
+    resolve(base + 0x10);
+    let diagnostics = backtrace::take_diagnostics();
+    assert!(backtrace::take_diagnostics().is_empty());
+    backtrace::set_diagnostics_enabled(false);
+    assert!(backtrace::unregister_module(base as *mut c_void));
+
+    if !cfg!(all(target_os = "linux", feature = "gimli-symbolize")) {
+        return;
+    }
+    assert!(
+        diagnostics.iter().all(|d| d.module() == path),
+        "{:?}",
+        diagnostics
+    );
+    assert!(diagnostics
+        .iter()
+        .any(|d| *d.kind() == DiagnosticKind::OpenFailed(path.clone())));
+    assert_eq!(
+        diagnostics.last().map(|d| d.kind()),
+        Some(&DiagnosticKind::LoadFailed)
+    );
+    assert_eq!(
+        diagnostics[0].to_string(),
+        "/nonexistent/libmissing.so: couldn't open /nonexistent/libmissing.so"
+    );
+}
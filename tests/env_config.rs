@@ -0,0 +1,20 @@
+// This lives in its own test binary as the environment is read once per
+// process, and would otherwise change how other tests print backtraces.
+
+#[test]
+fn backtraces_follow_the_environment() {
+    std::env::set_var("BACKTRACE_STYLE", "json");
+    std::env::set_var("BACKTRACE_NO_RESOLVE", "1");
+
+    let bt = backtrace::Backtrace::new();
+    assert!(bt.frames().iter().all(|frame| frame.symbols().is_empty()));
+
+    let printed = format!("{:?}", bt);
+    assert!(printed.starts_with('['), "{}", printed);
+    assert!(printed.trim_end().ends_with(']'), "{}", printed);
+    assert!(!format!("{:#?}", bt).starts_with('['));
+
+    // The variables have been read already.
+    std::env::remove_var("BACKTRACE_STYLE");
+    assert!(format!("{:?}", bt).starts_with('['));
+}
@@ -0,0 +1,88 @@
+use backtrace::{Backtrace, BacktraceFmt, BytesOrWideString, FrameAction, PrintFmt};
+use std::fmt;
+
+#[inline(never)]
+fn outer() -> Backtrace {
+    let bt = wrapper();
+    // Keeps this from being a tail call.
+    assert!(!bt.frames().is_empty());
+    bt
+}
+
+#[inline(never)]
+fn wrapper() -> Backtrace {
+    Backtrace::new()
+}
+
+fn name_contains(symbol: &backtrace::Symbol, s: &str) -> bool {
+    match symbol.name() {
+        Some(name) => name.to_string().contains(s),
+        None => false,
+    }
+}
+
+struct KeepAll<'a>(&'a Backtrace);
+
+impl fmt::Display for KeepAll<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut print_path = |fmt: &mut fmt::Formatter<'_>, path: BytesOrWideString<'_>| {
+            fmt::Display::fmt(&path, fmt)
+        };
+        let mut filter = |_: &backtrace::Symbol| FrameAction::Keep;
+        let mut f = BacktraceFmt::new(fmt, PrintFmt::Short, &mut print_path);
+        f.set_frame_filter(&mut filter);
+        for frame in self.0.frames() {
+            f.frame().backtrace_frame(frame)?;
+        }
+        f.finish()
+    }
+}
+
+#[test]
+fn filter_skips_and_stops() {
+    let bt = outer();
+    let unfiltered = format!("{:?}", bt);
+    assert!(
+        unfiltered.contains("frame_filter::wrapper"),
+        "{}",
+        unfiltered
+    );
+    assert!(unfiltered.contains("frame_filter::outer"), "{}", unfiltered);
+    assert!(
+        unfiltered.contains("filter_skips_and_stops"),
+        "{}",
+        unfiltered
+    );
+
+    backtrace::set_frame_filter(Box::new(|symbol| {
+        if name_contains(symbol, "frame_filter::wrapper") {
+            FrameAction::Skip
+        } else if name_contains(symbol, "filter_skips_and_stops") {
+            FrameAction::StopHere
+        } else {
+            FrameAction::Keep
+        }
+    }));
+
+    let filtered = format!("{:?}", bt);
+    assert!(!filtered.contains("frame_filter::wrapper"), "{}", filtered);
+    assert!(!filtered.contains("filter_skips_and_stops"), "{}", filtered);
+    // What's left is the frames in between, which keep their numbers.
+    let lines = unfiltered.lines().collect::<Vec<_>>();
+    let start = lines
+        .iter()
+        .position(|l| l.contains("frame_filter::outer"))
+        .unwrap();
+    let stop = lines
+        .iter()
+        .position(|l| l.contains("filter_skips_and_stops"))
+        .unwrap();
+    assert_eq!(filtered.lines().collect::<Vec<_>>(), &lines[start..stop]);
+
+    // A filter for one printing is used instead.
+    let kept = KeepAll(&bt).to_string();
+    assert!(kept.contains("frame_filter::outer"), "{}", kept);
+
+    backtrace::clear_frame_filter();
+    assert_eq!(format!("{:?}", bt), unfiltered);
+}
@@ -0,0 +1,435 @@
+// These tests change global state of the crate, like the symbol cache and how
+// it's populated, which would interfere with other tests running concurrently.
+// So they live in this test binary rather than with the others, and each one
+// holds the lock of `serialize` and undoes its changes before returning.
+
+use std::ffi::c_void;
+use std::sync::{Mutex, MutexGuard};
+
+static LOCK: Mutex<()> = Mutex::new(());
+
+fn serialize() -> MutexGuard<'static, ()> {
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[inline(never)]
+fn target() {}
+
+/// Resolves `target`, returning the name and line number of its symbol.
+fn resolve_target() -> Option<(String, Option<u32>)> {
+    let mut found = None;
+    // One past the function's start, as if it was a return address.
+    backtrace::resolve((target as usize + 1) as *mut c_void, |symbol| {
+        let name = symbol.name().map(|n| n.to_string()).unwrap_or_default();
+        if name.contains("global_state::target") && found.is_none() {
+            found = Some((name, symbol.lineno()));
+        }
+    });
+    found
+}
+
+// With an on-disk index lookups don't need to load modules in the first place.
+#[test]
+#[cfg(all(target_os = "linux", not(feature = "disk-cache")))]
+fn memory_limit() {
+    let _guard = serialize();
+    let resolve_with_lines = || resolve_target().map_or(false, |(_, line)| line.is_some());
+
+    backtrace::clear_symbol_cache();
+    backtrace::set_symbol_memory_limit(Some(1 << 30));
+    assert!(resolve_with_lines());
+    assert_eq!(backtrace::symbol_cache_usage().modules(), 0);
+
+    // Modules which don't fit into the limit aren't symbolized at all.
+    backtrace::set_symbol_memory_limit(Some(1));
+    assert!(!resolve_with_lines());
+
+    backtrace::set_symbol_memory_limit(None);
+    assert!(resolve_with_lines());
+    assert_eq!(backtrace::symbol_cache_usage().modules(), 1);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(feature = "disk-cache")))]
+fn symbol_cache_capacity_and_eviction() {
+    let _guard = serialize();
+    let addr = target as usize as *mut c_void;
+    backtrace::clear_symbol_cache();
+    backtrace::set_symbol_cache_capacity(1, None);
+    assert!(resolve_target().is_some());
+    let usage = backtrace::symbol_cache_usage();
+    assert_eq!(usage.modules(), 1);
+    assert!(usage.bytes() > 0);
+
+    // The most recently used module is kept even if it's over the limit.
+    backtrace::set_symbol_cache_capacity(0, Some(0));
+    assert_eq!(backtrace::symbol_cache_usage(), usage);
+
+    assert!(backtrace::evict_symbol_cache(addr));
+    assert!(!backtrace::evict_symbol_cache(addr));
+    assert_eq!(backtrace::symbol_cache_usage().modules(), 0);
+    assert_eq!(backtrace::symbol_cache_usage().bytes(), 0);
+
+    // Evicted modules are loaded again when needed.
+    assert!(resolve_target().is_some());
+    assert_eq!(backtrace::symbol_cache_usage().modules(), 1);
+
+    // Modules can be loaded ahead of time, too.
+    backtrace::clear_symbol_cache();
+    let exe = std::env::current_exe().unwrap();
+    let missing = std::path::Path::new("/nonexistent/libmissing.so");
+    assert_eq!(backtrace::prewarm_symbol_cache(Some(&[missing])), 0);
+    assert_eq!(backtrace::symbol_cache_usage().modules(), 0);
+    assert_eq!(backtrace::prewarm_symbol_cache(Some(&[&exe])), 1);
+    assert_eq!(backtrace::symbol_cache_usage().modules(), 1);
+    assert!(resolve_target().is_some());
+    assert_eq!(backtrace::symbol_cache_usage().modules(), 1);
+
+    // The cache reports on each module, and can evict them by path or
+    // build-id.
+    let stats = backtrace::symbol_cache_stats();
+    let exe_stats = stats.iter().find(|s| s.path() == exe).unwrap();
+    assert!(exe_stats.bytes() > 0);
+    assert!(exe_stats.hits() >= 1);
+    assert!(exe_stats.misses() >= 3);
+    assert_eq!(backtrace::symbol_cache_usage().bytes(), exe_stats.bytes());
+
+    assert!(backtrace::evict_symbol_cache_module(
+        backtrace::ModuleKey::Path(&exe)
+    ));
+    assert!(!backtrace::evict_symbol_cache_module(
+        backtrace::ModuleKey::Path(&exe)
+    ));
+    let stats = backtrace::symbol_cache_stats();
+    let exe_stats = stats.iter().find(|s| s.path() == exe).unwrap();
+    assert_eq!(exe_stats.bytes(), 0);
+
+    assert!(resolve_target().is_some());
+    if let Some(id) = exe_stats.build_id() {
+        assert!(backtrace::evict_symbol_cache_module(
+            backtrace::ModuleKey::BuildId(id)
+        ));
+        assert_eq!(backtrace::symbol_cache_usage().modules(), 0);
+    }
+
+    backtrace::set_symbol_cache_capacity(4, None);
+}
+
+#[cfg(all(target_os = "linux", not(feature = "disk-cache")))]
+mod object_source {
+    use super::*;
+    use backtrace::{ObjectData, ObjectSource};
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Serves this test binary from memory, and nothing else.
+    struct Memory {
+        path: PathBuf,
+        data: Arc<[u8]>,
+        opened: Arc<AtomicUsize>,
+    }
+
+    impl ObjectSource for Memory {
+        fn open_path(&self, path: &Path) -> Option<ObjectData> {
+            if path != self.path {
+                return None;
+            }
+            self.opened.fetch_add(1, Ordering::SeqCst);
+            Some(ObjectData::from_bytes(self.data.clone()))
+        }
+    }
+
+    struct Nothing;
+
+    impl ObjectSource for Nothing {
+        fn open_path(&self, _path: &Path) -> Option<ObjectData> {
+            None
+        }
+    }
+
+    #[test]
+    fn object_source() {
+        let _guard = serialize();
+        backtrace::set_object_source(Box::new(Nothing));
+        backtrace::clear_symbol_cache();
+        assert!(resolve_target().is_none());
+
+        let path = std::env::current_exe().unwrap();
+        let opened = Arc::new(AtomicUsize::new(0));
+        backtrace::set_object_source(Box::new(Memory {
+            data: std::fs::read(&path).unwrap().into(),
+            path,
+            opened: opened.clone(),
+        }));
+        backtrace::clear_symbol_cache();
+        assert!(resolve_target().is_some());
+        assert_eq!(opened.load(Ordering::SeqCst), 1);
+
+        backtrace::set_object_source(Box::new(backtrace::FileSystemSource));
+        backtrace::clear_symbol_cache();
+        assert!(resolve_target().is_some());
+        assert_eq!(opened.load(Ordering::SeqCst), 1);
+    }
+}
+
+#[test]
+fn resolves_without_mmap() {
+    let _guard = serialize();
+    backtrace::set_object_file_mmap(false);
+    backtrace::clear_symbol_cache();
+    assert!(resolve_target().is_some());
+
+    backtrace::set_object_file_mmap(true);
+    backtrace::clear_symbol_cache();
+}
+
+#[test]
+#[cfg(all(feature = "disk-cache", target_os = "linux"))]
+fn resolves_from_disk_cache_index() {
+    let _guard = serialize();
+    let dir = std::env::temp_dir().join(format!("backtrace-disk-cache-{}", std::process::id()));
+    let previous = std::env::var_os("XDG_CACHE_HOME");
+    std::env::set_var("XDG_CACHE_HOME", &dir);
+
+    // The first lookup parses the debug information and writes the index.
+    backtrace::clear_symbol_cache();
+    let expected = resolve_target().unwrap();
+    assert_eq!(
+        std::fs::read_dir(dir.join("backtrace-rs")).unwrap().count(),
+        1
+    );
+
+    // Later lookups get the same result from the index alone.
+    backtrace::clear_symbol_cache();
+    assert_eq!(resolve_target().unwrap(), expected);
+    assert_eq!(backtrace::symbol_cache_usage().modules(), 0);
+
+    match previous {
+        Some(previous) => std::env::set_var("XDG_CACHE_HOME", previous),
+        None => std::env::remove_var("XDG_CACHE_HOME"),
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn resolves_with_symbol_search_path() {
+    let _guard = serialize();
+    // The directory of the test executable is where its PDB file is, and no
+    // symbol server should be needed to find it.
+    let exe = std::env::current_exe().unwrap();
+    backtrace::set_symbol_server_downloads(false);
+    backtrace::set_symbol_search_path(Some(exe.parent().unwrap().as_os_str()));
+    assert!(resolve_target().is_some());
+
+    backtrace::set_symbol_search_path(None);
+    backtrace::set_symbol_server_downloads(true);
+}
+
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+mod register_module {
+    use super::*;
+    use backtrace::{LoadedModule, ModuleSource};
+    use std::sync::Arc;
+
+    fn names_at(addr: usize) -> Vec<String> {
+        let mut names = Vec::new();
+        backtrace::resolve(addr as *mut c_void, |symbol| {
+            names.extend(symbol.name().map(|n| n.to_string()));
+        });
+        names
+    }
+
+    fn module() -> LoadedModule {
+        backtrace::modules()
+            .into_iter()
+            .find(|m| m.contains(target as usize))
+            .unwrap()
+    }
+
+    fn check(module: &LoadedModule, source: ModuleSource) {
+        let offset = target as usize - module.bias();
+        let len = module.ranges().iter().map(|r| r.end).max().unwrap() - module.bias();
+
+        // Pretend a copy of the image was mapped somewhere nothing else is. The
+        // address is one past the function's start since it's treated as a
+        // return address.
+        let base = 1usize << 44;
+        let addr = base + offset + 1;
+        assert!(names_at(addr).is_empty());
+
+        backtrace::register_module(base as *mut c_void, len, source);
+        let names = names_at(addr);
+        assert!(
+            names.iter().any(|n| n.contains("global_state::target")),
+            "{:?}",
+            names
+        );
+
+        assert!(backtrace::unregister_module(base as *mut c_void));
+        assert!(!backtrace::unregister_module(base as *mut c_void));
+        assert!(names_at(addr).is_empty());
+    }
+
+    #[test]
+    fn resolves_in_registered_modules() {
+        let _guard = serialize();
+        let module = module();
+        check(&module, ModuleSource::Path(module.path().to_path_buf()));
+        let bytes = std::fs::read(module.path()).unwrap();
+        check(&module, ModuleSource::Bytes(Arc::from(bytes.clone())));
+        check(
+            &module,
+            ModuleSource::Static(Box::leak(bytes.into_boxed_slice())),
+        );
+    }
+
+    /// Writes a zip archive with the given entries stored without compression.
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = Vec::new();
+        let mut directory = Vec::new();
+        for (name, data) in entries {
+            let offset = zip.len() as u32;
+            let mut header = Vec::new();
+            header.extend_from_slice(&0u16.to_le_bytes()); // compression
+            header.extend_from_slice(&[0; 8]); // time, date, crc
+            header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes()); // extra
+            zip.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+            zip.extend_from_slice(&[0; 4]); // version, flags
+            zip.extend_from_slice(&header);
+            zip.extend_from_slice(name.as_bytes());
+            zip.extend_from_slice(data);
+
+            directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+            directory.extend_from_slice(&[0; 6]); // versions, flags
+            directory.extend_from_slice(&header);
+            directory.extend_from_slice(&[0; 10]); // comment, disk, attributes
+            directory.extend_from_slice(&offset.to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+        }
+        let offset = zip.len() as u32;
+        zip.extend_from_slice(&directory);
+        zip.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        zip.extend_from_slice(&[0; 4]); // disks
+        zip.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        zip.extend_from_slice(&offset.to_le_bytes());
+        zip.extend_from_slice(&0u16.to_le_bytes()); // comment
+        zip
+    }
+
+    // Android loads libraries straight out of APKs, and names them like
+    // `base.apk!/lib/arm64-v8a/libfoo.so`.
+    #[test]
+    fn resolves_in_zip_archives() {
+        let _guard = serialize();
+        let module = module();
+        let bytes = std::fs::read(module.path()).unwrap();
+        let archive = zip(&[
+            ("AndroidManifest.xml", b"<manifest/>"),
+            ("lib/x86_64/libfoo.so", &bytes),
+        ]);
+        let path = std::env::temp_dir().join(format!("backtrace-test-{}.apk", std::process::id()));
+        std::fs::write(&path, archive).unwrap();
+
+        let mut name = path.clone().into_os_string();
+        name.push("!/lib/x86_64/libfoo.so");
+        check(&module, ModuleSource::Path(name.into()));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn registered_modules_take_precedence() {
+        let _guard = serialize();
+        let module = module();
+        let len = module.ranges().iter().map(|r| r.end).max().unwrap() - module.bias();
+        let addr = target as usize + 1;
+        assert!(!names_at(addr).is_empty());
+
+        // Nothing can be found in an image which isn't an object file at all,
+        // so this only resolves to nothing if it's used over the loaded
+        // module.
+        static NOT_AN_OBJECT: [u8; 16] = [0; 16];
+        let base = module.bias() as *mut c_void;
+        backtrace::register_module(base, len, ModuleSource::from(&NOT_AN_OBJECT[..]));
+        assert!(names_at(addr).is_empty());
+
+        assert!(backtrace::unregister_module(base));
+        assert!(!names_at(addr).is_empty());
+    }
+}
+
+#[test]
+fn trace_respects_max_frames() {
+    let _guard = serialize();
+    backtrace::set_max_frames(2);
+    let mut frames = 0;
+    backtrace::trace(|_| {
+        frames += 1;
+        true
+    });
+    let bt = backtrace::Backtrace::new_unresolved();
+    backtrace::set_max_frames(65536);
+
+    assert!(frames <= 2);
+    assert!(bt.frames().len() <= 2);
+    if frames == 2 {
+        assert!(bt.is_truncated());
+    }
+}
+
+#[inline(never)]
+fn traced() -> Vec<usize> {
+    let mut ips = Vec::new();
+    backtrace::trace(|frame| {
+        ips.push(frame.ip() as usize);
+        true
+    });
+    ips
+}
+
+#[test]
+fn traces_without_dbghelp() {
+    let _guard = serialize();
+    backtrace::set_dbghelp_enabled(false);
+    let ips = traced();
+    backtrace::set_dbghelp_enabled(true);
+
+    // 32-bit Windows can't walk the stack without dbghelp.
+    if cfg!(all(windows, target_pointer_width = "32")) {
+        return;
+    }
+    // The unwinder must have made it out of `trace` into its caller.
+    let start = traced as usize;
+    assert!(
+        ips.iter().any(|ip| ip.wrapping_sub(start) < 1024),
+        "{:x?}",
+        ips
+    );
+}
+
+#[test]
+#[cfg(all(windows, not(target_vendor = "uwp")))]
+fn dbghelp_preinitialize_and_cleanup() {
+    use backtrace::{
+        cleanup_dbghelp, preinitialize_dbghelp, set_dbghelp_deferred_loads,
+        set_dbghelp_undecorated_names,
+    };
+
+    let _guard = serialize();
+    set_dbghelp_deferred_loads(false);
+    set_dbghelp_undecorated_names(true);
+    assert!(preinitialize_dbghelp());
+    assert!(preinitialize_dbghelp());
+    assert!(resolve_target().is_some());
+
+    // Symbolizing initializes dbghelp again after it's been torn down.
+    cleanup_dbghelp();
+    cleanup_dbghelp();
+    set_dbghelp_deferred_loads(true);
+    assert!(resolve_target().is_some());
+}
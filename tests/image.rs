@@ -0,0 +1,80 @@
+use backtrace::image::{Image, Images, Symbolizer};
+use backtrace::Backtrace;
+
+// The test executable, read back from its file.
+struct Executable {
+    images: Vec<Image>,
+    elf: Vec<u8>,
+}
+
+impl Images for Executable {
+    fn images(&self, f: &mut dyn FnMut(Image)) {
+        self.images.iter().for_each(|image| f(*image));
+    }
+
+    fn read(&self, _image: &Image) -> Option<&[u8]> {
+        Some(&self.elf)
+    }
+}
+
+#[test]
+#[cfg_attr(not(target_os = "linux"), ignore)]
+fn matches_in_process_resolution() {
+    let exe = std::env::current_exe().unwrap();
+    let module = backtrace::modules()
+        .into_iter()
+        .find(|m| m.path() == exe)
+        .unwrap();
+    let executable = Executable {
+        images: module
+            .ranges()
+            .iter()
+            .map(|range| Image {
+                id: 0,
+                start: range.start,
+                len: range.end - range.start,
+                bias: module.bias(),
+            })
+            .collect(),
+        elf: std::fs::read(&exe).unwrap(),
+    };
+
+    let bt = Backtrace::new();
+    let mut symbolizer = Symbolizer::new(&executable);
+    let mut matched = 0;
+    for frame in bt.frames() {
+        let expected = match frame.symbols().first() {
+            Some(symbol) => (
+                symbol.name().map(|n| n.to_string()),
+                symbol.filename().map(|f| f.to_path_buf()),
+                symbol.lineno(),
+            ),
+            None => continue,
+        };
+        let mut found = Vec::new();
+        symbolizer.resolve(frame.ip(), &mut |symbol| {
+            found.push((
+                symbol.name().map(|n| n.to_string()),
+                symbol.filename().map(|f| f.to_path_buf()),
+                symbol.lineno(),
+            ));
+        });
+        if let Some(symbol) = found.first() {
+            assert_eq!(*symbol, expected);
+            matched += 1;
+        }
+    }
+    assert!(matched > 0);
+}
+
+#[test]
+fn nothing_outside_images() {
+    let executable = Executable {
+        images: Vec::new(),
+        elf: Vec::new(),
+    };
+    let mut symbolizer = Symbolizer::new(&executable);
+    let mut found = 0;
+    symbolizer.resolve(matches_in_process_resolution as *mut _, &mut |_| found += 1);
+    assert_eq!(found, 0);
+}
@@ -0,0 +1,46 @@
+use backtrace::ModuleEvent;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn listeners_hear_of_loaded_modules() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+    backtrace::add_module_listener(Box::new(move |event| {
+        let event = match event {
+            ModuleEvent::Loaded(module) => (true, module.path().to_path_buf()),
+            ModuleEvent::Unloaded(module) => (false, module.path().to_path_buf()),
+        };
+        sink.lock().unwrap().push(event);
+    }));
+
+    // The modules which are loaded already are reported right away.
+    let modules = backtrace::modules();
+    let loaded = events.lock().unwrap().drain(..).collect::<Vec<_>>();
+    assert!(loaded.iter().all(|(loaded, _)| *loaded));
+    for module in modules.iter() {
+        assert!(loaded.iter().any(|(_, path)| path == module.path()));
+    }
+
+    // Nothing changed since.
+    backtrace::poll_module_events();
+    assert!(events.lock().unwrap().is_empty());
+
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    unsafe {
+        let handle = libc::dlopen(b"libz.so.1\0".as_ptr() as *const _, libc::RTLD_NOW);
+        if handle.is_null() {
+            return;
+        }
+        let is_libz = |path: &std::path::PathBuf| path.to_string_lossy().contains("libz.so");
+        backtrace::poll_module_events();
+        let loaded = events.lock().unwrap().drain(..).collect::<Vec<_>>();
+        assert!(loaded.iter().any(|(loaded, path)| *loaded && is_libz(path)));
+
+        libc::dlclose(handle);
+        backtrace::poll_module_events();
+        let unloaded = events.lock().unwrap().drain(..).collect::<Vec<_>>();
+        assert!(unloaded
+            .iter()
+            .any(|(loaded, path)| !*loaded && is_libz(path)));
+    }
+}
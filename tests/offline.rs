@@ -0,0 +1,141 @@
+use backtrace::offline::{resolve_minidump, MinidumpModule, Module, Symbolizer};
+use backtrace::Backtrace;
+
+#[test]
+#[cfg_attr(not(target_os = "linux"), ignore)]
+fn matches_in_process_resolution() {
+    let bt = Backtrace::new_unresolved();
+    let (decoded, modules) = Backtrace::from_raw_bytes(&bt.to_raw_bytes()).unwrap();
+    assert!(!modules.is_empty());
+
+    let mut symbolizer = Symbolizer::new();
+    for module in modules.iter() {
+        symbolizer.add_module(Module::from(module));
+    }
+
+    let mut resolved = bt.clone();
+    resolved.resolve();
+    let mut matched = 0;
+    for (frame, expected) in decoded.frames().iter().zip(resolved.frames()) {
+        let expected = expected.symbols().first().and_then(|s| s.name());
+        let expected = match expected {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let mut names = Vec::new();
+        symbolizer.resolve(frame.ip() as usize, &mut |symbol| {
+            names.extend(symbol.name().map(|n| n.to_string()));
+        });
+        if names.first() == Some(&expected) {
+            matched += 1;
+        }
+    }
+    assert!(matched > 0);
+}
+
+#[test]
+#[cfg_attr(not(target_os = "linux"), ignore)]
+fn resolves_minidump_frames() {
+    // Describe the modules of this process like a minidump does, by where
+    // their images start and end.
+    let modules = backtrace::modules()
+        .iter()
+        .filter(|m| !m.path().as_os_str().is_empty())
+        .map(|m| {
+            let start = m.ranges().first().unwrap().start;
+            let end = m.ranges().last().unwrap().end;
+            MinidumpModule {
+                name: m.path().to_path_buf(),
+                base: start,
+                size: end - start,
+                debug_id: m.build_id().map(|id| id.to_vec()),
+            }
+        })
+        .collect::<Vec<_>>();
+    assert!(!modules.is_empty());
+
+    let mut bt = Backtrace::new_unresolved();
+    let addrs = bt
+        .frames()
+        .iter()
+        .map(|f| f.ip() as usize)
+        .collect::<Vec<_>>();
+    let mut names = vec![None; addrs.len()];
+    resolve_minidump(&modules, &addrs, &mut |i, symbol| {
+        if names[i].is_none() {
+            names[i] = symbol.name().map(|n| n.to_string());
+        }
+    });
+
+    bt.resolve();
+    let mut matched = 0;
+    for (frame, name) in bt.frames().iter().zip(names) {
+        let expected = frame.symbols().first().and_then(|s| s.name());
+        if let (Some(expected), Some(name)) = (expected, name) {
+            assert_eq!(expected.to_string(), name);
+            matched += 1;
+        }
+    }
+    assert!(matched > 0);
+}
+
+// Builds a module which imports one function and defines `first` and
+// `second`, each with a body of `nop; end`, and names them.
+fn wasm_module() -> Vec<u8> {
+    fn section(module: &mut Vec<u8>, id: u8, contents: &[u8]) {
+        module.push(id);
+        module.push(contents.len() as u8);
+        module.extend_from_slice(contents);
+    }
+    fn name(out: &mut Vec<u8>, name: &str) {
+        out.push(name.len() as u8);
+        out.extend_from_slice(name.as_bytes());
+    }
+
+    let mut module = b"\0asm\x01\0\0\0".to_vec();
+    // One type, `() -> ()`.
+    section(&mut module, 1, &[1, 0x60, 0, 0]);
+    let mut imports = vec![1];
+    name(&mut imports, "env");
+    name(&mut imports, "imported");
+    imports.extend_from_slice(&[0, 0]);
+    section(&mut module, 2, &imports);
+    section(&mut module, 3, &[2, 0, 0]);
+    section(&mut module, 10, &[2, 3, 0, 0x01, 0x0b, 3, 0, 0x01, 0x0b]);
+
+    let mut names = Vec::new();
+    name(&mut names, "name");
+    let mut functions = vec![3];
+    for (index, function) in ["imported", "first", "second"].iter().enumerate() {
+        functions.push(index as u8);
+        name(&mut functions, function);
+    }
+    names.push(1);
+    names.push(functions.len() as u8);
+    names.extend_from_slice(&functions);
+    section(&mut module, 0, &names);
+    module
+}
+
+#[test]
+fn resolves_wasm_function_names() {
+    const BASE: usize = 0x1000;
+    let module = wasm_module();
+    // The bodies are at the end of the code section, each 3 bytes long.
+    let code_end = module.iter().rposition(|b| *b == 0x0b).unwrap() + 1;
+    let second = code_end - 2;
+    let first = second - 4;
+
+    let mut symbolizer = Symbolizer::new();
+    symbolizer.add_module(Module::from_bytes(module, BASE));
+    let mut resolve = |offset: usize| {
+        let mut names = Vec::new();
+        symbolizer.resolve(BASE + offset, &mut |symbol| {
+            names.extend(symbol.name().map(|n| n.to_string()));
+        });
+        names
+    };
+    assert_eq!(resolve(first), ["first"]);
+    assert_eq!(resolve(second), ["second"]);
+    assert!(resolve(0).is_empty());
+}
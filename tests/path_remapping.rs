@@ -0,0 +1,68 @@
+use backtrace::{BacktraceFmt, BytesOrWideString, PrintFmt};
+use std::fmt;
+
+struct Files(&'static [&'static str]);
+
+impl fmt::Display for Files {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut print_path = |fmt: &mut fmt::Formatter<'_>, path: BytesOrWideString<'_>| {
+            fmt::Display::fmt(&path, fmt)
+        };
+        let mut f = BacktraceFmt::new(fmt, PrintFmt::Short, &mut print_path);
+        for file in self.0 {
+            let file = BytesOrWideString::Bytes(file.as_bytes());
+            f.frame()
+                .print_raw(1 as *mut _, None, Some(file), Some(1))?;
+        }
+        f.finish()
+    }
+}
+
+fn printed_files(files: &'static [&'static str]) -> Vec<String> {
+    Files(files)
+        .to_string()
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| line.starts_with("at "))
+        .map(|line| line[3..].trim_end_matches(":1").to_string())
+        .collect()
+}
+
+#[test]
+fn filenames_are_remapped() {
+    let files = &[
+        "/home/ci/build/src/main.rs",
+        "/rustc/abc/library/std/src/rt.rs",
+        "/rustc/abcd/library/std/src/rt.rs",
+        "/home/ci",
+    ];
+    assert_eq!(printed_files(files), files);
+
+    backtrace::add_path_remapping("/home/ci/build", "");
+    backtrace::add_path_remapping("/rustc/abc", "/home/me/rust");
+    assert_eq!(
+        printed_files(files),
+        [
+            "src/main.rs",
+            "/home/me/rust/library/std/src/rt.rs",
+            // Only whole components are matched.
+            "/rustc/abcd/library/std/src/rt.rs",
+            "/home/ci",
+        ]
+    );
+
+    // The rule added last wins.
+    backtrace::add_path_remapping("/home/ci", "/checkout");
+    assert_eq!(
+        printed_files(files),
+        [
+            "/checkout/build/src/main.rs",
+            "/home/me/rust/library/std/src/rt.rs",
+            "/rustc/abcd/library/std/src/rt.rs",
+            "/checkout",
+        ]
+    );
+
+    backtrace::clear_path_remappings();
+    assert_eq!(printed_files(files), files);
+}
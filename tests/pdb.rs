@@ -0,0 +1,224 @@
+// Symbolizes addresses from a small PDB file built here, which works the same
+// on every platform.
+
+use backtrace::offline::{Module, Symbolizer};
+
+const BASE: usize = 0x1000_0000;
+const BLOCK_SIZE: usize = 512;
+
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u16(&mut self, value: u16) -> &mut Writer {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u32(&mut self, value: u32) -> &mut Writer {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> &mut Writer {
+        self.0.extend_from_slice(bytes);
+        self
+    }
+
+    fn align(&mut self) -> &mut Writer {
+        while self.0.len() % 4 != 0 {
+            self.0.push(0);
+        }
+        self
+    }
+
+    fn record(&mut self, kind: u16, data: &[u8]) -> &mut Writer {
+        self.u16(data.len() as u16 + 2).u16(kind).bytes(data)
+    }
+}
+
+fn proc(name: &str, offset: u32, len: u32) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.u32(0).u32(0).u32(0).u32(len).u32(0).u32(len).u32(0);
+    w.u32(offset)
+        .u16(1)
+        .bytes(&[0])
+        .bytes(name.as_bytes())
+        .bytes(&[0]);
+    w.0
+}
+
+fn public(name: &str, offset: u32) -> Vec<u8> {
+    let mut w = Writer::default();
+    w.u32(2)
+        .u32(offset)
+        .u16(1)
+        .bytes(name.as_bytes())
+        .bytes(&[0]);
+    w.0
+}
+
+/// Writes an MSF file with the given streams, putting the blocks of each
+/// stream in reverse order so that they aren't consecutive.
+fn msf(streams: &[Vec<u8>]) -> Vec<u8> {
+    let mut next = 5;
+    let mut blocks = Vec::new();
+    for stream in streams {
+        let count = (stream.len() + BLOCK_SIZE - 1) / BLOCK_SIZE;
+        blocks.push((next..next + count).rev().collect::<Vec<_>>());
+        next += count;
+    }
+
+    let mut directory = Writer::default();
+    directory.u32(streams.len() as u32);
+    for stream in streams {
+        directory.u32(stream.len() as u32);
+    }
+    for blocks in &blocks {
+        for block in blocks {
+            directory.u32(*block as u32);
+        }
+    }
+    assert!(directory.0.len() <= BLOCK_SIZE);
+
+    let mut file = vec![0; next * BLOCK_SIZE];
+    let mut header = Writer::default();
+    header.bytes(b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0");
+    header.u32(BLOCK_SIZE as u32).u32(1).u32(next as u32);
+    header.u32(directory.0.len() as u32).u32(0).u32(3);
+    file[..header.0.len()].copy_from_slice(&header.0);
+    file[3 * BLOCK_SIZE..][..4].copy_from_slice(&4u32.to_le_bytes());
+    file[4 * BLOCK_SIZE..][..directory.0.len()].copy_from_slice(&directory.0);
+    for (stream, blocks) in streams.iter().zip(&blocks) {
+        for (chunk, block) in stream.chunks(BLOCK_SIZE).zip(blocks) {
+            file[block * BLOCK_SIZE..][..chunk.len()].copy_from_slice(chunk);
+        }
+    }
+    file
+}
+
+/// Builds a PDB for an image with a `.text` section at 0x1000, where `foo` is
+/// at 0x1010 with line information, `bar` at 0x1040 without, and the public
+/// symbol `baz` at 0x1080.
+fn pdb() -> Vec<u8> {
+    let mut info = Writer::default();
+    info.u32(20000404).u32(0).u32(1).bytes(&[7; 16]);
+    info.u32(7).bytes(b"/names\0");
+    info.u32(1).u32(1).u32(1).u32(1).u32(0).u32(0).u32(8);
+
+    let mut names = Writer::default();
+    names
+        .u32(0xeffe_effe)
+        .u32(1)
+        .u32(12)
+        .bytes(b"\0src/lib.rs\0");
+
+    let mut module = Writer::default();
+    module.u32(4);
+    module.record(0x1110, &proc("foo", 0x10, 0x20));
+    module.record(0x0006, &[]);
+    module.record(0x110f, &proc("bar", 0x40, 0x10));
+    module.record(0x0006, &[]);
+    let symbols = module.0.len();
+    module.u32(0xf4).u32(8).u32(1).u16(0).u16(0);
+    module.u32(0xf2).u32(12 + 12 + 2 * 8 + 2 * 4);
+    module.u32(0x10).u16(1).u16(1).u32(0x20);
+    module.u32(0).u32(2).u32(12 + 2 * 8 + 2 * 4);
+    module.u32(0).u32(10 | 0x8000_0000).u32(8).u32(12);
+    module.u16(5).u16(0).u16(9).u16(0);
+    let lines = module.0.len() - symbols;
+
+    let mut dbi = Writer::default();
+    dbi.u32(0xffff_ffff).u32(19990903).u32(1);
+    dbi.u16(0xffff).u16(0).u16(0xffff).u16(0).u16(7).u16(0);
+    let mut modules = Writer::default();
+    modules.u32(0).bytes(&[0; 28]).u16(0).u16(6);
+    modules.u32(symbols as u32).u32(0).u32(lines as u32);
+    modules.u16(0).u16(0).u32(0).u32(0).u32(0);
+    modules.bytes(b"lib.obj\0lib.obj\0").align();
+    dbi.u32(modules.0.len() as u32)
+        .u32(0)
+        .u32(0)
+        .u32(0)
+        .u32(0)
+        .u32(0);
+    dbi.u32(11 * 2).u32(0).u16(0).u16(0x8664).u32(0);
+    dbi.bytes(&modules.0);
+    for i in 0..11 {
+        dbi.u16(if i == 5 { 5 } else { 0xffff });
+    }
+
+    let mut sections = Writer::default();
+    sections.bytes(b".text\0\0\0").u32(0x1000).u32(0x1000);
+    sections.bytes(&[0; 24]);
+
+    let mut records = Writer::default();
+    records.record(0x110e, &public("foo", 0x10));
+    records.record(0x110e, &public("baz", 0x80));
+
+    msf(&[
+        Vec::new(),
+        info.0,
+        Vec::new(),
+        dbi.0,
+        Vec::new(),
+        sections.0,
+        module.0,
+        records.0,
+        names.0,
+    ])
+}
+
+fn resolve(
+    symbolizer: &mut Symbolizer,
+    rva: usize,
+) -> Vec<(String, Option<String>, Option<u32>, Option<u32>)> {
+    let mut symbols = Vec::new();
+    // Addresses are taken to be return addresses, so point one past them.
+    symbolizer.resolve(BASE + rva + 1, &mut |symbol| {
+        symbols.push((
+            symbol.name().unwrap().to_string(),
+            symbol.filename().map(|f| f.display().to_string()),
+            symbol.lineno(),
+            symbol.colno(),
+        ));
+    });
+    symbols
+}
+
+#[test]
+fn resolves_from_pdb() {
+    let mut symbolizer = Symbolizer::new();
+    symbolizer.add_module(Module::from_bytes(pdb(), BASE));
+
+    let lib = Some("src/lib.rs".to_string());
+    assert_eq!(
+        resolve(&mut symbolizer, 0x1014),
+        [("foo".to_string(), lib.clone(), Some(10), Some(5))]
+    );
+    assert_eq!(
+        resolve(&mut symbolizer, 0x102f),
+        [("foo".to_string(), lib, Some(12), Some(9))]
+    );
+    assert_eq!(
+        resolve(&mut symbolizer, 0x1044),
+        [("bar".to_string(), None, None, None)]
+    );
+    assert_eq!(
+        resolve(&mut symbolizer, 0x1090),
+        [("baz".to_string(), None, None, None)]
+    );
+    assert!(resolve(&mut symbolizer, 0x3000).is_empty());
+}
+
+#[test]
+fn resolves_from_pdb_path() {
+    let path = std::env::temp_dir().join(format!("backtrace-test-{}.pdb", std::process::id()));
+    std::fs::write(&path, pdb()).unwrap();
+    let mut symbolizer = Symbolizer::new();
+    symbolizer.add_module(Module::from_path(&path, BASE));
+    let symbols = resolve(&mut symbolizer, 0x1014);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(symbols[0].0, "foo");
+    assert_eq!(symbols[0].2, Some(10));
+}
@@ -0,0 +1,88 @@
+#![cfg(all(any(target_os = "linux", target_os = "android"), not(miri)))]
+
+use backtrace::profiling::Sampler;
+use backtrace::Backtrace;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Only one sampler can run at a time.
+static SAMPLER: Mutex<()> = Mutex::new(());
+
+#[inline(never)]
+fn spin(time: Duration) -> u64 {
+    let start = Instant::now();
+    let mut x = 0u64;
+    while start.elapsed() < time {
+        for i in 0..1000 {
+            x = x.wrapping_mul(31).wrapping_add(i);
+        }
+    }
+    x
+}
+
+fn samples_of_spin(samples: &[backtrace::profiling::Sample]) -> bool {
+    samples.iter().any(|sample| {
+        sample.backtrace().frames().iter().any(|frame| {
+            frame.symbols().iter().any(|symbol| {
+                symbol
+                    .name()
+                    .map_or(false, |name| name.to_string().contains("spin"))
+            })
+        })
+    })
+}
+
+#[test]
+fn sampling() {
+    let _guard = SAMPLER.lock().unwrap();
+    let sampler = Sampler::start(997).unwrap();
+    assert!(Sampler::start(997).is_err());
+    spin(Duration::from_millis(200));
+    let samples = sampler.drain_resolved();
+    assert!(!samples.is_empty());
+    assert!(samples_of_spin(&samples), "{:?}", samples);
+    drop(sampler);
+
+    let (tx, rx) = mpsc::channel();
+    let sampler = Sampler::start_with_handler(997, Duration::from_millis(50), move |samples| {
+        tx.send(samples).unwrap();
+    })
+    .unwrap();
+    spin(Duration::from_millis(200));
+    drop(sampler);
+    let samples = rx.iter().flatten().collect::<Vec<_>>();
+    assert!(samples_of_spin(&samples));
+
+    assert!(Sampler::start(0).is_err());
+}
+
+#[test]
+fn capturing_threads_while_sampling() {
+    let _guard = SAMPLER.lock().unwrap();
+    let (tx, rx) = mpsc::channel();
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    let handle = thread::spawn(move || {
+        tx.send(backtrace::OsThreadId::current()).unwrap();
+        while done_rx.try_recv().is_err() {
+            spin(Duration::from_millis(1));
+        }
+    });
+    let id = rx.recv().unwrap();
+
+    let sampler = Sampler::start(997).unwrap();
+    for _ in 0..10 {
+        let bt = Backtrace::capture_thread(id).expect("thread not captured while sampling");
+        assert!(!bt.frames().is_empty());
+    }
+    spin(Duration::from_millis(100));
+    assert!(!sampler.drain().is_empty());
+    drop(sampler);
+
+    // Capturing still works once the sampler stopped, and signals of its
+    // timer arriving late don't terminate the process.
+    assert!(Backtrace::capture_thread(id).is_some());
+    spin(Duration::from_millis(50));
+    done_tx.send(()).unwrap();
+    handle.join().unwrap();
+}
@@ -0,0 +1,103 @@
+use backtrace::reentrant;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::ffi::c_void;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+struct Profiler;
+
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+static CAPTURED: AtomicUsize = AtomicUsize::new(0);
+static SKIPPED: AtomicUsize = AtomicUsize::new(0);
+static CAPTURING_BACKTRACES: AtomicBool = AtomicBool::new(false);
+static BACKTRACES: AtomicUsize = AtomicUsize::new(0);
+static RESOLVING_UNGUARDED: AtomicBool = AtomicBool::new(false);
+
+unsafe impl GlobalAlloc for Profiler {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if CAPTURING.load(Ordering::SeqCst) {
+            let mut ips = [ptr::null_mut(); 32];
+            match reentrant::capture(&mut ips) {
+                Some(len) if len > 0 => CAPTURED.fetch_add(1, Ordering::SeqCst),
+                _ => SKIPPED.fetch_add(1, Ordering::SeqCst),
+            };
+        }
+        if CAPTURING_BACKTRACES.load(Ordering::SeqCst) {
+            if let Some(_guard) = reentrant::enter() {
+                let bt = backtrace::Backtrace::new();
+                assert!(!bt.frames().is_empty());
+                BACKTRACES.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        if RESOLVING_UNGUARDED.load(Ordering::SeqCst) {
+            // Allocations of the symbolizer come back here while it resolves,
+            // and must not find anything then.
+            backtrace::resolve(target as *const () as *mut c_void, |_| {});
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Profiler = Profiler;
+
+#[inline(never)]
+fn target() -> Vec<u8> {
+    vec![1; 16]
+}
+
+fn names(f: impl FnOnce(&mut dyn FnMut(&backtrace::Symbol))) -> Vec<String> {
+    let mut names = Vec::new();
+    f(&mut |symbol| names.extend(symbol.name().map(|name| format!("{:#}", name))));
+    names
+}
+
+#[test]
+fn allocator_hooks_can_capture_backtraces() {
+    let addr = (target as *const () as usize + 1) as *mut c_void;
+
+    CAPTURING.store(true, Ordering::SeqCst);
+    drop(target());
+    assert!(CAPTURED.load(Ordering::SeqCst) > 0);
+
+    // Allocating while resolving captures nothing.
+    let skipped = SKIPPED.load(Ordering::SeqCst);
+    let captured = CAPTURED.load(Ordering::SeqCst);
+    backtrace::resolve(addr, |_| drop(target()));
+    assert!(SKIPPED.load(Ordering::SeqCst) > skipped);
+    CAPTURING.store(false, Ordering::SeqCst);
+    assert!(CAPTURED.load(Ordering::SeqCst) >= captured);
+
+    // Backtraces captured and resolved in the hook within `enter`.
+    CAPTURING_BACKTRACES.store(true, Ordering::SeqCst);
+    drop(target());
+    CAPTURING_BACKTRACES.store(false, Ordering::SeqCst);
+    assert!(BACKTRACES.load(Ordering::SeqCst) > 0);
+
+    // Resolving from the hook without `enter` doesn't break resolving.
+    RESOLVING_UNGUARDED.store(true, Ordering::SeqCst);
+    let found = names(|cb| backtrace::resolve(addr, cb));
+    RESOLVING_UNGUARDED.store(false, Ordering::SeqCst);
+    assert!(
+        found.iter().any(|name| name.ends_with("target")),
+        "{:?}",
+        found
+    );
+
+    #[cfg(unix)]
+    {
+        // Nothing is resolved before the debug information is loaded.
+        assert!(!reentrant::resolve(addr, |_| panic!()));
+        assert!(reentrant::prepare());
+        let found = names(|cb| assert!(reentrant::resolve(addr, cb)));
+        assert!(
+            found.iter().any(|name| name.ends_with("target")),
+            "{:?}",
+            found
+        );
+    }
+}
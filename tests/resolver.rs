@@ -0,0 +1,43 @@
+use backtrace::Symbol;
+use std::ffi::c_void;
+use std::path::Path;
+
+#[test]
+fn hooks_resolve_their_ranges() {
+    // Nothing is ever mapped this low, so the symbolizer itself won't find
+    // anything here.
+    backtrace::register_resolver(
+        0x1000..0x2000,
+        Box::new(|addr, cb| {
+            let sym = Symbol::new(format!("generated_{:x}", addr as usize))
+                .with_addr(0x1000 as *mut c_void)
+                .with_filename("script.lua")
+                .with_lineno(3)
+                .with_colno(7);
+            cb(&sym);
+        }),
+    );
+    backtrace::register_resolver(0x1800..0x1900, Box::new(|_, cb| cb(&Symbol::new("inner"))));
+
+    let mut found = Vec::new();
+    backtrace::resolve(0x1011 as *mut c_void, |sym| {
+        found.push(sym.name().unwrap().to_string());
+        assert_eq!(sym.addr(), Some(0x1000 as *mut c_void));
+        assert_eq!(sym.filename(), Some(Path::new("script.lua")));
+        assert_eq!(sym.lineno(), Some(3));
+        assert_eq!(sym.colno(), Some(7));
+    });
+    // Like for other addresses, the one before the return address is looked
+    // up.
+    assert_eq!(found, ["generated_1010"]);
+
+    let mut found = Vec::new();
+    backtrace::resolve(0x1811 as *mut c_void, |sym| {
+        found.push(sym.name().unwrap().to_string());
+    });
+    assert_eq!(found, ["inner"]);
+
+    let mut called = false;
+    backtrace::resolve(0x3000 as *mut c_void, |_| called = true);
+    assert!(!called);
+}
@@ -0,0 +1,99 @@
+#![cfg(unix)]
+
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::prelude::*;
+
+#[test]
+fn prints_to_fd() {
+    if !backtrace::signal_safe::prepare() {
+        return;
+    }
+    assert!(backtrace::signal_safe::is_prepared());
+
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    let mut read = unsafe { File::from_raw_fd(fds[0]) };
+    let write = unsafe { File::from_raw_fd(fds[1]) };
+
+    unsafe { backtrace::signal_safe::trace_and_print_signal_safe(write.as_raw_fd()) };
+    drop(write);
+
+    let mut output = String::new();
+    read.read_to_string(&mut output).unwrap();
+    assert!(output.contains("prints_to_fd"), "{}", output);
+}
+
+#[test]
+fn fd_writer_prints_frames() {
+    use std::fmt::Write;
+
+    backtrace::signal_safe::prepare();
+
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    let mut read = unsafe { File::from_raw_fd(fds[0]) };
+    let write = unsafe { File::from_raw_fd(fds[1]) };
+
+    let mut out = backtrace::signal_safe::FdWriter::new(write.as_raw_fd());
+    writeln!(out, "header {}", 1).unwrap();
+    // More than fits into the buffer at once.
+    out.write_bytes(&[b'x'; 1000]);
+    out.write_bytes(b"\n");
+    let mut index = 0;
+    unsafe {
+        backtrace::trace_unsynchronized(|frame| {
+            out.print_frame(index, frame.ip()).unwrap();
+            index += 1;
+            true
+        });
+    }
+    drop(out);
+    drop(write);
+
+    let mut output = String::new();
+    read.read_to_string(&mut output).unwrap();
+    let mut lines = output.lines();
+    assert_eq!(lines.next(), Some("header 1"));
+    assert_eq!(lines.next(), Some(&*"x".repeat(1000)));
+    assert!(lines.next().unwrap().starts_with("   0: 0x"), "{}", output);
+    if backtrace::signal_safe::is_prepared() {
+        assert!(output.contains("fd_writer_prints_frames"), "{}", output);
+    }
+}
+
+// Has a name which rustc-demangle can't demangle, so that printing it would
+// reach registered demanglers.
+#[no_mangle]
+#[inline(never)]
+pub extern "C" fn signal_safe_unmangled_frame(fd: libc::c_int) {
+    unsafe { backtrace::signal_safe::trace_and_print_signal_safe(fd) };
+}
+
+#[test]
+fn fd_writer_skips_registered_demanglers() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static CALLED: AtomicBool = AtomicBool::new(false);
+
+    if !backtrace::signal_safe::prepare() {
+        return;
+    }
+    backtrace::register_demangler(|_| {
+        CALLED.store(true, Ordering::SeqCst);
+        None
+    });
+
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    let mut read = unsafe { File::from_raw_fd(fds[0]) };
+    let write = unsafe { File::from_raw_fd(fds[1]) };
+
+    signal_safe_unmangled_frame(write.as_raw_fd());
+    drop(write);
+
+    let mut output = String::new();
+    read.read_to_string(&mut output).unwrap();
+    assert!(output.contains("signal_safe_unmangled_frame"), "{}", output);
+    assert!(!CALLED.load(Ordering::SeqCst));
+}
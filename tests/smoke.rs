@@ -321,3 +321,919 @@ fn sp_smoke_test() {
         }
     }
 }
+
+#[test]
+fn cfa_smoke_test() {
+    backtrace::trace(|frame| {
+        let sp = frame.sp() as usize;
+        if let Some(cfa) = frame.cfa() {
+            // The CFA is the caller's stack pointer, and the stack grows down.
+            assert!(sp == 0 || cfa as usize >= sp);
+        }
+        true
+    });
+}
+
+#[test]
+#[cfg(any(
+    all(target_os = "linux", not(target_env = "uclibc")),
+    target_os = "illumos",
+    target_os = "netbsd",
+    target_os = "solaris",
+))]
+fn modules_contain_code() {
+    let modules = backtrace::modules();
+    let addr = modules_contain_code as usize;
+    let module = modules.iter().find(|m| m.contains(addr)).unwrap();
+    assert!(module.ranges().iter().all(|r| r.start < r.end));
+    assert!(module.bias() <= addr);
+
+    // This test is part of the executable rather than a library.
+    let exe = std::env::current_exe().unwrap();
+    assert_eq!(module.path().file_name(), exe.file_name());
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn symbols_know_their_module() {
+    let bt = backtrace::Backtrace::new();
+    let ip = bt.frames()[0].ip();
+    let module = backtrace::modules()
+        .into_iter()
+        .find(|m| m.contains(ip as usize))
+        .unwrap();
+    let exe = std::env::current_exe().unwrap();
+
+    let mut found = false;
+    backtrace::resolve(ip, |symbol| {
+        found = true;
+        assert_eq!(symbol.module_path(), Some(&*exe));
+        assert_eq!(symbol.module_base(), Some(module.bias() as *mut _));
+    });
+    assert!(found);
+    let symbol = &bt.frames()[0].symbols()[0];
+    assert_eq!(symbol.module_path(), Some(&*exe));
+    assert_eq!(symbol.module_base(), Some(module.bias() as *mut _));
+
+    let mut called = false;
+    backtrace::resolve_many(&[ip], |i, symbol| {
+        called = true;
+        assert_eq!(i, 0);
+        assert_eq!(symbol.module_path(), Some(&*exe));
+    });
+    assert!(called);
+
+    let custom = backtrace::Symbol::new("custom");
+    assert_eq!(custom.module_path(), None);
+    assert_eq!(custom.module_base(), None);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn symbols_know_their_function_start() {
+    // Addresses are taken to be return addresses, so one past the start of a
+    // function resolves to the function.
+    let start = symbols_know_their_function_start as *const () as usize;
+    let mut found = false;
+    backtrace::resolve((start + 1) as *mut _, |symbol| {
+        found = true;
+        let name = symbol.name().unwrap().to_string();
+        assert!(
+            name.contains("symbols_know_their_function_start"),
+            "{}",
+            name
+        );
+        assert_eq!(symbol.function_start(), Some(start as *mut _));
+        assert_eq!(symbol.offset_in_function(), Some(1));
+    });
+    assert!(found);
+
+    let bt = backtrace::Backtrace::new();
+    let frame = &bt.frames()[0];
+    let symbol = &frame.symbols()[0];
+    let start = symbol.function_start().unwrap() as usize;
+    assert!(start < frame.ip() as usize);
+    assert_eq!(
+        symbol.offset_in_function(),
+        Some(frame.ip() as usize - start)
+    );
+
+    let custom = backtrace::Symbol::new("custom");
+    assert_eq!(custom.function_start(), None);
+    assert_eq!(custom.offset_in_function(), None);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn symbols_know_their_size_and_language() {
+    use backtrace::SymbolLanguage;
+
+    let start = symbols_know_their_size_and_language as *const () as usize;
+    let mut found = false;
+    backtrace::resolve((start + 1) as *mut _, |symbol| {
+        found = true;
+        assert!(symbol.function_size().unwrap() > 1);
+        assert_eq!(symbol.language(), Some(SymbolLanguage::Rust));
+    });
+    assert!(found);
+
+    let bt = backtrace::Backtrace::new();
+    let symbol = &bt.frames()[0].symbols()[0];
+    assert!(symbol.offset_in_function().unwrap() < symbol.function_size().unwrap());
+    assert_eq!(symbol.language(), Some(SymbolLanguage::Rust));
+
+    let custom = backtrace::Symbol::new("custom");
+    assert_eq!(custom.function_size(), None);
+    assert_eq!(custom.language(), None);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn process_modules_match_modules() {
+    let modules = backtrace::process_modules(std::process::id());
+    let exe = std::env::current_exe().unwrap();
+    assert_eq!(modules[0].path(), exe);
+
+    let addr = process_modules_match_modules as usize;
+    let module = modules.iter().find(|m| m.contains(addr)).unwrap();
+    let own = backtrace::modules();
+    let own = own.iter().find(|m| m.contains(addr)).unwrap();
+    assert_eq!(module.bias(), own.bias());
+    assert_eq!(module.build_id(), own.build_id());
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn remote_symbolizer_matches_resolve() {
+    let bt = backtrace::Backtrace::new_unresolved();
+    let ips = bt
+        .frames()
+        .iter()
+        .map(|f| f.ip() as usize)
+        .collect::<Vec<_>>();
+    let mut symbolizer = backtrace::remote::Symbolizer::new();
+    let mut remote = vec![None; ips.len()];
+    symbolizer.resolve_many(std::process::id(), &ips, &mut |i, symbol| {
+        if remote[i].is_none() {
+            remote[i] = symbol.name().map(|name| name.to_string());
+        }
+    });
+    assert!(remote.iter().any(|name| name.is_some()));
+    for (ip, remote) in ips.iter().zip(remote) {
+        let mut local = None;
+        backtrace::resolve(*ip as *mut _, |symbol| {
+            if local.is_none() {
+                local = symbol.name().map(|name| name.to_string());
+            }
+        });
+        assert_eq!(remote, local);
+    }
+}
+
+#[test]
+fn symbolizer_markup_lists_frames() {
+    let bt = backtrace::Backtrace::new_unresolved();
+    let output = bt.symbolizer_markup().to_string();
+    let mut lines = output.lines();
+    assert_eq!(lines.next(), Some("{{{reset}}}"));
+    let frames = lines.filter(|line| line.starts_with("{{{bt:")).count();
+    assert_eq!(frames, bt.frames().len(), "{}", output);
+    if cfg!(all(target_os = "linux", not(target_env = "uclibc")))
+        && backtrace::modules().iter().any(|m| m.build_id().is_some())
+    {
+        assert!(output.contains("{{{module:0:"), "{}", output);
+        assert!(output.contains("{{{mmap:0x"), "{}", output);
+    }
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn addr2line_pairs_functions_and_lines() {
+    let bt = backtrace::Backtrace::new();
+    let line = line!() - 1;
+
+    let output = bt.addr2line().to_string();
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len() % 2, 0, "{}", output);
+    assert!(lines[0].starts_with("_ZN5smoke"), "{}", output);
+    assert!(
+        lines[0].contains("addr2line_pairs_functions_and_lines"),
+        "{}",
+        output
+    );
+    assert!(
+        lines[1].ends_with(&format!("smoke.rs:{}", line)),
+        "{}",
+        output
+    );
+
+    let output = bt.addr2line().demangle(true).to_string();
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "smoke::addr2line_pairs_functions_and_lines");
+    let frames = bt.frames().iter().map(|f| f.symbols().len().max(1));
+    assert_eq!(lines.len(), 2 * frames.sum::<usize>());
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn breakpad_lists_modules_and_frames() {
+    let bt = backtrace::Backtrace::new();
+    let output = bt.breakpad().to_string();
+    let exe = std::env::current_exe().unwrap();
+    let exe = exe.file_name().unwrap().to_str().unwrap();
+
+    let main = output.lines().next().unwrap();
+    assert!(
+        main.starts_with(&format!("Module|{}||{}|", exe, exe)),
+        "{}",
+        output
+    );
+    assert!(main.ends_with("|1"), "{}", output);
+    let id = main.split('|').nth(4).unwrap();
+    if backtrace::modules()[0].build_id().is_some() {
+        assert_eq!(id.len(), 33, "{}", output);
+    }
+
+    let frames = output
+        .lines()
+        .filter(|line| line.starts_with("0|"))
+        .collect::<Vec<_>>();
+    assert_eq!(frames.len(), bt.frames().len(), "{}", output);
+    assert!(
+        frames
+            .iter()
+            .any(|line| line.contains("breakpad_lists_modules_and_frames")),
+        "{}",
+        output
+    );
+    for (i, line) in frames.iter().enumerate() {
+        let fields = line.split('|').collect::<Vec<_>>();
+        assert_eq!(fields.len(), 7, "{}", line);
+        assert_eq!(fields[1], i.to_string());
+        assert!(fields[6].starts_with("0x"), "{}", line);
+    }
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn compact_hides_runtime_frames() {
+    // `Option::map` puts a frame of `core` between two of this test.
+    let bt = Some(()).map(|_| backtrace::Backtrace::new()).unwrap();
+    let output = bt.compact().to_string();
+    let names = output
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("at "))
+        .collect::<Vec<_>>();
+    assert!(
+        names[0].ends_with("smoke::compact_hides_runtime_frames::{{closure}}"),
+        "{}",
+        output
+    );
+    assert_eq!(names[1], "      [... omitted 1 frame ...]", "{}", output);
+    assert!(
+        names[2].ends_with("smoke::compact_hides_runtime_frames"),
+        "{}",
+        output
+    );
+    // Printing stops at the marker the test harness runs tests under.
+    assert!(!output.contains("core::"), "{}", output);
+    assert!(!output.contains("test::"), "{}", output);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn recursion_is_collapsed() {
+    #[inline(never)]
+    fn recurse(depth: usize) -> backtrace::Backtrace {
+        if depth == 0 {
+            return backtrace::Backtrace::new();
+        }
+        let bt = recurse(depth - 1);
+        // Keep this from being a tail call.
+        assert!(bt.frames().len() > depth);
+        bt
+    }
+
+    let bt = recurse(100);
+    let output = format!("{:?}", bt);
+    let lines = output.lines().filter(|l| l.contains("recurse")).count();
+    assert!(lines < 10, "{}", output);
+    let note = output
+        .lines()
+        .find(|l| l.contains("repeated"))
+        .expect(&output);
+    assert!(note.contains("repeated 9"), "{}", output);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn module_offsets_are_printed() {
+    struct Relative(backtrace::Backtrace);
+
+    impl std::fmt::Display for Relative {
+        fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let mut print_path =
+                |fmt: &mut std::fmt::Formatter<'_>, path: backtrace::BytesOrWideString<'_>| {
+                    std::fmt::Display::fmt(&path, fmt)
+                };
+            let mut f =
+                backtrace::BacktraceFmt::new(fmt, backtrace::PrintFmt::Full, &mut print_path);
+            f.set_module_offsets(true);
+            f.frame().backtrace_frame(&self.0.frames()[0])?;
+            f.finish()
+        }
+    }
+
+    let bt = backtrace::Backtrace::new();
+    let ip = bt.frames()[0].ip() as usize;
+    let module = backtrace::modules()
+        .into_iter()
+        .find(|m| m.contains(ip))
+        .unwrap();
+    let output = Relative(bt).to_string();
+    let exe = std::env::current_exe().unwrap();
+    let prefix = format!("   0: {}+0x", exe.file_name().unwrap().to_str().unwrap());
+    assert!(output.starts_with(&prefix), "{}", output);
+    let offset = &output[prefix.len()..output.find(" - ").unwrap()];
+    let offset = usize::from_str_radix(offset, 16).unwrap();
+    assert_eq!(offset, ip - module.bias(), "{}", output);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn style_wraps_elements() {
+    struct Tagged(backtrace::Backtrace);
+
+    impl std::fmt::Display for Tagged {
+        fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let mut print_path =
+                |fmt: &mut std::fmt::Formatter<'_>, path: backtrace::BytesOrWideString<'_>| {
+                    std::fmt::Display::fmt(&path, fmt)
+                };
+            let mut style = |fmt: &mut std::fmt::Formatter<'_>,
+                             element: backtrace::PrintElement,
+                             text: &dyn std::fmt::Display| {
+                write!(fmt, "<{:?}>{}</{:?}>", element, text, element)
+            };
+            let mut f =
+                backtrace::BacktraceFmt::new(fmt, backtrace::PrintFmt::Full, &mut print_path);
+            f.set_style(&mut style);
+            f.frame().backtrace_frame(&self.0.frames()[0])?;
+            f.skip_repeated(1, 2)?;
+            f.finish()
+        }
+    }
+
+    let bt = backtrace::Backtrace::new();
+    let line = line!() - 1;
+    let ip = bt.frames()[0].ip();
+    let output = Tagged(bt).to_string();
+    let lines = output.lines().collect::<Vec<_>>();
+    assert!(
+        lines[0].starts_with(&format!(
+            "<FrameIndex>   0</FrameIndex>: <Address>{:1$?}</Address> - <Function>",
+            ip,
+            2 + 2 * std::mem::size_of::<usize>()
+        )),
+        "{}",
+        output
+    );
+    assert!(lines[0].contains("style_wraps_elements"), "{}", output);
+    assert!(lines[0].ends_with("</Function>"), "{}", output);
+    assert!(
+        lines[1].trim_start().starts_with("at <Path>") && lines[1].contains("smoke.rs</Path>"),
+        "{}",
+        output
+    );
+    assert!(
+        lines[1].ends_with(&format!(
+            ":<LineNumber>{}</LineNumber>:<ColumnNumber>14</ColumnNumber>",
+            line
+        )),
+        "{}",
+        output
+    );
+    assert_eq!(
+        lines[2],
+        "      <Note>[... frame 0 repeated 2 times ...]</Note>"
+    );
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn source_context_is_printed() {
+    struct Source(backtrace::Backtrace);
+
+    impl std::fmt::Display for Source {
+        fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let mut print_path =
+                |fmt: &mut std::fmt::Formatter<'_>, path: backtrace::BytesOrWideString<'_>| {
+                    std::fmt::Display::fmt(&path, fmt)
+                };
+            let mut f =
+                backtrace::BacktraceFmt::new(fmt, backtrace::PrintFmt::Short, &mut print_path);
+            f.set_source_context(3);
+            f.frame().backtrace_frame(&self.0.frames()[0])?;
+            f.finish()
+        }
+    }
+
+    let bt = backtrace::Backtrace::new();
+    let line = line!() - 1;
+    let output = Source(bt).to_string();
+    let lines = output.lines().collect::<Vec<_>>();
+    assert!(
+        lines[2].ends_with(&format!("{} | ", line - 1)),
+        "{}",
+        output
+    );
+    assert!(
+        lines[3].ends_with(&format!(
+            "> {} |     let bt = backtrace::Backtrace::new();",
+            line
+        )),
+        "{}",
+        output
+    );
+    assert!(lines[4].trim_start().starts_with("| "), "{}", output);
+    assert!(lines[4].ends_with('^'), "{}", output);
+    assert!(
+        lines[5].ends_with(&format!("{} |     let line = line!() - 1;", line + 1)),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn json_lists_symbols() {
+    let bt = backtrace::Backtrace::new();
+    let output = bt.json().to_string();
+    assert!(
+        output.starts_with("[{\"frame\":0,\"ip\":\"0x"),
+        "{}",
+        output
+    );
+    assert!(output.ends_with("}]\n"), "{}", output);
+    let objects = output.matches("{\"frame\":").count();
+    assert!(objects >= bt.frames().len(), "{}", output);
+    for key in &[
+        "\"module\":",
+        "\"module_offset\":",
+        "\"function\":",
+        "\"file\":",
+        "\"line\":",
+        "\"column\":",
+        "\"inline_depth\":",
+    ] {
+        assert_eq!(output.matches(key).count(), objects, "{}", output);
+    }
+    if cfg!(all(target_os = "linux", not(target_env = "uclibc"))) {
+        assert!(
+            output.contains("\"function\":\"smoke::json_lists_symbols\""),
+            "{}",
+            output
+        );
+        assert!(output.contains("\"module\":\"/"), "{}", output);
+    }
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn atos_names_the_module() {
+    let bt = backtrace::Backtrace::new();
+    let output = bt.atos().to_string();
+    let exe = std::env::current_exe().unwrap();
+    let module = format!(" (in {})", exe.file_name().unwrap().to_str().unwrap());
+    assert!(
+        output.lines().any(|line| line.contains(&module)),
+        "{}",
+        output
+    );
+}
+
+#[test]
+fn resolve_many_matches_resolve() {
+    let mut ips = Vec::new();
+    backtrace::trace(|frame| {
+        ips.push(frame.ip());
+        true
+    });
+    // Duplicates and addresses outside of any module are fine too.
+    ips.push(ips[0]);
+    ips.push(std::ptr::null_mut());
+
+    let describe = |symbol: &backtrace::Symbol| {
+        (
+            symbol.name().map(|n| n.to_string()),
+            symbol.filename().map(|f| f.to_path_buf()),
+            symbol.lineno(),
+        )
+    };
+    let mut expected = vec![Vec::new(); ips.len()];
+    for (i, ip) in ips.iter().enumerate() {
+        backtrace::resolve(*ip, |symbol| expected[i].push(describe(symbol)));
+    }
+    let mut actual = vec![Vec::new(); ips.len()];
+    backtrace::resolve_many(&ips, |i, symbol| actual[i].push(describe(symbol)));
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn inline_depths_count_down() {
+    #[inline(always)]
+    fn inlined() -> Vec<Vec<Option<u32>>> {
+        let mut depths = Vec::new();
+        backtrace::trace(|frame| {
+            let mut symbols = Vec::new();
+            backtrace::resolve_frame(frame, |symbol| symbols.push(symbol.inline_depth()));
+            depths.push(symbols);
+            true
+        });
+        depths
+    }
+    #[inline(never)]
+    fn outer() -> Vec<Vec<Option<u32>>> {
+        inlined()
+    }
+
+    let depths = outer();
+    for symbols in &depths {
+        // Symbols come innermost first and end with the physical function.
+        for (i, depth) in symbols.iter().enumerate() {
+            if let Some(depth) = depth {
+                assert_eq!(*depth as usize, symbols.len() - 1 - i);
+            }
+        }
+    }
+    if cfg!(all(target_os = "linux", debug_assertions)) {
+        assert!(depths.iter().any(|symbols| symbols.len() > 1));
+    }
+}
+
+#[test]
+fn captured_symbols_know_if_inlined() {
+    #[inline(always)]
+    fn inlined() -> backtrace::Backtrace {
+        backtrace::Backtrace::new()
+    }
+    #[inline(never)]
+    fn outer() -> backtrace::Backtrace {
+        inlined()
+    }
+
+    let bt = outer();
+    let mut any_inline = false;
+    for frame in bt.frames() {
+        let symbols = frame.symbols();
+        for (i, symbol) in symbols.iter().enumerate() {
+            if let Some(depth) = symbol.inline_depth() {
+                assert_eq!(depth as usize, symbols.len() - 1 - i);
+                assert_eq!(symbol.is_inline(), depth > 0);
+                any_inline |= symbol.is_inline();
+            }
+        }
+        if let Some(last) = symbols.last() {
+            assert!(!last.is_inline());
+        }
+    }
+    if cfg!(all(target_os = "linux", debug_assertions)) {
+        assert!(any_inline);
+    }
+}
+
+#[test]
+fn printed_backtraces_parse_back() {
+    let bt = backtrace::Backtrace::new();
+    let parsed = backtrace::parse_backtrace(&format!("{:#?}", bt));
+    // The full format also prints the frames of capturing the backtrace.
+    assert!(parsed.len() >= bt.frames().len());
+    let skipped = parsed.len() - bt.frames().len();
+    for (i, (parsed, frame)) in parsed[skipped..].iter().zip(bt.frames()).enumerate() {
+        assert_eq!(parsed.index(), skipped + i);
+        assert_eq!(parsed.ip(), Some(frame.ip() as usize));
+        assert_eq!(parsed.symbols().len(), frame.symbols().len().max(1));
+        for (parsed, symbol) in parsed.symbols().iter().zip(frame.symbols()) {
+            let name = symbol.name().map(|name| name.to_string());
+            assert_eq!(parsed.name(), name.as_deref());
+            if symbol.filename().is_some() {
+                assert_eq!(parsed.filename(), symbol.filename());
+                assert_eq!(parsed.lineno(), symbol.lineno());
+                assert_eq!(parsed.colno(), symbol.colno());
+            }
+        }
+    }
+}
+
+#[test]
+fn backtraces_diff_by_frames() {
+    #[inline(never)]
+    fn left() -> backtrace::Backtrace {
+        backtrace::Backtrace::new_unresolved()
+    }
+    #[inline(never)]
+    fn right() -> backtrace::Backtrace {
+        backtrace::Backtrace::new_unresolved()
+    }
+    #[inline(never)]
+    fn both() -> (backtrace::Backtrace, backtrace::Backtrace) {
+        (left(), right())
+    }
+
+    let (a, b) = both();
+    assert!(a.diff(&a).is_same());
+    assert_eq!(a.common_prefix(&a).len(), a.frames().len());
+
+    let diff = a.diff(&b);
+    assert!(!diff.is_same());
+    assert!(!diff.shared().is_empty());
+    assert_eq!(diff.shared().len(), a.common_prefix(&b).len());
+    let (ours, theirs) = diff.divergence();
+    assert_eq!(ours, diff.ours().len());
+    assert_eq!(theirs, diff.theirs().len());
+    assert_eq!(ours + diff.shared().len(), a.frames().len());
+    assert_eq!(theirs + diff.shared().len(), b.frames().len());
+    for (x, y) in diff.shared().iter().zip(&b.frames()[theirs..]) {
+        assert_eq!(x.ip(), y.ip());
+    }
+    assert_ne!(
+        diff.ours().last().map(|f| f.ip()),
+        diff.theirs().last().map(|f| f.ip())
+    );
+}
+
+#[test]
+fn backtraces_are_keyed_by_frames() {
+    use std::collections::HashMap;
+
+    #[inline(never)]
+    fn capture() -> backtrace::Backtrace {
+        backtrace::Backtrace::new_unresolved()
+    }
+
+    let mut counts = HashMap::new();
+    for _ in 0..3 {
+        *counts.entry(capture()).or_insert(0) += 1;
+    }
+    let other = capture();
+    *counts.entry(other.clone()).or_insert(0) += 1;
+    assert_eq!(counts.len(), 2);
+    assert_eq!(counts.values().copied().max(), Some(3));
+
+    let mut resolved = other.clone();
+    resolved.resolve();
+    assert_eq!(resolved, other);
+    assert_eq!(counts[&resolved], 1);
+
+    let mut all = counts.keys().cloned().collect::<Vec<_>>();
+    all.sort();
+    assert!(all[0] < all[1]);
+    assert_eq!(all[0].frames()[0], all[0].frames()[0].clone());
+}
+
+#[test]
+fn backtraces_record_thread_and_timestamp() {
+    let bt = backtrace::Backtrace::new_unresolved();
+    assert_eq!(bt.thread_id(), None);
+    assert_eq!(bt.timestamp(), None);
+    assert!(!format!("{:?}", bt).starts_with("captured"));
+
+    let builder = backtrace::Backtrace::builder()
+        .resolve(backtrace::ResolvePolicy::Lazy)
+        .record_thread(true)
+        .record_timestamp(true);
+    let first = builder.capture();
+    let (second, id) = std::thread::Builder::new()
+        .name("recorded".to_string())
+        .spawn(move || (builder.capture(), backtrace::OsThreadId::current()))
+        .unwrap()
+        .join()
+        .unwrap();
+
+    assert_eq!(first.thread_id(), Some(backtrace::OsThreadId::current()));
+    assert_eq!(second.thread_id(), Some(id));
+    assert_eq!(second.thread_name(), Some("recorded"));
+    assert!(first.timestamp().unwrap() <= second.timestamp().unwrap());
+
+    let printed = format!("{:?}", second);
+    let header = format!("captured on thread 'recorded' ({}) at ", id);
+    assert!(printed.starts_with(&header), "{}", printed);
+}
+
+#[test]
+fn backtraces_report_their_status() {
+    use backtrace::{Backtrace, BacktraceStatus};
+
+    let disabled = Backtrace::disabled();
+    assert_eq!(disabled.status(), BacktraceStatus::Disabled);
+    assert!(disabled.frames().is_empty());
+    assert_eq!(format!("{:?}", disabled), "disabled backtrace\n");
+
+    let bt = Backtrace::new_unresolved();
+    if bt.frames().is_empty() {
+        assert_eq!(bt.status(), BacktraceStatus::Unsupported);
+    } else {
+        assert_eq!(bt.status(), BacktraceStatus::Captured);
+    }
+}
+
+#[test]
+fn frames_can_trace_while_iterating() {
+    let frames = backtrace::frames();
+    let len = frames.len();
+    let mut nested = 0;
+    for _frame in frames {
+        // Unlike with the callback of `trace`, the lock isn't held here.
+        nested += backtrace::frames().count();
+    }
+    if len > 0 {
+        assert!(nested > len);
+    }
+}
+
+#[no_mangle]
+#[inline(never)]
+pub extern "C" fn backtrace_smoke_find_symbol_target() -> usize {
+    line!() as usize
+}
+
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+#[inline(never)]
+fn find_symbol_rust_target() -> usize {
+    line!() as usize
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn find_symbol_by_name() {
+    let (addr, path) = backtrace::find_symbol("backtrace_smoke_find_symbol_target").unwrap();
+    assert_eq!(
+        addr as usize,
+        backtrace_smoke_find_symbol_target as *const () as usize
+    );
+    assert_eq!(path, std::env::current_exe().unwrap());
+
+    let (addr, _) = backtrace::find_symbol("smoke::find_symbol_rust_target").unwrap();
+    assert_eq!(addr as usize, find_symbol_rust_target as *const () as usize);
+
+    assert!(backtrace::find_symbol("smoke::no_such_function").is_none());
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn perf_map_has_loaded_functions() {
+    let mut out = Vec::new();
+    let count = backtrace::write_perf_map(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out.lines().count(), count);
+
+    let target = find_symbol_rust_target as *const () as usize;
+    let line = out
+        .lines()
+        .find(|line| line.ends_with(" smoke::find_symbol_rust_target"))
+        .unwrap();
+    let mut parts = line.splitn(3, ' ');
+    let start = usize::from_str_radix(parts.next().unwrap(), 16).unwrap();
+    let size = usize::from_str_radix(parts.next().unwrap(), 16).unwrap();
+    assert_eq!(start, target);
+    assert!(size > 0);
+}
+
+// Since macOS 11 the system libraries are only in the dyld shared cache, and
+// have no file on disk.
+#[test]
+#[cfg(target_os = "macos")]
+fn resolves_in_dyld_shared_cache() {
+    extern "C" {
+        fn malloc(size: usize) -> *mut std::ffi::c_void;
+    }
+    let addr = (malloc as *const () as usize + 1) as *mut std::ffi::c_void;
+    let mut names = Vec::new();
+    backtrace::resolve(addr, |symbol| {
+        names.extend(symbol.name().map(|name| name.to_string()));
+    });
+    assert!(
+        names.iter().any(|name| name.contains("malloc")),
+        "{:?}",
+        names
+    );
+}
+
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+#[inline(never)]
+fn ip_adjustment_target() -> usize {
+    line!() as usize
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn ip_adjustment_looks_up_exact_addresses() {
+    use backtrace::IpAdjustment;
+
+    // The first byte of a function is only found with no adjustment, as the
+    // byte before it belongs to something else.
+    let addr = ip_adjustment_target as *const () as *mut std::ffi::c_void;
+    let names = |adjustment| {
+        let mut names = Vec::new();
+        backtrace::resolve_with_ip_adjustment(addr, adjustment, |symbol| {
+            names.extend(symbol.name().map(|name| name.to_string()));
+        });
+        names
+    };
+    let is_target = |name: &String| name.contains("ip_adjustment_target");
+    assert!(names(IpAdjustment::Never).iter().any(is_target));
+    assert!(!names(IpAdjustment::Always).iter().any(is_target));
+
+    let mut frames = Vec::new();
+    backtrace::trace(|frame| {
+        frames.push(frame.clone());
+        true
+    });
+    let return_addresses = frames
+        .iter()
+        .filter(|f| !f.ip().is_null() && f.is_signal_frame() != Some(true));
+    for frame in return_addresses {
+        assert_eq!(frame.adjusted_ip() as usize, frame.ip() as usize - 1);
+    }
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(target_env = "uclibc")))]
+fn file_entries_are_relative_to_the_compilation_dir() {
+    let mut checked = false;
+    backtrace::trace(|frame| {
+        backtrace::resolve_frame(frame, |symbol| {
+            let name = symbol.name().map(|name| name.to_string());
+            let in_test = match name {
+                Some(name) => name.contains("file_entries_are_relative"),
+                None => false,
+            };
+            if !in_test {
+                return;
+            }
+            let dir = symbol.compilation_dir().unwrap();
+            let entry = symbol.file_entry().unwrap();
+            assert_eq!(entry, std::path::Path::new("tests/smoke.rs"));
+            assert_eq!(dir.join(entry), symbol.filename().unwrap());
+            checked = true;
+        });
+        !checked
+    });
+    assert!(checked);
+}
+
+#[test]
+fn fixture_replaces_capture_and_resolution() {
+    use backtrace::{Backtrace, Fixture, Symbol};
+
+    let fixture = Fixture::new()
+        .frame(
+            0x1000,
+            vec![
+                Symbol::new("app::inlined")
+                    .with_filename("src/lib.rs")
+                    .with_lineno(3),
+                Symbol::new("app::inner")
+                    .with_filename("src/lib.rs")
+                    .with_lineno(10),
+            ],
+        )
+        .frame(0x2000, vec![Symbol::new("app::main").with_lineno(8)])
+        .frame(0x3000, Vec::new())
+        .symbols(0x4000, vec![Symbol::new("app::elsewhere")]);
+    let guard = backtrace::install_fixture(fixture);
+
+    let bt = Backtrace::new();
+    let ips = bt
+        .frames()
+        .iter()
+        .map(|f| f.ip() as usize)
+        .collect::<Vec<_>>();
+    assert_eq!(ips, [0x1000, 0x2000, 0x3000]);
+    let names = bt.frames()[0]
+        .symbols()
+        .iter()
+        .map(|s| s.name().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["app::inlined", "app::inner"]);
+    assert_eq!(bt.frames()[0].symbols()[1].lineno(), Some(10));
+    assert!(bt.frames()[2].symbols().is_empty());
+    assert_eq!(format!("{:?}", bt), format!("{:?}", Backtrace::new()));
+
+    let bt = Backtrace::builder().skip(1).max_frames(1).capture();
+    assert_eq!(bt.frames().len(), 1);
+    assert_eq!(bt.frames()[0].ip() as usize, 0x2000);
+    assert!(bt.is_truncated());
+
+    let mut names = Vec::new();
+    backtrace::resolve(0x4000 as *mut _, |s| {
+        names.push(s.name().unwrap().to_string())
+    });
+    assert_eq!(names, ["app::elsewhere"]);
+    let here = fixture_replaces_capture_and_resolution as *const () as usize + 1;
+    backtrace::resolve(here as *mut _, |_| {
+        panic!("resolved outside of the fixture")
+    });
+
+    drop(guard);
+    assert_ne!(Backtrace::new().frames()[0].ip() as usize, 0x1000);
+}
@@ -0,0 +1,88 @@
+use std::env;
+use std::process::Command;
+use std::thread;
+
+const VAR: &str = "__BACKTRACE_STACK_OVERFLOW_CHILD";
+
+fn main() {
+    // Miri does not have support for re-execing a file
+    if !cfg!(any(target_os = "linux", target_os = "android")) || cfg!(miri) {
+        println!("test result: ok");
+        return;
+    }
+
+    if env::var(VAR).is_err() {
+        parent();
+    } else {
+        child();
+    }
+}
+
+fn parent() {
+    let me = env::current_exe().unwrap();
+    let result = Command::new(&me).env(VAR, "1").output().unwrap();
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    if result.status.code() == Some(42)
+        && stderr.starts_with("stack overflow at")
+        && stderr.contains("recurse")
+    {
+        println!("test result: ok");
+        return;
+    }
+    println!("stderr:\n{}", stderr);
+    println!("code: {}", result.status);
+    panic!();
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn child() {
+    use backtrace::{signal_safe, stack_overflow};
+    use std::{mem, ptr};
+
+    extern "C" fn handler(_: libc::c_int, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+        unsafe {
+            if stack_overflow::is_stack_overflow(info) {
+                stack_overflow::print_stack_overflow(2, info, context);
+                libc::_exit(42);
+            }
+            libc::_exit(1);
+        }
+    }
+
+    #[inline(never)]
+    fn recurse(depth: usize) -> usize {
+        let buf = [depth as u8; 1024];
+        let buf = unsafe { ptr::read_volatile(&buf) };
+        if depth == usize::max_value() {
+            return 0;
+        }
+        recurse(depth + 1) + buf[depth % buf.len()] as usize
+    }
+
+    signal_safe::prepare();
+    thread::Builder::new()
+        .stack_size(256 * 1024)
+        .spawn(|| unsafe {
+            let mut altstack = vec![0u8; 64 * 1024];
+            let stack = libc::stack_t {
+                ss_sp: altstack.as_mut_ptr() as *mut libc::c_void,
+                ss_flags: 0,
+                ss_size: altstack.len(),
+            };
+            assert_eq!(libc::sigaltstack(&stack, ptr::null_mut()), 0);
+            assert!(stack_overflow::register_thread());
+
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = handler as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+            assert_eq!(libc::sigaction(libc::SIGSEGV, &action, ptr::null_mut()), 0);
+
+            recurse(0)
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn child() {}
@@ -0,0 +1,61 @@
+// Calls back into Rust through a function without unwind information, past
+// which only scanning the stack can continue a trace.
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+use backtrace::Backtrace;
+use std::sync::Mutex;
+
+std::arch::global_asm!(
+    ".text",
+    ".globl backtrace_test_no_cfi",
+    ".type backtrace_test_no_cfi, @function",
+    "backtrace_test_no_cfi:",
+    "sub rsp, 8",
+    "call rdi",
+    "add rsp, 8",
+    "ret",
+    ".size backtrace_test_no_cfi, . - backtrace_test_no_cfi",
+);
+
+extern "C" {
+    fn backtrace_test_no_cfi(cb: extern "C" fn());
+}
+
+static CAPTURED: Mutex<Option<Backtrace>> = Mutex::new(None);
+
+extern "C" fn capture() {
+    *CAPTURED.lock().unwrap() = Some(Backtrace::new());
+}
+
+#[inline(never)]
+fn calls_through_asm() -> Backtrace {
+    unsafe { backtrace_test_no_cfi(capture) };
+    CAPTURED.lock().unwrap().take().unwrap()
+}
+
+fn has_frame(bt: &Backtrace, name: &str) -> Option<bool> {
+    bt.frames()
+        .iter()
+        .find(|f| {
+            f.symbols()
+                .iter()
+                .any(|s| s.name().map_or(false, |n| n.to_string().contains(name)))
+        })
+        .map(|f| {
+            assert_eq!(f.is_scanned(), f.trust() == backtrace::FrameTrust::Scan);
+            f.is_scanned()
+        })
+}
+
+#[test]
+fn scans_past_missing_unwind_info() {
+    let bt = calls_through_asm();
+    assert_eq!(has_frame(&bt, "calls_through_asm"), None, "{:?}", bt);
+
+    backtrace::set_stack_scanning(true);
+    let bt = calls_through_asm();
+    backtrace::set_stack_scanning(false);
+    assert!(bt.frames().iter().any(|f| f.is_scanned()), "{:?}", bt);
+    assert_eq!(has_frame(&bt, "calls_through_asm"), Some(true), "{:?}", bt);
+    assert_eq!(has_frame(&bt, "capture"), Some(false), "{:?}", bt);
+}
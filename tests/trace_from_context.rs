@@ -0,0 +1,67 @@
+#[test]
+#[cfg(all(target_os = "linux", target_env = "gnu", target_arch = "x86_64"))]
+fn starts_at_saved_pc() {
+    use std::mem;
+
+    let mut ctx: libc::ucontext_t = unsafe { mem::zeroed() };
+    assert_eq!(unsafe { libc::getcontext(&mut ctx) }, 0);
+    let pc = ctx.uc_mcontext.gregs[libc::REG_RIP as usize] as usize;
+
+    let mut frames = Vec::new();
+    let mut trust = Vec::new();
+    let unwound = unsafe {
+        backtrace::trace_from_context(&ctx as *const _ as *const _, |frame| {
+            frames.push(frame.ip() as usize);
+            trust.push(frame.trust());
+            true
+        })
+    };
+
+    // The saved pc is just after the call to `getcontext`, which has returned,
+    // so the system unwinder never finds it on the stack and only yields the
+    // interrupted frame.
+    assert_eq!(unwound, cfg!(feature = "dwarf-unwind"));
+    assert_eq!(frames.first(), Some(&pc));
+    assert_eq!(trust[0], backtrace::FrameTrust::Context);
+    assert!(trust[1..].iter().all(|t| *t == backtrace::FrameTrust::Cfi));
+
+    let mut found = false;
+    backtrace::resolve(pc as *mut _, |sym| {
+        let name = sym.name().map(|n| n.to_string()).unwrap_or_default();
+        found |= name.contains("starts_at_saved_pc");
+    });
+    assert!(found);
+}
+
+#[test]
+#[cfg(all(windows, target_arch = "x86_64"))]
+fn from_exception_info_starts_at_saved_pc() {
+    use std::ffi::c_void;
+    use std::ptr;
+
+    // The x86_64 `CONTEXT`, whose `Rip` is at offset 0xf8.
+    #[repr(C, align(16))]
+    struct Context([u8; 1232]);
+
+    extern "system" {
+        fn RtlCaptureContext(context: *mut Context);
+    }
+
+    let mut context = Context([0; 1232]);
+    unsafe { RtlCaptureContext(&mut context) };
+    let mut rip = [0; 8];
+    rip.copy_from_slice(&context.0[0xf8..0x100]);
+    let pc = u64::from_le_bytes(rip) as usize;
+
+    // `EXCEPTION_POINTERS` is an exception record followed by the context.
+    let pointers: [*const c_void; 2] = [ptr::null(), &context as *const Context as *const c_void];
+    let bt = unsafe { backtrace::Backtrace::from_exception_info(pointers.as_ptr() as *const _) };
+
+    let first = &bt.frames()[0];
+    assert_eq!(first.ip() as usize, pc);
+    let found = first.symbols().iter().any(|sym| {
+        let name = sym.name().map(|n| n.to_string()).unwrap_or_default();
+        name.contains("from_exception_info_starts_at_saved_pc")
+    });
+    assert!(found);
+}
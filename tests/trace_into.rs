@@ -0,0 +1,26 @@
+use backtrace::Frame;
+use std::mem::MaybeUninit;
+use std::ptr;
+
+#[test]
+fn stops_when_full() {
+    let mut ips = [ptr::null_mut(); 3];
+    assert_eq!(backtrace::trace_ips_into(&mut ips), 3);
+    assert!(ips.iter().all(|ip| !ip.is_null()));
+
+    assert_eq!(backtrace::trace_ips_into(&mut []), 0);
+}
+
+#[test]
+fn captures_whole_stack() {
+    let mut ips = [ptr::null_mut(); 1024];
+    let ips_len = backtrace::trace_ips_into(&mut ips);
+    let mut frames: [MaybeUninit<Frame>; 1024] = unsafe { MaybeUninit::uninit().assume_init() };
+    let frames_len = backtrace::trace_into(&mut frames);
+    let frames = unsafe { &*(&frames[..frames_len] as *const _ as *const [Frame]) };
+    assert!(ips_len > 1 && ips_len < ips.len());
+    assert!(frames_len > 1 && frames_len < 1024);
+
+    // The outermost frames, which start the thread, are the same each time.
+    assert_eq!(frames.last().unwrap().ip(), ips[ips_len - 1]);
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+
+type Fields = Vec<(String, String)>;
+
+// Records the fields of each event.
+struct Collector(Arc<Mutex<Vec<Fields>>>);
+
+struct Visitor<'a>(&'a mut Fields);
+
+impl Visit for Visitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .push((field.name().to_string(), format!("{:?}", value)));
+    }
+}
+
+impl Subscriber for Collector {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+    fn new_span(&self, _: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+    fn event(&self, event: &Event<'_>) {
+        assert_eq!(event.metadata().target(), "backtrace");
+        assert_eq!(*event.metadata().level(), Level::INFO);
+        let mut fields = Vec::new();
+        event.record(&mut Visitor(&mut fields));
+        self.0.lock().unwrap().push(fields);
+    }
+    fn enter(&self, _: &Id) {}
+    fn exit(&self, _: &Id) {}
+}
+
+#[test]
+fn frames_are_emitted_as_events() {
+    #[inline(never)]
+    fn capture() -> backtrace::Backtrace {
+        backtrace::Backtrace::new()
+    }
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let bt = capture();
+    tracing::subscriber::with_default(Collector(events.clone()), || {
+        bt.emit_tracing_events(Level::INFO);
+    });
+
+    let events = events.lock().unwrap();
+    let symbols = bt.frames().iter().map(|f| f.symbols().len().max(1));
+    assert_eq!(events.len(), symbols.sum::<usize>());
+    for fields in events.iter() {
+        let names = fields.iter().map(|(name, _)| &**name).collect::<Vec<_>>();
+        assert_eq!(&names[..3], ["message", "index", "ip"]);
+    }
+    if cfg!(all(target_os = "linux", debug_assertions)) {
+        assert!(events.iter().any(|fields| fields
+            .iter()
+            .any(|(name, value)| name == "symbol" && value.contains("capture"))));
+    }
+}
@@ -0,0 +1,88 @@
+// Calls back into Rust through a function without unwind information, which
+// unwinding only gets past with a rule registered for it.
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+use backtrace::{Backtrace, UnwindRule};
+use std::ffi::c_void;
+use std::sync::Mutex;
+
+std::arch::global_asm!(
+    ".text",
+    ".globl backtrace_test_trampoline",
+    ".type backtrace_test_trampoline, @function",
+    "backtrace_test_trampoline:",
+    "sub rsp, 8",
+    ".globl backtrace_test_trampoline_body",
+    "backtrace_test_trampoline_body:",
+    "call rdi",
+    ".globl backtrace_test_trampoline_body_end",
+    "backtrace_test_trampoline_body_end:",
+    "add rsp, 8",
+    "ret",
+    ".size backtrace_test_trampoline, . - backtrace_test_trampoline",
+);
+
+extern "C" {
+    fn backtrace_test_trampoline(cb: extern "C" fn());
+    static backtrace_test_trampoline_body: u8;
+    static backtrace_test_trampoline_body_end: u8;
+}
+
+static CAPTURED: Mutex<Option<Backtrace>> = Mutex::new(None);
+
+extern "C" fn capture() {
+    *CAPTURED.lock().unwrap() = Some(Backtrace::new());
+}
+
+#[inline(never)]
+fn calls_through_trampoline() -> Backtrace {
+    unsafe { backtrace_test_trampoline(capture) };
+    CAPTURED.lock().unwrap().take().unwrap()
+}
+
+fn has_frame(bt: &Backtrace, name: &str) -> bool {
+    bt.frames().iter().any(|f| {
+        f.symbols()
+            .iter()
+            .any(|s| s.name().map_or(false, |n| n.to_string().contains(name)))
+    })
+}
+
+#[test]
+fn unwinds_through_registered_code() {
+    let bt = calls_through_trampoline();
+    assert!(has_frame(&bt, "capture"), "{:?}", bt);
+    assert!(!has_frame(&bt, "calls_through_trampoline"), "{:?}", bt);
+
+    let (start, end) = unsafe {
+        (
+            &backtrace_test_trampoline_body as *const u8 as *const c_void,
+            &backtrace_test_trampoline_body_end as *const u8 as *const c_void,
+        )
+    };
+    // Within the body the trampoline has pushed 8 bytes, above which is the
+    // return address.
+    assert!(!backtrace::register_unwind_rule(
+        start,
+        end,
+        UnwindRule::stack(16, 16)
+    ));
+    assert!(backtrace::register_unwind_rule(
+        start,
+        end,
+        UnwindRule::stack(8, 16)
+    ));
+    let bt = calls_through_trampoline();
+    assert!(has_frame(&bt, "capture"), "{:?}", bt);
+    assert!(has_frame(&bt, "calls_through_trampoline"), "{:?}", bt);
+    assert!(
+        has_frame(&bt, "unwinds_through_registered_code"),
+        "{:?}",
+        bt
+    );
+
+    assert!(backtrace::unregister_unwind_rule(start));
+    assert!(!backtrace::unregister_unwind_rule(start));
+    let bt = calls_through_trampoline();
+    assert!(!has_frame(&bt, "calls_through_trampoline"), "{:?}", bt);
+}